@@ -0,0 +1,66 @@
+use clap::{Arg, App};
+
+const N: usize = 1000;
+
+/// A smooth, hilly height field
+fn height(x: f64, y: f64) -> f64 {
+    (x * 3.0).sin() * (y * 2.0).cos() + 0.3 * (x * 7.0 + y * 5.0).sin()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = App::new("terrain")
+        .author("Matt Keeter <matt.j.keeter@gmail.com>")
+        .about("Triangulates and interpolates a sampled height field")
+        .arg(Arg::with_name("num")
+            .short("n")
+            .long("num")
+            .help("number of samples along each axis")
+            .takes_value(true))
+        .get_matches();
+
+    let num = matches.value_of("num")
+        .map(|s| s.parse())
+        .unwrap_or(Ok(N))?;
+
+    // Interior samples are slightly jittered, as if the grid was projected
+    // from a curved surface, but they're still stored row by row.  The grid
+    // path only applies if the boundary is convex, so it's left straight.
+    let mut points = Vec::with_capacity(num * num);
+    for j in 0..num {
+        for i in 0..num {
+            let u = i as f64 / (num - 1) as f64;
+            let v = j as f64 / (num - 1) as f64;
+            if i == 0 || j == 0 || i == num - 1 || j == num - 1 {
+                points.push((u, v));
+            } else {
+                let jitter = 0.2 / num as f64;
+                points.push((u + jitter * (u * 40.0).sin() * (v * 30.0).cos(),
+                             v + jitter * (u * 25.0).cos() * (v * 35.0).sin()));
+            }
+        }
+    }
+    let heights: Vec<f64> = points.iter().map(|p| height(p.0, p.1)).collect();
+
+    let now = std::time::Instant::now();
+    let triangles = cdt::triangulate_points(&points)?;
+    eprintln!("Triangulated {} samples into {} triangles in {:?}",
+              points.len(), triangles.len(), now.elapsed());
+
+    let now = std::time::Instant::now();
+    let t = cdt::Triangulation::build(&points)?;
+    eprintln!("General algorithm produced {} triangles in {:?}",
+              t.triangles().count(), now.elapsed());
+
+    // Resample the terrain between the original samples
+    let interp = cdt::Interpolator::new(&points)?;
+    let mut max_err: f64 = 0.0;
+    for k in 0..1000 {
+        let x = 0.05 + 0.9 * ((k as f64) * 0.618).fract();
+        let y = 0.05 + 0.9 * ((k as f64) * 0.414).fract();
+        if let Some(h) = interp.natural_neighbor((x, y), &heights) {
+            max_err = max_err.max((h - height(x, y)).abs());
+        }
+    }
+    eprintln!("Max interpolation error at 1000 points: {:.2e}", max_err);
+    Ok(())
+}
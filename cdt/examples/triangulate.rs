@@ -29,6 +29,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .long("seed")
             .help("seed for RNG")
             .takes_value(true))
+        .arg(Arg::with_name("threads")
+            .short("t")
+            .long("threads")
+            .help("also triangulate in parallel with this many threads")
+            .takes_value(true))
         .get_matches();
 
     let num = matches.value_of("num")
@@ -69,6 +74,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         result.len(),
     );
 
+    if let Some(threads) = matches.value_of("threads") {
+        let now = std::time::Instant::now();
+        let result = cdt::triangulate_points_parallel(&points, threads.parse()?)?;
+        let elapsed = now.elapsed();
+        eprintln!(
+            "    Triangulated in parallel in {}.{}s.\n    Generated {} triangles.",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            result.len(),
+        );
+    }
+
     if let Some(out) = matches.value_of("output") {
         eprintln!("    Saving {}", out);
         t.save_debug_svg(out).expect("Could not save SVG");
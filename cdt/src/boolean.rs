@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use crate::{Error, Point, Triangle, Triangulation, crossing::split_crossing_edges};
+
+/// Maximum number of passes when splitting edges, since the points inserted
+/// at crossings are rounded and may create new (tiny) crossings
+const MAX_SPLIT_PASSES: usize = 4;
+
+/// Operations for [`polygon_boolean`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BooleanOp {
+    /// Area covered by either set of polygons
+    Union,
+    /// Area covered by both sets of polygons
+    Intersection,
+    /// Area covered by the first set of polygons but not the second
+    Difference,
+}
+
+impl BooleanOp {
+    fn apply(&self, a: bool, b: bool) -> bool {
+        match self {
+            BooleanOp::Union => a || b,
+            BooleanOp::Intersection => a && b,
+            BooleanOp::Difference => a && !b,
+        }
+    }
+}
+
+/// Returns the index of a point, adding it to the list if it's new.  Points
+/// are matched by exact position, so that rings which share a vertex (or an
+/// edge) end up sharing indexes.
+fn intern(pts: &mut Vec<Point>, index: &mut HashMap<(u64, u64), usize>, p: Point) -> usize {
+    // Adding zero turns -0.0 into 0.0, so they're treated as the same point
+    let key = ((p.0 + 0.0).to_bits(), (p.1 + 0.0).to_bits());
+    *index.entry(key).or_insert_with(|| {
+        pts.push(p);
+        pts.len() - 1
+    })
+}
+
+/// Combines two sets of polygons, each given as a list of rings (in the same
+/// format as [`triangulate_polygon`](crate::triangulate_polygon)).  Each set
+/// uses the non-zero winding rule, so rings may overlap or intersect
+/// themselves, and holes must be wound opposite to the rings around them.
+///
+/// Returns rings which don't cross each other, with outer boundaries wound
+/// counter-clockwise and holes wound clockwise.  Using
+/// [`BooleanOp::Union`] with an empty second set cleans up a single set of
+/// messy rings.
+///
+/// # Errors
+/// Returns [`Error::InvalidInput`] if any point isn't finite, or an error
+/// from the constrained triangulation used to find the result.
+pub fn polygon_boolean<V>(a: &[V], b: &[V], op: BooleanOp)
+    -> Result<Vec<Vec<Point>>, Error>
+    where V: AsRef<[Point]>
+{
+    let mut pts = Vec::new();
+    let mut index = HashMap::new();
+    let mut edges = Vec::new();
+    let mut sets = Vec::new(); // Which set of polygons each edge came from
+    for (set, rings) in [a, b].iter().enumerate() {
+        for ring in rings.iter().map(|r| r.as_ref()) {
+            if ring.iter().any(|p| !p.0.is_finite() || !p.1.is_finite()) {
+                return Err(Error::InvalidInput);
+            }
+            let ids: Vec<usize> = ring.iter()
+                .map(|p| intern(&mut pts, &mut index, *p))
+                .collect();
+            for (i, &s) in ids.iter().enumerate() {
+                let t = ids[(i + 1) % ids.len()];
+                if s != t {
+                    edges.push((s, t));
+                    sets.push(set);
+                }
+            }
+        }
+    }
+
+    // Split edges where they cross, so that the triangulation can lock them
+    for _ in 0..MAX_SPLIT_PASSES {
+        let (split, crossings) = split_crossing_edges(&pts, &edges);
+        if split.len() == edges.len() {
+            break;
+        }
+        let base = pts.len();
+        let remap: Vec<usize> = crossings.iter()
+            .map(|c| intern(&mut pts, &mut index, c.pos))
+            .collect();
+        let remap = |i: usize| if i >= base { remap[i - base] } else { i };
+
+        // Each input edge became a chain of edges ending at its original end
+        let mut next_edges = Vec::with_capacity(split.len());
+        let mut next_sets = Vec::with_capacity(split.len());
+        let mut iter = split.iter();
+        for (&(_, end), &set) in edges.iter().zip(&sets) {
+            for &(s, t) in iter.by_ref() {
+                if remap(s) != remap(t) {
+                    next_edges.push((remap(s), remap(t)));
+                    next_sets.push(set);
+                }
+                if t == end {
+                    break;
+                }
+            }
+        }
+        edges = next_edges;
+        sets = next_sets;
+    }
+    if edges.is_empty() || pts.len() < 3 {
+        return Ok(Vec::new());
+    }
+
+    let mut t = Triangulation::new_with_edges(&pts, &edges)?;
+    t.set_keep_outside(true);
+    t.run()?;
+    let tris: Vec<Triangle> = t.triangles().collect();
+
+    // Crossing a ring from right to left increases its winding number
+    let mut delta: HashMap<(usize, usize), [i32; 2]> = HashMap::new();
+    for (&(s, t), &set) in edges.iter().zip(&sets) {
+        delta.entry((s, t)).or_default()[set] += 1;
+        delta.entry((t, s)).or_default()[set] -= 1;
+    }
+    let delta = |e| delta.get(&e).copied().unwrap_or_default();
+
+    let mut tri_of = HashMap::with_capacity(tris.len() * 3);
+    for (i, &(a, b, c)) in tris.iter().enumerate() {
+        tri_of.insert((a, b), i);
+        tri_of.insert((b, c), i);
+        tri_of.insert((c, a), i);
+    }
+    let sides = |(a, b, c): Triangle| [(a, b), (b, c), (c, a)];
+
+    // Flood-fill winding numbers, starting from a triangle on the convex
+    // hull (outside of which every winding number is zero)
+    let mut winding: Vec<Option<[i32; 2]>> = vec![None; tris.len()];
+    let mut todo = Vec::new();
+    if let Some((i, e)) = tris.iter()
+        .enumerate()
+        .flat_map(|(i, t)| sides(*t).map(|e| (i, e)))
+        .find(|(_, (a, b))| !tri_of.contains_key(&(*b, *a)))
+    {
+        winding[i] = Some(delta(e));
+        todo.push(i);
+    }
+    while let Some(i) = todo.pop() {
+        let w = winding[i].unwrap();
+        for (s, t) in sides(tris[i]) {
+            if let Some(&j) = tri_of.get(&(t, s)) {
+                if winding[j].is_none() {
+                    let d = delta((s, t));
+                    winding[j] = Some([w[0] - d[0], w[1] - d[1]]);
+                    todo.push(j);
+                }
+            }
+        }
+    }
+    let inside: Vec<bool> = winding.iter()
+        .map(|w| w.unwrap_or_default())
+        .map(|w| op.apply(w[0] != 0, w[1] != 0))
+        .collect();
+
+    // Collect the directed edges between selected and unselected triangles,
+    // which have the selected region on their left
+    let mut next: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (tri, _) in tris.iter().zip(&inside).filter(|(_, i)| **i) {
+        for (s, t) in sides(*tri) {
+            if !tri_of.get(&(t, s)).is_some_and(|j| inside[*j]) {
+                next.entry(s).or_default().push(t);
+            }
+        }
+    }
+
+    // Walk the boundary edges into rings.  Where several rings touch at a
+    // vertex, we take the outgoing edge with the smallest clockwise turn from
+    // the incoming edge, which stays on the boundary of the same region.
+    let angle = |a: usize, b: usize| (pts[b].1 - pts[a].1).atan2(pts[b].0 - pts[a].0);
+    let mut starts: Vec<usize> = next.keys().copied().collect();
+    starts.sort_unstable();
+    let mut out = Vec::new();
+    for start in starts {
+        while let Some(mut cur) = next.get_mut(&start).and_then(|n| n.pop()) {
+            let mut ring = vec![pts[start]];
+            let mut prev = start;
+            while cur != start {
+                ring.push(pts[cur]);
+                let outgoing = match next.get_mut(&cur) {
+                    Some(n) if !n.is_empty() => n,
+                    _ => break,
+                };
+                let back = angle(cur, prev);
+                let turn = |o: usize| {
+                    let a = (back - angle(cur, o)).rem_euclid(std::f64::consts::TAU);
+                    if a == 0.0 { std::f64::consts::TAU } else { a }
+                };
+                let k = (0..outgoing.len())
+                    .min_by(|&i, &j| turn(outgoing[i]).partial_cmp(&turn(outgoing[j])).unwrap())
+                    .unwrap();
+                prev = cur;
+                cur = outgoing.swap_remove(k);
+            }
+            out.push(ring);
+        }
+    }
+    Ok(out)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(rings: &[Vec<Point>]) -> f64 {
+        rings.iter()
+            .map(|r| (0..r.len())
+                .map(|i| {
+                    let (a, b) = (r[i], r[(i + 1) % r.len()]);
+                    a.0 * b.1 - b.0 * a.1
+                })
+                .sum::<f64>() / 2.0)
+            .sum()
+    }
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<Point> {
+        vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+    }
+
+    #[test]
+    fn overlapping_squares() {
+        let a = [square(0.0, 0.0, 2.0)];
+        let b = [square(1.0, 1.0, 2.0)];
+
+        let u = polygon_boolean(&a, &b, BooleanOp::Union).unwrap();
+        assert_eq!(u.len(), 1);
+        assert_eq!(area(&u), 7.0);
+
+        let i = polygon_boolean(&a, &b, BooleanOp::Intersection).unwrap();
+        assert_eq!(i.len(), 1);
+        assert_eq!(area(&i), 1.0);
+
+        let d = polygon_boolean(&a, &b, BooleanOp::Difference).unwrap();
+        assert_eq!(d.len(), 1);
+        assert_eq!(area(&d), 3.0);
+    }
+
+    #[test]
+    fn holes() {
+        // A clockwise hole is cut out of the outer ring, and survives the
+        // union as a separate (clockwise) ring
+        let mut hole = square(1.0, 1.0, 1.0);
+        hole.reverse();
+        let a = [square(0.0, 0.0, 3.0), hole];
+        let u = polygon_boolean(&a, &[], BooleanOp::Union).unwrap();
+        assert_eq!(u.len(), 2);
+        assert_eq!(area(&u), 8.0);
+
+        // Filling the hole with a second set removes it
+        let b = [square(1.0, 1.0, 1.0)];
+        let u = polygon_boolean(&a, &b, BooleanOp::Union).unwrap();
+        assert_eq!(u.len(), 1);
+        assert_eq!(area(&u), 9.0);
+    }
+
+    #[test]
+    fn self_intersecting() {
+        // A bow-tie becomes two counter-clockwise triangles, which touch at
+        // the crossing point
+        let a = [vec![(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)]];
+        let u = polygon_boolean(&a, &[], BooleanOp::Union).unwrap();
+        assert_eq!(u.len(), 2);
+        assert!(u.iter().all(|r| r.len() == 3 && area(std::slice::from_ref(r)) == 0.25));
+    }
+
+    #[test]
+    fn disjoint() {
+        let a = [square(0.0, 0.0, 1.0)];
+        let b = [square(2.0, 0.0, 1.0)];
+        assert_eq!(polygon_boolean(&a, &b, BooleanOp::Union).unwrap().len(), 2);
+        assert!(polygon_boolean(&a, &b, BooleanOp::Intersection).unwrap().is_empty());
+        assert_eq!(area(&polygon_boolean(&a, &b, BooleanOp::Difference).unwrap()), 1.0);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{Point, predicates::orient2d};
+
+/// A point which was inserted where two fixed edges cross
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Crossing {
+    /// Position of the new point
+    pub pos: Point,
+    /// The two edges which cross here, as indexes into the input edge list
+    pub edges: (usize, usize),
+    /// Position along each edge, from 0 at its first point to 1 at its second
+    pub t: (f64, f64),
+}
+
+/// Returns the position of `p` along the segment `a-b`, if it is exactly on
+/// the segment and strictly between its endpoints
+fn on_segment(a: Point, b: Point, p: Point) -> Option<f64> {
+    if orient2d(a, b, p) != 0.0 {
+        return None;
+    }
+    let d = (b.0 - a.0, b.1 - a.1);
+    let t = ((p.0 - a.0) * d.0 + (p.1 - a.1) * d.1) / (d.0 * d.0 + d.1 * d.1);
+    Some(t).filter(|t| *t > 0.0 && *t < 1.0)
+}
+
+/// Splits fixed edges wherever they cross each other, or where an edge
+/// passes exactly through another edge's endpoint.
+///
+/// Returns a new list of edges, which index into `pts` followed by the
+/// positions of the inserted points, along with the inserted points
+/// themselves (in order).  Edges which don't cross anything are returned
+/// unchanged.
+pub fn split_crossing_edges(pts: &[Point], edges: &[(usize, usize)])
+    -> (Vec<(usize, usize)>, Vec<Crossing>)
+{
+    // Sweep over the edges from left to right, only comparing edges whose
+    // x ranges overlap
+    let range = |(a, b): (usize, usize)| (pts[a].0.min(pts[b].0), pts[a].0.max(pts[b].0));
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_unstable_by(|&a, &b| {
+        range(edges[a]).0.partial_cmp(&range(edges[b]).0).unwrap()
+    });
+
+    // For each edge, the points at which it is split, as (t, index) pairs
+    let mut splits: Vec<Vec<(f64, usize)>> = vec![Vec::new(); edges.len()];
+    let mut crossings = Vec::new();
+    for (k, &i) in order.iter().enumerate() {
+        let (a, b) = edges[i];
+        let (pa, pb) = (pts[a], pts[b]);
+        let x_max = range(edges[i]).1;
+        for &j in order[k + 1..].iter()
+            .take_while(|&&j| range(edges[j]).0 <= x_max)
+        {
+            let (c, d) = edges[j];
+            let (pc, pd) = (pts[c], pts[d]);
+            if pa.1.max(pb.1) < pc.1.min(pd.1) || pc.1.max(pd.1) < pa.1.min(pb.1) {
+                continue;
+            }
+
+            // Points from one edge on the other edge's interior
+            for &(e, p) in &[(i, c), (i, d), (j, a), (j, b)] {
+                let (u, v) = edges[e];
+                if let Some(t) = on_segment(pts[u], pts[v], pts[p]) {
+                    splits[e].push((t, p));
+                }
+            }
+
+            // Proper crossings, where each edge has one point strictly on
+            // either side of the other
+            let (o_c, o_d) = (orient2d(pa, pb, pc), orient2d(pa, pb, pd));
+            let (o_a, o_b) = (orient2d(pc, pd, pa), orient2d(pc, pd, pb));
+            if o_c * o_d < 0.0 && o_a * o_b < 0.0 {
+                let t_i = o_a / (o_a - o_b);
+                let t_j = o_c / (o_c - o_d);
+                let index = pts.len() + crossings.len();
+                crossings.push(Crossing {
+                    pos: (pa.0 + (pb.0 - pa.0) * t_i, pa.1 + (pb.1 - pa.1) * t_i),
+                    edges: (i, j),
+                    t: (t_i, t_j),
+                });
+                splits[i].push((t_i, index));
+                splits[j].push((t_j, index));
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(edges.len() + crossings.len() * 2);
+    for (&(a, b), s) in edges.iter().zip(splits.iter_mut()) {
+        s.sort_unstable_by(|x, y| x.partial_cmp(y).unwrap());
+        s.dedup_by_key(|s| s.1);
+        let mut prev = a;
+        for &(_, p) in s.iter() {
+            out.push((prev, p));
+            prev = p;
+        }
+        out.push((prev, b));
+    }
+    (out, crossings)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bowtie() {
+        let pts = [(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)];
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        let (split, crossings) = split_crossing_edges(&pts, &edges);
+        assert_eq!(crossings.len(), 1);
+        assert_eq!(crossings[0].pos, (0.5, 0.5));
+        assert_eq!(crossings[0].t, (0.5, 0.5));
+        assert_eq!(split, vec![(0, 4), (4, 1), (1, 2), (2, 4), (4, 3), (3, 0)]);
+    }
+
+    #[test]
+    fn t_junction() {
+        // Point 3 sits exactly on the edge from 0 to 1
+        let pts = [(0.0, 0.0), (2.0, 0.0), (1.0, 1.0), (1.0, 0.0)];
+        let edges = [(0, 1), (2, 3)];
+        let (split, crossings) = split_crossing_edges(&pts, &edges);
+        assert!(crossings.is_empty());
+        assert_eq!(split, vec![(0, 3), (3, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn unchanged() {
+        let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0)];
+        let (split, crossings) = split_crossing_edges(&pts, &edges);
+        assert!(crossings.is_empty());
+        assert_eq!(split, edges);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{Point, predicates::{in_circle, orient2d}};
+
+/// Detects points which are laid out as a (possibly distorted) grid, stored
+/// row by row, and returns the number of points in each row.
+fn row_length(pts: &[Point]) -> Option<usize> {
+    if pts.len() < 4 {
+        return None;
+    }
+    // Rows end when we stop moving in the same direction as the first step
+    let d = (pts[1].0 - pts[0].0, pts[1].1 - pts[0].1);
+    let n = (2..pts.len())
+        .find(|&k| {
+            let s = (pts[k].0 - pts[k - 1].0, pts[k].1 - pts[k - 1].1);
+            s.0 * d.0 + s.1 * d.1 <= 0.0
+        })?;
+    Some(n).filter(|n| pts.len().is_multiple_of(*n))
+}
+
+/// Triangulates points on a structured grid directly, without running the
+/// sweep-line algorithm.  The result is checked to be a valid Delaunay
+/// triangulation of the points (i.e. every triangle is counter-clockwise,
+/// every edge is locally Delaunay, and the grid's boundary is convex).
+///
+/// Returns `None` if the points aren't a grid or the result wouldn't be
+/// Delaunay, in which case the general algorithm should be used instead.
+pub(crate) fn triangulate_grid(pts: &[Point]) -> Option<Vec<(usize, usize, usize)>> {
+    let nu = row_length(pts)?;
+    let nv = pts.len() / nu;
+    if nv < 2 {
+        return None;
+    }
+    let at = |i: usize, j: usize| j * nu + i;
+    let p = |i: usize| pts[i];
+
+    // Each cell is labelled a-b-c-d, starting at its lowest index and
+    // winding counter-clockwise if rows advance to the left of the first
+    // row.  Otherwise, every predicate is flipped.
+    let sign = if orient2d(p(at(0, 0)), p(at(1, 0)), p(at(1, 1))) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let orient = |a, b, c| sign * orient2d(p(a), p(b), p(c));
+    let in_circle = |(a, b, c), d| sign * in_circle(p(a), p(b), p(c), p(d));
+    let cell = |i: usize, j: usize| (at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j + 1));
+
+    // Pick whichever diagonal makes each cell locally Delaunay, recording
+    // whether it runs from b to d (rather than a to c)
+    let diag_bd: Vec<bool> = (0..nv - 1)
+        .flat_map(|j| (0..nu - 1).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            let (a, b, c, d) = cell(i, j);
+            in_circle((a, b, c), d) > 0.0
+        })
+        .collect();
+    let bd = |i: usize, j: usize| diag_bd[j * (nu - 1) + i];
+
+    let mut out = Vec::with_capacity((nu - 1) * (nv - 1) * 2);
+    for j in 0..nv - 1 {
+        for i in 0..nu - 1 {
+            let (a, b, c, d) = cell(i, j);
+            let tris = if bd(i, j) {
+                [(a, b, d), (b, c, d)]
+            } else {
+                [(a, b, c), (a, c, d)]
+            };
+            for &(x, y, z) in &tris {
+                if orient(x, y, z) <= 0.0 {
+                    return None;
+                }
+                out.push(if sign < 0.0 { (y, x, z) } else { (x, y, z) });
+            }
+        }
+    }
+
+    // Check the boundary for convexity
+    let boundary: Vec<usize> = (0..nu).map(|i| at(i, 0))
+        .chain((1..nv).map(|j| at(nu - 1, j)))
+        .chain((0..nu - 1).rev().map(|i| at(i, nv - 1)))
+        .chain((1..nv - 1).rev().map(|j| at(0, j)))
+        .collect();
+    let n = boundary.len();
+    if (0..n).any(|k| orient(boundary[k], boundary[(k + 1) % n],
+                             boundary[(k + 2) % n]) < 0.0) {
+        return None;
+    }
+
+    // Check that edges between cells are locally Delaunay, by testing a
+    // triangle on one side against the opposite vertex on the other side
+    for j in 0..nv - 1 {
+        for i in 0..nu - 1 {
+            let (a, b, c, d) = cell(i, j);
+            if i + 1 < nu - 1 {
+                let (ar, br, cr, _) = cell(i + 1, j);
+                let t = if bd(i, j) { (b, c, d) } else { (a, b, c) };
+                let opp = if bd(i + 1, j) { br } else { cr };
+                debug_assert!(ar == b);
+                if in_circle(t, opp) > 0.0 {
+                    return None;
+                }
+            }
+            if j + 1 < nv - 1 {
+                let (_, _, ct, dt) = cell(i, j + 1);
+                let t = if bd(i, j) { (b, c, d) } else { (a, c, d) };
+                let opp = if bd(i, j + 1) { dt } else { ct };
+                if in_circle(t, opp) > 0.0 {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regular() {
+        let mut pts = Vec::new();
+        for j in 0..4 {
+            for i in 0..5 {
+                pts.push((i as f64, j as f64 * 0.5));
+            }
+        }
+        let tris = triangulate_grid(&pts).expect("Could not triangulate grid");
+        assert_eq!(tris.len(), 4 * 3 * 2);
+
+        // Columns stored in reverse order still work
+        pts.iter_mut().for_each(|p| p.0 = -p.0);
+        assert!(triangulate_grid(&pts).is_some());
+    }
+
+    #[test]
+    fn matches_general() {
+        // With jittered interior points, the Delaunay triangulation is
+        // unique, so both algorithms should agree exactly
+        let mut pts = Vec::new();
+        for j in 0..6 {
+            for i in 0..7 {
+                let k = (i * 6 + j) as f64;
+                let jitter = if i == 0 || j == 0 || i == 6 || j == 5 { 0.0 } else { 0.2 };
+                pts.push((i as f64 + jitter * (k * 1.3).sin(),
+                          j as f64 + jitter * (k * 2.1).cos()));
+            }
+        }
+        let normalize = |mut t: Vec<(usize, usize, usize)>| {
+            for t in t.iter_mut() {
+                while t.0 > t.1 || t.0 > t.2 {
+                    *t = (t.1, t.2, t.0);
+                }
+            }
+            t.sort_unstable();
+            t
+        };
+        let grid = triangulate_grid(&pts).expect("Could not triangulate grid");
+        let general = crate::Triangulation::build(&pts)
+            .expect("Could not triangulate")
+            .triangles()
+            .collect();
+        assert_eq!(normalize(grid), normalize(general));
+    }
+
+    #[test]
+    fn not_a_grid() {
+        // Concave boundary
+        let pts = [(0.0, 0.0), (1.0, 0.5), (2.0, 0.0),
+                   (0.0, 1.0), (1.0, 1.0), (2.0, 1.0)];
+        assert!(triangulate_grid(&pts).is_none());
+
+        // Ragged rows
+        let pts = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0),
+                   (0.0, 1.0), (1.0, 1.0)];
+        assert!(triangulate_grid(&pts).is_none());
+
+        // Unstructured
+        let pts = [(0.0, 0.0), (1.0, 1.0), (0.2, 0.9), (0.7, 0.1)];
+        assert!(triangulate_grid(&pts).is_none());
+    }
+}
@@ -190,6 +190,11 @@ impl Half {
         }
     }
 
+    /// Returns the number of bytes allocated for the edge array
+    pub fn memory_usage(&self) -> usize {
+        self.edges.capacity() * std::mem::size_of::<Edge>()
+    }
+
     /// Sanity-checks the structure's invariants, raising an assertion if
     /// any invariants are broken.  This is a slow operation and should only
     /// be run in a debugging context.
@@ -4,17 +4,9 @@ use crate::{
 
 const N: usize = 1 << 10;
 
+/// Neighbors of a point in the hull, or `EMPTY_HULL`
 #[derive(Clone, Copy, Debug)]
-struct Node {
-    /// Pseudo-angle of the point
-    angle: f64,
-
-    /// `EdgeIndex` of the edge to the right of this point, i.e. having this
-    /// point as its `dst` (since the hull is on top of the shape and triangle
-    /// are wound counter-clockwise).
-    edge: EdgeIndex,
-
-    /// Neighbors, or `EMPTY_HULL`
+struct Link {
     left: HullIndex,
     right: HullIndex,
 }
@@ -31,25 +23,45 @@ struct Node {
 ///
 /// In addition, the Hull stores a random-access map from PointIndex to
 /// HullIndex (if present), for fast lookups without hash traversal.
+///
+/// Per-point data is stored as a structure of arrays, all indexed by
+/// `HullIndex`, so that the linked-list walk in [`Hull::get`] only touches
+/// the angles and links, rather than pulling edges into the cache as well.
 #[derive(Debug)]
 pub struct Hull {
     buckets: [HullIndex; N],
-    data: HullVec<Node>,
+
+    /// Pseudo-angle of each point
+    angles: HullVec<f64>,
+
+    /// Neighbors of each point
+    links: HullVec<Link>,
+
+    /// `EdgeIndex` of the edge to the right of each point, i.e. having that
+    /// point as its `dst` (since the hull is on top of the shape and triangle
+    /// are wound counter-clockwise).
+    edges: HullVec<EdgeIndex>,
+
+    /// Index of each point in the triangulation
+    points: HullVec<PointIndex>,
 
     /// Random-access lookup of point->hull; this is only needed when doing
     /// *constrained* Delaunay triangulation and is empty otherwise
-    points: PointVec<HullIndex>,
+    lookup: PointVec<HullIndex>,
 
-    /// Spare slots in the [`Hull::data`] array, to keep it small
+    /// Spare slots in the per-point arrays, to keep them small
     empty: Vec<HullIndex>,
 }
 
 impl Hull {
     pub fn new(num_points: usize, constrained: bool) -> Hull {
         Hull {
-            data: HullVec::new(),
+            angles: HullVec::new(),
+            links: HullVec::new(),
+            edges: HullVec::new(),
             buckets: [EMPTY_HULL; N],
-            points: if constrained {
+            points: HullVec::new(),
+            lookup: if constrained {
                 PointVec::of(vec![EMPTY_HULL; num_points])
             } else {
                 PointVec::new()
@@ -61,14 +73,12 @@ impl Hull {
     // Inserts the first point, along with its associated edge, tied into
     // a tiny loop with itself
     pub fn initialize(&mut self, p: PointIndex, angle: f64, edge: EdgeIndex) {
-        let h = self.data.push(Node {
-            angle,
-            left: self.data.next_index(),
-            right: self.data.next_index(),
-            edge,
-        });
-        if !self.points.is_empty() {
-            self.points[p] = h;
+        let h = self.push(angle, Link {
+            left: self.angles.next_index(),
+            right: self.angles.next_index(),
+        }, edge, p);
+        if !self.lookup.is_empty() {
+            self.lookup[p] = h;
         }
 
         let b = self.bucket(angle);
@@ -77,7 +87,7 @@ impl Hull {
     }
 
     pub fn update(&mut self, h: HullIndex, e: EdgeIndex) {
-        self.data[h].edge = e;
+        self.edges[h] = e;
     }
 
     /// For a given point, returns the HullIndex which will be split when this
@@ -106,8 +116,8 @@ impl Hull {
             // that our new point, or we leave this bucket, or we're about
             // to wrap around in the same bucket.
             let start = h;
-            while self.data[h].angle < angle && self.bucket_h(h) == b {
-                h = self.data[h].right;
+            while self.angles[h] < angle && self.bucket_h(h) == b {
+                h = self.links[h].right;
                 // If we've looped around, it means all points are in the same
                 // bucket *and* the new point is larger than all of them.  This
                 // means it will be inserted at the end of the bucket, and will
@@ -121,7 +131,7 @@ impl Hull {
 
         // Walk backwards one step to return the HullIndex which will be split
         // by this new point being inserted
-        self.data[h].left
+        self.links[h].left
     }
 
     pub fn start(&self) -> HullIndex {
@@ -132,6 +142,17 @@ impl Hull {
             .unwrap()
     }
 
+    /// Returns the number of bytes allocated for the hull's arrays
+    pub fn memory_usage(&self) -> usize {
+        use std::mem::size_of;
+        size_of::<[HullIndex; N]>()
+            + self.angles.capacity() * size_of::<f64>()
+            + self.links.capacity() * size_of::<Link>()
+            + self.edges.capacity() * size_of::<EdgeIndex>()
+            + self.points.capacity() * size_of::<PointIndex>()
+            + (self.lookup.capacity() + self.empty.capacity()) * size_of::<HullIndex>()
+    }
+
     /// Sanity-checks invariants of the data structure, raising an assertion
     /// failure if an invariant is broken.  This is a slow operation and should
     /// only be run in a debugging context.
@@ -155,8 +176,8 @@ impl Hull {
         // edges are correctly stitched together, and buckets are correct.
         loop {
             // Assert that the list is correctly stitched together
-            let next = self.data[index].right;
-            assert!(index == self.data[next].left);
+            let next = self.links[index].right;
+            assert!(index == self.links[next].left);
 
             // If this is the first item in a new bucket, it should be at the
             // head of the bucket's list.
@@ -170,8 +191,8 @@ impl Hull {
                 break;
             } else {
                 // Assert that position are increasing in the list
-                let my_position = self.data[index].angle;
-                let next_position = self.data[next].angle;
+                let my_position = self.angles[index];
+                let next_position = self.angles[next];
                 assert!(next_position >= my_position);
                 index = next;
             }
@@ -179,33 +200,35 @@ impl Hull {
     }
 
     pub fn left_hull(&self, h: HullIndex) -> HullIndex {
-        self.data[h].left
+        self.links[h].left
     }
 
     pub fn right_hull(&self, h: HullIndex) -> HullIndex {
-        self.data[h].right
+        self.links[h].right
     }
 
     pub fn edge(&self, h: HullIndex) -> EdgeIndex {
-        self.data[h].edge
+        self.edges[h]
     }
 
     /// Returns the hull index associated with the given point
     pub fn index_of(&self, p: PointIndex) -> HullIndex {
-        assert!(!self.points.is_empty());
-        let h = self.points[p];
+        assert!(!self.lookup.is_empty());
+        let h = self.lookup[p];
         assert!(h != EMPTY_HULL);
-        assert!(self.data[h].left != EMPTY_HULL ||
-                self.data[h].right != EMPTY_HULL);
+        assert!(self.links[h].left != EMPTY_HULL ||
+                self.links[h].right != EMPTY_HULL);
         h
     }
 
-    /// Transitions the point -> hull random lookup from `old` to `new`.  This
-    /// is required when two points have the exact same pseudoangle.
-    pub fn move_point(&mut self, old: PointIndex, new: PointIndex) {
-        if !self.points.is_empty() {
-            self.points[new] = self.points[old];
-            self.points[old] = EMPTY_HULL;
+    /// Moves the hull slot `h` to a new point, updating the point -> hull
+    /// random lookup.  This is required when two points have the exact same
+    /// pseudoangle.
+    pub fn move_point(&mut self, h: HullIndex, new: PointIndex) {
+        let old = std::mem::replace(&mut self.points[h], new);
+        if !self.lookup.is_empty() {
+            self.lookup[new] = h;
+            self.lookup[old] = EMPTY_HULL;
         }
     }
 
@@ -216,38 +239,48 @@ impl Hull {
         self.insert(self.get(angle), angle, point, e)
     }
 
+    /// Appends a new slot to each of the per-point arrays
+    fn push(&mut self, angle: f64, link: Link, edge: EdgeIndex,
+            point: PointIndex) -> HullIndex {
+        let h = self.angles.push(angle);
+        self.links.push(link);
+        self.edges.push(edge);
+        self.points.push(point);
+        h
+    }
+
     /// Insert a new Point-Edge pair into the hull, using a hint to save time
     /// searching for the new point's position.
     pub fn insert(&mut self, left: HullIndex, angle: f64,
                   point: PointIndex, edge: EdgeIndex) -> HullIndex {
         let right = self.right_hull(left);
 
+        let link = Link { left, right };
         let h = if let Some(h) = self.empty.pop() {
-            self.data[h] = Node {
-                angle, edge, left, right
-            };
+            self.angles[h] = angle;
+            self.links[h] = link;
+            self.edges[h] = edge;
+            self.points[h] = point;
             h
         } else {
-            self.data.push(Node{
-                angle, edge, left, right
-            })
+            self.push(angle, link, edge, point)
         };
 
         // If the target bucket is empty, or the given point is below the first
         // item in the target bucket, then it becomes the bucket's head
         let b = self.bucket(angle);
         if self.buckets[b] == EMPTY_HULL || (self.buckets[b] == right &&
-                                             angle < self.data[right].angle)
+                                             angle < self.angles[right])
         {
             self.buckets[b] = h;
         }
 
         // Stitch ourselves into the linked list
-        self.data[right].left = h;
-        self.data[left].right = h;
+        self.links[right].left = h;
+        self.links[left].right = h;
 
-        if !self.points.is_empty() {
-            self.points[point] = h;
+        if !self.lookup.is_empty() {
+            self.lookup[point] = h;
         }
 
         h
@@ -255,14 +288,12 @@ impl Hull {
 
     /// Removes the given point from the hull
     pub fn erase(&mut self, h: HullIndex) {
-        let next = self.data[h].right;
-        let prev = self.data[h].left;
+        let Link { left: prev, right: next } = self.links[h];
 
         // Cut this node out of the linked list
-        self.data[next].left = prev;
-        self.data[prev].right = next;
-        self.data[h].right = EMPTY_HULL;
-        self.data[h].left = EMPTY_HULL;
+        self.links[next].left = prev;
+        self.links[prev].right = next;
+        self.links[h] = Link { left: EMPTY_HULL, right: EMPTY_HULL };
 
         // If this is the head of the bucket, then replace it with the next
         // item in this bucket chain (assuming it belongs in the same bucket),
@@ -276,12 +307,55 @@ impl Hull {
             }
         }
 
+        if !self.lookup.is_empty() && self.lookup[self.points[h]] == h {
+            self.lookup[self.points[h]] = EMPTY_HULL;
+        }
+
         // Store this hull index for reuse
         self.empty.push(h);
     }
 
+    /// Iterates over all points in the Hull, in left-to-right order
+    pub fn points(&self) -> impl Iterator<Item=PointIndex> + '_ {
+        self.walk().map(move |h| self.points[h])
+    }
+
+    /// Returns the point to the right of the given point on the hull, or
+    /// `None` if the point isn't on the hull.  The hull is a loop, so the
+    /// rightmost point's neighbor is the leftmost point.
+    ///
+    /// This is a fast lookup for constrained triangulations, and walks the
+    /// hull otherwise (since the point -> hull table isn't allocated).
+    pub fn next(&self, p: PointIndex) -> Option<PointIndex> {
+        self.hull_index(p).map(|h| self.points[self.links[h].right])
+    }
+
+    /// Returns the point to the left of the given point on the hull, or
+    /// `None` if the point isn't on the hull.
+    pub fn prev(&self, p: PointIndex) -> Option<PointIndex> {
+        self.hull_index(p).map(|h| self.points[self.links[h].left])
+    }
+
+    /// Looks up a point's hull index, if it is on the hull
+    fn hull_index(&self, p: PointIndex) -> Option<HullIndex> {
+        if self.lookup.is_empty() {
+            return self.walk().find(|&h| self.points[h] == p);
+        }
+        let h = *self.lookup.get(p.0 as usize)?;
+        if h == EMPTY_HULL {
+            None
+        } else {
+            Some(h)
+        }
+    }
+
     /// Iterates over all edges stored in the Hull, in order
     pub fn values(&self) -> impl Iterator<Item=EdgeIndex> + '_ {
+        self.walk().map(move |h| self.edges[h])
+    }
+
+    /// Iterates over every index in the Hull, in order
+    fn walk(&self) -> impl Iterator<Item=HullIndex> + '_ {
         // Find the first non-empty bucket to use as our starting point for
         // walking around the hull's linked list.
         let mut point: HullIndex = self.buckets.iter()
@@ -294,11 +368,11 @@ impl Hull {
         let start = point;
         let mut started = false;
         std::iter::from_fn(move || {
-            let out = self.data[point].edge;
+            let out = point;
             if point == start && started {
                 None
             } else {
-                point = self.data[point].right;
+                point = self.links[point].right;
                 started = true;
                 Some(out)
             }
@@ -306,7 +380,7 @@ impl Hull {
     }
 
     pub fn bucket_h(&self, h: HullIndex) -> usize {
-        self.bucket(self.data[h].angle)
+        self.bucket(self.angles[h])
     }
 
     /// Looks up what bucket a given pseudo-angle will fall into.
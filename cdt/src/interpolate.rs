@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    Error, Point, Triangulation,
+    predicates::{circumcenter, in_circle, orient2d},
+};
+
+/// Marks a triangle edge with no neighbor, i.e. on the convex hull
+const NO_NEIGHBOR: usize = usize::MAX;
+
+/// Twice the signed area of a triangle, computed in plain floating-point
+fn area2(a: Point, b: Point, c: Point) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Interpolates values sampled at scattered points, using their Delaunay
+/// triangulation.
+///
+/// Values are passed to each query as a slice with one value per input
+/// point, so the same interpolator can be reused for several fields (e.g.
+/// height and temperature over the same terrain samples).
+///
+/// ```
+/// let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.4, 0.6)];
+/// let heights = [0.0, 1.0, 2.0, 1.0, 1.0];
+/// let interp = cdt::Interpolator::new(&pts).unwrap();
+/// assert!(interp.linear((0.5, 0.5), &heights).is_some());
+/// assert!(interp.natural_neighbor((2.0, 0.5), &heights).is_none());
+/// ```
+#[derive(Debug)]
+pub struct Interpolator {
+    points: Vec<Point>,
+    /// Counter-clockwise triangles, as indexes into `points`
+    triangles: Vec<[usize; 3]>,
+    /// For each triangle, the triangle across edge `i` (from vertex `i` to
+    /// vertex `i + 1`), or `NO_NEIGHBOR`
+    neighbors: Vec<[usize; 3]>,
+}
+
+impl Interpolator {
+    /// Builds the Delaunay triangulation of the given points
+    ///
+    /// # Errors
+    /// Returns the same errors as [`Triangulation::build`]
+    pub fn new(points: &[Point]) -> Result<Self, Error> {
+        let t = Triangulation::build(points)?;
+        let triangles: Vec<[usize; 3]> = t.triangles()
+            .map(|(a, b, c)| [a, b, c])
+            .collect();
+
+        let mut edges = HashMap::new();
+        for (i, t) in triangles.iter().enumerate() {
+            for j in 0..3 {
+                edges.insert((t[j], t[(j + 1) % 3]), i);
+            }
+        }
+        let neighbors = triangles.iter()
+            .map(|t| {
+                let mut out = [NO_NEIGHBOR; 3];
+                for (j, n) in out.iter_mut().enumerate() {
+                    if let Some(k) = edges.get(&(t[(j + 1) % 3], t[j])) {
+                        *n = *k;
+                    }
+                }
+                out
+            })
+            .collect();
+        Ok(Interpolator { points: points.to_vec(), triangles, neighbors })
+    }
+
+    /// Finds a triangle containing the given point (on its interior or
+    /// boundary), returning `None` if the point is outside the convex hull
+    fn locate(&self, p: Point) -> Option<usize> {
+        // Walk towards the point, which terminates for a Delaunay
+        // triangulation and typically takes O(sqrt(n)) steps
+        let mut t = 0;
+        'walk: for _ in 0..self.triangles.len() {
+            let tri = self.triangles[t];
+            for j in 0..3 {
+                let a = self.points[tri[j]];
+                let b = self.points[tri[(j + 1) % 3]];
+                if orient2d(a, b, p) < 0.0 {
+                    t = self.neighbors[t][j];
+                    if t == NO_NEIGHBOR {
+                        return None;
+                    }
+                    continue 'walk;
+                }
+            }
+            return Some(t);
+        }
+        None
+    }
+
+    /// Returns the barycentric weights of `p` in triangle `t`
+    fn barycentric(&self, t: usize, p: Point) -> [f64; 3] {
+        let [a, b, c] = self.triangles[t].map(|i| self.points[i]);
+        let area = area2(a, b, c);
+        [area2(p, b, c) / area, area2(a, p, c) / area, area2(a, b, p) / area]
+    }
+
+    /// Interpolates linearly within the triangle containing `p`, returning
+    /// `None` if `p` is outside the convex hull of the input points
+    ///
+    /// # Panics
+    /// Panics if `values` has fewer items than the input points
+    pub fn linear(&self, p: Point, values: &[f64]) -> Option<f64> {
+        let t = self.locate(p)?;
+        let w = self.barycentric(t, p);
+        Some(self.triangles[t].iter()
+            .zip(&w)
+            .map(|(i, w)| values[*i] * w)
+            .sum())
+    }
+
+    /// Interpolates using Sibson's natural-neighbor coordinates, which give a
+    /// smooth result (away from the input points) that still reproduces
+    /// linear functions exactly.  Returns `None` if `p` is outside the convex
+    /// hull of the input points.
+    ///
+    /// Points exactly on a triangle edge are interpolated linearly, since
+    /// the natural-neighbor construction is degenerate there.
+    ///
+    /// # Panics
+    /// Panics if `values` has fewer items than the input points
+    pub fn natural_neighbor(&self, p: Point, values: &[f64]) -> Option<f64> {
+        let t0 = self.locate(p)?;
+        let tri = self.triangles[t0];
+        if let Some(i) = tri.iter().find(|i| self.points[**i] == p) {
+            return Some(values[*i]);
+        }
+        if (0..3).any(|j| orient2d(self.points[tri[j]],
+                                   self.points[tri[(j + 1) % 3]], p) == 0.0) {
+            return self.linear(p, values);
+        }
+
+        // Find every triangle whose circumcircle contains p, which would be
+        // removed if p were inserted into the triangulation
+        let mut cavity = HashSet::new();
+        let mut todo = vec![t0];
+        while let Some(t) = todo.pop() {
+            if t == NO_NEIGHBOR || cavity.contains(&t) {
+                continue;
+            }
+            let [a, b, c] = self.triangles[t].map(|i| self.points[i]);
+            if t == t0 || in_circle(a, b, c, p) > 0.0 {
+                cavity.insert(t);
+                todo.extend_from_slice(&self.neighbors[t]);
+            }
+        }
+
+        // Each neighbor's weight is the area which p's new Voronoi cell
+        // steals from the neighbor's cell.  This is accumulated by walking
+        // the stolen polygon around each neighbor: it passes through the old
+        // circumcenters of the cavity triangles, and is closed by the new
+        // circumcenters of p with each boundary edge.
+        let mut weights: HashMap<usize, f64> = HashMap::new();
+        let mut succ = HashMap::new();
+        let mut pred = HashMap::new();
+        for &t in &cavity {
+            let tri = self.triangles[t];
+            let [pa, pb, pc] = tri.map(|i| self.points[i]);
+            let center = circumcenter(pa, pb, pc);
+            for j in 0..3 {
+                let (a, b, c) = (tri[j], tri[(j + 1) % 3], tri[(j + 2) % 3]);
+                let (pa, pb, pc) = (self.points[a], self.points[b], self.points[c]);
+                let g_ab = circumcenter(p, pa, pb);
+                let g_ca = circumcenter(p, pc, pa);
+                *weights.entry(a).or_default() +=
+                    area2(pa, g_ab, center) + area2(pa, center, g_ca);
+
+                if !cavity.contains(&self.neighbors[t][j]) {
+                    succ.insert(a, g_ab);
+                    pred.insert(b, g_ab);
+                }
+            }
+        }
+        let mut total = 0.0;
+        let mut sum = 0.0;
+        for (i, w) in weights.iter_mut() {
+            *w += area2(self.points[*i], pred[i], succ[i]);
+            total += *w;
+            sum += *w * values[*i];
+        }
+        Some(sum / total)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on a jittered grid, with values from a linear function
+    fn jittered() -> (Vec<Point>, Vec<f64>) {
+        let mut pts = Vec::new();
+        for i in 0..8 {
+            for j in 0..8 {
+                let k = (i * 8 + j) as f64;
+                pts.push((i as f64 + 0.3 * (k * 1.7).sin(),
+                          j as f64 + 0.3 * (k * 2.3).cos()));
+            }
+        }
+        let values = pts.iter().map(|p| 2.0 * p.0 - 3.0 * p.1 + 1.0).collect();
+        (pts, values)
+    }
+
+    #[test]
+    fn linear_precision() {
+        let (pts, values) = jittered();
+        let interp = Interpolator::new(&pts).expect("Could not triangulate");
+        for &q in &[(3.5, 3.5), (1.25, 5.1), (6.0, 2.2), pts[20]] {
+            let expected = 2.0 * q.0 - 3.0 * q.1 + 1.0;
+            let a = interp.linear(q, &values).expect("Could not interpolate");
+            let b = interp.natural_neighbor(q, &values)
+                .expect("Could not interpolate");
+            assert!((a - expected).abs() < 1e-9);
+            assert!((b - expected).abs() < 1e-9);
+        }
+        assert!(interp.linear((-5.0, 3.0), &values).is_none());
+        assert!(interp.natural_neighbor((3.0, 50.0), &values).is_none());
+    }
+
+    #[test]
+    fn natural_neighbor_is_smooth() {
+        // A single raised point in a square: the natural-neighbor result
+        // is continuous and stays within the range of the data
+        let pts = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (1.0, 1.1)];
+        let values = [0.0, 0.0, 0.0, 0.0, 1.0];
+        let interp = Interpolator::new(&pts).expect("Could not triangulate");
+        let mut prev = None;
+        for i in 1..40 {
+            let q = (i as f64 / 20.0, 0.7);
+            let v = interp.natural_neighbor(q, &values)
+                .expect("Could not interpolate");
+            assert!((0.0..=1.0).contains(&v));
+            if let Some(prev) = prev {
+                let d: f64 = v - prev;
+                assert!(d.abs() < 0.1);
+            }
+            prev = Some(v);
+        }
+    }
+}
@@ -32,6 +32,71 @@ for t in triangles {
 }
 ```
 
+## Crossing constraints
+Fixed edges which cross each other are normally an error, but can instead
+be split at their intersections, which are returned to the caller
+```rust
+let pts = vec![(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)];
+let edges = [(0, 1), (1, 2), (2, 3), (3, 0)];
+let (triangles, crossings) = cdt::triangulate_with_crossing_edges(&pts, &edges)
+    .unwrap();
+assert!(crossings.len() == 1 && triangles.len() == 2);
+```
+
+## Polygon triangulation
+This triangulates a square with a triangular hole, without building the
+point and edge lists by hand
+```rust
+let outer = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+let hole = vec![(0.2, 0.2), (0.8, 0.2), (0.5, 0.8)];
+let (pts, triangles) = cdt::triangulate_polygon(&outer, &[hole]).unwrap();
+assert!(pts.len() == 7);
+for t in triangles {
+    assert!(t.0 < 7 && t.1 < 7 && t.2 < 7);
+}
+```
+
+## Polygon booleans
+Overlapping or self-intersecting rings can be cleaned up before
+triangulation by combining them with other rings (or with nothing)
+```rust
+use cdt::BooleanOp;
+let a = [vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]];
+let b = [vec![(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)]];
+let rings = cdt::polygon_boolean(&a, &b, BooleanOp::Union).unwrap();
+assert!(rings.len() == 1);
+let (_, triangles) = cdt::triangulate_polygon(&rings[0], &[] as &[Vec<_>]).unwrap();
+assert!(!triangles.is_empty());
+```
+
+## Convex hull
+The advancing front of the triangulation finishes as the points' convex hull
+```rust
+let pts = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.5, 0.5)];
+let hull = cdt::convex_hull(&pts).unwrap();
+assert!(hull.len() == 4);
+```
+
+## Parallel triangulation
+Very large point sets can be split into strips and triangulated on several
+threads, falling back to the serial algorithm for small inputs
+```rust
+let pts: Vec<_> = (0..10_000)
+    .map(|i| ((i as f64 * 0.618).fract(), (i as f64 * 0.414).fract()))
+    .collect();
+let triangles = cdt::triangulate_points_parallel(&pts, 4).unwrap();
+assert!(!triangles.is_empty());
+```
+With fixed edges, this only works if the edges are already part of the
+Delaunay triangulation, returning `None` otherwise (so that the caller can
+use the serial algorithm)
+```rust
+let pts = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+let edges = [(0, 1), (1, 2), (2, 3), (3, 0)];
+// Too few points to be worth splitting up
+assert!(cdt::triangulate_with_edges_parallel(&pts, &edges, 4).is_none());
+```
+
 # Crate features
 By default, the library uses `u32` indexes for internal data structures,
 to improve performance.  If you are planning to triangulate more than 500M
@@ -39,17 +104,29 @@ points in a single pass, you should enable the `long-indexes` feature.
 */
 
 #![warn(missing_docs)]
+pub(crate) mod boolean;
 pub(crate) mod contour;
+pub(crate) mod crossing;
+pub(crate) mod grid;
 pub(crate) mod predicates;
 pub(crate) mod half;
 pub(crate) mod hull;
 pub(crate) mod indexes;
+pub(crate) mod interpolate;
+pub(crate) mod parallel;
+pub(crate) mod polygon;
 pub(crate) mod triangulate;
-pub use triangulate::Triangulation;
+pub use boolean::{BooleanOp, polygon_boolean};
+pub use crossing::{Crossing, split_crossing_edges};
+pub use interpolate::Interpolator;
+pub use parallel::{triangulate_points_parallel, triangulate_with_edges_parallel};
+pub use polygon::{RingInfo, orient_rings, signed_area};
+pub use triangulate::{MemoryUsage, Triangulation};
 
 ////////////////////////////////////////////////////////////////////////////////
 // Common types for points and strongly-typed vectors
 type Point = (f64, f64);
+type Triangle = (usize, usize, usize);
 
 ////////////////////////////////////////////////////////////////////////////////
 /// Single error type for this library
@@ -69,6 +146,13 @@ pub enum Error {
     #[error("Fixed edges cross each other")]
     CrossingFixedEdge,
 
+    /// Returned in [strict mode](Triangulation::set_strict) when a fixed
+    /// edge doesn't appear in the finished triangulation (e.g. because it
+    /// wasn't part of a closed boundary).  The variables are the indexes of
+    /// the edge's points.
+    #[error("Fixed edge is missing from the triangulation")]
+    MissingFixedEdge(usize, usize),
+
     /// Returned when the input is empty
     #[error("input cannot be empty")]
     EmptyInput,
@@ -106,7 +190,16 @@ pub enum Error {
 /// Triangulates a set of points, returning triangles as triples of indexes
 /// into the original points list.  The resulting triangulation has a convex
 /// hull.
+///
+/// Points which form a (possibly distorted) grid, stored row by row (e.g.
+/// samples of a height field or parametric surface), are triangulated
+/// directly, which is much faster than the general algorithm.
 pub fn triangulate_points(pts: &[Point]) -> Result<Vec<(usize, usize, usize)>, Error> {
+    if pts.iter().all(|p| p.0.is_finite() && p.1.is_finite()) {
+        if let Some(t) = grid::triangulate_grid(pts) {
+            return Ok(t);
+        }
+    }
     let t = Triangulation::build(&pts)?;
     Ok(t.triangles().collect())
 }
@@ -133,6 +226,71 @@ pub fn triangulate_with_edges<'a, E>(pts: &[Point], edges: E)
     Ok(t.triangles().collect())
 }
 
+/// Triangulates a set of points with fixed edges, like
+/// [`triangulate_with_edges`], but splits edges which cross each other
+/// (using [`split_crossing_edges`]) instead of returning
+/// [`Error::CrossingFixedEdge`].
+///
+/// Returns triangles as indexes into `pts` followed by the inserted points,
+/// along with the inserted points themselves.
+pub fn triangulate_with_crossing_edges(pts: &[Point], edges: &[(usize, usize)])
+    -> Result<(Vec<Triangle>, Vec<Crossing>), Error>
+{
+    let (edges, crossings) = split_crossing_edges(pts, edges);
+    let pts: Vec<Point> = pts.iter()
+        .copied()
+        .chain(crossings.iter().map(|c| c.pos))
+        .collect();
+    let t = Triangulation::build_with_edges(&pts, &edges)?;
+    Ok((t.triangles().collect(), crossings))
+}
+
+/// Triangulates a polygon with holes, where each ring is a list of points
+/// (in either winding order, and optionally repeating the first point at
+/// the end).
+///
+/// Returns the points of `outer` followed by the points of each hole in
+/// turn, with any repeated closing points removed, along with triangles as
+/// indexes into those points.
+///
+/// This uses a constrained triangulation, falling back to ear clipping
+/// for polygons without holes if the triangulation fails.
+pub fn triangulate_polygon<V>(outer: &[Point], holes: &[V])
+    -> Result<(Vec<Point>, Vec<Triangle>), Error>
+    where V: AsRef<[Point]>
+{
+    let mut pts = Vec::new();
+    let mut contours = Vec::new();
+    for ring in std::iter::once(outer).chain(holes.iter().map(|h| h.as_ref())) {
+        let ring = match ring.split_last() {
+            Some((last, rest)) if ring.len() > 1 && *last == ring[0] => rest,
+            _ => ring,
+        };
+        if ring.len() < 3 {
+            return Err(Error::TooFewPoints);
+        }
+        let start = pts.len();
+        pts.extend_from_slice(ring);
+        contours.push((start..pts.len()).chain(std::iter::once(start))
+            .collect::<Vec<usize>>());
+    }
+    let tris = match Triangulation::build_from_contours(&pts, &contours) {
+        Ok(t) => t.triangles().collect(),
+        Err(e) if holes.is_empty() => polygon::ear_clip(&pts).ok_or(e)?,
+        Err(e) => return Err(e),
+    };
+    Ok((pts, tris))
+}
+
+/// Finds the convex hull of a set of points, returning indexes into the
+/// original points list in counter-clockwise order.  This runs a full
+/// triangulation, so use [`Triangulation::convex_hull`] if you also need
+/// the triangles.
+pub fn convex_hull(pts: &[Point]) -> Result<Vec<usize>, Error> {
+    let t = Triangulation::build(pts)?;
+    Ok(t.convex_hull())
+}
+
 /// Given a set of points and edges which are known to panic, figures out the
 /// max number of save steps, then saves an SVG right before the panic occurs
 pub fn save_debug_panic<'a, E>(pts: &[Point], edges: E, filename: &str)
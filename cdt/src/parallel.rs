@@ -0,0 +1,470 @@
+//! Divide-and-conquer Delaunay triangulation across several threads.
+//!
+//! Points are split into vertical strips, each of which is triangulated on
+//! its own thread.  A triangle whose circumcircle lies strictly within its
+//! strip can't contain points from any other strip, so it must be part of
+//! the final triangulation.  The remaining triangles form seams along the
+//! strip boundaries; their points (plus each strip's hull) are triangulated
+//! again, and the parts of that triangulation which fill the seams are
+//! stitched in.
+//!
+//! Where points are cocircular, the Delaunay triangulation isn't unique, so
+//! the strip and seam triangulations may disagree about edges where they
+//! meet; the triangles around such edges are moved into the seam and the
+//! seam is triangulated again.  The merged result is checked to be a valid
+//! triangulation of the convex hull, and the serial algorithm is used if
+//! that check fails.
+//!
+//! Fixed edges aren't inserted here: if every fixed edge is already an edge
+//! of the Delaunay triangulation (as with finely sampled boundaries), then
+//! it's also the constrained triangulation, and only needs the triangles
+//! outside the fixed edges removed.  Otherwise, the caller falls back to
+//! the serial algorithm.
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    Error, Point, Triangle, Triangulation,
+    predicates::{circumcenter, distance2, orient2d},
+};
+
+/// Below this many points per strip, threading overhead isn't worthwhile
+const MIN_STRIP_POINTS: usize = 1024;
+
+/// Number of attempts at resolving disagreements along the seams
+const MAX_ROUNDS: usize = 4;
+
+/// Triangulation of a single strip
+struct Strip {
+    /// Triangles which are guaranteed to be in the final triangulation
+    accepted: Vec<Triangle>,
+    /// Directed edges of accepted triangles which aren't shared with another
+    /// accepted triangle
+    boundary: Vec<(usize, usize)>,
+    /// Points which must be triangulated again to fill the seams
+    seam: Vec<usize>,
+}
+
+/// Checks whether a triangle's circumcircle lies strictly within the slab
+/// `lo < x < hi`, with a margin for floating-point error
+fn inside_slab(pts: &[Point], (a, b, c): Triangle, lo: f64, hi: f64) -> bool {
+    let (pa, pb, pc) = (pts[a], pts[b], pts[c]);
+    let longest = distance2(pa, pb).max(distance2(pb, pc)).max(distance2(pc, pa));
+    // Circumcenters of slivers are unreliable, so leave them for the seam
+    if orient2d(pa, pb, pc).abs() < 1e-6 * longest {
+        return false;
+    }
+    let center = circumcenter(pa, pb, pc);
+    let r = distance2(center, pa).sqrt();
+    let margin = 1e-9 * (r + center.0.abs());
+    center.0 - r > lo + margin && center.0 + r < hi - margin
+}
+
+fn triangulate_strip(pts: &[Point], indexes: &[usize], lo: f64, hi: f64)
+    -> Result<Strip, Error>
+{
+    let local: Vec<Point> = indexes.iter().map(|&i| pts[i]).collect();
+    let t = Triangulation::build(&local)?;
+    // Triangles are classified using local indexes, then converted at the end
+    let mut accepted = Vec::new();
+    let mut seam_tris = Vec::new();
+    let mut in_seam = vec![false; local.len()];
+    for (a, b, c) in t.triangles() {
+        if inside_slab(&local, (a, b, c), lo, hi) {
+            accepted.push((a, b, c));
+        } else {
+            in_seam[a] = true;
+            in_seam[b] = true;
+            in_seam[c] = true;
+            seam_tris.push((a, b, c));
+        }
+    }
+    // Points on the strip's hull may connect to other strips' points
+    let hull: Vec<usize> = t.hull_points().collect();
+    for &i in &hull {
+        in_seam[i] = true;
+    }
+
+    // An accepted triangle's edge is on the boundary if the triangle across
+    // from it is in the seam, or if there's no triangle across from it.
+    // Either way, both of its points are in the seam.
+    let seam_edges = edges(&seam_tris);
+    let hull_edges: HashSet<(usize, usize)> = hull.iter()
+        .zip(hull.iter().cycle().skip(1))
+        .flat_map(|(&a, &b)| [(a, b), (b, a)])
+        .collect();
+    let mut boundary = Vec::new();
+    for &(a, b, c) in &accepted {
+        for e in [(a, b), (b, c), (c, a)] {
+            if in_seam[e.0] && in_seam[e.1] &&
+                (seam_edges.contains_key(&(e.1, e.0)) || hull_edges.contains(&e))
+            {
+                boundary.push((indexes[e.0], indexes[e.1]));
+            }
+        }
+    }
+    let out = Strip {
+        accepted: accepted.into_iter()
+            .map(|(a, b, c)| (indexes[a], indexes[b], indexes[c]))
+            .collect(),
+        boundary,
+        seam: (0..local.len()).filter(|i| in_seam[*i]).map(|i| indexes[i]).collect(),
+    };
+    Ok(out)
+}
+
+/// Returns the directed edges of a set of counter-clockwise triangles,
+/// mapped to the vertex opposite each edge
+fn edges(tris: &[Triangle]) -> HashMap<(usize, usize), usize> {
+    let mut out = HashMap::with_capacity(tris.len() * 3);
+    for &(a, b, c) in tris {
+        out.insert((a, b), c);
+        out.insert((b, c), a);
+        out.insert((c, a), b);
+    }
+    out
+}
+
+/// Returns the directed edges of a triangulation which have no twin
+fn unpaired(e: &HashMap<(usize, usize), usize>) -> HashSet<(usize, usize)> {
+    e.keys().filter(|(a, b)| !e.contains_key(&(*b, *a))).copied().collect()
+}
+
+/// Attempts a parallel triangulation, returning `None` if the strips
+/// couldn't be merged into a valid triangulation
+fn merge(pts: &[Point], strips: usize) -> Option<Vec<Triangle>> {
+    let mut order: Vec<usize> = (0..pts.len()).collect();
+    order.sort_unstable_by(|&a, &b| pts[a].partial_cmp(&pts[b]).unwrap());
+
+    // Bounds are shared between neighboring strips, so that points with
+    // the same x coordinate never end up strictly inside another slab
+    let chunk = pts.len().div_ceil(strips);
+    let chunks: Vec<&[usize]> = order.chunks(chunk).collect();
+    let bound = |k: usize| if k == 0 {
+        -f64::INFINITY
+    } else if k == chunks.len() {
+        f64::INFINITY
+    } else {
+        pts[chunks[k][0]].0
+    };
+
+    let results: Vec<Result<Strip, Error>> = std::thread::scope(|s| {
+        let handles: Vec<_> = chunks.iter().enumerate()
+            .map(|(k, c)| {
+                let (lo, hi) = (bound(k), bound(k + 1));
+                s.spawn(move || triangulate_strip(pts, c, lo, hi))
+            })
+            .collect();
+        handles.into_iter()
+            .map(|h| h.join().unwrap_or(Err(Error::CannotInitialize)))
+            .collect()
+    });
+    let mut accepted = Vec::new();
+    let mut boundary = HashSet::new();
+    let mut in_seam = vec![false; pts.len()];
+    for r in results {
+        let r = r.ok()?;
+        accepted.extend(r.accepted);
+        boundary.extend(r.boundary);
+        for i in r.seam {
+            in_seam[i] = true;
+        }
+    }
+
+    // The strips and the seam are triangulated independently, so they may
+    // disagree about edges along the seam boundary (e.g. where points are
+    // cocircular).  In that case, the accepted triangles touching the
+    // disputed edges are moved into the seam and we try again.
+    for _ in 0..MAX_ROUNDS {
+        let seam: Vec<usize> = (0..pts.len()).filter(|i| in_seam[*i]).collect();
+        let seam_pts: Vec<Point> = seam.iter().map(|&i| pts[i]).collect();
+        let seam_tris: Vec<Triangle> = Triangulation::build(&seam_pts).ok()?
+            .triangles()
+            .map(|(a, b, c)| (seam[a], seam[b], seam[c]))
+            .collect();
+        if accepted.is_empty() {
+            return Some(seam_tris);
+        }
+
+        let seam_edges = edges(&seam_tris);
+        let mut tri_of: HashMap<(usize, usize), usize> = HashMap::new();
+        for (i, &(a, b, c)) in seam_tris.iter().enumerate() {
+            tri_of.insert((a, b), i);
+            tri_of.insert((b, c), i);
+            tri_of.insert((c, a), i);
+        }
+
+        // Flood-fill the seam triangulation, starting on the far side of
+        // each boundary edge of the accepted triangles and never crossing
+        // into them
+        let mut todo = Vec::new();
+        let mut walls = HashSet::new();
+        let mut disputed = HashSet::new();
+        for &(a, b) in &boundary {
+            if let Some(t) = tri_of.get(&(b, a)) {
+                todo.push(*t);
+                walls.insert((b, a));
+            } else if !seam_edges.contains_key(&(a, b)) {
+                disputed.insert((a, b));
+            }
+        }
+        if !disputed.is_empty() {
+            accepted.retain(|&(a, b, c)| {
+                let tri = [(a, b), (b, c), (c, a)];
+                if !tri.iter().any(|e| disputed.contains(e)) {
+                    return true;
+                }
+                // Edges shared with accepted neighbors become boundary
+                // edges, and the triangle's own boundary edges disappear
+                for e in tri {
+                    if !boundary.remove(&e) {
+                        boundary.insert((e.1, e.0));
+                    }
+                }
+                in_seam[a] = true;
+                in_seam[b] = true;
+                in_seam[c] = true;
+                false
+            });
+            continue;
+        }
+
+        let mut filled = vec![false; seam_tris.len()];
+        while let Some(t) = todo.pop() {
+            if filled[t] {
+                continue;
+            }
+            filled[t] = true;
+            let (a, b, c) = seam_tris[t];
+            for &e in &[(a, b), (b, c), (c, a)] {
+                if !walls.contains(&e) {
+                    if let Some(n) = tri_of.get(&(e.1, e.0)) {
+                        todo.push(*n);
+                    }
+                }
+            }
+        }
+        let filled: Vec<Triangle> = seam_tris.iter()
+            .zip(&filled)
+            .filter(|(_, f)| **f)
+            .map(|(t, _)| *t)
+            .collect();
+
+        // Check that the result is a valid triangulation of the convex hull:
+        // the filled and accepted regions share their boundaries exactly,
+        // except along the seam triangulation's hull (which is the hull of
+        // the whole point set).
+        let filled_boundary = unpaired(&edges(&filled));
+        let outer: HashSet<(usize, usize)> = boundary.iter()
+            .filter(|(a, b)| !filled_boundary.contains(&(*b, *a)))
+            .chain(filled_boundary.iter()
+                .filter(|(a, b)| !boundary.contains(&(*b, *a))))
+            .copied()
+            .collect();
+        if outer != unpaired(&seam_edges) {
+            return None;
+        }
+        let mut out = accepted;
+        out.extend(filled);
+        return Some(out);
+    }
+    None
+}
+
+/// Triangulates a set of points using several threads, returning triangles
+/// as triples of indexes into the original points list (like
+/// [`triangulate_points`](crate::triangulate_points)).
+///
+/// This is worthwhile for inputs with hundreds of thousands of points or
+/// more; smaller inputs use fewer threads, down to the serial algorithm.
+pub fn triangulate_points_parallel(pts: &[Point], threads: usize)
+    -> Result<Vec<Triangle>, Error>
+{
+    let strips = threads.min(pts.len() / MIN_STRIP_POINTS);
+    if strips > 1 && pts.iter().all(|p| p.0.is_finite() && p.1.is_finite()) {
+        if let Some(t) = merge(pts, strips) {
+            return Ok(t);
+        }
+    }
+    crate::triangulate_points(pts)
+}
+
+/// Keeps the triangles which are inside the fixed edges, counting crossings
+/// from the hull (with edges that are fixed twice cancelling out, like the
+/// serial algorithm).  Returns `None` if a fixed edge isn't in the
+/// triangulation.
+fn fill(tris: &[Triangle], fixed: &[(usize, usize)]) -> Option<Vec<Triangle>> {
+    let mut tri_of: HashMap<(usize, usize), usize> = HashMap::with_capacity(tris.len() * 3);
+    for (i, &(a, b, c)) in tris.iter().enumerate() {
+        tri_of.insert((a, b), i);
+        tri_of.insert((b, c), i);
+        tri_of.insert((c, a), i);
+    }
+    let mut odd: HashMap<(usize, usize), bool> = HashMap::with_capacity(fixed.len());
+    for &(a, b) in fixed {
+        if !tri_of.contains_key(&(a, b)) && !tri_of.contains_key(&(b, a)) {
+            return None;
+        }
+        *odd.entry((a.min(b), a.max(b))).or_insert(false) ^= true;
+    }
+    let boundary = |a: usize, b: usize| odd.get(&(a.min(b), a.max(b))) == Some(&true);
+
+    // Flood-fill from every hull edge, toggling at each boundary crossed
+    let mut todo = Vec::new();
+    for (i, &(a, b, c)) in tris.iter().enumerate() {
+        for (x, y) in [(a, b), (b, c), (c, a)] {
+            if !tri_of.contains_key(&(y, x)) {
+                todo.push((i, boundary(x, y)));
+            }
+        }
+    }
+    let mut inside = vec![None; tris.len()];
+    while let Some((i, v)) = todo.pop() {
+        if inside[i].is_some() {
+            continue;
+        }
+        inside[i] = Some(v);
+        let (a, b, c) = tris[i];
+        for (x, y) in [(a, b), (b, c), (c, a)] {
+            if let Some(&n) = tri_of.get(&(y, x)) {
+                todo.push((n, v ^ boundary(x, y)));
+            }
+        }
+    }
+    Some(tris.iter()
+        .zip(&inside)
+        .filter(|(_, i)| **i == Some(true))
+        .map(|(t, _)| *t)
+        .collect())
+}
+
+/// Triangulates a set of points with fixed edges using several threads,
+/// returning the triangles inside the edges (like
+/// [`Triangulation::build_with_edges`]).
+///
+/// This only works if every fixed edge is already an edge of the points'
+/// Delaunay triangulation, which is usually true of finely sampled
+/// boundaries.  Returns `None` if a fixed edge is missing (e.g. because it
+/// crosses another edge or passes through a point), if the input is too
+/// small to be worth splitting up, or if the strips can't be merged; the
+/// caller should then use the serial algorithm, which also reports what was
+/// wrong with the input.
+pub fn triangulate_with_edges_parallel(pts: &[Point], edges: &[(usize, usize)],
+                                       threads: usize)
+    -> Option<Vec<Triangle>>
+{
+    let strips = threads.min(pts.len() / MIN_STRIP_POINTS);
+    if strips < 2 || !pts.iter().all(|p| p.0.is_finite() && p.1.is_finite()) {
+        return None;
+    }
+    fill(&merge(pts, strips)?, edges)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    /// Total area of a set of triangles, asserting that each one is
+    /// counter-clockwise and that no edge is shared in the same direction
+    fn area(pts: &[Point], tris: &[Triangle]) -> f64 {
+        assert_eq!(edges(tris).len(), tris.len() * 3);
+        tris.iter()
+            .map(|&(a, b, c)| {
+                let o = orient2d(pts[a], pts[b], pts[c]);
+                assert!(o > 0.0);
+                o / 2.0
+            })
+            .sum()
+    }
+
+    /// Sorts triangles, each rotated to start from its smallest index
+    fn canonical(tris: Vec<Triangle>) -> Vec<Triangle> {
+        let mut out: Vec<Triangle> = tris.into_iter()
+            .map(|(a, b, c)| if a < b && a < c {
+                (a, b, c)
+            } else if b < c {
+                (b, c, a)
+            } else {
+                (c, a, b)
+            })
+            .collect();
+        out.sort_unstable();
+        out
+    }
+
+    #[test]
+    fn matches_serial() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(12345);
+        let pts: Vec<Point> = (0..20_000)
+            .map(|_| (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)))
+            .collect();
+        let serial = crate::triangulate_points(&pts).expect("Could not triangulate");
+
+        // Random points have a unique Delaunay triangulation, so both
+        // should find the same triangles
+        let merged = merge(&pts, 8).expect("Could not merge strips");
+        assert_eq!(merged.len(), serial.len());
+        let (a, b) = (area(&pts, &merged), area(&pts, &serial));
+        assert!((a - b).abs() < 1e-9);
+        assert_eq!(canonical(merged), canonical(serial));
+    }
+
+    #[test]
+    fn falls_back() {
+        // Exactly cocircular points may not merge, but still triangulate
+        let mut pts = Vec::new();
+        for i in 0..100 {
+            for j in 0..50 {
+                pts.push((i as f64, j as f64));
+            }
+        }
+        let serial = crate::triangulate_points(&pts).expect("Could not triangulate");
+        let t = triangulate_points_parallel(&pts, 4).expect("Could not triangulate");
+        assert_eq!(t.len(), serial.len());
+    }
+
+    #[test]
+    fn fixed_edges() {
+        // A finely sampled square with a square hole, and random points
+        // between them (away from the edges, which are then Delaunay)
+        let mut pts = Vec::new();
+        let mut fixed = Vec::new();
+        for (lo, hi, n) in [(0.0, 1.0, 400), (0.4, 0.6, 80)] {
+            let start = pts.len();
+            for i in 0..4 * n {
+                let f = (i % n) as f64 / n as f64 * (hi - lo);
+                pts.push(match i / n {
+                    0 => (lo + f, lo),
+                    1 => (hi, lo + f),
+                    2 => (hi - f, hi),
+                    _ => (lo, hi - f),
+                });
+                fixed.push((start + i, start + (i + 1) % (4 * n)));
+            }
+        }
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(12345);
+        while pts.len() < 20_000 {
+            let p = (rng.gen_range(0.01..0.99), rng.gen_range(0.01..0.99));
+            if !(0.39..0.61).contains(&p.0) || !(0.39..0.61).contains(&p.1) {
+                pts.push(p);
+            }
+        }
+        let serial = crate::triangulate_with_edges(&pts, &fixed)
+            .expect("Could not triangulate");
+        let t = triangulate_with_edges_parallel(&pts, &fixed, 8)
+            .expect("Could not triangulate in parallel");
+        // Points around the corners are cocircular, so the triangles may
+        // differ, but both should fill the same region and keep every edge
+        assert_eq!(t.len(), serial.len());
+        assert!((area(&pts, &t) - 0.96).abs() < 1e-9);
+        assert!((area(&pts, &serial) - 0.96).abs() < 1e-9);
+        let e = edges(&t);
+        assert!(fixed.iter().all(|&(a, b)| e.contains_key(&(a, b)) || e.contains_key(&(b, a))));
+
+        // An edge which isn't Delaunay falls back to the serial algorithm
+        fixed.push((0, 1200));
+        assert!(triangulate_with_edges_parallel(&pts, &fixed, 8).is_none());
+    }
+}
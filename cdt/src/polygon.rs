@@ -0,0 +1,230 @@
+use crate::{Point, predicates::orient2d};
+
+/// Checks whether the corner at `ring[i]` is an ear, i.e. convex and with
+/// no other vertex of the ring inside (or on) the triangle it cuts off
+fn is_ear(pts: &[Point], ring: &[usize], i: usize) -> bool {
+    let m = ring.len();
+    let (a, b, c) = (ring[(i + m - 1) % m], ring[i], ring[(i + 1) % m]);
+    let (pa, pb, pc) = (pts[a], pts[b], pts[c]);
+    orient2d(pa, pb, pc) > 0.0 && ring.iter()
+        .filter(|&&p| p != a && p != b && p != c)
+        .all(|&p| {
+            let pp = pts[p];
+            orient2d(pa, pb, pp) < 0.0 || orient2d(pb, pc, pp) < 0.0 ||
+                orient2d(pc, pa, pp) < 0.0
+        })
+}
+
+/// Triangulates a simple polygon by ear clipping, returning triangles as
+/// counter-clockwise triples of indexes into `pts` (which may be given in
+/// either winding order).  This is O(n²), but is more forgiving than the
+/// constrained triangulation of nearly-degenerate input.
+///
+/// Returns `None` if no ear can be found, which may happen if the polygon
+/// intersects itself.
+pub(crate) fn ear_clip(pts: &[Point]) -> Option<Vec<(usize, usize, usize)>> {
+    let n = pts.len();
+    if n < 3 {
+        return None;
+    }
+    let mut ring: Vec<usize> = (0..n).collect();
+    if signed_area(pts) < 0.0 {
+        ring.reverse();
+    }
+
+    let mut out = Vec::with_capacity(n - 2);
+    while ring.len() > 3 {
+        let m = ring.len();
+        let corner = |i: usize| (ring[(i + m - 1) % m], ring[i], ring[(i + 1) % m]);
+        if let Some(i) = (0..m).find(|&i| is_ear(pts, &ring, i)) {
+            out.push(corner(i));
+            ring.remove(i);
+        } else if let Some(i) = (0..m).find(|&i| {
+            let (a, b, c) = corner(i);
+            orient2d(pts[a], pts[b], pts[c]) == 0.0
+        }) {
+            // Collinear vertices never form ears, but can be dropped without
+            // leaving a gap, since the triangle they'd form has zero area
+            ring.remove(i);
+        } else {
+            return None;
+        }
+    }
+    if orient2d(pts[ring[0]], pts[ring[1]], pts[ring[2]]) > 0.0 {
+        out.push((ring[0], ring[1], ring[2]));
+    }
+    Some(out)
+}
+
+/// Nesting and orientation of a ring, from [`orient_rings`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RingInfo {
+    /// Signed area of the ring (positive if it was counter-clockwise before
+    /// being oriented)
+    pub area: f64,
+    /// Number of other rings which contain this one
+    pub depth: usize,
+    /// The innermost ring containing this one
+    pub parent: Option<usize>,
+    /// Whether the ring was reversed
+    pub reversed: bool,
+}
+
+/// Returns the signed area of a ring (which may repeat its first point),
+/// which is positive if it's wound counter-clockwise
+pub fn signed_area(ring: &[Point]) -> f64 {
+    let n = ring.len();
+    (0..n).map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f64>() / 2.0
+}
+
+/// Checks whether a point is inside a ring, returning `None` if it's exactly
+/// on the ring's boundary
+fn contains(ring: &[Point], p: Point) -> Option<bool> {
+    let n = ring.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (ring[i], ring[(i + 1) % n]);
+        let o = orient2d(a, b, p);
+        if o == 0.0 && p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) &&
+            p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+        {
+            return None;
+        }
+        // Count crossings of a ray in the +x direction
+        if (a.1 > p.1) != (b.1 > p.1) && (o > 0.0) == (b.1 > a.1) {
+            inside = !inside;
+        }
+    }
+    Some(inside)
+}
+
+/// Checks whether ring `a` is inside ring `b`, assuming that they don't
+/// cross (though they may touch).  Vertices of `a` are tested in turn, then
+/// the midpoints of its edges, until one is found which isn't on `b`.
+fn inside_ring(a: &[Point], b: &[Point]) -> bool {
+    let n = a.len();
+    a.iter()
+        .copied()
+        .chain((0..n).map(|i| {
+            let (p, q) = (a[i], a[(i + 1) % n]);
+            ((p.0 + q.0) / 2.0, (p.1 + q.1) / 2.0)
+        }))
+        .find_map(|p| contains(b, p))
+        .unwrap_or(false)
+}
+
+/// Finds how a set of rings are nested, then reverses any rings which are
+/// wound the wrong way: outer boundaries (at even depths) should be
+/// counter-clockwise, and holes (at odd depths) should be clockwise.
+///
+/// Rings are assumed not to cross each other (see
+/// [`polygon_boolean`](crate::polygon_boolean) for cleaning up rings which
+/// do).  Rings with zero area are left as-is.
+pub fn orient_rings(rings: &mut [Vec<Point>]) -> Vec<RingInfo> {
+    let bbox = |r: &[Point]| r.iter().fold(
+        (f64::INFINITY, f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        |b, p| (b.0.min(p.0), b.1.min(p.1), b.2.max(p.0), b.3.max(p.1)));
+    let boxes: Vec<_> = rings.iter().map(|r| bbox(r)).collect();
+    let areas: Vec<f64> = rings.iter().map(|r| signed_area(r)).collect();
+
+    let mut out = Vec::with_capacity(rings.len());
+    for (i, ring) in rings.iter().enumerate() {
+        let (mut depth, mut parent) = (0usize, None::<usize>);
+        for (j, other) in rings.iter().enumerate() {
+            let (a, b) = (boxes[i], boxes[j]);
+            if i == j || areas[j] == 0.0 || a.0 < b.0 || a.1 < b.1 ||
+                a.2 > b.2 || a.3 > b.3 || !inside_ring(ring, other)
+            {
+                continue;
+            }
+            depth += 1;
+            if parent.is_none_or(|p| areas[j].abs() < areas[p].abs()) {
+                parent = Some(j);
+            }
+        }
+        let area = areas[i];
+        let reversed = area != 0.0 && (area > 0.0) != depth.is_multiple_of(2);
+        out.push(RingInfo { area, depth, parent, reversed });
+    }
+    for (ring, info) in rings.iter_mut().zip(&out) {
+        if info.reversed {
+            ring.reverse();
+        }
+    }
+    out
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l_shape() {
+        // Clockwise, with a collinear point along the bottom edge
+        let pts = [(0.0, 0.0), (0.0, 2.0), (1.0, 2.0), (1.0, 1.0),
+                   (2.0, 1.0), (2.0, 0.0), (1.0, 0.0)];
+        let tris = ear_clip(&pts).expect("Could not triangulate");
+        let area: f64 = tris.iter()
+            .map(|&(a, b, c)| orient2d(pts[a], pts[b], pts[c]) / 2.0)
+            .sum();
+        assert!(tris.iter().all(|&(a, b, c)| orient2d(pts[a], pts[b], pts[c]) > 0.0));
+        assert_eq!(area, 3.0);
+    }
+
+    #[test]
+    fn with_hole() {
+        // Closed rings, with the hole wound clockwise
+        let outer = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)];
+        let hole = vec![(0.5, 0.5), (0.5, 1.5), (1.5, 1.5), (1.5, 0.5)];
+        let (pts, tris) = crate::triangulate_polygon(&outer, std::slice::from_ref(&hole))
+            .expect("Could not triangulate");
+        // The outer ring's closing point is dropped, so the hole starts at 4
+        assert_eq!(pts.len(), 8);
+        assert_eq!(pts[4..], hole[..]);
+        assert!(tris.iter().all(|&(a, b, c)| a < 8 && b < 8 && c < 8));
+        let area: f64 = tris.iter()
+            .map(|&(a, b, c)| orient2d(pts[a], pts[b], pts[c]).abs() / 2.0)
+            .sum();
+        assert_eq!(area, 3.0);
+    }
+
+    #[test]
+    fn orient_nested() {
+        // Outer ring clockwise, hole counter-clockwise, and an island inside
+        // the hole which is already correct
+        let square = |x: f64, size: f64| vec![(x, x), (x + size, x),
+                                              (x + size, x + size), (x, x + size)];
+        let mut outer = square(0.0, 6.0);
+        outer.reverse();
+        let mut rings = vec![outer, square(1.0, 4.0), square(2.0, 2.0),
+                             square(10.0, 1.0)];
+        let info = orient_rings(&mut rings);
+        assert_eq!(info.iter().map(|i| i.depth).collect::<Vec<_>>(), [0, 1, 2, 0]);
+        assert_eq!(info.iter().map(|i| i.parent).collect::<Vec<_>>(),
+                   [None, Some(0), Some(1), None]);
+        assert_eq!(info.iter().map(|i| i.reversed).collect::<Vec<_>>(),
+                   [true, true, false, false]);
+        assert_eq!(info[0].area, -36.0);
+        assert!(rings.iter().zip(&info)
+            .all(|(r, i)| (signed_area(r) > 0.0) == (i.depth % 2 == 0)));
+    }
+
+    #[test]
+    fn orient_touching() {
+        // The hole shares a vertex with the outer ring
+        let mut rings = vec![
+            vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)],
+            vec![(0.0, 0.0), (1.0, 0.5), (0.5, 1.0)],
+        ];
+        let info = orient_rings(&mut rings);
+        assert_eq!(info[1].depth, 1);
+        assert!(info[1].reversed);
+        assert!(signed_area(&rings[1]) < 0.0);
+    }
+}
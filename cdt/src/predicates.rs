@@ -55,3 +55,14 @@ pub fn distance2(a: Point, b: Point) -> f64 {
     let dy = a.1 - b.1;
     dx*dx + dy*dy
 }
+
+/// Returns the center of the circle through three points, which must not be
+/// collinear
+pub fn circumcenter(a: Point, b: Point, c: Point) -> Point {
+    let (bx, by) = (b.0 - a.0, b.1 - a.1);
+    let (cx, cy) = (c.0 - a.0, c.1 - a.1);
+    let d = 2.0 * (bx * cy - by * cx);
+    let b2 = bx * bx + by * by;
+    let c2 = cx * cx + cy * cy;
+    (a.0 + (cy * b2 - by * c2) / d, a.1 + (bx * c2 - cx * b2) / d)
+}
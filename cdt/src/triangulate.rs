@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use crate::{
     contour::{Contour, ContourData},
     Error, Point,
@@ -12,6 +14,17 @@ enum Walk {
     Done(EdgeIndex),
 }
 
+/// Bytes allocated by each of a [`Triangulation`]'s data structures
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryUsage {
+    /// Sorted points, with their pseudo-angles and fixed edge endings
+    pub points: usize,
+    /// The sweep-line hull, including its per-point lookup table
+    pub hull: usize,
+    /// The half-edge data structure
+    pub half_edges: usize,
+}
+
 /// This `struct` contains all of the data needed to generate a (constrained)
 /// Delaunay triangulation of a set of input points and edges.  It is a
 /// **low-level** API; consider using the module-level functions if you don't
@@ -22,6 +35,8 @@ pub struct Triangulation {
     remap: PointVec<usize>,         // self.points[i] = input[self.remap[i]]
     next: PointIndex,               // Progress of the triangulation
     constrained: bool,
+    strict: bool,                   // Check fixed edges when finished
+    keep_outside: bool,             // Skip erasing triangles outside edges
 
     // If a point p terminates fixed edges, then endings[p] will be a tuple
     // range into ending_data containing the starting points of those edges.
@@ -256,6 +271,8 @@ impl Triangulation {
             hull: Hull::new(sorted_points.len(), has_edges),
             half: Half::new(sorted_points.len()),
             constrained: has_edges,
+            strict: false,
+            keep_outside: false,
 
             remap: map_reverse,
             next: PointIndex::new(0),
@@ -383,11 +400,46 @@ impl Triangulation {
         Self::new_with_edges(&pts, &edges)
     }
 
+    /// Enables or disables strict mode.  In strict mode, the final step of
+    /// the triangulation checks that every fixed edge appears in the result
+    /// (fixed edges are never flipped, but may be erased along with the
+    /// triangles around them if they don't form closed boundaries).
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Keeps triangles outside of the fixed edges' boundaries when
+    /// finishing a constrained triangulation, so that the result covers the
+    /// points' convex hull
+    pub(crate) fn set_keep_outside(&mut self, keep: bool) {
+        self.keep_outside = keep;
+    }
+
+    /// Checks that every fixed edge from the constructor is present in the
+    /// triangulation, returning [`Error::MissingFixedEdge`] otherwise
+    fn check_fixed_edges(&self) -> Result<(), Error> {
+        let fixed: HashSet<_> = self.half.iter_edges()
+            .filter(|e| e.2)
+            .map(|(a, b, _)| (a.0.min(b.0), a.0.max(b.0)))
+            .collect();
+        for (dst, &(start, end)) in self.endings.iter().enumerate() {
+            let dst = PointIndex::new(dst);
+            for &src in &self.ending_data[start..end] {
+                if !fixed.contains(&(src.0.min(dst.0), src.0.max(dst.0))) {
+                    return Err(Error::MissingFixedEdge(self.remap[src],
+                                                       self.remap[dst]));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Runs the triangulation algorithm until completion
     ///
     /// # Errors
     /// This may return [`Error::PointOnFixedEdge`], [`Error::NoMorePoints`],
-    /// or [`Error::CrossingFixedEdge`] if those error conditions are met.
+    /// or [`Error::CrossingFixedEdge`] if those error conditions are met, or
+    /// [`Error::MissingFixedEdge`] in [strict mode](Self::set_strict).
     pub fn run(&mut self) -> Result<(), Error> {
         while !self.done() {
             self.step()?;
@@ -466,14 +518,15 @@ impl Triangulation {
     fn finalize(&mut self) {
         assert!(self.next == self.points.len());
 
-        if self.constrained {
+        if self.constrained && !self.keep_outside {
             // For a constrained triangulation, flood fill and erase triangles
             // that are outside the shape boundaries.
             let h = self.hull.start();
             let e = self.hull.edge(h);
             self.half.flood_erase_from(e);
         } else {
-            // For an unconstrained triangulation, make the outer hull convex
+            // For an unconstrained triangulation (or when keeping triangles
+            // outside the fixed edges), make the outer hull convex
             self.make_outer_hull_convex();
         }
 
@@ -488,6 +541,14 @@ impl Triangulation {
     pub fn check(&self) {
         self.hull.check();
         self.half.check();
+
+        // Each point on the hull should be the destination of its hull edge,
+        // and linked to its neighbors in both directions
+        for (p, e) in self.hull.points().zip(self.hull.values()) {
+            assert!(self.half.edge(e).dst == p);
+            let next = self.hull.next(p).expect("Point should be on hull");
+            assert!(self.hull.prev(next) == Some(p));
+        }
     }
 
     /// Advances the triangulation by one step.
@@ -500,6 +561,9 @@ impl Triangulation {
             return Err(Error::NoMorePoints);
         } else if self.next == self.points.len() {
             self.finalize();
+            if self.strict {
+                self.check_fixed_edges()?;
+            }
             return Ok(());
         }
 
@@ -627,7 +691,7 @@ impl Triangulation {
                 // h_ca has the same X position as c-p, so we update the same
                 // slot in the hull, then move the point in the look-up table.
                 self.hull.update(h_ca, self.half.next(g));
-                self.hull.move_point(a, p);
+                self.hull.move_point(h_ca, p);
 
                 // Legalize the two new triangle edges
                 self.legalize(f);
@@ -1079,7 +1143,9 @@ impl Triangulation {
          *           d
          *  We check whether d is within the circumcircle of abc.
          *  If so, then we flip the edge and recurse based on the triangles
-         *  across from edges ad and db.
+         *  across from edges ad and db, as well as bc and ca: when a new
+         *  point is inserted at c, its own edges are already legal, but
+         *  that isn't true when filling in triangles along the hull.
          *
          *  This function may be called with a half-empty edge, e.g. while
          *  recursing; in that case, then return immediately.
@@ -1100,10 +1166,14 @@ impl Triangulation {
                      self.points[d]) > 0.0
         {
             let e_db = self.half.prev(e_ba);
+            let e_bc = self.half.next(e_ab);
+            let e_ca = self.half.prev(e_ab);
 
             self.half.swap(e_ab);
             self.legalize(e_ad);
             self.legalize(e_db);
+            self.legalize(e_bc);
+            self.legalize(e_ca);
         }
     }
 
@@ -1128,6 +1198,58 @@ impl Triangulation {
                 (self.remap[a], self.remap[b], self.remap[c]))
     }
 
+    /// Returns the convex hull of the input points, as indexes into the
+    /// original points list in counter-clockwise order.  Points which lie
+    /// exactly on a straight section of the hull are not included.
+    ///
+    /// # Panics
+    /// Panics if the triangulation isn't [done](Triangulation::done), since
+    /// the advancing front only becomes convex in the final step.
+    pub fn convex_hull(&self) -> Vec<usize> {
+        assert!(self.done(), "Triangulation is not complete");
+        let pts: Vec<PointIndex> = self.hull.points().collect();
+        let n = pts.len();
+        let mut out: Vec<usize> = (0..n)
+            .filter(|&i| self.orient2d(pts[(i + n - 1) % n], pts[i],
+                                       pts[(i + 1) % n]) != 0.0)
+            .map(|i| self.remap[pts[i]])
+            .collect();
+        if self.hull_is_clockwise(&pts) {
+            out.reverse();
+        }
+        out
+    }
+
+    /// Returns every point on the hull, including points along its straight
+    /// sections, as indexes into the original points list
+    pub(crate) fn hull_points(&self) -> impl Iterator<Item=usize> + '_ {
+        self.hull.points().map(move |p| self.remap[p])
+    }
+
+    /// Checks the winding of a convex loop of points, using the corner
+    /// with the sharpest turn for robustness
+    fn hull_is_clockwise(&self, pts: &[PointIndex]) -> bool {
+        let n = pts.len();
+        (0..n)
+            .map(|i| self.orient2d(pts[(i + n - 1) % n], pts[i], pts[(i + 1) % n]))
+            .fold(0.0, |a: f64, o| if o.abs() > a.abs() { o } else { a }) < 0.0
+    }
+
+    /// Returns the number of bytes allocated by the triangulation's data
+    /// structures, which is useful for tracking memory costs of large inputs
+    pub fn memory_usage(&self) -> MemoryUsage {
+        use std::mem::size_of;
+        MemoryUsage {
+            points: self.points.capacity() * size_of::<Point>()
+                + self.angles.capacity() * size_of::<f64>()
+                + self.remap.capacity() * size_of::<usize>()
+                + self.endings.capacity() * size_of::<(usize, usize)>()
+                + self.ending_data.capacity() * size_of::<PointIndex>(),
+            hull: self.hull.memory_usage(),
+            half_edges: self.half.memory_usage(),
+        }
+    }
+
     /// Checks whether the given point is inside or outside the triangulation.
     /// This is extremely inefficient, and should only be used for debugging
     /// or unit tests.
@@ -1287,6 +1409,73 @@ fn min3(buf: &[(usize, f64)], points: &[(f64, f64)]) -> [usize; 3] {
 mod tests {
     use super::*;
 
+    #[test]
+    fn memory_usage() {
+        let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let t = Triangulation::build(&pts[0..]).expect("Could not construct");
+        let m = t.memory_usage();
+        assert!(m.points >= pts.len() * std::mem::size_of::<Point>());
+        // The hull's bucket table dominates for tiny inputs
+        assert!(m.hull > m.half_edges);
+        assert!(m.half_edges > 0);
+
+        // The point -> hull table is only allocated for constrained edges
+        let c = Triangulation::build_with_edges(&pts[0..], &[(0, 2)])
+            .expect("Could not construct")
+            .memory_usage();
+        assert!(c.hull > m.hull);
+    }
+
+    #[test]
+    fn hull_neighbors() {
+        // Neighbors are found by walking the hull when unconstrained, and
+        // through the point -> hull table when there are fixed edges
+        let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.5, 0.4)];
+        let edges: &[(usize, usize)] = &[(0, 4)];
+        for edges in [&[], edges] {
+            let mut t = Triangulation::new_with_edges(&pts, edges)
+                .expect("Could not construct");
+            while !t.done() {
+                t.step().expect("Could not step");
+                t.check();
+            }
+
+            // The center point is swallowed, leaving the square's corners
+            let hull: Vec<PointIndex> = t.hull.points().collect();
+            assert_eq!(hull.len(), 4);
+            for (i, p) in hull.iter().enumerate() {
+                assert_eq!(t.hull.next(*p), Some(hull[(i + 1) % hull.len()]));
+                assert_eq!(t.hull.prev(hull[(i + 1) % hull.len()]), Some(*p));
+            }
+            let center = (0..pts.len())
+                .map(PointIndex::new)
+                .find(|p| !hull.contains(p))
+                .expect("Could not find center point");
+            assert_eq!(t.hull.next(center), None);
+        }
+    }
+
+    #[test]
+    fn convex_hull() {
+        let mut pts = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0),
+                           (0.5, 0.0), (0.5, 0.4), (0.3, 0.8)];
+        let t = Triangulation::build(&pts).expect("Could not construct");
+        let hull = t.convex_hull();
+        assert_eq!(hull.len(), 4);
+        let start = hull.iter().position(|&i| i == 0).unwrap();
+        let hull: Vec<usize> = hull[start..].iter()
+            .chain(&hull[..start]).copied().collect();
+        assert_eq!(hull, vec![0, 1, 2, 3]);
+
+        // Concave constraints don't affect the hull
+        pts.truncate(4);
+        pts.push((0.5, 0.5));
+        let t = Triangulation::build_with_edges(
+            &pts, &[(0, 1), (1, 4), (4, 2), (2, 3), (3, 0)])
+            .expect("Could not construct");
+        assert_eq!(t.convex_hull().len(), 4);
+    }
+
     #[test]
     fn simple_triangle() {
         let pts = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
@@ -1487,6 +1676,31 @@ mod tests {
         t.check();
     }
 
+    #[test]
+    fn random_is_delaunay() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(12345);
+        let points: Vec<Point> = (0..1000)
+            .map(|_| (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)))
+            .collect();
+        let t = Triangulation::build(&points)
+            .expect("Could not build triangulation");
+
+        // Every interior edge must be locally Delaunay
+        let mut opposite = std::collections::HashMap::new();
+        for (a, b, c) in t.triangles() {
+            opposite.insert((a, b), c);
+            opposite.insert((b, c), a);
+            opposite.insert((c, a), b);
+        }
+        for (&(a, b), &c) in &opposite {
+            if let Some(&d) = opposite.get(&(b, a)) {
+                assert!(in_circle(points[a], points[b], points[c],
+                                  points[d]) <= 0.0);
+            }
+        }
+    }
+
     #[test]
     fn grid_with_fixed_circle() {
         let mut edges = Vec::new();
@@ -1532,4 +1746,68 @@ mod tests {
             assert!(e == Error::OpenContour);
         }
     }
+
+    #[test]
+    fn fixed_edges_preserved() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(12345);
+
+        // Two concentric rings, with random points scattered around them
+        let mut points = Vec::new();
+        let mut contours = Vec::new();
+        for &r in &[1.0, 0.5] {
+            let start = points.len();
+            for i in 0..64 {
+                let a = i as f64 / 64.0 * std::f64::consts::TAU;
+                points.push((r * a.cos(), r * a.sin()));
+            }
+            contours.push((start..points.len()).chain(std::iter::once(start))
+                .collect::<Vec<usize>>());
+        }
+        for _ in 0..1000 {
+            points.push((rng.gen_range(-1.2..1.2), rng.gen_range(-1.2..1.2)));
+        }
+
+        let mut t = Triangulation::new_from_contours(&points, &contours)
+            .expect("Could not construct");
+        t.set_strict(true);
+        t.run().expect("Could not triangulate");
+
+        let mut edges = std::collections::HashSet::new();
+        for (a, b, c) in t.triangles() {
+            edges.extend([(a, b), (b, a), (b, c), (c, b), (c, a), (a, c)]);
+        }
+        for c in &contours {
+            for (a, b) in c.iter().zip(&c[1..]) {
+                assert!(edges.contains(&(*a, *b)));
+            }
+        }
+    }
+
+    #[test]
+    fn crossing_fixed_edges() {
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.3, 0.6)];
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (1, 3)];
+        let mut t = Triangulation::new_with_edges(&points, &edges)
+            .expect("Could not construct");
+        t.set_strict(true);
+        assert_eq!(t.run(), Err(Error::CrossingFixedEdge));
+    }
+
+    #[test]
+    fn strict_missing_edge() {
+        // The edge from 4 to 5 is outside of the closed boundary, so it's
+        // erased with the triangles around it
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0),
+                      (2.0, 0.2), (2.0, 0.8), (0.5, 0.5)];
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0), (4, 5)];
+        let t = Triangulation::build_with_edges(&points, &edges)
+            .expect("Could not triangulate");
+        assert!(t.triangles().count() > 0);
+
+        let mut t = Triangulation::new_with_edges(&points, &edges)
+            .expect("Could not construct");
+        t.set_strict(true);
+        assert_eq!(t.run(), Err(Error::MissingFixedEdge(5, 4)));
+    }
 }
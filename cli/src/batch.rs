@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::ArgMatches;
+use rayon::prelude::*;
+
+use serde_json::json;
+
+/// Checks whether a file name matches a pattern, where `*` matches any run
+/// of characters and `?` matches a single character
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) =>
+            (0..=name.len()).any(|i| matches_pattern(rest, &name[i..])),
+        Some((c, rest)) => match name.split_first() {
+            Some((n, name)) if *c == '?' || c == n => matches_pattern(rest, name),
+            _ => false,
+        },
+    }
+}
+
+fn is_step_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| matches!(e.to_lowercase().as_str(), "step" | "stp"))
+        .unwrap_or(false)
+}
+
+/// Recursively finds STEP files in a directory, pushing them into `out`
+/// with their paths relative to `root`
+fn walk_dir(root: &Path, dir: &Path, out: &mut Vec<(PathBuf, PathBuf)>)
+    -> std::io::Result<()>
+{
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk_dir(root, &path, out)?;
+        } else if is_step_file(&path) {
+            let rel = path.strip_prefix(root).expect("Could not get relative path");
+            out.push((path.clone(), rel.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Expands an input argument (a file, a directory, or a pattern with
+/// wildcards in its final component) into `(input, relative output)` pairs.
+/// Files found in a directory keep their relative path in the output.
+fn expand(arg: &str) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn std::error::Error>> {
+    let path = Path::new(arg);
+    let mut out = Vec::new();
+    if path.is_dir() {
+        walk_dir(path, path, &mut out)?;
+    } else if arg.contains(&['*', '?'][..]) {
+        let dir = match path.parent() {
+            Some(p) if p.as_os_str().is_empty() => Path::new("."),
+            Some(p) => p,
+            None => Path::new("."),
+        };
+        if dir.to_string_lossy().contains(&['*', '?'][..]) {
+            return Err(format!("Wildcards are only supported in file names \
+                                (got '{}')", arg).into());
+        }
+        let pattern: Vec<char> = path.file_name()
+            .map(|n| n.to_string_lossy().chars().collect())
+            .unwrap_or_default();
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for p in entries {
+            let name: Vec<char> = match p.file_name() {
+                Some(n) => n.to_string_lossy().chars().collect(),
+                None => continue,
+            };
+            if p.is_file() && matches_pattern(&pattern, &name) {
+                out.push((p.clone(), PathBuf::from(p.file_name().unwrap())));
+            }
+        }
+    } else {
+        let name = path.file_name()
+            .ok_or_else(|| format!("Invalid input '{}'", arg))?;
+        out.push((path.to_owned(), PathBuf::from(name)));
+    }
+    Ok(out)
+}
+
+/// Converts a single file, returning its triangle count
+fn convert(m: &ArgMatches, input: &Path, output: &Path)
+    -> Result<usize, Box<dyn std::error::Error>>
+{
+    let input = input.to_str().ok_or("Input path is not valid UTF-8")?;
+    let output = output.to_str().ok_or("Output path is not valid UTF-8")?;
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    // Lenient parsing skips what it can't read, so an unreadable file
+    // shows up as an empty mesh, which should count as a failure here
+    if mesh.triangles.is_empty() {
+        return Err("No geometry was triangulated".into());
+    }
+    if let Some(angle) = m.value_of("crease") {
+        mesh.smooth_normals(angle.parse::<f64>()?.to_radians());
+    }
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+    crate::save_mesh(&mesh, output)?;
+    Ok(mesh.triangles.len())
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = Path::new(m.value_of("output").expect("Could not get output"));
+    let ext = m.value_of("ext").expect("Could not get extension");
+
+    let mut jobs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    // Inputs by their output paths, to catch files which would overwrite
+    // each other
+    let mut outputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for arg in m.values_of("input").expect("Could not get input") {
+        for (input, rel) in expand(arg)? {
+            let output = out_dir.join(rel.with_extension(ext));
+            if let Some(prev) = outputs.insert(output.clone(), input.clone()) {
+                return Err(format!("{} and {} would both be written to {}",
+                                   prev.display(), input.display(),
+                                   output.display()).into());
+            }
+            jobs.push((input, output));
+        }
+    }
+    if jobs.is_empty() {
+        return Err("No STEP files matched the inputs".into());
+    }
+
+    let start = Instant::now();
+    let results: Vec<(Result<usize, String>, Duration)> = jobs.par_iter()
+        .map(|(input, output)| {
+            let t = Instant::now();
+            // A panic in one file shouldn't take down the whole batch
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                if let Some(dir) = output.parent() {
+                    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                convert(m, input, output).map_err(|e| e.to_string())
+            }));
+            (r.unwrap_or_else(|_| Err("panicked".to_owned())), t.elapsed())
+        })
+        .collect();
+    let elapsed = start.elapsed();
+
+    let failed = results.iter().filter(|(r, _)| r.is_err()).count();
+    if crate::json_output(m) {
+        let files = jobs.iter().zip(&results).map(|((input, output), (r, dt))|
+            json!({
+                "input": input.to_string_lossy(),
+                "output": output.to_string_lossy(),
+                "triangles": r.as_ref().ok(),
+                "error": r.as_ref().err(),
+                "seconds": dt.as_secs_f64(),
+            }))
+            .collect::<Vec<_>>();
+        println!("{}", json!({
+            "files": files,
+            "converted": jobs.len() - failed,
+            "failed": failed,
+            "seconds": elapsed.as_secs_f64(),
+        }));
+    } else {
+        for ((input, output), (r, dt)) in jobs.iter().zip(&results) {
+            match r {
+                Ok(n) => println!("ok      {} -> {} ({} triangles, {:.2?})",
+                                  input.display(), output.display(), n, dt),
+                Err(e) => println!("FAILED  {}: {} ({:.2?})",
+                                   input.display(), e, dt),
+            }
+        }
+        let slowest = jobs.iter().zip(&results)
+            .max_by_key(|(_, (_, dt))| *dt)
+            .map(|((input, _), (_, dt))| (input, dt));
+
+        println!();
+        println!("{:<20}{}", "Converted:", jobs.len() - failed);
+        println!("{:<20}{}", "Failed:", failed);
+        println!("{:<20}{:.2?}", "Total time:", elapsed);
+        if let Some((input, dt)) = slowest {
+            println!("{:<20}{} ({:.2?})", "Slowest:", input.display(), dt);
+        }
+    }
+    if failed > 0 {
+        Err(format!("{} of {} files failed to convert", failed, jobs.len()).into())
+    } else {
+        Ok(())
+    }
+}
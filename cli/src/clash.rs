@@ -0,0 +1,51 @@
+use clap::ArgMatches;
+
+use triangulate::{clash::find_clashes, mesh::Solid};
+
+use serde_json::{json, Value};
+
+fn describe(s: &Solid) -> String {
+    match &s.name {
+        Some(name) => format!("Solid #{} ({})", s.id, name),
+        None => format!("Solid #{}", s.id),
+    }
+}
+
+fn solid_json(s: &Solid) -> Value {
+    json!({
+        "id": s.id,
+        "name": s.name,
+    })
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let clearance: f64 = match m.value_of("clearance") {
+        Some(s) => s.parse()?,
+        None => 0.0,
+    };
+    let mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                crate::tessellation_params(m)?)?;
+    let clashes = find_clashes(&mesh, clearance);
+
+    if crate::json_output(m) {
+        let clashes: Vec<Value> = clashes.iter().map(|c| json!({
+            "a": solid_json(&mesh.solids[c.a]),
+            "b": solid_json(&mesh.solids[c.b]),
+            "kind": c.kind.name(),
+            "distance": c.distance,
+        })).collect();
+        println!("{}", Value::from(clashes));
+    } else {
+        for c in &clashes {
+            print!("{} and {}: {}", describe(&mesh.solids[c.a]),
+                   describe(&mesh.solids[c.b]), c.kind.name());
+            if c.distance > 0.0 {
+                print!(" ({})", c.distance);
+            }
+            println!();
+        }
+        println!("Found {} clashes between {} solids", clashes.len(), mesh.solids.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use clap::ArgMatches;
+use nalgebra_glm::{DMat4, DVec3};
+
+use triangulate::{
+    bvh::Bvh,
+    diff::{colorize_signed, signed_deviation, DeviationStats},
+    scan::{centroid_alignment, load_scan, register},
+};
+
+use serde_json::json;
+
+/// Parses a row-major transform matrix, as 12 or 16 comma-separated numbers
+fn parse_transform(s: &str) -> Result<DMat4, Box<dyn std::error::Error>> {
+    let mut v = s.split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<f64>, _>>()?;
+    if v.len() == 12 {
+        v.extend([0.0, 0.0, 0.0, 1.0]);
+    }
+    if v.len() != 16 {
+        return Err(format!("Expected 12 or 16 comma-separated numbers, got '{}'", s).into());
+    }
+    Ok(DMat4::from_row_slice(&v))
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let scan_file = m.value_of("scan").expect("Could not get scan");
+    let iterations: usize = m.value_of("iterations")
+        .expect("Could not get iterations").parse()?;
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    let mut scan = load_scan(scan_file)?;
+    if scan.verts.is_empty() {
+        return Err(format!("'{}' has no points", scan_file).into());
+    }
+    let output = m.value_of("output");
+    let ext = output.and_then(|o| o.rsplit('.').next()).map(|e| e.to_lowercase());
+    match ext.as_deref() {
+        None | Some("ply") => (),
+        Some("png") if scan.triangles.is_empty() =>
+            return Err("Only scanned meshes can be rendered; \
+                        save point clouds as .ply instead".into()),
+        Some("png") => (),
+        _ => return Err(format!("Unknown output format for '{}' \
+                                 (expected .ply or .png)", output.unwrap()).into()),
+    }
+
+    let start = std::time::SystemTime::now();
+    let bvh = Bvh::new(&mesh);
+    let points: Vec<DVec3> = scan.verts.iter().map(|v| v.pos).collect();
+    let initial = match m.value_of("transform") {
+        Some(t) => parse_transform(t)?,
+        None => centroid_alignment(&points, &mesh),
+    };
+    let reg = register(&points, &bvh, initial, iterations);
+    scan.transform(&reg.transform);
+    let d = signed_deviation(&scan, &bvh, &mesh);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+
+    let stats = DeviationStats::new(&d);
+    let rows: Vec<[f64; 4]> = (0..4)
+        .map(|i| [0, 1, 2, 3].map(|j| reg.transform[(i, j)]))
+        .collect();
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "transform": rows,
+            "iterations": reg.iterations,
+            "registered_points": reg.points,
+            "registration_rms": reg.rms,
+            "count": stats.count,
+            "seconds": dt.as_secs_f64(),
+            "min": stats.min,
+            "max": stats.max,
+            "mean": stats.mean,
+            "rms": stats.rms,
+        }));
+    } else {
+        println!("Registered {} points in {} iterations (rms {}) in {:?}",
+                 reg.points, reg.iterations, reg.rms, dt);
+        for r in &rows {
+            println!("    [{:12.6} {:12.6} {:12.6} {:12.6}]", r[0], r[1], r[2], r[3]);
+        }
+        println!("Signed deviation from the model:");
+        println!("    min:  {}", stats.min);
+        println!("    max:  {}", stats.max);
+        println!("    mean: {}", stats.mean);
+        println!("    rms:  {}", stats.rms);
+    }
+
+    if let Some(out) = output {
+        let max = match m.value_of("max") {
+            Some(s) => s.parse()?,
+            None => stats.max.max(-stats.min),
+        };
+        colorize_signed(&mut scan, &d, max);
+        if ext.as_deref() == Some("png") {
+            crate::render::render_png(m, &scan, out)?;
+        } else {
+            scan.save_ply(out)?;
+        }
+    }
+    Ok(())
+}
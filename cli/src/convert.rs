@@ -0,0 +1,68 @@
+use clap::ArgMatches;
+
+use step::{cancel::CancelToken, step_file::StepFile};
+use triangulate::triangulate::triangulate_out_of_core;
+
+use serde_json::json;
+
+fn report(m: &ArgMatches, output: &str, triangles: usize) {
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "triangles": triangles,
+        }));
+    } else {
+        println!("Wrote {} triangles", triangles);
+    }
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let ext = output.rsplit('.').next().map(|e| e.to_lowercase());
+
+    // Check the format before doing any work
+    if !matches!(ext.as_deref(), Some("stl") | Some("obj") | Some("ply") | Some("glb")) {
+        return Err(format!("Unknown output format for '{}' \
+                            (expected .stl, .obj, .ply, or .glb)", output).into());
+    }
+    let mut mesh = match m.value_of("max-memory") {
+        None => crate::load_mesh(input, crate::parse_mode(m),
+                                 crate::tessellation_params(m)?)?,
+        Some(s) => {
+            let max_memory = s.parse::<usize>()? * 1024 * 1024;
+            let data = std::fs::read(input)?;
+            let mode = crate::parse_mode(m);
+            let flat = StepFile::preprocess(&data, mode);
+            let step = StepFile::parse_with(&flat, mode, &CancelToken::default())?
+                .ok_or_else(crate::cancelled)?;
+            let (mesh, _stats) = triangulate_out_of_core(
+                    &step, crate::tessellation_params(m)?, &Default::default(),
+                    max_memory, &CancelToken::default())?
+                .ok_or_else(crate::cancelled)?;
+
+            // STL and PLY files are streamed straight from the temporary
+            // files, unless the mesh has to be changed first; otherwise,
+            // it's loaded back into memory.
+            let changed = ["crease", "units", "up"].iter().any(|a| m.is_present(a));
+            if !changed && matches!(ext.as_deref(), Some("stl") | Some("ply")) {
+                if ext.as_deref() == Some("stl") {
+                    mesh.save_stl(output)?;
+                } else {
+                    mesh.save_ply(output)?;
+                }
+                report(m, output, mesh.triangles().len());
+                return Ok(());
+            }
+            mesh.to_mesh()
+        },
+    };
+    if let Some(angle) = m.value_of("crease") {
+        mesh.smooth_normals(angle.parse::<f64>()?.to_radians());
+    }
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+    crate::save_mesh(&mesh, output)?;
+    report(m, output, mesh.triangles.len());
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use clap::ArgMatches;
+
+use triangulate::decimate::{decimate, Target};
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let target = match (m.value_of("triangles"), m.value_of("error")) {
+        (Some(n), _) => Target::Triangles(n.parse()?),
+        (None, Some(e)) => Target::Error(e.parse()?),
+        (None, None) => return Err("Either --triangles or --error is required".into()),
+    };
+
+    let mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    let start = std::time::SystemTime::now();
+    let mut out = decimate(&mesh, target);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "input_triangles": mesh.triangles.len(),
+            "triangles": out.triangles.len(),
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Decimated {} triangles to {} in {:?}",
+                 mesh.triangles.len(), out.triangles.len(), dt);
+    }
+    crate::convert_units(m, &mut out)?;
+    crate::convert_up_axis(m, &mut out);
+
+    crate::save_mesh(&out, output)
+}
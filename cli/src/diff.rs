@@ -0,0 +1,58 @@
+use clap::ArgMatches;
+
+use triangulate::{
+    bvh::Bvh,
+    diff::{colorize, deviation, DeviationStats},
+};
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let a = m.value_of("a").expect("Could not get first input");
+    let b = m.value_of("b").expect("Could not get second input");
+
+    // Tessellate both files in parallel, since they're independent
+    let mode = crate::parse_mode(m);
+    let params = crate::tessellation_params(m)?;
+    let (a_mesh, b_mesh) = {
+        let b = b.to_owned();
+        let handle = std::thread::spawn(move || crate::load_mesh(&b, mode, params));
+        let a_mesh = crate::load_mesh(a, mode, params)?;
+        (a_mesh, handle.join().expect("Loader thread panicked")?)
+    };
+
+    let start = std::time::SystemTime::now();
+    let bvh = Bvh::new(&b_mesh);
+    let d = deviation(&a_mesh, &bvh);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+
+    let stats = DeviationStats::new(&d);
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "count": stats.count,
+            "seconds": dt.as_secs_f64(),
+            "min": stats.min,
+            "max": stats.max,
+            "mean": stats.mean,
+            "rms": stats.rms,
+        }));
+    } else {
+        println!("Computed {} deviations in {:?}", stats.count, dt);
+        println!("    min:  {}", stats.min);
+        println!("    max:  {}", stats.max);
+        println!("    mean: {}", stats.mean);
+        println!("    rms:  {}", stats.rms);
+    }
+
+    if let Some(out) = m.value_of("output") {
+        let max = match m.value_of("max") {
+            Some(s) => s.parse()?,
+            None => stats.max,
+        };
+        let mut mesh = a_mesh;
+        colorize(&mut mesh, &d, max);
+        mesh.save_ply(out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use clap::ArgMatches;
+
+use triangulate::holes::find_holes;
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    let holes = find_holes(&mesh);
+
+    if crate::json_output(m) {
+        let holes: Vec<_> = holes.iter().map(|h| json!({
+            "origin": h.origin.as_slice(),
+            "axis": h.axis.as_slice(),
+            "diameter": h.diameter,
+            "depth": h.depth,
+            "through": h.through,
+            "counterbore": h.counterbore.map(|c| json!({
+                "diameter": c.diameter,
+                "depth": c.depth,
+            })),
+            "faces": h.faces.iter().map(|f| mesh.faces[*f].id).collect::<Vec<_>>(),
+        })).collect();
+        println!("{}", json!(holes));
+    } else {
+        for h in &holes {
+            print!("{} hole at ({:.6}, {:.6}, {:.6}) along ({:.3}, {:.3}, {:.3}): \
+                    diameter {:.6}, depth {:.6}",
+                   if h.through { "Through" } else { "Blind" },
+                   h.origin.x, h.origin.y, h.origin.z,
+                   h.axis.x, h.axis.y, h.axis.z, h.diameter, h.depth);
+            if let Some(c) = h.counterbore {
+                print!(", counterbore diameter {:.6}, depth {:.6}", c.diameter, c.depth);
+            }
+            println!();
+        }
+        println!("Found {} holes", holes.len());
+    }
+    Ok(())
+}
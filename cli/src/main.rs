@@ -0,0 +1,736 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use nalgebra_glm::DVec3;
+
+use step::{cancel::CancelToken, prescan::Prescan, step_file::ParseMode};
+use triangulate::{
+    external::triangulate_file, mesh::{Mesh, UpAxis},
+    triangulate::TessellationParams,
+};
+
+mod batch;
+mod clash;
+mod compare;
+mod convert;
+mod decimate;
+mod diff;
+mod holes;
+mod outline;
+mod points;
+mod quads;
+mod render;
+mod scan;
+mod sdf;
+mod section;
+mod slice;
+mod stats;
+mod voxels;
+
+/// Counts allocations by pipeline stage, for `foxtrot stats`
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOC: triangulate::memory::CountingAlloc = triangulate::memory::CountingAlloc;
+
+/// Files which are expected to take longer than this to load get a warning
+const SLOW_LOAD: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Loads, parses, and triangulates a STEP file, along with any files that it
+/// references for component geometry
+pub(crate) fn load_mesh(filename: &str, mode: ParseMode,
+                        params: TessellationParams) -> std::io::Result<Mesh> {
+    let data = std::fs::read(filename)?;
+    let scan = Prescan::new(&data);
+    if scan.estimated_load_time() > SLOW_LOAD {
+        eprintln!("{} has {} entities, which may take a while to load \
+                   (roughly {:.0?}, guessing from the entity count alone)",
+                  filename, scan.entities, scan.estimated_load_time());
+    }
+    let (mesh, _stats) = triangulate_file(filename.as_ref(), params,
+                                          &Default::default(), mode,
+                                          &CancelToken::default())?
+        .ok_or_else(cancelled)?;
+    Ok(mesh)
+}
+
+/// Error for a load which stopped before finishing, which only happens if
+/// its cancel token is triggered
+pub(crate) fn cancelled() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Interrupted, "Loading was cancelled")
+}
+
+/// Saves a mesh, picking the format from the filename's extension
+pub(crate) fn save_mesh(mesh: &Mesh, filename: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    match filename.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("stl") => mesh.save_stl(filename)?,
+        Some("obj") => mesh.save_obj(filename)?,
+        Some("ply") => mesh.save_ply(filename)?,
+        Some("glb") => mesh.save_glb(filename)?,
+        _ => return Err(format!("Unknown output format for '{}' \
+                                 (expected .stl, .obj, .ply, or .glb)",
+                                filename).into()),
+    }
+    Ok(())
+}
+
+/// Checks whether results should be printed as JSON (`--format json`)
+pub(crate) fn json_output(m: &ArgMatches) -> bool {
+    m.value_of("format") == Some("json")
+}
+
+/// Picks the parse mode from the `--strict` flag
+pub(crate) fn parse_mode(m: &ArgMatches) -> ParseMode {
+    if m.is_present("strict") {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    }
+}
+
+/// Builds tessellation settings from the `--sew`, `--tolerance`, and
+/// `--min-segments` options
+pub(crate) fn tessellation_params(m: &ArgMatches)
+    -> Result<TessellationParams, Box<dyn std::error::Error>>
+{
+    let tolerance = match m.value_of("tolerance") {
+        None | Some("auto") => None,
+        Some(t) => Some(t.parse()?),
+    };
+    Ok(TessellationParams {
+        tolerance,
+        sewing: m.value_of("sew").map(str::parse).transpose()?,
+        min_segments_per_turn: m.value_of("min-segments").map(str::parse).transpose()?,
+        ..Default::default()
+    })
+}
+
+/// Rescales the mesh from the file's declared length unit into the unit
+/// chosen with `--units`, if any
+pub(crate) fn convert_units(m: &ArgMatches, mesh: &mut Mesh)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let metres = match m.value_of("units") {
+        Some("mm") => 1e-3,
+        Some("cm") => 1e-2,
+        Some("m") => 1.0,
+        Some("in") | Some("inch") => 0.0254,
+        Some(u) => return Err(format!("Unknown unit '{}'", u).into()),
+        None => return Ok(()),
+    };
+    let unit = mesh.length_unit
+        .ok_or("File does not declare a length unit, so it can't be converted")?;
+    mesh.scale(unit / metres);
+    mesh.length_unit = Some(metres);
+    Ok(())
+}
+
+/// Rotates the mesh from STEP's Z-up convention into the one chosen with
+/// `--up`, if any
+pub(crate) fn convert_up_axis(m: &ArgMatches, mesh: &mut Mesh) {
+    let up = match m.value_of("up") {
+        Some("y") => UpAxis::Y,
+        _ => UpAxis::Z,
+    };
+    mesh.transform(&up.from_z_up());
+}
+
+/// Parses a comma-separated vector, e.g. `0,0,1`
+pub(crate) fn parse_vec3(s: &str) -> Result<DVec3, Box<dyn std::error::Error>> {
+    let v = s.split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<f64>, _>>()?;
+    match v[..] {
+        [x, y, z] => Ok(DVec3::new(x, y, z)),
+        _ => Err(format!("Expected three comma-separated numbers, got '{}'", s).into()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    // Shared by every subcommand which parses STEP files
+    let strict = Arg::with_name("strict")
+        .long("strict")
+        .help("Reject files which deviate from the Part 21 grammar, rather \
+               than working around common exporter quirks");
+    let sew = Arg::with_name("sew")
+        .long("sew")
+        .help("Stitch together face boundaries which are separated by gaps \
+               of up to this size, in model units")
+        .takes_value(true);
+    let tolerance = Arg::with_name("tolerance")
+        .long("tolerance")
+        .help("Largest distance between an edge's segments and the curve \
+               which they approximate, in model units, or 'auto' for a \
+               ten-thousandth of the model's size")
+        .default_value("auto")
+        .takes_value(true);
+    let min_segments = Arg::with_name("min-segments")
+        .long("min-segments")
+        .help("Smallest number of segments in a full circle, whatever the \
+               tolerance, so that small holes stay round (defaults to 16)")
+        .takes_value(true);
+    // Shared by every subcommand
+    let format = Arg::with_name("format")
+        .long("format")
+        .help("Print results as human-readable text or as JSON")
+        .possible_values(&["text", "json"])
+        .default_value("text")
+        .takes_value(true);
+    // Shared by every subcommand which writes a mesh
+    let units = Arg::with_name("units")
+        .long("units")
+        .help("Rescale the output from the file's units into mm, cm, m, or in")
+        .possible_values(&["mm", "cm", "m", "in", "inch"])
+        .takes_value(true);
+    let up = Arg::with_name("up")
+        .long("up")
+        .help("Axis which points upwards in the output (defaults to z, as in \
+               STEP; glTF and most animation tools expect y)")
+        .possible_values(&["y", "z"])
+        .takes_value(true);
+
+    let matches = App::new("foxtrot")
+        .author("Matt Keeter <matt@formlabs.com>")
+        .about("Command-line tools for STEP files")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("diff")
+            .about("Measures deviation between two STEP files")
+            .arg(Arg::with_name("a")
+                .help("Reference model (deviation is measured at its vertices)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("b")
+                .help("Model to compare against")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("PLY file to write the colored deviation mesh")
+                .takes_value(true))
+            .arg(Arg::with_name("max")
+                .short("m")
+                .long("max")
+                .help("Deviation mapped to the top of the color ramp \
+                       (defaults to the largest deviation)")
+                .takes_value(true))
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("batch")
+            .about("Converts many STEP files in parallel, printing a summary")
+            .arg(Arg::with_name("input")
+                .help("STEP files, directories (searched recursively for \
+                       .step and .stp files), or file name patterns with \
+                       * and ? wildcards")
+                .takes_value(true)
+                .multiple(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Directory in which to write meshes")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("ext")
+                .short("e")
+                .long("ext")
+                .help("Extension of the meshes to write, which picks their format")
+                .possible_values(&["stl", "obj", "ply", "glb"])
+                .default_value("stl")
+                .takes_value(true))
+            .arg(Arg::with_name("crease")
+                .long("crease")
+                .help("Smooth normals across boundaries between faces which \
+                       meet at less than this angle, in degrees")
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("clash")
+            .about("Finds pairs of solids which intersect or come too close")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("clearance")
+                .short("c")
+                .long("clearance")
+                .help("Also report solids which come closer than this \
+                       distance, in model units")
+                .takes_value(true))
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("compare")
+            .about("Registers a 3D scan against a STEP file and measures \
+                    the scan's deviation from the model")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("scan")
+                .help("Scanned mesh or point cloud (.ply or .stl), whose \
+                       deviation is measured at its vertices")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Write the registered scan, colored by deviation, to a \
+                       PLY file or render it to a PNG")
+                .takes_value(true))
+            .arg(Arg::with_name("max")
+                .short("m")
+                .long("max")
+                .help("Deviation mapped to the ends of the color ramp \
+                       (defaults to the largest deviation)")
+                .takes_value(true))
+            .arg(Arg::with_name("transform")
+                .long("transform")
+                .help("Initial transform from the scan into the model, as a \
+                       row-major matrix of 12 or 16 comma-separated numbers \
+                       (defaults to lining up their centroids)")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(Arg::with_name("iterations")
+                .long("iterations")
+                .help("Largest number of ICP steps which refine the \
+                       registration; 0 uses the initial transform as is")
+                .default_value("50")
+                .takes_value(true))
+            .arg(Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .help("Paths traced per pixel, when rendering")
+                .default_value("64")
+                .takes_value(true))
+            .arg(Arg::with_name("size")
+                .long("size")
+                .help("Image size, as WIDTHxHEIGHT, when rendering")
+                .default_value("800x600")
+                .takes_value(true))
+            .arg(Arg::with_name("view")
+                .short("v")
+                .long("view")
+                .help("Direction from the model towards the viewer, as x,y,z, \
+                       when rendering")
+                .default_value("1,-1,1")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("convert")
+            .about("Triangulates a STEP file and saves it as a mesh")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Mesh file to write (.stl, .obj, .ply, or .glb)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("max-memory")
+                .long("max-memory")
+                .help("Triangulate one solid at a time, keeping at most \
+                       this many megabytes of mesh data in memory and moving \
+                       the rest to temporary files (without loading \
+                       externally referenced files).  STL and PLY files are \
+                       streamed from disk unless --crease, --units, or --up \
+                       changes the mesh.")
+                .takes_value(true))
+            .arg(Arg::with_name("crease")
+                .long("crease")
+                .help("Smooth normals across boundaries between faces which \
+                       meet at less than this angle, in degrees")
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("decimate")
+            .about("Triangulates a STEP file and simplifies the mesh, \
+                    keeping boundaries between faces intact")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Mesh file to write (.stl, .obj, .ply, or .glb)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("triangles")
+                .short("t")
+                .long("triangles")
+                .help("Target triangle count")
+                .takes_value(true))
+            .arg(Arg::with_name("error")
+                .short("e")
+                .long("error")
+                .help("Maximum deviation from the original mesh, in model units")
+                .takes_value(true)
+                .conflicts_with("triangles"))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("holes")
+            .about("Finds round holes and counterbores, printing their axes \
+                    and sizes")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(units.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("points")
+            .about("Samples points (with normals) evenly over a STEP file's \
+                    surface, e.g. for registration against a 3D scan")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Point cloud to write (.ply or .xyz)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("count")
+                .short("n")
+                .long("count")
+                .help("Approximate number of points")
+                .default_value("100000")
+                .takes_value(true))
+            .arg(Arg::with_name("spacing")
+                .long("spacing")
+                .help("Average distance between neighboring points, in \
+                       output units (overrides --count)")
+                .takes_value(true))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .help("Seed for the random placement of points")
+                .default_value("0")
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("scan")
+            .about("Quickly counts the entities in a STEP file by type")
+            .arg(Arg::with_name("input")
+                .help("STEP file to scan")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("top")
+                .short("n")
+                .long("top")
+                .help("Only print the most common entity types")
+                .takes_value(true))
+            .arg(format.clone()))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Prints a STEP file's header metadata and triangulation \
+                    statistics")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("density")
+                .long("density")
+                .help("Density in kg/m³ for reporting mass properties of \
+                       solids which don't have a material density in the file")
+                .takes_value(true))
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("quads")
+            .about("Triangulates a STEP file, building rectangular faces on \
+                    planes, cylinders, and cones as grids of quads")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("OBJ file to write")
+                .takes_value(true)
+                .required(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("outline")
+            .about("Saves a hidden-line drawing of a STEP file's silhouette \
+                    and visible edges, as seen from a chosen direction")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Drawing to write (.svg or .pdf)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("view")
+                .short("v")
+                .long("view")
+                .help("Direction from the model towards the viewer, as x,y,z \
+                       (e.g. 0,-1,0 for a front view)")
+                .default_value("1,-1,1")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("render")
+            .about("Path-traces a high-quality still of a STEP file, with \
+                    soft shadows and ambient occlusion")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("PNG file to write")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .help("Paths traced per pixel; more samples take longer but \
+                       give a less noisy image")
+                .default_value("64")
+                .takes_value(true))
+            .arg(Arg::with_name("size")
+                .long("size")
+                .help("Image size, as WIDTHxHEIGHT")
+                .default_value("800x600")
+                .takes_value(true))
+            .arg(Arg::with_name("view")
+                .short("v")
+                .long("view")
+                .help("Direction from the model towards the viewer, as x,y,z")
+                .default_value("1,-1,1")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("sdf")
+            .about("Samples the signed distance to a STEP file's solids on a \
+                    voxel grid, e.g. for lattice generation or simulation")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Volume to write: raw 32-bit floats (.raw), a NRRD \
+                       volume (.nrrd), or a sparse narrow band (.fsdf)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("resolution")
+                .short("r")
+                .long("resolution")
+                .help("Number of voxels along the model's longest side")
+                .default_value("128")
+                .takes_value(true))
+            .arg(Arg::with_name("voxel")
+                .long("voxel")
+                .help("Voxel size, in output units (overrides --resolution)")
+                .takes_value(true))
+            .arg(Arg::with_name("padding")
+                .long("padding")
+                .help("Extra voxels around the model on every side")
+                .default_value("2")
+                .takes_value(true))
+            .arg(Arg::with_name("band")
+                .long("band")
+                .help("Half-width of the narrow band kept in .fsdf files, in \
+                       voxels")
+                .default_value("3")
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("voxels")
+            .about("Fills a voxel grid from a STEP file's solids, as an \
+                    occupancy volume or one image per print layer")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Bytes which are 1 inside the model (.raw), a NRRD \
+                       volume (.nrrd), or layer images (e.g. 'slices/layer.png' \
+                       writes slices/layer_0000.png, ... from the bottom up)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("resolution")
+                .short("r")
+                .long("resolution")
+                .help("Number of pixels along the model's longest horizontal side")
+                .default_value("256")
+                .takes_value(true))
+            .arg(Arg::with_name("pixel")
+                .long("pixel")
+                .help("Size of a pixel within each layer, in output units \
+                       (overrides --resolution)")
+                .takes_value(true))
+            .arg(Arg::with_name("layer")
+                .long("layer")
+                .help("Layer height along Z, in output units (defaults to the \
+                       pixel size)")
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("section")
+            .about("Cuts a STEP file with a plane and saves the section \
+                    outlines as a 2D drawing")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Drawing to write (.svg or .dxf)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("normal")
+                .short("n")
+                .long("normal")
+                .help("Plane normal as x,y,z; the drawing looks down onto \
+                       the plane from this side")
+                .default_value("0,0,1")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(Arg::with_name("offset")
+                .long("offset")
+                .help("Distance of the plane from the origin along the \
+                       normal, in output units (defaults to the middle of \
+                       the model)")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(units.clone())
+            .arg(up.clone())
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .subcommand(SubCommand::with_name("slice")
+            .about("Cuts a STEP file's solids into a stack of layers, each a \
+                    set of polygons with holes, as for 3D printing")
+            .arg(Arg::with_name("input")
+                .help("STEP file to load")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("out")
+                .help("Every layer's polygons in one file (.json), or one \
+                       drawing per layer (e.g. 'slices/layer.svg' writes \
+                       slices/layer_0000.svg, ... from the bottom up)")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("normal")
+                .short("n")
+                .long("normal")
+                .help("Direction in which layers are stacked, as x,y,z")
+                .default_value("0,0,1")
+                .allow_hyphen_values(true)
+                .takes_value(true))
+            .arg(Arg::with_name("layers")
+                .long("layers")
+                .help("Number of layers to cut the model into")
+                .default_value("100")
+                .takes_value(true))
+            .arg(Arg::with_name("layer")
+                .long("layer")
+                .help("Layer height, in output units (overrides --layers)")
+                .takes_value(true))
+            .arg(units)
+            .arg(up)
+            .arg(format.clone())
+            .arg(strict.clone())
+            .arg(sew.clone())
+            .arg(tolerance.clone())
+            .arg(min_segments.clone()))
+        .get_matches();
+
+    match matches.subcommand() {
+        ("batch", Some(m)) => batch::run(m),
+        ("clash", Some(m)) => clash::run(m),
+        ("compare", Some(m)) => compare::run(m),
+        ("convert", Some(m)) => convert::run(m),
+        ("diff", Some(m)) => diff::run(m),
+        ("decimate", Some(m)) => decimate::run(m),
+        ("holes", Some(m)) => holes::run(m),
+        ("outline", Some(m)) => outline::run(m),
+        ("points", Some(m)) => points::run(m),
+        ("quads", Some(m)) => quads::run(m),
+        ("render", Some(m)) => render::run(m),
+        ("scan", Some(m)) => scan::run(m),
+        ("sdf", Some(m)) => sdf::run(m),
+        ("section", Some(m)) => section::run(m),
+        ("slice", Some(m)) => slice::run(m),
+        ("stats", Some(m)) => stats::run(m),
+        ("voxels", Some(m)) => voxels::run(m),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,41 @@
+use clap::ArgMatches;
+
+use triangulate::outline::outline;
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let view = crate::parse_vec3(m.value_of("view").expect("Could not get view"))?;
+    if view.norm() == 0.0 {
+        return Err("View direction can't be zero".into());
+    }
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+
+    let start = std::time::SystemTime::now();
+    let drawing = outline(&mesh, view);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+    match output.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("svg") => drawing.save_svg(mesh.length_unit, output)?,
+        Some("pdf") => drawing.save_pdf(mesh.length_unit, output)?,
+        _ => return Err(format!("Unknown output format for '{}' \
+                                 (expected .svg or .pdf)", output).into()),
+    }
+
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "lines": drawing.lines.len(),
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Drew {} visible lines in {:?}", drawing.lines.len(), dt);
+    }
+    Ok(())
+}
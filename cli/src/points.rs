@@ -0,0 +1,45 @@
+use clap::ArgMatches;
+
+use triangulate::points::PointCloud;
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let ext = output.rsplit('.').next().map(|e| e.to_lowercase());
+    if !matches!(ext.as_deref(), Some("ply") | Some("xyz")) {
+        return Err(format!("Unknown output format for '{}' \
+                            (expected .ply or .xyz)", output).into());
+    }
+    let seed = m.value_of("seed").expect("Could not get seed").parse()?;
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+
+    // Spacing is measured in output units, so pick the density after
+    // converting the mesh
+    let density = match (m.value_of("spacing"), m.value_of("count")) {
+        (Some(s), _) => 1.0 / s.parse::<f64>()?.powi(2),
+        (None, Some(n)) => n.parse::<f64>()? / mesh.area(0..mesh.triangles.len()),
+        (None, None) => unreachable!("count has a default value"),
+    };
+    let cloud = PointCloud::sample(&mesh, density, seed);
+    if ext.as_deref() == Some("ply") {
+        cloud.save_ply(output)?;
+    } else {
+        cloud.save_xyz(output)?;
+    }
+
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "points": cloud.points.len(),
+        }));
+    } else {
+        println!("Wrote {} points", cloud.points.len());
+    }
+    Ok(())
+}
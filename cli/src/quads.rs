@@ -0,0 +1,32 @@
+use clap::ArgMatches;
+
+use triangulate::{quad::QuadMesh, triangulate::TessellationParams};
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+
+    // Rectangular faces are tessellated as grids of quads; others are
+    // paired up afterwards
+    let params = TessellationParams { quad_grids: true, ..crate::tessellation_params(m)? };
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m), params)?;
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+    let quads = QuadMesh::from_mesh(&mesh);
+    let n = quads.quad_count();
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "input_triangles": mesh.triangles.len(),
+            "quads": n,
+            "triangles": quads.elements.len() - n,
+        }));
+    } else {
+        println!("Converted {} triangles into {} quads and {} triangles",
+                 mesh.triangles.len(), n, quads.elements.len() - n);
+    }
+    quads.save_obj(&mesh, output)?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use clap::ArgMatches;
+
+use std::time::Duration;
+
+use triangulate::{
+    mesh::Mesh, render::{render, RenderParams}, section::plane_basis,
+};
+
+use serde_json::json;
+
+/// Parses a size string like `800x600`
+fn parse_size(s: &str) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let mut iter = s.split('x').map(|v| v.parse::<usize>());
+    match (iter.next(), iter.next(), iter.next()) {
+        (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(format!("Invalid size '{}' (expected WIDTHxHEIGHT)", s).into()),
+    }
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                crate::tessellation_params(m)?)?;
+    let (width, height, samples, dt) = render_png(m, &mesh, output)?;
+
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "width": width,
+            "height": height,
+            "samples": samples,
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Rendered {}x{} pixels with {} samples each in {:?}",
+                 width, height, samples, dt);
+    }
+    Ok(())
+}
+
+/// Path-traces a mesh with the `--view`, `--size`, and `--samples` options,
+/// saving it as a PNG.  Returns the image size, samples per pixel, and the
+/// time spent rendering.
+pub(crate) fn render_png(m: &ArgMatches, mesh: &Mesh, output: &str)
+    -> Result<(usize, usize, usize, Duration), Box<dyn std::error::Error>>
+{
+    let view = crate::parse_vec3(m.value_of("view").expect("Could not get view"))?;
+    if view.norm() == 0.0 {
+        return Err("View direction can't be zero".into());
+    }
+    let view = view.normalize();
+    let (width, height) = parse_size(m.value_of("size").expect("Could not get size"))?;
+    let samples: usize = m.value_of("samples").expect("Could not get samples").parse()?;
+
+    // Fit an orthographic view around the model, looking along -view with
+    // the drawing's axes (as in `foxtrot outline`) across the image
+    let (u, v) = plane_basis(view);
+    let mut lo = (f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut hi = (-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+    for p in mesh.verts.iter().map(|v| v.pos) {
+        let q = (p.dot(&u), p.dot(&v), p.dot(&view));
+        lo = (lo.0.min(q.0), lo.1.min(q.1), lo.2.min(q.2));
+        hi = (hi.0.max(q.0), hi.1.max(q.1), hi.2.max(q.2));
+    }
+    if mesh.verts.is_empty() {
+        return Err("Model is empty".into());
+    }
+    let center = ((lo.0 + hi.0) / 2.0, (lo.1 + hi.1) / 2.0);
+    let scale = ((hi.0 - lo.0) / width as f64).max((hi.1 - lo.1) / height as f64)
+        .max(f64::MIN_POSITIVE) * 1.1;
+    let eye = hi.2 + (hi.2 - lo.2).max(1.0);
+    let ray = |x: f64, y: f64| {
+        let origin = u * (center.0 + (x - width as f64 / 2.0) * scale) +
+                     v * (center.1 - (y - height as f64 / 2.0) * scale) +
+                     view * eye;
+        (origin, -view)
+    };
+
+    let params = RenderParams {
+        width,
+        height,
+        samples,
+        // Light from above and to the left of the viewer
+        light: view + v - u * 0.5,
+        ..Default::default()
+    };
+    let start = std::time::SystemTime::now();
+    let pixels = render(mesh, &params, ray);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+
+    let file = std::io::BufWriter::new(std::fs::File::create(output)?);
+    let mut enc = png::Encoder::new(file, width as u32, height as u32);
+    enc.set_color(png::ColorType::RGBA);
+    enc.set_depth(png::BitDepth::Eight);
+    enc.write_header()?.write_image_data(&pixels)?;
+
+    Ok((width, height, samples, dt))
+}
@@ -0,0 +1,41 @@
+use clap::ArgMatches;
+
+use step::prescan::Prescan;
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let data = std::fs::read(input)?;
+
+    let start = std::time::SystemTime::now();
+    let scan = Prescan::new(&data);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+
+    let top: usize = match m.value_of("top") {
+        Some(s) => s.parse()?,
+        None => usize::MAX,
+    };
+    let sorted = scan.sorted();
+    if crate::json_output(m) {
+        let types: Vec<_> = sorted.into_iter().take(top)
+            .map(|(name, count)| json!({ "name": name, "count": count }))
+            .collect();
+        println!("{}", json!({
+            "entities": scan.entities,
+            "scan_seconds": dt.as_secs_f64(),
+            "estimated_load_seconds": scan.estimated_load_time().as_secs_f64(),
+            "types": types,
+        }));
+        return Ok(());
+    }
+
+    println!("Scanned {} entities in {:?}", scan.entities, dt);
+    println!("Rough load time guess (from entity count only): {:.1?}",
+             scan.estimated_load_time());
+    for (name, count) in sorted.into_iter().take(top) {
+        println!("{:>10} {}", count, name);
+    }
+    Ok(())
+}
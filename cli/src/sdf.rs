@@ -0,0 +1,66 @@
+use clap::ArgMatches;
+
+use triangulate::sdf::DistanceField;
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let ext = output.rsplit('.').next().map(|e| e.to_lowercase());
+    if !matches!(ext.as_deref(), Some("raw") | Some("nrrd") | Some("fsdf")) {
+        return Err(format!("Unknown output format for '{}' \
+                            (expected .raw, .nrrd, or .fsdf)", output).into());
+    }
+    let padding: usize = m.value_of("padding").expect("Could not get padding").parse()?;
+    let band: f64 = m.value_of("band").expect("Could not get band").parse()?;
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    if mesh.triangles.is_empty() {
+        return Err("Model is empty".into());
+    }
+
+    // Voxels are either given directly or picked to fit the model's longest
+    // side, in output units
+    let spacing = match (m.value_of("voxel"), m.value_of("resolution")) {
+        (Some(v), _) => v.parse()?,
+        (None, Some(n)) => {
+            let (lo, hi) = mesh.verts.iter().fold(
+                (nalgebra_glm::DVec3::repeat(f64::INFINITY),
+                 nalgebra_glm::DVec3::repeat(-f64::INFINITY)),
+                |(lo, hi), v| (lo.inf(&v.pos), hi.sup(&v.pos)));
+            (hi - lo).max() / n.parse::<usize>()?.max(1) as f64
+        },
+        (None, None) => unreachable!("resolution has a default value"),
+    };
+    if spacing.is_nan() || spacing <= 0.0 {
+        return Err("Voxel size must be positive".into());
+    }
+
+    let start = std::time::SystemTime::now();
+    let sdf = DistanceField::new(&mesh, spacing, padding);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+    match ext.as_deref() {
+        Some("raw") => sdf.save_raw(output)?,
+        Some("nrrd") => sdf.save_nrrd(output)?,
+        _ => sdf.save_sparse((band * spacing) as f32, output)?,
+    }
+
+    let [nx, ny, nz] = sdf.dims;
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "dims": sdf.dims,
+            "origin": sdf.origin.as_slice(),
+            "spacing": sdf.spacing,
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Sampled {}x{}x{} voxels of size {} in {:?}", nx, ny, nz, spacing, dt);
+        println!("    origin: ({}, {}, {})", sdf.origin.x, sdf.origin.y, sdf.origin.z);
+    }
+    Ok(())
+}
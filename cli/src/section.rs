@@ -0,0 +1,64 @@
+use clap::ArgMatches;
+
+use triangulate::section::{save_dxf, save_svg, section};
+
+use serde_json::json;
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let normal = crate::parse_vec3(m.value_of("normal").expect("Could not get normal"))?;
+    if normal.norm() == 0.0 {
+        return Err("Plane normal can't be zero".into());
+    }
+    let normal = normal.normalize();
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+
+    // Without an offset, the plane passes through the middle of the model
+    let offset = match m.value_of("offset") {
+        Some(s) => s.parse()?,
+        None => {
+            let (lo, hi) = mesh.verts.iter().fold(
+                (f64::INFINITY, -f64::INFINITY),
+                |(lo, hi), v| {
+                    let d = v.pos.dot(&normal);
+                    (lo.min(d), hi.max(d))
+                });
+            if lo <= hi { (lo + hi) / 2.0 } else { 0.0 }
+        },
+    };
+    let sections = section(&mesh, normal * offset, normal);
+
+    match output.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("svg") => save_svg(&sections, normal, mesh.length_unit, output)?,
+        Some("dxf") => save_dxf(&sections, normal, mesh.length_unit, output)?,
+        _ => return Err(format!("Unknown output format for '{}' \
+                                 (expected .svg or .dxf)", output).into()),
+    }
+
+    let loops: usize = sections.iter().map(|s| s.loops.len()).sum();
+    let open: usize = sections.iter().map(|s| s.open).sum();
+    let area: f64 = sections.iter().map(|s| s.area(normal)).sum();
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "offset": offset,
+            "solids": sections.len(),
+            "loops": loops,
+            "open": open,
+            "area": area,
+        }));
+    } else {
+        println!("Cut {} solids at offset {}, giving {} loops with a total \
+                  area of {}", sections.len(), offset, loops, area);
+        if open > 0 {
+            println!("Warning: {} chains couldn't be closed (the mesh may have \
+                      gaps) and were left out", open);
+        }
+    }
+    Ok(())
+}
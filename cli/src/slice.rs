@@ -0,0 +1,132 @@
+use std::io::Write;
+
+use clap::ArgMatches;
+
+use triangulate::{
+    slice::{layer_heights, slice, Layer},
+    svg::{self, Svg},
+};
+
+use serde_json::{json, Value};
+
+/// Writes every layer's polygons to a single JSON file
+fn save_json(layers: &[Layer], filename: &str) -> std::io::Result<()> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+    // Points are tuples, which serialize as `[u, v]` pairs
+    let layers: Vec<_> = layers.iter().map(|l| json!({
+        "height": l.height,
+        "polygons": l.polygons.iter().map(|p| json!({
+            "outer": p.outer,
+            "holes": p.holes,
+        })).collect::<Vec<_>>(),
+    })).collect();
+    writeln!(out, "{}", Value::from(layers))?;
+    out.flush()
+}
+
+/// Writes each layer to its own SVG drawing, numbering the files from a
+/// template like `slices/layer.svg` (as `slices/layer_0000.svg`, ...).
+/// Every drawing has the same bounds, so that they line up when stacked.
+fn save_svgs(layers: &[Layer], length_unit: Option<f64>, template: &str)
+    -> std::io::Result<()>
+{
+    let stem = &template[..template.len() - ".svg".len()];
+    let mut lo = (f64::INFINITY, f64::INFINITY);
+    let mut hi = (-f64::INFINITY, -f64::INFINITY);
+    for p in layers.iter().flat_map(|l| &l.polygons).flat_map(|p| &p.outer) {
+        lo = (lo.0.min(p.0), lo.1.min(p.1));
+        hi = (hi.0.max(p.0), hi.1.max(p.1));
+    }
+    let (lo, hi) = svg::page(lo, hi);
+
+    for (i, layer) in layers.iter().enumerate() {
+        let filename = format!("{}_{:04}.svg", stem, i);
+        let mut out = Svg::create(&filename, lo, hi, length_unit)?;
+        for p in &layer.polygons {
+            out.path("fill=\"black\" fill-rule=\"evenodd\"",
+                     std::iter::once(&p.outer).chain(&p.holes), true)?;
+        }
+        out.finish()?;
+    }
+    Ok(())
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let ext = output.rsplit('.').next().map(|e| e.to_lowercase());
+    if !matches!(ext.as_deref(), Some("svg") | Some("json")) {
+        return Err(format!("Unknown output format for '{}' \
+                            (expected .svg or .json)", output).into());
+    }
+    let normal = crate::parse_vec3(m.value_of("normal").expect("Could not get normal"))?;
+    if normal.norm() == 0.0 {
+        return Err("Plane normal can't be zero".into());
+    }
+    let normal = normal.normalize();
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    crate::convert_up_axis(m, &mut mesh);
+    if mesh.triangles.is_empty() {
+        return Err("Model is empty".into());
+    }
+
+    // The layer height is either given directly or picked to split the
+    // model into the requested number of layers
+    let thickness: f64 = match (m.value_of("layer"), m.value_of("layers")) {
+        (Some(h), _) => h.parse()?,
+        (None, Some(n)) => {
+            let (lo, hi) = mesh.verts.iter().fold(
+                (f64::INFINITY, -f64::INFINITY),
+                |(lo, hi), v| {
+                    let d = v.pos.dot(&normal);
+                    (lo.min(d), hi.max(d))
+                });
+            (hi - lo) / n.parse::<usize>()?.max(1) as f64
+        },
+        (None, None) => unreachable!("layers has a default value"),
+    };
+    if thickness.is_nan() || thickness <= 0.0 {
+        return Err("Layer height must be positive".into());
+    }
+
+    let start = std::time::SystemTime::now();
+    let layers = slice(&mesh, normal, &layer_heights(&mesh, normal, thickness));
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+    match ext.as_deref() {
+        Some("svg") => save_svgs(&layers, mesh.length_unit, output)?,
+        _ => save_json(&layers, output)?,
+    }
+
+    let polygons: usize = layers.iter().map(|l| l.polygons.len()).sum();
+    let holes: usize = layers.iter()
+        .flat_map(|l| &l.polygons)
+        .map(|p| p.holes.len())
+        .sum();
+    let open: usize = layers.iter().map(|l| l.open).sum();
+    let largest = layers.iter().map(|l| l.area()).fold(0.0, f64::max);
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "layers": layers.len(),
+            "layer_height": thickness,
+            "polygons": polygons,
+            "holes": holes,
+            "open": open,
+            "largest_layer_area": largest,
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Cut {} layers of height {} in {:?}, giving {} polygons \
+                  with {} holes", layers.len(), thickness, dt, polygons, holes);
+        println!("    largest layer area: {}", largest);
+        if open > 0 {
+            println!("Warning: {} chains couldn't be closed (the mesh may have \
+                      gaps) and were left out", open);
+        }
+    }
+    Ok(())
+}
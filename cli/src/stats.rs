@@ -0,0 +1,231 @@
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use step::{cancel::CancelToken, header::Header};
+use triangulate::{
+    external::triangulate_file,
+    hull::{BoundingSphere, ConvexHull},
+    mass::{mass_properties, MassProperties},
+    memory,
+    mesh::{Mesh, Solid},
+};
+
+use serde_json::{json, Value};
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let header = Header::read(input)?;
+    let density: Option<f64> = m.value_of("density").map(str::parse).transpose()?;
+
+    let (mesh, stats) = triangulate_file(Path::new(input),
+                                         crate::tessellation_params(m)?,
+                                         &Default::default(),
+                                         crate::parse_mode(m),
+                                         &CancelToken::default())?
+        .ok_or_else(crate::cancelled)?;
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "header": {
+                "name": header.name,
+                "schema": header.schema,
+                "protocol": header.protocol().to_string(),
+                "author": header.author,
+                "organization": header.organization,
+                "originating_system": header.originating_system,
+                "preprocessor_version": header.preprocessor_version,
+                "time_stamp": header.time_stamp,
+                "description": header.description,
+            },
+            "solids": mesh.solids.len(),
+            "shells": stats.num_shells,
+            "faces": stats.num_faces,
+            "failed_faces": stats.num_errors + stats.num_panics,
+            "sewn_vertices": stats.num_sewn,
+            "draughting_entities": stats.num_draughting,
+            "triangles": mesh.triangles.len(),
+            "vertices": mesh.verts.len(),
+            "mass_properties": mesh.solids.iter()
+                .map(|s| solid_mass_json(&mesh, s, density))
+                .collect::<Vec<_>>(),
+            "bounds": mesh.solids.iter()
+                .map(|s| solid_bounds_json(&mesh, s))
+                .collect::<Vec<_>>(),
+            "memory": memory_json(),
+        }));
+        return Ok(());
+    }
+
+    println!("{}", header);
+    println!();
+    println!("{:<20}{}", "Solids:", mesh.solids.len());
+    println!("{:<20}{}", "Shells:", stats.num_shells);
+    println!("{:<20}{}", "Faces:", stats.num_faces);
+    println!("{:<20}{}", "Failed faces:", stats.num_errors + stats.num_panics);
+    if stats.num_sewn > 0 {
+        println!("{:<20}{}", "Sewn vertices:", stats.num_sewn);
+    }
+    if stats.num_draughting > 0 {
+        println!("{:<20}{}", "Draughting skipped:", stats.num_draughting);
+    }
+    println!("{:<20}{}", "Triangles:", mesh.triangles.len());
+    println!("{:<20}{}", "Vertices:", mesh.verts.len());
+    for s in &mesh.solids {
+        print_solid_mass(&mesh, s, density);
+        print_solid_bounds(&mesh, s);
+    }
+    if cfg!(feature = "memory-profile") {
+        print_memory();
+    }
+    Ok(())
+}
+
+/// Computes a solid's mass properties, using its material's density or the
+/// `--density` fallback.  If neither is known (or the file has no length
+/// unit), only the volume and centroid are meaningful, so the density is
+/// returned as `None`.
+fn solid_mass(mesh: &Mesh, s: &Solid, fallback: Option<f64>)
+    -> Option<(MassProperties, Option<f64>)>
+{
+    let density = s.material.as_ref().and_then(|m| m.density).or(fallback)
+        .filter(|_| mesh.length_unit.is_some());
+    let p = mass_properties(mesh, [(s.triangles.clone(), density.unwrap_or(1.0))],
+                            mesh.length_unit.unwrap_or(1.0))?;
+    Some((p, density))
+}
+
+/// Prints the mass properties of one solid
+fn print_solid_mass(mesh: &Mesh, s: &Solid, fallback: Option<f64>) {
+    println!();
+    match &s.name {
+        Some(name) => println!("Solid #{} ({})", s.id, name),
+        None => println!("Solid #{}", s.id),
+    }
+    if let Some(name) = s.material.as_ref().and_then(|m| m.name.as_ref()) {
+        println!("  {:<18}{}", "Material:", name);
+    }
+    let (p, density) = match solid_mass(mesh, s, fallback) {
+        Some(p) => p,
+        None => {
+            println!("  {:<18}none (not a closed shell)", "Volume:");
+            return;
+        },
+    };
+    println!("  {:<18}{:.6e}", "Volume:", p.volume);
+    let c = p.center;
+    let label = if density.is_some() { "Center of mass:" } else { "Centroid:" };
+    println!("  {:<18}{:.6}, {:.6}, {:.6}", label, c.x, c.y, c.z);
+    if let Some(d) = density {
+        println!("  {:<18}{} kg/m³", "Density:", d);
+        println!("  {:<18}{:.6} kg", "Mass:", p.mass);
+        println!("  {:<18}(kg·m², about the center of mass)", "Inertia:");
+        for i in 0..3 {
+            println!("  {:<18}{:>14.6e}{:>14.6e}{:>14.6e}", "",
+                     p.inertia[(i, 0)], p.inertia[(i, 1)], p.inertia[(i, 2)]);
+        }
+        let m = p.principal_moments();
+        println!("  {:<18}{:.6e}, {:.6e}, {:.6e}", "Principal:", m.x, m.y, m.z);
+    }
+}
+
+fn solid_mass_json(mesh: &Mesh, s: &Solid, fallback: Option<f64>) -> Value {
+    let mass = solid_mass(mesh, s, fallback);
+    let known = mass.and_then(|(p, d)| d.map(|d| (p, d)));
+    json!({
+        "solid": s.id,
+        "name": s.name,
+        "material": s.material.as_ref().and_then(|m| m.name.as_deref()),
+        "volume": mass.map(|(p, _)| p.volume),
+        "center": mass.map(|(p, _)| p.center.as_slice().to_vec()),
+        "density": known.map(|(_, d)| d),
+        "mass": known.map(|(p, _)| p.mass),
+        "inertia": known.map(|(p, _)| (0..3)
+            .map(|i| [p.inertia[(i, 0)], p.inertia[(i, 1)], p.inertia[(i, 2)]])
+            .collect::<Vec<_>>()),
+    })
+}
+
+/// Finds the convex hull and smallest bounding sphere of one solid.  The
+/// sphere only depends on the hull's corners, so it's found from those
+/// unless the solid is flat (and has no hull).
+fn solid_bounds(mesh: &Mesh, s: &Solid)
+    -> (Option<ConvexHull>, Option<BoundingSphere>)
+{
+    let pts = mesh.points(s.triangles.clone());
+    let hull = ConvexHull::from_points(&pts);
+    let sphere = BoundingSphere::from_points(hull.as_ref().map(|h| &h.points[..])
+                                                 .unwrap_or(&pts));
+    (hull, sphere)
+}
+
+/// Prints the convex hull and bounding sphere of one solid
+fn print_solid_bounds(mesh: &Mesh, s: &Solid) {
+    let (hull, sphere) = solid_bounds(mesh, s);
+    match hull {
+        Some(h) => {
+            println!("  {:<18}{} corners, {} triangles", "Convex hull:",
+                     h.points.len(), h.triangles.len());
+            println!("  {:<18}{:.6e}", "Hull volume:", h.volume());
+            println!("  {:<18}{:.6e}", "Hull area:", h.area());
+        },
+        None => println!("  {:<18}none (flat or empty)", "Convex hull:"),
+    }
+    if let Some(s) = sphere {
+        let c = s.center;
+        println!("  {:<18}{:.6} at {:.6}, {:.6}, {:.6}", "Bounding sphere:",
+                 s.radius, c.x, c.y, c.z);
+    }
+}
+
+fn solid_bounds_json(mesh: &Mesh, s: &Solid) -> Value {
+    let (hull, sphere) = solid_bounds(mesh, s);
+    json!({
+        "solid": s.id,
+        "hull_corners": hull.as_ref().map(|h| h.points.len()),
+        "hull_triangles": hull.as_ref().map(|h| h.triangles.len()),
+        "hull_volume": hull.as_ref().map(|h| h.volume()),
+        "hull_area": hull.as_ref().map(|h| h.area()),
+        "sphere_center": sphere.map(|s| s.center.as_slice().to_vec()),
+        "sphere_radius": sphere.map(|s| s.radius),
+    })
+}
+
+/// Prints per-stage allocation statistics, which are only collected when
+/// built with the `memory-profile` feature
+fn print_memory() {
+    let r = memory::report();
+    println!();
+    println!("{:<20}{:>14}{:>14}{:>14}", "Stage", "Allocations", "Allocated", "Peak");
+    for s in &r.stages {
+        println!("{:<20}{:>14}{:>14}{:>14}", format!("{:?}", s.stage),
+                 s.allocations, s.bytes, s.peak);
+    }
+    println!();
+    println!("{:<20}{}", "CDT faces:", r.faces);
+    println!("{:<20}{}", "Total hull bytes:", r.total_hull);
+    println!("{:<20}{} points, {} hull, {} half-edges", "Largest CDT bytes:",
+             r.max_cdt.points, r.max_cdt.hull, r.max_cdt.half_edges);
+}
+
+fn memory_json() -> Option<Value> {
+    if !cfg!(feature = "memory-profile") {
+        return None;
+    }
+    let r = memory::report();
+    let stages: Vec<Value> = r.stages.iter().map(|s| json!({
+        "stage": format!("{:?}", s.stage).to_lowercase(),
+        "allocations": s.allocations,
+        "bytes": s.bytes,
+        "peak": s.peak,
+    })).collect();
+    Some(json!({
+        "stages": stages,
+        "cdt_faces": r.faces,
+        "total_hull_bytes": r.total_hull,
+        "max_cdt": {
+            "points": r.max_cdt.points,
+            "hull": r.max_cdt.hull,
+            "half_edges": r.max_cdt.half_edges,
+        },
+    }))
+}
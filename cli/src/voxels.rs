@@ -0,0 +1,98 @@
+use clap::ArgMatches;
+
+use triangulate::voxel::Occupancy;
+
+use serde_json::json;
+
+/// Writes each layer to its own grayscale PNG, numbering the files from a
+/// template like `slices/layer.png` (as `slices/layer_0000.png`, ...)
+fn save_layers(grid: &Occupancy, template: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let stem = &template[..template.len() - ".png".len()];
+    let [nx, ny, nz] = grid.dims;
+    for z in 0..nz {
+        let file = std::io::BufWriter::new(
+            std::fs::File::create(format!("{}_{:04}.png", stem, z))?);
+        let mut enc = png::Encoder::new(file, nx as u32, ny as u32);
+        enc.set_color(png::ColorType::Grayscale);
+        enc.set_depth(png::BitDepth::Eight);
+        enc.write_header()?.write_image_data(&grid.layer_image(z))?;
+    }
+    Ok(())
+}
+
+pub fn run(m: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = m.value_of("input").expect("Could not get input");
+    let output = m.value_of("output").expect("Could not get output");
+    let ext = output.rsplit('.').next().map(|e| e.to_lowercase());
+    if !matches!(ext.as_deref(), Some("raw") | Some("nrrd") | Some("png")) {
+        return Err(format!("Unknown output format for '{}' \
+                            (expected .raw, .nrrd, or .png)", output).into());
+    }
+
+    let mut mesh = crate::load_mesh(input, crate::parse_mode(m),
+                                    crate::tessellation_params(m)?)?;
+    crate::convert_units(m, &mut mesh)?;
+    if mesh.triangles.is_empty() {
+        return Err("Model is empty".into());
+    }
+
+    // Pixels are either given directly or picked to fit the model's longest
+    // horizontal side, in output units
+    let pixel: f64 = match (m.value_of("pixel"), m.value_of("resolution")) {
+        (Some(p), _) => p.parse()?,
+        (None, Some(n)) => {
+            let (lo, hi) = mesh.verts.iter().fold(
+                (nalgebra_glm::DVec3::repeat(f64::INFINITY),
+                 nalgebra_glm::DVec3::repeat(-f64::INFINITY)),
+                |(lo, hi), v| (lo.inf(&v.pos), hi.sup(&v.pos)));
+            (hi.x - lo.x).max(hi.y - lo.y) / n.parse::<usize>()?.max(1) as f64
+        },
+        (None, None) => unreachable!("resolution has a default value"),
+    };
+    let layer = match m.value_of("layer") {
+        Some(h) => h.parse()?,
+        None => pixel,
+    };
+    if pixel.is_nan() || pixel <= 0.0 || layer.is_nan() || layer <= 0.0 {
+        return Err("Pixel size and layer height must be positive".into());
+    }
+
+    let start = std::time::SystemTime::now();
+    let grid = Occupancy::new(&mesh, pixel, layer);
+    let end = std::time::SystemTime::now();
+    let dt = end.duration_since(start).expect("Time went backwards");
+    match ext.as_deref() {
+        Some("raw") => grid.save_raw(output)?,
+        Some("nrrd") => grid.save_nrrd(output)?,
+        _ => save_layers(&grid, output)?,
+    }
+
+    // The largest cross-section, which is a quick check for whether a part
+    // fits the printer's bed
+    let per_layer = grid.dims[0] * grid.dims[1];
+    let largest = grid.filled.chunks(per_layer)
+        .map(|l| l.iter().filter(|f| **f).count())
+        .max()
+        .unwrap_or(0) as f64 * pixel * pixel;
+    let [nx, ny, nz] = grid.dims;
+    if crate::json_output(m) {
+        println!("{}", json!({
+            "output": output,
+            "dims": grid.dims,
+            "origin": grid.origin.as_slice(),
+            "pixel": pixel,
+            "layer": layer,
+            "filled": grid.count(),
+            "volume": grid.volume(),
+            "largest_layer_area": largest,
+            "seconds": dt.as_secs_f64(),
+        }));
+    } else {
+        println!("Filled {} of {}x{}x{} voxels in {:?}", grid.count(), nx, ny, nz, dt);
+        println!("    volume:             {}", grid.volume());
+        println!("    largest layer area: {}", largest);
+    }
+    Ok(())
+}
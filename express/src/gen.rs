@@ -352,7 +352,10 @@ impl<'a> ParseFromChunks<'a> for {0}_<'a> {{
                          capitalize(&name))?;
                 // Then, write a series of parsers which build the whole struct
                 for (i,a) in attrs.iter().enumerate() {
-                    if a.derived {
+                    if a.derived && REFERENCED_DERIVED.contains(&(a.from.unwrap(), a.name)) {
+                        write!(buf,
+                                 r#"        let (s, _) = param_from_chunks::<DerivedOrRef>"#)?;
+                    } else if a.derived {
                         write!(buf,
                                  r#"        let (s, _) = param_from_chunks::<Derived>"#)?;
                     } else {
@@ -405,6 +408,10 @@ impl<'a> HasId for {}_<'a> {{
     }
 }
 
+/// Derived attributes (as `(entity, attribute)`) for which exporters commonly
+/// write a reference instead of `*`, which are parsed leniently
+const REFERENCED_DERIVED: &[(&str, &str)] = &[("named_unit", "dimensions")];
+
 #[derive(Clone, Debug)]
 struct AttributeData<'a> {
     name: &'a str, // already camel-case
@@ -445,7 +452,7 @@ pub fn gen(s: &mut Syntax) -> Result<String, std::fmt::Error> {
     type_map.0.insert("bool", Type::Primitive("bool"));
     type_map.0.insert("i64", Type::Primitive("i64"));
     type_map.0.insert("f64", Type::Primitive("f64"));
-    type_map.0.insert("&'a str", Type::Primitive("&'a str"));
+    type_map.0.insert("Cow<'a, str>", Type::Primitive("Cow<'a, str>"));
 
     for k in ref_map.keys() {
         type_map.build(k);
@@ -456,9 +463,10 @@ pub fn gen(s: &mut Syntax) -> Result<String, std::fmt::Error> {
     keys.sort_unstable();
     let mut buf = String::new();
     writeln!(&mut buf, "// Autogenerated file, do not hand-edit!
+use std::borrow::Cow;
 use crate::{{
     id::{{Id, HasId}},
-    parse::{{IResult, Logical, Derived, Parse, ParseFromChunks, nom_alt_err,
+    parse::{{IResult, Logical, Derived, DerivedOrRef, Parse, ParseFromChunks, nom_alt_err,
             parse_enum_tag, param_from_chunks, parse_complex_mapping}},
     step_file::FromEntity,
 }};
@@ -947,7 +955,7 @@ impl <'a> SimpleTypes<'a> {
             SimpleTypes::Logical => "Logical",
             SimpleTypes::Number => "f64",
             SimpleTypes::Real(_) => "f64",
-            SimpleTypes::String(_) => "&'a str",
+            SimpleTypes::String(_) => "Cow<'a, str>",
         }
     }
     fn to_type(&self) -> Type {
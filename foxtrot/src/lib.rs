@@ -0,0 +1,381 @@
+//! Loads STEP files into triangle meshes with a single call, hiding the
+//! parser and triangulator behind a small set of plain data types.
+//!
+//! ```no_run
+//! let scene = foxtrot::load("part.step").expect("Could not load file");
+//! for part in &scene.parts {
+//!     let mesh = &scene.meshes[part.mesh];
+//!     println!("{:?}: {} triangles", part.name, mesh.indices.len());
+//! }
+//! ```
+//!
+//! # Stability
+//! The types in this crate are a stable data model, which is decoupled from
+//! the internal representations used by `step` and `triangulate`.  Every
+//! type can be serialized with `serde`, and a [`Scene`] records the
+//! [`VERSION`] of the model which produced it.
+//!
+//! The version is bumped whenever a change would break existing consumers,
+//! i.e. when a field is removed, renamed, or changes meaning.  New fields
+//! may be added without a version bump, so structs are `#[non_exhaustive]`
+//! and missing fields take their default values when deserializing.  The
+//! version itself is required, and scenes with a version that this build
+//! doesn't know (e.g. from a newer release) are rejected.
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use step::{cancel::CancelToken, step_file::ParseMode};
+use triangulate::{external::triangulate_file, groups, mesh};
+
+/// Version of the data model, stored in [`Scene::version`]
+pub const VERSION: u32 = 1;
+
+/// A column-major 4x4 transform matrix
+pub type Transform = [[f64; 4]; 4];
+
+fn identity() -> Transform {
+    [[1.0, 0.0, 0.0, 0.0],
+     [0.0, 1.0, 0.0, 0.0],
+     [0.0, 0.0, 1.0, 0.0],
+     [0.0, 0.0, 0.0, 1.0]]
+}
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Could not load file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not parse file: {0}")]
+    Parse(String),
+
+    #[error("Loading was cancelled")]
+    Cancelled,
+}
+
+/// A triangle mesh, which may be shared between several [`Part`]s
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Mesh {
+    pub positions: Vec<[f64; 3]>,
+    /// Unit normals, one per position
+    pub normals: Vec<[f64; 3]>,
+    /// Triangles, as indices into `positions` with counter-clockwise winding
+    pub indices: Vec<[u32; 3]>,
+    /// The STEP faces which make up the mesh.  Faces which couldn't be
+    /// triangulated are omitted.
+    pub faces: Vec<Face>,
+    /// Volume enclosed by the mesh, in cubic scene units.  This is only
+    /// meaningful for closed meshes.
+    pub volume: f64,
+}
+
+/// A face from the STEP file, as a run of triangles in its [`Mesh`]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Face {
+    /// Index of the face's entity in the STEP file
+    pub id: usize,
+    /// Range of [`Mesh::indices`] which belong to this face
+    pub triangles: Range<usize>,
+}
+
+/// Material properties which the file assigns to a part's product
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Density in kg/m³
+    pub density: Option<f64>,
+    /// Surface finish, e.g. "Anodized"
+    pub finish: Option<String>,
+}
+
+/// A single placed solid
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Part {
+    /// Index of the solid's entity in the STEP file
+    pub id: usize,
+    pub name: Option<String>,
+    /// Index into [`Scene::meshes`]
+    pub mesh: usize,
+    /// Transform from the mesh's coordinates into the scene's
+    pub transform: Transform,
+    /// RGB color from the file's styling, if any
+    pub color: Option<[f64; 3]>,
+    pub material: Option<Material>,
+}
+
+impl Default for Part {
+    fn default() -> Self {
+        Part {
+            id: 0, name: None, mesh: 0, transform: identity(), color: None,
+            material: None,
+        }
+    }
+}
+
+/// A product in the assembly tree
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Node {
+    pub name: String,
+    /// Index into [`Scene::tree`] of the enclosing product, if any
+    pub parent: Option<usize>,
+    /// Indices into [`Scene::tree`] of the products directly inside this one
+    pub children: Vec<usize>,
+    /// Transform from this product's coordinates into its parent's
+    pub transform: Transform,
+    /// Indices into [`Scene::parts`] of the solids directly in this product
+    pub parts: Vec<usize>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node {
+            name: String::new(), parent: None, children: Vec::new(),
+            transform: identity(), parts: Vec::new(),
+        }
+    }
+}
+
+/// Counts of what was (and wasn't) triangulated
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct Diagnostics {
+    pub shells: usize,
+    pub faces: usize,
+    /// Faces which couldn't be triangulated, and are missing from the meshes
+    pub failed_faces: usize,
+    /// Vertices moved or inserted when sewing faces together
+    pub sewn_vertices: usize,
+    /// Entities which only describe 2D drawings or annotations, and were
+    /// skipped
+    pub draughting_entities: usize,
+}
+
+/// What the faces in a [`FaceGroup`] have in common
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum GroupKind {
+    /// Planar faces which lie in the same plane
+    #[default]
+    Plane,
+    /// Cylindrical and conical faces which share an axis
+    Axis,
+}
+
+/// A face of a particular part
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct FaceRef {
+    /// Index into [`Scene::parts`]
+    pub part: usize,
+    /// Index into the part's [`Mesh::faces`]
+    pub face: usize,
+}
+
+/// Faces which lie in the same plane or around the same axis, possibly on
+/// several parts (e.g. the mating surfaces of an assembly)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(default)]
+pub struct FaceGroup {
+    pub kind: GroupKind,
+    /// A point on the plane or axis, in scene coordinates
+    pub point: [f64; 3],
+    /// Unit normal of the plane, or direction of the axis.  Faces may point
+    /// either way along it.
+    pub direction: [f64; 3],
+    pub faces: Vec<FaceRef>,
+}
+
+/// A fully loaded STEP file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Scene {
+    /// Version of the data model, which is [`VERSION`] for scenes loaded by
+    /// this build of the library
+    #[serde(deserialize_with = "deserialize_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+    #[serde(default)]
+    pub meshes: Vec<Mesh>,
+    /// Named products in the assembly, listed before their children.  Parts
+    /// which aren't placed by an assembly (as in many single-part files)
+    /// aren't listed in any node.
+    #[serde(default)]
+    pub tree: Vec<Node>,
+    /// Length of one scene unit in metres, if the file declares it
+    #[serde(default)]
+    pub units: Option<f64>,
+    #[serde(default)]
+    pub diagnostics: Diagnostics,
+    /// Groups of two or more coplanar or coaxial faces, across all parts
+    #[serde(default)]
+    pub face_groups: Vec<FaceGroup>,
+}
+
+/// Reads a scene's version, rejecting versions which this build doesn't
+/// know.  Version 1 is the only one so far, so there's nothing to migrate.
+fn deserialize_version<'de, D>(d: D) -> Result<u32, D::Error>
+    where D: serde::Deserializer<'de>
+{
+    let v = u32::deserialize(d)?;
+    if v == 0 || v > VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "Unsupported scene version {} (expected 1 to {})", v, VERSION)));
+    }
+    Ok(v)
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            version: VERSION,
+            parts: Vec::new(),
+            meshes: Vec::new(),
+            tree: Vec::new(),
+            units: None,
+            diagnostics: Diagnostics::default(),
+            face_groups: Vec::new(),
+        }
+    }
+}
+
+impl Scene {
+    /// Estimates the mass of a part in kg, from its mesh's volume and its
+    /// material's density.  Returns `None` if the density or the scene's
+    /// units are unknown.
+    pub fn mass(&self, part: &Part) -> Option<f64> {
+        let density = part.material.as_ref()?.density?;
+        let unit = self.units?;
+        Some(self.meshes[part.mesh].volume * unit.powi(3) * density)
+    }
+}
+
+/// Loads and triangulates a STEP file, along with any files which it
+/// references for component geometry
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, Error> {
+    let (mesh, stats) = triangulate_file(path.as_ref(), Default::default(),
+                                         &Default::default(), ParseMode::Lenient,
+                                         &CancelToken::default())
+        .map_err(|e| match e.kind() {
+            // Parse errors are reported as invalid data
+            std::io::ErrorKind::InvalidData => Error::Parse(e.to_string()),
+            _ => Error::Io(e),
+        })?
+        .ok_or(Error::Cancelled)?;
+    let mut scene = Scene {
+        units: mesh.length_unit,
+        diagnostics: Diagnostics {
+            shells: stats.num_shells,
+            faces: stats.num_faces,
+            failed_faces: stats.num_errors + stats.num_panics,
+            sewn_vertices: stats.num_sewn,
+            draughting_entities: stats.num_draughting,
+        },
+        ..Default::default()
+    };
+
+    // Instanced solids share the mesh of their original, whose faces are
+    // copied in the same order
+    let mut mesh_of: HashMap<usize, usize> = HashMap::new();
+    let mut face_of: HashMap<usize, FaceRef> = HashMap::new();
+    for (i, solid) in mesh.solids.iter().enumerate() {
+        let m = match solid.instance_of {
+            Some(j) => mesh_of[&j],
+            None => {
+                scene.meshes.push(extract(&mesh, solid.triangles.clone()));
+                scene.meshes.len() - 1
+            },
+        };
+        mesh_of.insert(i, m);
+        let faces = mesh.faces.iter().enumerate()
+            .filter(|(_, f)| !f.triangles.is_empty() &&
+                             solid.triangles.contains(&f.triangles.start));
+        for (j, (f, _)) in faces.enumerate() {
+            face_of.insert(f, FaceRef { part: i, face: j });
+        }
+        scene.parts.push(Part {
+            id: solid.id,
+            name: solid.name.clone(),
+            mesh: m,
+            transform: solid.transform.into(),
+            color: solid.color.map(|c| c.into()),
+            material: solid.material.as_ref().map(|m| Material {
+                name: m.name.clone(),
+                density: m.density,
+                finish: m.finish.clone(),
+            }),
+        });
+    }
+
+    scene.tree = mesh.assembly.iter()
+        .map(|n| Node {
+            name: n.name.clone(),
+            parent: n.parent,
+            children: Vec::new(),
+            transform: n.transform.into(),
+            parts: n.solids.clone(),
+        })
+        .collect();
+    for i in 0..scene.tree.len() {
+        if let Some(p) = scene.tree[i].parent {
+            scene.tree[p].children.push(i);
+        }
+    }
+
+    scene.face_groups = groups::face_groups(&mesh).into_iter()
+        .map(|g| FaceGroup {
+            kind: match g.kind {
+                groups::GroupKind::Plane => GroupKind::Plane,
+                groups::GroupKind::Axis => GroupKind::Axis,
+            },
+            point: g.point.into(),
+            direction: g.direction.into(),
+            faces: g.faces.iter().filter_map(|f| face_of.get(f).copied()).collect(),
+        })
+        .collect();
+    Ok(scene)
+}
+
+/// Copies a range of triangles (and the faces which they belong to) into a
+/// standalone mesh, keeping only the vertices which they use
+fn extract(mesh: &mesh::Mesh, triangles: Range<usize>) -> Mesh {
+    let faces = mesh.faces.iter()
+        .filter(|f| !f.triangles.is_empty() && triangles.contains(&f.triangles.start))
+        .map(|f| Face {
+            id: f.id,
+            triangles: (f.triangles.start - triangles.start)..
+                       (f.triangles.end - triangles.start),
+        })
+        .collect();
+    let volume = mesh.volume(triangles.clone()).abs();
+    let mut out = Mesh { faces, volume, ..Default::default() };
+    let mut remap = HashMap::new();
+    for t in &mesh.triangles[triangles] {
+        let mut tri = [0; 3];
+        for (j, v) in t.verts.iter().enumerate() {
+            tri[j] = *remap.entry(*v).or_insert_with(|| {
+                let vert = &mesh.verts[*v as usize];
+                out.positions.push(vert.pos.into());
+                out.normals.push(vert.norm.into());
+                out.positions.len() as u32 - 1
+            });
+        }
+        out.indices.push(tri);
+    }
+    out
+}
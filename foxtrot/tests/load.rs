@@ -0,0 +1,98 @@
+//! Tests for loading whole files through the facade API
+
+#[test]
+fn loads_assembly() {
+    let scene = foxtrot::load("../examples/abstract_pca.step")
+        .expect("Could not load file");
+    assert_eq!(scene.units, Some(1e-3));
+    assert_eq!(scene.parts.len(), 2);
+    assert_eq!(scene.diagnostics.failed_faces, 0);
+    for part in &scene.parts {
+        let mesh = &scene.meshes[part.mesh];
+        assert!(!mesh.indices.is_empty());
+        assert_eq!(mesh.positions.len(), mesh.normals.len());
+        assert!(mesh.indices.iter().flatten().all(|i| (*i as usize) < mesh.positions.len()));
+        assert!(mesh.volume > 0.0);
+        // The file doesn't assign materials, so masses are unknown
+        assert!(part.material.is_none());
+        assert_eq!(scene.mass(part), None);
+    }
+
+    // Every part is listed under the product which contains it
+    let root = &scene.tree[0];
+    assert_eq!(root.name, "PCB");
+    assert!(root.parent.is_none());
+    let cylinder = scene.tree.iter()
+        .find(|n| n.name == "Cylinder")
+        .expect("Could not find cylinder");
+    assert_eq!(cylinder.parts.len(), 1);
+    assert_eq!(scene.parts[cylinder.parts[0]].name.as_deref(), Some("Cylinder"));
+    for (i, n) in scene.tree.iter().enumerate() {
+        for c in &n.children {
+            assert_eq!(scene.tree[*c].parent, Some(i));
+        }
+    }
+}
+
+#[test]
+fn round_trips_through_serde() {
+    let scene = foxtrot::load("../examples/abstract_pca.step")
+        .expect("Could not load file");
+    assert_eq!(scene.version, foxtrot::VERSION);
+    let json = serde_json::to_string(&scene).expect("Could not serialize");
+    let out: foxtrot::Scene = serde_json::from_str(&json).expect("Could not deserialize");
+    assert_eq!(scene, out);
+
+    // Fields which are missing (e.g. added after the data was written) take
+    // their default values
+    let part: foxtrot::Part = serde_json::from_str(r#"{"id": 12, "mesh": 0}"#)
+        .expect("Could not deserialize");
+    assert_eq!(part.id, 12);
+    assert_eq!(part.transform[3], [0.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn reports_missing_files() {
+    match foxtrot::load("../examples/missing.step") {
+        Err(foxtrot::Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        r => panic!("Expected an I/O error, got {:?}", r.map(|s| s.parts.len())),
+    }
+}
+
+#[test]
+fn checks_versions() {
+    let scene: foxtrot::Scene = serde_json::from_str(r#"{"version": 1}"#)
+        .expect("Could not deserialize");
+    assert!(scene.parts.is_empty());
+
+    // Scenes must say which version wrote them, and it must be known
+    let newer = format!(r#"{{"version": {}}}"#, foxtrot::VERSION + 1);
+    for json in [r#"{}"#, r#"{"version": 0}"#, &newer] {
+        let err = serde_json::from_str::<foxtrot::Scene>(json).unwrap_err().to_string();
+        assert!(err.contains("version"), "{}: {}", json, err);
+    }
+}
+
+#[test]
+fn groups_faces() {
+    let scene = foxtrot::load("../examples/abstract_pca.step")
+        .expect("Could not load file");
+    for part in &scene.parts {
+        let mesh = &scene.meshes[part.mesh];
+        assert!(!mesh.faces.is_empty());
+        assert_eq!(mesh.faces[0].triangles.start, 0);
+        assert_eq!(mesh.faces.last().unwrap().triangles.end, mesh.indices.len());
+    }
+
+    // The bottoms of the board and the cylinder are coplanar
+    assert_eq!(scene.face_groups.len(), 1);
+    let g = &scene.face_groups[0];
+    assert_eq!(g.kind, foxtrot::GroupKind::Plane);
+    assert_eq!(g.direction[2].abs(), 1.0);
+    let parts: Vec<_> = g.faces.iter().map(|f| f.part).collect();
+    assert_eq!(parts, vec![0, 1]);
+    for f in &g.faces {
+        let mesh = &scene.meshes[scene.parts[f.part].mesh];
+        assert!(f.face < mesh.faces.len());
+    }
+}
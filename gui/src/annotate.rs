@@ -0,0 +1,60 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{lines::Segment, measure::Plane};
+
+/// Length of an annotation's leader line, as a fraction of the model's size
+const LEADER_LENGTH: f64 = 0.1;
+
+/// A review note pinned to a point on the model
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Point on the surface, in model coordinates
+    pub point: DVec3,
+    /// Surface normal at the point, along which the leader runs
+    pub normal: DVec3,
+    pub text: String,
+}
+
+impl Annotation {
+    /// Returns the end of the leader line, where the note's text is drawn,
+    /// given the size of the model
+    pub fn label_pos(&self, model_size: f64) -> DVec3 {
+        self.point + self.normal * model_size * LEADER_LENGTH
+    }
+
+    /// Builds the leader line, from the surface point out to the label
+    pub fn leader(&self, model_size: f64, color: Vec3) -> Segment {
+        Segment {
+            start: glm::convert(self.point),
+            end: glm::convert(self.label_pos(model_size)),
+            start_color: color,
+            end_color: color,
+        }
+    }
+}
+
+/// A note which is being typed, after its point has been picked
+pub struct Draft {
+    /// Viewport in which the point was picked
+    pub viewport: usize,
+    pub plane: Plane,
+    pub text: String,
+}
+
+impl Draft {
+    /// Finishes the note, returning `None` if no text was entered
+    pub fn finish(self) -> Option<Annotation> {
+        let text = self.text.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(Annotation {
+                point: self.plane.point,
+                normal: self.plane.normal,
+                text: text.to_owned(),
+            })
+        }
+    }
+}
@@ -1,82 +1,361 @@
+use std::collections::{BTreeSet, HashSet};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::time::Instant;
+
 use nalgebra_glm as glm;
-use glm::Vec2;
+use glm::{DVec3, Vec2, Vec3};
 use winit::{
     dpi::{PhysicalSize},
-    event::{ElementState, ModifiersState, WindowEvent, DeviceEvent, VirtualKeyCode, MouseScrollDelta},
+    event::{
+        ElementState, KeyboardInput, ModifiersState, WindowEvent, DeviceEvent,
+        MouseButton, MouseScrollDelta, VirtualKeyCode,
+    },
+};
+
+use crate::{
+    annotate::Draft,
+    backdrop::Backdrop, camera::FLY_BOOST, clipboard,
+    environment::{Environment, EnvironmentKind}, input::{Action, InputMap},
+    loader::Loader,
+    measure::{Measure, Measurement},
+    overlay::Overlay, report, screenshot, search::Search, session, session::Session,
+    theme::ThemeKind, viewport::{ViewOptions, Viewport}, vis::ColorMode,
 };
 
-use triangulate::mesh::Mesh;
-use crate::{backdrop::Backdrop, camera::Camera, model::Model};
+/// Paths traced per pixel when rendering the view
+const RENDER_SAMPLES: usize = 64;
+/// File to which path-traced renders are saved
+const RENDER_FILE: &str = "render.png";
 
 pub struct App {
     start_time: std::time::SystemTime,
 
+    adapter: wgpu::Adapter,
     surface: wgpu::Surface,
     device: wgpu::Device,
+    queue: wgpu::Queue,
     swapchain_format: wgpu::TextureFormat,
     swapchain: wgpu::SwapChain,
+    /// Vsync'd in low-power mode, to avoid rendering unseen frames
+    present_mode: wgpu::PresentMode,
 
-    loader: Option<std::thread::JoinHandle<Mesh>>,
-    model: Option<Model>,
+    viewports: Vec<Viewport>,
+    /// Source file for each viewport, used when saving the session
+    files: Vec<std::path::PathBuf>,
     backdrop: Backdrop,
-    camera: Camera,
+    overlay: Overlay,
+
+    /// Description of the face under the cursor, and the cursor position
+    tooltip: Option<(Vec2, String)>,
+
+    /// Index of the viewport which receives mouse input
+    active: usize,
+
+    /// When true, camera motion in one viewport is mirrored in the others
+    sync_cameras: bool,
+
+    /// Number of mouse buttons currently held down
+    buttons_down: usize,
+
+    /// Debug visualization mode, shared by every viewport
+    color_mode: ColorMode,
+    show_normals: bool,
+    show_draughting: bool,
+    /// Reflection stripes, for checking surface continuity
+    zebra: bool,
+    /// Diagonal hatching over clipped cross-sections
+    hatch: bool,
+    /// Image-based lighting, shared by every viewport
+    environment: EnvironmentKind,
+    metallic: bool,
+    /// Show each file's header metadata in the HUD
+    show_info: bool,
+    /// Show the list of key bindings
+    show_help: bool,
+
+    /// Movement keys held down in first-person mode
+    fly_keys: HashSet<VirtualKeyCode>,
+    /// Time of the last first-person movement step, so that speed doesn't
+    /// depend on frame rate
+    last_step: Option<Instant>,
+
+    /// Color theme, shared by every viewport and the HUD
+    theme: ThemeKind,
+
+    /// Active tool, which decides what a left click does
+    tool: Tool,
+
+    /// Part search over the active viewport, which captures the keyboard
+    /// while it's open
+    search: Option<Search>,
+
+    /// Set when the user asks to copy the view to the clipboard, which
+    /// happens at the next redraw (since that's when we have a queue)
+    copy_view: bool,
+    /// Set when the user asks for a review report, which is also written at
+    /// the next redraw (since it includes a screenshot)
+    export_report: bool,
+
+    /// Completed measurements, which are saved with the session
+    measurements: Vec<Measurement>,
+
+    /// Where the session is saved, which is the file it was loaded from (if
+    /// any)
+    session_path: String,
+    /// Session state to apply once the models have loaded
+    pending_session: Option<Session>,
 
     depth: (wgpu::Texture, wgpu::TextureView),
     size: PhysicalSize<u32>,
 
     modifiers: ModifiersState,
+    input_map: InputMap,
+
+    /// Set by the device's error handler, which means that the device is
+    /// probably lost and should be recreated
+    device_error: Arc<AtomicBool>,
+    /// Number of consecutive frames where the swapchain was lost, even
+    /// after being rebuilt
+    lost_frames: usize,
 
     first_frame: bool,
 }
 
+/// Interactive tools, which change the meaning of a left click
+enum Tool {
+    /// Clicking prints the picked point and selects its part
+    Select,
+    /// Clicking two faces measures the angle between them
+    Measure(Measure),
+    /// Clicking copies the picked coordinate to the clipboard; the string
+    /// is a description of the last probed point
+    Probe(Option<String>),
+    /// Clicking a point starts a note, which is typed in and pinned to the
+    /// model with `Enter`
+    Annotate(Option<Draft>),
+    /// Dragging a face of the clip box moves it, while dragging elsewhere
+    /// rotates the camera as usual
+    Clip,
+}
+
+impl Tool {
+    /// Returns a description of the tool's state, for display
+    fn status(&self) -> Option<String> {
+        match self {
+            Tool::Select => None,
+            Tool::Measure(m) => Some(m.status()),
+            Tool::Probe(None) =>
+                Some("Probe: click to copy a coordinate\n(shift snaps to surface)".to_owned()),
+            Tool::Probe(Some(p)) => Some(format!("Probe: {}", p)),
+            Tool::Annotate(None) =>
+                Some("Annotate: click a point to add a note".to_owned()),
+            Tool::Annotate(Some(d)) =>
+                Some(format!("Note: {}_\n(Enter to save, Esc to cancel)", d.text)),
+            Tool::Clip =>
+                Some("Clip: drag a face of the box to move it".to_owned()),
+        }
+    }
+}
+
 pub enum Reply {
     Continue,
     Redraw,
     Quit,
+    /// Toggles fullscreen mode, which is handled by the window's owner
+    Fullscreen,
+}
+
+/// Startup options which aren't saved in the session
+pub struct Options {
+    /// Cap frame rate and prefer power-efficient hardware
+    pub low_power: bool,
+    pub input_map: InputMap,
+    pub view: ViewOptions,
 }
 
 impl App {
     pub fn new(start_time: std::time::SystemTime, size: PhysicalSize<u32>,
                adapter: wgpu::Adapter, surface: wgpu::Surface,
-               device: wgpu::Device, loader: std::thread::JoinHandle<Mesh>)
+               loaders: Vec<Loader>,
+               session: Session, options: Options)
         -> Self
     {
+        let device_error = Arc::new(AtomicBool::new(false));
+        let (device, queue) = pollster::block_on(
+                Self::request_device_(&adapter, &device_error))
+            .expect("Failed to create device");
         let swapchain_format = adapter.get_swap_chain_preferred_format(&surface)
             .expect("Could not get swapchain format");
 
+        let present_mode = if options.low_power {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Mailbox
+        };
         let swapchain = Self::rebuild_swapchain_(
-            size, swapchain_format, &surface, &device);
+            size, swapchain_format, present_mode, &surface, &device);
         let depth = Self::rebuild_depth_(size, &device);
-        let backdrop = Backdrop::new(&device, swapchain_format);
+        let theme = ThemeKind::default();
+        let backdrop = Backdrop::new(&device, swapchain_format, theme.theme());
+        let overlay = Overlay::new(&device, swapchain_format, theme.theme());
+
+        let n = loaders.len() as u32;
+        let viewports = loaders.into_iter()
+            .enumerate()
+            .map(|(i, loader)| {
+                let (x, vsize) = Self::viewport_rect_(size, i as u32, n);
+                Viewport::new(loader, options.view, x, vsize)
+            })
+            .collect();
+        // Lighting is applied up front (rather than with the rest of the
+        // session), since it may also come from the command line
+        let environment = session.environment.clone();
+        let metallic = session.metallic;
 
-        Self {
+        let mut app = Self {
             start_time,
 
             swapchain,
+            present_mode,
             depth,
             backdrop,
+            overlay,
+            tooltip: None,
+            search: None,
             swapchain_format,
-            loader: Some(loader),
-            model: None,
-            camera: Camera::new(size.width as f32, size.height as f32),
+            viewports,
+            files: session.files.clone(),
+            active: 0,
+            sync_cameras: session.sync_cameras,
+            buttons_down: 0,
+            color_mode: ColorMode::Shaded,
+            show_normals: false,
+            show_draughting: false,
+            zebra: false,
+            hatch: false,
+            environment: EnvironmentKind::default(),
+            metallic: false,
+            show_info: false,
+            show_help: false,
+            fly_keys: HashSet::new(),
+            last_step: None,
+            theme,
+            tool: Tool::Select,
+            copy_view: false,
+            export_report: false,
+            measurements: Vec::new(),
+            session_path: session.path.clone()
+                .unwrap_or_else(|| format!("session.{}", session::EXTENSION)),
+            // Only restore state from sessions which came from a file, since
+            // otherwise everything is already at its default
+            pending_session: Some(session).filter(|s| s.path.is_some()),
+            adapter,
             surface,
             device,
+            queue,
             size,
 
             modifiers: ModifiersState::empty(),
+            input_map: options.input_map,
+
+            device_error,
+            lost_frames: 0,
 
             first_frame: true,
+        };
+        app.set_environment(environment);
+        app.set_metallic(metallic);
+        app
+    }
+
+    /// Creates the logical device and command queue.  Errors which aren't
+    /// otherwise handled are logged and raise `error`, rather than panicking
+    /// (which is wgpu's default behavior).
+    async fn request_device_(adapter: &wgpu::Adapter, error: &Arc<AtomicBool>)
+        -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError>
+    {
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await?;
+        let error = error.clone();
+        device.on_uncaptured_error(move |e| {
+            eprintln!("GPU error: {}", e);
+            error.store(true, Ordering::Relaxed);
+        });
+        Ok((device, queue))
+    }
+
+    /// Replaces a lost device, recreating every GPU resource from CPU-side
+    /// state.  Returns false if no new device could be created.
+    fn reinitialize(&mut self) -> bool {
+        eprintln!("GPU device lost; reinitializing");
+        self.device_error.store(false, Ordering::Relaxed);
+        let (device, queue) = match pollster::block_on(
+            Self::request_device_(&self.adapter, &self.device_error))
+        {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Could not recreate device: {}", e);
+                return false;
+            },
+        };
+        self.device = device;
+        self.queue = queue;
+        self.swapchain = Self::rebuild_swapchain_(
+            self.size, self.swapchain_format, self.present_mode,
+            &self.surface, &self.device);
+        self.depth = Self::rebuild_depth_(self.size, &self.device);
+        self.backdrop = Backdrop::new(&self.device, self.swapchain_format,
+                                      self.theme.theme());
+        self.overlay = Overlay::new(&self.device, self.swapchain_format,
+                                    self.theme.theme());
+        for v in self.viewports.iter_mut() {
+            v.rebuild_gpu(&self.device, &self.queue, self.swapchain_format);
         }
+        true
     }
 
     pub fn device_event(&mut self, e: DeviceEvent) {
         if let DeviceEvent::MouseWheel { delta } = e {
             if let MouseScrollDelta::PixelDelta(p) = delta {
-                self.camera.mouse_scroll(p.y as f32);
+                self.viewports[self.active].mouse_scroll(p.y as f32);
+                self.sync_from_active();
             }
         }
     }
 
+    /// Returns the position and size of viewport `i` out of `n`, which are
+    /// tiled horizontally across a window of the given size.
+    pub(crate) fn viewport_rect_(size: PhysicalSize<u32>, i: u32, n: u32)
+        -> (u32, PhysicalSize<u32>)
+    {
+        let width = size.width / n;
+        let x = width * i;
+        // The last viewport absorbs any leftover pixels from rounding
+        let width = if i + 1 == n { size.width - x } else { width };
+        (x, PhysicalSize::new(width, size.height))
+    }
+
+    /// Copies the active viewport's camera into every other viewport, if
+    /// camera synchronization is enabled.
+    fn sync_from_active(&mut self) {
+        if !self.sync_cameras {
+            return;
+        }
+        let (before, rest) = self.viewports.split_at_mut(self.active);
+        let (active, after) = rest.split_first_mut().unwrap();
+        for v in before.iter_mut().chain(after.iter_mut()) {
+            v.camera.sync_from(&active.camera);
+        }
+    }
+
     pub fn window_event(&mut self, e: WindowEvent) -> Reply {
         match e {
             WindowEvent::Resized(size) => {
@@ -92,28 +371,141 @@ impl App {
                 self.modifiers = m;
                 Reply::Continue
             },
+            // Releases aren't seen while the window is unfocused, so forget
+            // any held keys rather than flying off forever
+            WindowEvent::Focused(false) => {
+                self.fly_keys.clear();
+                Reply::Continue
+            },
+            WindowEvent::KeyboardInput { input, .. } if self.search.is_some() =>
+                self.search_key(input),
+            WindowEvent::KeyboardInput { input, .. }
+                if matches!(self.tool, Tool::Annotate(Some(_))) => self.annotate_key(input),
+            // Movement keys take the place of their usual bindings while
+            // flying, but still allow shortcuts with Ctrl/Alt/Cmd.  Releases
+            // are always handled, so that keys can't get stuck.
+            WindowEvent::KeyboardInput { input, .. }
+                if self.is_flying() && input.virtual_keycode.and_then(fly_axis).is_some()
+                    && (input.state == ElementState::Released
+                        || !(self.modifiers.ctrl() || self.modifiers.alt()
+                             || self.modifiers.logo())) => self.fly_key(input),
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                match (&mut self.search, &mut self.tool) {
+                    (Some(search), _) => {
+                        search.query.push(c);
+                        search.cursor = 0;
+                    },
+                    (None, Tool::Annotate(Some(d))) => d.text.push(c),
+                    _ => return Reply::Continue,
+                }
+                Reply::Redraw
+            },
             WindowEvent::KeyboardInput { input, .. } => {
-                if self.modifiers.logo() && input.virtual_keycode == Some(VirtualKeyCode::Q) {
-                    Reply::Quit
-                } else {
-                    Reply::Continue
+                match input.virtual_keycode
+                    .filter(|_| input.state == ElementState::Pressed)
+                    .and_then(|k| self.input_map.action(k, self.modifiers))
+                {
+                    Some(a) => self.action(a),
+                    None => Reply::Continue,
                 }
             },
             WindowEvent::MouseInput { button, state, .. } => {
                 use ElementState::*;
+                let v = &mut self.viewports[self.active];
                 match state {
-                    Pressed => self.camera.mouse_pressed(button),
-                    Released => self.camera.mouse_released(button),
+                    Pressed => {
+                        self.buttons_down += 1;
+                        let grabbed = matches!(self.tool, Tool::Clip)
+                            && button == MouseButton::Left
+                            && v.clip_pressed();
+                        if !grabbed {
+                            v.mouse_pressed(button);
+                        }
+                    },
+                    Released => {
+                        self.buttons_down = self.buttons_down.saturating_sub(1);
+                        let hit = v.mouse_released(button);
+                        let ctrl = self.modifiers.ctrl();
+                        if let Some(h) = hit.as_ref().filter(|_| ctrl) {
+                            let toggled = if self.modifiers.shift() {
+                                v.toggle_group_selection(&self.device, self.swapchain_format, h)
+                            } else {
+                                v.toggle_selection(&self.device, self.swapchain_format, h)
+                            };
+                            if toggled {
+                                return Reply::Redraw;
+                            }
+                        } else if let (Some(h), Tool::Measure(m)) = (&hit, &mut self.tool) {
+                            if let Some(r) = v.plane_at(h).and_then(|p| m.add(p)) {
+                                println!("{}", r.describe());
+                                self.measurements.push(r);
+                            }
+                            return Reply::Redraw;
+                        } else if let (Some(h), Tool::Annotate(draft)) = (&hit, &mut self.tool) {
+                            if let Some(plane) = v.plane_at(h) {
+                                *draft = Some(Draft {
+                                    viewport: self.active, plane, text: String::new(),
+                                });
+                            }
+                            return Reply::Redraw;
+                        } else if let (Some(h), Tool::Probe(last)) = (&hit, &mut self.tool) {
+                            let (p, snapped) = v.probe(h, self.modifiers.shift());
+                            let text = format!("{}, {}, {}", p.x, p.y, p.z);
+                            println!("Probed ({}){}", text,
+                                     if snapped { " on surface" } else { "" });
+                            if let Err(e) = clipboard::copy_text(&text) {
+                                eprintln!("Could not copy to clipboard: {}", e);
+                            }
+                            *last = Some(format!("({:.6}, {:.6}, {:.6}){}",
+                                p.x, p.y, p.z, if snapped { "\nsnapped to surface" } else { "" }));
+                            return Reply::Redraw;
+                        } else if let Some(h) = hit {
+                            let p = h.hit.point;
+                            print!("Picked ({}, {}, {})", p.x, p.y, p.z);
+                            if let Some(id) = h.hit.face_id {
+                                print!(" on face #{}", id);
+                            }
+                            if let Some(id) = v.solid_id(h.solid) {
+                                print!(" of solid #{}", id);
+                            }
+                            println!();
+                            if let Some(d) = v.dimensions(&h) {
+                                println!("  {}", d.replace('\n', "\n  "));
+                            }
+                            v.select_part(&h);
+                            return Reply::Redraw;
+                        }
+                    },
                 }
                 Reply::Continue
             }
             WindowEvent::CursorMoved { position, .. } => {
-                self.camera.mouse_move(Vec2::new(position.x as f32, position.y as f32));
+                let pos = Vec2::new(position.x as f32, position.y as f32);
+                // Mouse drags stay attached to the viewport where they began;
+                // otherwise, input goes to whichever viewport is under the
+                // cursor.
+                if self.buttons_down == 0 {
+                    if let Some(i) = self.viewports.iter()
+                        .position(|v| v.contains(pos.x))
+                    {
+                        self.active = i;
+                    }
+                }
+                let v = &mut self.viewports[self.active];
+                v.mouse_move(pos);
+                v.clip_dragged(&self.device, self.swapchain_format);
+                self.tooltip = if self.buttons_down == 0 {
+                    v.hover().and_then(|h| v.describe(&h)).map(|t| (pos, t))
+                } else {
+                    None
+                };
+                self.sync_from_active();
                 Reply::Redraw
             },
             WindowEvent::MouseWheel { delta, ..} => {
                 if let MouseScrollDelta::LineDelta(_, verti) = delta {
-                    self.camera.mouse_scroll(verti * 10.0);
+                    self.viewports[self.active].mouse_scroll(verti * 10.0);
+                    self.sync_from_active();
                 }
                 Reply::Redraw
             },
@@ -121,16 +513,435 @@ impl App {
         }
     }
 
+    fn is_flying(&self) -> bool {
+        self.viewports[self.active].camera.is_flying()
+    }
+
+    /// Tracks a movement key in first-person mode
+    fn fly_key(&mut self, input: KeyboardInput) -> Reply {
+        let k = match input.virtual_keycode {
+            Some(k) => k,
+            None => return Reply::Continue,
+        };
+        match input.state {
+            ElementState::Pressed => {
+                if self.fly_keys.is_empty() {
+                    self.last_step = Some(Instant::now());
+                }
+                self.fly_keys.insert(k);
+            },
+            ElementState::Released => {
+                self.fly_keys.remove(&k);
+            },
+        }
+        Reply::Redraw
+    }
+
+    /// Moves the active first-person camera by the time since the last
+    /// step, according to the held keys
+    fn fly_step(&mut self) {
+        let now = Instant::now();
+        // Cap the step, so that a stalled frame doesn't teleport the eye
+        let dt = self.last_step.map(|t| (now - t).as_secs_f32().min(0.1)).unwrap_or(0.0);
+        self.last_step = Some(now);
+        let dir = self.fly_keys.iter()
+            .filter_map(|k| fly_axis(*k))
+            .fold(Vec3::zeros(), |a, b| a + b);
+        let boost = if self.modifiers.shift() { FLY_BOOST } else { 1.0 };
+        if self.viewports[self.active].camera.fly_move(dir, boost, dt) {
+            self.sync_from_active();
+        }
+    }
+
+    /// Returns a description of first-person mode, if it's active
+    fn fly_status(&self) -> Option<String> {
+        self.viewports[self.active].camera.fly_speed().map(|s| format!(
+            "Fly: WASD to move, Q/E down/up, drag to look\n\
+             speed {:.3}/s (scroll to change, Shift for {}x)", s, FLY_BOOST))
+    }
+
+    /// Handles a key press while the part search is open, which edits the
+    /// query, moves through the matches, or picks one
+    fn search_key(&mut self, input: KeyboardInput) -> Reply {
+        let search = match &mut self.search {
+            Some(s) if input.state == ElementState::Pressed => s,
+            _ => return Reply::Continue,
+        };
+        let v = &mut self.viewports[self.active];
+        let matches = search.matches(v.parts());
+        match input.virtual_keycode {
+            Some(VirtualKeyCode::Escape) => self.search = None,
+            Some(VirtualKeyCode::Back) => {
+                search.query.pop();
+                search.cursor = 0;
+            },
+            Some(VirtualKeyCode::Up) => search.step(-1, matches.len()),
+            Some(VirtualKeyCode::Down) | Some(VirtualKeyCode::Tab) =>
+                search.step(1, matches.len()),
+            Some(VirtualKeyCode::Return) => {
+                if let Some(&i) = matches.get(search.cursor) {
+                    v.focus_part(&self.device, self.swapchain_format, i);
+                    if let Some(text) = v.part_info() {
+                        println!("{}", text);
+                    }
+                    self.sync_from_active();
+                }
+                self.search = None;
+            },
+            _ => return Reply::Continue,
+        }
+        Reply::Redraw
+    }
+
+    /// Handles a key press while a note is being typed
+    fn annotate_key(&mut self, input: KeyboardInput) -> Reply {
+        let draft = match &mut self.tool {
+            Tool::Annotate(d @ Some(_)) if input.state == ElementState::Pressed => d,
+            _ => return Reply::Continue,
+        };
+        match input.virtual_keycode {
+            Some(VirtualKeyCode::Escape) => *draft = None,
+            Some(VirtualKeyCode::Back) => if let Some(d) = draft {
+                d.text.pop();
+            },
+            Some(VirtualKeyCode::Return) => {
+                let d = draft.take().unwrap();
+                let i = d.viewport;
+                if let Some(a) = d.finish() {
+                    let p = a.point;
+                    println!("Added note at ({}, {}, {}): {}", p.x, p.y, p.z, a.text);
+                    self.viewports[i].add_annotation(&self.device, self.swapchain_format, a);
+                }
+            },
+            _ => return Reply::Continue,
+        }
+        Reply::Redraw
+    }
+
+    fn set_theme(&mut self, theme: ThemeKind) {
+        self.theme = theme;
+        self.backdrop.set_theme(&self.device, theme.theme());
+        self.overlay.set_theme(theme.theme());
+        for v in self.viewports.iter_mut() {
+            v.set_theme(&self.device, self.swapchain_format, theme);
+        }
+    }
+
+    /// Lights every viewport with a different environment, keeping the
+    /// current lighting if it can't be loaded
+    fn set_environment(&mut self, kind: EnvironmentKind) {
+        match Environment::load(&kind) {
+            Ok(env) => {
+                let env = env.map(Arc::new);
+                for v in self.viewports.iter_mut() {
+                    v.set_environment(Some((&self.device, &self.queue)), env.clone());
+                }
+                self.environment = kind;
+            },
+            Err(e) => eprintln!("Could not load environment: {}", e),
+        }
+    }
+
+    fn set_metallic(&mut self, metallic: bool) {
+        self.metallic = metallic;
+        for v in self.viewports.iter_mut() {
+            v.set_metallic(metallic);
+        }
+    }
+
+    /// Renders the current view and writes it to an HTML report, along with
+    /// every note and measurement
+    fn write_report(&self, filename: &str) {
+        let pixels = screenshot::render(&self.device, &self.queue,
+                                        self.swapchain_format,
+                                        &self.backdrop, &self.viewports,
+                                        self.size);
+        let markers = self.viewports.iter()
+            .flat_map(|v| v.annotation_labels().into_iter().map(|(p, _)| p))
+            .collect();
+        let view = report::View { size: self.size, pixels, markers };
+        let sections: Vec<_> = self.viewports.iter()
+            .zip(&self.files)
+            .map(|(v, f)| report::Section {
+                file: f, header: v.file_info(), annotations: v.annotations(),
+            })
+            .collect();
+        match report::write(filename, &sections, &self.measurements, Some(view)) {
+            Ok(()) => println!("Wrote review report to {}", filename),
+            Err(e) => eprintln!("Could not write report: {}", e),
+        }
+    }
+
+    /// Captures the current viewer state and writes it to the session file
+    fn save_session(&self) {
+        let session = Session {
+            version: session::VERSION,
+            files: self.files.clone(),
+            sync_cameras: self.sync_cameras,
+            color_mode: self.color_mode,
+            theme: self.theme,
+            show_normals: self.show_normals,
+            show_draughting: self.show_draughting,
+            zebra: self.zebra,
+            hatch: self.hatch,
+            environment: self.environment.clone(),
+            metallic: self.metallic,
+            viewports: self.viewports.iter()
+                .map(|v| session::ViewportState {
+                    camera: Some(v.camera.state()),
+                    selection: v.selection(),
+                    part_colors: v.part_colors().iter()
+                        .map(|(id, c)| (*id, [c.x, c.y, c.z]))
+                        .collect(),
+                    hidden_parts: v.hidden_parts().clone(),
+                    annotations: v.annotations().to_vec(),
+                    clip: v.clip(),
+                })
+                .collect(),
+            measurements: self.measurements.clone(),
+            path: None,
+        };
+        match session.save(&self.session_path) {
+            Ok(()) => println!("Saved session to {}", self.session_path),
+            Err(e) => eprintln!("Could not save session: {}", e),
+        }
+    }
+
+    /// Applies a saved session's state to the freshly-loaded viewports
+    fn restore_session(&mut self, session: Session) {
+        self.sync_cameras = session.sync_cameras;
+        self.color_mode = session.color_mode;
+        self.show_normals = session.show_normals;
+        self.show_draughting = session.show_draughting;
+        self.zebra = session.zebra;
+        self.hatch = session.hatch;
+        self.set_theme(session.theme);
+        if session.environment != self.environment {
+            self.set_environment(session.environment.clone());
+        }
+        self.set_metallic(session.metallic);
+        for (v, state) in self.viewports.iter_mut().zip(&session.viewports) {
+            v.set_color_mode(&self.device, self.swapchain_format, self.color_mode);
+            v.set_show_normals(&self.device, self.swapchain_format, self.show_normals);
+            v.set_show_draughting(&self.device, self.swapchain_format,
+                                  self.show_draughting);
+            v.set_zebra(self.zebra);
+            v.set_hatch(self.hatch);
+            if let Some(c) = &state.camera {
+                v.camera.set_state(c);
+            }
+            v.set_selection(&self.device, self.swapchain_format, &state.selection);
+            v.set_part_colors(&self.device, self.swapchain_format,
+                state.part_colors.iter()
+                    .map(|(id, c)| (*id, DVec3::new(c[0], c[1], c[2])))
+                    .collect());
+            v.set_hidden_parts(&self.device, self.swapchain_format,
+                               state.hidden_parts.clone());
+            v.set_annotations(&self.device, self.swapchain_format,
+                              state.annotations.clone());
+            v.set_clip(&self.device, self.swapchain_format, state.clip);
+        }
+        for m in &session.measurements {
+            println!("Restored measurement: {}", m.describe().replace('\n', ", "));
+        }
+        self.measurements = session.measurements;
+    }
+
+    /// Performs an action from the keyboard
+    fn action(&mut self, a: Action) -> Reply {
+        let v = &mut self.viewports[self.active];
+        match a {
+            Action::Quit => return Reply::Quit,
+            Action::Fullscreen => return Reply::Fullscreen,
+            Action::Cancel => if let Tool::Select = self.tool {
+                for v in self.viewports.iter_mut() {
+                    v.clear_selection(&self.device, self.swapchain_format);
+                }
+            } else {
+                self.tool = Tool::Select;
+            },
+            Action::CycleColorMode => {
+                self.color_mode = self.color_mode.next();
+                println!("Color mode: {}", self.color_mode.name());
+                for v in self.viewports.iter_mut() {
+                    v.set_color_mode(&self.device, self.swapchain_format,
+                                     self.color_mode);
+                }
+            },
+            Action::CyclePartColor => {
+                match v.cycle_part_color(&self.device, self.swapchain_format) {
+                    Some(s) => println!("{}", s),
+                    None => {
+                        println!("Click or search for a part to color it");
+                        return Reply::Continue;
+                    },
+                }
+            },
+            Action::HidePart => {
+                match v.hide_part(&self.device, self.swapchain_format) {
+                    Some(s) => println!("{}", s),
+                    None => {
+                        println!("Click or search for a part to hide it");
+                        return Reply::Continue;
+                    },
+                }
+            },
+            Action::ShowAllParts => {
+                v.set_hidden_parts(&self.device, self.swapchain_format, BTreeSet::new());
+                println!("Showing every part");
+            },
+            Action::CycleTheme => {
+                self.set_theme(self.theme.next());
+                println!("Theme: {}", self.theme.name());
+            },
+            Action::ToggleMeasure => self.tool = match self.tool {
+                Tool::Measure(_) => Tool::Select,
+                _ => Tool::Measure(Measure::default()),
+            },
+            Action::ToggleProbe => self.tool = match self.tool {
+                Tool::Probe(_) => Tool::Select,
+                _ => Tool::Probe(None),
+            },
+            Action::ToggleAnnotate => self.tool = match self.tool {
+                Tool::Annotate(_) => Tool::Select,
+                _ => Tool::Annotate(None),
+            },
+            Action::ToggleClip => self.tool = match self.tool {
+                Tool::Clip => Tool::Select,
+                _ => {
+                    for v in self.viewports.iter_mut() {
+                        v.add_clip(&self.device, self.swapchain_format);
+                    }
+                    Tool::Clip
+                },
+            },
+            Action::ClearClip => for v in self.viewports.iter_mut() {
+                v.set_clip(&self.device, self.swapchain_format, None);
+            },
+            Action::ToggleHatch => {
+                self.hatch = !self.hatch;
+                for v in self.viewports.iter_mut() {
+                    v.set_hatch(self.hatch);
+                }
+            },
+            Action::CycleEnvironment => {
+                self.set_environment(self.environment.next());
+                println!("Lighting: {}", self.environment.name());
+            },
+            Action::ToggleMetallic => self.set_metallic(!self.metallic),
+            Action::ToggleNormals => {
+                self.show_normals = !self.show_normals;
+                for v in self.viewports.iter_mut() {
+                    v.set_show_normals(&self.device, self.swapchain_format,
+                                       self.show_normals);
+                }
+            },
+            Action::ToggleDraughting => {
+                self.show_draughting = !self.show_draughting;
+                for v in self.viewports.iter_mut() {
+                    v.set_show_draughting(&self.device, self.swapchain_format,
+                                          self.show_draughting);
+                }
+            },
+            Action::ToggleZebra => {
+                self.zebra = !self.zebra;
+                for v in self.viewports.iter_mut() {
+                    v.set_zebra(self.zebra);
+                }
+            },
+            Action::ToggleInfo => self.show_info = !self.show_info,
+            Action::ToggleFly => {
+                for v in self.viewports.iter_mut() {
+                    v.camera.toggle_fly();
+                }
+                self.fly_keys.clear();
+            },
+            Action::ToggleHelp => self.show_help = !self.show_help,
+            Action::FindPart => if v.parts().is_empty() {
+                println!("No named parts");
+                return Reply::Continue;
+            } else {
+                self.search = Some(Search::default());
+            },
+            Action::FindClashes => {
+                v.find_clashes();
+                if let Some(text) = v.part_info() {
+                    println!("{}", text);
+                }
+            },
+            Action::ExportSelection | Action::ExportSelectionObj => {
+                let filename = if a == Action::ExportSelectionObj {
+                    "selection.obj"
+                } else {
+                    "selection.stl"
+                };
+                match v.export_selection(filename) {
+                    Ok(0) => println!("No faces selected"),
+                    Ok(n) => println!("Exported {} faces to {}", n, filename),
+                    Err(e) => eprintln!("Could not export selection: {}", e),
+                }
+                return Reply::Continue;
+            },
+            Action::CopySelection => {
+                let ids = v.selected_ids().iter()
+                    .map(|i| format!("#{}", i))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                // Print the ids as well, in case the clipboard is unavailable
+                println!("Selected faces: {}", ids);
+                if let Err(e) = clipboard::copy_text(&ids) {
+                    eprintln!("Could not copy to clipboard: {}", e);
+                }
+                return Reply::Continue;
+            },
+            Action::CopyView => self.copy_view = true,
+            Action::ExportReport => self.export_report = true,
+            Action::Render => {
+                match v.path_tracer(RENDER_SAMPLES) {
+                    Some(job) => {
+                        println!("Rendering with {} samples per pixel...", RENDER_SAMPLES);
+                        std::thread::spawn(move || {
+                            let (size, pixels) = job();
+                            let r = std::fs::File::create(RENDER_FILE)
+                                .and_then(|f| screenshot::write_png(
+                                    std::io::BufWriter::new(f), size, &pixels));
+                            match r {
+                                Ok(()) => println!("Saved render to {}", RENDER_FILE),
+                                Err(e) => eprintln!("Could not save render: {}", e),
+                            }
+                        });
+                    },
+                    None => println!("Nothing to render"),
+                }
+                return Reply::Continue;
+            },
+            Action::SaveSession => {
+                self.save_session();
+                return Reply::Continue;
+            },
+        }
+        Reply::Redraw
+    }
+
     fn resize(&mut self, size: PhysicalSize<u32>) {
         self.size = size;
+        // Minimized windows have no area, and can't have a swapchain; we'll
+        // rebuild everything when the window is restored.
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
         self.swapchain = Self::rebuild_swapchain_(
-            size, self.swapchain_format,
+            size, self.swapchain_format, self.present_mode,
             &self.surface, &self.device);
         self.depth = Self::rebuild_depth_(size, &self.device);
-        self.camera.set_size(size.width as f32, size.height as f32);
+        let n = self.viewports.len() as u32;
+        for (i, v) in self.viewports.iter_mut().enumerate() {
+            let (x, vsize) = Self::viewport_rect_(size, i as u32, n);
+            v.set_rect(x, vsize);
+        }
     }
 
-    fn rebuild_depth_(size: PhysicalSize<u32>, device: &wgpu::Device)
+    pub(crate) fn rebuild_depth_(size: PhysicalSize<u32>, device: &wgpu::Device)
         -> (wgpu::Texture, wgpu::TextureView)
     {
         let size = wgpu::Extent3d {
@@ -154,6 +965,7 @@ impl App {
     }
 
     fn rebuild_swapchain_(size: PhysicalSize<u32>, format: wgpu::TextureFormat,
+                          present_mode: wgpu::PresentMode,
                           surface: &wgpu::Surface, device: &wgpu::Device)
         -> wgpu::SwapChain
     {
@@ -162,28 +974,142 @@ impl App {
             format: format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Mailbox,
+            present_mode,
         };
         device.create_swap_chain(surface, &sc_desc)
     }
 
-    // Redraw the GUI, returning true if the model was not drawn (which means
-    // that the parent loop should keep calling redraw to force model load)
-    pub fn redraw(&mut self, queue: &wgpu::Queue) -> bool {
-        let frame = self.swapchain
-            .get_current_frame()
-            .expect("Failed to acquire next swap chain texture")
-            .output;
+    /// Checks whether every viewport's model has been loaded
+    pub fn is_loaded(&self) -> bool {
+        self.viewports.iter().all(|v| v.is_loaded())
+    }
+
+    /// Aborts any loads which are still in progress
+    pub fn cancel_loads(&mut self) {
+        for v in self.viewports.iter_mut() {
+            v.cancel_load();
+        }
+    }
+
+    // Redraw the GUI, returning Reply::Redraw if the model was not drawn
+    // (which means that the parent loop should keep calling redraw to force
+    // model load, or to retry after a swapchain error)
+    pub fn redraw(&mut self) -> Reply {
+        if self.size.width == 0 || self.size.height == 0 {
+            return Reply::Continue;
+        }
+        if self.device_error.load(Ordering::Relaxed) && !self.reinitialize() {
+            return Reply::Quit;
+        }
+        let frame = match self.swapchain.get_current_frame() {
+            Ok(frame) => {
+                self.lost_frames = 0;
+                frame.output
+            },
+            Err(wgpu::SwapChainError::Timeout) => return Reply::Redraw,
+            Err(wgpu::SwapChainError::Outdated) => {
+                self.resize(self.size);
+                return Reply::Redraw;
+            },
+            // If rebuilding the swapchain doesn't help, then the device has
+            // probably been lost (e.g. by switching GPUs or sleeping)
+            Err(wgpu::SwapChainError::Lost) => {
+                self.lost_frames += 1;
+                if self.lost_frames > 1 && !self.reinitialize() {
+                    return Reply::Quit;
+                }
+                self.resize(self.size);
+                return Reply::Redraw;
+            },
+            Err(wgpu::SwapChainError::OutOfMemory) => {
+                eprintln!("Out of GPU memory");
+                return Reply::Quit;
+            },
+        };
+        if !self.fly_keys.is_empty() {
+            self.fly_step();
+        }
+        let editing_clip = matches!(self.tool, Tool::Clip);
+        for v in self.viewports.iter_mut() {
+            v.show_clip_box = editing_clip;
+        }
+        let queue = &self.queue;
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor { label: None });
 
-        self.backdrop.draw(&frame, &self.depth.1, &mut encoder);
-        if let Some(model) = &self.model {
-            model.draw(&self.camera, &queue, &frame, &self.depth.1, &mut encoder);
+        self.backdrop.draw(&frame.view, &self.depth.1, &mut encoder);
+        for v in &self.viewports {
+            v.draw(queue, &frame.view, &self.depth.1, &mut encoder);
         }
-        let drew_model = self.model.is_some();
+
+        self.overlay.clear();
+        let status = self.tool.status().into_iter()
+            .chain(self.fly_status())
+            .collect::<Vec<_>>();
+        if !status.is_empty() {
+            self.overlay.label(Vec2::new(8.0, 8.0), &status.join("\n"), self.size);
+        }
+        for v in &self.viewports {
+            for (pos, text) in v.annotation_labels() {
+                if let Some(pos) = pos {
+                    self.overlay.label(pos, text, self.size);
+                }
+            }
+            if let Some(text) = v.part_info() {
+                let w = Overlay::text_size(text).x;
+                self.overlay.label(Vec2::new(v.right() - w - 24.0, 8.0),
+                                   text, self.size);
+            }
+            if let Some(text) = v.status() {
+                self.overlay.status_bar(v.left(), v.right() - v.left(),
+                                        &text, self.size);
+            }
+            if self.show_info {
+                let text = v.file_info();
+                let h = Overlay::text_size(&text).y + Overlay::status_bar_height();
+                self.overlay.label(
+                    Vec2::new(v.left() + 8.0, self.size.height as f32 - h - 24.0),
+                    &text, self.size);
+            }
+        }
+        if let Some(search) = &self.search {
+            let v = &self.viewports[self.active];
+            let text = search.text(v.parts(), &search.matches(v.parts()));
+            let w = Overlay::text_size(&text).x;
+            self.overlay.label(Vec2::new((v.left() + v.right() - w) / 2.0, 8.0),
+                               &text, self.size);
+        }
+        if self.show_help {
+            let text = self.input_map.help_text();
+            let size = Overlay::text_size(&text);
+            let window = Vec2::new(self.size.width as f32, self.size.height as f32);
+            self.overlay.label((window - size) / 2.0, &text, self.size);
+        }
+        if let Some((pos, text)) = &self.tooltip {
+            self.overlay.label(pos + Vec2::new(16.0, 16.0), text, self.size);
+        }
+        self.overlay.draw(&self.device, &frame.view, self.size, &mut encoder);
+
+        let drew_model = self.is_loaded();
         queue.submit(Some(encoder.finish()));
 
+        if self.copy_view {
+            self.copy_view = false;
+            let data = screenshot::render(&self.device, &self.queue,
+                                          self.swapchain_format,
+                                          &self.backdrop, &self.viewports,
+                                          self.size);
+            match clipboard::copy_image(self.size.width, self.size.height, data) {
+                Ok(()) => println!("Copied view to clipboard"),
+                Err(e) => eprintln!("Could not copy to clipboard: {}", e),
+            }
+        }
+
+        if self.export_report {
+            self.export_report = false;
+            self.write_report("report.html");
+        }
+
         if drew_model && self.first_frame {
             let end = std::time::SystemTime::now();
             let dt = end.duration_since(self.start_time).expect("dt < 0??");
@@ -194,21 +1120,44 @@ impl App {
         // This is very awkward, but WebGPU doesn't actually do the GPU work
         // until after a queue is submitted, so we don't wait to wait for
         // the model until the _second_ frame.
-        if !self.first_frame && self.model.is_none() {
-            println!("Waiting for mesh");
-            let mesh = self.loader.take()
-                .unwrap()
-                .join()
-                .expect("Failed to load mesh");
-            let model = Model::new(&self.device, self.swapchain_format,
-                                   &mesh.verts, &mesh.triangles);
-            self.model = Some(model);
-            self.camera.fit_verts(&mesh.verts);
-            self.first_frame = true;
+        //
+        // Models are only picked up once their loader threads are done, so
+        // that the window stays responsive (and can be closed) meanwhile.
+        if !self.first_frame && !drew_model {
+            for v in self.viewports.iter_mut().filter(|v| v.is_load_finished()) {
+                v.load(&self.device, self.swapchain_format, false);
+            }
+            if self.is_loaded() {
+                // Start every viewport from the same view, so that models
+                // with a shared coordinate system line up for comparison.
+                if self.sync_cameras {
+                    self.active = 0;
+                    self.sync_from_active();
+                }
+                if let Some(session) = self.pending_session.take() {
+                    self.restore_session(session);
+                }
+                self.first_frame = true;
+            }
         } else {
             self.first_frame = false;
         }
 
-        !drew_model
+        // Keep drawing frames while flying, since the eye keeps moving
+        if drew_model && self.fly_keys.is_empty() { Reply::Continue } else { Reply::Redraw }
     }
 }
+
+/// Returns the direction (in camera axes) of a first-person movement key
+fn fly_axis(k: VirtualKeyCode) -> Option<Vec3> {
+    use VirtualKeyCode::*;
+    Some(match k {
+        W => Vec3::new(0.0, 0.0, -1.0),
+        S => Vec3::new(0.0, 0.0, 1.0),
+        A => Vec3::new(-1.0, 0.0, 0.0),
+        D => Vec3::new(1.0, 0.0, 0.0),
+        Q => Vec3::new(0.0, -1.0, 0.0),
+        E => Vec3::new(0.0, 1.0, 0.0),
+        _ => return None,
+    })
+}
@@ -1,11 +1,18 @@
 use std::borrow::Cow;
 
+use wgpu::util::DeviceExt;
+
+use crate::theme::Theme;
+
 pub struct Backdrop {
     render_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
 }
 
 impl Backdrop {
-    pub fn new(device: &wgpu::Device, swapchain_format: wgpu::TextureFormat) -> Self {
+    pub fn new(device: &wgpu::Device, swapchain_format: wgpu::TextureFormat,
+               theme: &Theme) -> Self {
         // Load the shaders from disk, either at runtime or compile-time
         #[cfg(feature = "bundle-shaders")]
         let backdrop_src = Cow::Borrowed(include_str!("backdrop.wgsl"));
@@ -23,10 +30,26 @@ impl Backdrop {
             flags: wgpu::ShaderFlags::all(),
         });
 
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(32),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
         let pipeline_layout = device.create_pipeline_layout(
             &wgpu::PipelineLayoutDescriptor {
                 label: None,
-                bind_group_layouts: &[],
+                bind_group_layouts: &[&bind_group_layout],
                 push_constant_ranges: &[],
             });
 
@@ -55,12 +78,41 @@ impl Backdrop {
                 multisample: wgpu::MultisampleState::default(),
         });
 
+        let bind_group = Self::bind_group_(device, &bind_group_layout, theme);
         Backdrop {
             render_pipeline,
+            bind_group_layout,
+            bind_group,
         }
     }
 
-    pub fn draw(&self, frame: &wgpu::SwapChainTexture,
+    /// Builds a uniform buffer (and its bind group) holding the gradient
+    fn bind_group_(device: &wgpu::Device, layout: &wgpu::BindGroupLayout,
+                   theme: &Theme) -> wgpu::BindGroup {
+        let [lo, hi] = theme.backdrop;
+        let colors = [lo.x, lo.y, lo.z, 1.0, hi.x, hi.y, hi.z, 1.0];
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Backdrop uniform buffer"),
+            contents: bytemuck::cast_slice(&colors),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    pub fn set_theme(&mut self, device: &wgpu::Device, theme: &Theme) {
+        self.bind_group = Self::bind_group_(device, &self.bind_group_layout, theme);
+    }
+
+    pub fn draw(&self, view: &wgpu::TextureView,
                 depth_view: &wgpu::TextureView,
                 encoder: &mut wgpu::CommandEncoder)
     {
@@ -68,7 +120,7 @@ impl Backdrop {
             &wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
@@ -86,6 +138,7 @@ impl Backdrop {
                     }),
             });
         rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
         rpass.draw(0..6, 0..1);
     }
 }
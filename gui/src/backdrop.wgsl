@@ -3,11 +3,19 @@ struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
 };
 
+[[block]]
+struct Locals {
+    bottom: vec4<f32>;
+    top: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var r_locals: Locals;
+
 [[stage(vertex)]]
 fn vs_main([[builtin(vertex_index)]] in_vertex_index: u32) -> VertexOutput {
     var out: VertexOutput;
-    var c1: vec4<f32> = vec4<f32>(0.05, 0.06, 0.10, 1.0);
-    var c2: vec4<f32> = vec4<f32>(0.17, 0.22, 0.29, 1.0);
+    var c1: vec4<f32> = r_locals.bottom;
+    var c2: vec4<f32> = r_locals.top;
     if (in_vertex_index == 0u || in_vertex_index == 5u) {
         out.color = c1;
         out.position = vec4<f32>(-1.0, -1.0, 0.0, 1.0);
@@ -1,6 +1,7 @@
 use itertools::Itertools;
 use nalgebra_glm as glm;
-use glm::{Vec2, Vec3, Vec4, Mat4};
+use glm::{DVec3, Vec2, Vec3, Vec4, Mat3, Mat4};
+use serde::{Deserialize, Serialize};
 use winit::event::MouseButton;
 
 use triangulate::mesh::Vertex;
@@ -13,6 +14,39 @@ enum MouseState {
     Pan(Vec2, Vec3),
 }
 
+/// Vertical field of view in first-person mode, in radians
+const FLY_FOV: f32 = std::f32::consts::FRAC_PI_3;
+
+/// Default first-person speed, in model sizes per second
+const FLY_SPEED: f32 = 0.25;
+
+/// Speed multiplier while Shift is held in first-person mode
+pub const FLY_BOOST: f32 = 4.0;
+
+/// First-person navigation state.  The view direction comes from the
+/// camera's Euler angles, so only the eye position is stored here.  There's
+/// no collision detection, so the eye passes freely through walls.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Fly {
+    /// Eye position, in model coordinates
+    pub eye: Vec3,
+    /// Movement speed, in model units per second
+    pub speed: f32,
+}
+
+/// The parts of a [`Camera`] which are saved in a session
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct CameraState {
+    pub pitch: f32,
+    pub yaw: f32,
+    pub scale: f32,
+    pub center: Vec3,
+    /// First-person state, if the camera was flying
+    #[serde(default)]
+    pub fly: Option<Fly>,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     /// Aspect ratio of the window
     width: f32,
@@ -30,6 +64,10 @@ pub struct Camera {
     /// Center of view volume
     center: Vec3,
 
+    /// First-person state, which replaces the orbiting orthographic view
+    /// with a perspective view from the eye
+    fly: Option<Fly>,
+
     mouse: MouseState,
 }
 
@@ -42,6 +80,7 @@ impl Camera {
             yaw: 0.0,
             scale: 1.0,
             center: Vec3::zeros(),
+            fly: None,
             mouse: MouseState::Unknown,
         }
     }
@@ -51,7 +90,8 @@ impl Camera {
         if let MouseState::Free(pos) = &self.mouse {
             match button {
                 MouseButton::Left => Some(MouseState::Rotate(*pos)),
-                MouseButton::Right => Some(MouseState::Pan(*pos, self.mouse_pos(*pos))),
+                MouseButton::Right if self.fly.is_none() =>
+                    Some(MouseState::Pan(*pos, self.mouse_pos(*pos))),
                 _ => None,
             }.map(|m| self.mouse = m);
         }
@@ -76,15 +116,50 @@ impl Camera {
             .expect("Failed to invert mouse matrix")
     }
 
+    /// Converts a point in normalized device coordinates into model space
+    fn unproject(mat_i: &Mat4, p: Vec3) -> Vec3 {
+        let q = mat_i * Vec4::new(p.x, p.y, p.z, 1.0);
+        q.xyz() / q.w
+    }
+
     /// Converts a normalized mouse position into 3D
     pub fn mouse_pos(&self, pos_norm: Vec2) -> Vec3 {
-        (self.mat_i() * Vec4::new(pos_norm.x, pos_norm.y, 0.0, 1.0)).xyz()
+        Self::unproject(&self.mat_i(), Vec3::new(pos_norm.x, pos_norm.y, 0.0))
+    }
+
+    /// Projects a model-space point into a position in pixels, which is the
+    /// inverse of [`Camera::ray`].  Returns `None` for points behind the eye
+    /// in first-person mode.
+    pub fn project(&self, p: Vec3) -> Option<Vec2> {
+        let q = self.mat() * Vec4::new(p.x, p.y, p.z, 1.0);
+        if q.w <= 0.0 {
+            return None;
+        }
+        Some(Vec2::new((q.x / q.w / 2.0 + 0.5) * self.width,
+                       (0.5 - q.y / q.w / 2.0) * self.height))
+    }
+
+    /// Converts a position in pixels into normalized device coordinates
+    fn normalize(&self, pos: Vec2) -> Vec2 {
+        let x_norm =  2.0 * (pos.x / self.width - 0.5);
+        let y_norm = -2.0 * (pos.y / self.height - 0.5);
+        Vec2::new(x_norm, y_norm)
+    }
+
+    /// Returns a model-space ray `(origin, dir)` under the given mouse
+    /// position (in pixels).  The ray starts at the near clipping plane and
+    /// reaches the far clipping plane at `origin + dir`.
+    pub fn ray(&self, pos: Vec2) -> (Vec3, Vec3) {
+        let pos = self.normalize(pos);
+        let mat_i = self.mat_i();
+        // Depth testing uses CompareFunction::Greater, so Z = 1 is nearest
+        let near = Self::unproject(&mat_i, Vec3::new(pos.x, pos.y, 1.0));
+        let far = Self::unproject(&mat_i, Vec3::new(pos.x, pos.y, 0.0));
+        (near, far - near)
     }
 
     pub fn mouse_move(&mut self, new_pos: Vec2) {
-        let x_norm =  2.0 * (new_pos.x / self.width - 0.5);
-        let y_norm = -2.0 * (new_pos.y / self.height - 0.5);
-        let new_pos = Vec2::new(x_norm, y_norm);
+        let new_pos = self.normalize(new_pos);
 
         // Pan or rotate depending on current mouse state
         match &self.mouse {
@@ -93,6 +168,15 @@ impl Camera {
                 let delta_pos = orig - current_pos;
                 self.center += delta_pos;
             },
+            MouseState::Rotate(pos) if self.fly.is_some() => {
+                // Mouse-look turns the view the way the mouse moves, and
+                // stops at looking straight up or down so that the view
+                // can't flip over
+                let delta = new_pos - *pos;
+                self.pitch += delta.x * FLY_FOV;
+                self.yaw = (self.yaw - delta.y * FLY_FOV * self.height / self.width)
+                    .clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+            },
             MouseState::Rotate(pos) => {
                 let delta = new_pos - *pos;
                 self.spin(delta.x * 3.0,
@@ -110,8 +194,11 @@ impl Camera {
         }
     }
 
+    /// Zooms about the cursor or, in first-person mode, changes speed
     pub fn mouse_scroll(&mut self, delta: f32) {
-        if let MouseState::Free(pos) = self.mouse {
+        if let Some(fly) = &mut self.fly {
+            fly.speed *= 1.0 + delta / 200.0;
+        } else if let MouseState::Free(pos) = self.mouse {
             self.scale(1.0 + delta / 200.0, pos);
         }
     }
@@ -120,13 +207,122 @@ impl Camera {
         let xb = verts.iter().map(|v| v.pos.x).minmax().into_option().unwrap();
         let yb = verts.iter().map(|v| v.pos.y).minmax().into_option().unwrap();
         let zb = verts.iter().map(|v| v.pos.z).minmax().into_option().unwrap();
-        let dx = xb.1 - xb.0;
-        let dy = yb.1 - yb.0;
-        let dz = zb.1 - zb.0;
-        self.scale = (1.0 / dx.max(dy).max(dz)) as f32;
-        self.center = Vec3::new((xb.0 + xb.1) as f32 / 2.0,
-                                (yb.0 + yb.1) as f32 / 2.0,
-                                (zb.0 + zb.1) as f32 / 2.0);
+        self.fit_bounds(DVec3::new(xb.0, yb.0, zb.0), DVec3::new(xb.1, yb.1, zb.1));
+    }
+
+    /// Centers the view on a bounding box, scaling it to fill the window
+    pub fn fit_bounds(&mut self, min: DVec3, max: DVec3) {
+        let d = max - min;
+        self.scale = (1.0 / d.max()) as f32;
+        self.center = glm::convert((min + max) / 2.0);
+        let eye = self.center + self.back() * self.fly_distance();
+        if let Some(fly) = &mut self.fly {
+            fly.eye = eye;
+        }
+    }
+
+    /// Returns the rotation from model to camera axes
+    fn rotation(&self) -> Mat3 {
+        let i = Mat4::identity();
+        glm::mat4_to_mat3(&(glm::rotate_x(&i, self.yaw) * glm::rotate_y(&i, self.pitch)))
+    }
+
+    /// Returns the model-space direction pointing out of the screen, towards
+    /// the viewer
+    fn back(&self) -> Vec3 {
+        self.rotation().transpose() * Vec3::z()
+    }
+
+    /// Returns how far from the center a first-person eye must be to see
+    /// roughly the same region as the orthographic view
+    fn fly_distance(&self) -> f32 {
+        self.height / self.width / self.scale / (FLY_FOV / 2.0).tan()
+    }
+
+    /// Returns the eye in homogeneous model coordinates, for lighting: the
+    /// first-person eye's position (with W = 1), or the direction towards
+    /// the viewer (with W = 0) for the orthographic view
+    pub fn eye(&self) -> Vec4 {
+        match &self.fly {
+            Some(f) => Vec4::new(f.eye.x, f.eye.y, f.eye.z, 1.0),
+            None => {
+                let b = self.back();
+                Vec4::new(b.x, b.y, b.z, 0.0)
+            },
+        }
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.fly.is_some()
+    }
+
+    /// Switches between orbiting and first-person views, keeping the view
+    /// direction.  Entering places the eye so that the visible region
+    /// roughly matches; leaving puts the center back in front of the eye.
+    pub fn toggle_fly(&mut self) {
+        self.fly = match self.fly.take() {
+            Some(f) => {
+                self.center = f.eye - self.back() * self.fly_distance();
+                None
+            },
+            None => Some(Fly {
+                eye: self.center + self.back() * self.fly_distance(),
+                speed: FLY_SPEED / self.scale,
+            }),
+        };
+    }
+
+    /// Moves the first-person eye for `dt` seconds, given a direction in
+    /// camera axes (x right, y up, z backwards) and a speed multiplier.
+    /// Returns false if the camera isn't flying.
+    pub fn fly_move(&mut self, dir: Vec3, boost: f32, dt: f32) -> bool {
+        let r = self.rotation().transpose();
+        match &mut self.fly {
+            Some(f) => {
+                f.eye += r * dir * f.speed * boost * dt;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns the first-person speed, in model units per second
+    pub fn fly_speed(&self) -> Option<f32> {
+        self.fly.map(|f| f.speed)
+    }
+
+    /// Copies orientation, scale, and center from another camera, leaving
+    /// window size and mouse state unchanged.
+    pub fn sync_from(&mut self, other: &Camera) {
+        self.pitch = other.pitch;
+        self.yaw = other.yaw;
+        self.scale = other.scale;
+        self.center = other.center;
+        self.fly = other.fly;
+    }
+
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            pitch: self.pitch,
+            yaw: self.yaw,
+            scale: self.scale,
+            center: self.center,
+            fly: self.fly,
+        }
+    }
+
+    pub fn set_state(&mut self, s: &CameraState) {
+        self.pitch = s.pitch;
+        self.yaw = s.yaw;
+        self.scale = s.scale;
+        self.center = s.center;
+        self.fly = s.fly;
+    }
+
+    /// Sets the camera's Euler angles directly
+    pub fn set_orientation(&mut self, pitch: f32, yaw: f32) {
+        self.pitch = pitch;
+        self.yaw = yaw;
     }
 
     pub fn set_size(&mut self, width: f32, height: f32) {
@@ -136,6 +332,12 @@ impl Camera {
 
     pub fn model_matrix(&self) -> Mat4 {
         let i = Mat4::identity();
+        if let Some(f) = &self.fly {
+            // Move the eye to the origin, then turn to face along -Z
+            return glm::rotate_x(&i, self.yaw) *
+                   glm::rotate_y(&i, self.pitch) *
+                   glm::translate(&i, &-f.eye);
+        }
         // The transforms below are applied bottom-to-top when thinking about
         // the model, i.e. it's translated, then scaled, then rotated, etc.
 
@@ -153,6 +355,14 @@ impl Camera {
     /// Returns a matrix which compensates for window aspect ratio and clipping
     pub fn view_matrix(&self) -> Mat4 {
         let i = Mat4::identity();
+        if self.fly.is_some() {
+            // Depth testing uses CompareFunction::Greater, so the near plane
+            // maps to Z = 1.  Clipping planes scale with the model, so that
+            // the eye can get close to small parts without clipping them.
+            let size = 1.0 / self.scale;
+            return glm::reversed_perspective_rh_zo(
+                self.width / self.height, FLY_FOV, size * 1e-4, size * 100.0);
+        }
         // The Z clipping range is 0-1, so push forward
         glm::translate(&i, &Vec3::new(0.0, 0.0, 0.5)) *
 
@@ -0,0 +1,118 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::lines::Segment;
+
+/// Smallest allowed thickness of the box, as a fraction of its largest side
+const MIN_THICKNESS: f64 = 1e-3;
+
+/// An axis-aligned box, outside of which the model is hidden.  This isolates
+/// a sub-volume of an assembly without hiding whole parts; a single clipping
+/// plane is a box with only one face pulled in.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ClipBox {
+    pub min: DVec3,
+    pub max: DVec3,
+}
+
+/// One face of a [`ClipBox`], which is dragged to move it along its axis
+#[derive(Copy, Clone, Debug)]
+pub struct Handle {
+    /// Axis (0, 1, or 2) to which the face is perpendicular
+    pub axis: usize,
+    /// True for the face at `max[axis]`
+    pub max: bool,
+}
+
+/// A face which is being dragged, along with the point where it was grabbed
+#[derive(Copy, Clone, Debug)]
+pub struct Drag {
+    pub handle: Handle,
+    grab: DVec3,
+}
+
+impl ClipBox {
+    /// Builds a box around the model's bounds, grown slightly so that
+    /// nothing is clipped at first
+    pub fn around(min: DVec3, max: DVec3) -> Self {
+        let margin = (max - min).max() * 0.01;
+        Self { min: min.add_scalar(-margin), max: max.add_scalar(margin) }
+    }
+
+    pub fn contains(&self, p: DVec3) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+
+    /// Returns the box's bounds in single precision, for drawing
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        (glm::convert(self.min), glm::convert(self.max))
+    }
+
+    /// Builds the box's twelve edges, for drawing it as a wireframe
+    pub fn edges(&self, color: Vec3) -> Vec<Segment> {
+        let (lo, hi) = self.bounds();
+        let corner = |i: usize| Vec3::new(
+            if i & 1 == 0 { lo.x } else { hi.x },
+            if i & 2 == 0 { lo.y } else { hi.y },
+            if i & 4 == 0 { lo.z } else { hi.z });
+        // Each edge joins two corners which differ in one bit
+        (0..8).flat_map(|i| [1, 2, 4].iter().copied()
+                .filter(move |b| i & b == 0)
+                .map(move |b| (i, i | b)))
+            .map(|(a, b)| Segment {
+                start: corner(a),
+                end: corner(b),
+                start_color: color,
+                end_color: color,
+            })
+            .collect()
+    }
+
+    /// Finds the nearest face hit by the ray `origin + t * dir`, looking at
+    /// the faces from either side
+    pub fn pick(&self, origin: DVec3, dir: DVec3) -> Option<Drag> {
+        let mut best: Option<(f64, Handle)> = None;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                continue;
+            }
+            for max in [false, true] {
+                let v = if max { self.max[axis] } else { self.min[axis] };
+                let t = (v - origin[axis]) / dir[axis];
+                let p = origin + dir * t;
+                let inside = (0..3).filter(|&i| i != axis)
+                    .all(|i| p[i] >= self.min[i] && p[i] <= self.max[i]);
+                if t >= 0.0 && inside && best.map(|b| t < b.0).unwrap_or(true) {
+                    best = Some((t, Handle { axis, max }));
+                }
+            }
+        }
+        best.map(|(t, handle)| Drag { handle, grab: origin + dir * t })
+    }
+
+    /// Moves the dragged face so that it follows the ray, by finding the
+    /// point on the face's axis (through the grab point) which is nearest
+    /// to the ray.  Rays which are parallel to the axis are ignored.
+    pub fn drag(&mut self, drag: &Drag, origin: DVec3, dir: DVec3) {
+        let axis = drag.handle.axis;
+        let mut u = DVec3::zeros();
+        u[axis] = 1.0;
+        let d = dir.normalize();
+        let w = drag.grab - origin;
+        let b = u.dot(&d);
+        let denom = 1.0 - b * b;
+        if denom < 1e-6 {
+            return;
+        }
+        let s = (b * d.dot(&w) - u.dot(&w)) / denom;
+        let v = drag.grab[axis] + s;
+
+        let gap = (self.max - self.min).max() * MIN_THICKNESS;
+        if drag.handle.max {
+            self.max[axis] = v.max(self.min[axis] + gap);
+        } else {
+            self.min[axis] = v.min(self.max[axis] - gap);
+        }
+    }
+}
@@ -0,0 +1,13 @@
+/// Copies a string to the system clipboard
+pub fn copy_text(text: &str) -> Result<(), arboard::Error> {
+    arboard::Clipboard::new()?.set_text(text.to_owned())
+}
+
+/// Copies an image to the system clipboard, given tightly-packed RGBA pixels
+pub fn copy_image(width: u32, height: u32, rgba: Vec<u8>) -> Result<(), arboard::Error> {
+    arboard::Clipboard::new()?.set_image(arboard::ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: rgba.into(),
+    })
+}
@@ -0,0 +1,559 @@
+use std::f32::consts::{PI, TAU};
+use std::path::PathBuf;
+
+use nalgebra_glm as glm;
+use glm::Vec3;
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+/// Largest width of an environment map, in pixels; bigger images are
+/// shrunk when loaded, since reflections don't need more detail than this
+const MAX_WIDTH: usize = 512;
+
+/// Size of the built-in environment maps
+const BUILTIN_SIZE: (usize, usize) = (512, 256);
+
+/// Environments are shrunk to this size before convolving them into
+/// diffuse lighting, which is smooth enough that nothing is lost
+const CONVOLVE_SIZE: (usize, usize) = (64, 32);
+
+/// Size of the diffuse irradiance map
+const IRRADIANCE_SIZE: (usize, usize) = (32, 16);
+
+/// Reflectance of non-metallic surfaces when seen face-on
+const DIELECTRIC_F0: f32 = 0.04;
+
+/// Selects how models are lit in the realtime renderer
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentKind {
+    /// A light at the viewer's eye, which is the default
+    #[default]
+    Headlight,
+    Studio,
+    Sky,
+    Sunset,
+    /// An equirectangular HDR image in Radiance (`.hdr`) format
+    File(PathBuf),
+}
+
+impl EnvironmentKind {
+    /// Returns the next built-in environment, for cycling through them with
+    /// a hotkey.  Files are left as the cycle's first step back to the
+    /// headlight.
+    pub fn next(&self) -> Self {
+        match self {
+            EnvironmentKind::Headlight => EnvironmentKind::Studio,
+            EnvironmentKind::Studio => EnvironmentKind::Sky,
+            EnvironmentKind::Sky => EnvironmentKind::Sunset,
+            EnvironmentKind::Sunset | EnvironmentKind::File(_) =>
+                EnvironmentKind::Headlight,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            EnvironmentKind::Headlight => "headlight".to_owned(),
+            EnvironmentKind::Studio => "studio".to_owned(),
+            EnvironmentKind::Sky => "sky".to_owned(),
+            EnvironmentKind::Sunset => "sunset".to_owned(),
+            EnvironmentKind::File(p) => p.display().to_string(),
+        }
+    }
+
+    /// Parses a built-in environment's name, treating anything else as the
+    /// path to an HDR file
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "headlight" => EnvironmentKind::Headlight,
+            "studio" => EnvironmentKind::Studio,
+            "sky" => EnvironmentKind::Sky,
+            "sunset" => EnvironmentKind::Sunset,
+            _ => EnvironmentKind::File(PathBuf::from(s)),
+        }
+    }
+}
+
+/// An image of the light arriving from every direction, stored as an
+/// equirectangular map of linear RGB.  Model coordinates are Z-up, so the
+/// top row of the map is straight up.
+struct EnvMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+/// Returns the position in an equirectangular map (from 0 to 1 on each
+/// axis) which a direction points to, matching `equirect` in `model.wgsl`
+fn equirect(d: Vec3) -> (f32, f32) {
+    let u = d.y.atan2(d.x) / TAU + 0.5;
+    let v = d.z.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+/// Inverse of [`equirect`], for the center of a pixel in a map of the given
+/// size
+fn direction(x: usize, y: usize, width: usize, height: usize) -> Vec3 {
+    let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * TAU;
+    let theta = (y as f32 + 0.5) / height as f32 * PI;
+    Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos())
+}
+
+fn luminance(c: Vec3) -> f32 {
+    c.dot(&Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+impl EnvMap {
+    fn from_fn<F: Fn(Vec3) -> Vec3>(width: usize, height: usize, f: F) -> Self {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(direction(x, y, width, height)))
+            .collect();
+        Self { width, height, pixels }
+    }
+
+    /// Looks up the light from a direction, with bilinear filtering.  The
+    /// map wraps around horizontally and is clamped vertically, like the
+    /// GPU sampler.
+    fn sample(&self, d: Vec3) -> Vec3 {
+        let (u, v) = equirect(d);
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let px = |x: f32, y: f32| {
+            let x = (x as isize).rem_euclid(self.width as isize) as usize;
+            let y = (y as usize).min(self.height - 1);
+            self.pixels[y * self.width + x]
+        };
+        let top = px(x0, y0) * (1.0 - fx) + px(x0 + 1.0, y0) * fx;
+        let bottom = px(x0, y0 + 1.0) * (1.0 - fx) + px(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Shrinks the map by averaging blocks of pixels, so that the total
+    /// light (including from small, bright sources) is kept
+    fn downsample(&self, width: usize, height: usize) -> Self {
+        let (sx, sy) = ((self.width / width).max(1), (self.height / height).max(1));
+        let (width, height) = (self.width / sx, self.height / sy);
+        let mut pixels = vec![Vec3::zeros(); width * height];
+        for y in 0..height * sy {
+            for x in 0..width * sx {
+                pixels[(y / sy) * width + x / sx] += self.pixels[y * self.width + x];
+            }
+        }
+        for p in pixels.iter_mut() {
+            *p /= (sx * sy) as f32;
+        }
+        Self { width, height, pixels }
+    }
+
+    /// Builds the diffuse lighting map, where each pixel is the average of
+    /// the incoming light over the hemisphere around its direction,
+    /// weighted by the cosine of the angle to that direction
+    fn irradiance(&self) -> Self {
+        let src = self.downsample(CONVOLVE_SIZE.0, CONVOLVE_SIZE.1);
+        // The solid angle of each source pixel shrinks towards the poles
+        let texels: Vec<(Vec3, Vec3, f32)> = (0..src.height)
+            .flat_map(|y| (0..src.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let d = direction(x, y, src.width, src.height);
+                let theta = (y as f32 + 0.5) / src.height as f32 * PI;
+                (d, src.pixels[y * src.width + x], theta.sin())
+            })
+            .collect();
+        let (w, h) = IRRADIANCE_SIZE;
+        Self::from_fn(w, h, |n| {
+            let (mut sum, mut weight) = (Vec3::zeros(), 0.0);
+            for (d, c, area) in &texels {
+                let cos = n.dot(d);
+                if cos > 0.0 {
+                    sum += c * (cos * area);
+                    weight += cos * area;
+                }
+            }
+            sum / weight.max(f32::MIN_POSITIVE)
+        })
+    }
+
+    /// Multiplies every pixel by a constant
+    fn scale(&mut self, k: f32) {
+        for p in self.pixels.iter_mut() {
+            *p *= k;
+        }
+    }
+
+    /// Packs the map into `Rgba16Float` texels
+    fn to_f16(&self) -> Vec<u8> {
+        self.pixels.iter()
+            .flat_map(|c| [c.x, c.y, c.z, 1.0])
+            .flat_map(|f| f32_to_f16(f).to_le_bytes())
+            .collect()
+    }
+
+    /// Reads a Radiance HDR image, supporting both flat and run-length
+    /// encoded scanlines
+    fn load_hdr(data: &[u8]) -> Result<Self, String> {
+        let mut pos = 0;
+        let mut line = || -> Result<&[u8], String> {
+            let start = pos;
+            let end = data[start..].iter().position(|&b| b == b'\n')
+                .map(|i| start + i)
+                .ok_or("Unexpected end of HDR header")?;
+            pos = end + 1;
+            Ok(&data[start..end])
+        };
+        if !line()?.starts_with(b"#?") {
+            return Err("Not a Radiance HDR file".to_owned());
+        }
+        loop {
+            let l = line()?;
+            if l.is_empty() {
+                break;
+            } else if l.starts_with(b"FORMAT=") && l != b"FORMAT=32-bit_rle_rgbe" {
+                return Err(format!("Unsupported HDR pixel format '{}'",
+                                   String::from_utf8_lossy(l)));
+            }
+        }
+        let res = String::from_utf8_lossy(line()?).into_owned();
+        let (height, width) = match res.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", h, "+X", w] => (h.parse::<usize>().ok(), w.parse::<usize>().ok()),
+            _ => (None, None),
+        };
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(format!("Unsupported HDR resolution '{}'", res)),
+        };
+        // A run packs up to 127 values of a channel into two bytes, so there
+        // can't be more than 16 pixels per byte of data.  This rejects
+        // corrupt headers before allocating space for their pixels.
+        if width.checked_mul(height).is_none_or(|n| n / 16 > data.len() - pos) {
+            return Err(format!("HDR resolution '{}' is larger than its data", res));
+        }
+
+        let mut data = &data[pos..];
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            if data.len() < n {
+                return Err("Unexpected end of HDR data".to_owned());
+            }
+            let (a, b) = data.split_at(n);
+            data = b;
+            Ok(a)
+        };
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut row = vec![0u8; width * 4];
+        for _ in 0..height {
+            let head = take(4)?;
+            let rle = (8..0x8000).contains(&width) && head[0] == 2 && head[1] == 2 &&
+                ((head[2] as usize) << 8 | head[3] as usize) == width;
+            if rle {
+                // Each channel is stored separately, as runs and literals
+                for c in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let n = take(1)?[0] as usize;
+                        let (n, run) = if n > 128 { (n - 128, true) } else { (n, false) };
+                        if n == 0 || x + n > width {
+                            return Err("Corrupt HDR scanline".to_owned());
+                        }
+                        if run {
+                            let v = take(1)?[0];
+                            for i in 0..n {
+                                row[(x + i) * 4 + c] = v;
+                            }
+                        } else {
+                            for (i, &v) in take(n)?.iter().enumerate() {
+                                row[(x + i) * 4 + c] = v;
+                            }
+                        }
+                        x += n;
+                    }
+                }
+            } else {
+                row[..4].copy_from_slice(head);
+                row[4..].copy_from_slice(take(width * 4 - 4)?);
+            }
+            pixels.extend(row.chunks(4).map(|p| {
+                if p[3] == 0 {
+                    Vec3::zeros()
+                } else {
+                    let f = 2f32.powi(p[3] as i32 - 136);
+                    Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32) * f
+                }
+            }));
+        }
+        let map = Self { width, height, pixels };
+        Ok(if width > MAX_WIDTH {
+            map.downsample(MAX_WIDTH, MAX_WIDTH / 2)
+        } else {
+            map
+        })
+    }
+}
+
+/// Smoothly goes from 0 to 1 as `x` goes from `a` to `b`
+fn smoothstep(a: f32, b: f32, x: f32) -> f32 {
+    let t = ((x - a) / (b - a)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Returns 1 within a soft-edged disk of the given angular radius around
+/// `center`, and 0 outside of it
+fn disk(d: Vec3, center: Vec3, radius: f32) -> f32 {
+    let angle = d.dot(&center.normalize()).clamp(-1.0, 1.0).acos();
+    1.0 - smoothstep(radius * 0.8, radius, angle)
+}
+
+/// Returns the unit vector at an azimuth and elevation, in degrees
+fn spherical(azimuth: f32, elevation: f32) -> Vec3 {
+    let (a, e) = (azimuth.to_radians(), elevation.to_radians());
+    Vec3::new(e.cos() * a.cos(), e.cos() * a.sin(), e.sin())
+}
+
+/// A dim gray room with large soft boxes to either side and overhead,
+/// which gives broad, clean highlights on shiny parts
+fn studio(d: Vec3) -> Vec3 {
+    let wall = 0.25 + 0.25 * smoothstep(-0.2, 0.8, d.z);
+    let floor = 0.08;
+    let mut c = Vec3::repeat(floor + (wall - floor) * smoothstep(-0.05, 0.05, d.z));
+    // Soft boxes are long strips: wide in azimuth, narrow in elevation
+    for (az, el, k) in [(60.0, 25.0, 6.0), (-120.0, 20.0, 3.0)] {
+        let a = d.y.atan2(d.x).to_degrees();
+        let da = ((a - az + 540.0) % 360.0 - 180.0).abs();
+        let e = d.z.clamp(-1.0, 1.0).asin().to_degrees();
+        c += Vec3::repeat(k * (1.0 - smoothstep(20.0, 25.0, da)) *
+                          (1.0 - smoothstep(8.0, 10.0, (e - el).abs())));
+    }
+    c + Vec3::repeat(4.0 * disk(d, Vec3::z(), 0.35))
+}
+
+/// A clear blue sky over neutral ground, with a high sun
+fn sky(d: Vec3) -> Vec3 {
+    let horizon = Vec3::new(0.85, 0.9, 1.0);
+    let zenith = Vec3::new(0.25, 0.45, 0.9);
+    let ground = Vec3::new(0.25, 0.23, 0.2);
+    let up = smoothstep(0.0, 1.0, d.z.max(0.0)).sqrt();
+    let sky = horizon * (1.0 - up) + zenith * up;
+    let c = ground + (sky - ground) * smoothstep(-0.02, 0.02, d.z);
+    c + Vec3::new(1.0, 0.95, 0.85) * (200.0 * disk(d, spherical(45.0, 50.0), 0.03))
+}
+
+/// A warm, low sun over a darkening sky and dark ground
+fn sunset(d: Vec3) -> Vec3 {
+    let horizon = Vec3::new(1.0, 0.55, 0.25);
+    let zenith = Vec3::new(0.1, 0.12, 0.3);
+    let ground = Vec3::new(0.06, 0.05, 0.05);
+    let up = smoothstep(0.0, 0.7, d.z.max(0.0));
+    let sky = horizon * (1.0 - up) + zenith * up;
+    let c = ground + (sky - ground) * smoothstep(-0.02, 0.02, d.z);
+    let sun = spherical(-30.0, 5.0);
+    // The glow around the sun lights the horizon unevenly
+    let glow = d.dot(&sun).max(0.0).powi(8);
+    c + Vec3::new(1.0, 0.5, 0.2) * glow +
+        Vec3::new(1.0, 0.6, 0.3) * (150.0 * disk(d, sun, 0.04))
+}
+
+/// Converts a float into IEEE half precision, rounding towards zero.  Tiny
+/// values become zero and huge values saturate, which is fine for light.
+fn f32_to_f16(f: f32) -> u16 {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    if f.is_nan() {
+        0x7e00
+    } else if exp <= 0 {
+        sign
+    } else if exp >= 0x1f {
+        sign | 0x7bff
+    } else {
+        sign | ((exp as u16) << 10) | ((bits >> 13) & 0x3ff) as u16
+    }
+}
+
+/// Image-based lighting for the realtime renderer: an environment map for
+/// reflections, and a blurred copy for diffuse light
+pub struct Environment {
+    env: EnvMap,
+    irradiance: EnvMap,
+}
+
+impl Environment {
+    /// Loads or builds the environment, returning `None` for the headlight
+    /// (which needs no maps)
+    pub fn load(kind: &EnvironmentKind) -> Result<Option<Self>, String> {
+        let (w, h) = BUILTIN_SIZE;
+        let mut env = match kind {
+            EnvironmentKind::Headlight => return Ok(None),
+            EnvironmentKind::Studio => EnvMap::from_fn(w, h, studio),
+            EnvironmentKind::Sky => EnvMap::from_fn(w, h, sky),
+            EnvironmentKind::Sunset => EnvMap::from_fn(w, h, sunset),
+            EnvironmentKind::File(path) => {
+                let data = std::fs::read(path)
+                    .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+                EnvMap::load_hdr(&data)?
+            }
+        };
+        // Expose so that the brightest diffuse light is white, which keeps
+        // shading in the same range as the headlight
+        let mut irradiance = env.irradiance();
+        let k = irradiance.pixels.iter().copied().map(luminance).fold(0.0, f32::max);
+        if k > 0.0 {
+            env.scale(1.0 / k);
+            irradiance.scale(1.0 / k);
+        }
+        Ok(Some(Self { env, irradiance }))
+    }
+
+    /// Shades a surface point, matching `fs_main` in `model.wgsl`.  `n` is
+    /// the surface normal and `v` points towards the viewer, both in model
+    /// coordinates.
+    pub fn shade(&self, albedo: Vec3, n: Vec3, v: Vec3, metallic: bool) -> Vec3 {
+        let n = if n.dot(&v) < 0.0 { -n } else { n };
+        let r = n * (2.0 * n.dot(&v)) - v;
+        let diffuse = self.irradiance.sample(n);
+        let spec = self.env.sample(r);
+        let f0 = if metallic { albedo } else { Vec3::repeat(DIELECTRIC_F0) };
+        let f = f0 + (Vec3::repeat(1.0) - f0) * (1.0 - n.dot(&v).max(0.0)).powi(5);
+        if metallic {
+            spec.component_mul(&f)
+        } else {
+            albedo.component_mul(&diffuse).component_mul(&(Vec3::repeat(1.0) - f)) +
+                spec.component_mul(&f)
+        }
+    }
+
+    /// Copies the maps into GPU textures
+    pub fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> GpuEnv {
+        let texture = |map: &EnvMap| device
+            .create_texture_with_data(queue, &texture_desc(map.width, map.height),
+                                      &map.to_f16())
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        GpuEnv {
+            env: texture(&self.env),
+            irradiance: texture(&self.irradiance),
+            sampler: sampler(device),
+        }
+    }
+}
+
+fn texture_desc(width: usize, height: usize) -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some("environment"),
+        size: wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    }
+}
+
+fn sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("environment"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// An environment's maps on the GPU, which are bound by `model.wgsl`
+pub struct GpuEnv {
+    pub env: wgpu::TextureView,
+    pub irradiance: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl GpuEnv {
+    /// Builds tiny stand-in textures, to fill the bindings when lighting
+    /// with the headlight (in which case they're never sampled)
+    pub fn placeholder(device: &wgpu::Device) -> Self {
+        let texture = || device.create_texture(&texture_desc(1, 1))
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        Self { env: texture(), irradiance: texture(), sampler: sampler(device) }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an HDR file with the given resolution and pixel data
+    fn hdr(width: usize, height: usize, data: &[u8]) -> Vec<u8> {
+        let mut out = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+                              height, width).into_bytes();
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn flat_scanlines() {
+        // An exponent of 129 scales each channel by 2^-7, and an exponent
+        // of zero is black
+        let data = [128, 64, 0, 129,  0, 0, 128, 129,  255, 255, 255, 0,
+                    16, 32, 64, 136,  0, 0, 0, 0,  128, 128, 128, 128];
+        let map = EnvMap::load_hdr(&hdr(3, 2, &data)).unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.pixels, vec![
+            Vec3::new(1.0, 0.5, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::zeros(),
+            Vec3::new(16.0, 32.0, 64.0), Vec3::zeros(), Vec3::new(0.5, 0.5, 0.5)]);
+    }
+
+    #[test]
+    fn rle_scanline() {
+        let data = [2, 2, 0, 8,
+                    128 + 8, 128,                        // red: one run
+                    8, 0, 16, 32, 48, 64, 80, 96, 112,   // green: literals
+                    128 + 4, 0, 128 + 4, 128,            // blue: two runs
+                    128 + 8, 129];                       // exponent
+        let map = EnvMap::load_hdr(&hdr(8, 1, &data)).unwrap();
+        assert_eq!((map.width, map.height), (8, 1));
+        for (i, p) in map.pixels.iter().enumerate() {
+            let blue = if i < 4 { 0.0 } else { 1.0 };
+            assert_eq!(*p, Vec3::new(1.0, i as f32 / 8.0, blue));
+        }
+    }
+
+    #[test]
+    fn bad_files() {
+        assert!(EnvMap::load_hdr(b"P6\n3 2\n255\n").is_err());
+        assert!(EnvMap::load_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0")
+            .is_err());
+        assert!(EnvMap::load_hdr(&hdr(0, 1, &[])).is_err());
+        // Truncated header and pixels
+        assert!(EnvMap::load_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe").is_err());
+        assert!(EnvMap::load_hdr(&hdr(3, 2, &[0; 20])).is_err());
+        // A run which goes past the end of the scanline
+        assert!(EnvMap::load_hdr(&hdr(8, 1, &[2, 2, 0, 8, 128 + 9, 0])).is_err());
+        // Absurd resolutions are rejected without allocating them
+        assert!(EnvMap::load_hdr(&hdr(1 << 30, 1 << 30, &[0; 64])).is_err());
+        assert!(EnvMap::load_hdr(&hdr(usize::MAX, 2, &[0; 64])).is_err());
+    }
+
+    #[test]
+    fn f16_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        // Rounds towards zero
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 4096.0), 0x3c00);
+        // The smallest normal survives; subnormals become zero
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_f16(2f32.powi(-15)), 0);
+        assert_eq!(f32_to_f16(-1e-7), 0x8000);
+        // Overflow saturates at the largest finite value
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e9), 0x7bff);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfbff);
+        assert_eq!(f32_to_f16(f32::NAN), 0x7e00);
+    }
+}
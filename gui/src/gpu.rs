@@ -0,0 +1,52 @@
+/// Parses a backend name from the command line
+pub fn parse_backend(s: &str) -> Result<wgpu::BackendBit, String> {
+    match s.to_lowercase().as_str() {
+        "vulkan" => Ok(wgpu::BackendBit::VULKAN),
+        "metal" => Ok(wgpu::BackendBit::METAL),
+        "dx12" => Ok(wgpu::BackendBit::DX12),
+        "dx11" => Ok(wgpu::BackendBit::DX11),
+        "gl" => Ok(wgpu::BackendBit::GL),
+        "all" => Ok(wgpu::BackendBit::all()),
+        _ => Err(format!("Unknown backend '{}' (expected vulkan, metal, \
+                          dx12, dx11, gl, or all)", s)),
+    }
+}
+
+/// Ranks device types, with lower values being preferred
+fn rank(t: wgpu::DeviceType, low_power: bool) -> usize {
+    match t {
+        wgpu::DeviceType::DiscreteGpu => if low_power { 1 } else { 0 },
+        wgpu::DeviceType::IntegratedGpu => if low_power { 0 } else { 1 },
+        wgpu::DeviceType::VirtualGpu => 2,
+        wgpu::DeviceType::Other => 3,
+        wgpu::DeviceType::Cpu => 4,
+    }
+}
+
+/// Picks the best adapter on the given backends, preferring discrete GPUs,
+/// then integrated GPUs, then anything else (e.g. software rasterizers);
+/// `low_power` swaps the order of discrete and integrated GPUs.
+/// If a surface is provided, only adapters which can render to it are
+/// considered.
+pub fn pick_adapter(instance: &wgpu::Instance, backends: wgpu::BackendBit,
+                    surface: Option<&wgpu::Surface>, low_power: bool)
+    -> Option<wgpu::Adapter>
+{
+    instance.enumerate_adapters(backends)
+        .filter(|a| surface.map(|s| a.get_swap_chain_preferred_format(s).is_some())
+                           .unwrap_or(true))
+        .min_by_key(|a| rank(a.get_info().device_type, low_power))
+}
+
+/// Returns a short human-readable description of an adapter
+pub fn describe(adapter: &wgpu::Adapter) -> String {
+    let info = adapter.get_info();
+    let kind = match info.device_type {
+        wgpu::DeviceType::DiscreteGpu => "discrete GPU",
+        wgpu::DeviceType::IntegratedGpu => "integrated GPU",
+        wgpu::DeviceType::VirtualGpu => "virtual GPU",
+        wgpu::DeviceType::Cpu => "software",
+        wgpu::DeviceType::Other => "unknown device",
+    };
+    format!("{} ({:?}, {})", info.name, info.backend, kind)
+}
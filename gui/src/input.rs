@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+/// Something the user can do from the keyboard
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    /// Leaves the active tool, or clears the selection if there isn't one
+    Cancel,
+    Fullscreen,
+    CycleColorMode,
+    CycleTheme,
+    /// Steps the selected part through a palette of colors
+    CyclePartColor,
+    /// Hides the selected part
+    HidePart,
+    ShowAllParts,
+    ToggleMeasure,
+    ToggleProbe,
+    /// Enters annotation mode, where clicking a point starts a note
+    ToggleAnnotate,
+    ToggleNormals,
+    /// Switches between normal shading and zebra-stripe reflections
+    ToggleZebra,
+    /// Shows or hides the STEP header metadata for each file
+    ToggleInfo,
+    /// Lists pairs of intersecting solids in the active viewport
+    FindClashes,
+    /// Opens a type-to-filter search over part names, which zooms to and
+    /// highlights the chosen part
+    FindPart,
+    ExportSelection,
+    ExportSelectionObj,
+    CopySelection,
+    CopyView,
+    SaveSession,
+    /// Writes notes, measurements, and the current view to `report.html`
+    ExportReport,
+    /// Shows or hides the list of key bindings
+    ToggleHelp,
+    /// Switches between orbiting and first-person (fly-through) cameras
+    ToggleFly,
+    /// Enters or leaves the clip box tool, adding a box if there isn't one
+    ToggleClip,
+    /// Removes the clip box, showing the whole model again
+    ClearClip,
+    /// Turns hatching of clipped cross-sections on or off
+    ToggleHatch,
+    /// Path-traces the active viewport to `render.png` in the background
+    Render,
+    /// Steps through the headlight and built-in lighting environments
+    CycleEnvironment,
+    /// Switches between plain and metallic shading under an environment
+    ToggleMetallic,
+    /// Shows or hides the curves of the file's 2D annotations (e.g. PMI)
+    ToggleDraughting,
+}
+
+impl Action {
+    /// Returns a short description, for the key binding overlay
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Cancel => "Leave tool / clear selection",
+            Action::Fullscreen => "Toggle fullscreen",
+            Action::CycleColorMode => "Cycle color mode",
+            Action::CycleTheme => "Cycle theme",
+            Action::CyclePartColor => "Cycle selected part's color",
+            Action::HidePart => "Hide selected part",
+            Action::ShowAllParts => "Show hidden parts",
+            Action::ToggleMeasure => "Measure tool",
+            Action::ToggleProbe => "Probe tool",
+            Action::ToggleAnnotate => "Annotate tool",
+            Action::ToggleNormals => "Show normals",
+            Action::ToggleZebra => "Zebra stripes",
+            Action::ToggleInfo => "Show file info",
+            Action::FindClashes => "Find clashes",
+            Action::FindPart => "Find part by name",
+            Action::ExportSelection => "Export selection (STL)",
+            Action::ExportSelectionObj => "Export selection (OBJ)",
+            Action::CopySelection => "Copy selected face ids",
+            Action::CopyView => "Copy view as image",
+            Action::SaveSession => "Save session",
+            Action::ExportReport => "Export review report",
+            Action::ToggleHelp => "Show key bindings",
+            Action::ToggleFly => "Fly-through camera",
+            Action::ToggleClip => "Clip box tool",
+            Action::ClearClip => "Remove clip box",
+            Action::ToggleHatch => "Hatch cross-sections",
+            Action::Render => "Path-trace view to PNG",
+            Action::CycleEnvironment => "Cycle lighting environment",
+            Action::ToggleMetallic => "Metallic shading",
+            Action::ToggleDraughting => "Show 2D annotations (PMI)",
+        }
+    }
+}
+
+/// A key plus the exact set of modifiers which must be held with it
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Chord {
+    key: VirtualKeyCode,
+    mods: ModifiersState,
+}
+
+impl Chord {
+    fn new(key: VirtualKeyCode, mods: ModifiersState) -> Self {
+        Self { key, mods }
+    }
+
+    /// Parses a chord like `ctrl+shift+E`.  Modifier names are `ctrl`,
+    /// `shift`, `alt`, and `cmd` (or `logo` / `super`); key names are
+    /// letters, digits, `F1`-`F12`, and a handful of named keys.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut mods = ModifiersState::empty();
+        let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let key = parts.pop().unwrap_or("");
+        for m in parts {
+            mods |= match m.to_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "shift" => ModifiersState::SHIFT,
+                "alt" | "option" => ModifiersState::ALT,
+                "cmd" | "logo" | "super" => ModifiersState::LOGO,
+                _ => return Err(format!("Unknown modifier '{}' in '{}'", m, s)),
+            };
+        }
+        parse_key(key)
+            .map(|key| Self { key, mods })
+            .ok_or_else(|| format!("Unknown key '{}' in '{}'", key, s))
+    }
+}
+
+impl std::fmt::Display for Chord {
+    /// Formats the chord like `Ctrl+Shift+E`, which [`Chord::parse`] accepts
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (m, name) in [(ModifiersState::CTRL, "Ctrl"), (ModifiersState::SHIFT, "Shift"),
+                          (ModifiersState::ALT, "Alt"), (ModifiersState::LOGO, "Cmd")] {
+            if self.mods.contains(m) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        use VirtualKeyCode::*;
+        match self.key {
+            Back => write!(f, "Backspace"),
+            Return => write!(f, "Enter"),
+            Escape => write!(f, "Esc"),
+            Slash => write!(f, "/"),
+            k => {
+                let name = format!("{:?}", k);
+                write!(f, "{}", name.strip_prefix("Key").unwrap_or(&name))
+            },
+        }
+    }
+}
+
+fn parse_key(s: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M,
+        N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    const DIGITS: [VirtualKeyCode; 10] = [
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const FN: [VirtualKeyCode; 12] = [
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+    let lower = s.to_lowercase();
+    let mut chars = lower.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'a'..='z'), None) => return Some(LETTERS[c as usize - 'a' as usize]),
+        (Some(c @ '0'..='9'), None) => return Some(DIGITS[c as usize - '0' as usize]),
+        _ => (),
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FN.get(n.checked_sub(1)?).copied();
+    }
+    Some(match lower.as_str() {
+        "esc" | "escape" => Escape,
+        "space" => Space,
+        "tab" => Tab,
+        "enter" | "return" => Return,
+        "backspace" => Back,
+        "delete" => Delete,
+        "/" | "slash" => Slash,
+        _ => return None,
+    })
+}
+
+/// Maps key chords to actions
+pub struct InputMap(Vec<(Chord, Action)>);
+
+impl Default for InputMap {
+    /// Builds the default bindings, following each platform's conventions
+    /// for quitting and fullscreen
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+        let none = ModifiersState::empty();
+        let ctrl = ModifiersState::CTRL;
+        let shift = ModifiersState::SHIFT;
+        let mut map = vec![
+            (Chord::new(Escape, none), Action::Cancel),
+            (Chord::new(C, none), Action::CycleColorMode),
+            (Chord::new(T, none), Action::CycleTheme),
+            (Chord::new(C, shift), Action::CyclePartColor),
+            (Chord::new(V, none), Action::HidePart),
+            (Chord::new(V, shift), Action::ShowAllParts),
+            (Chord::new(M, none), Action::ToggleMeasure),
+            (Chord::new(P, none), Action::ToggleProbe),
+            (Chord::new(A, none), Action::ToggleAnnotate),
+            (Chord::new(N, none), Action::ToggleNormals),
+            (Chord::new(Z, none), Action::ToggleZebra),
+            (Chord::new(I, none), Action::ToggleInfo),
+            (Chord::new(K, none), Action::FindClashes),
+            (Chord::new(F, ctrl), Action::FindPart),
+            (Chord::new(E, ctrl), Action::ExportSelection),
+            (Chord::new(E, ctrl | shift), Action::ExportSelectionObj),
+            (Chord::new(C, ctrl), Action::CopySelection),
+            (Chord::new(C, ctrl | shift), Action::CopyView),
+            (Chord::new(S, ctrl), Action::SaveSession),
+            (Chord::new(R, ctrl), Action::ExportReport),
+            (Chord::new(R, ctrl | shift), Action::Render),
+            (Chord::new(F, none), Action::ToggleFly),
+            (Chord::new(X, none), Action::ToggleClip),
+            (Chord::new(X, shift), Action::ClearClip),
+            (Chord::new(H, none), Action::ToggleHatch),
+            (Chord::new(L, none), Action::CycleEnvironment),
+            (Chord::new(L, shift), Action::ToggleMetallic),
+            (Chord::new(D, none), Action::ToggleDraughting),
+            (Chord::new(Slash, shift), Action::ToggleHelp),
+            (Chord::new(F1, none), Action::ToggleHelp),
+        ];
+        if cfg!(target_os = "macos") {
+            let cmd = ModifiersState::LOGO;
+            map.push((Chord::new(Q, cmd), Action::Quit));
+            map.push((Chord::new(F, cmd | ctrl), Action::Fullscreen));
+        } else {
+            map.push((Chord::new(Q, ctrl), Action::Quit));
+            map.push((Chord::new(F4, ModifiersState::ALT), Action::Quit));
+            map.push((Chord::new(F11, none), Action::Fullscreen));
+        }
+        Self(map)
+    }
+}
+
+impl InputMap {
+    /// Loads bindings from a JSON file, which maps action names to lists of
+    /// chords, e.g. `{"quit": ["ctrl+q", "ctrl+w"]}`.  Actions which aren't
+    /// mentioned in the file keep their default bindings, unless one of
+    /// their chords is bound to something else.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_json(&std::fs::read(path)?)
+    }
+
+    /// Implementation of [`InputMap::load`], given the file's contents
+    fn from_json(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let custom: HashMap<Action, Vec<String>> = serde_json::from_slice(data)?;
+        let mut bound = Vec::new();
+        for (action, chords) in custom.iter() {
+            for c in chords {
+                bound.push((Chord::parse(c)?, *action));
+            }
+        }
+        let mut map = Self::default();
+        map.0.retain(|(c, a)| !custom.contains_key(a)
+                              && !bound.iter().any(|(b, _)| b == c));
+        map.0.extend(bound);
+        Ok(map)
+    }
+
+    /// Lists every action with a binding, along with its chords, in the
+    /// order in which they were bound
+    pub fn bindings(&self) -> Vec<(Action, Vec<Chord>)> {
+        let mut out: Vec<(Action, Vec<Chord>)> = Vec::new();
+        for (c, a) in &self.0 {
+            match out.iter_mut().find(|(b, _)| b == a) {
+                Some((_, chords)) => chords.push(*c),
+                None => out.push((*a, vec![*c])),
+            }
+        }
+        out
+    }
+
+    /// Builds the text of the key binding overlay, with one action per line
+    pub fn help_text(&self) -> String {
+        let rows: Vec<(String, &str)> = self.bindings().into_iter()
+            .map(|(a, chords)| {
+                let keys = chords.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                (keys.join(", "), a.description())
+            })
+            .collect();
+        let width = rows.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+        rows.iter()
+            .map(|(k, d)| format!("{:width$}  {}", k, d, width = width))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Looks up the action bound to a key with the given modifiers
+    pub fn action(&self, key: VirtualKeyCode, mods: ModifiersState)
+        -> Option<Action>
+    {
+        let chord = Chord::new(key, mods);
+        self.0.iter().find(|(c, _)| *c == chord).map(|(_, a)| *a)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_chords() {
+        let c = Chord::parse("ctrl+shift+E").unwrap();
+        assert_eq!(c, Chord::new(VirtualKeyCode::E,
+                                 ModifiersState::CTRL | ModifiersState::SHIFT));
+        assert_eq!(Chord::parse(" Alt + f4 ").unwrap(),
+                   Chord::new(VirtualKeyCode::F4, ModifiersState::ALT));
+        assert_eq!(Chord::parse("slash").unwrap(),
+                   Chord::new(VirtualKeyCode::Slash, ModifiersState::empty()));
+        assert!(Chord::parse("hyper+a").is_err());
+        assert!(Chord::parse("ctrl+F13").is_err());
+        assert!(Chord::parse("ctrl+").is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["Ctrl+Shift+E", "Alt+F4", "Cmd+Q", "Esc", "Shift+/", "Enter",
+                  "Backspace", "Space", "Ctrl+7"] {
+            let c = Chord::parse(s).unwrap();
+            assert_eq!(c.to_string(), s);
+            assert_eq!(Chord::parse(&c.to_string()).unwrap(), c);
+        }
+    }
+
+    #[test]
+    fn load_overrides() {
+        let none = ModifiersState::empty();
+        let map = InputMap::from_json(br#"{"quit": ["c"], "toggle_fly": ["ctrl+g"]}"#)
+            .unwrap();
+        // A chord bound by the file replaces its default action...
+        assert_eq!(map.action(VirtualKeyCode::C, none), Some(Action::Quit));
+        // ...and rebound actions lose their default chords
+        assert_eq!(map.action(VirtualKeyCode::F, none), None);
+        assert_eq!(map.action(VirtualKeyCode::G, ModifiersState::CTRL),
+                   Some(Action::ToggleFly));
+        // Everything else keeps its defaults
+        assert_eq!(map.action(VirtualKeyCode::T, none), Some(Action::CycleTheme));
+        assert_eq!(map.action(VirtualKeyCode::C, ModifiersState::SHIFT),
+                   Some(Action::CyclePartColor));
+
+        assert!(InputMap::from_json(br#"{"quit": ["ctrl+nope"]}"#).is_err());
+        assert!(InputMap::from_json(br#"{"not_an_action": ["q"]}"#).is_err());
+    }
+}
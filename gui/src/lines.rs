@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra_glm as glm;
+use glm::{Vec3, Vec4, Mat4};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GPULineVertex {
+    pos: [f32; 4],
+    color: [f32; 4],
+}
+
+/// A line segment in model space, with a color at each end
+#[derive(Copy, Clone, Debug)]
+pub struct Segment {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub start_color: Vec3,
+    pub end_color: Vec3,
+}
+
+/// A set of line segments drawn in model space, which are depth-tested
+/// against the model (used for debug overlays)
+pub struct Lines {
+    vertex_buf: wgpu::Buffer,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertex_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Lines {
+    pub fn new(device: &wgpu::Device, swapchain_format: wgpu::TextureFormat,
+               segments: &[Segment]) -> Self {
+        let v = |p: Vec3, c: Vec3| GPULineVertex {
+            pos: [p.x, p.y, p.z, 1.0],
+            color: [c.x, c.y, c.z, 1.0],
+        };
+        let vertex_data: Vec<GPULineVertex> = segments.iter()
+            .flat_map(|s| vec![v(s.start, s.start_color), v(s.end, s.end_color)])
+            .collect();
+
+        let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Line vertex buffer"),
+            contents: bytemuck::cast_slice(&vertex_data),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Line uniform buffer"),
+            size: std::mem::size_of::<Mat4>() as wgpu::BufferAddress * 2,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Mat4>() as u64 * 2),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GPULineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                // Positions
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                // Colors
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<Vec4>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+            ],
+        };
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+            ],
+            label: None,
+        });
+
+        // Load the shaders from disk, either at runtime or compile-time
+        #[cfg(feature = "bundle-shaders")]
+        let lines_src = Cow::Borrowed(include_str!("lines.wgsl"));
+
+        #[cfg(not(feature = "bundle-shaders"))]
+        let lines_src = Cow::Owned(
+            String::from_utf8(
+                std::fs::read("gui/src/lines.wgsl")
+                    .expect("Could not read shader"))
+                    .expect("Shader is invalid UTF-8"));
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(lines_src),
+            flags: wgpu::ShaderFlags::all(),
+        });
+
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[vertex_buf_layout],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[swapchain_format.into()],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+        });
+
+        Lines {
+            render_pipeline,
+            vertex_buf,
+            uniform_buf,
+            bind_group,
+            vertex_count: vertex_data.len() as u32,
+        }
+    }
+
+    pub fn draw(&self, camera: &Camera,
+                queue: &wgpu::Queue,
+                view: &wgpu::TextureView,
+                depth_view: &wgpu::TextureView,
+                rect: (f32, f32, f32, f32),
+                encoder: &mut wgpu::CommandEncoder)
+    {
+        let view_mat = camera.view_matrix();
+        let model_mat = camera.model_matrix();
+        queue.write_buffer(&self.uniform_buf, 0,
+            bytemuck::cast_slice(view_mat.as_slice()));
+        queue.write_buffer(&self.uniform_buf,
+            std::mem::size_of::<Mat4>() as wgpu::BufferAddress,
+            bytemuck::cast_slice(model_mat.as_slice()));
+
+        let mut rpass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view: depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+            });
+        rpass.set_viewport(rect.0, rect.1, rect.2, rect.3, 0.0, 1.0);
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..self.vertex_count, 0..1);
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use step::{cancel::CancelToken, header::Header, step_file::{ParseMode, StepFile}};
+use triangulate::{
+    external::triangulate_file,
+    mesh::{Mesh, UpAxis},
+    stats::Stats,
+    triangulate::{triangulate_out_of_core, TessellationParams},
+};
+
+/// Settings which apply to every model as it's loaded
+#[derive(Copy, Clone, Debug)]
+pub struct LoadOptions {
+    /// Angle (in radians) below which normals are smoothed across face
+    /// boundaries, if any
+    pub crease_angle: Option<f64>,
+    /// Models are rotated from STEP's Z-up convention into this one as
+    /// they're loaded
+    pub up: UpAxis,
+    /// If set, solids are triangulated one at a time, and moved to
+    /// temporary files once more than this many bytes of mesh data are in
+    /// memory.  This lowers peak memory use while loading, but externally
+    /// referenced files aren't loaded.
+    pub max_memory: Option<usize>,
+    /// Smallest number of segments in a full circle, if not the
+    /// triangulator's default
+    pub min_segments_per_turn: Option<usize>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            crease_angle: None, up: UpAxis::Z, max_memory: None,
+            min_segments_per_turn: None,
+        }
+    }
+}
+
+/// Parses and triangulates a single file out-of-core, returning `None` if
+/// the token is cancelled
+fn load_out_of_core(path: &Path, params: TessellationParams, max_bytes: usize,
+                    cancel: &CancelToken)
+    -> std::io::Result<Option<(Mesh, Stats)>>
+{
+    let start = Instant::now();
+    let flat = StepFile::preprocess(&std::fs::read(path)?, ParseMode::Lenient);
+    let step = match StepFile::parse_with(&flat, ParseMode::Lenient, cancel)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+    {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let parsed = Instant::now();
+    let out = triangulate_out_of_core(&step, params, &Default::default(),
+                                      max_bytes, cancel)?;
+    Ok(out.map(|(spilled, mut stats)| {
+        stats.parse_time = parsed - start;
+        stats.triangulate_time = parsed.elapsed();
+        (spilled.to_mesh(), stats)
+    }))
+}
+
+/// A STEP file which is being parsed and triangulated on a background
+/// thread, which can be abandoned partway through
+pub struct Loader {
+    handle: Option<JoinHandle<Option<(Mesh, Header, Stats)>>>,
+    cancel: CancelToken,
+}
+
+impl Loader {
+    /// Starts loading a file
+    pub fn spawn(path: PathBuf, options: LoadOptions) -> Self {
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let handle = std::thread::spawn(move || {
+            // This goes to stderr, since stdout may be carrying raw frames
+            eprintln!("Loading mesh!");
+            let header = Header::read(&path).expect("Could not open file");
+            // Faces which fail to triangulate are drawn as placeholders, so
+            // that they show up as highlighted patches instead of holes
+            let params = TessellationParams {
+                placeholders: true,
+                annotations: true,
+                min_segments_per_turn: options.min_segments_per_turn,
+                ..Default::default()
+            };
+            let loaded = match options.max_memory {
+                Some(max) => load_out_of_core(&path, params, max, &token),
+                None => triangulate_file(&path, params, &Default::default(),
+                                         ParseMode::Lenient, &token),
+            };
+            loaded.expect("Could not open file")
+                .map(|(mut mesh, stats)| {
+                    if let Some(crease) = options.crease_angle {
+                        mesh.smooth_normals(crease);
+                    }
+                    if options.up != UpAxis::Z {
+                        mesh.transform(&options.up.from_z_up());
+                    }
+                    (mesh, header, stats)
+                })
+        });
+        Self { handle: Some(handle), cancel }
+    }
+
+    /// Checks whether the thread is done, so that [`Loader::join`] won't block
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().map(|h| h.is_finished()).unwrap_or(true)
+    }
+
+    /// Waits for the mesh, file header, and triangulation statistics, which
+    /// are `None` if the load was cancelled
+    pub fn join(mut self) -> Option<(Mesh, Header, Stats)> {
+        self.handle.take()
+            .and_then(|h| h.join().expect("Failed to load mesh"))
+    }
+
+    /// Aborts the load and waits for the thread to stop, which happens
+    /// between entities (while parsing) or faces (while triangulating)
+    pub fn cancel(mut self) {
+        self.cancel.cancel();
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
@@ -1,71 +1,154 @@
 use std::time::SystemTime;
+use nalgebra_glm as glm;
+use triangulate::mesh::UpAxis;
 use winit::{
-    event::{Event},
+    dpi::PhysicalSize,
+    event::{Event, StartCause},
     event_loop::{ControlFlow, EventLoop},
-    window::Window,
+    window::{Fullscreen, Window},
 };
 
+pub(crate) mod annotate;
 pub(crate) mod app;
 pub(crate) mod backdrop;
 pub(crate) mod camera;
+pub(crate) mod clip;
+pub(crate) mod clipboard;
+pub(crate) mod environment;
+pub(crate) mod font;
+pub(crate) mod gpu;
+pub(crate) mod input;
+pub(crate) mod lines;
+pub(crate) mod loader;
+pub(crate) mod measure;
 pub(crate) mod model;
+pub(crate) mod orient;
+pub(crate) mod overlay;
+pub(crate) mod pacing;
+pub(crate) mod raster;
+pub(crate) mod report;
+pub(crate) mod screenshot;
+pub(crate) mod search;
+pub(crate) mod session;
+pub(crate) mod settings;
+pub(crate) mod theme;
+pub(crate) mod viewport;
+pub(crate) mod vis;
 
-use crate::app::App;
-use triangulate::mesh::Mesh;
+use crate::{
+    app::{App, Options}, environment::EnvironmentKind, input::InputMap,
+    loader::{LoadOptions, Loader}, session::Session,
+    settings::{Settings, WindowState}, viewport::ViewOptions,
+};
 
-async fn run(start: SystemTime, event_loop: EventLoop<()>, window: Window,
-             loader: std::thread::JoinHandle<Mesh>)
+fn run(start: SystemTime, event_loop: EventLoop<()>, window: Window,
+       loaders: Vec<Loader>, session: Session,
+       backends: wgpu::BackendBit, options: Options)
 {
     let size = window.inner_size();
     let (surface, adapter) = {
-        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let instance = wgpu::Instance::new(backends);
         let surface = unsafe { instance.create_surface(&window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                // Request an adapter which can render to our surface
-                compatible_surface: Some(&surface),
-            })
-            .await
+        // Pick an adapter which can render to our surface
+        let adapter = gpu::pick_adapter(&instance, backends, Some(&surface),
+                                        options.low_power)
             .expect("Failed to find an appropriate adapter");
         (surface, adapter)
     };
+    let name = gpu::describe(&adapter);
+    println!("Using {}", name);
+    window.set_title(&format!("Foxtrot — {}", name));
 
-    // Create the logical device and command queue
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-            },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
+    let mut pacer = pacing::Pacer::new(options.low_power);
+    let mut app = App::new(start, size, adapter, surface, loaders, session,
+                           options);
 
-    let mut app = App::new(start, size, adapter, surface, device, loader);
+    // Window state from before entering fullscreen, which is what we want
+    // to restore when leaving it (whether now, or in a later run)
+    let mut windowed: Option<WindowState> = None;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+        if *control_flow == ControlFlow::Exit && event != Event::LoopDestroyed {
+            return;
+        }
         use app::Reply;
         match event {
-            Event::WindowEvent { event, .. } => match app.window_event(event) {
-                Reply::Continue => (),
-                Reply::Quit => *control_flow = ControlFlow::Exit,
-                Reply::Redraw => if app.redraw(&queue) {
-                    window.request_redraw();
-                },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. })
+                if pacer.wake() => window.request_redraw(),
+            Event::WindowEvent { event, .. } => {
+                pacer.input();
+                match app.window_event(event) {
+                    Reply::Continue => (),
+                    Reply::Quit => *control_flow = ControlFlow::Exit,
+                    Reply::Redraw => window.request_redraw(),
+                    Reply::Fullscreen => if window.fullscreen().is_some() {
+                        window.set_fullscreen(None);
+                    } else {
+                        windowed = Some(WindowState::from_window(&window));
+                        window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+                    },
+                }
             },
-            Event::RedrawRequested(_) => if app.redraw(&queue) {
-                window.request_redraw();
+            Event::RedrawRequested(_) => if pacer.ready() {
+                match app.redraw() {
+                    Reply::Continue | Reply::Fullscreen => (),
+                    Reply::Quit => *control_flow = ControlFlow::Exit,
+                    // Keep retrying until the models are loaded, but give
+                    // up on retries (e.g. while the screen is asleep) once
+                    // the user has walked away; input will wake us again.
+                    Reply::Redraw => if !app.is_loaded() || !pacer.is_idle() {
+                        window.request_redraw();
+                    },
+                }
             },
             Event::DeviceEvent { event, .. } => app.device_event(event),
+            // This is the last event in each pass through the loop
+            Event::RedrawEventsCleared => *control_flow = pacer.control_flow(),
+            Event::LoopDestroyed => {
+                // Stop any loads which are still running, rather than
+                // leaving their threads to be killed mid-flight
+                app.cancel_loads();
+                let mut state = WindowState::from_window(&window);
+                if let (true, Some(w)) = (state.fullscreen, &windowed) {
+                    state.size = w.size;
+                    state.position = w.position;
+                }
+                let mut settings = Settings::load();
+                settings.window = Some(state);
+                if let Err(e) = settings.save() {
+                    eprintln!("Could not save settings: {}", e);
+                }
+            },
             _ => (),
         }
     });
 }
 
+/// Parses a size string like `800x600`
+fn parse_size(s: &str) -> Result<PhysicalSize<u32>, String> {
+    let err = || format!("Invalid size '{}' (expected WIDTHxHEIGHT)", s);
+    let mut iter = s.split('x').map(|v| v.parse::<u32>());
+    match (iter.next(), iter.next(), iter.next()) {
+        (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 =>
+            Ok(PhysicalSize::new(w, h)),
+        _ => Err(err()),
+    }
+}
+
+/// Parses a direction given as `X,Y,Z`
+fn parse_direction(s: &str) -> Result<glm::DVec3, String> {
+    let err = || format!("Invalid direction '{}' (expected X,Y,Z)", s);
+    let v: Vec<f64> = s.split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| err())?;
+    match v.as_slice() {
+        &[x, y, z] if x != 0.0 || y != 0.0 || z != 0.0 =>
+            Ok(glm::DVec3::new(x, y, z).normalize()),
+        _ => Err(err()),
+    }
+}
+
 fn main() {
     let start = SystemTime::now();
     env_logger::init();
@@ -74,29 +157,213 @@ fn main() {
         .author("Matt Keeter <matt@formlabs.com>")
         .about("Renders a STEP file")
         .arg(clap::Arg::with_name("input")
+            .help("STEP file to render; pass two files to compare them \
+                   side-by-side, or a .foxtrot file to restore a session")
             .takes_value(true)
+            .min_values(1)
+            .max_values(2)
             .required(true))
+        .arg(clap::Arg::with_name("sync")
+            .short("s")
+            .long("sync")
+            .help("Synchronize cameras when comparing two files"))
+        .arg(clap::Arg::with_name("screenshot")
+            .short("o")
+            .long("screenshot")
+            .help("Render to a PNG file without opening a window")
+            .takes_value(true))
+        .arg(clap::Arg::with_name("turntable")
+            .long("turntable")
+            .help("Render this many frames of a full rotation, saved as \
+                   numbered PNGs (or raw RGBA frames, if the screenshot \
+                   target is '-')")
+            .takes_value(true)
+            .requires("screenshot")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(()),
+                _ => Err(format!("Invalid frame count '{}'", s)),
+            }))
+        .arg(clap::Arg::with_name("size")
+            .long("size")
+            .help("Screenshot size, as WIDTHxHEIGHT")
+            .takes_value(true)
+            .default_value("800x600")
+            .validator(|s| parse_size(&s).map(|_| ())))
+        .arg(clap::Arg::with_name("backend")
+            .long("backend")
+            .help("Graphics API to use: vulkan, metal, dx12, dx11, gl, or all")
+            .takes_value(true)
+            .default_value("all")
+            .validator(|s| gpu::parse_backend(&s).map(|_| ())))
+        .arg(clap::Arg::with_name("software")
+            .long("software")
+            .help("Draw screenshots with the CPU rasterizer, even if a GPU \
+                   is available"))
+        .arg(clap::Arg::with_name("env")
+            .long("env")
+            .help("Light models with an environment: studio, sky, sunset, \
+                   or a Radiance .hdr file (instead of the headlight)")
+            .takes_value(true))
+        .arg(clap::Arg::with_name("metallic")
+            .long("metallic")
+            .help("Shade models as metal, which reflects the environment"))
+        .arg(clap::Arg::with_name("density")
+            .long("density")
+            .help("Density in kg/m³ for estimating the mass of parts which \
+                   don't have a material density in the file")
+            .takes_value(true)
+            .validator(|s| match s.parse::<f64>() {
+                Ok(d) if d > 0.0 => Ok(()),
+                _ => Err(format!("Invalid density '{}'", s)),
+            }))
+        .arg(clap::Arg::with_name("low-power")
+            .long("low-power")
+            .help("Cap the frame rate and prefer an integrated GPU, to save \
+                   battery"))
+        .arg(clap::Arg::with_name("keymap")
+            .long("keymap")
+            .help("JSON file with custom key bindings")
+            .takes_value(true))
+        .arg(clap::Arg::with_name("quantize")
+            .long("quantize")
+            .help("Store vertices in compact 16-bit formats on the GPU, \
+                   to reduce memory use for very large models"))
+        .arg(clap::Arg::with_name("crease")
+            .long("crease")
+            .help("Smooth shading across boundaries between faces which meet \
+                   at less than this angle, in degrees")
+            .takes_value(true)
+            .validator(|s| match s.parse::<f64>() {
+                Ok(a) if a >= 0.0 => Ok(()),
+                _ => Err(format!("Invalid angle '{}'", s)),
+            }))
+        .arg(clap::Arg::with_name("min-segments")
+            .long("min-segments")
+            .help("Smallest number of segments in a full circle, whatever \
+                   the tolerance, so that small holes stay round (defaults \
+                   to 16)")
+            .takes_value(true)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(_) => Ok(()),
+                _ => Err(format!("Invalid count '{}'", s)),
+            }))
+        .arg(clap::Arg::with_name("max-memory")
+            .long("max-memory")
+            .help("Triangulate one solid at a time, keeping at most this many \
+                   megabytes of mesh data in memory while loading and moving \
+                   the rest to temporary files (without loading externally \
+                   referenced files)")
+            .takes_value(true)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(_) => Ok(()),
+                _ => Err(format!("Invalid size '{}'", s)),
+            }))
+        .arg(clap::Arg::with_name("up")
+            .long("up")
+            .help("Axis which points upwards in the model; models are Z-up \
+                   in STEP, and are rotated to match if this is y")
+            .takes_value(true)
+            .possible_values(&["y", "z"])
+            .default_value("z"))
+        .arg(clap::Arg::with_name("pull")
+            .long("pull")
+            .help("Pull direction for the draft angle color mode, as X,Y,Z")
+            .takes_value(true)
+            .default_value("0,0,1")
+            .validator(|s| parse_direction(&s).map(|_| ())))
+        .arg(clap::Arg::with_name("draft")
+            .long("draft")
+            .help("Minimum draft angle, in degrees; walls with less draft \
+                   are highlighted in the draft angle color mode")
+            .takes_value(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<f64>() {
+                Ok(a) if (0.0..90.0).contains(&a) => Ok(()),
+                _ => Err(format!("Invalid angle '{}'", s)),
+            }))
         .get_matches();
-    let input = matches.value_of("input")
+    let backends = gpu::parse_backend(matches.value_of("backend").unwrap()).unwrap();
+    let load_options = LoadOptions {
+        crease_angle: matches.value_of("crease")
+            .map(|a| a.parse::<f64>().unwrap().to_radians())
+            .filter(|a| *a > 0.0),
+        up: match matches.value_of("up") {
+            Some("y") => UpAxis::Y,
+            _ => UpAxis::Z,
+        },
+        max_memory: matches.value_of("max-memory")
+            .map(|m| m.parse::<usize>().unwrap() * 1024 * 1024),
+        min_segments_per_turn: matches.value_of("min-segments")
+            .map(|n| n.parse().unwrap()),
+    };
+    let view = ViewOptions {
+        draft: vis::Draft {
+            pull: parse_direction(matches.value_of("pull").unwrap()).unwrap(),
+            limit: matches.value_of("draft").unwrap().parse::<f64>().unwrap().to_radians(),
+        },
+        density: matches.value_of("density").map(|d| d.parse().unwrap()),
+        quantize: matches.is_present("quantize"),
+    };
+    let inputs: Vec<String> = matches.values_of("input")
         .expect("Could not get input file")
-        .to_owned();
+        .map(|s| s.to_owned())
+        .collect();
+    // A session file replaces the list of inputs with its own files
+    let mut session = match inputs.as_slice() {
+        [path] if session::is_session(path) => {
+            let mut s = Session::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not load session {}: {}", path, e);
+                std::process::exit(1);
+            });
+            s.sync_cameras |= matches.is_present("sync");
+            s
+        },
+        _ => Session {
+            files: inputs.iter().map(std::path::PathBuf::from).collect(),
+            sync_cameras: matches.is_present("sync"),
+            ..Session::default()
+        },
+    };
 
-    // Kick off the loader thread immediately, so that the STEP file is parsed
-    // and triangulated in the background while we wait for a GPU context
-    let loader = std::thread::spawn(|| {
-        println!("Loading mesh!");
-        use step::step_file::StepFile;
-        use triangulate::triangulate::triangulate;
+    if let Some(env) = matches.value_of("env") {
+        session.environment = EnvironmentKind::parse(env);
+    }
+    session.metallic |= matches.is_present("metallic");
 
-        let data = std::fs::read(input).expect("Could not open file");
-        let flat = StepFile::strip_flatten(&data);
-        let step = StepFile::parse(&flat);
-        let (mesh, _stats) = triangulate(&step);
-        mesh
-    });
+    let input_map = match matches.value_of("keymap") {
+        Some(path) => InputMap::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load key bindings from {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => InputMap::default(),
+    };
+
+    // Kick off the loader threads immediately, so that the STEP files are
+    // parsed and triangulated in the background while we wait for a GPU
+    // context
+    let loaders = session.files.clone().into_iter()
+        .map(|f| Loader::spawn(f, load_options))
+        .collect();
+
+    if let Some(out) = matches.value_of("screenshot") {
+        let size = parse_size(matches.value_of("size").unwrap()).unwrap();
+        let frames = matches.value_of("turntable").map(|s| s.parse().unwrap());
+        let backends = Some(backends).filter(|_| !matches.is_present("software"));
+        pollster::block_on(screenshot::run(loaders, view, out.to_owned(), size,
+                                           &session, frames, backends));
+        return;
+    }
 
     let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
-    window.set_title("Foxtrot");
-    pollster::block_on(run(start, event_loop, window, loader));
+    let builder = winit::window::WindowBuilder::new().with_title("Foxtrot");
+    let window = match Settings::load().window {
+        Some(state) => state.build(builder, &event_loop),
+        None => builder.build(&event_loop).expect("Could not build window"),
+    };
+    let options = Options {
+        low_power: matches.is_present("low-power"),
+        input_map,
+        view,
+    };
+    run(start, event_loop, window, loaders, session, backends, options);
 }
@@ -0,0 +1,215 @@
+use nalgebra_glm as glm;
+use glm::DVec3;
+use serde::{Deserialize, Serialize};
+
+use triangulate::mesh::{Mesh, SurfaceType};
+
+/// Normals within roughly this angle (in radians) are treated as parallel
+const PARALLEL_TOLERANCE: f64 = 1e-3;
+
+/// A plane through a picked point, which is exact for planar faces and a
+/// local approximation (using the picked triangle) for curved faces.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Plane {
+    pub point: DVec3,
+    pub normal: DVec3,
+}
+
+impl Plane {
+    /// Builds the plane of the face which contains the given triangle at
+    /// the picked point.  For planar faces, the normal is averaged over the
+    /// whole face to smooth out tessellation error.
+    pub fn from_pick(mesh: &Mesh, triangle: usize, point: DVec3) -> Option<Self> {
+        let face = mesh.face_of(triangle);
+        let range = match face {
+            Some(f) if f.surface == SurfaceType::Plane => f.triangles.clone(),
+            _ => triangle..(triangle + 1),
+        };
+        let mut normal = DVec3::zeros();
+        let mut shading = DVec3::zeros();
+        for t in &mesh.triangles[range] {
+            let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+                .map(|i| mesh.verts[i as usize]);
+            normal += (b.pos - a.pos).cross(&(c.pos - a.pos));
+            shading += a.norm + b.norm + c.norm;
+        }
+        // Winding order isn't guaranteed, so orient the normal to agree
+        // with the vertex normals (which point out of the solid)
+        if normal.dot(&shading) < 0.0 {
+            normal = -normal;
+        }
+        if normal.norm_squared() == 0.0 {
+            return None;
+        }
+        Some(Plane { point, normal: normal.normalize() })
+    }
+}
+
+/// The relationship between two picked planes
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Measurement {
+    pub a: Plane,
+    pub b: Plane,
+    /// Angle between the two normals, in degrees
+    pub angle: f64,
+    /// Distance between the planes, if they're parallel
+    pub distance: Option<f64>,
+}
+
+impl Measurement {
+    pub fn new(a: &Plane, b: &Plane) -> Self {
+        let angle = a.normal.dot(&b.normal).clamp(-1.0, 1.0).acos();
+        // The sine of the angle is small for both parallel and anti-parallel
+        // normals
+        let distance = if a.normal.cross(&b.normal).norm() < PARALLEL_TOLERANCE {
+            Some((b.point - a.point).dot(&a.normal).abs())
+        } else {
+            None
+        };
+        Measurement { a: *a, b: *b, angle: angle.to_degrees(), distance }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut out = format!("Angle: {:.3} deg", self.angle);
+        if let Some(d) = self.distance {
+            out += &format!("\nDistance: {:.4}", d);
+        }
+        out
+    }
+}
+
+/// State machine for the two-pick angle measurement tool
+#[derive(Default)]
+pub struct Measure {
+    first: Option<Plane>,
+    last: Option<Measurement>,
+}
+
+impl Measure {
+    /// Records a picked plane, returning a measurement once two planes have
+    /// been picked.
+    pub fn add(&mut self, p: Plane) -> Option<Measurement> {
+        match self.first.take() {
+            None => {
+                self.first = Some(p);
+                None
+            },
+            Some(a) => {
+                let m = Measurement::new(&a, &p);
+                self.last = Some(m);
+                Some(m)
+            },
+        }
+    }
+
+    /// Returns a description of the tool's state, for display
+    pub fn status(&self) -> String {
+        let prompt = if self.first.is_none() {
+            "Measure: pick first face"
+        } else {
+            "Measure: pick second face"
+        };
+        match &self.last {
+            Some(m) => format!("{}\n{}", prompt, m.describe()),
+            None => prompt.to_owned(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glm::{DMat4, DVec2, U32Vec3};
+    use triangulate::mesh::{Face, Triangle, Vertex};
+
+    fn vertex(x: f64, y: f64, z: f64, norm: DVec3) -> Vertex {
+        Vertex { pos: DVec3::new(x, y, z), norm, color: DVec3::zeros(), uv: DVec2::zeros() }
+    }
+
+    fn face(triangles: std::ops::Range<usize>, surface: SurfaceType) -> Face {
+        Face {
+            id: 0, triangles, surface, dimensions: None, geometry: None,
+            transform: DMat4::identity(), failed: false, grid: None,
+        }
+    }
+
+    fn plane(point: DVec3, normal: DVec3) -> Plane {
+        Plane { point, normal: normal.normalize() }
+    }
+
+    #[test]
+    fn pick_planes() {
+        let up = DVec3::z();
+        let out = DVec3::new(1.0, 0.0, 1.0).normalize();
+        let mesh = Mesh {
+            verts: vec![
+                // A unit square with a slightly bumpy corner, wound clockwise
+                // when seen from above (against its vertex normals)
+                vertex(0.0, 0.0, 0.0, up), vertex(1.0, 0.0, 0.0, up),
+                vertex(1.0, 1.0, 0.01, up), vertex(0.0, 1.0, 0.0, up),
+                // A triangle on a curved face, tilted by 45°
+                vertex(0.0, 0.0, 1.0, out), vertex(1.0, 0.0, 0.0, out),
+                vertex(0.0, 1.0, 1.0, out),
+                // A sliver with no area
+                vertex(0.0, 0.0, 0.0, up), vertex(1.0, 0.0, 0.0, up),
+                vertex(2.0, 0.0, 0.0, up),
+            ],
+            triangles: [[0, 2, 1], [0, 3, 2], [4, 5, 6], [7, 8, 9]].iter()
+                .map(|t| Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) })
+                .collect(),
+            faces: vec![face(0..2, SurfaceType::Plane), face(2..3, SurfaceType::Cylinder)],
+            ..Mesh::default()
+        };
+
+        // Both triangles of a planar face give the same normal, averaged
+        // over the face and flipped to agree with the vertex normals
+        let p = DVec3::new(0.5, 0.5, 0.0);
+        let a = Plane::from_pick(&mesh, 0, p).unwrap();
+        let b = Plane::from_pick(&mesh, 1, p).unwrap();
+        assert_eq!(a.point, p);
+        assert!(a.normal.z > 0.9999 && (a.normal.norm() - 1.0).abs() < 1e-12);
+        assert!((a.normal - b.normal).norm() < 1e-12);
+
+        // Curved faces only use the picked triangle
+        let c = Plane::from_pick(&mesh, 2, p).unwrap();
+        assert!((c.normal - out).norm() < 1e-12);
+
+        assert!(Plane::from_pick(&mesh, 3, p).is_none());
+    }
+
+    #[test]
+    fn measure_planes() {
+        let a = plane(DVec3::zeros(), DVec3::z());
+
+        let m = Measurement::new(&a, &plane(DVec3::new(1.0, 2.0, 3.0), DVec3::z()));
+        assert!(m.angle.abs() < 1e-6);
+        assert!((m.distance.unwrap() - 3.0).abs() < 1e-12);
+
+        // Facing planes are anti-parallel, and still have a distance
+        let m = Measurement::new(&a, &plane(DVec3::new(5.0, 0.0, -2.0), -DVec3::z()));
+        assert!((m.angle - 180.0).abs() < 1e-6);
+        assert!((m.distance.unwrap() - 2.0).abs() < 1e-12);
+        assert_eq!(m.describe(), "Angle: 180.000 deg\nDistance: 2.0000");
+
+        // Nearly parallel planes are still measured; others aren't
+        let m = Measurement::new(&a, &plane(DVec3::z() * 4.0, DVec3::new(1e-4, 0.0, 1.0)));
+        assert!((m.distance.unwrap() - 4.0).abs() < 1e-6);
+        let m = Measurement::new(&a, &plane(DVec3::z(), DVec3::new(1.0, 0.0, 1.0)));
+        assert!((m.angle - 45.0).abs() < 1e-9);
+        assert!(m.distance.is_none());
+        assert_eq!(m.describe(), "Angle: 45.000 deg");
+    }
+
+    #[test]
+    fn measure_tool() {
+        let mut tool = Measure::default();
+        assert_eq!(tool.status(), "Measure: pick first face");
+        assert!(tool.add(plane(DVec3::zeros(), DVec3::z())).is_none());
+        assert_eq!(tool.status(), "Measure: pick second face");
+        let m = tool.add(plane(DVec3::zeros(), DVec3::x())).unwrap();
+        assert!((m.angle - 90.0).abs() < 1e-9);
+        assert_eq!(tool.status(), "Measure: pick first face\nAngle: 90.000 deg");
+    }
+}
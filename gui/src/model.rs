@@ -1,13 +1,21 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 
 use bytemuck::{Pod, Zeroable};
 use nalgebra_glm as glm;
-use glm::{Vec4, Mat4};
+use glm::{DVec3, Vec2, Vec3, Vec4, Mat4};
 use wgpu::util::DeviceExt;
 
 use triangulate::mesh::{Vertex, Triangle};
 
-use crate::camera::Camera;
+/// A run of triangles which is drawn once per transform, so that repeated
+/// parts of an assembly share vertex data on the GPU
+pub struct Batch {
+    pub triangles: std::ops::Range<usize>,
+    pub transforms: Vec<Mat4>,
+}
+
+use crate::{camera::Camera, environment::GpuEnv};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
@@ -27,42 +35,161 @@ impl GPUVertex {
     }
 }
 
+/// A vertex with 16-bit positions (normalized to the model's bounding
+/// cube), octahedral-encoded 16-bit normals, and 8-bit colors
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, Eq, PartialEq, Hash)]
+struct GPUQuantizedVertex {
+    pos: [u16; 4],
+    norm: [i16; 2],
+    color: [u8; 4],
+}
+
+impl GPUQuantizedVertex {
+    /// Quantizes a vertex, given the corner and side length of a cube which
+    /// encloses every vertex in the model
+    fn from_vertex(v: &Vertex, origin: DVec3, scale: f64) -> Self {
+        let p = (v.pos - origin) / scale;
+        let q = |f: f64| (f.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        let n = oct_encode(glm::convert(v.norm));
+        let c = |f: f64| (f.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Self {
+            pos: [q(p.x), q(p.y), q(p.z), u16::MAX],
+            norm: [(n.x * 32767.0).round() as i16, (n.y * 32767.0).round() as i16],
+            color: [c(v.color.x), c(v.color.y), c(v.color.z), u8::MAX],
+        }
+    }
+}
+
+/// Maps a unit vector onto the octahedron, then unfolds it to the [-1, 1]
+/// square; `vs_quantized` in `model.wgsl` does the inverse.
+fn oct_encode(n: Vec3) -> Vec2 {
+    let l1 = n.x.abs() + n.y.abs() + n.z.abs();
+    if l1 == 0.0 {
+        return Vec2::zeros();
+    }
+    let p = Vec2::new(n.x, n.y) / l1;
+    if n.z >= 0.0 {
+        p
+    } else {
+        let sign = |f: f32| if f >= 0.0 { 1.0 } else { -1.0 };
+        Vec2::new((1.0 - p.y.abs()) * sign(p.x), (1.0 - p.x.abs()) * sign(p.y))
+    }
+}
+
+/// Size of the `Locals` uniform in `model.wgsl`: view and model matrices,
+/// followed by a vector of drawing options, the clip box's corners, the
+/// eye's position, and environment lighting options
+const UNIFORM_SIZE: wgpu::BufferAddress =
+    (std::mem::size_of::<Mat4>() * 2 + std::mem::size_of::<Vec4>() * 5) as wgpu::BufferAddress;
+
 pub struct Model {
     vertex_buf: wgpu::Buffer,
     index_buf: wgpu::Buffer,
+    instance_buf: wgpu::Buffer,
     uniform_buf: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-    index_count: u32,
+    index_format: wgpu::IndexFormat,
+    /// Index and instance ranges for each draw call
+    draws: Vec<(std::ops::Range<u32>, std::ops::Range<u32>)>,
     render_pipeline: wgpu::RenderPipeline,
+
+    /// Total size of the model's GPU buffers, in bytes
+    pub gpu_bytes: usize,
+
+    /// Draw reflection stripes instead of the usual shading
+    pub zebra: bool,
+    /// Corners of a box in model coordinates, outside of which nothing is
+    /// drawn
+    pub clip: Option<(Vec3, Vec3)>,
+    /// Draw diagonal hatching over the model, which is used for section caps
+    pub hatch: bool,
+    /// Light the model with the bound environment maps, rather than the
+    /// headlight
+    lit_by_env: bool,
+    /// Shade the model as metal, which only reflects its environment
+    pub metallic: bool,
 }
 
 impl Model {
+    /// Builds a model on the GPU, which is lit by `env` if it's given (or
+    /// by a headlight otherwise).  If `quantize` is true, vertices are
+    /// uploaded with quantized attributes, which use a third of the memory
+    /// of full-precision vertices.
     pub fn new(device: &wgpu::Device, swapchain_format: wgpu::TextureFormat,
-               verts: &[Vertex], tris: &[Triangle]) -> Self {
+               verts: &[Vertex], tris: &[Triangle], batches: &[Batch],
+               env: Option<&GpuEnv>, quantize: bool) -> Self {
+        // Quantized positions are relative to a cube around the model, which
+        // is undone by folding a scale and offset into each instance's
+        // transform.  Using a cube (rather than the bounding box) keeps the
+        // scale uniform, so normals aren't skewed.
+        let mut lo = DVec3::repeat(f64::INFINITY);
+        let mut hi = DVec3::repeat(-f64::INFINITY);
+        for v in verts {
+            lo = lo.inf(&v.pos);
+            hi = hi.sup(&v.pos);
+        }
+        let scale = (hi - lo).max();
+        let scale = if scale > 0.0 { scale } else { 1.0 };
+        let dequantize = if quantize && !verts.is_empty() {
+            glm::translation(&glm::convert::<_, Vec3>(lo)) *
+            glm::scaling(&Vec3::repeat(scale as f32))
+        } else {
+            Mat4::identity()
+        };
 
-        let vertex_data: Vec<GPUVertex> = verts.into_iter()
-            .map(GPUVertex::from_vertex)
-            .collect();
-        let index_data: Vec<u32> = tris.into_iter()
-            .flat_map(|t| t.verts.iter())
-            .copied()
-            .collect();
+        let (vertex_bytes, index_data, vertex_stride) = if quantize {
+            let (v, i) = Self::dedup_(verts, tris,
+                |v| GPUQuantizedVertex::from_vertex(v, lo, scale), |g| *g);
+            (bytemuck::cast_slice(&v).to_vec(), i,
+             std::mem::size_of::<GPUQuantizedVertex>())
+        } else {
+            let (v, i) = Self::dedup_(verts, tris,
+                GPUVertex::from_vertex, |g| bytemuck::cast::<_, [u32; 12]>(*g));
+            (bytemuck::cast_slice(&v).to_vec(), i,
+             std::mem::size_of::<GPUVertex>())
+        };
+        let vertex_count = vertex_bytes.len() / vertex_stride;
 
         let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex buffer"),
-            contents: bytemuck::cast_slice(&vertex_data),
+            contents: &vertex_bytes,
             usage: wgpu::BufferUsage::VERTEX,
         });
 
+        // Use 16-bit indices whenever they're large enough
+        let (index_bytes, index_format) = if vertex_count <= u16::MAX as usize + 1 {
+            let i: Vec<u16> = index_data.iter().map(|&i| i as u16).collect();
+            (bytemuck::cast_slice(&i).to_vec(), wgpu::IndexFormat::Uint16)
+        } else {
+            (bytemuck::cast_slice(&index_data).to_vec(), wgpu::IndexFormat::Uint32)
+        };
         let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index buffer"),
-            contents: bytemuck::cast_slice(&index_data),
+            contents: &index_bytes,
             usage: wgpu::BufferUsage::INDEX,
         });
 
+        let mut instance_data: Vec<f32> = Vec::new();
+        let mut draws = Vec::with_capacity(batches.len());
+        for b in batches {
+            let i = (instance_data.len() / 16) as u32;
+            for t in &b.transforms {
+                instance_data.extend((t * dequantize).as_slice());
+            }
+            draws.push(((b.triangles.start as u32 * 3)..(b.triangles.end as u32 * 3),
+                        i..(i + b.transforms.len() as u32)));
+        }
+        let instance_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
         let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Uniform Buffer"),
-            size: std::mem::size_of::<Mat4>() as wgpu::BufferAddress * 2,
+            size: UNIFORM_SIZE,
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
             mapped_at_creation: false,
         });
@@ -72,12 +199,41 @@ impl Model {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(
-                            std::mem::size_of::<Mat4>() as u64 * 2),
+                        min_binding_size: wgpu::BufferSize::new(UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+                // Environment and irradiance maps, then their sampler
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
                     },
                     count: None,
                 },
@@ -91,10 +247,11 @@ impl Model {
             push_constant_ranges: &[],
         });
 
-        let vertex_buf_layout = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<GPUVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
+        let attributes = if quantize {
+            wgpu::vertex_attr_array![
+                0 => Unorm16x4, 1 => Snorm16x2, 2 => Unorm8x4]
+        } else {
+            [
                 // Positions
                 wgpu::VertexAttribute {
                     format: wgpu::VertexFormat::Float32x4,
@@ -113,20 +270,24 @@ impl Model {
                     offset: 2*std::mem::size_of::<Vec4>() as wgpu::BufferAddress,
                     shader_location: 2,
                 },
-            ],
+            ]
+        };
+        let vertex_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: vertex_stride as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &attributes,
         };
 
-        // Create bind group
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buf.as_entire_binding(),
-                },
-            ],
-            label: None,
-        });
+        // Per-instance transforms, passed as four column vectors
+        let instance_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Mat4>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![
+                3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4],
+        };
+
+        let bind_group = Self::bind_group_(device, &bind_group_layout,
+                                           &uniform_buf, env);
 
         // Load the shaders from disk, either at runtime or compile-time
         #[cfg(feature = "bundle-shaders")]
@@ -151,8 +312,8 @@ impl Model {
                 layout: Some(&pipeline_layout),
                 vertex: wgpu::VertexState {
                     module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[vertex_buf_layout],
+                    entry_point: if quantize { "vs_quantized" } else { "vs_main" },
+                    buffers: &[vertex_buf_layout, instance_buf_layout],
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &shader,
@@ -174,16 +335,101 @@ impl Model {
             render_pipeline,
             index_buf,
             vertex_buf,
+            instance_buf,
             uniform_buf,
+            bind_group_layout,
             bind_group,
-            index_count: tris.len() as u32 * 3,
+            index_format,
+            draws,
+            gpu_bytes: vertex_bytes.len() + index_bytes.len() +
+                       std::mem::size_of_val(instance_data.as_slice()) +
+                       UNIFORM_SIZE as usize,
+            zebra: false,
+            clip: None,
+            hatch: false,
+            lit_by_env: env.is_some(),
+            metallic: false,
         }
     }
 
+    /// Binds the uniform buffer and environment maps.  Without an
+    /// environment, stand-in maps fill the bindings.
+    fn bind_group_(device: &wgpu::Device, layout: &wgpu::BindGroupLayout,
+                   uniform_buf: &wgpu::Buffer, env: Option<&GpuEnv>)
+        -> wgpu::BindGroup
+    {
+        let placeholder;
+        let env = match env {
+            Some(e) => e,
+            None => {
+                placeholder = GpuEnv::placeholder(device);
+                &placeholder
+            },
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&env.env),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&env.irradiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&env.sampler),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    /// Switches to lighting from a different environment, or back to the
+    /// headlight if `env` is `None`
+    pub fn set_environment(&mut self, device: &wgpu::Device, env: Option<&GpuEnv>) {
+        self.lit_by_env = env.is_some();
+        self.bind_group = Self::bind_group_(device, &self.bind_group_layout,
+                                            &self.uniform_buf, env);
+    }
+
+    /// Converts vertices to their GPU representation, merging any which are
+    /// identical after conversion (the triangulator emits a separate copy of
+    /// each vertex for every face and instance, and debug color modes may
+    /// unshare vertices entirely).  `key` maps a GPU vertex to a hashable
+    /// value.  Returns vertex and index data.
+    fn dedup_<T, K, F, G>(verts: &[Vertex], tris: &[Triangle], convert: F, key: G)
+        -> (Vec<T>, Vec<u32>)
+        where T: Copy, K: Eq + std::hash::Hash, F: Fn(&Vertex) -> T, G: Fn(&T) -> K
+    {
+        let mut vertex_data = Vec::with_capacity(verts.len());
+        let mut seen: HashMap<K, u32> = HashMap::with_capacity(verts.len());
+        let remap: Vec<u32> = verts.iter()
+            .map(|v| {
+                let g = convert(v);
+                *seen.entry(key(&g)).or_insert_with(|| {
+                    vertex_data.push(g);
+                    vertex_data.len() as u32 - 1
+                })
+            })
+            .collect();
+        let index_data = tris.iter()
+            .flat_map(|t| t.verts.iter())
+            .map(|i| remap[*i as usize])
+            .collect();
+        (vertex_data, index_data)
+    }
+
     pub fn draw(&self, camera: &Camera,
                 queue: &wgpu::Queue,
-                frame: &wgpu::SwapChainTexture,
+                view: &wgpu::TextureView,
                 depth_view: &wgpu::TextureView,
+                rect: (f32, f32, f32, f32),
                 encoder: &mut wgpu::CommandEncoder)
     {
         // Update the uniform buffer with our new matrix
@@ -194,12 +440,25 @@ impl Model {
         queue.write_buffer(&self.uniform_buf,
             std::mem::size_of::<Mat4>() as wgpu::BufferAddress,
             bytemuck::cast_slice(model_mat.as_slice()));
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        let options = Vec4::new(flag(self.zebra), flag(self.clip.is_some()),
+                                flag(self.hatch), 0.0);
+        let (lo, hi) = self.clip.unwrap_or((Vec3::zeros(), Vec3::zeros()));
+        let lighting = Vec4::new(flag(self.lit_by_env), flag(self.metallic), 0.0, 0.0);
+        let locals: Vec<f32> = [options, glm::vec3_to_vec4(&lo), glm::vec3_to_vec4(&hi),
+                                camera.eye(), lighting]
+            .iter()
+            .flat_map(|v| v.iter().copied())
+            .collect();
+        queue.write_buffer(&self.uniform_buf,
+            std::mem::size_of::<Mat4>() as wgpu::BufferAddress * 2,
+            bytemuck::cast_slice(&locals));
 
         let mut rpass = encoder.begin_render_pass(
             &wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Load,
@@ -216,10 +475,14 @@ impl Model {
                         stencil_ops: None,
                     }),
             });
+        rpass.set_viewport(rect.0, rect.1, rect.2, rect.3, 0.0, 1.0);
         rpass.set_pipeline(&self.render_pipeline);
-        rpass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+        rpass.set_index_buffer(self.index_buf.slice(..), self.index_format);
         rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buf.slice(..));
         rpass.set_bind_group(0, &self.bind_group, &[]);
-        rpass.draw_indexed(0..self.index_count, 0, 0..1);
+        for (indices, instances) in &self.draws {
+            rpass.draw_indexed(indices.clone(), 0, instances.clone());
+        }
     }
 }
@@ -2,30 +2,160 @@ struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
     [[location(0)]] normal: vec4<f32>;
     [[location(1)]] color: vec4<f32>;
+    // Position in model coordinates, for clipping
+    [[location(2)]] world: vec4<f32>;
+    // Normal in model coordinates, for environment lighting
+    [[location(3)]] world_normal: vec4<f32>;
 };
 
 [[block]]
 struct Locals {
     view_mat: mat4x4<f32>;
     model_mat: mat4x4<f32>;
+    // x is 1 when drawing zebra stripes, y is 1 when clipping to the box
+    // given by clip_min and clip_max, and z is 1 when drawing hatched
+    // section caps
+    options: vec4<f32>;
+    clip_min: vec4<f32>;
+    clip_max: vec4<f32>;
+    // In model coordinates, the eye position (with w = 1) or the direction
+    // towards the viewer (with w = 0)
+    eye: vec4<f32>;
+    // x is 1 when lighting with the environment maps, and y is 1 when the
+    // surface is metallic
+    lighting: vec4<f32>;
 };
 [[group(0), binding(0)]]
 var r_locals: Locals;
+[[group(0), binding(1)]]
+var t_env: texture_2d<f32>;
+[[group(0), binding(2)]]
+var t_irradiance: texture_2d<f32>;
+[[group(0), binding(3)]]
+var s_env: sampler;
+
+fn transform(position: vec4<f32>, normal: vec4<f32>, color: vec4<f32>,
+             instance_mat: mat4x4<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.world = instance_mat * vec4<f32>(position.xyz, 1.0);
+    out.position = r_locals.view_mat * r_locals.model_mat * out.world;
+    out.world_normal = instance_mat * vec4<f32>(normal.xyz, 0.0);
+    out.normal = normalize(r_locals.model_mat * out.world_normal);
+    out.color = color;
+    return out;
+}
 
 [[stage(vertex)]]
 fn vs_main(
     [[location(0)]] position: vec4<f32>,
     [[location(1)]] normal: vec4<f32>,
     [[location(2)]] color: vec4<f32>,
+    [[location(3)]] instance_0: vec4<f32>,
+    [[location(4)]] instance_1: vec4<f32>,
+    [[location(5)]] instance_2: vec4<f32>,
+    [[location(6)]] instance_3: vec4<f32>,
 ) -> VertexOutput {
-    var out: VertexOutput;
-    out.position = r_locals.view_mat * r_locals.model_mat * vec4<f32>(position.xyz, 1.0);
-    out.normal = normalize(r_locals.model_mat * vec4<f32>(normal.xyz, 0.0));
-    out.color = color;
-    return out;
+    return transform(position, normal, color, mat4x4<f32>(
+        instance_0, instance_1, instance_2, instance_3));
+}
+
+// Inverse of oct_encode in model.rs
+fn oct_decode(e: vec2<f32>) -> vec3<f32> {
+    var n: vec3<f32> = vec3<f32>(e.x, e.y, 1.0 - abs(e.x) - abs(e.y));
+    var t: f32 = max(-n.z, 0.0);
+    if (n.x >= 0.0) { n.x = n.x - t; } else { n.x = n.x + t; }
+    if (n.y >= 0.0) { n.y = n.y - t; } else { n.y = n.y + t; }
+    return normalize(n);
+}
+
+// Quantized positions are in the 0-1 range, and are scaled back into model
+// coordinates by the instance transform.
+[[stage(vertex)]]
+fn vs_quantized(
+    [[location(0)]] position: vec4<f32>,
+    [[location(1)]] normal: vec2<f32>,
+    [[location(2)]] color: vec4<f32>,
+    [[location(3)]] instance_0: vec4<f32>,
+    [[location(4)]] instance_1: vec4<f32>,
+    [[location(5)]] instance_2: vec4<f32>,
+    [[location(6)]] instance_3: vec4<f32>,
+) -> VertexOutput {
+    return transform(position, vec4<f32>(oct_decode(normal), 0.0), color,
+        mat4x4<f32>(instance_0, instance_1, instance_2, instance_3));
+}
+
+// Matches HATCH_SPACING and HATCH_SHADE in raster.rs
+let HATCH_SPACING: f32 = 8.0;
+let HATCH_SHADE: f32 = 0.4;
+
+// Matches ZEBRA_STRIPES in raster.rs
+let ZEBRA_STRIPES: f32 = 12.0;
+
+// Reflects the (orthographic) view ray off the surface into an environment
+// of horizontal stripes.  The reflected ray's vertical component doesn't
+// depend on which way the normal points, so back faces stripe correctly.
+fn zebra(n: vec3<f32>) -> f32 {
+    let r: f32 = clamp(2.0 * n.z * n.y, -1.0, 1.0);
+    let s: f32 = asin(r) / 3.14159265 * ZEBRA_STRIPES;
+    // Blend across a pixel's worth of stripe, to avoid aliasing
+    let w: f32 = max(fwidth(s), 0.0001);
+    let f: f32 = abs(fract(s) - 0.5);
+    return clamp((f - 0.25 + w) / (2.0 * w), 0.0, 1.0);
+}
+
+// Matches DIELECTRIC_F0 in environment.rs
+let DIELECTRIC_F0: f32 = 0.04;
+
+// Maps a direction (with Z up) to a position in an equirectangular map,
+// matching equirect in environment.rs
+fn equirect(d: vec3<f32>) -> vec2<f32> {
+    let u: f32 = atan2(d.y, d.x) / 6.28318531 + 0.5;
+    let v: f32 = acos(clamp(d.z, -1.0, 1.0)) / 3.14159265;
+    return vec2<f32>(u, v);
+}
+
+// Lights a surface with diffuse light from the irradiance map and a mirror
+// reflection of the environment, blended by Schlick's Fresnel term.  This
+// matches Environment::shade in environment.rs.
+fn environment(albedo: vec3<f32>, normal: vec3<f32>, world: vec3<f32>) -> vec3<f32> {
+    let v: vec3<f32> = normalize(r_locals.eye.xyz - world * r_locals.eye.w);
+    var n: vec3<f32> = normalize(normal);
+    if (dot(n, v) < 0.0) {
+        n = -n;
+    }
+    let r: vec3<f32> = reflect(-v, n);
+    let diffuse: vec3<f32> = textureSampleLevel(t_irradiance, s_env, equirect(n), 0.0).rgb;
+    let spec: vec3<f32> = textureSampleLevel(t_env, s_env, equirect(r), 0.0).rgb;
+    let metallic: bool = r_locals.lighting.y > 0.5;
+    var f0: vec3<f32> = vec3<f32>(DIELECTRIC_F0);
+    if (metallic) {
+        f0 = albedo;
+    }
+    let f: vec3<f32> = f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - max(dot(n, v), 0.0), 5.0);
+    if (metallic) {
+        return spec * f;
+    }
+    return albedo * diffuse * (vec3<f32>(1.0) - f) + spec * f;
 }
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(abs(in.normal.z) * in.color.xyz, 1.0);
+    if (r_locals.options.y > 0.5 &&
+        (any(in.world.xyz < r_locals.clip_min.xyz) ||
+         any(in.world.xyz > r_locals.clip_max.xyz))) {
+        discard;
+    }
+    if (r_locals.options.x > 0.5) {
+        let z: f32 = zebra(normalize(in.normal.xyz));
+        return vec4<f32>(vec3<f32>(0.02 + 0.98 * z), 1.0);
+    }
+    var c: vec3<f32> = abs(in.normal.z) * in.color.xyz;
+    if (r_locals.lighting.x > 0.5) {
+        c = environment(in.color.xyz, in.world_normal.xyz, in.world.xyz);
+    }
+    if (r_locals.options.z > 0.5 &&
+        fract((in.position.x + in.position.y) / HATCH_SPACING) < 0.25) {
+        c = c * HATCH_SHADE;
+    }
+    return vec4<f32>(c, 1.0);
 }
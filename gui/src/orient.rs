@@ -0,0 +1,137 @@
+use nalgebra_glm as glm;
+use glm::{Mat4, Vec2, Vec3};
+
+use triangulate::mesh::Mesh;
+
+/// Resolution of the coarse software rasterizer used to score views
+const RESOLUTION: usize = 64;
+
+/// Returns a set of candidate `(pitch, yaw)` orientations, matching the Euler
+/// angles used by the [`Camera`](crate::camera::Camera).  These are the 26
+/// views looking at the faces, edges, and corners of a cube.
+fn candidates() -> Vec<(f32, f32)> {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+    let mut out = Vec::new();
+    for yaw in &[-FRAC_PI_4, 0.0, FRAC_PI_4] {
+        for i in 0..8 {
+            out.push((i as f32 * FRAC_PI_4, *yaw));
+        }
+    }
+    out.push((0.0, FRAC_PI_2));
+    out.push((0.0, -FRAC_PI_2));
+    out
+}
+
+/// Renders the mesh from the given orientation into a small label buffer,
+/// then returns its viewpoint entropy.
+///
+/// Each pixel is labelled with the face which is visible there (or with the
+/// background), and the entropy is computed over the area fraction of each
+/// label.  Views which show many faces at similar sizes score highly, while
+/// edge-on or end-on views (with a small silhouette and only a few visible
+/// faces) score poorly.
+fn viewpoint_entropy(mesh: &Mesh, labels: &[usize], center: Vec3,
+                     radius: f32, pitch: f32, yaw: f32) -> f32
+{
+    let i = Mat4::identity();
+    let mat = glm::rotate_x(&i, yaw) * glm::rotate_y(&i, pitch);
+
+    // Project every vertex into pixel coordinates, with depth in Z.  The
+    // bounding sphere is used for scaling, so that silhouette sizes are
+    // comparable between views.
+    let scale = RESOLUTION as f32 / (2.0 * radius);
+    let pts: Vec<Vec3> = mesh.verts.iter()
+        .map(|v| {
+            let p: Vec3 = glm::convert(v.pos);
+            let p = (mat * (p - center).to_homogeneous()).xyz();
+            Vec3::new((p.x + radius) * scale, (p.y + radius) * scale, p.z)
+        })
+        .collect();
+
+    let mut depth = vec![-f32::INFINITY; RESOLUTION * RESOLUTION];
+    let mut label = vec![usize::MAX; RESOLUTION * RESOLUTION];
+    for (t, l) in mesh.triangles.iter().zip(labels) {
+        let a = pts[t.verts.x as usize];
+        let b = pts[t.verts.y as usize];
+        let c = pts[t.verts.z as usize];
+        let area = edge(a.xy(), b.xy(), c.xy());
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+        let clamp = |v: f32| (v.max(0.0) as usize).min(RESOLUTION - 1);
+        let (x0, x1) = (clamp(a.x.min(b.x).min(c.x)), clamp(a.x.max(b.x).max(c.x)));
+        let (y0, y1) = (clamp(a.y.min(b.y).min(c.y)), clamp(a.y.max(b.y).max(c.y)));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(b.xy(), c.xy(), p) / area;
+                let wb = edge(c.xy(), a.xy(), p) / area;
+                let wc = edge(a.xy(), b.xy(), p) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                // Larger Z is closer, matching the GPU's depth test
+                let z = wa * a.z + wb * b.z + wc * c.z;
+                let k = y * RESOLUTION + x;
+                if z > depth[k] {
+                    depth[k] = z;
+                    label[k] = *l;
+                }
+            }
+        }
+    }
+
+    label.sort_unstable();
+    let total = label.len() as f32;
+    label.chunk_by(|a, b| a == b)
+        .map(|run| run.len() as f32 / total)
+        .map(|p| -p * p.log2())
+        .sum()
+}
+
+/// Signed area of the parallelogram spanned by `ab` and `ap`
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Picks the most informative `(pitch, yaw)` orientation for the mesh, by
+/// viewpoint entropy.  This is used to pick thumbnail views, so that they
+/// don't end up looking at a model end-on.
+pub fn best_orientation(mesh: &Mesh) -> (f32, f32) {
+    if mesh.verts.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    // Label each triangle with its source face, falling back to a unique
+    // per-triangle label for triangles that don't have face information.
+    let n = mesh.faces.len();
+    let mut labels: Vec<usize> = (n..(n + mesh.triangles.len())).collect();
+    for (i, f) in mesh.faces.iter().enumerate() {
+        for t in f.triangles.clone() {
+            labels[t] = i;
+        }
+    }
+
+    let mut lo = Vec3::repeat(f32::INFINITY);
+    let mut hi = Vec3::repeat(-f32::INFINITY);
+    for v in &mesh.verts {
+        let p: Vec3 = glm::convert(v.pos);
+        lo = lo.inf(&p);
+        hi = hi.sup(&p);
+    }
+    let center = (lo + hi) / 2.0;
+    let radius = mesh.verts.iter()
+        .map(|v| (glm::convert::<_, Vec3>(v.pos) - center).norm())
+        .fold(f32::EPSILON, f32::max);
+
+    let mut best = (0.0, 0.0);
+    let mut best_score = -f32::INFINITY;
+    for (pitch, yaw) in candidates() {
+        let score = viewpoint_entropy(mesh, &labels, center, radius, pitch, yaw);
+        if score > best_score {
+            best = (pitch, yaw);
+            best_score = score;
+        }
+    }
+    best
+}
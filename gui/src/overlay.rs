@@ -0,0 +1,237 @@
+use std::borrow::Cow;
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra_glm as glm;
+use glm::{Vec2, Vec4};
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH}, theme::Theme};
+
+/// Number of screen pixels per font pixel
+const TEXT_SCALE: f32 = 2.0;
+
+/// Padding around text in a [`Overlay::label`], in screen pixels
+const PADDING: f32 = 6.0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GPUOverlayVertex {
+    pos: [f32; 2],
+    color: [f32; 4],
+}
+
+/// A screen-space layer of flat-colored rectangles and bitmap text, which is
+/// drawn on top of everything else.  Shapes are accumulated in pixel
+/// coordinates (with the origin at the top left of the window), then uploaded
+/// and drawn in a single pass.
+pub struct Overlay {
+    render_pipeline: wgpu::RenderPipeline,
+
+    /// Corners of each rectangle, as (min, max, color)
+    rects: Vec<(Vec2, Vec2, Vec4)>,
+
+    /// Colors for [`Overlay::label`]
+    background: Vec4,
+    foreground: Vec4,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, swapchain_format: wgpu::TextureFormat,
+               theme: &Theme) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buf_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GPUOverlayVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                // Positions
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                // Colors
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<Vec2>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+            ],
+        };
+
+        // Load the shaders from disk, either at runtime or compile-time
+        #[cfg(feature = "bundle-shaders")]
+        let overlay_src = Cow::Borrowed(include_str!("overlay.wgsl"));
+
+        #[cfg(not(feature = "bundle-shaders"))]
+        let overlay_src = Cow::Owned(
+            String::from_utf8(
+                std::fs::read("gui/src/overlay.wgsl")
+                    .expect("Could not read shader"))
+                    .expect("Shader is invalid UTF-8"));
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(overlay_src),
+            flags: wgpu::ShaderFlags::all(),
+        });
+
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[vertex_buf_layout],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: swapchain_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+        });
+
+        Overlay {
+            render_pipeline,
+            rects: Vec::new(),
+            background: theme.hud_background,
+            foreground: theme.hud_text,
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.background = theme.hud_background;
+        self.foreground = theme.hud_text;
+    }
+
+    /// Removes every shape from the overlay
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Vec4) {
+        self.rects.push((pos, pos + size, color));
+    }
+
+    /// Returns the size of a block of text, in pixels
+    pub fn text_size(text: &str) -> Vec2 {
+        let cols = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let rows = text.lines().count();
+        Vec2::new((cols * (GLYPH_WIDTH + 1)) as f32,
+                  (rows * (GLYPH_HEIGHT + 1)) as f32) * TEXT_SCALE
+    }
+
+    /// Draws a block of text with its top-left corner at the given position
+    pub fn text(&mut self, pos: Vec2, text: &str, color: Vec4) {
+        for (row, line) in text.lines().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let corner = pos + Vec2::new(
+                    (col * (GLYPH_WIDTH + 1)) as f32,
+                    (row * (GLYPH_HEIGHT + 1)) as f32) * TEXT_SCALE;
+                for (x, bits) in glyph(c).iter().enumerate() {
+                    // Emit one rectangle per vertical run of set bits
+                    let mut y = 0;
+                    while y < GLYPH_HEIGHT {
+                        if bits & (1 << y) == 0 {
+                            y += 1;
+                            continue;
+                        }
+                        let start = y;
+                        while y < GLYPH_HEIGHT && bits & (1 << y) != 0 {
+                            y += 1;
+                        }
+                        self.rect(
+                            corner + Vec2::new(x as f32, start as f32) * TEXT_SCALE,
+                            Vec2::new(1.0, (y - start) as f32) * TEXT_SCALE,
+                            color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws text on a translucent background, with its top-left corner at
+    /// the given position.  The label is nudged to stay inside the window.
+    pub fn label(&mut self, pos: Vec2, text: &str, window: PhysicalSize<u32>) {
+        let size = Self::text_size(text) + Vec2::repeat(PADDING * 2.0);
+        let pos = Vec2::new(
+            pos.x.min(window.width as f32 - size.x).max(0.0),
+            pos.y.min(window.height as f32 - size.y).max(0.0));
+        self.rect(pos, size, self.background);
+        self.text(pos + Vec2::repeat(PADDING), text, self.foreground);
+    }
+
+    /// Returns the height of a [`Overlay::status_bar`], in pixels
+    pub fn status_bar_height() -> f32 {
+        ((GLYPH_HEIGHT + 1) as f32) * TEXT_SCALE + PADDING * 2.0
+    }
+
+    /// Draws a line of text on a translucent strip along the bottom of the
+    /// window, running from `x` to `x + width`
+    pub fn status_bar(&mut self, x: f32, width: f32, text: &str,
+                      window: PhysicalSize<u32>) {
+        let h = Self::status_bar_height();
+        let pos = Vec2::new(x, window.height as f32 - h);
+        self.rect(pos, Vec2::new(width, h), self.background);
+        self.text(pos + Vec2::repeat(PADDING), text, self.foreground);
+    }
+
+    pub fn draw(&self, device: &wgpu::Device,
+                view: &wgpu::TextureView,
+                size: PhysicalSize<u32>,
+                encoder: &mut wgpu::CommandEncoder)
+    {
+        if self.rects.is_empty() {
+            return;
+        }
+
+        // Convert from pixels to normalized device coordinates
+        let ndc = |p: Vec2| [2.0 * p.x / size.width as f32 - 1.0,
+                             1.0 - 2.0 * p.y / size.height as f32];
+        let vertex_data: Vec<GPUOverlayVertex> = self.rects.iter()
+            .flat_map(|(lo, hi, color)| {
+                let v = |x, y| GPUOverlayVertex {
+                    pos: ndc(Vec2::new(x, y)),
+                    color: [color.x, color.y, color.z, color.w],
+                };
+                vec![v(lo.x, lo.y), v(hi.x, lo.y), v(hi.x, hi.y),
+                     v(lo.x, lo.y), v(hi.x, hi.y), v(lo.x, hi.y)]
+            })
+            .collect();
+        let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay vertex buffer"),
+            contents: bytemuck::cast_slice(&vertex_data),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let mut rpass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, vertex_buf.slice(..));
+        rpass.draw(0..vertex_data.len() as u32, 0..1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use winit::event_loop::ControlFlow;
+
+/// Frame interval in low-power mode
+const LOW_POWER_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+
+/// Time without input after which the viewer stops redrawing on its own
+const IDLE_AFTER: Duration = Duration::from_secs(5);
+
+/// Decides when to draw frames, so that the viewer uses no CPU or GPU time
+/// when nothing is happening.  Redraws are coalesced into at most one per
+/// pass through the event loop and, in low-power mode, capped to a fixed
+/// frame rate.
+pub struct Pacer {
+    interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    last_input: Instant,
+    /// Time at which a deferred redraw should happen
+    deadline: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(low_power: bool) -> Self {
+        Self {
+            interval: if low_power { Some(LOW_POWER_INTERVAL) } else { None },
+            last_frame: None,
+            last_input: Instant::now(),
+            deadline: None,
+        }
+    }
+
+    /// Records that user input arrived, which wakes the viewer from idle
+    pub fn input(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    /// Checks whether the user has been away long enough that the viewer
+    /// should stop scheduling its own redraws (e.g. retries after swapchain
+    /// errors) and wait for input.
+    pub fn is_idle(&self) -> bool {
+        self.last_input.elapsed() > IDLE_AFTER
+    }
+
+    /// Called when a redraw is requested.  Returns true if the frame should
+    /// be drawn now; otherwise, the redraw is deferred until the frame rate
+    /// cap allows it, and [`Pacer::wake`] will return true at that time.
+    pub fn ready(&mut self) -> bool {
+        let now = Instant::now();
+        if let (Some(interval), Some(last)) = (self.interval, self.last_frame) {
+            if now < last + interval {
+                self.deadline = Some(last + interval);
+                return false;
+            }
+        }
+        self.last_frame = Some(now);
+        self.deadline = None;
+        true
+    }
+
+    /// Clears a deferred redraw, returning true if there was one
+    pub fn wake(&mut self) -> bool {
+        self.deadline.take().is_some()
+    }
+
+    /// Returns how long the event loop should sleep
+    pub fn control_flow(&self) -> ControlFlow {
+        match self.deadline {
+            Some(t) => ControlFlow::WaitUntil(t),
+            None => ControlFlow::Wait,
+        }
+    }
+}
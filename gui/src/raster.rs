@@ -0,0 +1,284 @@
+use std::sync::Arc;
+
+use nalgebra_glm as glm;
+use glm::{Vec3, Vec4};
+use winit::dpi::PhysicalSize;
+
+use triangulate::mesh::{Triangle, Vertex};
+use crate::{camera::Camera, environment::Environment, theme::Theme};
+
+/// A CPU rasterizer which mimics the GPU pipelines in `backdrop.wgsl` and
+/// `model.wgsl`, for rendering screenshots on machines without any GPU (or
+/// software GPU driver).  Colors are accumulated in linear space, then
+/// encoded as sRGB to match the `Rgba8UnormSrgb` screenshot texture.
+pub struct Canvas {
+    size: PhysicalSize<u32>,
+    color: Vec<Vec3>,
+    /// Depth buffer, with larger values being nearer (like the GPU path,
+    /// which uses `CompareFunction::Greater` and clears to 0)
+    depth: Vec<f32>,
+    /// Environment which lights the triangles being drawn, and whether
+    /// they're metallic; without one, they're lit from the viewer
+    env: Option<(Arc<Environment>, bool)>,
+}
+
+impl Canvas {
+    /// Builds a canvas which is filled with the theme's backdrop gradient
+    pub fn new(size: PhysicalSize<u32>, theme: &Theme) -> Self {
+        let (w, h) = (size.width as usize, size.height as usize);
+        let mut color = Vec::with_capacity(w * h);
+        for y in 0..h {
+            let t = 1.0 - (y as f32 + 0.5) / h as f32;
+            let c = theme.backdrop[0] * (1.0 - t) + theme.backdrop[1] * t;
+            color.extend(std::iter::repeat_n(c, w));
+        }
+        Self { size, color, depth: vec![0.0; w * h], env: None }
+    }
+
+    /// Sets the lighting for subsequent calls to [`Canvas::draw`]
+    pub fn set_environment(&mut self, env: Option<(Arc<Environment>, bool)>) {
+        self.env = env;
+    }
+
+    /// Draws shaded triangles into the given viewport rectangle, which is
+    /// specified as (x, width, height), in pixels.  Pixels outside of the
+    /// `clip` box (given by its corners in model coordinates) are skipped.
+    pub fn draw(&mut self, verts: &[Vertex], tris: &[Triangle],
+                camera: &Camera, rect: (u32, u32, u32), shading: Shading,
+                clip: Option<(Vec3, Vec3)>)
+    {
+        let zebra = shading == Shading::Zebra;
+        let (x0, w, h) = (rect.0 as f32, rect.1 as f32, rect.2 as f32);
+        let view = camera.view_matrix();
+        let model = camera.model_matrix();
+        let mat = view * model;
+        let eye = camera.eye();
+
+        // Project every vertex into (pixel x, pixel y, depth), and find its
+        // shaded color, which is found per vertex (unlike on the GPU, where
+        // it's per pixel).  Zebra stripes need the normal itself, which
+        // takes the place of the color.  The model-space position is kept
+        // for clipping.
+        let projected: Vec<(Vec3, Vec3, Vec3)> = verts.iter()
+            .map(|v| {
+                let world: Vec3 = glm::convert(v.pos);
+                let n: Vec3 = glm::convert(v.norm);
+                let p = mat * Vec4::new(world.x, world.y, world.z, 1.0);
+                let n = model * Vec4::new(n.x, n.y, n.z, 0.0);
+                let n = if n.norm() > 0.0 { n.normalize() } else { n };
+                // Points behind a first-person eye can't be projected, so
+                // they're marked as NaN and their triangles are skipped
+                let pos = if p.w > 0.0 {
+                    let p = p.xyz() / p.w;
+                    Vec3::new(x0 + (p.x + 1.0) / 2.0 * w, (1.0 - p.y) / 2.0 * h, p.z)
+                } else {
+                    Vec3::repeat(f32::NAN)
+                };
+                if zebra {
+                    return (pos, n.xyz(), world);
+                }
+                let c: Vec3 = glm::convert(v.color);
+                if let Some((env, metallic)) = &self.env {
+                    let view = (eye.xyz() - world * eye.w).normalize();
+                    return (pos, env.shade(c, glm::convert(v.norm), view, *metallic), world);
+                }
+                (pos, c * n.z.abs(), world)
+            })
+            .collect();
+
+        let xmax = (rect.0 + rect.1).min(self.size.width);
+        let ymax = rect.2.min(self.size.height);
+        for t in tris {
+            let [(a, ca, ma), (b, cb, mb), (c, cc, mc)] = [
+                projected[t.verts.x as usize],
+                projected[t.verts.y as usize],
+                projected[t.verts.z as usize]];
+            let area = edge(a, b, c);
+            if area == 0.0 || area.is_nan() {
+                continue;
+            }
+            let lo = a.inf(&b).inf(&c);
+            let hi = a.sup(&b).sup(&c);
+            let xa = (lo.x.floor().max(x0) as u32).min(xmax);
+            let xb = (hi.x.ceil().max(0.0) as u32).min(xmax);
+            let ya = (lo.y.floor().max(0.0) as u32).min(ymax);
+            let yb = (hi.y.ceil().max(0.0) as u32).min(ymax);
+            for y in ya..yb {
+                for x in xa..xb {
+                    let p = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                    // Barycentric coordinates, normalized by signed area so
+                    // that either winding is accepted (there's no culling)
+                    let wa = edge(b, c, p) / area;
+                    let wb = edge(c, a, p) / area;
+                    let wc = edge(a, b, p) / area;
+                    if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                        continue;
+                    }
+                    let z = a.z * wa + b.z * wb + c.z * wc;
+                    let i = (y * self.size.width + x) as usize;
+                    if !(0.0..=1.0).contains(&z) || z <= self.depth[i] {
+                        continue;
+                    }
+                    if let Some((lo, hi)) = clip {
+                        let m = ma * wa + mb * wb + mc * wc;
+                        if (0..3).any(|j| m[j] < lo[j] || m[j] > hi[j]) {
+                            continue;
+                        }
+                    }
+                    self.depth[i] = z;
+                    let c = ca * wa + cb * wb + cc * wc;
+                    self.color[i] = match shading {
+                        Shading::Zebra => Vec3::repeat(0.02 + 0.98 * zebra_stripe(c)),
+                        Shading::Hatched if hatch_stripe(p.x, p.y) => c * HATCH_SHADE,
+                        _ => c,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Returns tightly-packed sRGB pixels (row-major, top row first)
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let encode = |c: f32| {
+            let c = c.clamp(0.0, 1.0);
+            let s = if c <= 0.003_130_8 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            (s * 255.0).round() as u8
+        };
+        self.color.iter()
+            .flat_map(|c| [encode(c.x), encode(c.y), encode(c.z), 255])
+            .collect()
+    }
+}
+
+/// How [`Canvas::draw`] colors triangles, matching the options in
+/// `model.wgsl`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Shading {
+    /// Vertex colors, lit from the viewer's direction
+    Lit,
+    /// Reflection stripes, which are found per pixel from the interpolated
+    /// normal
+    Zebra,
+    /// Lit, with diagonal hatching in screen space (for section caps)
+    Hatched,
+}
+
+/// Matches `HATCH_SPACING` in `model.wgsl`
+const HATCH_SPACING: f32 = 8.0;
+/// Matches `HATCH_SHADE` in `model.wgsl`
+const HATCH_SHADE: f32 = 0.4;
+
+/// Checks whether a pixel center is on one of the hatch lines, as in
+/// `model.wgsl`
+fn hatch_stripe(x: f32, y: f32) -> bool {
+    ((x + y) / HATCH_SPACING).rem_euclid(1.0) < 0.25
+}
+
+/// Matches `ZEBRA_STRIPES` in `model.wgsl`
+const ZEBRA_STRIPES: f32 = 12.0;
+
+/// Brightness of the striped environment in the direction that the view ray
+/// reflects off a surface with the given normal, as in `model.wgsl` (but
+/// without antialiasing)
+fn zebra_stripe(n: Vec3) -> f32 {
+    let n = if n.norm() > 0.0 { n.normalize() } else { n };
+    let r = (2.0 * n.z * n.y).clamp(-1.0, 1.0);
+    let s = r.asin() / std::f32::consts::PI * ZEBRA_STRIPES;
+    if (s.rem_euclid(1.0) - 0.5).abs() > 0.25 { 1.0 } else { 0.0 }
+}
+
+/// Twice the signed area of the triangle `abc`, in screen space
+fn edge(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glm::{DVec2, DVec3, U32Vec3};
+    use crate::theme::ThemeKind;
+
+    const N: u32 = 8;
+
+    /// Builds triangles covering the given corners of the default camera's
+    /// view (from -1 to 1 on each axis), facing the viewer
+    fn triangles(corners: &[(f64, f64)], z: f64, color: DVec3) -> (Vec<Vertex>, Vec<Triangle>) {
+        let verts = corners.iter().map(|&(x, y)| Vertex {
+            pos: DVec3::new(x, y, z), norm: DVec3::z(), color, uv: DVec2::zeros(),
+        }).collect();
+        let tris = (2..corners.len() as u32)
+            .map(|i| Triangle { verts: U32Vec3::new(0, i - 1, i) })
+            .collect();
+        (verts, tris)
+    }
+
+    fn blank() -> (Canvas, Camera) {
+        let size = PhysicalSize::new(N, N);
+        (Canvas::new(size, ThemeKind::default().theme()), Camera::new(N as f32, N as f32))
+    }
+
+    #[test]
+    fn coverage() {
+        // A triangle covering the lower-left half of the view, which takes
+        // pixels whose centers are on its diagonal edge
+        let (mut canvas, camera) = blank();
+        let backdrop = canvas.color.clone();
+        let (verts, tris) = triangles(&[(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0)],
+                                      0.0, DVec3::repeat(1.0));
+        canvas.draw(&verts, &tris, &camera, (0, N, N), Shading::Lit, None);
+        for y in 0..N {
+            for x in 0..N {
+                let i = (y * N + x) as usize;
+                let expected = if x <= y { Vec3::repeat(1.0) } else { backdrop[i] };
+                assert_eq!(canvas.color[i], expected, "at ({}, {})", x, y);
+            }
+        }
+
+        // Drawing into the right half of the canvas only covers that half
+        let (mut canvas, camera) = blank();
+        let square = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let (verts, tris) = triangles(&square, 0.0, DVec3::repeat(1.0));
+        canvas.draw(&verts, &tris, &camera, (N / 2, N / 2, N), Shading::Lit, None);
+        let covered = canvas.color.iter().enumerate()
+            .filter(|(_, c)| **c == Vec3::repeat(1.0))
+            .map(|(i, _)| i as u32 % N)
+            .collect::<Vec<_>>();
+        assert_eq!(covered.len() as u32, N * N / 2);
+        assert!(covered.iter().all(|&x| x >= N / 2));
+    }
+
+    #[test]
+    fn depth_and_clipping() {
+        let square = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let (near, near_tris) = triangles(&square, 1.0, DVec3::new(0.0, 1.0, 0.0));
+        let (far, far_tris) = triangles(&square, -1.0, DVec3::new(1.0, 0.0, 0.0));
+
+        // The nearer square wins, whichever order they're drawn in
+        let (mut canvas, camera) = blank();
+        canvas.draw(&near, &near_tris, &camera, (0, N, N), Shading::Lit, None);
+        canvas.draw(&far, &far_tris, &camera, (0, N, N), Shading::Lit, None);
+        assert!(canvas.color.iter().all(|c| *c == Vec3::y()));
+
+        // Clipping away the left half of the near square shows the far one
+        let (mut canvas, camera) = blank();
+        let clip = (Vec3::new(0.0, -2.0, -2.0), Vec3::repeat(2.0));
+        canvas.draw(&near, &near_tris, &camera, (0, N, N), Shading::Lit, Some(clip));
+        canvas.draw(&far, &far_tris, &camera, (0, N, N), Shading::Lit, None);
+        for (i, c) in canvas.color.iter().enumerate() {
+            let x = i as u32 % N;
+            assert_eq!(*c, if x < N / 2 { Vec3::x() } else { Vec3::y() }, "at {}", i);
+        }
+
+        // Colors are encoded as opaque sRGB
+        let rgba = canvas.to_rgba();
+        assert_eq!(rgba.len() as u32, N * N * 4);
+        assert_eq!(rgba[..4], [255, 0, 0, 255]);
+        assert_eq!(rgba[rgba.len() - 4..], [0, 255, 0, 255]);
+    }
+}
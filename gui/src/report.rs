@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use nalgebra_glm as glm;
+use glm::Vec2;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    annotate::Annotation,
+    font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH},
+    measure::Measurement,
+    screenshot,
+};
+
+/// Number of image pixels per font pixel, for note numbers in screenshots
+const MARKER_SCALE: usize = 2;
+
+/// Everything in the report about one viewport's model
+pub struct Section<'a> {
+    pub file: &'a Path,
+    /// Description of the STEP file's header metadata
+    pub header: String,
+    pub annotations: &'a [Annotation],
+}
+
+/// A rendered view of the session, with the window position of each note
+/// (which is numbered in order across every section)
+pub struct View {
+    pub size: PhysicalSize<u32>,
+    /// Tightly-packed RGBA pixels
+    pub pixels: Vec<u8>,
+    /// Positions of the notes, which are `None` if out of view
+    pub markers: Vec<Option<Vec2>>,
+}
+
+/// Escapes text for use in HTML
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            '\'' => out += "&#39;",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Encodes bytes as standard (padded) base64, for embedding images
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Draws a short label (light text on a dark box) into RGBA pixels, with
+/// its top-left corner at the given position.  Pixels outside the image are
+/// skipped.
+fn stamp(pixels: &mut [u8], size: PhysicalSize<u32>, pos: Vec2, text: &str) {
+    let (w, h) = (size.width as i64, size.height as i64);
+    let mut put = |x: i64, y: i64, c: [u8; 4]| {
+        if x >= 0 && y >= 0 && x < w && y < h {
+            let i = ((y * w + x) * 4) as usize;
+            pixels[i..i + 4].copy_from_slice(&c);
+        }
+    };
+    let (x0, y0) = (pos.x as i64, pos.y as i64);
+    let pad = MARKER_SCALE as i64 * 2;
+    let cols = text.chars().count() * (GLYPH_WIDTH + 1) * MARKER_SCALE;
+    let rows = (GLYPH_HEIGHT + 1) * MARKER_SCALE;
+    for y in -pad..(rows as i64 + pad) {
+        for x in -pad..(cols as i64 + pad) {
+            put(x0 + x, y0 + y, [20, 20, 20, 255]);
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let left = x0 + (i * (GLYPH_WIDTH + 1) * MARKER_SCALE) as i64;
+        for (col, bits) in glyph(c).iter().enumerate() {
+            for row in (0..GLYPH_HEIGHT).filter(|r| bits & (1 << r) != 0) {
+                for dy in 0..MARKER_SCALE {
+                    for dx in 0..MARKER_SCALE {
+                        put(left + (col * MARKER_SCALE + dx) as i64,
+                            y0 + (row * MARKER_SCALE + dy) as i64,
+                            [255, 255, 0, 255]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes a standalone HTML review report, with the rendered view (if any)
+/// embedded as a PNG, followed by each model's notes and the session's
+/// measurements
+pub fn write(filename: &str, sections: &[Section], measurements: &[Measurement],
+             view: Option<View>) -> std::io::Result<()>
+{
+    let title = sections.iter()
+        .map(|s| s.file.file_name().map(|f| f.to_string_lossy()).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut out = String::new();
+    out += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+    out += &format!("<title>Review: {}</title>\n", escape(&title));
+    out += "<style>\n\
+            body { font-family: sans-serif; max-width: 60em; margin: 2em auto; }\n\
+            img { max-width: 100%; border: 1px solid #888; }\n\
+            pre { background: #eee; padding: 0.5em; }\n\
+            .point { color: #666; font-family: monospace; }\n\
+            </style>\n</head>\n<body>\n";
+    out += &format!("<h1>Review: {}</h1>\n", escape(&title));
+
+    if let Some(mut view) = view {
+        for (i, pos) in view.markers.iter().enumerate() {
+            if let Some(pos) = pos {
+                stamp(&mut view.pixels, view.size, *pos, &format!("{}", i + 1));
+            }
+        }
+        let mut png = Vec::new();
+        screenshot::write_png(&mut png, view.size, &view.pixels)?;
+        out += &format!("<img src=\"data:image/png;base64,{}\" alt=\"Current view\">\n",
+                        base64(&png));
+    }
+
+    let mut n = 0;
+    for s in sections {
+        out += &format!("<h2>{}</h2>\n<pre>{}</pre>\n",
+                        escape(&s.file.display().to_string()), escape(&s.header));
+        if s.annotations.is_empty() {
+            out += "<p>No notes.</p>\n";
+            continue;
+        }
+        out += &format!("<h3>Notes</h3>\n<ol start=\"{}\">\n", n + 1);
+        for a in s.annotations {
+            let p = a.point;
+            out += &format!("<li>{} <span class=\"point\">({:.4}, {:.4}, {:.4})</span></li>\n",
+                            escape(&a.text), p.x, p.y, p.z);
+        }
+        out += "</ol>\n";
+        n += s.annotations.len();
+    }
+
+    out += "<h2>Measurements</h2>\n";
+    if measurements.is_empty() {
+        out += "<p>No measurements.</p>\n";
+    } else {
+        out += "<ol>\n";
+        for m in measurements {
+            out += &format!("<li>{}</li>\n", escape(&m.describe()).replace('\n', "<br>"));
+        }
+        out += "</ol>\n";
+    }
+    out += "</body>\n</html>\n";
+    std::fs::write(filename, out)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html() {
+        assert_eq!(escape(r#"<a href="x">Tom's & Jerry's</a>"#),
+                   "&lt;a href=&quot;x&quot;&gt;Tom&#39;s &amp; Jerry&#39;s&lt;/a&gt;");
+        assert_eq!(escape("plain text, µm"), "plain text, µm");
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn base64_vectors() {
+        // Test vectors from RFC 4648, section 10, which cover every amount
+        // of padding
+        for (data, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="),
+                                ("foo", "Zm9v"), ("foob", "Zm9vYg=="),
+                                ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+}
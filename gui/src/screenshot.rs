@@ -0,0 +1,231 @@
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app::App, backdrop::Backdrop, environment::Environment, gpu, loader::Loader,
+    raster::Canvas, session::Session, theme::ThemeKind,
+    viewport::{ViewOptions, Viewport},
+};
+
+/// Texture format for offscreen rendering, which matches PNG's RGBA layout
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Builds a device and queue which aren't attached to any window surface,
+/// returning `None` if there's no suitable adapter.
+pub async fn headless_device(backends: wgpu::BackendBit)
+    -> Option<(wgpu::Device, wgpu::Queue)>
+{
+    let instance = wgpu::Instance::new(backends);
+    let adapter = gpu::pick_adapter(&instance, backends, None, false)?;
+    eprintln!("Using {}", gpu::describe(&adapter));
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        )
+        .await
+        .ok()
+}
+
+/// Draws the viewports into an offscreen texture, then reads it back and
+/// returns tightly-packed RGBA pixels (row-major, top row first).
+///
+/// `format` must match the format with which the backdrop and viewports were
+/// built; BGRA formats are swizzled into RGBA during readback.
+pub fn render(device: &wgpu::Device, queue: &wgpu::Queue,
+              format: wgpu::TextureFormat,
+              backdrop: &Backdrop, viewports: &[Viewport],
+              size: PhysicalSize<u32>) -> Vec<u8>
+{
+    let extent = wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+    };
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("screenshot tex"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsage::RENDER_ATTACHMENT |
+               wgpu::TextureUsage::COPY_SRC,
+    });
+    let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth = App::rebuild_depth_(size, device);
+
+    // Rows in the readback buffer must be padded to a fixed alignment
+    let row_bytes = size.width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row_bytes = row_bytes.div_ceil(align) * align;
+    let buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("screenshot buf"),
+        size: (padded_row_bytes * size.height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: None });
+    backdrop.draw(&view, &depth.1, &mut encoder);
+    for v in viewports {
+        v.draw(queue, &view, &depth.1, &mut encoder);
+    }
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture: &tex,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buf,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                rows_per_image: None,
+            },
+        },
+        extent);
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buf.slice(..);
+    let mapped = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapped).expect("Could not map screenshot buffer");
+
+    let data = slice.get_mapped_range();
+    let mut out = Vec::with_capacity((row_bytes * size.height) as usize);
+    for row in data.chunks(padded_row_bytes as usize) {
+        out.extend(&row[..row_bytes as usize]);
+    }
+    if matches!(format, wgpu::TextureFormat::Bgra8Unorm |
+                        wgpu::TextureFormat::Bgra8UnormSrgb) {
+        for px in out.chunks_mut(4) {
+            px.swap(0, 2);
+        }
+    }
+    out
+}
+
+/// Writes tightly-packed RGBA pixels to a PNG file
+pub fn save_png(filename: &str, size: PhysicalSize<u32>, data: &[u8])
+    -> std::io::Result<()>
+{
+    let file = std::fs::File::create(filename)?;
+    write_png(std::io::BufWriter::new(file), size, data)
+}
+
+/// Encodes tightly-packed RGBA pixels as a PNG
+pub fn write_png<W: std::io::Write>(w: W, size: PhysicalSize<u32>, data: &[u8])
+    -> std::io::Result<()>
+{
+    let mut enc = png::Encoder::new(w, size.width, size.height);
+    enc.set_color(png::ColorType::RGBA);
+    enc.set_depth(png::BitDepth::Eight);
+    let mut writer = enc.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+/// Builds the filename for frame `i` of a turntable sequence, by inserting
+/// the frame number before the extension (`out.png` becomes `out_007.png`)
+fn frame_filename(filename: &str, i: usize) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    path.with_file_name(format!("{}_{:03}.{}", stem, i, ext))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Renders the given models without opening a window, then saves the image
+/// to a PNG.  Each camera is turned to an automatically-chosen view, unless
+/// the session's cameras are synchronized, in which case every viewport uses
+/// the first model's view.  Models are lit as set in the session.
+///
+/// If `frames` is provided, then the models are spun through a full turn
+/// over that many frames, each of which is saved to a numbered PNG.  An
+/// output filename of `-` writes raw RGBA frames to stdout instead, which
+/// is suitable for piping into `ffmpeg -f rawvideo`.
+///
+/// If there's no GPU (or `backends` is `None`), images are drawn with the
+/// CPU rasterizer instead, which is slower but works on headless servers.
+pub async fn run(loaders: Vec<Loader>, view: ViewOptions,
+                 filename: String, size: PhysicalSize<u32>,
+                 session: &Session, frames: Option<usize>,
+                 backends: Option<wgpu::BackendBit>)
+{
+    let gpu = match backends {
+        Some(b) => headless_device(b).await,
+        None => None,
+    };
+    if gpu.is_none() {
+        eprintln!("Using software rasterizer");
+    }
+    let theme = ThemeKind::default().theme();
+    let env = Environment::load(&session.environment).unwrap_or_else(|e| {
+        eprintln!("Could not load environment: {}", e);
+        None
+    }).map(Arc::new);
+    let backdrop = gpu.as_ref()
+        .map(|(device, _)| Backdrop::new(device, FORMAT, theme));
+
+    let n = loaders.len() as u32;
+    let mut viewports: Vec<Viewport> = loaders.into_iter()
+        .enumerate()
+        .map(|(i, loader)| {
+            let (x, vsize) = App::viewport_rect_(size, i as u32, n);
+            Viewport::new(loader, view, x, vsize)
+        })
+        .collect();
+    for v in viewports.iter_mut() {
+        v.set_environment(gpu.as_ref().map(|(d, q)| (d, q)), env.clone());
+        v.set_metallic(session.metallic);
+        match &gpu {
+            Some((device, _)) => v.load(device, FORMAT, true),
+            None => { v.join_loader(true); },
+        }
+    }
+    if session.sync_cameras {
+        let (first, rest) = viewports.split_first_mut().unwrap();
+        for v in rest {
+            v.camera.sync_from(&first.camera);
+        }
+    }
+
+    let to_stdout = filename == "-";
+    let mut stdout = std::io::stdout();
+    let count = frames.unwrap_or(1);
+    for i in 0..count {
+        let data = match (&gpu, &backdrop) {
+            (Some((device, queue)), Some(backdrop)) =>
+                render(device, queue, FORMAT, backdrop, &viewports, size),
+            _ => {
+                let mut canvas = Canvas::new(size, theme);
+                for v in &viewports {
+                    v.rasterize(&mut canvas);
+                }
+                canvas.to_rgba()
+            },
+        };
+        if to_stdout {
+            use std::io::Write;
+            stdout.write_all(&data).expect("Could not write frame");
+        } else if frames.is_some() {
+            save_png(&frame_filename(&filename, i), size, &data)
+                .expect("Could not save frame");
+        } else {
+            save_png(&filename, size, &data).expect("Could not save screenshot");
+        }
+        for v in viewports.iter_mut() {
+            v.camera.spin(std::f32::consts::TAU / count as f32, 0.0);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use triangulate::mesh::Mesh;
+
+/// Maximum number of matches listed in the search overlay
+const MAX_SHOWN: usize = 12;
+
+/// A named part of the model, which may be made of several solids (from a
+/// product's subtree, or from repeated instances with the same name)
+pub struct Part {
+    pub name: String,
+    /// Indices into `Mesh::solids`
+    pub solids: Vec<usize>,
+}
+
+/// Collects every named product in the assembly tree, along with named
+/// solids that aren't covered by the tree.  Parts with the same name are
+/// merged, so searching for a fastener finds every copy of it.
+pub fn parts(mesh: &Mesh) -> Vec<Part> {
+    let mut children = vec![Vec::new(); mesh.assembly.len()];
+    for (i, node) in mesh.assembly.iter().enumerate() {
+        if let Some(p) = node.parent {
+            children[p].push(i);
+        }
+    }
+    let mut by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, node) in mesh.assembly.iter().enumerate() {
+        if node.name.is_empty() {
+            continue;
+        }
+        let solids = by_name.entry(&node.name).or_default();
+        // Walk down the tree from this node
+        let mut todo = vec![i];
+        while let Some(j) = todo.pop() {
+            solids.extend(&mesh.assembly[j].solids);
+            todo.extend(&children[j]);
+        }
+    }
+    for (i, s) in mesh.solids.iter().enumerate() {
+        if let Some(name) = s.name.as_deref().filter(|n| !n.is_empty()) {
+            by_name.entry(name).or_default().push(i);
+        }
+    }
+    by_name.into_iter()
+        .map(|(name, mut solids)| {
+            solids.sort_unstable();
+            solids.dedup();
+            Part { name: name.to_owned(), solids }
+        })
+        .filter(|p| !p.solids.is_empty())
+        .collect()
+}
+
+/// Type-to-filter search over part names
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    /// Index of the highlighted match, within the list of matches
+    pub cursor: usize,
+}
+
+impl Search {
+    /// Returns the indices of parts whose names contain the query (ignoring
+    /// case), with names that start with the query listed first
+    pub fn matches(&self, parts: &[Part]) -> Vec<usize> {
+        let query = self.query.to_lowercase();
+        let mut out: Vec<(bool, usize)> = parts.iter()
+            .enumerate()
+            .filter_map(|(i, p)| {
+                let name = p.name.to_lowercase();
+                name.find(&query).map(|pos| (pos != 0, i))
+            })
+            .collect();
+        out.sort_unstable();
+        out.into_iter().map(|(_, i)| i).collect()
+    }
+
+    /// Moves the highlighted match up or down, wrapping around the ends
+    pub fn step(&mut self, delta: isize, count: usize) {
+        if count > 0 {
+            self.cursor = (self.cursor as isize + delta)
+                .rem_euclid(count as isize) as usize;
+        }
+    }
+
+    /// Builds the overlay text, listing the first few matches with the
+    /// highlighted one marked
+    pub fn text(&self, parts: &[Part], matches: &[usize]) -> String {
+        let mut out = format!("Find part: {}_", self.query);
+        if matches.is_empty() {
+            out += "\n  (no matches)";
+        }
+        // Scroll the list so that the highlighted match stays visible
+        let first = self.cursor.saturating_sub(MAX_SHOWN - 1);
+        for (i, &m) in matches.iter().enumerate().skip(first).take(MAX_SHOWN) {
+            let p = &parts[m];
+            out += if i == self.cursor { "\n> " } else { "\n  " };
+            out += &p.name;
+            if p.solids.len() > 1 {
+                out += &format!(" ({} solids)", p.solids.len());
+            }
+        }
+        if matches.len() > first + MAX_SHOWN {
+            out += &format!("\n  ... {} more", matches.len() - first - MAX_SHOWN);
+        }
+        out
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::DMat4;
+    use triangulate::mesh::{AssemblyNode, Solid};
+
+    fn node(name: &str, parent: Option<usize>, solids: Vec<usize>) -> AssemblyNode {
+        AssemblyNode { name: name.to_owned(), parent, transform: DMat4::identity(), solids }
+    }
+
+    fn solid(name: Option<&str>) -> Solid {
+        Solid {
+            id: 0, name: name.map(str::to_owned), color: None, material: None,
+            triangles: 0..0, instance_of: None, transform: DMat4::identity(),
+        }
+    }
+
+    #[test]
+    fn named_parts() {
+        // An assembly with two copies of a bolt, one under an unnamed
+        // sub-assembly, plus a named solid outside the tree
+        let mesh = Mesh {
+            assembly: vec![
+                node("Frame", None, vec![0]),
+                node("Bolt", Some(0), vec![1]),
+                node("", Some(0), vec![]),
+                node("Bolt", Some(2), vec![2]),
+                node("Empty", None, vec![]),
+            ],
+            solids: vec![solid(None), solid(None), solid(None), solid(Some("Washer"))],
+            ..Mesh::default()
+        };
+
+        let parts = parts(&mesh);
+        let names: Vec<_> = parts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["Bolt", "Frame", "Washer"]);
+        assert_eq!(parts[0].solids, vec![1, 2]);
+        assert_eq!(parts[1].solids, vec![0, 1, 2]);
+        assert_eq!(parts[2].solids, vec![3]);
+
+        let search = Search { query: "B".to_owned(), cursor: 0 };
+        assert_eq!(search.matches(&parts), vec![0]);
+        let search = Search { query: "r".to_owned(), cursor: 0 };
+        assert_eq!(search.matches(&parts), vec![1, 2]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    annotate::Annotation, camera::CameraState, clip::ClipBox,
+    environment::EnvironmentKind, measure::Measurement, theme::ThemeKind,
+    vis::ColorMode,
+};
+
+/// File extension for saved sessions
+pub const EXTENSION: &str = "foxtrot";
+
+/// Version of the session format, which is bumped whenever a change would
+/// stop older sessions from loading correctly
+pub const VERSION: u32 = 1;
+
+/// Per-viewport state in a saved session
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewportState {
+    pub camera: Option<CameraState>,
+    /// Selected faces, as indices into the mesh's face list
+    pub selection: Vec<usize>,
+    /// User-assigned part colors as RGB, keyed by solid STEP entity id
+    pub part_colors: BTreeMap<usize, [f64; 3]>,
+    /// Hidden parts, as solid STEP entity ids
+    pub hidden_parts: BTreeSet<usize>,
+    /// Review notes pinned to the model
+    pub annotations: Vec<Annotation>,
+    /// Box outside of which the model is hidden
+    pub clip: Option<ClipBox>,
+}
+
+/// Everything needed to restore a viewing session, which is saved as JSON.
+/// Missing fields fall back to their defaults, so that older session files
+/// keep loading as new state is added; only the version is required.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Format version, which is [`VERSION`] for sessions saved by this build
+    pub version: u32,
+    /// STEP files, one per viewport.  Relative paths are resolved against
+    /// the session file's directory.
+    pub files: Vec<PathBuf>,
+    pub sync_cameras: bool,
+    pub color_mode: ColorMode,
+    pub theme: ThemeKind,
+    pub show_normals: bool,
+    /// Draw the curves of the files' 2D annotations
+    pub show_draughting: bool,
+    pub zebra: bool,
+    /// Hatch the cross-sections where the clip box cuts through solids
+    pub hatch: bool,
+    /// Lighting for the realtime renderer.  Like the models, an HDR file's
+    /// path may be relative to the session file.
+    pub environment: EnvironmentKind,
+    pub metallic: bool,
+    pub viewports: Vec<ViewportState>,
+    pub measurements: Vec<Measurement>,
+
+    /// The file from which this session was loaded, if any
+    #[serde(skip)]
+    pub path: Option<String>,
+}
+
+/// Checks whether a path names a session file, based on its extension
+pub fn is_session(path: &str) -> bool {
+    Path::new(path).extension().map(|e| e == EXTENSION).unwrap_or(false)
+}
+
+impl Session {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = std::fs::read(path)?;
+        let value: serde_json::Value = serde_json::from_slice(&data)?;
+        // Version 1 is the only one so far, so there's nothing to migrate
+        match value.get("version").map(|v| v.as_u64()) {
+            None => return Err("Session file has no version".into()),
+            Some(Some(v)) if (1..=VERSION as u64).contains(&v) => (),
+            Some(v) => return Err(format!(
+                "Unsupported session version {} (expected 1 to {})",
+                v.map(|v| v.to_string()).unwrap_or_default(), VERSION).into()),
+        }
+        let mut session: Session = serde_json::from_value(value)?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let env = match &mut session.environment {
+            EnvironmentKind::File(f) => Some(f),
+            _ => None,
+        };
+        for f in session.files.iter_mut().chain(env) {
+            if f.is_relative() {
+                *f = dir.join(&f);
+            }
+        }
+        session.path = Some(path.to_owned());
+        Ok(session)
+    }
+
+    /// Writes the session to a file.  Model (and HDR) paths are stored
+    /// relative to the session file where possible, so that a directory containing both can
+    /// be shared.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = match Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()) {
+            Some(d) => d.canonicalize()?,
+            None => std::env::current_dir()?,
+        };
+        let mut out = self.clone();
+        out.version = VERSION;
+        let env = match &mut out.environment {
+            EnvironmentKind::File(f) => Some(f),
+            _ => None,
+        };
+        for f in out.files.iter_mut().chain(env) {
+            if let Ok(abs) = f.canonicalize() {
+                *f = abs.strip_prefix(&dir).map(|p| p.to_owned()).unwrap_or(abs);
+            }
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&out)?)?;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        let path = std::env::temp_dir()
+            .join(format!("foxtrot-session-{}.{}", std::process::id(), EXTENSION));
+        let file = path.to_str().unwrap();
+        let session = Session {
+            hatch: true,
+            viewports: vec![ViewportState {
+                hidden_parts: [12, 34].iter().copied().collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        session.save(file).unwrap();
+        let loaded = Session::load(file).unwrap();
+        assert_eq!(loaded.version, VERSION);
+        assert!(loaded.hatch);
+        assert_eq!(loaded.viewports[0].hidden_parts, session.viewports[0].hidden_parts);
+
+        // Sessions without a version, or from a newer build, are rejected
+        let newer = format!(r#"{{"version": {}}}"#, VERSION + 1);
+        for json in ["{}", r#"{"version": 0}"#, r#"{"version": "1"}"#, &newer] {
+            std::fs::write(&path, json).unwrap();
+            let err = Session::load(file).unwrap_err().to_string();
+            assert!(err.contains("version"), "{}: {}", json, err);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
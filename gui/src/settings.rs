@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event_loop::EventLoop,
+    window::{Fullscreen, Window, WindowBuilder},
+};
+
+/// Window placement, which is remembered between runs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WindowState {
+    /// Inner size, in physical pixels
+    pub size: (u32, u32),
+    /// Outer position, in physical pixels, if the platform reports it
+    pub position: Option<(i32, i32)>,
+    /// Name of the monitor which the window was on
+    pub monitor: Option<String>,
+    pub fullscreen: bool,
+}
+
+/// Per-user settings, which are stored as JSON in the platform's
+/// configuration directory.  Like sessions, missing fields fall back to
+/// their defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: Option<WindowState>,
+}
+
+/// Returns the path to the settings file, or `None` if there's no obvious
+/// place to put it
+fn path() -> Option<PathBuf> {
+    let env = |k| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dir = if cfg!(target_os = "windows") {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|h| h.join("Library").join("Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|h| h.join(".config")))
+    }?;
+    Some(dir.join("foxtrot").join("settings.json"))
+}
+
+impl Settings {
+    /// Loads settings, falling back to defaults if the file is missing or
+    /// invalid (which isn't worth refusing to start over)
+    pub fn load() -> Self {
+        path().and_then(|p| std::fs::read(p).ok())
+            .and_then(|data| match serde_json::from_slice(&data) {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    eprintln!("Ignoring invalid settings file: {}", e);
+                    None
+                },
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path().ok_or("Could not find a settings directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl WindowState {
+    /// Captures the state of a window
+    pub fn from_window(window: &Window) -> Self {
+        let size = window.inner_size();
+        Self {
+            size: (size.width, size.height),
+            position: window.outer_position().ok().map(|p| (p.x, p.y)),
+            monitor: window.current_monitor().and_then(|m| m.name()),
+            fullscreen: window.fullscreen().is_some(),
+        }
+    }
+
+    /// Builds a window with this state.  The saved position is only used if
+    /// its monitor is still connected and the position is on one of the
+    /// screens, so that the window can't be restored out of sight.
+    pub fn build<T>(&self, builder: WindowBuilder, event_loop: &EventLoop<T>)
+        -> Window
+    {
+        let monitor = event_loop.available_monitors()
+            .find(|m| m.name().is_some() && m.name() == self.monitor);
+        let builder = builder
+            .with_inner_size(PhysicalSize::new(self.size.0, self.size.1))
+            .with_fullscreen(if self.fullscreen {
+                Some(Fullscreen::Borderless(monitor.clone()))
+            } else {
+                None
+            });
+        let window = builder.build(event_loop).expect("Could not build window");
+        if let (Some(_), Some((x, y))) = (&monitor, self.position) {
+            let visible = event_loop.available_monitors().any(|m| {
+                let (p, s) = (m.position(), m.size());
+                x >= p.x && y >= p.y &&
+                x < p.x + s.width as i32 && y < p.y + s.height as i32
+            });
+            if visible && !self.fullscreen {
+                window.set_outer_position(PhysicalPosition::new(x, y));
+            }
+        }
+        window
+    }
+}
@@ -0,0 +1,73 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// A consistent set of colors for every part of the viewer
+pub struct Theme {
+    /// Backdrop gradient, from the bottom of the window to the top
+    pub backdrop: [Vec3; 2],
+    /// Color for parts which aren't styled in the STEP file
+    pub part: DVec3,
+    /// Tint applied to selected faces
+    pub highlight: DVec3,
+    /// Color for placeholders standing in for faces which failed to
+    /// triangulate
+    pub failed: DVec3,
+    /// Color for line overlays drawn on the model
+    pub edge: Vec3,
+    /// Background and text colors for HUD labels
+    pub hud_background: Vec4,
+    pub hud_text: Vec4,
+}
+
+const DARK: Theme = Theme {
+    backdrop: [Vec3::new(0.05, 0.06, 0.10), Vec3::new(0.17, 0.22, 0.29)],
+    part: DVec3::new(0.5, 0.5, 0.5),
+    highlight: DVec3::new(1.0, 0.5, 0.0),
+    failed: DVec3::new(0.9, 0.1, 0.1),
+    edge: Vec3::new(1.0, 1.0, 0.0),
+    hud_background: Vec4::new(0.1, 0.1, 0.1, 0.8),
+    hud_text: Vec4::new(1.0, 1.0, 1.0, 1.0),
+};
+
+const LIGHT: Theme = Theme {
+    backdrop: [Vec3::new(0.72, 0.75, 0.80), Vec3::new(0.97, 0.97, 0.98)],
+    part: DVec3::new(0.62, 0.66, 0.72),
+    highlight: DVec3::new(0.9, 0.3, 0.0),
+    failed: DVec3::new(0.8, 0.0, 0.0),
+    edge: Vec3::new(0.1, 0.2, 0.6),
+    hud_background: Vec4::new(1.0, 1.0, 1.0, 0.85),
+    hud_text: Vec4::new(0.05, 0.05, 0.05, 1.0),
+};
+
+/// Selects one of the built-in themes
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeKind {
+    /// Returns the next theme, for cycling through them with a hotkey
+    pub fn next(self) -> Self {
+        match self {
+            ThemeKind::Dark => ThemeKind::Light,
+            ThemeKind::Light => ThemeKind::Dark,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemeKind::Dark => "dark",
+            ThemeKind::Light => "light",
+        }
+    }
+
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            ThemeKind::Dark => &DARK,
+            ThemeKind::Light => &LIGHT,
+        }
+    }
+}
@@ -0,0 +1,1178 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, Mat4, U32Vec3, Vec2};
+use winit::{dpi::PhysicalSize, event::MouseButton};
+
+use step::header::Header;
+use triangulate::{
+    bvh::{Aabb, Hit, SolidBvh, SolidHit},
+    clash::find_clashes,
+    groups::{face_groups, FaceGroup, GroupKind},
+    mass::mass_properties,
+    mesh::{Dimensions, Mesh, Solid, Triangle, Vertex},
+    obb::Obb,
+    render::{render, RenderParams},
+    section::section,
+};
+use crate::{
+    annotate::Annotation,
+    camera::Camera, clip::{ClipBox, Drag}, environment::{Environment, GpuEnv},
+    lines::Lines, loader::Loader, measure::Plane,
+    model::{Batch, Model},
+    orient,
+    raster::{Canvas, Shading},
+    search::{self, Part},
+    theme::ThemeKind, vis, vis::ColorMode,
+};
+
+/// Maximum cursor motion (in pixels) between press and release for the
+/// mouse action to count as a click, rather than a drag.
+const CLICK_DISTANCE: f32 = 3.0;
+
+/// Distance by which section caps are moved inside the clip box, as a
+/// fraction of the model's size, so that they aren't clipped themselves
+const CAP_INSET: f64 = 1e-5;
+
+/// Timing and size of the most recent load, for the status bar
+struct LoadStats {
+    parse: Duration,
+    triangulate: Duration,
+    /// Time spent building the first GPU model, which is `None` until it's
+    /// been uploaded (or forever, when rendering without a GPU)
+    upload: Option<Duration>,
+    triangles: usize,
+    /// Size of the CPU-side mesh's vertex and triangle arrays
+    mesh_bytes: usize,
+}
+
+/// Settings which apply to every viewport, set from the command line
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ViewOptions {
+    /// Pull direction and limit for the draft angle color mode
+    pub draft: vis::Draft,
+    /// Density (in kg/m³) for solids which don't have one from their
+    /// material
+    pub density: Option<f64>,
+    /// Upload models with quantized vertex attributes (see [`Model::new`])
+    pub quantize: bool,
+}
+
+/// A `Viewport` is a region of the window which draws a single model with
+/// its own camera.  Viewports are tiled horizontally across the window.
+pub struct Viewport {
+    loader: Option<Loader>,
+    options: ViewOptions,
+    model: Option<Model>,
+    pub camera: Camera,
+
+    /// CPU-side copy of the mesh, used to rebuild the model when switching
+    /// between color modes
+    mesh: Option<Mesh>,
+    color_mode: ColorMode,
+    theme: ThemeKind,
+
+    /// Vertex normal segments, built the first time they're shown
+    normals: Option<Lines>,
+    /// Curves of the file's 2D annotations, built when first shown
+    draughting: Option<Lines>,
+    /// Review notes pinned to the model, and their leader lines (which are
+    /// `None` if there are no notes)
+    annotations: Vec<Annotation>,
+    leaders: Option<Lines>,
+    /// Largest dimension of the model's bounding box, which sets the
+    /// length of leader lines
+    model_size: f64,
+    show_normals: bool,
+    show_draughting: bool,
+    /// Draw reflection stripes instead of the usual shading
+    zebra: bool,
+    /// Image-based lighting, and its maps on the GPU (which are `None` when
+    /// lighting with the headlight, or rendering without a GPU)
+    environment: Option<Arc<Environment>>,
+    gpu_env: Option<GpuEnv>,
+    /// Shade the model as metal, which only shows with an environment
+    metallic: bool,
+
+    /// Box outside of which the model is hidden, and its wireframe (which
+    /// is `None` if there's no box)
+    clip: Option<ClipBox>,
+    clip_lines: Option<Lines>,
+    /// Face of the clip box which is being dragged
+    clip_drag: Option<Drag>,
+    /// Draw the clip box's wireframe, which is only shown while editing it
+    pub show_clip_box: bool,
+    /// Filled cross-sections where the clip box cuts through solids
+    caps: Option<Model>,
+    /// Draw diagonal hatching over the section caps
+    hatch: bool,
+
+    /// Acceleration structure for picking, built when the model is loaded
+    bvh: Option<SolidBvh>,
+    solids: Vec<Solid>,
+    /// Named parts, for searching by name
+    parts: Vec<Part>,
+    /// Coplanar and coaxial faces, for selecting as groups
+    face_groups: Vec<FaceGroup>,
+
+    /// Selected faces, as indices into `mesh.faces`
+    selection: BTreeSet<usize>,
+    /// Solids in the part which was last clicked or searched for, as
+    /// indices into `solids`
+    selected_part: Vec<usize>,
+    /// User-assigned colors, keyed by solid STEP entity id (so that every
+    /// instance of a solid shares its color)
+    part_colors: BTreeMap<usize, DVec3>,
+    /// Hidden parts, keyed by solid STEP entity id (so that every instance
+    /// of a solid is hidden with it)
+    hidden: BTreeSet<usize>,
+
+    /// Description of the selected part's bounding boxes, for the HUD
+    part_info: Option<String>,
+    /// Metadata from the STEP file's header
+    header: Header,
+    load_stats: Option<LoadStats>,
+
+    /// Last cursor position, in viewport-local coordinates
+    cursor: Vec2,
+    /// Position at which the left mouse button was pressed
+    click: Option<Vec2>,
+
+    /// Horizontal offset of the viewport within the window, in pixels
+    x: u32,
+    size: PhysicalSize<u32>,
+}
+
+impl Viewport {
+    pub fn new(loader: Loader, options: ViewOptions,
+               x: u32, size: PhysicalSize<u32>) -> Self {
+        Self {
+            loader: Some(loader),
+            options,
+            model: None,
+            camera: Camera::new(size.width as f32, size.height as f32),
+            mesh: None,
+            color_mode: ColorMode::Shaded,
+            theme: ThemeKind::default(),
+            normals: None,
+            draughting: None,
+            annotations: Vec::new(),
+            leaders: None,
+            model_size: 1.0,
+            show_normals: false,
+            show_draughting: false,
+            zebra: false,
+            environment: None,
+            gpu_env: None,
+            metallic: false,
+            clip: None,
+            clip_lines: None,
+            clip_drag: None,
+            show_clip_box: false,
+            caps: None,
+            hatch: false,
+            bvh: None,
+            solids: Vec::new(),
+            parts: Vec::new(),
+            face_groups: Vec::new(),
+            selection: BTreeSet::new(),
+            selected_part: Vec::new(),
+            part_colors: BTreeMap::new(),
+            hidden: BTreeSet::new(),
+            part_info: None,
+            header: Header::default(),
+            load_stats: None,
+            cursor: Vec2::zeros(),
+            click: None,
+            x, size,
+        }
+    }
+
+    pub fn set_rect(&mut self, x: u32, size: PhysicalSize<u32>) {
+        self.x = x;
+        self.size = size;
+        self.camera.set_size(size.width as f32, size.height as f32);
+    }
+
+    /// Checks whether the given window-space X coordinate is in this viewport
+    pub fn contains(&self, x: f32) -> bool {
+        x >= self.x as f32 && x < (self.x + self.size.width) as f32
+    }
+
+    /// Converts a window-space position into viewport-local coordinates
+    fn local(&self, pos: Vec2) -> Vec2 {
+        Vec2::new(pos.x - self.x as f32, pos.y)
+    }
+
+    pub fn mouse_move(&mut self, pos: Vec2) {
+        self.cursor = self.local(pos);
+        self.camera.mouse_move(self.cursor);
+    }
+    pub fn mouse_pressed(&mut self, button: MouseButton) {
+        if button == MouseButton::Left {
+            self.click = Some(self.cursor);
+        }
+        self.camera.mouse_pressed(button);
+    }
+
+    /// Handles a mouse release, returning the picked point if this was a
+    /// left click (rather than a drag) over the model.
+    pub fn mouse_released(&mut self, button: MouseButton)
+        -> Option<SolidHit<Hit>>
+    {
+        self.camera.mouse_released(button);
+        if button != MouseButton::Left || self.clip_drag.take().is_some() {
+            return None;
+        }
+        self.click.take()
+            .filter(|c| (c - self.cursor).norm() <= CLICK_DISTANCE)
+            .and_then(|_| self.pick(self.cursor))
+    }
+
+    /// Casts a ray into the model under the cursor
+    pub fn hover(&self) -> Option<SolidHit<Hit>> {
+        self.pick(self.cursor)
+    }
+
+    /// Casts a ray into the model at a viewport-local position, skipping
+    /// hidden parts and anything which is hidden by the clip box
+    pub fn pick(&self, pos: Vec2) -> Option<SolidHit<Hit>> {
+        let (origin, dir) = self.camera.ray(pos);
+        let bvh = self.bvh.as_ref()?;
+        let (origin, dir) = (glm::convert(origin), glm::convert(dir));
+        if self.clip.is_none() && self.hidden.is_empty() {
+            return bvh.raycast(origin, dir);
+        }
+        bvh.raycast_all(origin, dir).into_iter()
+            .find(|h| self.clip.map(|c| c.contains(h.hit.point)).unwrap_or(true) &&
+                      !self.is_hidden(h.solid))
+    }
+
+    /// Checks whether a solid (as an index into `solids`) is hidden
+    fn is_hidden(&self, solid: usize) -> bool {
+        self.solids.get(solid).map(|s| self.hidden.contains(&s.id)).unwrap_or(false)
+    }
+
+    /// Starts dragging the clip box face under the cursor, returning false
+    /// if there isn't one (in which case the click goes to the camera)
+    pub fn clip_pressed(&mut self) -> bool {
+        let (origin, dir) = self.camera.ray(self.cursor);
+        self.clip_drag = self.clip.as_ref()
+            .filter(|_| self.show_clip_box)
+            .and_then(|c| c.pick(glm::convert(origin), glm::convert(dir)));
+        self.clip_drag.is_some()
+    }
+
+    /// Moves the dragged clip box face to follow the cursor, returning false
+    /// if no face is being dragged
+    pub fn clip_dragged(&mut self, device: &wgpu::Device,
+                        swapchain_format: wgpu::TextureFormat) -> bool {
+        let (origin, dir) = self.camera.ray(self.cursor);
+        match (&self.clip_drag, &mut self.clip) {
+            (Some(d), Some(c)) => c.drag(d, glm::convert(origin), glm::convert(dir)),
+            _ => return false,
+        }
+        self.rebuild_clip(device, swapchain_format);
+        true
+    }
+
+    /// Adds a clip box around the whole model, if there isn't one already
+    pub fn add_clip(&mut self, device: &wgpu::Device,
+                    swapchain_format: wgpu::TextureFormat) {
+        if self.clip.is_none() {
+            if let Some(aabb) = self.bvh.as_ref().map(|b| b.bounds()) {
+                self.set_clip(device, swapchain_format,
+                              Some(ClipBox::around(aabb.min, aabb.max)));
+            }
+        }
+    }
+
+    pub fn clip(&self) -> Option<ClipBox> {
+        self.clip
+    }
+
+    pub fn set_clip(&mut self, device: &wgpu::Device,
+                    swapchain_format: wgpu::TextureFormat,
+                    clip: Option<ClipBox>) {
+        self.clip = clip;
+        self.clip_drag = None;
+        self.rebuild_clip(device, swapchain_format);
+    }
+
+    /// Applies the clip box to the GPU model and rebuilds its wireframe and
+    /// section caps
+    fn rebuild_clip(&mut self, device: &wgpu::Device,
+                    swapchain_format: wgpu::TextureFormat) {
+        if let Some(model) = &mut self.model {
+            model.clip = self.clip.map(|c| c.bounds());
+        }
+        let color = self.theme.theme().edge;
+        self.clip_lines = self.clip
+            .map(|c| Lines::new(device, swapchain_format, &c.edges(color)));
+        self.rebuild_caps(device, swapchain_format);
+    }
+
+    fn rebuild_caps(&mut self, device: &wgpu::Device,
+                    swapchain_format: wgpu::TextureFormat) {
+        self.caps = self.cap_mesh().map(|(verts, tris)| {
+            let batch = Batch {
+                triangles: 0..tris.len(),
+                transforms: vec![Mat4::identity()],
+            };
+            let mut caps = Model::new(device, swapchain_format, &verts, &tris, &[batch],
+                                      self.gpu_env.as_ref(), self.options.quantize);
+            caps.clip = self.clip.map(|c| c.bounds());
+            caps.hatch = self.hatch;
+            caps.metallic = self.metallic;
+            caps
+        });
+    }
+
+    /// Builds filled polygons where each face of the clip box cuts through
+    /// solids, in the solids' shaded colors.  Returns `None` if nothing is
+    /// cut.
+    fn cap_mesh(&self) -> Option<(Vec<Vertex>, Vec<Triangle>)> {
+        let (mesh, clip, bvh) = (self.mesh.as_ref()?, self.clip?, self.bvh.as_ref()?);
+        let bounds = bvh.bounds();
+        let theme = self.theme.theme();
+        let colors = vis::solid_colors(mesh, theme, &self.part_colors);
+        let inset = self.model_size * CAP_INSET;
+        let mut verts = Vec::new();
+        let mut tris = Vec::new();
+        for axis in 0..3 {
+            for max in [false, true] {
+                let v = if max { clip.max[axis] - inset } else { clip.min[axis] + inset };
+                if v <= bounds.min[axis] || v >= bounds.max[axis] {
+                    continue;
+                }
+                let mut origin = DVec3::zeros();
+                origin[axis] = v;
+                let mut normal = DVec3::zeros();
+                normal[axis] = if max { 1.0 } else { -1.0 };
+                for s in section(mesh, origin, normal) {
+                    if s.solid.map(|i| self.hidden.contains(&mesh.solids[i].id))
+                        .unwrap_or(false)
+                    {
+                        continue;
+                    }
+                    let color = s.solid.map(|i| colors[i]).unwrap_or(theme.part);
+                    if let Some((pts, t)) = s.cap(normal) {
+                        let start = verts.len() as u32;
+                        verts.extend(pts.iter().map(|p| Vertex {
+                            pos: *p, norm: normal, color, uv: DVec2::zeros(),
+                        }));
+                        tris.extend(t.iter().map(|t| Triangle {
+                            verts: U32Vec3::new(t[0] as u32, t[1] as u32, t[2] as u32)
+                                .add_scalar(start),
+                        }));
+                    }
+                }
+            }
+        }
+        if tris.is_empty() {
+            None
+        } else {
+            Some((verts, tris))
+        }
+    }
+
+    /// Turns hatching of the section caps on or off
+    pub fn set_hatch(&mut self, hatch: bool) {
+        self.hatch = hatch;
+        if let Some(caps) = &mut self.caps {
+            caps.hatch = hatch;
+        }
+    }
+
+    /// Builds a multi-line description of the face under a pick result
+    pub fn describe(&self, h: &SolidHit<Hit>) -> Option<String> {
+        let mesh = self.mesh.as_ref()?;
+        let mut out = String::new();
+        match mesh.face_of(h.hit.triangle) {
+            Some(f) => {
+                out += &format!("Face #{} ({:?})\nArea: {:.3}", f.id, f.surface,
+                                mesh.area(f.triangles.clone()));
+                if let Some(d) = &f.dimensions {
+                    out += "\n";
+                    out += &describe_dimensions(d);
+                }
+            },
+            None => out += &format!("Triangle {}", h.hit.triangle),
+        }
+        if let Some(s) = self.solids.get(h.solid) {
+            out += &format!("\nSolid #{}", s.id);
+            if let Some(name) = &s.name {
+                out += &format!(": {}", name);
+            }
+        }
+        Some(out)
+    }
+
+    /// Describes the analytic dimensions of the face under a pick result
+    pub fn dimensions(&self, h: &SolidHit<Hit>) -> Option<String> {
+        let face = self.mesh.as_ref()?.face_of(h.hit.triangle)?;
+        face.dimensions.as_ref().map(describe_dimensions)
+    }
+
+    /// Returns the point under a pick result.  If `snap` is set, the point
+    /// is projected onto the face's analytic surface, rather than lying on
+    /// the tessellation; the flag returned indicates whether that worked.
+    pub fn probe(&self, h: &SolidHit<Hit>, snap: bool) -> (DVec3, bool) {
+        let snapped = self.mesh.as_ref()
+            .filter(|_| snap)
+            .and_then(|m| m.face_of(h.hit.triangle))
+            .and_then(|f| f.project(h.hit.point));
+        match snapped {
+            Some(p) => (p, true),
+            None => (h.hit.point, false),
+        }
+    }
+
+    /// Returns the (possibly local) plane of the face under a pick result
+    pub fn plane_at(&self, h: &SolidHit<Hit>) -> Option<Plane> {
+        Plane::from_pick(self.mesh.as_ref()?, h.hit.triangle, h.hit.point)
+    }
+
+    /// Adds the face under a pick result to the selection, or removes it if
+    /// it was already selected.  Returns false if the hit isn't on a face.
+    pub fn toggle_selection(&mut self, device: &wgpu::Device,
+                            swapchain_format: wgpu::TextureFormat,
+                            h: &SolidHit<Hit>) -> bool {
+        let i = match self.mesh.as_ref().and_then(|m| m.face_index(h.hit.triangle)) {
+            Some(i) => i,
+            None => return false,
+        };
+        if !self.selection.remove(&i) {
+            self.selection.insert(i);
+        }
+        self.rebuild_model(device, swapchain_format);
+        true
+    }
+
+    /// Adds every face which is coplanar or coaxial with the face under a
+    /// pick result to the selection, or removes them if they were all
+    /// already selected, and describes the group in the HUD.  Faces which
+    /// aren't in a group are toggled alone.  Returns false if the hit isn't
+    /// on a face.
+    pub fn toggle_group_selection(&mut self, device: &wgpu::Device,
+                                  swapchain_format: wgpu::TextureFormat,
+                                  h: &SolidHit<Hit>) -> bool {
+        let (mesh, i) = match self.mesh.as_ref()
+            .and_then(|m| Some((m, m.face_index(h.hit.triangle)?)))
+        {
+            Some(m) => m,
+            None => return false,
+        };
+        let g = match self.face_groups.iter().find(|g| g.faces.contains(&i)) {
+            Some(g) => g,
+            None => return self.toggle_selection(device, swapchain_format, h),
+        };
+        if g.faces.iter().all(|f| self.selection.contains(f)) {
+            for f in &g.faces {
+                self.selection.remove(f);
+            }
+        } else {
+            self.selection.extend(g.faces.iter().copied());
+        }
+        let kind = match g.kind {
+            GroupKind::Plane => "Coplanar",
+            GroupKind::Axis => "Coaxial",
+        };
+        let (p, d) = (g.point, g.direction);
+        self.part_info = Some(format!(
+            "{} faces: {} on {} solids\npoint: ({:.6}, {:.6}, {:.6})\n{}: ({:.4}, {:.4}, {:.4})",
+            kind, g.faces.len(), g.solids(mesh).len(), p.x, p.y, p.z,
+            if g.kind == GroupKind::Plane { "normal" } else { "axis" }, d.x, d.y, d.z));
+        self.rebuild_model(device, swapchain_format);
+        true
+    }
+
+    /// Selects the part (solid) under a pick result, computing its bounding
+    /// box dimensions for display
+    pub fn select_part(&mut self, h: &SolidHit<Hit>) {
+        let mesh = match &self.mesh {
+            Some(m) => m,
+            None => return,
+        };
+        let (label, range) = match self.solids.get(h.solid) {
+            Some(s) => (format!("Solid #{}", s.id), s.triangles.clone()),
+            None => ("Model".to_owned(), 0..mesh.triangles.len()),
+        };
+        self.selected_part = if h.solid < self.solids.len() {
+            vec![h.solid]
+        } else {
+            Vec::new()
+        };
+        let pts = part_points(mesh, std::iter::once(range));
+        let mut info = describe_bounds(&label, &pts).0;
+        if let Some(m) = describe_material(mesh, &self.selected_part, self.options.density) {
+            info += &m;
+        }
+        self.part_info = Some(info);
+    }
+
+    /// Returns the named parts in the model, for searching
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Zooms the camera to one of the parts from [`Viewport::parts`],
+    /// selecting its faces and describing it in the HUD
+    pub fn focus_part(&mut self, device: &wgpu::Device,
+                      swapchain_format: wgpu::TextureFormat, i: usize) {
+        let (mesh, part) = match (&self.mesh, self.parts.get(i)) {
+            (Some(m), Some(p)) => (m, p),
+            _ => return,
+        };
+        let ranges: Vec<_> = part.solids.iter()
+            .map(|&s| mesh.solids[s].triangles.clone())
+            .collect();
+        let pts = part_points(mesh, ranges.iter().cloned());
+        if pts.is_empty() {
+            return;
+        }
+        let (mut info, aabb) = describe_bounds(&part.name, &pts);
+        if let Some(m) = describe_material(mesh, &part.solids, self.options.density) {
+            info += &m;
+        }
+        self.camera.fit_bounds(aabb.min, aabb.max);
+        self.part_info = Some(info);
+        self.selected_part = part.solids.clone();
+        self.selection = mesh.faces.iter()
+            .enumerate()
+            .filter(|(_, f)| !f.triangles.is_empty() &&
+                              ranges.iter().any(|r| r.contains(&f.triangles.start)))
+            .map(|(i, _)| i)
+            .collect();
+        self.rebuild_model(device, swapchain_format);
+    }
+
+    /// Checks the model for intersecting solids, listing them in the HUD
+    pub fn find_clashes(&mut self) {
+        let mesh = match &self.mesh {
+            Some(m) => m,
+            None => return,
+        };
+        let describe = |i: usize| {
+            let s = &mesh.solids[i];
+            match &s.name {
+                Some(name) => format!("#{} ({})", s.id, name),
+                None => format!("#{}", s.id),
+            }
+        };
+        let clashes = find_clashes(mesh, 0.0);
+        let mut out = format!("{} clashes", clashes.len());
+        for c in &clashes {
+            out += &format!("\n{} / {}: {}", describe(c.a), describe(c.b), c.kind.name());
+        }
+        self.part_info = Some(out);
+    }
+
+    /// Steps the selected part's color through the palette, then back to
+    /// its original color.  Returns a description of the new color, or
+    /// `None` if no part is selected.
+    pub fn cycle_part_color(&mut self, device: &wgpu::Device,
+                            swapchain_format: wgpu::TextureFormat)
+        -> Option<String>
+    {
+        let ids: BTreeSet<usize> = self.selected_part.iter()
+            .map(|&s| self.solids[s].id)
+            .collect();
+        let first = *ids.iter().next()?;
+        let next = match self.part_colors.get(&first) {
+            None => Some(0),
+            Some(c) => (0..vis::PART_COLORS)
+                .position(|i| vis::palette(i) == *c)
+                .map(|i| i + 1)
+                .filter(|i| *i < vis::PART_COLORS),
+        };
+        let out = match next {
+            Some(i) => {
+                let c = vis::palette(i);
+                for id in &ids {
+                    self.part_colors.insert(*id, c);
+                }
+                format!("Part color: ({:.2}, {:.2}, {:.2})", c.x, c.y, c.z)
+            },
+            None => {
+                for id in &ids {
+                    self.part_colors.remove(id);
+                }
+                "Part color: default".to_owned()
+            },
+        };
+        self.rebuild_model(device, swapchain_format);
+        Some(out)
+    }
+
+    /// Returns the user-assigned part colors, keyed by solid entity id
+    pub fn part_colors(&self) -> &BTreeMap<usize, DVec3> {
+        &self.part_colors
+    }
+
+    /// Replaces the user-assigned part colors
+    pub fn set_part_colors(&mut self, device: &wgpu::Device,
+                           swapchain_format: wgpu::TextureFormat,
+                           colors: BTreeMap<usize, DVec3>) {
+        self.part_colors = colors;
+        self.rebuild_model(device, swapchain_format);
+    }
+
+    /// Hides the selected part (and every instance of it), deselecting it.
+    /// Returns a description of what was hidden, or `None` if no part is
+    /// selected.
+    pub fn hide_part(&mut self, device: &wgpu::Device,
+                     swapchain_format: wgpu::TextureFormat)
+        -> Option<String>
+    {
+        if self.selected_part.is_empty() {
+            return None;
+        }
+        for &s in &self.selected_part {
+            self.hidden.insert(self.solids[s].id);
+        }
+        self.selected_part.clear();
+        self.part_info = None;
+        self.rebuild_model(device, swapchain_format);
+        Some(format!("Hidden parts: {}", self.hidden.len()))
+    }
+
+    /// Returns the hidden parts, as solid entity ids
+    pub fn hidden_parts(&self) -> &BTreeSet<usize> {
+        &self.hidden
+    }
+
+    /// Replaces the set of hidden parts, e.g. showing every part again
+    pub fn set_hidden_parts(&mut self, device: &wgpu::Device,
+                            swapchain_format: wgpu::TextureFormat,
+                            hidden: BTreeSet<usize>) {
+        self.hidden = hidden;
+        self.rebuild_model(device, swapchain_format);
+    }
+
+    /// Pins a note to the model
+    pub fn add_annotation(&mut self, device: &wgpu::Device,
+                          swapchain_format: wgpu::TextureFormat,
+                          a: Annotation) {
+        self.annotations.push(a);
+        self.rebuild_leaders(device, swapchain_format);
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Replaces every note, e.g. when restoring a session
+    pub fn set_annotations(&mut self, device: &wgpu::Device,
+                           swapchain_format: wgpu::TextureFormat,
+                           annotations: Vec<Annotation>) {
+        self.annotations = annotations;
+        self.rebuild_leaders(device, swapchain_format);
+    }
+
+    /// Returns the text of each note and the window-space position at the
+    /// end of its leader line, which is `None` if it's behind the camera
+    pub fn annotation_labels(&self) -> Vec<(Option<Vec2>, &str)> {
+        self.annotations.iter()
+            .map(|a| {
+                let p = self.camera.project(glm::convert(a.label_pos(self.model_size)));
+                (p.map(|p| p + Vec2::new(self.x as f32, 0.0)), a.text.as_str())
+            })
+            .collect()
+    }
+
+    fn rebuild_leaders(&mut self, device: &wgpu::Device,
+                       swapchain_format: wgpu::TextureFormat) {
+        let color = self.theme.theme().edge;
+        let segments: Vec<_> = self.annotations.iter()
+            .map(|a| a.leader(self.model_size, color))
+            .collect();
+        self.leaders = if segments.is_empty() {
+            None
+        } else {
+            Some(Lines::new(device, swapchain_format, &segments))
+        };
+    }
+
+    /// Returns the HUD text for the selected part, if any
+    pub fn part_info(&self) -> Option<&str> {
+        self.part_info.as_deref()
+    }
+
+    /// Returns the HUD text for the file's header metadata
+    pub fn file_info(&self) -> String {
+        let info = self.header.to_string();
+        if info.is_empty() {
+            "No header metadata".to_owned()
+        } else {
+            info
+        }
+    }
+
+    /// Returns the status bar text, with timing and memory usage for the
+    /// load, or `None` if the model hasn't loaded yet
+    pub fn status(&self) -> Option<String> {
+        let s = self.load_stats.as_ref()?;
+        let mut out = format!("Parse {:.1?} | Triangulate {:.1?}",
+                              s.parse, s.triangulate);
+        if let Some(upload) = s.upload {
+            out += &format!(" | Upload {:.1?}", upload);
+        }
+        out += &format!(" | {} triangles | Mesh {}", s.triangles,
+                        format_bytes(s.mesh_bytes));
+        if let Some(model) = &self.model {
+            out += &format!(", GPU {}", format_bytes(model.gpu_bytes));
+        }
+        Some(out)
+    }
+
+    /// Returns the window-space X coordinate of the viewport's left edge
+    pub fn left(&self) -> f32 {
+        self.x as f32
+    }
+
+    /// Returns the window-space X coordinate of the viewport's right edge
+    pub fn right(&self) -> f32 {
+        (self.x + self.size.width) as f32
+    }
+
+    /// Returns the selected faces, as indices into the mesh's face list
+    pub fn selection(&self) -> Vec<usize> {
+        self.selection.iter().copied().collect()
+    }
+
+    /// Replaces the face selection, ignoring out-of-range indices
+    pub fn set_selection(&mut self, device: &wgpu::Device,
+                         swapchain_format: wgpu::TextureFormat,
+                         faces: &[usize]) {
+        let n = self.mesh.as_ref().map(|m| m.faces.len()).unwrap_or(0);
+        self.selection = faces.iter().copied().filter(|&i| i < n).collect();
+        self.rebuild_model(device, swapchain_format);
+    }
+
+    pub fn clear_selection(&mut self, device: &wgpu::Device,
+                           swapchain_format: wgpu::TextureFormat) {
+        self.part_info = None;
+        self.selected_part.clear();
+        if !self.selection.is_empty() {
+            self.selection.clear();
+            self.rebuild_model(device, swapchain_format);
+        }
+    }
+
+    /// Returns the STEP entity indices of the selected faces, without
+    /// duplicates (which occur when a face is instanced)
+    pub fn selected_ids(&self) -> Vec<usize> {
+        let ids: BTreeSet<usize> = self.mesh.iter()
+            .flat_map(|m| self.selection.iter().map(move |&i| m.faces[i].id))
+            .collect();
+        ids.into_iter().collect()
+    }
+
+    /// Writes the selected faces to a mesh file, picking the format from the
+    /// filename's extension (OBJ, PLY, GLB, or STL).  Returns the number of
+    /// faces saved.
+    pub fn export_selection(&self, filename: &str) -> std::io::Result<usize> {
+        let faces: Vec<usize> = self.selection.iter().copied().collect();
+        let mesh = match &self.mesh {
+            Some(m) => m.extract_faces(&faces),
+            None => return Ok(0),
+        };
+        if filename.ends_with(".obj") {
+            mesh.save_obj(filename)?;
+        } else if filename.ends_with(".ply") {
+            mesh.save_ply(filename)?;
+        } else if filename.ends_with(".glb") {
+            mesh.save_glb(filename)?;
+        } else {
+            mesh.save_stl(filename)?;
+        }
+        Ok(faces.len())
+    }
+
+    /// Returns the STEP entity index of the given solid, if known
+    pub fn solid_id(&self, solid: usize) -> Option<usize> {
+        self.solids.get(solid).map(|s| s.id)
+    }
+    pub fn mouse_scroll(&mut self, delta: f32) {
+        self.camera.mouse_scroll(delta);
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.model.is_some()
+    }
+
+    /// Checks whether the loader thread is done, so that loading won't block
+    pub fn is_load_finished(&self) -> bool {
+        self.loader.as_ref().map(|l| l.is_finished()).unwrap_or(true)
+    }
+
+    /// Aborts an in-progress load
+    pub fn cancel_load(&mut self) {
+        if let Some(loader) = self.loader.take() {
+            loader.cancel();
+        }
+    }
+
+    /// Blocks until the loader thread has finished, then uploads its mesh
+    /// to the GPU and fits the camera to it.  If `auto_orient` is set, the
+    /// camera is also turned to the most informative view of the model.
+    pub fn load(&mut self, device: &wgpu::Device,
+                swapchain_format: wgpu::TextureFormat, auto_orient: bool) {
+        if self.join_loader(auto_orient) {
+            let start = Instant::now();
+            self.rebuild_model(device, swapchain_format);
+            if let Some(s) = &mut self.load_stats {
+                s.upload = Some(start.elapsed());
+            }
+            if self.show_normals {
+                self.set_show_normals(device, swapchain_format, true);
+            }
+            if self.show_draughting {
+                self.set_show_draughting(device, swapchain_format, true);
+            }
+        }
+    }
+
+    /// Blocks until the loader thread has finished, then fits the camera to
+    /// its mesh, without touching the GPU.  Returns true if a mesh was
+    /// loaded by this call.
+    pub fn join_loader(&mut self, auto_orient: bool) -> bool {
+        if let Some(loader) = self.loader.take() {
+            // A cancelled load leaves the viewport empty
+            let (mesh, header, stats) = loader.join().unwrap_or_default();
+            self.header = header;
+            self.load_stats = Some(LoadStats {
+                parse: stats.parse_time,
+                triangulate: stats.triangulate_time,
+                upload: None,
+                triangles: mesh.triangles.len(),
+                mesh_bytes: std::mem::size_of_val(mesh.verts.as_slice()) +
+                            std::mem::size_of_val(mesh.triangles.as_slice()),
+            });
+            self.camera.fit_verts(&mesh.verts);
+            let mut aabb = Aabb::empty();
+            for v in &mesh.verts {
+                aabb.expand(v.pos);
+            }
+            self.model_size = (aabb.max - aabb.min).max();
+            if auto_orient {
+                let (pitch, yaw) = orient::best_orientation(&mesh);
+                self.camera.set_orientation(pitch, yaw);
+            }
+            self.bvh = Some(SolidBvh::new(&mesh));
+            self.solids = mesh.solids.clone();
+            self.parts = search::parts(&mesh);
+            self.face_groups = face_groups(&mesh);
+            self.mesh = Some(mesh);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Shows or hides the vertex normals hedgehog
+    pub fn set_show_normals(&mut self, device: &wgpu::Device,
+                            swapchain_format: wgpu::TextureFormat,
+                            show: bool) {
+        self.show_normals = show;
+        if show && self.normals.is_none() {
+            if let Some(mesh) = &self.mesh {
+                self.normals = Some(Lines::new(device, swapchain_format,
+                                               &vis::normals(mesh, self.theme.theme())));
+            }
+        }
+    }
+
+    /// Shows or hides the curves of the file's 2D annotations
+    pub fn set_show_draughting(&mut self, device: &wgpu::Device,
+                               swapchain_format: wgpu::TextureFormat,
+                               show: bool) {
+        self.show_draughting = show;
+        if show && self.draughting.is_none() {
+            if let Some(mesh) = &self.mesh {
+                self.draughting = Some(Lines::new(device, swapchain_format,
+                                                  &vis::annotations(mesh, self.theme.theme())));
+            }
+        }
+    }
+
+    /// Switches between normal shading and zebra-stripe reflections
+    pub fn set_zebra(&mut self, zebra: bool) {
+        self.zebra = zebra;
+        if let Some(model) = &mut self.model {
+            model.zebra = zebra;
+        }
+    }
+
+    /// Lights the model with an environment, or with the headlight if `env`
+    /// is `None`.  The environment's maps are uploaded if there's a GPU,
+    /// which there isn't when rendering with the CPU rasterizer.
+    pub fn set_environment(&mut self, gpu: Option<(&wgpu::Device, &wgpu::Queue)>,
+                           env: Option<Arc<Environment>>) {
+        self.gpu_env = gpu.zip(env.as_ref()).map(|((d, q), e)| e.upload(d, q));
+        self.environment = env;
+        if let Some((device, _)) = gpu {
+            for m in self.model.iter_mut().chain(self.caps.iter_mut()) {
+                m.set_environment(device, self.gpu_env.as_ref());
+            }
+        }
+    }
+
+    /// Switches between plain and metallic shading
+    pub fn set_metallic(&mut self, metallic: bool) {
+        self.metallic = metallic;
+        for m in self.model.iter_mut().chain(self.caps.iter_mut()) {
+            m.metallic = metallic;
+        }
+    }
+
+    /// Rebuilds the GPU model and overlays with a different color theme
+    pub fn set_theme(&mut self, device: &wgpu::Device,
+                     swapchain_format: wgpu::TextureFormat,
+                     theme: ThemeKind) {
+        self.theme = theme;
+        self.rebuild_drawables(device, swapchain_format);
+    }
+
+    /// Recreates every GPU resource, e.g. on a new device after the old one
+    /// was lost
+    pub fn rebuild_gpu(&mut self, device: &wgpu::Device, queue: &wgpu::Queue,
+                       swapchain_format: wgpu::TextureFormat) {
+        self.gpu_env = self.environment.as_ref().map(|e| e.upload(device, queue));
+        self.rebuild_drawables(device, swapchain_format);
+    }
+
+    /// Rebuilds the model and every overlay, which all depend on the theme
+    fn rebuild_drawables(&mut self, device: &wgpu::Device,
+                         swapchain_format: wgpu::TextureFormat) {
+        self.normals = None;
+        self.draughting = None;
+        self.rebuild_model(device, swapchain_format);
+        self.rebuild_leaders(device, swapchain_format);
+        self.rebuild_clip(device, swapchain_format);
+        self.set_show_normals(device, swapchain_format, self.show_normals);
+        self.set_show_draughting(device, swapchain_format, self.show_draughting);
+    }
+
+    /// Rebuilds the GPU model with a different color mode
+    pub fn set_color_mode(&mut self, device: &wgpu::Device,
+                          swapchain_format: wgpu::TextureFormat,
+                          mode: ColorMode) {
+        self.color_mode = mode;
+        self.rebuild_model(device, swapchain_format);
+    }
+
+    /// Rebuilds the GPU model from the CPU-side mesh, applying the current
+    /// color mode and selection highlight
+    fn rebuild_model(&mut self, device: &wgpu::Device,
+                     swapchain_format: wgpu::TextureFormat) {
+        if let Some(mesh) = &self.mesh {
+            let theme = self.theme.theme();
+            let (mut verts, mut tris) = vis::colorize(mesh, self.color_mode, theme,
+                                                      &self.part_colors, &self.options.draft);
+            vis::highlight(mesh, &mut verts, &tris, &self.selection, theme);
+            vis::hide(mesh, &mut tris, &self.hidden);
+            let (verts, tris, batches) = vis::instance(mesh, &verts, &tris);
+            let mut model = Model::new(device, swapchain_format,
+                                       &verts, &tris, &batches, self.gpu_env.as_ref(),
+                                       self.options.quantize);
+            model.zebra = self.zebra;
+            model.metallic = self.metallic;
+            model.clip = self.clip.map(|c| c.bounds());
+            self.model = Some(model);
+            // Caps take their colors from the solids, which may have changed
+            self.rebuild_caps(device, swapchain_format);
+        }
+    }
+
+    /// Prepares a path-traced render of the current view, in the current
+    /// colors and within the clip box.  This takes a while, so the returned
+    /// closure (which renders the image) is meant to be run on another
+    /// thread.  Returns `None` if there's no model.
+    pub fn path_tracer(&self, samples: usize)
+        -> Option<impl FnOnce() -> (PhysicalSize<u32>, Vec<u8>) + Send>
+    {
+        let mesh = self.mesh.as_ref()?;
+        let theme = self.theme.theme();
+        let (verts, mut triangles) = vis::colorize(mesh, self.color_mode, theme,
+                                                   &self.part_colors, &self.options.draft);
+        vis::hide(mesh, &mut triangles, &self.hidden);
+        let mesh = Mesh { verts, triangles, ..Default::default() };
+
+        // Light from above and to the left of the viewer, in model space
+        let camera = self.camera.clone();
+        let at = |x, y| -> DVec3 { glm::convert(camera.mouse_pos(Vec2::new(x, y))) };
+        let (right, up) = ((at(1.0, 0.0) - at(0.0, 0.0)).normalize(),
+                           (at(0.0, 1.0) - at(0.0, 0.0)).normalize());
+        let center = Vec2::new(self.size.width as f32, self.size.height as f32) / 2.0;
+        let toward: DVec3 = -glm::convert::<_, DVec3>(camera.ray(center).1).normalize();
+
+        let size = self.size;
+        let params = RenderParams {
+            width: size.width as usize,
+            height: size.height as usize,
+            samples,
+            light: toward + up - right * 0.5,
+            background: glm::convert(theme.backdrop[1]),
+            clip: self.clip.map(|c| Aabb { min: c.min, max: c.max }),
+            ..Default::default()
+        };
+        Some(move || {
+            let pixels = render(&mesh, &params, |x, y| {
+                let (origin, dir) = camera.ray(Vec2::new(x as f32, y as f32));
+                (glm::convert(origin), glm::convert(dir))
+            });
+            (size, pixels)
+        })
+    }
+
+    /// Draws the model with the CPU rasterizer, as a fallback when there's
+    /// no GPU available
+    pub fn rasterize(&self, canvas: &mut Canvas) {
+        if let Some(mesh) = &self.mesh {
+            let theme = self.theme.theme();
+            let (mut verts, mut tris) = vis::colorize(mesh, self.color_mode, theme,
+                                                      &self.part_colors, &self.options.draft);
+            vis::highlight(mesh, &mut verts, &tris, &self.selection, theme);
+            vis::hide(mesh, &mut tris, &self.hidden);
+            canvas.set_environment(self.environment.clone().map(|e| (e, self.metallic)));
+            canvas.draw(&verts, &tris, &self.camera,
+                        (self.x, self.size.width, self.size.height),
+                        if self.zebra { Shading::Zebra } else { Shading::Lit },
+                        self.clip.map(|c| c.bounds()));
+            if let Some((verts, tris)) = self.cap_mesh() {
+                canvas.draw(&verts, &tris, &self.camera,
+                            (self.x, self.size.width, self.size.height),
+                            if self.hatch { Shading::Hatched } else { Shading::Lit },
+                            self.clip.map(|c| c.bounds()));
+            }
+        }
+    }
+
+    pub fn draw(&self, queue: &wgpu::Queue,
+                view: &wgpu::TextureView,
+                depth_view: &wgpu::TextureView,
+                encoder: &mut wgpu::CommandEncoder)
+    {
+        if let Some(model) = &self.model {
+            let rect = (self.x as f32, 0.0,
+                        self.size.width as f32, self.size.height as f32);
+            model.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            if let Some(caps) = &self.caps {
+                caps.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            }
+            if let Some(normals) = self.normals.as_ref().filter(|_| self.show_normals) {
+                normals.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            }
+            if let Some(d) = self.draughting.as_ref().filter(|_| self.show_draughting) {
+                d.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            }
+            if let Some(leaders) = &self.leaders {
+                leaders.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            }
+            if let Some(lines) = self.clip_lines.as_ref().filter(|_| self.show_clip_box) {
+                lines.draw(&self.camera, queue, view, depth_view, rect, encoder);
+            }
+        }
+    }
+}
+
+/// Returns the distinct vertex positions used by ranges of triangles
+fn part_points<I>(mesh: &Mesh, ranges: I) -> Vec<DVec3>
+    where I: Iterator<Item=std::ops::Range<usize>>
+{
+    let mut verts: Vec<u32> = ranges
+        .flat_map(|r| mesh.triangles[r].iter())
+        .flat_map(|t| t.verts.iter().copied().collect::<Vec<_>>())
+        .collect();
+    verts.sort_unstable();
+    verts.dedup();
+    verts.iter().map(|&i| mesh.verts[i as usize].pos).collect()
+}
+
+/// Describes the bounding boxes of a set of points for the HUD, returning
+/// the text and the axis-aligned box
+fn describe_bounds(label: &str, pts: &[DVec3]) -> (String, Aabb) {
+    let mut aabb = Aabb::empty();
+    for p in pts {
+        aabb.expand(*p);
+    }
+    let a = aabb.max - aabb.min;
+    let mut out = format!("{}\nAABB: {:.4} x {:.4} x {:.4}", label, a.x, a.y, a.z);
+    if let Some(obb) = Obb::from_points(pts) {
+        let o = obb.size();
+        out += &format!("\nOBB:  {:.4} x {:.4} x {:.4}", o.x, o.y, o.z);
+    }
+    (out, aabb)
+}
+
+/// Describes the material and mass properties of a set of solids for the
+/// HUD.  Mass properties are shown when every solid has a density (from its
+/// material or the `fallback`) and the model's units are known.  Each line
+/// starts with a newline, so the result can be appended to
+/// [`describe_bounds`].
+fn describe_material(mesh: &Mesh, solids: &[usize], fallback: Option<f64>)
+    -> Option<String>
+{
+    let mut out = String::new();
+    if let Some(material) = solids.iter()
+        .find_map(|&s| mesh.solids[s].material.as_ref())
+    {
+        if let Some(name) = &material.name {
+            out += &format!("\nMaterial: {}", name);
+        }
+        if let Some(finish) = &material.finish {
+            out += &format!("\nFinish: {}", finish);
+        }
+    }
+
+    let densities: Option<Vec<f64>> = solids.iter()
+        .map(|&s| mesh.solids[s].material.as_ref()
+            .and_then(|m| m.density)
+            .or(fallback))
+        .collect();
+    let mass = densities.zip(mesh.length_unit).and_then(|(d, unit)|
+        mass_properties(mesh, solids.iter().zip(d.iter().copied())
+            .map(|(&s, d)| (mesh.solids[s].triangles.clone(), d)), unit)
+            .map(|p| (p, d)));
+    if let Some((p, d)) = mass {
+        if d.windows(2).all(|w| w[0] == w[1]) {
+            out += &format!("\nDensity: {:.1} kg/m³", d[0]);
+        }
+        let (c, m) = (p.center, p.principal_moments());
+        out += &format!("\nMass: {:.4} kg\nCenter of mass: {:.4}, {:.4}, {:.4}\n\
+                         Principal inertia: {:.3e}, {:.3e}, {:.3e} kg·m²",
+                        p.mass, c.x, c.y, c.z, m.x, m.y, m.z);
+    }
+    Some(out).filter(|o| !o.is_empty())
+}
+
+/// Formats a size in bytes with a binary unit prefix
+fn format_bytes(n: usize) -> String {
+    let mut v = n as f64;
+    for unit in ["B", "KiB", "MiB"] {
+        if v < 1024.0 {
+            return format!("{:.1} {}", v, unit);
+        }
+        v /= 1024.0;
+    }
+    format!("{:.1} GiB", v)
+}
+
+/// Formats a face's analytic dimensions for display.  Radii are shown along
+/// with diameters, since hole sizes are usually specified by diameter.
+pub fn describe_dimensions(d: &Dimensions) -> String {
+    match d {
+        Dimensions::Cylinder { radius } | Dimensions::Sphere { radius } =>
+            format!("Radius: {:.4} (diameter {:.4})", radius, radius * 2.0),
+        Dimensions::Cone { radius, half_angle } =>
+            format!("Radius: {:.4} (diameter {:.4})\nHalf-angle: {:.3} deg",
+                    radius, radius * 2.0, half_angle.to_degrees()),
+        Dimensions::Torus { major_radius, minor_radius } =>
+            format!("Major radius: {:.4}\nMinor radius: {:.4}",
+                    major_radius, minor_radius),
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use triangulate::{
+    curvature::principal_curvatures,
+    diff::deviation_color,
+    holes::find_holes,
+    thickness::wall_thickness,
+    mesh::{Mesh, SurfaceType, Triangle, Vertex},
+};
+use crate::{lines::Segment, model::Batch, theme::Theme};
+
+/// Debug visualizations, which replace the model's colors
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// Colors from the STEP file
+    #[default]
+    Shaded,
+    /// Triangle quality, from blue (equilateral) to red (slivers)
+    Quality,
+    /// A distinct color for each B-rep face
+    Face,
+    /// Color by underlying surface type
+    Surface,
+    /// Per-face UV parameters, in the red and green channels
+    Uv,
+    /// Gaussian curvature, from blue (saddles) to red (domes)
+    GaussianCurvature,
+    /// Mean curvature, from blue (concave) to red (convex)
+    MeanCurvature,
+    /// Draft angle relative to the pull direction in [`Draft`]
+    Draft,
+    /// Wall thickness, from red (thin) to blue (thick)
+    Thickness,
+    /// A distinct color for each round hole
+    Holes,
+}
+
+impl ColorMode {
+    /// Returns the next mode, for cycling through them with a hotkey
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Shaded => ColorMode::Quality,
+            ColorMode::Quality => ColorMode::Face,
+            ColorMode::Face => ColorMode::Surface,
+            ColorMode::Surface => ColorMode::Uv,
+            ColorMode::Uv => ColorMode::GaussianCurvature,
+            ColorMode::GaussianCurvature => ColorMode::MeanCurvature,
+            ColorMode::MeanCurvature => ColorMode::Draft,
+            ColorMode::Draft => ColorMode::Thickness,
+            ColorMode::Thickness => ColorMode::Holes,
+            ColorMode::Holes => ColorMode::Shaded,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Shaded => "shaded",
+            ColorMode::Quality => "triangle quality",
+            ColorMode::Face => "face",
+            ColorMode::Surface => "surface type",
+            ColorMode::Uv => "UV parameters",
+            ColorMode::GaussianCurvature => "Gaussian curvature",
+            ColorMode::MeanCurvature => "mean curvature",
+            ColorMode::Draft => "draft angle",
+            ColorMode::Thickness => "wall thickness",
+            ColorMode::Holes => "holes",
+        }
+    }
+}
+
+/// Settings for the draft angle color mode
+#[derive(Copy, Clone, Debug)]
+pub struct Draft {
+    /// Direction in which the part is pulled from the mold
+    pub pull: DVec3,
+    /// Minimum draft angle, in radians
+    pub limit: f64,
+}
+
+impl Default for Draft {
+    fn default() -> Self {
+        Self { pull: DVec3::z(), limit: 1f64.to_radians() }
+    }
+}
+
+/// Returns the angle between a surface and the pull direction, which is
+/// positive when the surface faces along the pull direction (so it releases
+/// cleanly) and negative when it faces away
+pub fn draft_angle(norm: DVec3, pull: DVec3) -> f64 {
+    (norm.normalize().dot(&pull.normalize())).clamp(-1.0, 1.0).asin()
+}
+
+/// Colors a draft angle: green for positive draft, red for negative draft,
+/// and yellow for walls closer to the pull direction than the limit
+fn draft_color(angle: f64, limit: f64) -> DVec3 {
+    if angle >= limit {
+        DVec3::new(0.2, 0.8, 0.2)
+    } else if angle <= -limit {
+        DVec3::new(0.9, 0.2, 0.2)
+    } else {
+        DVec3::new(0.95, 0.85, 0.2)
+    }
+}
+
+/// Returns the ratio of inradius to circumradius, scaled so that an
+/// equilateral triangle scores 1 and a degenerate triangle scores 0.
+pub fn triangle_quality(a: DVec3, b: DVec3, c: DVec3) -> f64 {
+    let la = (b - c).norm();
+    let lb = (c - a).norm();
+    let lc = (a - b).norm();
+    let s = (la + lb + lc) / 2.0;
+    let q = (s - la) * (s - lb) * (s - lc) * 8.0 / (la * lb * lc);
+    if q.is_finite() { q.clamp(0.0, 1.0) } else { 0.0 }
+}
+
+/// Picks a well-spaced color for the `i`'th item, by stepping the hue by the
+/// golden ratio.
+pub fn palette(i: usize) -> DVec3 {
+    let h = (i as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Desaturate slightly, so that lighting is still legible
+    DVec3::new(r, g, b) * 0.7 + DVec3::repeat(0.2)
+}
+
+fn surface_color(s: SurfaceType) -> DVec3 {
+    match s {
+        SurfaceType::Plane => DVec3::new(0.6, 0.6, 0.6),
+        SurfaceType::Cylinder => DVec3::new(0.2, 0.4, 0.9),
+        SurfaceType::Cone => DVec3::new(0.2, 0.8, 0.8),
+        SurfaceType::Sphere => DVec3::new(0.9, 0.8, 0.2),
+        SurfaceType::Torus => DVec3::new(0.9, 0.5, 0.1),
+        SurfaceType::BSpline => DVec3::new(0.8, 0.2, 0.8),
+        SurfaceType::NURBS => DVec3::new(0.9, 0.2, 0.3),
+    }
+}
+
+/// Colors each vertex by a signed value on a blue-green-red ramp, centered
+/// on zero.  The ramp saturates at the 95th percentile of magnitudes, so that
+/// a few sharp fillets don't wash out the rest of the model.
+fn color_signed(verts: &mut [Vertex], values: &[f64]) {
+    let mut mags: Vec<f64> = values.iter().map(|v| v.abs()).collect();
+    mags.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let scale = mags.get(mags.len() * 95 / 100).copied()
+        .filter(|s| *s > 0.0)
+        .unwrap_or(1.0);
+    for (v, k) in verts.iter_mut().zip(values) {
+        v.color = deviation_color(0.5 + 0.5 * k / scale);
+    }
+}
+
+/// Number of colors which [`Viewport::cycle_part_color`] steps through
+///
+/// [`Viewport::cycle_part_color`]: crate::viewport::Viewport::cycle_part_color
+pub const PART_COLORS: usize = 8;
+
+/// Returns the color of each solid in shaded mode, which is the user's
+/// choice (from `part_colors`, keyed by STEP entity id), then the STEP
+/// file's style, then the theme's part color.  If the whole file is
+/// unstyled and has several parts to tell apart, they're colored from the
+/// [`palette`] instead, with instances sharing the color of their original
+/// solid.
+pub fn solid_colors(mesh: &Mesh, theme: &Theme,
+                    part_colors: &BTreeMap<usize, DVec3>) -> Vec<DVec3> {
+    let auto = mesh.solids.iter().all(|s| s.color.is_none()) &&
+        mesh.solids.iter().filter(|s| s.instance_of.is_none()).count() > 1;
+    mesh.solids.iter().enumerate()
+        .map(|(i, s)| match (part_colors.get(&s.id), s.color) {
+            (Some(c), _) => *c,
+            (None, Some(c)) => c,
+            (None, None) if auto => palette(s.instance_of.unwrap_or(i)),
+            (None, None) => theme.part,
+        })
+        .collect()
+}
+
+/// Builds vertex and triangle arrays which show the mesh in the given mode.
+/// In shaded mode, solids are colored by [`solid_colors`], except that
+/// styled solids which are missing from `part_colors` keep the per-face
+/// colors from the file.
+pub fn colorize(mesh: &Mesh, mode: ColorMode, theme: &Theme,
+                part_colors: &BTreeMap<usize, DVec3>, draft: &Draft)
+    -> (Vec<Vertex>, Vec<Triangle>)
+{
+    let mut verts = mesh.verts.clone();
+    let mut recolor = |f: &dyn Fn(usize) -> DVec3| {
+        for (i, face) in mesh.faces.iter().enumerate() {
+            let c = f(i);
+            for t in &mesh.triangles[face.triangles.clone()] {
+                for v in t.verts.iter() {
+                    verts[*v as usize].color = c;
+                }
+            }
+        }
+    };
+    match mode {
+        ColorMode::Shaded => {
+            let colors = solid_colors(mesh, theme, part_colors);
+            for (s, &color) in mesh.solids.iter().zip(&colors) {
+                // Styled solids keep their per-face colors from the file
+                if s.color.is_some() && !part_colors.contains_key(&s.id) {
+                    continue;
+                }
+                for t in &mesh.triangles[s.triangles.clone()] {
+                    for v in t.verts.iter() {
+                        verts[*v as usize].color = color;
+                    }
+                }
+            }
+        },
+        ColorMode::Face => recolor(&palette),
+        ColorMode::Surface => recolor(&|i| surface_color(mesh.faces[i].surface)),
+        ColorMode::Uv => for v in verts.iter_mut() {
+            v.color = DVec3::new(v.uv.x, v.uv.y, 0.25);
+        },
+        ColorMode::GaussianCurvature => {
+            let k: Vec<f64> = principal_curvatures(mesh).iter()
+                .map(|k| k.x * k.y)
+                .collect();
+            color_signed(&mut verts, &k);
+        },
+        ColorMode::MeanCurvature => {
+            let k: Vec<f64> = principal_curvatures(mesh).iter()
+                .map(|k| (k.x + k.y) / 2.0)
+                .collect();
+            color_signed(&mut verts, &k);
+        },
+        ColorMode::Draft => {
+            for v in verts.iter_mut() {
+                v.color = draft_color(draft_angle(v.norm, draft.pull), draft.limit);
+            }
+        },
+        ColorMode::Thickness => {
+            // Like the curvature modes, saturate at the 95th percentile, so
+            // that a few long rays (e.g. across a hollow part) don't hide the
+            // variation in thin walls
+            let t = wall_thickness(mesh);
+            let mut known: Vec<f64> = t.iter().flatten().copied().collect();
+            known.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let scale = known.get(known.len() * 95 / 100).copied()
+                .filter(|s| *s > 0.0)
+                .unwrap_or(1.0);
+            for (v, t) in verts.iter_mut().zip(t) {
+                v.color = match t {
+                    Some(t) => deviation_color(1.0 - t / scale),
+                    None => theme.part,
+                };
+            }
+        },
+        ColorMode::Holes => {
+            let mut colors = vec![theme.part; mesh.faces.len()];
+            for (i, h) in find_holes(mesh).iter().enumerate() {
+                for &f in &h.faces {
+                    colors[f] = palette(i);
+                }
+            }
+            recolor(&|i| colors[i]);
+        },
+        ColorMode::Quality => {
+            // Quality is per-triangle, so we unshare every vertex to give each
+            // triangle a flat color.
+            let mut out = Vec::with_capacity(mesh.triangles.len() * 3);
+            for t in &mesh.triangles {
+                let abc = [mesh.verts[t.verts.x as usize],
+                           mesh.verts[t.verts.y as usize],
+                           mesh.verts[t.verts.z as usize]];
+                let q = triangle_quality(abc[0].pos, abc[1].pos, abc[2].pos);
+                let color = deviation_color(1.0 - q);
+                out.extend(abc.iter().map(|v| Vertex { color, ..*v }));
+            }
+            let tris = (0..mesh.triangles.len() as u32)
+                .map(|i| Triangle { verts: U32Vec3::new(3 * i, 3 * i + 1, 3 * i + 2) })
+                .collect();
+            return (out, tris);
+        },
+    }
+    // Placeholders for faces which failed to triangulate stand out in every
+    // mode, since their colors wouldn't mean anything
+    for face in mesh.faces.iter().filter(|f| f.failed) {
+        for t in &mesh.triangles[face.triangles.clone()] {
+            for v in t.verts.iter() {
+                verts[*v as usize].color = theme.failed;
+            }
+        }
+    }
+    (verts, mesh.triangles.clone())
+}
+
+/// Tints the selected faces (given as indices into `mesh.faces`), modifying
+/// vertex and triangle arrays built by [`colorize`].  Vertices shared with
+/// unselected faces are tinted as well.
+pub fn highlight<'a, I>(mesh: &Mesh, verts: &mut [Vertex], tris: &[Triangle],
+                        faces: I, theme: &Theme)
+    where I: IntoIterator<Item=&'a usize>
+{
+    let tint = theme.highlight;
+    let mut done = vec![false; verts.len()];
+    for &f in faces {
+        for t in &tris[mesh.faces[f].triangles.clone()] {
+            for v in t.verts.iter() {
+                let v = *v as usize;
+                if !done[v] {
+                    verts[v].color = verts[v].color * 0.3 + tint * 0.7;
+                    done[v] = true;
+                }
+            }
+        }
+    }
+}
+
+/// Collapses the triangles of hidden solids (given by their STEP entity
+/// ids) into points, modifying a triangle array built by [`colorize`].  The
+/// triangles stay in place, so solids' triangle ranges still apply.
+pub fn hide(mesh: &Mesh, tris: &mut [Triangle], hidden: &BTreeSet<usize>) {
+    for s in mesh.solids.iter().filter(|s| hidden.contains(&s.id)) {
+        for t in &mut tris[s.triangles.clone()] {
+            t.verts = U32Vec3::repeat(t.verts.x);
+        }
+    }
+}
+
+/// Collapses repeated solids into instanced batches, given vertex and
+/// triangle arrays built by [`colorize`] (and perhaps [`highlight`]).
+///
+/// A repeated solid is only drawn as an instance of the original if its
+/// colors match; otherwise (e.g. when one copy is highlighted), it is drawn
+/// separately.  Vertices which aren't used by any remaining triangle are
+/// dropped from the output.
+pub fn instance(mesh: &Mesh, verts: &[Vertex], tris: &[Triangle])
+    -> (Vec<Vertex>, Vec<Triangle>, Vec<Batch>)
+{
+    // Meshes without complete solid information are drawn as a single batch
+    let covered: usize = mesh.solids.iter().map(|s| s.triangles.len()).sum();
+    if covered != tris.len() {
+        let all = Batch {
+            triangles: 0..tris.len(),
+            transforms: vec![glm::Mat4::identity()],
+        };
+        return (verts.to_vec(), tris.to_vec(), vec![all]);
+    }
+
+    let same_colors = |a: &std::ops::Range<usize>, b: &std::ops::Range<usize>| {
+        tris[a.clone()].iter().zip(&tris[b.clone()]).all(|(ta, tb)| {
+            ta.verts.iter().zip(tb.verts.iter())
+                .all(|(i, j)| verts[*i as usize].color == verts[*j as usize].color)
+        })
+    };
+
+    // Pick out the transforms for each batch, keyed by the index of the
+    // solid which provides the triangles
+    let mut transforms: Vec<Vec<glm::Mat4>> = vec![Vec::new(); mesh.solids.len()];
+    for (i, s) in mesh.solids.iter().enumerate() {
+        match s.instance_of {
+            Some(j) if same_colors(&mesh.solids[j].triangles, &s.triangles) =>
+                transforms[j].push(glm::convert(s.transform)),
+            _ => transforms[i].push(glm::Mat4::identity()),
+        }
+    }
+
+    let mut out_verts = Vec::new();
+    let mut out_tris = Vec::new();
+    let mut remap = vec![u32::MAX; verts.len()];
+    let mut push = |range: std::ops::Range<usize>,
+                    out_verts: &mut Vec<Vertex>,
+                    out_tris: &mut Vec<Triangle>| {
+        let start = out_tris.len();
+        for t in &tris[range] {
+            let mut t = *t;
+            for v in t.verts.iter_mut() {
+                let r = &mut remap[*v as usize];
+                if *r == u32::MAX {
+                    *r = out_verts.len() as u32;
+                    out_verts.push(verts[*v as usize]);
+                }
+                *v = *r;
+            }
+            out_tris.push(t);
+        }
+        start..out_tris.len()
+    };
+
+    let mut batches = Vec::new();
+    for (s, transforms) in mesh.solids.iter().zip(transforms) {
+        if !transforms.is_empty() {
+            let triangles = push(s.triangles.clone(), &mut out_verts, &mut out_tris);
+            batches.push(Batch { triangles, transforms });
+        }
+    }
+    (out_verts, out_tris, batches)
+}
+
+/// Builds segments along the curves of the file's 2D annotations, drawn in
+/// the theme's edge color
+pub fn annotations(mesh: &Mesh, theme: &Theme) -> Vec<Segment> {
+    mesh.annotations.iter()
+        .flat_map(|c| c.windows(2))
+        .map(|w| Segment {
+            start: glm::convert(w[0]),
+            end: glm::convert(w[1]),
+            start_color: theme.edge,
+            end_color: theme.edge,
+        })
+        .collect()
+}
+
+/// Builds a "hedgehog" of short segments along each vertex normal, which
+/// fade from the theme's edge color at the surface to red at the tip.
+/// Segment length is a small fraction of the model's size.
+pub fn normals(mesh: &Mesh, theme: &Theme) -> Vec<Segment> {
+    let mut lo = DVec3::repeat(f64::INFINITY);
+    let mut hi = DVec3::repeat(-f64::INFINITY);
+    for v in &mesh.verts {
+        lo = lo.inf(&v.pos);
+        hi = hi.sup(&v.pos);
+    }
+    let len = (hi - lo).norm() * 0.02;
+    mesh.verts.iter()
+        .filter(|v| v.norm.norm_squared() > 0.0)
+        .map(|v| Segment {
+            start: glm::convert(v.pos),
+            end: glm::convert(v.pos + v.norm.normalize() * len),
+            start_color: theme.edge,
+            end_color: Vec3::new(1.0, 0.0, 0.0),
+        })
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glm::DVec2;
+    use triangulate::mesh::Solid;
+    use crate::theme::ThemeKind;
+
+    #[test]
+    fn hide_solids() {
+        // Two solids, where the second is a copy of the first (and so
+        // shares its entity id)
+        let mut mesh = Mesh::default();
+        for x in [0.0, 2.0] {
+            for p in [DVec3::new(x, 0.0, 0.0), DVec3::new(x + 1.0, 0.0, 0.0),
+                      DVec3::new(x, 1.0, 0.0)] {
+                mesh.verts.push(Vertex {
+                    pos: p, norm: DVec3::z(), color: DVec3::zeros(), uv: DVec2::zeros(),
+                });
+            }
+        }
+        mesh.triangles.push(Triangle { verts: U32Vec3::new(0, 1, 2) });
+        mesh.triangles.push(Triangle { verts: U32Vec3::new(3, 4, 5) });
+        for (i, id) in [(0, 7), (1, 7)] {
+            mesh.solids.push(Solid {
+                id, name: None, color: None, material: None, triangles: i..i + 1,
+                instance_of: None, transform: glm::DMat4::identity(),
+            });
+        }
+
+        let mut tris = mesh.triangles.clone();
+        let verts = |tris: &[Triangle]| tris.iter().map(|t| t.verts).collect::<Vec<_>>();
+        hide(&mesh, &mut tris, &BTreeSet::new());
+        assert_eq!(verts(&tris), verts(&mesh.triangles));
+        hide(&mesh, &mut tris, &BTreeSet::from([7]));
+        assert_eq!(verts(&tris), vec![U32Vec3::repeat(0), U32Vec3::repeat(3)]);
+    }
+
+    #[test]
+    fn draft() {
+        // One vertex facing along each of the pull direction, sideways
+        // (with half a degree of draft), and against the pull direction
+        let mut mesh = Mesh::default();
+        let tilt = 0.5f64.to_radians();
+        for norm in [DVec3::z(), DVec3::new(tilt.cos(), 0.0, tilt.sin()), -DVec3::z()] {
+            mesh.verts.push(Vertex {
+                pos: DVec3::zeros(), norm, color: DVec3::zeros(), uv: DVec2::zeros(),
+            });
+        }
+        mesh.triangles.push(Triangle { verts: U32Vec3::new(0, 1, 2) });
+        let theme = ThemeKind::default().theme();
+        let colors = |draft: Draft| -> Vec<DVec3> {
+            colorize(&mesh, ColorMode::Draft, theme, &BTreeMap::new(), &draft).0
+                .iter().map(|v| v.color).collect()
+        };
+        let (good, bad, low) = (draft_color(1.0, 0.1), draft_color(-1.0, 0.1),
+                                draft_color(0.0, 0.1));
+
+        assert_eq!(colors(Draft::default()), vec![good, low, bad]);
+        // With a looser limit, the sideways wall has enough draft
+        let loose = Draft { pull: DVec3::z(), limit: 0.25f64.to_radians() };
+        assert_eq!(colors(loose), vec![good, good, bad]);
+        // Pulling sideways instead makes the top and bottom vertical walls
+        let sideways = Draft { pull: DVec3::x(), limit: 1f64.to_radians() };
+        assert_eq!(colors(sideways), vec![low, good, low]);
+
+        assert!((draft_angle(DVec3::new(1.0, 0.0, 1.0), DVec3::z()).to_degrees() - 45.0)
+                .abs() < 1e-9);
+    }
+}
@@ -0,0 +1,154 @@
+/// Nodes and weights for 5-point Gauss-Legendre quadrature on [-1, 1]
+const GAUSS: [(f64, f64); 5] = [
+    (0.0, 0.568_888_888_888_888_9),
+    (-0.538_469_310_105_683_1, 0.478_628_670_499_366_5),
+    (0.538_469_310_105_683_1, 0.478_628_670_499_366_5),
+    (-0.906_179_845_938_664, 0.236_926_885_056_189_1),
+    (0.906_179_845_938_664, 0.236_926_885_056_189_1),
+];
+
+/// Number of quadrature intervals between each pair of break points
+const SUBDIVISIONS: usize = 8;
+
+/// Table of cumulative arc length along a parametric curve, used to convert
+/// between parameters and distances along the curve.
+///
+/// The curve is described by its speed (the length of its first derivative)
+/// as a function of the parameter, which is integrated piecewise between
+/// break points (e.g. knots, where the derivative may not be smooth).
+/// Within each quadrature interval, speed is treated as varying linearly.
+#[derive(Debug, Clone)]
+pub struct ArcLength {
+    /// Parameters at the ends of each quadrature interval, ascending
+    params: Vec<f64>,
+    /// Arc length from the first parameter to each entry in `params`
+    lengths: Vec<f64>,
+    /// Speed at the start and end of each quadrature interval
+    speeds: Vec<(f64, f64)>,
+}
+
+impl ArcLength {
+    /// Builds a table for the curve between `u_start` and `u_end`, where
+    /// `breaks` are additional parameters at which the speed may jump
+    pub fn new<F>(speed: F, u_start: f64, u_end: f64, breaks: &[f64]) -> Self
+        where F: Fn(f64) -> f64
+    {
+        let (lo, hi) = if u_start <= u_end {
+            (u_start, u_end)
+        } else {
+            (u_end, u_start)
+        };
+        let mut stops = vec![lo];
+        stops.extend(breaks.iter().copied().filter(|&b| b > lo && b < hi));
+        stops.push(hi);
+        stops.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        stops.dedup();
+
+        let mut params = vec![lo];
+        let mut lengths = vec![0.0];
+        let mut speeds = Vec::new();
+        for w in stops.windows(2) {
+            for i in 0..SUBDIVISIONS {
+                let a = w[0] + (w[1] - w[0]) * (i as f64 / SUBDIVISIONS as f64);
+                let b = w[0] + (w[1] - w[0]) * ((i + 1) as f64 / SUBDIVISIONS as f64);
+                let (mid, half) = ((a + b) / 2.0, (b - a) / 2.0);
+                let d: f64 = GAUSS.iter()
+                    .map(|(x, w)| w * speed(mid + half * x))
+                    .sum();
+                params.push(b);
+                lengths.push(lengths.last().unwrap() + d * half);
+                speeds.push((speed(a), speed(b)));
+            }
+        }
+        Self { params, lengths, speeds }
+    }
+
+    /// Returns the total length of the curve
+    pub fn total(&self) -> f64 {
+        *self.lengths.last().unwrap()
+    }
+
+    /// Returns the arc length from the start of the table to a parameter
+    pub fn length_at(&self, u: f64) -> f64 {
+        let i = Self::interval(&self.params, u);
+        let (u0, u1) = (self.params[i], self.params[i + 1]);
+        let t = ((u - u0) / (u1 - u0).max(f64::EPSILON)).clamp(0.0, 1.0);
+        let (v0, v1) = self.speeds[i];
+        // Fraction of the interval's length covered by t, with linear speed
+        let f = if v0 + v1 > 0.0 {
+            (v0 * t + (v1 - v0) * t * t / 2.0) / ((v0 + v1) / 2.0)
+        } else {
+            t
+        };
+        self.lengths[i] + (self.lengths[i + 1] - self.lengths[i]) * f
+    }
+
+    /// Returns the parameter at an arc length from the start of the table
+    pub fn param_at(&self, s: f64) -> f64 {
+        let i = Self::interval(&self.lengths, s);
+        let (u0, u1) = (self.params[i], self.params[i + 1]);
+        let (s0, s1) = (self.lengths[i], self.lengths[i + 1]);
+        let f = ((s - s0) / (s1 - s0).max(f64::EPSILON)).clamp(0.0, 1.0);
+        // Invert `length_at` by solving (v1 - v0) t² / 2 + v0 t = c
+        let (v0, v1) = self.speeds[i];
+        let c = f * (v0 + v1) / 2.0;
+        let d = v0 + (v0 * v0 + 2.0 * (v1 - v0) * c).max(0.0).sqrt();
+        let t = if d > f64::EPSILON { (2.0 * c / d).clamp(0.0, 1.0) } else { f };
+        u0 + (u1 - u0) * t
+    }
+
+    /// Returns `n + 1` parameters, evenly spaced in arc length from the
+    /// table's start to its end
+    pub fn uniform_params(&self, n: usize) -> Vec<f64> {
+        let total = self.total();
+        let n = n.max(1);
+        let mut out: Vec<f64> = (0..=n)
+            .map(|i| self.param_at(total * (i as f64 / n as f64)))
+            .collect();
+        out[0] = self.params[0];
+        out[n] = *self.params.last().unwrap();
+        out
+    }
+
+    /// Finds the interval in a sorted list which contains `x`
+    fn interval(list: &[f64], x: f64) -> usize {
+        let i = list.partition_point(|&v| v <= x);
+        i.saturating_sub(1).min(list.len() - 2)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle() {
+        // A circle of radius 2, parameterized by angle, has constant speed
+        let a = ArcLength::new(|_| 2.0, 0.0, std::f64::consts::PI, &[1.0]);
+        assert!((a.total() - 2.0 * std::f64::consts::PI).abs() < 1e-12);
+        assert!((a.length_at(1.0) - 2.0).abs() < 1e-12);
+        assert!((a.param_at(3.0) - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parabola() {
+        // y = x², parameterized by x, from 0 to 1
+        let speed = |u: f64| (1.0 + 4.0 * u * u).sqrt();
+        let a = ArcLength::new(speed, 0.0, 1.0, &[]);
+        let exact = (2.0 * 5f64.sqrt() + (2.0 + 5f64.sqrt()).ln()) / 4.0;
+        assert!((a.total() - exact).abs() < 1e-9);
+
+        // The parameters which it picks are evenly spaced along the curve,
+        // to within a small fraction of their spacing
+        let params = a.uniform_params(10);
+        assert_eq!(params.len(), 11);
+        let step = exact / 10.0;
+        for (i, u) in params.iter().enumerate() {
+            let s = ArcLength::new(speed, 0.0, *u, &[]).total();
+            assert!((s - step * i as f64).abs() < step * 1e-3, "{} at {}", s, i);
+            assert!((a.length_at(*u) - s).abs() < step * 1e-3);
+        }
+    }
+}
@@ -3,6 +3,7 @@
 // single-character names everywhere, so we're matching their convention.
 
 mod abstract_curve;
+mod arc_length;
 mod abstract_surface;
 mod bspline_curve;
 mod bspline_surface;
@@ -18,6 +19,7 @@ use smallvec::{SmallVec};
 type VecF = SmallVec<[f64; 8]>;
 
 pub use crate::abstract_curve::AbstractCurve;
+pub use crate::arc_length::ArcLength;
 pub use crate::abstract_surface::AbstractSurface;
 pub use crate::bspline_curve::BSplineCurve;
 pub use crate::bspline_surface::BSplineSurface;
@@ -1,5 +1,5 @@
 use nalgebra_glm::{dot, length, length2, DVec3};
-use crate::{abstract_curve::AbstractCurve, nd_curve::NDBSplineCurve};
+use crate::{abstract_curve::AbstractCurve, arc_length::ArcLength, nd_curve::NDBSplineCurve};
 
 #[derive(Debug)]
 pub struct SampledCurve<const N: usize> {
@@ -80,6 +80,16 @@ impl<const N: usize> SampledCurve<N>
         }
     }
 
+    /// Evaluates the curve at the given parameter
+    pub fn point(&self, u: f64) -> DVec3 {
+        self.curve.point(u)
+    }
+
+    /// Returns the points at the start and end of the curve's parameter range
+    pub fn ends(&self) -> (DVec3, DVec3) {
+        (self.curve.point(self.curve.min_u()), self.curve.point(self.curve.max_u()))
+    }
+
     pub fn u_from_point(&self, p: DVec3) -> f64 {
         use ordered_float::OrderedFloat;
         let best_u = self.samples.iter()
@@ -88,6 +98,50 @@ impl<const N: usize> SampledCurve<N>
         self.u_from_point_newtons_method(p, best_u)
     }
 
+    /// Builds a table of arc length along the curve between two parameters,
+    /// integrating separately over each knot span
+    pub fn arc_length(&self, u_start: f64, u_end: f64) -> ArcLength {
+        let knots: Vec<f64> = (0..self.curve.knots.len())
+            .map(|i| self.curve.knots[i])
+            .collect();
+        ArcLength::new(|u| length(&self.curve.derivs::<1>(u)[1]),
+                       u_start, u_end, &knots)
+    }
+
+    /// Returns the length of the curve between two parameters
+    pub fn length(&self, u_start: f64, u_end: f64) -> f64 {
+        self.arc_length(u_start, u_end).total()
+    }
+
+    /// Samples the curve between two parameters with points which are evenly
+    /// spaced along it.  The number of segments matches [`as_polyline`]:
+    /// `num_points_per_knot` for each knot span overlapping the range.
+    ///
+    /// [`as_polyline`]: Self::as_polyline
+    pub fn as_polyline_by_length(&self, u_start: f64, u_end: f64,
+                                 num_points_per_knot: usize) -> Vec<DVec3> {
+        let (u_min, u_max) = if u_start < u_end {
+            (u_start, u_end)
+        } else {
+            (u_end, u_start)
+        };
+        let spans = (0..self.curve.knots.len() - 1)
+            .filter(|&i| self.curve.knots[i] < self.curve.knots[i + 1]
+                && self.curve.knots[i + 1] > u_min
+                && self.curve.knots[i] < u_max)
+            .count()
+            .max(1);
+        let mut result: Vec<DVec3> = self.arc_length(u_min, u_max)
+            .uniform_params(spans * num_points_per_knot)
+            .into_iter()
+            .map(|u| self.curve.point(u))
+            .collect();
+        if u_start > u_end {
+            result.reverse();
+        }
+        result
+    }
+
     pub fn as_polyline(&self, u_start: f64, u_end: f64, num_points_per_knot: usize) -> Vec<DVec3> {
         let (u_min, u_max) = if u_start < u_end {
             (u_start, u_end)
@@ -1,7 +1,8 @@
 // Autogenerated file, do not hand-edit!
+use std::borrow::Cow;
 use crate::{
     id::{Id, HasId},
-    parse::{IResult, Logical, Derived, Parse, ParseFromChunks, nom_alt_err,
+    parse::{IResult, Logical, Derived, DerivedOrRef, Parse, ParseFromChunks, nom_alt_err,
             parse_enum_tag, param_from_chunks, parse_complex_mapping},
     step_file::FromEntity,
 };
@@ -8295,7 +8296,7 @@ impl<'a> ParseFromChunks<'a> for ConversionBasedUnit_<'a> {
     fn parse_chunks(strs: &[&'a str]) -> IResult<'a, Self> {
         let mut i = 0;
         let (s, _) = tag("CONVERSION_BASED_UNIT(")(strs[0])?;
-        let (s, _) = param_from_chunks::<Derived>(false, s, &mut i, strs)?;
+        let (s, _) = param_from_chunks::<DerivedOrRef>(false, s, &mut i, strs)?;
         let (s, name) = param_from_chunks::<Label<'a>>(false, s, &mut i, strs)?;
         let (s, conversion_factor) = param_from_chunks::<MeasureWithUnit<'a>>(true, s, &mut i, strs)?;
         Ok((s, Self {
@@ -10014,10 +10015,10 @@ pub struct DescriptionAttributeSelect_<'a>(std::marker::PhantomData<&'a ()>); //
 pub type DescriptionAttributeSelect<'a> = Id<DescriptionAttributeSelect_<'a>>;
 
 #[derive(Debug)]
-pub struct DescriptiveMeasure<'a>(pub &'a str, std::marker::PhantomData<&'a ()>); // primitive
+pub struct DescriptiveMeasure<'a>(pub Cow<'a, str>, std::marker::PhantomData<&'a ()>); // primitive
 impl<'a> Parse<'a> for DescriptiveMeasure<'a> {
     fn parse(s: &'a str) -> IResult<'a, Self> {
-        map(<&str>::parse, |r| Self(r, std::marker::PhantomData))(s)
+        map(<Cow<'a, str>>::parse, |r| Self(r, std::marker::PhantomData))(s)
     }
 }
 impl<'a> HasId for DescriptiveMeasure<'a> {
@@ -16380,10 +16381,10 @@ impl<'a> HasId for IdentificationRole_<'a> {
     }
 }
 #[derive(Debug)]
-pub struct Identifier<'a>(pub &'a str, std::marker::PhantomData<&'a ()>); // primitive
+pub struct Identifier<'a>(pub Cow<'a, str>, std::marker::PhantomData<&'a ()>); // primitive
 impl<'a> Parse<'a> for Identifier<'a> {
     fn parse(s: &'a str) -> IResult<'a, Self> {
-        map(<&str>::parse, |r| Self(r, std::marker::PhantomData))(s)
+        map(<Cow<'a, str>>::parse, |r| Self(r, std::marker::PhantomData))(s)
     }
 }
 impl<'a> HasId for Identifier<'a> {
@@ -17652,10 +17653,10 @@ impl<'a> HasId for KnownSource_<'a> {
     }
 }
 #[derive(Debug)]
-pub struct Label<'a>(pub &'a str, std::marker::PhantomData<&'a ()>); // primitive
+pub struct Label<'a>(pub Cow<'a, str>, std::marker::PhantomData<&'a ()>); // primitive
 impl<'a> Parse<'a> for Label<'a> {
     fn parse(s: &'a str) -> IResult<'a, Self> {
-        map(<&str>::parse, |r| Self(r, std::marker::PhantomData))(s)
+        map(<Cow<'a, str>>::parse, |r| Self(r, std::marker::PhantomData))(s)
     }
 }
 impl<'a> HasId for Label<'a> {
@@ -24417,10 +24418,10 @@ impl<'a> HasId for PreferredSurfaceCurveRepresentation<'a> {
 }
 
 #[derive(Debug)]
-pub struct PresentableText<'a>(pub &'a str, std::marker::PhantomData<&'a ()>); // primitive
+pub struct PresentableText<'a>(pub Cow<'a, str>, std::marker::PhantomData<&'a ()>); // primitive
 impl<'a> Parse<'a> for PresentableText<'a> {
     fn parse(s: &'a str) -> IResult<'a, Self> {
-        map(<&str>::parse, |r| Self(r, std::marker::PhantomData))(s)
+        map(<Cow<'a, str>>::parse, |r| Self(r, std::marker::PhantomData))(s)
     }
 }
 impl<'a> HasId for PresentableText<'a> {
@@ -30139,7 +30140,7 @@ impl<'a> ParseFromChunks<'a> for SiUnit_<'a> {
     fn parse_chunks(strs: &[&'a str]) -> IResult<'a, Self> {
         let mut i = 0;
         let (s, _) = tag("SI_UNIT(")(strs[0])?;
-        let (s, _) = param_from_chunks::<Derived>(false, s, &mut i, strs)?;
+        let (s, _) = param_from_chunks::<DerivedOrRef>(false, s, &mut i, strs)?;
         let (s, prefix) = param_from_chunks::<Option<SiPrefix<'a>>>(false, s, &mut i, strs)?;
         let (s, name) = param_from_chunks::<SiUnitName<'a>>(true, s, &mut i, strs)?;
         Ok((s, Self {
@@ -31395,7 +31396,7 @@ impl<'a> HasId for StringExpression_<'a> {
 }
 #[derive(Debug)]
 pub struct StringLiteral_<'a> { // entity
-    pub the_value: &'a str,
+    pub the_value: Cow<'a, str>,
     _marker: std::marker::PhantomData<&'a ()>,
 }
 pub type StringLiteral<'a> = Id<StringLiteral_<'a>>;
@@ -31411,7 +31412,7 @@ impl<'a> ParseFromChunks<'a> for StringLiteral_<'a> {
     fn parse_chunks(strs: &[&'a str]) -> IResult<'a, Self> {
         let mut i = 0;
         let (s, _) = tag("STRING_LITERAL(")(strs[0])?;
-        let (s, the_value) = param_from_chunks::<&'a str>(true, s, &mut i, strs)?;
+        let (s, the_value) = param_from_chunks::<Cow<'a, str>>(true, s, &mut i, strs)?;
         Ok((s, Self {
             the_value,
             _marker: std::marker::PhantomData}))
@@ -33297,10 +33298,10 @@ impl<'a> HasId for TerminatorSymbol_<'a> {
     }
 }
 #[derive(Debug)]
-pub struct Text<'a>(pub &'a str, std::marker::PhantomData<&'a ()>); // primitive
+pub struct Text<'a>(pub Cow<'a, str>, std::marker::PhantomData<&'a ()>); // primitive
 impl<'a> Parse<'a> for Text<'a> {
     fn parse(s: &'a str) -> IResult<'a, Self> {
-        map(<&str>::parse, |r| Self(r, std::marker::PhantomData))(s)
+        map(<Cow<'a, str>>::parse, |r| Self(r, std::marker::PhantomData))(s)
     }
 }
 impl<'a> HasId for Text<'a> {
@@ -0,0 +1,22 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+/// A flag which is shared between the thread running a long operation (like
+/// parsing or triangulation) and a thread which may want to abort it.
+/// Clones refer to the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks operations using this token to stop as soon as possible
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+
+/// Converts raw bytes to a string.  Part 21 files should be 7-bit ASCII, but
+/// exporters often write UTF-8 or ISO 8859-1 directly; data which isn't valid
+/// UTF-8 is assumed to be ISO 8859-1 (whose bytes map directly to code
+/// points).
+pub fn to_utf8(data: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(data) {
+        Ok(s) => Cow::Borrowed(s),
+        Err(_) => Cow::Owned(data.iter().map(|&c| c as char).collect()),
+    }
+}
+
+/// Parses `n` hex digits from the start of `s`
+fn hex(s: &[u8], n: usize) -> Option<u32> {
+    let digits = std::str::from_utf8(s.get(..n)?).ok()?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Decodes the contents of a Part 21 string (without its surrounding quotes),
+/// handling doubled quotes and backslashes, as well as the control
+/// directives:
+/// - `\S\c`, for characters in the upper half of an 8-bit code page
+/// - `\P?\`, which selects the code page for `\S\`
+/// - `\X\hh`, for a single ISO 8859-1 character
+/// - `\X2\hhhh...\X0\` and `\X4\hhhhhhhh...\X0\`, for UTF-16 / UCS-4 text
+///
+/// Only ISO 8859-1 is supported for `\S\`; other code pages selected with
+/// `\P?\` are decoded as if they were ISO 8859-1.  Malformed directives are
+/// kept as literal text.
+pub fn decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') && !s.contains("''") {
+        return Cow::Borrowed(s);
+    }
+    let b = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < b.len() {
+        let rest = &b[i..];
+        if rest.starts_with(b"''") {
+            out.push('\'');
+            i += 2;
+        } else if rest.starts_with(b"\\\\") {
+            out.push('\\');
+            i += 2;
+        } else if rest.starts_with(b"\\S\\") && rest.len() > 3 {
+            // Characters are given as their value minus 128
+            let c = s[i + 3..].chars().next().unwrap_or(' ');
+            out.push(char::from_u32(c as u32 + 128).unwrap_or(c));
+            i += 3 + c.len_utf8();
+        } else if rest.starts_with(b"\\P") && rest.get(3) == Some(&b'\\') {
+            i += 4;
+        } else if let Some(c) = rest.strip_prefix(b"\\X\\").and_then(|r| hex(r, 2)) {
+            out.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+            i += 5;
+        } else if let Some((text, n)) = decode_wide(rest) {
+            out.push_str(&text);
+            i += n;
+        } else {
+            // Other bytes (including malformed directives) are passed
+            // through, a whole character at a time
+            let c = s[i..].chars().next().expect("Could not get char");
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Decodes a `\X2\` or `\X4\` directive at the start of `s`, returning its
+/// text and the number of bytes consumed (including the closing `\X0\`)
+fn decode_wide(s: &[u8]) -> Option<(String, usize)> {
+    let width = if s.starts_with(b"\\X2\\") {
+        4
+    } else if s.starts_with(b"\\X4\\") {
+        8
+    } else {
+        return None;
+    };
+    let end = memchr::memmem::find(s, b"\\X0\\")?;
+    let digits = &s[4..end];
+    if !digits.len().is_multiple_of(width) {
+        return None;
+    }
+    let units = digits.chunks(width).map(|c| hex(c, width));
+    let text = if width == 4 {
+        let units: Option<Vec<u16>> = units.map(|u| u.map(|u| u as u16)).collect();
+        std::char::decode_utf16(units?)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    } else {
+        units.map(|u| u.map(|u| char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER)))
+            .collect::<Option<String>>()?
+    };
+    Some((text, end + 4))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_directives() {
+        assert!(matches!(decode("plain"), Cow::Borrowed("plain")));
+        assert_eq!(decode("it''s"), "it's");
+        assert_eq!(decode("back\\\\slash"), "back\\slash");
+        assert_eq!(decode("Gr\\X\\FC\\X\\DFe"), "Grüße");
+        assert_eq!(decode("\\S\\|"), "ü");
+        assert_eq!(decode("\\PA\\\\S\\|"), "ü");
+        assert_eq!(decode("\\X2\\90E854C1\\X0\\-1"), "部品-1");
+        // Surrogate pair for U+1F600
+        assert_eq!(decode("\\X2\\D83DDE00\\X0\\"), "\u{1F600}");
+        assert_eq!(decode("\\X4\\0001F600\\X0\\"), "\u{1F600}");
+        // Malformed directives are kept as-is
+        assert_eq!(decode("\\X2\\123\\X0\\"), "\\X2\\123\\X0\\");
+        assert_eq!(decode("\\X\\G"), "\\X\\G");
+    }
+
+    #[test]
+    fn latin1_fallback() {
+        assert_eq!(to_utf8("Grüße".as_bytes()), "Grüße");
+        assert_eq!(to_utf8(b"Gr\xfc\xdfe"), "Grüße");
+    }
+}
@@ -0,0 +1,306 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::encoding::{decode, to_utf8};
+use crate::protocol::Protocol;
+
+/// Metadata from a STEP file's header section, which is made up of the
+/// `FILE_DESCRIPTION`, `FILE_NAME`, and `FILE_SCHEMA` entities.
+/// Missing fields are left empty.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Header {
+    /// Free-form description of the file's contents
+    pub description: Vec<String>,
+    /// Conformance level, e.g. `2;1`
+    pub implementation_level: String,
+
+    /// Name of the file when it was written
+    pub name: String,
+    /// Time at which the file was written, in ISO 8601 format
+    pub time_stamp: String,
+    pub author: Vec<String>,
+    pub organization: Vec<String>,
+    /// System which wrote the STEP file (typically a translator library)
+    pub preprocessor_version: String,
+    /// CAD system from which the data originated
+    pub originating_system: String,
+    pub authorization: String,
+
+    /// Names of the schemas used by the data section, e.g. `AUTOMOTIVE_DESIGN`
+    pub schema: Vec<String>,
+}
+
+/// A parameter in a header entity, with anything other than strings and
+/// lists (like `$` or typed parameters) left uninterpreted
+#[derive(Debug)]
+enum Value {
+    String(String),
+    List(Vec<Value>),
+    Other,
+}
+
+impl Value {
+    fn string(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the non-empty strings in a list
+    fn strings(&self) -> Vec<String> {
+        match self {
+            Value::List(v) => v.iter()
+                .map(Value::string)
+                .filter(|s| !s.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Minimal recursive-descent parser for header entities.  We can't use the
+/// main parser here, because it requires the input to be passed through
+/// [`StepFile::strip_flatten`](crate::step_file::StepFile::strip_flatten),
+/// which strips whitespace from strings.
+struct Parser<'a> {
+    data: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.i).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_ascii_whitespace() {
+                self.i += 1;
+            } else if c == b'/' && self.data.get(self.i + 1) == Some(&b'*') {
+                self.i = self.data[self.i + 2..].windows(2)
+                    .position(|w| w == b"*/")
+                    .map(|j| self.i + j + 4)
+                    .unwrap_or(self.data.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> &'a str {
+        let start = self.i;
+        while self.peek().map(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+            .unwrap_or(false)
+        {
+            self.i += 1;
+        }
+        // Identifiers are ASCII, so this can't fail
+        std::str::from_utf8(&self.data[start..self.i]).unwrap_or("")
+    }
+
+    /// Parses a quoted string, decoding `''` and other escapes
+    fn string(&mut self) -> String {
+        self.i += 1; // opening quote
+        let start = self.i;
+        let mut end = self.data.len();
+        while let Some(c) = self.peek() {
+            self.i += 1;
+            if c == b'\'' {
+                if self.peek() == Some(b'\'') {
+                    self.i += 1;
+                } else {
+                    end = self.i - 1;
+                    break;
+                }
+            }
+        }
+        decode(&to_utf8(&self.data[start..end])).into_owned()
+    }
+
+    /// Parses a parenthesized list, starting at the opening paren
+    fn list(&mut self) -> Vec<Value> {
+        let mut out = Vec::new();
+        self.i += 1; // opening paren
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(b')') => {
+                    self.i += 1;
+                    break;
+                },
+                Some(b',') => self.i += 1,
+                _ => out.push(self.value()),
+            }
+        }
+        out
+    }
+
+    fn value(&mut self) -> Value {
+        match self.peek() {
+            Some(b'\'') => Value::String(self.string()),
+            Some(b'(') => Value::List(self.list()),
+            _ => {
+                // Skip over `$`, `*`, numbers, enums, and typed parameters
+                while let Some(c) = self.peek() {
+                    match c {
+                        b',' | b')' | b';' => break,
+                        b'(' => { self.list(); },
+                        b'\'' => { self.string(); },
+                        _ => self.i += 1,
+                    }
+                }
+                Value::Other
+            }
+        }
+    }
+
+    /// Parses an entity like `NAME(...);`, returning `None` at the end of
+    /// the section (or the data)
+    fn entity(&mut self) -> Option<(&'a str, Vec<Value>)> {
+        self.skip_whitespace();
+        let name = self.identifier();
+        if name.is_empty() || name == "ENDSEC" {
+            return None;
+        }
+        self.skip_whitespace();
+        let params = if self.peek() == Some(b'(') {
+            self.list()
+        } else {
+            Vec::new()
+        };
+        self.skip_whitespace();
+        if self.peek() == Some(b';') {
+            self.i += 1;
+        }
+        Some((name, params))
+    }
+}
+
+impl Header {
+    /// Parses the header section from raw STEP data (which should not be
+    /// passed through `strip_flatten`)
+    pub fn new(data: &[u8]) -> Self {
+        let mut out = Self::default();
+        let start = match memchr::memmem::find(data, b"HEADER;") {
+            Some(i) => i + b"HEADER;".len(),
+            None => return out,
+        };
+        let mut p = Parser { data, i: start };
+        while let Some((name, params)) = p.entity() {
+            let param = |i: usize| params.get(i).unwrap_or(&Value::Other);
+            match name {
+                "FILE_DESCRIPTION" => {
+                    out.description = param(0).strings();
+                    out.implementation_level = param(1).string();
+                },
+                "FILE_NAME" => {
+                    out.name = param(0).string();
+                    out.time_stamp = param(1).string();
+                    out.author = param(2).strings();
+                    out.organization = param(3).strings();
+                    out.preprocessor_version = param(4).string();
+                    out.originating_system = param(5).string();
+                    out.authorization = param(6).string();
+                },
+                "FILE_SCHEMA" => out.schema = param(0).strings(),
+                _ => (),
+            }
+        }
+        out
+    }
+
+    /// Returns the application protocol named by the file's schema
+    pub fn protocol(&self) -> Protocol {
+        Protocol::detect(&self.schema)
+    }
+
+    /// Reads the header from the start of a file, without loading the rest
+    pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut f = std::fs::File::open(path)?;
+        let mut data = Vec::new();
+        let mut chunk = [0; 16384];
+        loop {
+            let n = f.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            // Search from a little before the new chunk, in case the end of
+            // the section straddles two chunks
+            let from = data.len().saturating_sub(b"ENDSEC".len());
+            data.extend_from_slice(&chunk[..n]);
+            if memchr::memmem::find(&data[from..], b"ENDSEC").is_some() {
+                break;
+            }
+        }
+        Ok(Self::new(&data))
+    }
+}
+
+impl std::fmt::Display for Header {
+    /// Formats the non-empty fields, one per line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = [
+            ("File", self.name.clone()),
+            ("Schema", match self.protocol() {
+                Protocol::Unknown => self.schema.join(", "),
+                p => format!("{} ({})", self.schema.join(", "), p),
+            }),
+            ("Author", self.author.join(", ")),
+            ("Organization", self.organization.join(", ")),
+            ("Originating system", self.originating_system.clone()),
+            ("Preprocessor", self.preprocessor_version.clone()),
+            ("Timestamp", self.time_stamp.clone()),
+            ("Description", self.description.join(", ")),
+        ];
+        let mut first = true;
+        for (label, value) in fields.iter().filter(|(_, v)| !v.trim().is_empty()) {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{:<20}{}", format!("{}:", label), value)?;
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header() {
+        let data = b"ISO-10303-21;
+HEADER;
+/* Generated by a CAD system */
+FILE_DESCRIPTION(('A test part', ''), '2;1');
+FILE_NAME('my part.step', '2021-03-04T05:06:07',
+          ('Jane Doe', 'J\\X2\\00FC\\X0\\rgen M\\X\\FCller'),
+          ('Formlabs', 'It''s a company'),
+          'Translator 1.0', 'Some CAD 2021', $);
+FILE_SCHEMA(('AUTOMOTIVE_DESIGN { 1 0 10303 214 1 1 1 1 }'));
+ENDSEC;
+DATA;
+#1=CARTESIAN_POINT('',(0.,0.,0.));
+ENDSEC;
+END-ISO-10303-21;
+";
+        let h = Header::new(data);
+        assert_eq!(h.description, vec!["A test part"]);
+        assert_eq!(h.implementation_level, "2;1");
+        assert_eq!(h.name, "my part.step");
+        assert_eq!(h.time_stamp, "2021-03-04T05:06:07");
+        assert_eq!(h.author, vec!["Jane Doe", "Jürgen Müller"]);
+        assert_eq!(h.organization, vec!["Formlabs", "It's a company"]);
+        assert_eq!(h.preprocessor_version, "Translator 1.0");
+        assert_eq!(h.originating_system, "Some CAD 2021");
+        assert_eq!(h.authorization, "");
+        assert_eq!(h.schema, vec!["AUTOMOTIVE_DESIGN { 1 0 10303 214 1 1 1 1 }"]);
+        assert_eq!(h.protocol(), Protocol::Ap214);
+        assert!(h.to_string().starts_with("File:               my part.step\n"));
+    }
+}
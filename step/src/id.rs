@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use arrayvec::ArrayVec;
 
 #[derive(Debug)]
@@ -70,7 +72,7 @@ impl HasId for i64 {
 impl HasId for f64 {
     fn append_ids(&self, _v: &mut Vec<usize>) { /* Nothing to do here */ }
 }
-impl HasId for &str {
+impl HasId for Cow<'_, str> {
     fn append_ids(&self, _v: &mut Vec<usize>) { /* Nothing to do here */ }
 }
 impl HasId for bool {
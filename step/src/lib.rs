@@ -1,4 +1,10 @@
 pub mod parse;
+pub mod part21;
+pub mod prescan;
+pub mod protocol;
 pub mod step_file;
 pub mod ap214; // autogenerated!
+pub mod cancel;
+pub mod encoding;
+pub mod header;
 pub mod id;
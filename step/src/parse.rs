@@ -1,7 +1,8 @@
+use std::borrow::Cow;
 use std::collections::{HashSet, HashMap};
 use nom::{
     branch::{alt},
-    bytes::complete::{is_not, tag},
+    bytes::complete::tag,
     character::complete::{char, digit1},
     combinator::{map, map_res, opt},
     error::*,
@@ -11,7 +12,7 @@ use nom::{
 use memchr::{memchr, memchr3};
 use arrayvec::ArrayVec;
 
-use crate::{id::{Id, HasId}, ap214::{Entity, superclasses_of}};
+use crate::{id::{Id, HasId}, ap214::{Entity, superclasses_of}, encoding::decode};
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -62,13 +63,29 @@ impl Parse<'_> for i64 {
             })(s)
     }
 }
-impl<'a> Parse<'a> for &'a str {
-    fn parse(s: &'a str) -> IResult<'a, &'a str> {
+/// Parses a quoted string, returning its contents without decoding escapes
+/// (so `''` is returned as two quotes)
+fn quoted_string(s: &str) -> IResult<'_, &str> {
+    let (s, _) = char('\'')(s)?;
+    let b = s.as_bytes();
+    let mut i = 0;
+    loop {
+        match memchr::memchr(b'\'', &b[i..]) {
+            None => return nom_err(s, ErrorKind::Char),
+            Some(j) if b.get(i + j + 1) == Some(&b'\'') => i += j + 2,
+            Some(j) => return Ok((&s[i + j + 1..], &s[..i + j])),
+        }
+    }
+}
+
+/// Strings are decoded as they're parsed, so escapes like `''` and `\X2\`
+/// are already resolved.  This only allocates when a string has escapes.
+impl<'a> Parse<'a> for Cow<'a, str> {
+    fn parse(s: &'a str) -> IResult<'a, Cow<'a, str>> {
         alt((
-            map(delimited(char('\''), opt(is_not("'")), char('\'')),
-                |r| r.unwrap_or("")),
+            map(quoted_string, decode),
             // NUL REF
-            map(char('$'), |_| "")))(s)
+            map(char('$'), |_| Cow::Borrowed(""))))(s)
     }
 }
 
@@ -163,6 +180,19 @@ impl<'a> Parse<'a> for Derived {
     }
 }
 
+// Like `Derived`, but also accepts (and ignores) a reference.  Exporters
+// commonly write one for `named_unit.dimensions`, which SI and
+// conversion-based units derive (e.g. `NAMED_UNIT(#12)` alongside a
+// `CONVERSION_BASED_UNIT`), so the generated parsers use this for that
+// attribute alone.
+pub struct DerivedOrRef;
+impl<'a> Parse<'a> for DerivedOrRef {
+    fn parse(s: &'a str) -> IResult<'a, Self> {
+        alt((map(char('*'), |_| DerivedOrRef),
+             map(Id::<()>::parse, |_| DerivedOrRef)))(s)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 /// Parse a single attribute from a parameter list, consuming the trailing
@@ -231,7 +261,7 @@ pub(crate) fn parse_complex_mapping(s: &str) -> IResult<Entity> {
     let mut index = 0;
     let mut args_start = 0;
     let mut name: &str = "";
-    loop {
+    let rest = loop {
         let next = match memchr3(b'(', b')', b'\'', &bstr[index..]) {
             Some(i) => i,
             None => return nom_err(s, ErrorKind::Alt),
@@ -258,7 +288,7 @@ pub(crate) fn parse_complex_mapping(s: &str) -> IResult<Entity> {
                         .expect("Could not convert args");
                     subentities.insert(name, args);
                 } else if depth == 0 {
-                    break;
+                    break &s[(index + next + 1)..];
                 }
             },
             b'\'' => {
@@ -272,7 +302,7 @@ pub(crate) fn parse_complex_mapping(s: &str) -> IResult<Entity> {
             c => panic!("Invalid char: {}", c),
         }
         index += next + 1;
-    }
+    };
     // Filter out the list of subclasses to those which aren't a parent of
     // another item in the set; these are our potential leafs.
     let mut potential_leafs: HashSet<&str> = subentities.keys()
@@ -315,12 +345,10 @@ pub(crate) fn parse_complex_mapping(s: &str) -> IResult<Entity> {
         }
         leaf_entities.push(Entity::parse_chunks(&new_decl)?.1)
     }
-    // At this point, we assume that there's nothing left to parse, so we
-    // return an empty string for the 'remaining' text
     if leaf_entities.len() == 1 {
-        Ok(("", leaf_entities.pop().unwrap()))
+        Ok((rest, leaf_entities.pop().unwrap()))
     } else {
-        Ok(("", Entity::ComplexEntity(leaf_entities)))
+        Ok((rest, Entity::ComplexEntity(leaf_entities)))
     }
 }
 
@@ -336,4 +364,26 @@ mod tests {
         parse_entity_decl(b"#395359=UNCERTAINTY_MEASURE_WITH_UNIT(LENGTH_MEASURE(1.E-007),#395356,'distance_accuracy_value','confusion accuracy');").unwrap();
         parse_entity_decl(b"#1632=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));").unwrap();
     }
+
+    #[test]
+    fn test_derived() {
+        parse_entity_decl(b"#1=ORIENTED_EDGE('',*,*,#2,.T.);").unwrap();
+        assert!(parse_entity_decl(b"#1=ORIENTED_EDGE('',#3,#4,#2,.T.);").is_err());
+        parse_entity_decl(b"#4=(CONVERSION_BASED_UNIT('INCH',#2)LENGTH_UNIT()NAMED_UNIT(#3));").unwrap();
+        parse_entity_decl(b"#4=(CONVERSION_BASED_UNIT('INCH',#2)LENGTH_UNIT()NAMED_UNIT(*));").unwrap();
+    }
+
+    #[test]
+    fn test_decoded_strings() {
+        let (_, (_, e)) = parse_entity_decl(
+            b"#1=PRODUCT('It''s','Gr\\X\\FC\\X\\DFe','plain',());").unwrap();
+        match e {
+            Entity::Product(p) => {
+                assert_eq!(p.id.0, "It's");
+                assert_eq!(p.name.0, "Gr\u{fc}\u{df}e");
+                assert!(matches!(p.description.map(|d| d.0), Some(Cow::Borrowed("plain"))));
+            },
+            _ => panic!("Expected a PRODUCT"),
+        }
+    }
 }
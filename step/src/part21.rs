@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+use crate::step_file::StepFile;
+
+/// The kinds of section in a Part 21 exchange structure.  Edition 3 adds
+/// anchor and reference sections, and allows more than one data section.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum SectionKind {
+    Header,
+    Anchor,
+    Reference,
+    Data,
+}
+
+/// Finds the sections in a list of blocks (from [`StepFile::into_blocks`]),
+/// returning each section's kind and the range of blocks between its
+/// opening keyword and `ENDSEC;`.  Unterminated sections run to the end.
+pub(crate) fn sections(blocks: &[&[u8]]) -> Vec<(SectionKind, Range<usize>)> {
+    let mut out = Vec::new();
+    let mut current: Option<(SectionKind, usize)> = None;
+    for (i, b) in blocks.iter().enumerate() {
+        if *b == b"ENDSEC;" {
+            if let Some((kind, start)) = current.take() {
+                out.push((kind, start..i));
+            }
+            continue;
+        } else if current.is_some() {
+            continue;
+        }
+        let kind = match *b {
+            b"HEADER;" => SectionKind::Header,
+            b"ANCHOR;" => SectionKind::Anchor,
+            b"REFERENCE;" => SectionKind::Reference,
+            b"DATA;" => SectionKind::Data,
+            // Edition 3 data sections may be named, e.g. DATA('a',('AP242'));
+            b if b.starts_with(b"DATA(") => SectionKind::Data,
+            _ => continue,
+        };
+        current = Some((kind, i + 1));
+    }
+    if let Some((kind, start)) = current {
+        out.push((kind, start..blocks.len()));
+    }
+    out
+}
+
+/// An entry in the anchor section, which exports an entity under a name so
+/// that other files can refer to it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Anchor {
+    pub name: String,
+    /// Entity instance id
+    pub id: usize,
+}
+
+/// An entry in the reference section, which stands in for an entity (or a
+/// whole file) stored elsewhere
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reference {
+    /// Entity instance id used by this file's data sections
+    pub id: usize,
+    /// URI of the referenced entity, e.g. `part.stp#shape`
+    pub uri: String,
+}
+
+impl Reference {
+    /// Returns the file portion of the URI (which may be empty, for
+    /// references within the same file)
+    pub fn file(&self) -> &str {
+        self.uri.split('#').next().unwrap_or("")
+    }
+
+    /// Returns the anchor name within the referenced file, if present
+    pub fn anchor(&self) -> Option<&str> {
+        self.uri.split_once('#').map(|(_, a)| a)
+    }
+}
+
+/// The edition 3 structure of a STEP file: names of its data sections, plus
+/// the contents of its anchor and reference sections.  Entities from every
+/// data section are parsed together by [`StepFile::parse`].
+#[derive(Clone, Debug, Default)]
+pub struct Sections {
+    /// One entry per data section, with its name if given
+    pub data: Vec<Option<String>>,
+    pub anchors: Vec<Anchor>,
+    pub references: Vec<Reference>,
+}
+
+impl Sections {
+    /// Reads section structure from a STEP file, which must be preprocessed
+    /// by [`StepFile::strip_flatten`] first
+    pub fn new(data: &[u8]) -> Self {
+        let blocks = StepFile::into_blocks(data);
+        let mut out = Self::default();
+        for (kind, range) in sections(&blocks) {
+            match kind {
+                SectionKind::Data => {
+                    let b = blocks[range.start - 1];
+                    out.data.push(quoted(b).map(|s| s.to_owned()));
+                },
+                SectionKind::Anchor => out.anchors.extend(blocks[range].iter()
+                    .filter_map(|b| {
+                        // <name>=#123;
+                        let (name, rest) = split_once(b, b'=')?;
+                        let name = angled(name)?;
+                        let id = parse_id(rest.strip_suffix(b";")?)?;
+                        Some(Anchor { name: name.to_owned(), id })
+                    })),
+                SectionKind::Reference => out.references.extend(blocks[range].iter()
+                    .filter_map(|b| {
+                        // #123=<file.stp#name>;
+                        let (id, rest) = split_once(b, b'=')?;
+                        let id = parse_id(id)?;
+                        let uri = angled(rest.strip_suffix(b";")?)?;
+                        Some(Reference { id, uri: uri.to_owned() })
+                    })),
+                SectionKind::Header => (),
+            }
+        }
+        out
+    }
+}
+
+fn split_once(b: &[u8], c: u8) -> Option<(&[u8], &[u8])> {
+    let i = b.iter().position(|x| *x == c)?;
+    Some((&b[..i], &b[i + 1..]))
+}
+
+/// Parses `#123` into `123`
+fn parse_id(b: &[u8]) -> Option<usize> {
+    std::str::from_utf8(b.strip_prefix(b"#")?).ok()?.parse().ok()
+}
+
+/// Returns the contents of `<...>`
+fn angled(b: &[u8]) -> Option<&str> {
+    let inner = b.strip_prefix(b"<")?.strip_suffix(b">")?;
+    std::str::from_utf8(inner).ok()
+}
+
+/// Returns the contents of the first quoted string in a block
+pub(crate) fn quoted(b: &[u8]) -> Option<&str> {
+    let start = b.iter().position(|c| *c == b'\'')? + 1;
+    let len = b[start..].iter().position(|c| *c == b'\'')?;
+    std::str::from_utf8(&b[start..start + len]).ok()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED3: &[u8] = b"ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('assembly'),'3;1');
+FILE_NAME('asm.stp','2021-01-01T00:00:00',(''),(''),'','','');
+FILE_SCHEMA(('AP242_MANAGED_MODEL_BASED_3D_ENGINEERING_MIM_LF'));
+ENDSEC;
+ANCHOR;
+<root>=#1;
+ENDSEC;
+REFERENCE;
+#50=<part.stp#shape>;
+ENDSEC;
+DATA('first',('AP242_MANAGED_MODEL_BASED_3D_ENGINEERING_MIM_LF'));
+#1=CARTESIAN_POINT('',(0.,0.,0.));
+ENDSEC;
+DATA('second',('AP242_MANAGED_MODEL_BASED_3D_ENGINEERING_MIM_LF'));
+#2=DIRECTION('',(0.,0.,1.));
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    #[test]
+    fn edition_3_sections() {
+        let flat = StepFile::strip_flatten(ED3);
+        let s = Sections::new(&flat);
+        assert_eq!(s.data, vec![Some("first".to_owned()), Some("second".to_owned())]);
+        assert_eq!(s.anchors, vec![Anchor { name: "root".to_owned(), id: 1 }]);
+        assert_eq!(s.references.len(), 1);
+        assert_eq!(s.references[0].id, 50);
+        assert_eq!(s.references[0].file(), "part.stp");
+        assert_eq!(s.references[0].anchor(), Some("shape"));
+
+        // Entities from both data sections are parsed
+        let step = StepFile::parse(&flat);
+        assert_eq!(step.protocol(), crate::protocol::Protocol::Ap242);
+        assert!(matches!(step.0[1], crate::ap214::Entity::CartesianPoint(_)));
+        assert!(matches!(step.0[2], crate::ap214::Entity::Direction(_)));
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Assumed end-to-end throughput (parsing and triangulation) for load time
+/// estimates.  This is a rough heuristic rather than a calibrated figure:
+/// real throughput depends on the machine, thread count, and mix of
+/// geometry, and can easily differ by an order of magnitude.
+const ENTITIES_PER_SECOND: f64 = 100_000.0;
+
+/// Entity counts from a quick scan of a STEP file, which is much cheaper
+/// than a full parse and can be used to warn about large files (or pick a
+/// different loading strategy) before committing to loading them.
+#[derive(Clone, Debug, Default)]
+pub struct Prescan<'a> {
+    /// Total number of entity instances (`#id = ...`)
+    pub entities: usize,
+    /// Number of instances of each entity type.  Complex entities count
+    /// once towards each of their components.
+    pub counts: HashMap<&'a str, usize>,
+    /// Size of the scanned data, in bytes
+    pub bytes: usize,
+}
+
+impl<'a> Prescan<'a> {
+    /// Scans raw STEP data, which doesn't need to be passed through
+    /// [`StepFile::strip_flatten`](crate::step_file::StepFile::strip_flatten)
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut out = Self { bytes: data.len(), ..Self::default() };
+        let mut i = 0;
+        let mut at_start = true;
+        while i < data.len() {
+            match data[i] {
+                b'\'' => i = skip_string(data, i),
+                b'/' if data.get(i + 1) == Some(&b'*') => i = skip_comment(data, i),
+                b';' => {
+                    at_start = true;
+                    i += 1;
+                },
+                c if c.is_ascii_whitespace() => i += 1,
+                b'#' if at_start => {
+                    at_start = false;
+                    i = out.instance(data, i + 1);
+                },
+                _ => {
+                    at_start = false;
+                    i += 1;
+                },
+            }
+        }
+        out
+    }
+
+    /// Records an entity instance, given the position just after its `#`.
+    /// Returns the position at which scanning should continue.
+    fn instance(&mut self, data: &'a [u8], mut i: usize) -> usize {
+        while i < data.len() && data[i].is_ascii_digit() {
+            i += 1;
+        }
+        i = skip_whitespace(data, i);
+        if data.get(i) != Some(&b'=') {
+            return i;
+        }
+        i = skip_whitespace(data, i + 1);
+        self.entities += 1;
+        if data.get(i) == Some(&b'(') {
+            // Complex entity, e.g. `(A(...) B(...))`
+            i = skip_whitespace(data, i + 1);
+            while i < data.len() && data[i] != b')' {
+                let (name, next) = identifier(data, i);
+                if name.is_empty() {
+                    break;
+                }
+                self.record(name);
+                i = skip_whitespace(data, skip_parens(data, skip_whitespace(data, next)));
+            }
+            i
+        } else {
+            let (name, next) = identifier(data, i);
+            if !name.is_empty() {
+                self.record(name);
+            }
+            next
+        }
+    }
+
+    fn record(&mut self, name: &'a str) {
+        *self.counts.entry(name).or_default() += 1;
+    }
+
+    /// Returns entity types and their counts, most common first
+    pub fn sorted(&self) -> Vec<(&'a str, usize)> {
+        let mut out: Vec<_> = self.counts.iter().map(|(k, v)| (*k, *v)).collect();
+        out.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        out
+    }
+
+    /// Returns a very rough estimate of how long it will take to load and
+    /// triangulate the file, based only on its entity count and a fixed
+    /// assumed throughput.  This is only good enough for telling small files
+    /// from huge ones, so it should be presented to users as a guess.
+    pub fn estimated_load_time(&self) -> Duration {
+        Duration::from_secs_f64(self.entities as f64 / ENTITIES_PER_SECOND)
+    }
+}
+
+fn skip_whitespace(data: &[u8], mut i: usize) -> usize {
+    loop {
+        match data.get(i) {
+            Some(c) if c.is_ascii_whitespace() => i += 1,
+            Some(b'/') if data.get(i + 1) == Some(&b'*') => i = skip_comment(data, i),
+            _ => return i,
+        }
+    }
+}
+
+/// Skips a quoted string starting at `i`.  Escaped quotes (`''`) are handled
+/// by treating them as two adjacent strings.
+fn skip_string(data: &[u8], i: usize) -> usize {
+    memchr::memchr(b'\'', &data[i + 1..])
+        .map(|j| i + j + 2)
+        .unwrap_or(data.len())
+}
+
+/// Skips a `/* ... */` comment starting at `i`
+fn skip_comment(data: &[u8], i: usize) -> usize {
+    data[i + 2..].windows(2)
+        .position(|w| w == b"*/")
+        .map(|j| i + j + 4)
+        .unwrap_or(data.len())
+}
+
+/// Skips a balanced parenthesized group starting at `i`, if there is one
+fn skip_parens(data: &[u8], mut i: usize) -> usize {
+    if data.get(i) != Some(&b'(') {
+        return i;
+    }
+    let mut depth = 0;
+    while i < data.len() {
+        match data[i] {
+            b'\'' => {
+                i = skip_string(data, i);
+                continue;
+            },
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Reads an entity name starting at `i`, returning it and the position
+/// after it
+fn identifier(data: &[u8], i: usize) -> (&str, usize) {
+    let end = data[i..].iter()
+        .position(|c| !(c.is_ascii_alphanumeric() || *c == b'_'))
+        .map(|j| i + j)
+        .unwrap_or(data.len());
+    // Identifiers are ASCII, so this can't fail
+    (std::str::from_utf8(&data[i..end]).unwrap_or(""), end)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_entities() {
+        let data = b"ISO-10303-21;
+HEADER;
+FILE_NAME('a;b.step', '', (''), (''), '', '', '');
+ENDSEC;
+DATA;
+#1 = CARTESIAN_POINT('it''s; #2 = FAKE()', (0., 0., 0.));
+/* #3 = COMMENTED_OUT(); */
+#4=CARTESIAN_POINT ( '' , (1., 0., 0.) ) ;
+#5 = ( GEOMETRIC_REPRESENTATION_CONTEXT(3)
+       GLOBAL_UNIT_ASSIGNED_CONTEXT((#6)) REPRESENTATION_CONTEXT('', '') );
+#6=DIRECTION('',(0.,0.,1.));
+ENDSEC;
+END-ISO-10303-21;
+";
+        let p = Prescan::new(data);
+        assert_eq!(p.entities, 4);
+        assert_eq!(p.counts.get("CARTESIAN_POINT"), Some(&2));
+        assert_eq!(p.counts.get("DIRECTION"), Some(&1));
+        assert_eq!(p.counts.get("GLOBAL_UNIT_ASSIGNED_CONTEXT"), Some(&1));
+        assert_eq!(p.counts.get("REPRESENTATION_CONTEXT"), Some(&1));
+        assert_eq!(p.counts.get("FAKE"), None);
+        assert_eq!(p.counts.get("COMMENTED_OUT"), None);
+        assert_eq!(p.sorted()[0], ("CARTESIAN_POINT", 2));
+    }
+}
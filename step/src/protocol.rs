@@ -0,0 +1,86 @@
+/// The STEP application protocol which a file claims to follow, detected
+/// from the schema name in its `FILE_SCHEMA` header entity.  Protocols
+/// differ in which entities exporters use for things like colors and
+/// assembly structure, so consumers can branch on this to pick a strategy.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Protocol {
+    /// Configuration controlled 3D design (`CONFIG_CONTROL_DESIGN` or
+    /// `AP203_CONFIGURATION_CONTROLLED_3D_DESIGN_...`)
+    Ap203,
+    /// Automotive design (`AUTOMOTIVE_DESIGN` or `AP214_...`)
+    Ap214,
+    /// Managed model-based 3D engineering (`AP242_...`)
+    Ap242,
+    /// Missing or unrecognized schema
+    #[default]
+    Unknown,
+}
+
+impl Protocol {
+    /// Detects the protocol from a single schema name, which may include an
+    /// object identifier (e.g. `AUTOMOTIVE_DESIGN { 1 0 10303 214 1 1 1 1 }`)
+    pub fn from_schema(name: &str) -> Self {
+        let name = name.split(|c: char| c == '{' || c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_uppercase();
+        if name == "CONFIG_CONTROL_DESIGN" || name.starts_with("AP203") {
+            Protocol::Ap203
+        } else if name == "AUTOMOTIVE_DESIGN" || name.starts_with("AP214") {
+            Protocol::Ap214
+        } else if name.starts_with("AP242") {
+            Protocol::Ap242
+        } else {
+            Protocol::Unknown
+        }
+    }
+
+    /// Detects the protocol from the list of schemas in `FILE_SCHEMA`, using
+    /// the first one which is recognized
+    pub fn detect<S: AsRef<str>>(schemas: &[S]) -> Self {
+        schemas.iter()
+            .map(|s| Self::from_schema(s.as_ref()))
+            .find(|p| *p != Protocol::Unknown)
+            .unwrap_or(Protocol::Unknown)
+    }
+
+    /// Returns a short human-readable name, e.g. `AP214`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Ap203 => "AP203",
+            Protocol::Ap214 => "AP214",
+            Protocol::Ap242 => "AP242",
+            Protocol::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_protocols() {
+        assert_eq!(Protocol::from_schema("CONFIG_CONTROL_DESIGN"), Protocol::Ap203);
+        assert_eq!(Protocol::from_schema(
+            "AP203_CONFIGURATION_CONTROLLED_3D_DESIGN_OF_MECHANICAL_PARTS_AND_ASSEMBLIES_MIM_LF \
+             { 1 0 10303 403 1 1 4 }"), Protocol::Ap203);
+        assert_eq!(Protocol::from_schema("AUTOMOTIVE_DESIGN { 1 0 10303 214 1 1 1 1 }"),
+                   Protocol::Ap214);
+        assert_eq!(Protocol::from_schema("AUTOMOTIVE_DESIGN{1 0 10303 214 3 1 1}"),
+                   Protocol::Ap214);
+        assert_eq!(Protocol::from_schema("ap242_managed_model_based_3d_engineering_mim_lf"),
+                   Protocol::Ap242);
+        assert_eq!(Protocol::from_schema("IFC4"), Protocol::Unknown);
+        assert_eq!(Protocol::detect(&["IFC4", "AUTOMOTIVE_DESIGN"]), Protocol::Ap214);
+        assert_eq!(Protocol::detect::<&str>(&[]), Protocol::Unknown);
+    }
+}
@@ -1,51 +1,152 @@
 use memchr::{memchr, memchr2, memchr_iter};
-use log::warn;
+use log::{debug, warn};
 
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
 use crate::{
     ap214::Entity,
+    cancel::CancelToken,
+    encoding::to_utf8,
     id::Id,
+    part21::{quoted, sections, SectionKind},
+    protocol::Protocol,
     parse::{parse_entity_decl, parse_entity_fallback},
 };
 
+/// How strictly to follow the Part 21 grammar when parsing
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ParseMode {
+    /// Rejects any deviation from the grammar, for use when validating files
+    Strict,
+    /// Works around common exporter quirks (missing semicolons, unescaped
+    /// quotes, lowercase keywords), and skips entities which can't be
+    /// parsed
+    #[default]
+    Lenient,
+}
+
+/// An error from parsing in [`ParseMode::Strict`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub reason: &'static str,
+    /// The offending block (an entity declaration or section keyword), if
+    /// the error is associated with one
+    pub block: Option<String>,
+}
+
+impl ParseError {
+    fn new(reason: &'static str, block: Option<&[u8]>) -> Self {
+        Self { reason, block: block.map(|b| String::from_utf8_lossy(b).into_owned()) }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.block {
+            // Long entities are truncated to keep messages readable
+            Some(b) => match b.char_indices().nth(80) {
+                Some((i, _)) => write!(f, "{}: {}...", self.reason, &b[..i]),
+                None => write!(f, "{}: {}", self.reason, b),
+            },
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Result of parsing a single block, which is `None` if the block was skipped
+type ParsedBlock<'a> = Result<Option<(usize, Entity<'a>)>, ParseError>;
+
+/// A parsed STEP file, with entities indexed by their instance ids, along
+/// with the application protocol detected from its header
 #[derive(Debug)]
-pub struct StepFile<'a>(pub Vec<Entity<'a>>);
+pub struct StepFile<'a>(pub Vec<Entity<'a>>, Protocol);
 impl<'a> StepFile<'a> {
     /// Parses a STEP file from a raw array of bytes
     /// `data` must be preprocessed by [`strip_flatten`] first
     pub fn parse(data: &'a [u8]) -> Self {
-        let blocks = Self::into_blocks(&data);
-        let data_start = blocks.iter()
-            .position(|b| b == b"DATA;")
-            .unwrap_or(0) + 1;
-        let data_end = blocks.iter()
-            .skip(data_start)
-            .position(|b| b == b"ENDSEC;")
-            .unwrap_or(0) + data_start;
+        Self::parse_cancellable(data, &CancelToken::default())
+            .expect("Parsing can't be cancelled without a token")
+    }
+
+    /// Parses a STEP file like [`StepFile::parse`], returning `None` if the
+    /// token is cancelled before parsing finishes
+    pub fn parse_cancellable(data: &'a [u8], cancel: &CancelToken) -> Option<Self> {
+        Self::parse_with(data, ParseMode::Lenient, cancel)
+            .expect("Lenient parsing should not fail")
+    }
+
+    /// Parses a STEP file in the given mode, returning `Ok(None)` if the
+    /// token is cancelled before parsing finishes.  `data` must be
+    /// preprocessed by [`StepFile::preprocess`] (with the same mode) first.
+    /// Only [`ParseMode::Strict`] returns errors.
+    pub fn parse_with(data: &'a [u8], mode: ParseMode, cancel: &CancelToken)
+        -> Result<Option<Self>, ParseError>
+    {
+        let strict = mode == ParseMode::Strict;
+        let blocks = Self::into_blocks(data);
+        if strict {
+            if blocks.first() != Some(&&b"ISO-10303-21;"[..]) {
+                return Err(ParseError::new("Missing ISO-10303-21 start token",
+                                           blocks.first().copied()));
+            } else if blocks.last() != Some(&&b"END-ISO-10303-21;"[..]) {
+                return Err(ParseError::new("Missing END-ISO-10303-21 end token",
+                                           blocks.last().copied()));
+            }
+        }
+
+        // Edition 3 files may have several data sections (as well as anchor
+        // and reference sections, which aren't entities), so we gather the
+        // blocks from every data section.
+        let sections = sections(&blocks);
+        if strict && !sections.iter().any(|(kind, _)| *kind == SectionKind::Data) {
+            return Err(ParseError::new("Missing DATA section", None));
+        }
+        let data_blocks: Vec<&[u8]> = sections.iter()
+            .filter(|(kind, _)| *kind == SectionKind::Data)
+            .flat_map(|(_, range)| blocks[range.clone()].iter().copied())
+            .collect();
+
+        // The schema is only used to detect the protocol, so we don't need
+        // the full header parser (and its support for spaces in strings)
+        let protocol = sections.iter()
+            .filter(|(kind, _)| *kind == SectionKind::Header)
+            .flat_map(|(_, range)| blocks[range.clone()].iter())
+            .find(|b| b.starts_with(b"FILE_SCHEMA("))
+            .and_then(|b| quoted(b))
+            .map(Protocol::from_schema)
+            .unwrap_or_default();
 
         // Parse every block, accumulating a Vec of Results.  We parse in
         // single-threaded mode in WASM builds, because there's no thread
         // pool.
         let block_iter = {
-            let block_slice = &blocks[data_start..data_end];
+            let block_slice = &data_blocks[..];
             #[cfg(feature = "rayon")]
             { block_slice.par_iter() }
             #[cfg(not(feature = "rayon"))]
             { block_slice.iter() }
         };
 
-        let parsed: Vec<(usize, Entity)> = block_iter
-            .filter_map(|b| parse_entity_decl(*b)
-                .or_else(|e| {
-                    warn!("Failed to parse {}: {:?}",
-                        std::str::from_utf8(b).unwrap_or("[INVALID UTF-8]"),
-                              e);
-                    parse_entity_fallback(*b)
-                })
-                .ok())
-            .map(|b| b.1)
+        // Each block produces `None` if we've been cancelled, which stops
+        // the collection early
+        let parsed: Option<Vec<ParsedBlock>> = block_iter
+            .map(|b| if cancel.is_cancelled() {
+                None
+            } else {
+                Some(Self::parse_block_(b, mode))
+            })
+            .collect();
+        let parsed = match parsed {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let parsed: Vec<(usize, Entity)> = parsed.into_iter()
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
             .collect();
 
         // Awkward construction because `Entity` is not `Clone`
@@ -55,13 +156,42 @@ impl<'a> StepFile<'a> {
             .collect();
 
         for p in parsed.into_iter() {
+            if strict && !matches!(out[p.0], Entity::_EmptySlot) {
+                return Err(ParseError::new("Duplicate entity id", None));
+            }
             out[p.0] = p.1;
         }
 
-        Self(out)
+        Ok(Some(Self(out, protocol)))
+    }
+
+    /// Parses a single entity declaration.  In lenient mode, unparseable
+    /// entities are replaced with `Entity::_FailedToParse` (or skipped, if
+    /// not even their id can be read).
+    fn parse_block_(b: &'a [u8], mode: ParseMode) -> ParsedBlock<'a> {
+        match parse_entity_decl(b) {
+            Ok((";", e)) => Ok(Some(e)),
+            Ok(_) if mode == ParseMode::Strict =>
+                Err(ParseError::new("Unexpected data after entity \
+                                     (missing semicolon?)", Some(b))),
+            Ok((_, e)) => Ok(Some(e)),
+            Err(_) if mode == ParseMode::Strict =>
+                Err(ParseError::new("Invalid entity", Some(b))),
+            Err(e) => {
+                let text = std::str::from_utf8(b).unwrap_or("[INVALID UTF-8]");
+                if is_draughting_keyword(b) {
+                    debug!("Skipping draughting entity {}", text);
+                } else {
+                    warn!("Failed to parse {}: {:?}", text, e);
+                }
+                Ok(parse_entity_fallback(b).ok().map(|b| b.1))
+            },
+        }
     }
 
-    /// Flattens a STEP file, removing comments and whitespace
+    /// Flattens a STEP file, removing comments and whitespace.  Strings are
+    /// kept as they are, except for line breaks (which exporters use to wrap
+    /// long strings, and aren't part of their values).
     pub fn strip_flatten(data: &[u8]) -> Vec<u8> {
         let mut out = Vec::with_capacity(data.len());
         let mut i = 0;
@@ -75,7 +205,13 @@ impl<'a> StepFile<'a> {
                         }
                     }
                 }
-                // TODO: don't skip whitespace inside of strings
+                b'\'' => {
+                    let end = string_end(data, i + 1);
+                    out.extend(data[i..end].iter()
+                        .filter(|c| !matches!(c, b'\r' | b'\n')));
+                    i = end;
+                    continue;
+                },
                 c if c.is_ascii_whitespace() => (),
                 c => out.push(c),
             }
@@ -84,17 +220,83 @@ impl<'a> StepFile<'a> {
         out
     }
 
+    /// Flattens a STEP file with [`StepFile::strip_flatten`].  In lenient
+    /// mode, this also repairs common exporter quirks: unescaped quotes
+    /// within strings are doubled, missing semicolons between entities are
+    /// inserted, keywords are converted to uppercase, and files which aren't
+    /// valid UTF-8 are converted from ISO 8859-1.
+    pub fn preprocess(data: &[u8], mode: ParseMode) -> Vec<u8> {
+        let flat = Self::strip_flatten(data);
+        match mode {
+            ParseMode::Strict => flat,
+            ParseMode::Lenient => Self::repair(to_utf8(&flat).as_bytes()),
+        }
+    }
+
+    /// Implementation of the lenient repairs in [`StepFile::preprocess`]
+    fn repair(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        let mut depth = 0;
+        let mut i = 0;
+        while i < data.len() {
+            let c = data[i];
+            match c {
+                b'\'' => {
+                    out.push(c);
+                    i += 1;
+                    while let Some(&c) = data.get(i) {
+                        i += 1;
+                        if c != b'\'' {
+                            out.push(c);
+                        } else if data.get(i) == Some(&b'\'') {
+                            out.extend_from_slice(b"''");
+                            i += 1;
+                        } else if matches!(data.get(i),
+                            None | Some(b',') | Some(b')') | Some(b';'))
+                        {
+                            out.push(c);
+                            break;
+                        } else {
+                            // A quote which can't end the string, because
+                            // of what follows it, must be part of it
+                            out.extend_from_slice(b"''");
+                        }
+                    }
+                    continue;
+                },
+                // URIs (in anchor and reference sections) are case-sensitive
+                b'<' => {
+                    let end = memchr(b'>', &data[i..]).map(|j| i + j + 1)
+                        .unwrap_or(data.len());
+                    out.extend_from_slice(&data[i..end]);
+                    i = end;
+                    continue;
+                },
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                b';' => depth = 0,
+                b'#' if depth == 0 && out.last() == Some(&b')') => out.push(b';'),
+                _ => (),
+            }
+            out.push(c.to_ascii_uppercase());
+            i += 1;
+        }
+        out
+    }
+
     /// Splits a STEP file into individual blocks.  The input must be pre-processed
     /// by [`strip_flatten`] beforehand.
-    fn into_blocks(data: &[u8]) -> Vec<&[u8]> {
+    pub(crate) fn into_blocks(data: &[u8]) -> Vec<&[u8]> {
         let mut blocks = Vec::new();
         let mut i = 0;
         let mut start = 0;
-        while i < data.len() {
-            let next = memchr2(b'\'', b';', &data[i..]).unwrap();
+        while let Some(next) = memchr2(b'\'', b';', &data[i..]) {
             match data[i + next] {
-                // Skip over quoted blocks
-                b'\'' => i += next + memchr(b'\'', &data[i + next..]).unwrap() + 1,
+                // Skip over quoted blocks, stopping at an unterminated string
+                b'\'' => match memchr(b'\'', &data[i + next + 1..]) {
+                    Some(j) => i += next + j + 2,
+                    None => break,
+                },
                 b';' => {
                     blocks.push(&data[start..=(i + next)]);
 
@@ -104,9 +306,19 @@ impl<'a> StepFile<'a> {
                 _ => unreachable!(),
             }
         }
+        // Trailing data without a semicolon is kept as its own block, which
+        // is rejected when parsing strictly
+        if start < data.len() {
+            blocks.push(&data[start..]);
+        }
         blocks
     }
 
+    /// Returns the application protocol named in the file's header
+    pub fn protocol(&self) -> Protocol {
+        self.1
+    }
+
     pub fn entity<T: FromEntity<'a>>(&'a self, i: Id<T>) -> Option<&'a T> {
         T::try_from_entity(&self.0[i.0])
     }
@@ -123,3 +335,113 @@ impl<'a, T> std::ops::Index<Id<T>> for StepFile<'a> {
 pub trait FromEntity<'a> {
     fn try_from_entity(e: &'a Entity<'a>) -> Option<&'a Self>;
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Prefixes of entity names which only describe drawings or the
+/// presentation of annotations.  Newer protocols (e.g. AP242) add many of
+/// these beyond the AP214 schema, so they can't be parsed; since they don't
+/// affect the geometry, they're skipped without a warning.
+const DRAUGHTING_PREFIXES: &[&[u8]] = &[
+    b"ANNOTATION_", b"CAMERA_", b"DIMENSION_", b"DRAUGHTING_", b"DRAWING_",
+    b"LEADER_", b"PRESENTATION_", b"TESSELLATED_ANNOTATION_",
+    b"TESSELLATED_CURVE_SET", b"TESSELLATED_GEOMETRIC_SET", b"TEXT_LITERAL",
+];
+
+/// Checks whether a flattened entity declaration is a draughting entity
+fn is_draughting_keyword(b: &[u8]) -> bool {
+    match memchr(b'=', b) {
+        Some(i) => DRAUGHTING_PREFIXES.iter().any(|p| b[i + 1..].starts_with(p)),
+        None => false,
+    }
+}
+
+/// Finds the end of a string whose contents start at `data[i]`, returning
+/// the index just past its closing quote (or the end of the data, if it's
+/// unterminated).  Doubled quotes are escapes, and as in lenient repairs, a
+/// quote which isn't followed by the end of a parameter (ignoring
+/// whitespace) is part of the string.
+fn string_end(data: &[u8], mut i: usize) -> usize {
+    while let Some(j) = memchr(b'\'', &data[i..]) {
+        i += j + 1;
+        if data.get(i) == Some(&b'\'') {
+            i += 1;
+            continue;
+        }
+        let next = data[i..].iter().find(|c| !c.is_ascii_whitespace());
+        if matches!(next, None | Some(b',') | Some(b')') | Some(b';')) {
+            return i;
+        }
+    }
+    data.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLEAN: &[u8] = b"ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('clean'),'2;1');
+ENDSEC;
+DATA;
+#1=CARTESIAN_POINT('it''s fine',(0.,0.,0.));
+#2=DIRECTION('',(0.,0.,1.));
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    /// The same file, with a lowercase keyword, an unescaped quote, and a
+    /// missing semicolon
+    const QUIRKY: &[u8] = b"ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('quirky'),'2;1');
+ENDSEC;
+DATA;
+#1=cartesian_point('it's fine',(0.,0.,0.))
+#2=DIRECTION('',(0.,0.,1.));
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    fn parse(data: &[u8], mode: ParseMode) -> Result<usize, ParseError> {
+        let flat = StepFile::preprocess(data, mode);
+        let step = StepFile::parse_with(&flat, mode, &CancelToken::default())?
+            .unwrap();
+        Ok(step.0.iter()
+            .filter(|e| matches!(e, Entity::CartesianPoint(_) | Entity::Direction(_)))
+            .count())
+    }
+
+    #[test]
+    fn parse_modes() {
+        assert_eq!(parse(CLEAN, ParseMode::Strict), Ok(2));
+        assert_eq!(parse(CLEAN, ParseMode::Lenient), Ok(2));
+        assert_eq!(parse(QUIRKY, ParseMode::Lenient), Ok(2));
+        assert!(parse(QUIRKY, ParseMode::Strict).is_err());
+
+        let truncated = &CLEAN[..CLEAN.len() - b"END-ISO-10303-21;\n".len()];
+        assert_eq!(parse(truncated, ParseMode::Lenient), Ok(2));
+        assert_eq!(parse(truncated, ParseMode::Strict).unwrap_err().reason,
+                   "Missing END-ISO-10303-21 end token");
+    }
+
+    #[test]
+    fn strings_keep_whitespace() {
+        let flat = StepFile::strip_flatten(b"#1=PRODUCT('Bolt M6 x 20' , /* note */
+            'it''s a /* string */ x','wrapped long\n string');\n");
+        assert_eq!(std::str::from_utf8(&flat).unwrap(),
+            "#1=PRODUCT('Bolt M6 x 20','it''s a /* string */ x','wrapped long string');");
+
+        // Spaces survive lenient repairs, including around unescaped quotes
+        let flat = StepFile::preprocess(b"#1=PRODUCT('Bolt's M6 x 20', '');", ParseMode::Lenient);
+        assert_eq!(std::str::from_utf8(&flat).unwrap(), "#1=PRODUCT('Bolt''s M6 x 20','');");
+    }
+
+    #[test]
+    fn draughting_keywords() {
+        assert!(is_draughting_keyword(b"#5=TESSELLATED_ANNOTATION_OCCURRENCE('',(#6),#7);"));
+        assert!(is_draughting_keyword(b"#5=DRAUGHTING_MODEL('',(#6),#7);"));
+        assert!(!is_draughting_keyword(b"#5=CARTESIAN_POINT('',(0.,0.,0.));"));
+    }
+}
@@ -0,0 +1,627 @@
+use nalgebra_glm::DVec3;
+
+use crate::mesh::Mesh;
+
+/// Triangles with a near-zero determinant are treated as parallel to the ray
+const RAY_EPSILON: f64 = 1e-12;
+
+/// Maximum number of triangles stored in a single leaf node
+const LEAF_SIZE: usize = 4;
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: DVec3,
+    pub max: DVec3,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: DVec3::repeat(f64::INFINITY),
+            max: DVec3::repeat(-f64::INFINITY),
+        }
+    }
+
+    pub fn expand(&mut self, p: DVec3) {
+        self.min = self.min.inf(&p);
+        self.max = self.max.sup(&p);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    /// Returns the index (0, 1, or 2) of the box's longest axis
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Returns a copy of the box, grown by `margin` on every side
+    pub fn inflate(&self, margin: f64) -> Aabb {
+        Aabb { min: self.min.add_scalar(-margin), max: self.max.add_scalar(margin) }
+    }
+
+    /// Checks whether two boxes overlap (including touching)
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    /// Returns the squared distance from the point to the box, which is zero
+    /// if the point is inside the box.
+    pub fn distance2(&self, p: DVec3) -> f64 {
+        let d = (self.min - p).sup(&DVec3::zeros()).sup(&(p - self.max));
+        d.norm_squared()
+    }
+
+    /// Returns the distance along the ray at which it enters the box (which
+    /// is zero if the origin is inside the box), or `None` if it misses.
+    /// `inv_dir` is the component-wise reciprocal of the ray direction.
+    pub fn ray_distance(&self, origin: DVec3, inv_dir: DVec3) -> Option<f64> {
+        let mut t_min = 0.0f64;
+        let mut t_max = f64::INFINITY;
+        for i in 0..3 {
+            // Rays parallel to this slab either always or never overlap it,
+            // and must be special-cased to avoid computing 0 * inf = NaN
+            if inv_dir[i].is_infinite() {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (self.min[i] - origin[i]) * inv_dir[i];
+            let b = (self.max[i] - origin[i]) * inv_dir[i];
+            t_min = t_min.max(a.min(b));
+            t_max = t_max.min(a.max(b));
+        }
+        if t_min <= t_max {
+            Some(t_min)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    bounds: Aabb,
+
+    /// For leaf nodes, this is the index of the first triangle; for inner
+    /// nodes, it's the index of the first child (the second is `start + 1`)
+    start: usize,
+
+    /// Number of triangles in a leaf, or 0 for inner nodes
+    count: usize,
+}
+
+/// Result of a closest-point query
+#[derive(Copy, Clone, Debug)]
+pub struct ClosestPoint {
+    /// Position on the mesh surface
+    pub point: DVec3,
+    /// Index into the original mesh's `triangles` array
+    pub triangle: usize,
+    /// Distance from the query point to `point`
+    pub distance: f64,
+}
+
+/// Result of a ray query
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    /// Position on the mesh surface
+    pub point: DVec3,
+    /// Index of the `ADVANCED_FACE` entity which produced the triangle, if
+    /// the mesh came from a STEP file
+    pub face_id: Option<usize>,
+    /// Index into the original mesh's `triangles` array
+    pub triangle: usize,
+    /// Distance along the ray, in units of the ray's direction vector
+    pub distance: f64,
+}
+
+/// A bounding-volume hierarchy over the triangles of a [`Mesh`], which
+/// accelerates spatial queries.  Triangle positions are copied into the
+/// `Bvh`, so it doesn't borrow the mesh after construction.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    tris: Vec<[DVec3; 3]>,
+
+    /// Map from our (reordered) triangles back into the mesh
+    index: Vec<usize>,
+
+    /// Source face of each (reordered) triangle
+    faces: Vec<Option<usize>>,
+}
+
+impl Bvh {
+    pub fn new(mesh: &Mesh) -> Self {
+        Self::from_triangles(mesh, 0..mesh.triangles.len())
+    }
+
+    /// Builds a hierarchy over a subset of the mesh's triangles.  Triangle
+    /// indices in query results still refer to the full mesh.
+    pub fn from_triangles(mesh: &Mesh, range: std::ops::Range<usize>) -> Self {
+        let mut order: Vec<(usize, [DVec3; 3], DVec3)> = range
+            .map(|i| {
+                let t = &mesh.triangles[i];
+                let p = [mesh.verts[t.verts.x as usize].pos,
+                         mesh.verts[t.verts.y as usize].pos,
+                         mesh.verts[t.verts.z as usize].pos];
+                (i, p, (p[0] + p[1] + p[2]) / 3.0)
+            })
+            .collect();
+
+        let mut nodes = vec![Node { bounds: Aabb::empty(), start: 0, count: 0 }];
+        if !order.is_empty() {
+            Self::build(&mut nodes, 0, &mut order, 0);
+        }
+        Self {
+            nodes,
+            faces: order.iter().map(|o| mesh.face_of(o.0).map(|f| f.id)).collect(),
+            index: order.iter().map(|o| o.0).collect(),
+            tris: order.into_iter().map(|o| o.1).collect(),
+        }
+    }
+
+    /// Recursively populates `nodes[n]` with the triangles in `order`, which
+    /// begin at index `offset` in the final triangle array.
+    fn build(nodes: &mut Vec<Node>, n: usize,
+             order: &mut [(usize, [DVec3; 3], DVec3)], offset: usize)
+    {
+        let mut bounds = Aabb::empty();
+        let mut centers = Aabb::empty();
+        for (_, p, c) in order.iter() {
+            p.iter().for_each(|p| bounds.expand(*p));
+            centers.expand(*c);
+        }
+        nodes[n].bounds = bounds;
+
+        if order.len() <= LEAF_SIZE {
+            nodes[n].start = offset;
+            nodes[n].count = order.len();
+            return;
+        }
+
+        // Split at the median centroid along the longest axis
+        let axis = centers.longest_axis();
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |a, b|
+            a.2[axis].partial_cmp(&b.2[axis])
+                .unwrap_or(std::cmp::Ordering::Equal));
+
+        let child = nodes.len();
+        nodes[n].start = child;
+        nodes[n].count = 0;
+        nodes.push(Node { bounds: Aabb::empty(), start: 0, count: 0 });
+        nodes.push(Node { bounds: Aabb::empty(), start: 0, count: 0 });
+
+        let (lo, hi) = order.split_at_mut(mid);
+        Self::build(nodes, child, lo, offset);
+        Self::build(nodes, child + 1, hi, offset + mid);
+    }
+
+    /// Returns the bounds of every triangle in the hierarchy
+    pub fn bounds(&self) -> Aabb {
+        self.nodes[0].bounds
+    }
+
+    /// Finds the closest point on the mesh to the given position, returning
+    /// `None` if the mesh is empty.
+    pub fn closest_point(&self, p: DVec3) -> Option<ClosestPoint> {
+        if self.tris.is_empty() {
+            return None;
+        }
+        let mut best: Option<(f64, DVec3, usize)> = None;
+        let mut todo = vec![0];
+        while let Some(n) = todo.pop() {
+            let node = &self.nodes[n];
+            let best_d2 = best.map(|b| b.0).unwrap_or(f64::INFINITY);
+            if node.bounds.distance2(p) >= best_d2 {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..(node.start + node.count) {
+                    let t = &self.tris[i];
+                    let q = closest_point_on_triangle(p, t[0], t[1], t[2]);
+                    let d2 = (q - p).norm_squared();
+                    if best.map(|b| d2 < b.0).unwrap_or(true) {
+                        best = Some((d2, q, i));
+                    }
+                }
+            } else {
+                // Push the farther child first, so that the nearer child is
+                // searched first and tightens the bound for its sibling.
+                let a = node.start;
+                let b = node.start + 1;
+                if self.nodes[a].bounds.distance2(p) <
+                   self.nodes[b].bounds.distance2(p)
+                {
+                    todo.push(b);
+                    todo.push(a);
+                } else {
+                    todo.push(a);
+                    todo.push(b);
+                }
+            }
+        }
+        best.map(|(d2, point, i)| ClosestPoint {
+            point,
+            triangle: self.index[i],
+            distance: d2.sqrt(),
+        })
+    }
+
+    /// Finds the first intersection of the ray `origin + t * dir` (with
+    /// `t >= 0`) with the mesh, returning `None` if it doesn't hit anything.
+    pub fn raycast(&self, origin: DVec3, dir: DVec3) -> Option<Hit> {
+        if self.tris.is_empty() {
+            return None;
+        }
+        let inv_dir = DVec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut best: Option<(f64, usize)> = None;
+        let mut todo = vec![0];
+        while let Some(n) = todo.pop() {
+            let node = &self.nodes[n];
+            let t_box = match node.bounds.ray_distance(origin, inv_dir) {
+                Some(t) => t,
+                None => continue,
+            };
+            if best.map(|b| t_box > b.0).unwrap_or(false) {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..(node.start + node.count) {
+                    let t = &self.tris[i];
+                    if let Some(d) = ray_triangle(origin, dir, t[0], t[1], t[2]) {
+                        if best.map(|b| d < b.0).unwrap_or(true) {
+                            best = Some((d, i));
+                        }
+                    }
+                }
+            } else {
+                // As above, search the nearer child first
+                let a = node.start;
+                let b = node.start + 1;
+                let ta = self.nodes[a].bounds.ray_distance(origin, inv_dir);
+                let tb = self.nodes[b].bounds.ray_distance(origin, inv_dir);
+                if ta.unwrap_or(f64::INFINITY) < tb.unwrap_or(f64::INFINITY) {
+                    todo.push(b);
+                    todo.push(a);
+                } else {
+                    todo.push(a);
+                    todo.push(b);
+                }
+            }
+        }
+        best.map(|(distance, i)| self.hit(i, origin, dir, distance))
+    }
+
+    /// Finds every intersection of the ray `origin + t * dir` (with `t >= 0`)
+    /// with the mesh, sorted from nearest to farthest.
+    pub fn raycast_all(&self, origin: DVec3, dir: DVec3) -> Vec<Hit> {
+        let mut out = Vec::new();
+        if self.tris.is_empty() {
+            return out;
+        }
+        let inv_dir = DVec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut todo = vec![0];
+        while let Some(n) = todo.pop() {
+            let node = &self.nodes[n];
+            if node.bounds.ray_distance(origin, inv_dir).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..(node.start + node.count) {
+                    let t = &self.tris[i];
+                    if let Some(d) = ray_triangle(origin, dir, t[0], t[1], t[2]) {
+                        out.push(self.hit(i, origin, dir, d));
+                    }
+                }
+            } else {
+                todo.push(node.start);
+                todo.push(node.start + 1);
+            }
+        }
+        out.sort_by(|a, b| a.distance.partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal));
+        out
+    }
+
+    /// Returns a vertex of the first triangle, or `None` if the hierarchy is
+    /// empty
+    pub fn first_point(&self) -> Option<DVec3> {
+        self.tris.first().map(|t| t[0])
+    }
+
+    /// Finds every triangle whose bounding box overlaps the given box,
+    /// returning its index into the original mesh and its vertex positions
+    pub fn overlapping(&self, b: &Aabb) -> Vec<(usize, [DVec3; 3])> {
+        let mut out = Vec::new();
+        if self.tris.is_empty() {
+            return out;
+        }
+        let mut todo = vec![0];
+        while let Some(n) = todo.pop() {
+            let node = &self.nodes[n];
+            if !node.bounds.overlaps(b) {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..(node.start + node.count) {
+                    let mut t = Aabb::empty();
+                    self.tris[i].iter().for_each(|p| t.expand(*p));
+                    if t.overlaps(b) {
+                        out.push((self.index[i], self.tris[i]));
+                    }
+                }
+            } else {
+                todo.push(node.start);
+                todo.push(node.start + 1);
+            }
+        }
+        out
+    }
+
+    /// Builds a hit record for reordered triangle `i`
+    fn hit(&self, i: usize, origin: DVec3, dir: DVec3, distance: f64) -> Hit {
+        Hit {
+            point: origin + dir * distance,
+            face_id: self.faces[i],
+            triangle: self.index[i],
+            distance,
+        }
+    }
+}
+
+/// Hit from a [`SolidBvh`] query, which also records the solid
+#[derive(Copy, Clone, Debug)]
+pub struct SolidHit<T> {
+    /// Index into the original mesh's `solids` array
+    pub solid: usize,
+    pub hit: T,
+}
+
+/// A two-level hierarchy, with one [`Bvh`] per solid in the mesh.  This means
+/// that queries can report which solid they landed on, and that solids can
+/// be hidden or rebuilt individually.
+pub struct SolidBvh {
+    solids: Vec<Bvh>,
+}
+
+impl SolidBvh {
+    /// Builds a hierarchy for each of the mesh's solids.  If the mesh doesn't
+    /// have any solid information, then it's treated as a single solid.
+    pub fn new(mesh: &Mesh) -> Self {
+        let solids = if mesh.solids.is_empty() {
+            vec![Bvh::new(mesh)]
+        } else {
+            mesh.solids.iter()
+                .map(|s| Bvh::from_triangles(mesh, s.triangles.clone()))
+                .collect()
+        };
+        Self { solids }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.solids.iter()
+            .fold(Aabb::empty(), |a, b| a.union(&b.bounds()))
+    }
+
+    pub fn closest_point(&self, p: DVec3) -> Option<SolidHit<ClosestPoint>> {
+        let mut best: Option<SolidHit<ClosestPoint>> = None;
+        for (i, b) in self.solids.iter().enumerate() {
+            let bound = best.map(|b| b.hit.distance).unwrap_or(f64::INFINITY);
+            if b.bounds().distance2(p) >= bound * bound {
+                continue;
+            }
+            if let Some(hit) = b.closest_point(p) {
+                if hit.distance < bound {
+                    best = Some(SolidHit { solid: i, hit });
+                }
+            }
+        }
+        best
+    }
+
+    pub fn raycast(&self, origin: DVec3, dir: DVec3) -> Option<SolidHit<Hit>> {
+        let mut best: Option<SolidHit<Hit>> = None;
+        for (i, b) in self.solids.iter().enumerate() {
+            if let Some(hit) = b.raycast(origin, dir) {
+                if best.map(|b| hit.distance < b.hit.distance).unwrap_or(true) {
+                    best = Some(SolidHit { solid: i, hit });
+                }
+            }
+        }
+        best
+    }
+
+    /// Finds every intersection with every solid, sorted from nearest to
+    /// farthest.  A ray passing through a closed solid will usually have an
+    /// even number of hits on it, which is handy for inside/outside tests.
+    pub fn raycast_all(&self, origin: DVec3, dir: DVec3) -> Vec<SolidHit<Hit>> {
+        let mut out: Vec<SolidHit<Hit>> = self.solids.iter()
+            .enumerate()
+            .flat_map(|(i, b)| b.raycast_all(origin, dir).into_iter()
+                .map(move |hit| SolidHit { solid: i, hit }))
+            .collect();
+        out.sort_by(|a, b| a.hit.distance.partial_cmp(&b.hit.distance)
+            .unwrap_or(std::cmp::Ordering::Equal));
+        out
+    }
+}
+
+/// Intersects the ray `origin + t * dir` with triangle `abc`, returning `t`
+/// if they intersect with `t >= 0`.
+///
+/// This is the Möller-Trumbore algorithm, which hits triangles from either
+/// side (since STEP faces aren't guaranteed to be consistently oriented).
+pub fn ray_triangle(origin: DVec3, dir: DVec3,
+                    a: DVec3, b: DVec3, c: DVec3) -> Option<f64> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = dir.cross(&ac);
+    let det = ab.dot(&p);
+    if det.abs() < RAY_EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let s = origin - a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&ab);
+    let v = dir.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(&q) * inv_det;
+    if t >= 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Returns the point on triangle `abc` which is closest to `p`
+///
+/// This is the algorithm from Ericson's _Real-Time Collision Detection_,
+/// section 5.1.5, which checks Voronoi regions of the triangle's features.
+pub fn closest_point_on_triangle(p: DVec3, a: DVec3, b: DVec3, c: DVec3) -> DVec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+
+    // Vertex region outside a
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    // Vertex region outside b
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    // Edge region of ab
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return a + v * ab;
+    }
+
+    // Vertex region outside c
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    // Edge region of ac
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return a + w * ac;
+    }
+
+    // Edge region of bc
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return b + w * (c - b);
+    }
+
+    // Inside the face region.  A zero-area triangle should have been caught
+    // by one of the feature regions above, but we check to avoid NaNs.
+    let sum = va + vb + vc;
+    if sum <= 0.0 {
+        return a;
+    }
+    let denom = 1.0 / sum;
+    let v = vb * denom;
+    let w = vc * denom;
+    a + ab * v + ac * w
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Builds a bumpy height-field with `n x n` vertices as a test mesh
+    fn grid(n: usize) -> Mesh {
+        let mut mesh = Mesh::default();
+        fixtures::grid(&mut mesh, n - 1, |x, y| {
+            DVec3::new(x, y, (x * 0.7).sin() * (y * 0.3).cos())
+        });
+        mesh
+    }
+
+    #[test]
+    fn closest_point_matches_brute_force() {
+        let mesh = grid(16);
+        let bvh = Bvh::new(&mesh);
+        for q in 0..64 {
+            let q = q as f64;
+            let p = DVec3::new((q * 1.3) % 17.0 - 1.0, (q * 2.9) % 17.0 - 1.0,
+                               (q * 0.37).sin() * 3.0);
+            let best = mesh.triangles.iter()
+                .map(|t| {
+                    let c = closest_point_on_triangle(p,
+                        mesh.verts[t.verts.x as usize].pos,
+                        mesh.verts[t.verts.y as usize].pos,
+                        mesh.verts[t.verts.z as usize].pos);
+                    (c - p).norm()
+                })
+                .fold(f64::INFINITY, f64::min);
+            let c = bvh.closest_point(p).unwrap();
+            assert!((c.distance - best).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn raycast_matches_brute_force() {
+        let mesh = grid(16);
+        let bvh = Bvh::new(&mesh);
+        for q in 0..64 {
+            let q = q as f64;
+            let origin = DVec3::new((q * 1.3) % 15.0, (q * 2.9) % 15.0, 5.0);
+            let dir = DVec3::new((q * 0.7).sin() * 0.3, (q * 0.3).cos() * 0.3, -1.0);
+            let best = mesh.triangles.iter()
+                .filter_map(|t| ray_triangle(origin, dir,
+                        mesh.verts[t.verts.x as usize].pos,
+                        mesh.verts[t.verts.y as usize].pos,
+                        mesh.verts[t.verts.z as usize].pos))
+                .fold(f64::INFINITY, f64::min);
+            match bvh.raycast(origin, dir) {
+                Some(hit) => assert!((hit.distance - best).abs() < 1e-9),
+                None => assert!(best.is_infinite()),
+            }
+        }
+        // A vertical ray through a height-field hits exactly one triangle
+        // (unless it lands on an edge, which we avoid here)
+        let hits = bvh.raycast_all(DVec3::new(4.3, 4.6, 5.0),
+                                   DVec3::new(0.0, 0.0, -1.0));
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].face_id.is_none());
+
+        // Rays pointing away from the mesh shouldn't hit anything
+        assert!(bvh.raycast(DVec3::new(4.0, 4.0, 5.0),
+                            DVec3::new(0.0, 0.0, 1.0)).is_none());
+    }
+}
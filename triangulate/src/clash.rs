@@ -0,0 +1,269 @@
+use nalgebra_glm::DVec3;
+
+use crate::{
+    bvh::{closest_point_on_triangle, Aabb, Bvh},
+    mesh::Mesh,
+};
+
+/// Distance below which surfaces are treated as touching, relative to the
+/// size of the triangles (or solids) involved
+const CONTACT_EPSILON: f64 = 1e-9;
+
+/// How two solids interfere
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClashKind {
+    /// The solids' surfaces cross each other
+    Intersecting,
+    /// One solid is entirely inside the other
+    Contained,
+    /// The solids don't touch, but are closer than the clearance
+    Clearance,
+}
+
+impl ClashKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClashKind::Intersecting => "intersecting",
+            ClashKind::Contained => "contained",
+            ClashKind::Clearance => "clearance",
+        }
+    }
+}
+
+/// A pair of interfering solids
+#[derive(Copy, Clone, Debug)]
+pub struct Clash {
+    /// Indices into `Mesh::solids`, with `a < b`
+    pub a: usize,
+    pub b: usize,
+    pub kind: ClashKind,
+    /// Smallest distance between the solids' surfaces, which is zero unless
+    /// they're only within the clearance
+    pub distance: f64,
+}
+
+/// Finds every pair of solids in the mesh whose triangles intersect, where
+/// one is inside the other, or (if `clearance` is positive) which come
+/// closer than `clearance` to each other.  Solids which only touch (like
+/// stacked or bolted parts) aren't clashes, though they're within any
+/// positive clearance.
+///
+/// This works on the tessellation, so curved surfaces which touch in the
+/// model (like a pin in a hole of the same size) may be reported as a clash.
+pub fn find_clashes(mesh: &Mesh, clearance: f64) -> Vec<Clash> {
+    let bvhs: Vec<Bvh> = mesh.solids.iter()
+        .map(|s| Bvh::from_triangles(mesh, s.triangles.clone()))
+        .collect();
+    let mut out = Vec::new();
+    for i in 0..bvhs.len() {
+        for j in (i + 1)..bvhs.len() {
+            if let Some((kind, distance)) = check_pair(&bvhs[i], &bvhs[j], clearance) {
+                out.push(Clash { a: i, b: j, kind, distance });
+            }
+        }
+    }
+    out
+}
+
+fn check_pair(a: &Bvh, b: &Bvh, clearance: f64) -> Option<(ClashKind, f64)> {
+    let (ba, bb) = (a.bounds(), b.bounds());
+    if !ba.inflate(clearance).overlaps(&bb) {
+        return None;
+    }
+
+    let mut best = f64::INFINITY;
+    for (_, t) in a.overlapping(&bb.inflate(clearance)) {
+        let mut tb = Aabb::empty();
+        t.iter().for_each(|p| tb.expand(*p));
+        for (_, u) in b.overlapping(&tb.inflate(clearance)) {
+            if triangles_intersect(&t, &u) {
+                return Some((ClashKind::Intersecting, 0.0));
+            } else if clearance > 0.0 {
+                best = best.min(triangle_distance(&t, &u));
+            }
+        }
+    }
+
+    // Without any crossings, either solid may still be inside the other,
+    // which we check by counting crossings along a ray (whose direction is
+    // picked to avoid running along axis-aligned edges).  The point may be
+    // where the solids touch, so hits right at the start don't count.
+    let dir = DVec3::new(1.0, 0.754_877_666_2, 0.569_840_291_0);
+    let inside = |p: Option<DVec3>, bvh: &Bvh| p.map(|p| {
+        let b = bvh.bounds();
+        let eps = CONTACT_EPSILON * (b.max - b.min).norm() / dir.norm();
+        bvh.raycast_all(p, dir).iter().filter(|h| h.distance > eps).count() % 2 == 1
+    }).unwrap_or(false);
+    if inside(a.first_point(), b) || inside(b.first_point(), a) {
+        Some((ClashKind::Contained, 0.0))
+    } else if best < clearance {
+        Some((ClashKind::Clearance, best))
+    } else {
+        None
+    }
+}
+
+/// Returns the interval which a triangle covers along the line where it
+/// crosses another triangle's plane (with normal `n` through `o`), measured
+/// along `dir`, or `None` unless its vertices are strictly on both sides
+fn plane_interval(t: &[DVec3; 3], n: DVec3, o: DVec3, dir: DVec3, tol: f64)
+    -> Option<(f64, f64)>
+{
+    let d = t.map(|p| n.dot(&(p - o)));
+    if !d.iter().any(|&d| d > tol) || !d.iter().any(|&d| d < -tol) {
+        return None;
+    }
+    let mut lo = f64::INFINITY;
+    let mut hi = -f64::INFINITY;
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        let p = if d[i].abs() <= tol {
+            t[i]
+        } else if (d[i] > tol && d[j] < -tol) || (d[i] < -tol && d[j] > tol) {
+            t[i] + (t[j] - t[i]) * (d[i] / (d[i] - d[j]))
+        } else {
+            continue;
+        };
+        let s = dir.dot(&p);
+        lo = lo.min(s);
+        hi = hi.max(s);
+    }
+    Some((lo, hi))
+}
+
+/// Checks whether two triangles cross each other, i.e. each one has
+/// vertices strictly on both sides of the other's plane and they overlap
+/// along the line where the planes meet.  Triangles which only touch (lying
+/// against each other, or meeting at an edge or a point) don't count.
+fn triangles_intersect(t: &[DVec3; 3], u: &[DVec3; 3]) -> bool {
+    let size = t.iter().chain(u)
+        .map(|p| (p - t[0]).norm())
+        .fold(0.0, f64::max);
+    let (nt, nu) = ((t[1] - t[0]).cross(&(t[2] - t[0])), (u[1] - u[0]).cross(&(u[2] - u[0])));
+    let dir = nt.cross(&nu);
+    let tol = CONTACT_EPSILON * size;
+    let a = plane_interval(t, nu, u[0], dir, tol * nu.norm());
+    let b = plane_interval(u, nt, t[0], dir, tol * nt.norm());
+    match (a, b) {
+        (Some(a), Some(b)) => a.1.min(b.1) - a.0.max(b.0) > tol * dir.norm(),
+        _ => false,
+    }
+}
+
+/// Returns the smallest distance between two (non-intersecting) triangles,
+/// which is found between a vertex and a triangle or between two edges
+fn triangle_distance(t: &[DVec3; 3], u: &[DVec3; 3]) -> f64 {
+    let mut best = f64::INFINITY;
+    for (a, b) in [(t, u), (u, t)] {
+        for p in a {
+            let q = closest_point_on_triangle(*p, b[0], b[1], b[2]);
+            best = best.min((q - p).norm());
+        }
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            best = best.min(segment_distance(t[i], t[(i + 1) % 3],
+                                             u[j], u[(j + 1) % 3]));
+        }
+    }
+    best
+}
+
+/// Returns the distance between segments `p1-q1` and `p2-q2`
+///
+/// This is the algorithm from Ericson's _Real-Time Collision Detection_,
+/// section 5.1.9.
+fn segment_distance(p1: DVec3, q1: DVec3, p2: DVec3, q2: DVec3) -> f64 {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.norm_squared();
+    let e = d2.norm_squared();
+    let f = d2.dot(&r);
+    let (s, t) = if a <= f64::EPSILON && e <= f64::EPSILON {
+        (0.0, 0.0)
+    } else if a <= f64::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(&r);
+        if e <= f64::EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(&d2);
+            let denom = a * e - b * b;
+            let s = if denom > 0.0 { ((b * f - c * e) / denom).clamp(0.0, 1.0) } else { 0.0 };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+    ((p1 + d1 * s) - (p2 + d2 * t)).norm()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cuboid, end_solid};
+
+    /// Adds an axis-aligned box to the mesh as a new solid
+    fn add_box(mesh: &mut Mesh, lo: DVec3, hi: DVec3) {
+        cuboid(mesh, lo, hi, false);
+        end_solid(mesh);
+    }
+
+    #[test]
+    fn finds_clashes() {
+        let mut mesh = Mesh::default();
+        add_box(&mut mesh, DVec3::zeros(), DVec3::repeat(1.0));
+        // Overlaps the first box
+        add_box(&mut mesh, DVec3::repeat(0.5), DVec3::repeat(1.5));
+        // Inside the first box
+        add_box(&mut mesh, DVec3::repeat(0.1), DVec3::repeat(0.2));
+        // Separated from the first box by a gap of 0.25
+        add_box(&mut mesh, DVec3::new(0.0, -1.0, 0.0), DVec3::new(1.0, -0.25, 1.0));
+
+        let clashes = find_clashes(&mesh, 0.0);
+        let pairs: Vec<_> = clashes.iter().map(|c| (c.a, c.b, c.kind)).collect();
+        assert_eq!(pairs, vec![(0, 1, ClashKind::Intersecting),
+                               (0, 2, ClashKind::Contained)]);
+
+        // With enough clearance, the separated box is reported too
+        let clashes = find_clashes(&mesh, 0.5);
+        let c = clashes.iter().find(|c| (c.a, c.b) == (0, 3)).unwrap();
+        assert_eq!(c.kind, ClashKind::Clearance);
+        assert!((c.distance - 0.25).abs() < 1e-9);
+        assert!(clashes.iter().all(|c| (c.a, c.b) != (1, 3)));
+    }
+
+    #[test]
+    fn touching_isnt_clashing() {
+        // Boxes stacked face to face, and a plate lying against the side of
+        // the first box
+        let mut mesh = Mesh::default();
+        add_box(&mut mesh, DVec3::zeros(), DVec3::repeat(1.0));
+        add_box(&mut mesh, DVec3::new(0.0, 0.0, 1.0), DVec3::new(1.0, 1.0, 2.0));
+        add_box(&mut mesh, DVec3::new(1.0, 0.25, 0.25), DVec3::new(1.1, 0.75, 0.75));
+        assert!(find_clashes(&mesh, 0.0).is_empty());
+
+        // They're still closer than any clearance
+        let clashes = find_clashes(&mesh, 0.01);
+        let pairs: Vec<_> = clashes.iter().map(|c| (c.a, c.b, c.kind)).collect();
+        assert_eq!(pairs, vec![(0, 1, ClashKind::Clearance), (0, 2, ClashKind::Clearance)]);
+        assert!(clashes.iter().all(|c| c.distance == 0.0));
+
+        // Pushing the plate into the box makes it clash
+        let mut mesh = Mesh::default();
+        add_box(&mut mesh, DVec3::zeros(), DVec3::repeat(1.0));
+        add_box(&mut mesh, DVec3::new(0.9, 0.25, 0.25), DVec3::new(1.1, 0.75, 0.75));
+        let clashes = find_clashes(&mesh, 0.0);
+        assert_eq!(clashes.len(), 1);
+        assert_eq!(clashes[0].kind, ClashKind::Intersecting);
+    }
+}
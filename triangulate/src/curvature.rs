@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3};
+
+use crate::mesh::{Face, Mesh};
+
+/// Returns per-vertex principal curvatures (the larger first), signed so
+/// that convex regions are positive relative to the vertex normals.
+///
+/// Curvatures are evaluated on each face's analytic surface where possible,
+/// falling back to a discrete estimate from the mesh's vertex normals for
+/// faces without geometry (or points which fail to evaluate).
+pub fn principal_curvatures(mesh: &Mesh) -> Vec<DVec2> {
+    let mut out = discrete_curvatures(mesh);
+    for face in &mesh.faces {
+        let mut done = HashSet::new();
+        for t in &mesh.triangles[face.triangles.clone()] {
+            for &v in t.verts.iter() {
+                if !done.insert(v) {
+                    continue;
+                }
+                let vert = &mesh.verts[v as usize];
+                if let Some(k) = analytic_curvature(face, vert.pos, vert.norm) {
+                    out[v as usize] = k;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Evaluates curvature on a face's surface, converting from the surface's
+/// local coordinates (including any scale in the face's transform)
+fn analytic_curvature(face: &Face, pos: DVec3, norm: DVec3) -> Option<DVec2> {
+    let surf = face.geometry.as_ref()?;
+    let mat = face.transform;
+    let mat_i = mat.try_inverse()?;
+    let local = (mat_i * pos.push(1.0)).xyz();
+    let k = surf.principal_curvatures(local)?;
+
+    let n = (mat * surf.normal(local, DVec2::zeros()).push(0.0)).xyz();
+    let scale = glm::mat4_to_mat3(&mat).determinant().abs().cbrt();
+    if scale < f64::EPSILON || !n.iter().all(|c| c.is_finite()) {
+        return None;
+    }
+    // Curvature is relative to the surface's own normal, which may point
+    // the opposite way from the mesh if the face is reversed
+    Some(if n.dot(&norm) < 0.0 {
+        DVec2::new(-k.y, -k.x)
+    } else {
+        k
+    } / scale)
+}
+
+/// Estimates principal curvatures as the extreme normal curvatures along
+/// each vertex's edges, where the normal curvature along edge `ij` is
+/// `(n_i - n_j) · (p_i - p_j) / |p_i - p_j|²`
+fn discrete_curvatures(mesh: &Mesh) -> Vec<DVec2> {
+    let mut lo = vec![f64::INFINITY; mesh.verts.len()];
+    let mut hi = vec![-f64::INFINITY; mesh.verts.len()];
+    for t in &mesh.triangles {
+        for i in 0..3 {
+            let (a, b) = (t.verts[i] as usize, t.verts[(i + 1) % 3] as usize);
+            let (va, vb) = (&mesh.verts[a], &mesh.verts[b]);
+            let d = va.pos - vb.pos;
+            let k = (va.norm - vb.norm).dot(&d) / d.norm_squared();
+            if !k.is_finite() {
+                continue;
+            }
+            for v in [a, b] {
+                lo[v] = lo[v].min(k);
+                hi[v] = hi[v].max(k);
+            }
+        }
+    }
+    lo.into_iter().zip(hi)
+        .map(|(lo, hi)| if lo <= hi { DVec2::new(hi, lo) } else { DVec2::zeros() })
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use glm::{DMat4, U32Vec3};
+    use crate::{
+        mesh::{SurfaceType, Triangle, Vertex},
+        surface::Surface,
+    };
+
+    /// Builds an octahedron inscribed in a sphere of the given radius, with
+    /// normals pointing outwards
+    fn octahedron(radius: f64) -> Mesh {
+        let dirs = [DVec3::x(), -DVec3::x(), DVec3::y(),
+                    -DVec3::y(), DVec3::z(), -DVec3::z()];
+        let verts = dirs.iter().map(|d| Vertex {
+            pos: d * radius,
+            norm: *d,
+            color: DVec3::zeros(),
+            uv: DVec2::zeros(),
+        }).collect();
+        let triangles = [[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+                         [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5]]
+            .iter()
+            .map(|t| Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) })
+            .collect();
+        Mesh { verts, triangles, ..Default::default() }
+    }
+
+    #[test]
+    fn discrete_sphere() {
+        for k in principal_curvatures(&octahedron(2.0)) {
+            assert!((k.x - 0.5).abs() < 1e-9 && (k.y - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn analytic_sphere() {
+        let mut mesh = octahedron(2.0);
+        mesh.faces.push(Face {
+            id: 0,
+            triangles: 0..mesh.triangles.len(),
+            surface: SurfaceType::Sphere,
+            dimensions: None,
+            geometry: Some(Arc::new(Surface::new_sphere(DVec3::zeros(), 1.0))),
+            // The sphere is scaled up to match the mesh
+            transform: DMat4::new_scaling(2.0),
+            failed: false,
+            grid: None,
+        });
+        for k in principal_curvatures(&mesh) {
+            assert!((k - DVec2::repeat(0.5)).norm() < 1e-9);
+        }
+
+        // Flipping the mesh's normals flips the curvature's sign
+        for v in mesh.verts.iter_mut() {
+            v.norm = -v.norm;
+        }
+        for k in principal_curvatures(&mesh) {
+            assert!((k + DVec2::repeat(0.5)).norm() < 1e-9);
+        }
+    }
+}
@@ -1,8 +1,40 @@
 use nalgebra_glm as glm;
 use glm::{DVec3, DVec4, DMat4};
 
-use nurbs::{AbstractCurve, NDBSplineCurve, SampledCurve};
-use crate::surface::Surface;
+use nurbs::{AbstractCurve, ArcLength, NDBSplineCurve, SampledCurve};
+use crate::{surface::Surface, triangulate::TessellationParams};
+
+/// Number of segments in a full turn of a circle, without a tolerance
+const DEFAULT_SEGMENTS_PER_TURN: usize = 64;
+
+/// Smallest number of segments in a full turn of a circle, unless
+/// [`TessellationParams::min_segments_per_turn`] says otherwise
+const DEFAULT_MIN_SEGMENTS_PER_TURN: usize = 16;
+
+/// Number of samples per B-spline knot span, without a tolerance
+const DEFAULT_SAMPLES_PER_KNOT: usize = 8;
+
+/// Number of segments along a hyperbola or parabola, without a tolerance
+const DEFAULT_CONIC_SEGMENTS: usize = 16;
+
+/// Returns the number of segments needed to approximate a full circle of
+/// the given radius within the tolerance in `params`.  Small circles get at
+/// least the minimum number of segments in `params`, so that holes don't
+/// turn into visible polygons at coarse tolerances.
+pub fn segments_per_turn(radius: f64, params: &TessellationParams) -> usize {
+    let min = params.min_segments_per_turn
+        .unwrap_or(DEFAULT_MIN_SEGMENTS_PER_TURN)
+        .max(4);
+    match params.tolerance {
+        None => DEFAULT_SEGMENTS_PER_TURN.max(min),
+        Some(t) if t >= radius => min,
+        Some(t) => {
+            // A chord spanning angle a deviates from the arc by r(1 - cos(a/2))
+            let a = 2.0 * (1.0 - t / radius).acos();
+            ((2.0 * std::f64::consts::PI / a).ceil() as usize).clamp(min, 4096.max(min))
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Curve {
@@ -13,9 +45,35 @@ pub enum Curve {
         closed: bool,
         dir: bool
     },
+    /// A hyperbola, which is `a cosh(t)` along its plane's X axis and
+    /// `b sinh(t)` along its Y axis (the branch with positive X)
+    Hyperbola {
+        plane_from_world: DMat4,
+        world_from_plane: DMat4,
+        a: f64,
+        b: f64,
+    },
+    /// A parabola, which is `f t²` along its plane's X axis and `2 f t`
+    /// along its Y axis, where `f` is the distance to its focus
+    Parabola {
+        plane_from_world: DMat4,
+        world_from_plane: DMat4,
+        f: f64,
+    },
     Line,
     BSplineCurveWithKnots(SampledCurve<3>),
     NURBSCurve(SampledCurve<4>),
+    /// A polyline, with its points in the order in which it's walked
+    Polyline {
+        points: Vec<DVec3>,
+        closed: bool,
+    },
+    /// The segments of a composite curve, in the order in which they're
+    /// walked, each with its start and end points
+    Composite {
+        segments: Vec<(Curve, DVec3, DVec3)>,
+        closed: bool,
+    },
 }
 
 impl Curve {
@@ -44,26 +102,271 @@ impl Curve {
                           radius, radius, closed, dir)
     }
 
+    /// Builds a rigid transform from a conic's plane, with its X axis
+    /// along the reference direction
+    fn conic_plane(location: DVec3, axis: DVec3, ref_direction: DVec3)
+        -> (DMat4, DMat4)
+    {
+        let axis = axis.normalize();
+        let x = (ref_direction - axis * ref_direction.dot(&axis)).normalize();
+        let world_from_plane = Surface::make_affine_transform(
+            axis, x, axis.cross(&x), location);
+        let plane_from_world = world_from_plane
+            .try_inverse()
+            .expect("Could not invert");
+        (plane_from_world, world_from_plane)
+    }
+
+    pub fn new_hyperbola(location: DVec3, axis: DVec3, ref_direction: DVec3,
+                         semi_axis: f64, semi_imag_axis: f64) -> Self {
+        let (plane_from_world, world_from_plane) =
+            Self::conic_plane(location, axis, ref_direction);
+        Self::Hyperbola {
+            plane_from_world, world_from_plane,
+            a: semi_axis, b: semi_imag_axis,
+        }
+    }
+
+    pub fn new_parabola(location: DVec3, axis: DVec3, ref_direction: DVec3,
+                        focal_dist: f64) -> Self {
+        let (plane_from_world, world_from_plane) =
+            Self::conic_plane(location, axis, ref_direction);
+        Self::Parabola { plane_from_world, world_from_plane, f: focal_dist }
+    }
+
+    /// Evaluates a hyperbola or parabola in its own plane
+    fn conic_point(&self, t: f64) -> Option<DVec3> {
+        let (world_from_plane, x, y) = match self {
+            Self::Hyperbola { world_from_plane, a, b, .. } =>
+                (world_from_plane, a * t.cosh(), b * t.sinh()),
+            Self::Parabola { world_from_plane, f, .. } =>
+                (world_from_plane, f * t * t, 2.0 * f * t),
+            _ => return None,
+        };
+        Some(glm::vec4_to_vec3(&(world_from_plane * DVec4::new(x, y, 0.0, 1.0))))
+    }
+
+    /// Finds the parameter of a point on a hyperbola or parabola, from the
+    /// point's position along the conic's Y axis
+    fn conic_param(&self, p: DVec3) -> f64 {
+        match self {
+            Self::Hyperbola { plane_from_world, b, .. } =>
+                ((plane_from_world * DVec4::new(p.x, p.y, p.z, 1.0)).y / b).asinh(),
+            Self::Parabola { plane_from_world, f, .. } =>
+                (plane_from_world * DVec4::new(p.x, p.y, p.z, 1.0)).y / (2.0 * f),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the length of the derivative of a hyperbola or parabola
+    fn conic_speed(&self, t: f64) -> f64 {
+        match self {
+            Self::Hyperbola { a, b, .. } =>
+                (a * t.sinh()).hypot(b * t.cosh()),
+            Self::Parabola { f, .. } => 2.0 * f * t.hypot(1.0),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Samples a hyperbola or parabola with points evenly spaced along it,
+    /// doubling the number of samples until the polyline is within tolerance
+    fn conic_points(&self, u: DVec3, v: DVec3, params: &TessellationParams)
+        -> Vec<DVec3>
+    {
+        let (t0, t1) = (self.conic_param(u), self.conic_param(v));
+        let length = ArcLength::new(|t| self.conic_speed(t), t0, t1, &[]);
+        // Doubling the sample count reproduces exactly the same points (as
+        // every other sample), which the tolerance check relies on
+        let sample = |n: usize| -> Vec<DVec3> {
+            let mut ts = length.uniform_params(n);
+            if t0 > t1 {
+                ts.reverse();
+            }
+            ts.into_iter().filter_map(|t| self.conic_point(t)).collect()
+        };
+        let mut n = DEFAULT_CONIC_SEGMENTS;
+        let mut c = sample(n);
+        if let Some(tol) = params.tolerance {
+            while n < 4096 {
+                let fine = sample(n * 2);
+                let err = Self::polyline_deviation_(&c, &fine);
+                c = fine;
+                n *= 2;
+                if err <= tol {
+                    break;
+                }
+            }
+        }
+        c[0] = u;
+        *c.last_mut().unwrap() = v;
+        c
+    }
+
     pub fn new_line() -> Self {
         Self::Line
     }
 
-    fn curve_points<const N: usize>(u: DVec3, v: DVec3, curve: &SampledCurve<N>) -> Vec<DVec3>
+    /// Evaluates the curve at a parameter, which is an angle in radians for
+    /// ellipses.  Returns `None` for curves which can't be evaluated on
+    /// their own (lines only store their endpoints)
+    pub fn point(&self, t: f64) -> Option<DVec3> {
+        match self {
+            Self::Ellipse { world_from_eplane, .. } => Some(glm::vec4_to_vec3(
+                &(world_from_eplane * DVec4::new(t.cos(), t.sin(), 0.0, 1.0)))),
+            Self::BSplineCurveWithKnots(curve) => Some(curve.point(t)),
+            Self::NURBSCurve(curve) => Some(curve.point(t)),
+            Self::Hyperbola { .. } | Self::Parabola { .. } => self.conic_point(t),
+            Self::Line | Self::Polyline { .. } | Self::Composite { .. } => None,
+        }
+    }
+
+    /// Returns the start and end points of a bounded curve
+    pub fn ends(&self) -> Option<(DVec3, DVec3)> {
+        match self {
+            Self::BSplineCurveWithKnots(curve) => Some(curve.ends()),
+            Self::NURBSCurve(curve) => Some(curve.ends()),
+            Self::Polyline { points, .. } =>
+                Some((*points.first()?, *points.last()?)),
+            Self::Composite { segments, .. } =>
+                Some((segments.first()?.1, segments.last()?.2)),
+            Self::Line | Self::Ellipse { .. } | Self::Hyperbola { .. }
+                | Self::Parabola { .. } => None,
+        }
+    }
+
+    /// Returns the position of the nearest point to `p` along a polyline,
+    /// as a segment index plus the fraction of the way along that segment
+    fn polyline_position(points: &[DVec3], p: DVec3) -> f64 {
+        let mut best = (f64::INFINITY, 0.0);
+        for (i, w) in points.windows(2).enumerate() {
+            let d = w[1] - w[0];
+            let t = ((p - w[0]).dot(&d) / d.norm_squared().max(f64::EPSILON))
+                .clamp(0.0, 1.0);
+            let dist = (p - (w[0] + d * t)).norm();
+            if dist < best.0 {
+                best = (dist, i as f64 + t);
+            }
+        }
+        best.1
+    }
+
+    /// Returns the part of a polyline between the points nearest to `u` and
+    /// `v`, walking forward from `u`.  If the polyline is a loop, the walk
+    /// may wrap around its start, and `closed` walks all the way around.
+    fn clip_polyline(points: &[DVec3], u: DVec3, v: DVec3, closed: bool)
+        -> Vec<DVec3>
+    {
+        let n = points.len();
+        if n < 2 {
+            return vec![u, v];
+        }
+        let length: f64 = points.windows(2).map(|w| (w[1] - w[0]).norm()).sum();
+        let ring = n > 2 && (points[0] - points[n - 1]).norm() <= length * 1e-9;
+
+        let pu = Self::polyline_position(points, u);
+        let pv = if closed {
+            pu
+        } else {
+            Self::polyline_position(points, v)
+        };
+        let between = |lo: f64, hi: f64| (0..n)
+            .filter(move |&k| (k as f64) > lo && (k as f64) < hi)
+            .map(|k| points[k]);
+
+        let mut out = vec![u];
+        if closed && !ring {
+            out.extend(&points[1..n - 1]);
+        } else if pu < pv || (pu == pv && !closed) {
+            out.extend(between(pu, pv));
+        } else if ring {
+            // The last point of a ring is the same as its first, so it's
+            // skipped when wrapping around
+            out.extend(between(pu, (n - 1) as f64));
+            out.extend(between(-1.0, pv));
+        } else {
+            let mut rev: Vec<DVec3> = between(pv, pu).collect();
+            rev.reverse();
+            out.extend(rev);
+        }
+        out.push(v);
+        out
+    }
+
+    fn curve_points<const N: usize>(u: DVec3, v: DVec3, curve: &SampledCurve<N>,
+                                    params: &TessellationParams) -> Vec<DVec3>
         where NDBSplineCurve<N>: AbstractCurve
     {
         let t_start = curve.u_from_point(u);
         let t_end = curve.u_from_point(v);
-        let mut c = curve.as_polyline(t_start, t_end, 8);
+        // Points are spaced evenly along the curve, rather than in its
+        // parameter, which would cluster them where the parameter slows down
+        let mut n = DEFAULT_SAMPLES_PER_KNOT;
+        let mut c = curve.as_polyline_by_length(t_start, t_end, n);
+        // With a tolerance, keep doubling the sampling rate until the finer
+        // polyline is within tolerance of the coarser one
+        if let Some(tol) = params.tolerance {
+            while n < 1024 {
+                let fine = curve.as_polyline_by_length(t_start, t_end, n * 2);
+                let err = Self::polyline_deviation_(&c, &fine);
+                c = fine;
+                n *= 2;
+                if err <= tol {
+                    break;
+                }
+            }
+        }
         c[0] = u;
         *c.last_mut().unwrap() = v;
         c
     }
 
-    pub fn build(&self, u: DVec3, v: DVec3) -> Vec<DVec3> {
+    /// Returns the largest distance from a point in `fine` to `coarse`,
+    /// where the points of `coarse` are a subsequence of those in `fine`.
+    fn polyline_deviation_(coarse: &[DVec3], fine: &[DVec3]) -> f64 {
+        let mut j = 0;
+        let mut err: f64 = 0.0;
+        for p in fine {
+            if j + 1 < coarse.len() && *p == coarse[j + 1] {
+                j += 1;
+            } else if j + 1 < coarse.len() {
+                let (a, b) = (coarse[j], coarse[j + 1]);
+                let d = b - a;
+                let t = ((p - a).dot(&d) / d.norm_squared().max(f64::EPSILON))
+                    .clamp(0.0, 1.0);
+                err = err.max((p - (a + d * t)).norm());
+            }
+        }
+        err
+    }
+
+    /// Samples the curve between two points, which must lie on the curve
+    pub fn build(&self, u: DVec3, v: DVec3, params: &TessellationParams)
+        -> Vec<DVec3>
+    {
         match self {
             Self::Line => vec![u, v],
-            Self::BSplineCurveWithKnots(curve) => Self::curve_points(u, v, curve),
-            Self::NURBSCurve(curve) => Self::curve_points(u, v, curve),
+            Self::BSplineCurveWithKnots(curve) =>
+                Self::curve_points(u, v, curve, params),
+            Self::NURBSCurve(curve) =>
+                Self::curve_points(u, v, curve, params),
+            Self::Hyperbola { .. } | Self::Parabola { .. } =>
+                self.conic_points(u, v, params),
+            Self::Polyline { points, closed } =>
+                Self::clip_polyline(points, u, v, *closed),
+            Self::Composite { segments, closed } => {
+                // Sample each segment separately, then trim the whole curve
+                // to the requested points.  Segments which continue from
+                // one another share an end point, which is only kept once.
+                let mut points: Vec<DVec3> = Vec::new();
+                for (c, a, b) in segments {
+                    let p = c.build(*a, *b, params);
+                    if points.last() == p.first() {
+                        points.pop();
+                    }
+                    points.extend(p);
+                }
+                Self::clip_polyline(&points, u, v, *closed)
+            },
             Self::Ellipse {
                 eplane_from_world, world_from_eplane, closed, dir
             } => {
@@ -90,10 +393,18 @@ impl Curve {
                     v_ang -= PI2;
                 }
 
-                const N: usize = 64;
-                let count = 4.max(
-                    (N as f64 * (u_ang - v_ang).abs() /
-                    (2.0 * std::f64::consts::PI)).round() as usize);
+                // The ellipse's larger radius is the length of the longer
+                // of the plane's two in-plane axes
+                let radius = world_from_eplane.column(0).xyz().norm()
+                    .max(world_from_eplane.column(1).xyz().norm());
+                let turns = (u_ang - v_ang).abs() / (2.0 * std::f64::consts::PI);
+                let n = segments_per_turn(radius, params);
+                // Number of points, including both ends.  With a tolerance,
+                // we round up to guarantee that it's met.
+                let count = 4.max(match params.tolerance {
+                    None => (n as f64 * turns).round() as usize,
+                    Some(_) => (n as f64 * turns).ceil() as usize + 1,
+                });
 
                 let mut out_world = vec![u];
                 // Walk around the circle, using the true positions for start
@@ -113,3 +424,173 @@ impl Curve {
         }
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle_within_tolerance() {
+        let radius = 10.0;
+        for &tol in &[1.0, 0.1, 0.001] {
+            let params = TessellationParams { tolerance: Some(tol), ..Default::default() };
+            let c = Curve::new_circle(DVec3::zeros(), DVec3::z(), DVec3::x(),
+                                      radius, true, true);
+            let p = DVec3::new(radius, 0.0, 0.0);
+            let pts = c.build(p, p, &params);
+            for w in pts.windows(2) {
+                // The midpoint of each chord is its farthest point from
+                // the circle
+                let mid = (w[0] + w[1]) / 2.0;
+                assert!(radius - mid.norm() <= tol * 1.0001,
+                        "{} > {}", radius - mid.norm(), tol);
+            }
+        }
+        // Without a tolerance, we get the fixed segment count
+        let c = Curve::new_circle(DVec3::zeros(), DVec3::z(), DVec3::x(),
+                                  radius, true, true);
+        let p = DVec3::new(radius, 0.0, 0.0);
+        assert_eq!(c.build(p, p, &TessellationParams::default()).len(),
+                   DEFAULT_SEGMENTS_PER_TURN);
+    }
+
+    #[test]
+    fn spline_evenly_spaced() {
+        // A quadratic spline which runs along the X axis, but whose
+        // parameter moves slowly near its start and quickly near its end
+        let knots = nurbs::KnotVector::from_multiplicities(2, &[0.0, 1.0], &[3, 3]);
+        let curve = NDBSplineCurve::new(false, knots, vec![
+            DVec3::zeros(), DVec3::new(0.5, 0.0, 0.0), DVec3::new(10.0, 0.0, 0.0)]);
+        let c = Curve::BSplineCurveWithKnots(SampledCurve::new(curve));
+        let pts = c.build(DVec3::zeros(), DVec3::new(10.0, 0.0, 0.0),
+                          &TessellationParams::default());
+        let step = 10.0 / (pts.len() - 1) as f64;
+        for w in pts.windows(2) {
+            let d = (w[1] - w[0]).norm();
+            assert!((d - step).abs() < step * 0.01, "{} != {}", d, step);
+        }
+    }
+
+    #[test]
+    fn small_circles() {
+        // A tolerance larger than the circle would allow a square, but the
+        // minimum segment count keeps it round
+        let coarse = TessellationParams { tolerance: Some(1.0), ..Default::default() };
+        assert_eq!(segments_per_turn(0.5, &coarse), DEFAULT_MIN_SEGMENTS_PER_TURN);
+        assert_eq!(segments_per_turn(0.5, &TessellationParams {
+            min_segments_per_turn: Some(6), ..coarse
+        }), 6);
+        // ...which never drops below a square
+        assert_eq!(segments_per_turn(0.5, &TessellationParams {
+            min_segments_per_turn: Some(0), ..coarse
+        }), 4);
+        // Without a tolerance, a larger minimum also applies
+        assert_eq!(segments_per_turn(0.5, &TessellationParams {
+            min_segments_per_turn: Some(100), ..Default::default()
+        }), 100);
+        // Big circles are unaffected
+        assert!(segments_per_turn(1000.0, &coarse) > DEFAULT_MIN_SEGMENTS_PER_TURN);
+
+        let c = Curve::new_circle(DVec3::zeros(), DVec3::z(), DVec3::x(),
+                                  0.5, true, true);
+        let p = DVec3::new(0.5, 0.0, 0.0);
+        assert_eq!(c.build(p, p, &coarse).len(), DEFAULT_MIN_SEGMENTS_PER_TURN + 1);
+    }
+
+    #[test]
+    fn open_conics() {
+        // A hyperbola and parabola in the XZ plane, opening along +X (with
+        // their Y axes along +Z)
+        let hyperbola = Curve::new_hyperbola(DVec3::zeros(), -DVec3::y(), DVec3::x(),
+                                              2.0, 1.0);
+        let parabola = Curve::new_parabola(DVec3::zeros(), -DVec3::y(), DVec3::x(),
+                                           0.5);
+        // Implicit equations, which are zero on each curve
+        let on_hyperbola = |p: &DVec3| (p.x / 2.0).powi(2) - p.z.powi(2) - 1.0;
+        let on_parabola = |p: &DVec3| p.z * p.z - 4.0 * 0.5 * p.x;
+        type Case<'a> = (&'a Curve, &'a dyn Fn(&DVec3) -> f64, &'a str);
+        let cases: [Case; 2] = [
+            (&hyperbola, &on_hyperbola, "hyperbola"),
+            (&parabola, &on_parabola, "parabola"),
+        ];
+
+        for (c, on, name) in cases {
+            let (u, v) = (c.point(-1.5).unwrap(), c.point(1.0).unwrap());
+            assert!(on(&u).abs() < 1e-9, "{} start is not on the curve", name);
+            assert!(u.z < 0.0 && v.z > 0.0);
+
+            let tol = 1e-4;
+            let params = TessellationParams { tolerance: Some(tol), ..Default::default() };
+            let pts = c.build(u, v, &params);
+            assert_eq!((pts[0], pts[pts.len() - 1]), (u, v));
+            assert!(pts.iter().all(|p| on(p).abs() < 1e-9 && p.y == 0.0));
+            // Every chord stays close to the curve at its midpoint
+            for w in pts.windows(2) {
+                let (t0, t1) = (c.conic_param(w[0]), c.conic_param(w[1]));
+                let mid = c.point((t0 + t1) / 2.0).unwrap();
+                let d = w[1] - w[0];
+                let s = ((mid - w[0]).dot(&d) / d.norm_squared()).clamp(0.0, 1.0);
+                assert!((mid - (w[0] + d * s)).norm() <= tol * 1.01, "{}", name);
+            }
+
+            // Walking the other way gives the same points, reversed
+            let mut rev = c.build(v, u, &params);
+            rev.reverse();
+            assert_eq!(rev.len(), pts.len());
+            for (a, b) in rev.iter().zip(&pts) {
+                assert!((a - b).norm() < 1e-9, "{}: {:?} vs {:?}", name, a, b);
+            }
+        }
+    }
+
+    /// Builds a unit circle around the Z axis from two half circles
+    fn arcs(closed: bool) -> Curve {
+        let (a, b) = (DVec3::new(1.0, 0.0, 0.0), DVec3::new(-1.0, 0.0, 0.0));
+        let half = || Curve::new_circle(DVec3::zeros(), DVec3::z(), DVec3::x(),
+                                        1.0, false, true);
+        let (top, bottom) = (half(), half());
+        Curve::Composite { segments: vec![(top, a, b), (bottom, b, a)], closed }
+    }
+
+    #[test]
+    fn composite_of_arcs() {
+        let (a, b) = (DVec3::new(1.0, 0.0, 0.0), DVec3::new(-1.0, 0.0, 0.0));
+        let c = arcs(false);
+        assert_eq!(c.ends(), Some((a, a)));
+
+        let params = TessellationParams::default();
+        let pts = c.build(a, b, &params);
+        assert_eq!(pts.len(), DEFAULT_SEGMENTS_PER_TURN / 2);
+        assert!(pts.iter().all(|p| p.y >= 0.0));
+
+        // Walking from the bottom of the circle to the top, which crosses
+        // the joint between the two segments
+        let (lo, hi) = (DVec3::new(0.0, -1.0, 0.0), DVec3::new(0.0, 1.0, 0.0));
+        let pts = c.build(lo, hi, &params);
+        assert_eq!((pts[0], pts[pts.len() - 1]), (lo, hi));
+        assert!(pts.iter().all(|p| p.x >= -1e-9 && (p.norm() - 1.0).abs() < 1e-9));
+        for w in pts.windows(2) {
+            assert!(w[0] != w[1]);
+        }
+
+        // A closed walk goes all the way around, through both halves (whose
+        // shared points are only kept once)
+        assert_eq!(arcs(true).build(a, a, &params).len(),
+                   DEFAULT_SEGMENTS_PER_TURN - 1);
+    }
+
+    #[test]
+    fn clip_polyline() {
+        let points = vec![DVec3::new(0.0, 0.0, 0.0), DVec3::new(1.0, 0.0, 0.0),
+                          DVec3::new(2.0, 0.0, 0.0), DVec3::new(3.0, 0.0, 0.0)];
+        let c = Curve::Polyline { points, closed: false };
+        let (u, v) = (DVec3::new(0.5, 0.0, 0.0), DVec3::new(2.5, 0.0, 0.0));
+        assert_eq!(c.build(u, v, &TessellationParams::default()),
+                   vec![u, DVec3::new(1.0, 0.0, 0.0), DVec3::new(2.0, 0.0, 0.0), v]);
+        // Walking backwards along an open polyline
+        assert_eq!(c.build(v, u, &TessellationParams::default()),
+                   vec![v, DVec3::new(2.0, 0.0, 0.0), DVec3::new(1.0, 0.0, 0.0), u]);
+    }
+}
@@ -0,0 +1,510 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::convert::TryInto;
+
+use nalgebra_glm as glm;
+use glm::{DMat3, DMat4, DVec3, DVec4, U32Vec3};
+
+use crate::mesh::{Face, Mesh, Solid, Triangle};
+
+/// When to stop collapsing edges
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    /// Collapse edges until there are at most this many triangles
+    Triangles(usize),
+    /// Only perform collapses which keep the surface within roughly this
+    /// distance of the original triangles (measured as the RMS distance from
+    /// a collapsed vertex to the planes of the triangles that it replaced)
+    Error(f64),
+}
+
+/// An error quadric, in the style of Garland and Heckbert's "Surface
+/// Simplification Using Quadric Error Metrics".  Planes are weighted by
+/// triangle area, so `area` is tracked to turn errors back into distances.
+#[derive(Copy, Clone, Debug)]
+struct Quadric {
+    q: DMat4,
+    area: f64,
+}
+
+impl Quadric {
+    fn zero() -> Self {
+        Self { q: DMat4::zeros(), area: 0.0 }
+    }
+
+    fn from_triangle(a: DVec3, b: DVec3, c: DVec3) -> Self {
+        let n = (b - a).cross(&(c - a));
+        let len = n.norm();
+        if len == 0.0 {
+            return Self::zero();
+        }
+        let n = n / len;
+        let p = DVec4::new(n.x, n.y, n.z, -n.dot(&a));
+        let area = len / 2.0;
+        Self { q: p * p.transpose() * area, area }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Self { q: self.q + other.q, area: self.area + other.area }
+    }
+
+    /// Returns the RMS distance from `p` to the quadric's planes
+    fn error(&self, p: DVec3) -> f64 {
+        if self.area == 0.0 {
+            return 0.0;
+        }
+        let v = p.push(1.0);
+        (v.dot(&(self.q * v)).max(0.0) / self.area).sqrt()
+    }
+
+    /// Returns the point which minimizes the error, if it's well-defined
+    fn optimum(&self) -> Option<DVec3> {
+        let a: DMat3 = self.q.fixed_slice::<3, 3>(0, 0).into();
+        let b = DVec3::new(self.q[(0, 3)], self.q[(1, 3)], self.q[(2, 3)]);
+        // Flat and cylindrical regions have a singular matrix, so require it
+        // to be reasonably well-conditioned (relative to its scale)
+        let scale = a.norm();
+        if scale == 0.0 || a.determinant().abs() < scale.powi(3) * 1e-6 {
+            return None;
+        }
+        a.try_inverse().map(|a_i| -(a_i * b))
+    }
+}
+
+/// A possible edge collapse, which merges `remove` into `keep` and moves
+/// `keep` to `pos`.  The version stamps are used to discard candidates after
+/// either vertex changes.
+struct Candidate {
+    error: f64,
+    keep: u32,
+    remove: u32,
+    stamp: (u32, u32),
+    pos: DVec3,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    /// Reversed, so that `BinaryHeap` pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+            .then_with(|| (other.keep, other.remove).cmp(&(self.keep, self.remove)))
+    }
+}
+
+struct Decimator<'a> {
+    mesh: &'a Mesh,
+    pos: Vec<DVec3>,
+    norm: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    /// Locked vertices are never moved or removed
+    locked: Vec<bool>,
+    version: Vec<u32>,
+    /// Triangles which use each vertex (including dead triangles, which are
+    /// skipped when iterating)
+    tris_of: Vec<Vec<usize>>,
+    tris: Vec<U32Vec3>,
+    alive: Vec<bool>,
+    /// Each triangle counts this many times towards the total, because
+    /// instanced copies of its solid are updated along with it
+    weight: Vec<usize>,
+    heap: BinaryHeap<Candidate>,
+}
+
+impl<'a> Decimator<'a> {
+    fn new(mesh: &'a Mesh) -> Self {
+        let nv = mesh.verts.len();
+        let nt = mesh.triangles.len();
+
+        // Face and solid indices for each triangle, along with whether it's
+        // part of an instanced copy (which is rebuilt from its original)
+        let mut face = vec![usize::MAX; nt];
+        for (i, f) in mesh.faces.iter().enumerate() {
+            face[f.triangles.clone()].iter_mut().for_each(|t| *t = i);
+        }
+        let mut frozen = vec![false; nt];
+        let mut weight = vec![1; nt];
+        for s in &mesh.solids {
+            if let Some(i) = s.instance_of {
+                frozen[s.triangles.clone()].iter_mut().for_each(|t| *t = true);
+                weight[mesh.solids[i].triangles.clone()].iter_mut()
+                    .for_each(|w| *w += 1);
+            }
+        }
+
+        // Find vertices on face boundaries, which must stay put so that
+        // neighboring faces still meet.  These are vertices of edges used
+        // by a single triangle (or more than two), and vertices which are
+        // shared between faces.
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        let mut locked = vec![false; nv];
+        let mut owner = vec![usize::MAX; nv];
+        let mut tris_of = vec![Vec::new(); nv];
+        for (i, t) in mesh.triangles.iter().enumerate() {
+            for j in 0..3 {
+                let (a, b) = (t.verts[j], t.verts[(j + 1) % 3]);
+                let v = a as usize;
+                if frozen[i] {
+                    locked[v] = true;
+                } else if owner[v] == usize::MAX {
+                    owner[v] = face[i];
+                } else if owner[v] != face[i] {
+                    locked[v] = true;
+                }
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+                tris_of[v].push(i);
+            }
+        }
+        for (&(a, b), &n) in &edges {
+            if n != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+
+        let pos: Vec<DVec3> = mesh.verts.iter().map(|v| v.pos).collect();
+        let mut quadrics = vec![Quadric::zero(); nv];
+        for t in &mesh.triangles {
+            let q = Quadric::from_triangle(pos[t.verts.x as usize],
+                                           pos[t.verts.y as usize],
+                                           pos[t.verts.z as usize]);
+            for v in t.verts.iter() {
+                quadrics[*v as usize] = quadrics[*v as usize].add(&q);
+            }
+        }
+
+        let mut out = Self {
+            mesh,
+            pos,
+            norm: mesh.verts.iter().map(|v| v.norm).collect(),
+            quadrics,
+            locked,
+            version: vec![0; nv],
+            tris_of,
+            tris: mesh.triangles.iter().map(|t| t.verts).collect(),
+            alive: vec![true; nt],
+            weight,
+            heap: BinaryHeap::new(),
+        };
+        let mut keys: Vec<(u32, u32)> = edges.into_iter()
+            .filter(|(_, n)| *n == 2)
+            .map(|(k, _)| k)
+            .collect();
+        keys.sort_unstable(); // for deterministic output
+        for (a, b) in keys {
+            out.push_candidate(a, b, face[out.tris_of[a as usize][0]]);
+        }
+        out
+    }
+
+    /// Computes the cost of collapsing the edge `a-b` and adds it to the heap
+    fn push_candidate(&mut self, a: u32, b: u32, face: usize) {
+        let (ia, ib) = (a as usize, b as usize);
+        let (keep, remove) = match (self.locked[ia], self.locked[ib]) {
+            (true, true) => return,
+            (true, false) => (a, b),
+            (false, true) => (b, a),
+            (false, false) => (a, b),
+        };
+        let q = self.quadrics[ia].add(&self.quadrics[ib]);
+        let (pa, pb) = (self.pos[ia], self.pos[ib]);
+        let pos = if self.locked[keep as usize] {
+            self.pos[keep as usize]
+        } else {
+            let mut options = vec![pa, pb, (pa + pb) / 2.0];
+            options.extend(q.optimum());
+            let best = options.into_iter()
+                .min_by(|x, y| q.error(*x).total_cmp(&q.error(*y)))
+                .unwrap();
+            // Snap merged vertices back onto curved surfaces, unless the
+            // projection jumps somewhere unreasonable
+            let length = (pa - pb).norm();
+            match self.mesh.faces.get(face).and_then(|f| f.project(best)) {
+                Some(p) if (p - best).norm() <= length => p,
+                _ => best,
+            }
+        };
+        self.heap.push(Candidate {
+            error: q.error(pos),
+            keep,
+            remove,
+            stamp: (self.version[keep as usize], self.version[remove as usize]),
+            pos,
+        });
+    }
+
+    /// Returns the distinct neighbors of a vertex
+    fn neighbors(&self, v: u32) -> HashSet<u32> {
+        self.tris_of[v as usize].iter()
+            .filter(|t| self.alive[**t])
+            .flat_map(|t| self.tris[*t].iter().copied())
+            .filter(|n| *n != v)
+            .collect()
+    }
+
+    /// Checks whether a collapse keeps the mesh manifold and doesn't flip
+    /// any triangles
+    fn is_valid(&self, c: &Candidate) -> bool {
+        let shared = self.tris_of[c.keep as usize].iter()
+            .filter(|t| self.alive[**t] && self.tris[**t].iter().any(|v| *v == c.remove))
+            .count();
+        let common = self.neighbors(c.keep)
+            .intersection(&self.neighbors(c.remove))
+            .count();
+        if common != shared {
+            return false;
+        }
+        for v in [c.keep, c.remove] {
+            for &t in &self.tris_of[v as usize] {
+                let tri = self.tris[t];
+                if !self.alive[t] || tri.iter().any(|u| *u == c.keep)
+                                  && tri.iter().any(|u| *u == c.remove)
+                {
+                    continue;
+                }
+                let p = |u: u32| self.pos[u as usize];
+                let before = (p(tri.y) - p(tri.x)).cross(&(p(tri.z) - p(tri.x)));
+                let moved = |u: u32| if u == v { c.pos } else { p(u) };
+                let after = (moved(tri.y) - moved(tri.x))
+                    .cross(&(moved(tri.z) - moved(tri.x)));
+                if after.norm() == 0.0 ||
+                    after.dot(&before) < 0.5 * after.norm() * before.norm()
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Performs a collapse, returning the (weighted) number of triangles
+    /// which were removed
+    fn collapse(&mut self, c: &Candidate) -> usize {
+        let (keep, remove) = (c.keep as usize, c.remove as usize);
+        let mut removed = 0;
+        for t in std::mem::take(&mut self.tris_of[remove]) {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.tris[t].iter().any(|u| *u == c.keep) {
+                self.alive[t] = false;
+                removed += self.weight[t];
+            } else {
+                self.tris[t].iter_mut().filter(|u| **u == c.remove)
+                    .for_each(|u| *u = c.keep);
+                self.tris_of[keep].push(t);
+            }
+        }
+        let alive = &self.alive;
+        self.tris_of[keep].retain(|t| alive[*t]);
+
+        if !self.locked[keep] {
+            let n = self.norm[keep] + self.norm[remove];
+            if n.norm() > 0.0 {
+                self.norm[keep] = n.normalize();
+            }
+        }
+        self.pos[keep] = c.pos;
+        self.quadrics[keep] = self.quadrics[keep].add(&self.quadrics[remove]);
+        self.version[keep] += 1;
+        self.version[remove] += 1;
+
+        let face = self.tris_of[keep].first()
+            .and_then(|t| self.mesh.face_index(*t))
+            .unwrap_or(usize::MAX);
+        let mut ns: Vec<u32> = self.neighbors(c.keep).into_iter().collect();
+        ns.sort_unstable();
+        for n in ns {
+            self.push_candidate(c.keep, n, face);
+        }
+        removed
+    }
+
+    fn run(&mut self, target: Target) {
+        let mut count: usize = self.alive.iter().enumerate()
+            .filter(|(_, a)| **a)
+            .map(|(i, _)| self.weight[i])
+            .sum();
+        while let Some(c) = self.heap.pop() {
+            match target {
+                Target::Triangles(n) if count <= n => break,
+                Target::Error(e) if c.error > e => break,
+                _ => (),
+            }
+            let stamp = (self.version[c.keep as usize],
+                         self.version[c.remove as usize]);
+            if stamp != c.stamp || !self.is_valid(&c) {
+                continue;
+            }
+            count -= self.collapse(&c);
+        }
+    }
+
+    /// Builds the output mesh, rebuilding instanced copies from their
+    /// (decimated) originals
+    fn finish(self) -> Mesh {
+        let nt = self.tris.len();
+        let mut out = Mesh {
+            length_unit: self.mesh.length_unit,
+            assembly: self.mesh.assembly.clone(),
+            annotations: self.mesh.annotations.clone(),
+            ..Default::default()
+        };
+        let mut remap = vec![u32::MAX; self.pos.len()];
+        // Index of the first output triangle generated by each input
+        // triangle, so that face and solid ranges can be remapped
+        let mut start = vec![0; nt + 1];
+
+        let mut copies: Vec<Option<&Solid>> = vec![None; nt];
+        for s in &self.mesh.solids {
+            if s.instance_of.is_some() {
+                copies[s.triangles.clone()].iter_mut().for_each(|c| *c = Some(s));
+            }
+        }
+
+        let mut t = 0;
+        while t < nt {
+            if let Some(s) = copies[t] {
+                let original = &self.mesh.solids[s.instance_of.unwrap()];
+                let mut remap = HashMap::new();
+                let norm_mat = glm::mat4_to_mat3(&s.transform);
+                for (j, i) in s.triangles.clone().enumerate() {
+                    start[i] = out.triangles.len();
+                    let o = original.triangles.start + j;
+                    for k in start[o]..start[o + 1] {
+                        let mut verts = out.triangles[k].verts;
+                        for v in verts.iter_mut() {
+                            *v = *remap.entry(*v).or_insert_with(|| {
+                                let mut vert = out.verts[*v as usize];
+                                vert.pos = (s.transform * vert.pos.push(1.0)).xyz();
+                                let n = norm_mat * vert.norm;
+                                if n.norm() > 0.0 {
+                                    vert.norm = n.normalize();
+                                }
+                                out.verts.push(vert);
+                                (out.verts.len() - 1).try_into()
+                                    .expect("Too many vertices")
+                            });
+                        }
+                        out.triangles.push(Triangle { verts });
+                    }
+                }
+                t = s.triangles.end;
+                continue;
+            }
+            start[t] = out.triangles.len();
+            if self.alive[t] {
+                let mut verts = self.tris[t];
+                for v in verts.iter_mut() {
+                    let r = &mut remap[*v as usize];
+                    if *r == u32::MAX {
+                        *r = out.verts.len().try_into()
+                            .expect("Too many vertices");
+                        let mut vert = self.mesh.verts[*v as usize];
+                        vert.pos = self.pos[*v as usize];
+                        vert.norm = self.norm[*v as usize];
+                        out.verts.push(vert);
+                    }
+                    *v = *r;
+                }
+                out.triangles.push(Triangle { verts });
+            }
+            t += 1;
+        }
+        start[nt] = out.triangles.len();
+
+        let range = |r: &std::ops::Range<usize>| start[r.start]..start[r.end];
+        out.faces = self.mesh.faces.iter()
+            // Decimated faces are no longer structured grids
+            .map(|f| Face { triangles: range(&f.triangles), grid: None, ..f.clone() })
+            .collect();
+        out.solids = self.mesh.solids.iter()
+            .map(|s| Solid { triangles: range(&s.triangles), ..s.clone() })
+            .collect();
+        out
+    }
+}
+
+/// Simplifies a mesh by collapsing edges in order of increasing quadric
+/// error, until the target is reached.
+///
+/// Boundaries between B-rep faces are preserved exactly (so faces still
+/// meet without cracks), as are the face and solid triangle ranges.
+/// Instanced solids are decimated once, then copied to each instance.
+pub fn decimate(mesh: &Mesh, target: Target) -> Mesh {
+    let mut d = Decimator::new(mesh);
+    d.run(target);
+    d.finish()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, mesh::SurfaceType};
+
+    /// Builds two gridded faces: a flat square and a bent one sharing its
+    /// edge at x = 1
+    fn two_faces(n: usize) -> Mesh {
+        let mut mesh = Mesh::default();
+        let k = n as f64;
+        let flat = fixtures::grid(&mut mesh, n, |i, j| DVec3::new(i / k, j / k, 0.0));
+        fixtures::push_face(&mut mesh, flat, SurfaceType::Plane);
+        let bent = fixtures::grid(&mut mesh, n, |i, j| {
+            let (u, v) = (i / k, j / k);
+            DVec3::new(1.0 + u, v, (u * 3.0).sin() * (v * 2.0).cos() * 0.2)
+        });
+        fixtures::push_face(&mut mesh, bent, SurfaceType::Plane);
+        mesh
+    }
+
+    /// Returns the sorted positions of boundary vertices for each face
+    fn boundaries(mesh: &Mesh) -> Vec<Vec<(i64, i64, i64)>> {
+        mesh.faces.iter().map(|f| {
+            let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+            for t in &mesh.triangles[f.triangles.clone()] {
+                for j in 0..3 {
+                    let (a, b) = (t.verts[j], t.verts[(j + 1) % 3]);
+                    *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+                }
+            }
+            let mut pts: Vec<_> = edges.into_iter()
+                .filter(|(_, n)| *n == 1)
+                .flat_map(|((a, b), _)| [a, b])
+                .map(|v| {
+                    let p = mesh.verts[v as usize].pos * 1e6;
+                    (p.x.round() as i64, p.y.round() as i64, p.z.round() as i64)
+                })
+                .collect();
+            pts.sort_unstable();
+            pts.dedup();
+            pts
+        }).collect()
+    }
+
+    #[test]
+    fn preserves_face_boundaries() {
+        let mesh = two_faces(8);
+        let out = decimate(&mesh, Target::Triangles(0));
+        assert!(out.triangles.len() < mesh.triangles.len() / 2);
+        assert_eq!(boundaries(&mesh), boundaries(&out));
+        assert_eq!(out.faces[0].triangles.start, 0);
+        assert_eq!(out.faces[0].triangles.end, out.faces[1].triangles.start);
+        assert_eq!(out.faces[1].triangles.end, out.triangles.len());
+
+        // A tiny error bound only allows collapses on the flat face
+        let out = decimate(&mesh, Target::Error(1e-9));
+        assert!(out.faces[0].triangles.len() < mesh.faces[0].triangles.len());
+        assert_eq!(out.faces[1].triangles.len(), mesh.faces[1].triangles.len());
+    }
+}
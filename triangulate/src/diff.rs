@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::Bvh, mesh::Mesh};
+
+/// Summary statistics for a set of deviations
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DeviationStats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub rms: f64,
+}
+
+impl DeviationStats {
+    pub fn new(d: &[f64]) -> Self {
+        if d.is_empty() {
+            return Self::default();
+        }
+        let n = d.len() as f64;
+        Self {
+            count: d.len(),
+            min: d.iter().copied().fold(f64::INFINITY, f64::min),
+            max: d.iter().copied().fold(-f64::INFINITY, f64::max),
+            mean: d.iter().sum::<f64>() / n,
+            rms: (d.iter().map(|d| d * d).sum::<f64>() / n).sqrt(),
+        }
+    }
+}
+
+/// Computes the unsigned distance from every vertex in `a` to the surface
+/// of the mesh stored in `b`.  Vertices are returned in the same order as
+/// `a.verts`.
+pub fn deviation(a: &Mesh, b: &Bvh) -> Vec<f64> {
+    let verts = {
+        #[cfg(feature = "rayon")]
+        { a.verts.par_iter() }
+        #[cfg(not(feature = "rayon"))]
+        { a.verts.iter() }
+    };
+    verts.map(|v| b.closest_point(v.pos)
+            .map(|c| c.distance)
+            .unwrap_or(f64::INFINITY))
+        .collect()
+}
+
+/// Like [`deviation`], but negative for vertices of `a` which lie behind
+/// the surface of `b` (i.e. inside it, for a closed mesh).  The side is
+/// judged by the angle-weighted pseudonormal at the closest point, so that
+/// points nearest to an edge or vertex get the right sign even where the
+/// surface is sharply curved.  `b_mesh` must be the mesh from which `b` was
+/// built.
+pub fn signed_deviation(a: &Mesh, b: &Bvh, b_mesh: &Mesh) -> Vec<f64> {
+    let normals = Pseudonormals::new(b_mesh);
+    let verts = {
+        #[cfg(feature = "rayon")]
+        { a.verts.par_iter() }
+        #[cfg(not(feature = "rayon"))]
+        { a.verts.iter() }
+    };
+    verts.map(|v| match b.closest_point(v.pos) {
+            Some(c) => {
+                let n = normals.at(b_mesh, c.triangle, c.point);
+                if (v.pos - c.point).dot(&n) < 0.0 { -c.distance } else { c.distance }
+            },
+            None => f64::INFINITY,
+        })
+        .collect()
+}
+
+/// Vertex positions are compared exactly, since faces which share an edge
+/// reuse the same samples along it but not the same vertices
+type Key = [u64; 3];
+
+fn key(p: DVec3) -> Key {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+/// Angle-weighted pseudonormals (Bærentzen and Aanæs, 2005) at the vertices
+/// and edges of a mesh.  The dot product of a point's offset from its
+/// closest point on the surface with the pseudonormal there is negative
+/// exactly when the point is inside a closed mesh.
+struct Pseudonormals {
+    verts: HashMap<Key, DVec3>,
+    edges: HashMap<(Key, Key), DVec3>,
+}
+
+impl Pseudonormals {
+    fn new(mesh: &Mesh) -> Self {
+        let mut verts: HashMap<Key, DVec3> = HashMap::new();
+        let mut edges: HashMap<(Key, Key), DVec3> = HashMap::new();
+        for t in &mesh.triangles {
+            let pts = [t.verts.x, t.verts.y, t.verts.z]
+                .map(|i| mesh.verts[i as usize].pos);
+            let n = (pts[1] - pts[0]).cross(&(pts[2] - pts[0]));
+            if n.norm() == 0.0 {
+                continue;
+            }
+            let n = n.normalize();
+            for i in 0..3 {
+                let (p, q, r) = (pts[i], pts[(i + 1) % 3], pts[(i + 2) % 3]);
+                let angle = (q - p).angle(&(r - p));
+                *verts.entry(key(p)).or_insert_with(DVec3::zeros) += n * angle;
+                *edges.entry(edge_key(p, q)).or_insert_with(DVec3::zeros) += n;
+            }
+        }
+        Self { verts, edges }
+    }
+
+    /// Returns the pseudonormal at a point on the given triangle: the face
+    /// normal in its interior, or the pseudonormal of the edge or vertex
+    /// which the point lies on
+    fn at(&self, mesh: &Mesh, triangle: usize, p: DVec3) -> DVec3 {
+        let t = mesh.triangles[triangle].verts;
+        let pts = [t.x, t.y, t.z].map(|i| mesh.verts[i as usize].pos);
+        let n = (pts[1] - pts[0]).cross(&(pts[2] - pts[0]));
+        let area = n.norm();
+        if area == 0.0 {
+            return n;
+        }
+        // Barycentric coordinates of the point, where a (near-)zero
+        // coordinate means that it's on the opposite edge
+        let eps = 1e-9;
+        let on: Vec<bool> = (0..3)
+            .map(|i| {
+                let (q, r) = (pts[(i + 1) % 3], pts[(i + 2) % 3]);
+                (q - p).cross(&(r - p)).dot(&n) / (area * area) < eps
+            })
+            .collect();
+        match on.iter().filter(|o| **o).count() {
+            0 => n,
+            1 => {
+                let i = on.iter().position(|o| *o).unwrap();
+                let (q, r) = (pts[(i + 1) % 3], pts[(i + 2) % 3]);
+                self.edges.get(&edge_key(q, r)).copied().unwrap_or(n)
+            },
+            _ => {
+                let i = on.iter().position(|o| !*o).unwrap_or(0);
+                self.verts.get(&key(pts[i])).copied().unwrap_or(n)
+            },
+        }
+    }
+}
+
+fn edge_key(a: DVec3, b: DVec3) -> (Key, Key) {
+    let (a, b) = (key(a), key(b));
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Maps a value in the 0-1 range to a blue-green-red color ramp
+pub fn deviation_color(t: f64) -> DVec3 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let f = t * 2.0;
+        DVec3::new(0.0, f, 1.0 - f)
+    } else {
+        let f = (t - 0.5) * 2.0;
+        DVec3::new(f, 1.0 - f, 0.0)
+    }
+}
+
+/// Recolors every vertex in the mesh based on its deviation, with `max`
+/// (and anything beyond it) mapped to the top end of the color ramp.
+pub fn colorize(mesh: &mut Mesh, deviations: &[f64], max: f64) {
+    assert!(mesh.verts.len() == deviations.len());
+    for (v, d) in mesh.verts.iter_mut().zip(deviations) {
+        v.color = deviation_color(if max > 0.0 { d / max } else { 0.0 });
+    }
+}
+
+/// Recolors every vertex in the mesh based on its signed deviation, with
+/// zero in the middle of the color ramp and `-max` and `max` (and anything
+/// beyond them) at its ends
+pub fn colorize_signed(mesh: &mut Mesh, deviations: &[f64], max: f64) {
+    assert!(mesh.verts.len() == deviations.len());
+    for (v, d) in mesh.verts.iter_mut().zip(deviations) {
+        v.color = deviation_color(if max > 0.0 { 0.5 + 0.5 * d / max } else { 0.5 });
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{DVec2, U32Vec3};
+    use crate::mesh::{Triangle, Vertex};
+
+    fn vertex(pos: DVec3) -> Vertex {
+        Vertex { pos, norm: DVec3::zeros(), color: DVec3::zeros(), uv: DVec2::zeros() }
+    }
+
+    #[test]
+    fn sharp_edge_sign() {
+        // A prism along the z axis with a 30° wedge at the top, so that the
+        // normals of the faces on either side of its ridge are 150° apart
+        let a = 15f64.to_radians().tan();
+        let mut mesh = Mesh::default();
+        for z in [0.0, 1.0] {
+            for (x, y) in [(0.0, 0.0), (a, -1.0), (-a, -1.0)] {
+                mesh.verts.push(vertex(DVec3::new(x, y, z)));
+            }
+        }
+        for t in [[0, 1, 2], [3, 5, 4], [0, 4, 1], [0, 3, 4],
+                  [0, 5, 3], [0, 2, 5], [1, 5, 2], [1, 4, 5]] {
+            mesh.triangles.push(Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) });
+        }
+        let bvh = Bvh::new(&mesh);
+
+        // Points just above the ridge are closest to it, but are more than
+        // 90° away from the normal of one of the faces beside it
+        let d = 45f64.to_radians();
+        let mut probe = Mesh::default();
+        for p in [DVec3::new(d.sin(), d.cos(), 5.0), DVec3::new(-d.sin(), d.cos(), 5.0),
+                  DVec3::new(0.0, -5.0, 5.0)] {
+            probe.verts.push(vertex(p.component_mul(&DVec3::new(0.1, 0.1, 0.1))));
+        }
+        let out = signed_deviation(&probe, &bvh, &mesh);
+        assert!((out[0] - 0.1).abs() < 1e-9, "{:?}", out);
+        assert!((out[1] - 0.1).abs() < 1e-9, "{:?}", out);
+        assert!(out[2] < 0.0, "{:?}", out);
+    }
+}
@@ -0,0 +1,146 @@
+use log::debug;
+use nalgebra_glm::DVec3;
+
+use step::{ap214::*, step_file::StepFile};
+use crate::{
+    curve::segments_per_turn,
+    triangulate::{axis2_placement_3d, cartesian_point, TessellationParams},
+};
+
+/// Returns true for entities which only describe 2D drawings or the
+/// presentation of annotations (AP202 draughting and PMI), rather than 3D
+/// geometry.  These are skipped when building the mesh.
+pub(crate) fn is_draughting(e: &Entity) -> bool {
+    match e {
+        Entity::ComplexEntity(v) => v.iter().any(is_draughting),
+        Entity::AngularDimension(_)
+        | Entity::AnnotationCurveOccurrence(_)
+        | Entity::AnnotationFillArea(_)
+        | Entity::AnnotationFillAreaOccurrence(_)
+        | Entity::AnnotationOccurrence(_)
+        | Entity::AnnotationOccurrenceRelationship(_)
+        | Entity::AnnotationPlane(_)
+        | Entity::AnnotationSubfigureOccurrence(_)
+        | Entity::AnnotationSymbol(_)
+        | Entity::AnnotationSymbolOccurrence(_)
+        | Entity::AnnotationText(_)
+        | Entity::AnnotationTextCharacter(_)
+        | Entity::AnnotationTextOccurrence(_)
+        | Entity::CameraModelD2(_)
+        | Entity::CameraModelD3(_)
+        | Entity::CameraUsage(_)
+        | Entity::CurveDimension(_)
+        | Entity::DatumFeatureCallout(_)
+        | Entity::DatumTargetCallout(_)
+        | Entity::DiameterDimension(_)
+        | Entity::DimensionCallout(_)
+        | Entity::DimensionCurve(_)
+        | Entity::DimensionCurveDirectedCallout(_)
+        | Entity::DimensionCurveTerminator(_)
+        | Entity::DraughtingAnnotationOccurrence(_)
+        | Entity::DraughtingCallout(_)
+        | Entity::DraughtingCalloutRelationship(_)
+        | Entity::DraughtingElements(_)
+        | Entity::DraughtingModel(_)
+        | Entity::DraughtingModelItemAssociation(_)
+        | Entity::DraughtingSubfigureRepresentation(_)
+        | Entity::DraughtingSymbolRepresentation(_)
+        | Entity::DraughtingTextLiteralWithDelineation(_)
+        | Entity::DraughtingTitle(_)
+        | Entity::DrawingDefinition(_)
+        | Entity::DrawingRevision(_)
+        | Entity::DrawingSheetRevision(_)
+        | Entity::DrawingSheetRevisionUsage(_)
+        | Entity::GeometricalToleranceCallout(_)
+        | Entity::LeaderCurve(_)
+        | Entity::LeaderDirectedCallout(_)
+        | Entity::LeaderDirectedDimension(_)
+        | Entity::LeaderTerminator(_)
+        | Entity::LinearDimension(_)
+        | Entity::MechanicalDesignGeometricPresentationArea(_)
+        | Entity::OrdinateDimension(_)
+        | Entity::PresentationArea(_)
+        | Entity::PresentationView(_)
+        | Entity::ProjectionDirectedCallout(_)
+        | Entity::RadiusDimension(_)
+        | Entity::StructuredDimensionCallout(_)
+        | Entity::SurfaceConditionCallout(_)
+        | Entity::TerminatorSymbol(_)
+        | Entity::TextLiteral(_)
+        | Entity::TextLiteralWithAssociatedCurves(_)
+        | Entity::TextLiteralWithBlankingBox(_)
+        | Entity::TextLiteralWithDelineation(_)
+        | Entity::TextLiteralWithExtent(_) => true,
+        _ => false,
+    }
+}
+
+/// Counts the draughting-only entities in the file
+pub(crate) fn count(s: &StepFile) -> usize {
+    s.0.iter().filter(|e| is_draughting(e)).count()
+}
+
+/// Collects the curves drawn by annotation occurrences (e.g. the leader
+/// lines and dimension text of PMI) as polylines in model coordinates.
+///
+/// Only polylines and circles are converted, which covers the graphical
+/// PMI written by most exporters; annotations placed within an assembly are
+/// not moved by the assembly's transforms.
+pub(crate) fn annotation_curves(s: &StepFile, params: &TessellationParams)
+    -> Vec<Vec<DVec3>>
+{
+    let mut out = Vec::new();
+    for e in &s.0 {
+        let parts = match e {
+            Entity::ComplexEntity(v) => v.iter().collect(),
+            e => vec![e],
+        };
+        let item = parts.iter().find_map(|e| match e {
+            Entity::AnnotationCurveOccurrence(a) => Some(a.item),
+            Entity::AnnotationOccurrence(a) => Some(a.item),
+            Entity::DraughtingAnnotationOccurrence(a) => Some(a.item),
+            _ => None,
+        });
+        if let Some(item) = item {
+            curves(s, item.0, params, &mut out, 0);
+        }
+    }
+    out
+}
+
+/// Converts a curve (or a set of curves) into polylines, appending them
+/// to `out`.  `depth` guards against cycles through nested sets.
+fn curves(s: &StepFile, id: usize, params: &TessellationParams,
+          out: &mut Vec<Vec<DVec3>>, depth: usize)
+{
+    if depth > 8 {
+        return;
+    }
+    match s.0.get(id) {
+        Some(Entity::GeometricCurveSet(c)) =>
+            for e in &c.elements {
+                curves(s, e.0, params, out, depth + 1);
+            },
+        Some(Entity::GeometricSet(c)) =>
+            for e in &c.elements {
+                curves(s, e.0, params, out, depth + 1);
+            },
+        Some(Entity::Polyline(p)) => out.push(p.points.iter()
+            .map(|p| cartesian_point(s, *p))
+            .collect()),
+        Some(Entity::Circle(c)) => {
+            let (center, axis, x) = axis2_placement_3d(s, c.position.cast());
+            let r = c.radius.0.0.0;
+            let x = (x - axis * x.dot(&axis)).normalize() * r;
+            let y = axis.normalize().cross(&x);
+            let n = segments_per_turn(r, params);
+            out.push((0..=n)
+                .map(|i| {
+                    let a = i as f64 / n as f64 * std::f64::consts::TAU;
+                    center + x * a.cos() + y * a.sin()
+                })
+                .collect());
+        },
+        e => debug!("Skipping annotation curve {:?}", e),
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use log::warn;
+use nalgebra_glm::DMat4;
+
+use step::{
+    ap214::*, cancel::CancelToken, part21::Sections,
+    step_file::{FromEntity, ParseMode, StepFile},
+};
+use crate::{
+    memory::{self, Stage},
+    mesh::Mesh,
+    stats::Stats,
+    triangulate::{
+        triangulate_cancellable, walk_assembly, TessellationOverrides,
+        TessellationParams,
+    },
+};
+
+/// A file containing component geometry for an assembly, which is placed
+/// into the assembly once per transform
+#[derive(Clone, Debug)]
+pub struct ExternalReference {
+    /// Path of the file, relative to the assembly's own file
+    pub file: String,
+    pub transforms: Vec<DMat4>,
+}
+
+/// Looks up an entity by id, returning `None` for ids which are out of range
+/// (which happens for edition 3 references) or of the wrong type
+fn get<'a, T: FromEntity<'a>>(s: &'a StepFile, id: usize) -> Option<&'a T> {
+    s.0.get(id).and_then(T::try_from_entity)
+}
+
+/// Checks whether a file name looks like a STEP file, since documents can
+/// also be drawings, specifications, etc.
+fn is_step_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [".stp", ".step", ".p21"].iter().any(|ext| name.ends_with(ext))
+}
+
+/// Maps each representation which stands in for geometry stored in another
+/// file to that file's name.  This covers both edition 3 references (where
+/// the representation's id is declared in the reference section) and
+/// `DOCUMENT_FILE`s attached to the representation's product definition with
+/// an `APPLIED_DOCUMENT_REFERENCE` or
+/// `PRODUCT_DEFINITION_WITH_ASSOCIATED_DOCUMENTS`.
+fn referenced_files<'a>(s: &'a StepFile, sections: &'a Sections)
+    -> HashMap<usize, &'a str>
+{
+    // product definition -> file name
+    let mut docs: HashMap<usize, &str> = HashMap::new();
+    let file_name = |d: &Document| get::<DocumentFile_>(s, d.0)
+        .and_then(|f| Some(&*f.id.0).filter(|n| is_step_file(n))
+            .or_else(|| Some(&*f.document__name.0).filter(|n| is_step_file(n))));
+    for (i, e) in s.0.iter().enumerate() {
+        match e {
+            Entity::AppliedDocumentReference(r) => {
+                if let Some(f) = file_name(&r.assigned_document) {
+                    for item in &r.items {
+                        docs.insert(item.0, f);
+                    }
+                }
+            },
+            Entity::ProductDefinitionWithAssociatedDocuments(p) => {
+                if let Some(f) = p.documentation_ids.iter().find_map(file_name) {
+                    docs.insert(i, f);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // representation -> file name, following the chain
+    // SHAPE_DEFINITION_REPRESENTATION -> PRODUCT_DEFINITION_SHAPE ->
+    // PRODUCT_DEFINITION
+    let mut out: HashMap<usize, &str> = s.0.iter()
+        .filter_map(ShapeDefinitionRepresentation_::try_from_entity)
+        .filter_map(|r| get::<ProductDefinitionShape_>(s, r.definition.0)
+            .and_then(|p| docs.get(&p.definition.0))
+            .map(|f| (r.used_representation.0, *f)))
+        .collect();
+    for r in &sections.references {
+        if !r.file().is_empty() {
+            out.insert(r.id, r.file());
+        }
+    }
+    out
+}
+
+/// Finds the external files which an assembly pulls geometry from, along
+/// with the placement of each instance within the assembly
+pub fn external_references(s: &StepFile, sections: &Sections)
+    -> Vec<ExternalReference>
+{
+    let files = referenced_files(s, sections);
+    if files.is_empty() {
+        return Vec::new();
+    }
+    let mut found: HashMap<&str, Vec<DMat4>> = HashMap::new();
+    walk_assembly(s, |id, mat, _name| {
+        if let Some(f) = files.get(&id.0) {
+            found.entry(f).or_default().push(mat);
+        }
+    });
+    // Representations which aren't part of a transform chain are placed at
+    // the origin, like unattached solids in `triangulate`
+    for f in files.values() {
+        found.entry(f).or_insert_with(|| vec![DMat4::identity()]);
+    }
+    let mut out: Vec<_> = found.into_iter()
+        .map(|(file, transforms)| ExternalReference {
+            file: file.to_owned(), transforms
+        })
+        .collect();
+    out.sort_by(|a, b| a.file.cmp(&b.file));
+    out
+}
+
+/// Loads and triangulates a STEP file, along with any sibling files which it
+/// references for component geometry (recursively).  Referenced files are
+/// found relative to the directory containing `path`, and are triangulated
+/// with `params` (but not `overrides`, since their entity ids are unrelated).
+/// Returns `None` if the token is cancelled.
+///
+/// Every file is parsed in the given `mode`; strict parse errors are returned
+/// as `InvalidData` errors.  Referenced files which can't be loaded are
+/// skipped with a warning.
+pub fn triangulate_file(path: &Path, params: TessellationParams,
+                        overrides: &TessellationOverrides, mode: ParseMode,
+                        cancel: &CancelToken)
+    -> std::io::Result<Option<(Mesh, Stats)>>
+{
+    triangulate_file_(path, params, overrides, mode, cancel, &mut Vec::new())
+}
+
+/// Implementation of [`triangulate_file`], with the chain of files being
+/// loaded in `stack` so that circular references can be skipped
+fn triangulate_file_(path: &Path, params: TessellationParams,
+                     overrides: &TessellationOverrides, mode: ParseMode,
+                     cancel: &CancelToken, stack: &mut Vec<PathBuf>)
+    -> std::io::Result<Option<(Mesh, Stats)>>
+{
+    let start = Instant::now();
+    let flat = memory::stage(Stage::Parse, || {
+        std::fs::read(path).map(|data| StepFile::preprocess(&data, mode))
+    })?;
+    let step = match memory::stage(Stage::Parse, || StepFile::parse_with(&flat, mode, cancel))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+    {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let parsed = Instant::now();
+    let (mut mesh, mut stats) =
+        match triangulate_cancellable(&step, params, overrides, cancel) {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+    stats.parse_time = parsed - start;
+    stats.triangulate_time = parsed.elapsed();
+
+    let refs = external_references(&step, &Sections::new(&flat));
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    stack.push(path.canonicalize().unwrap_or_else(|_| path.to_owned()));
+    for r in refs {
+        let child = dir.join(&r.file);
+        let key = child.canonicalize().unwrap_or_else(|_| child.clone());
+        if stack.contains(&key) {
+            warn!("Skipping circular reference to {}", child.display());
+            continue;
+        }
+        match triangulate_file_(&child, params, &HashMap::new(), mode, cancel,
+                                stack)
+        {
+            Ok(Some((m, st))) => {
+                // Component geometry is converted into this file's units
+                // before it's placed
+                let mut m = m;
+                if let (Some(a), Some(b)) = (mesh.length_unit, m.length_unit) {
+                    if a != b {
+                        m.scale(b / a);
+                    }
+                }
+                for mat in &r.transforms {
+                    let mut m = m.clone();
+                    m.transform(mat);
+                    mesh = Mesh::combine(mesh, m);
+                }
+                stats = Stats::combine(stats, st);
+            },
+            Ok(None) => return Ok(None),
+            Err(e) => warn!("Could not load {}: {}", child.display(), e),
+        }
+    }
+    stack.pop();
+    Ok(Some((mesh, stats)))
+}
@@ -0,0 +1,114 @@
+//! Builders for small hand-made meshes, shared between unit tests
+use std::ops::Range;
+use std::sync::Arc;
+
+use nalgebra_glm::{DMat4, DVec2, DVec3, U32Vec3};
+
+use crate::{mesh::{Face, Mesh, Solid, SurfaceType, Triangle, Vertex}, surface::Surface};
+
+/// Returns a black vertex with no texture coordinates
+pub(crate) fn vertex(pos: DVec3, norm: DVec3) -> Vertex {
+    Vertex { pos, norm, color: DVec3::zeros(), uv: DVec2::zeros() }
+}
+
+/// Appends vertices and triangles (which index into `verts`), returning the
+/// range of new triangles
+pub(crate) fn push_triangles<I>(mesh: &mut Mesh, verts: Vec<Vertex>, tris: I) -> Range<usize>
+    where I: IntoIterator<Item=[usize; 3]>
+{
+    let start = mesh.verts.len() as u32;
+    let t_start = mesh.triangles.len();
+    mesh.verts.extend(verts);
+    mesh.triangles.extend(tris.into_iter().map(|[a, b, c]| Triangle {
+        verts: U32Vec3::new(a as u32, b as u32, c as u32).add_scalar(start),
+    }));
+    t_start..mesh.triangles.len()
+}
+
+/// Appends an `n x n` grid of split squares, with vertex `(i, j)` at
+/// `pos(i, j)` and texture coordinates running from 0 to 1 across the grid.
+/// Returns the range of new triangles.
+pub(crate) fn grid<F>(mesh: &mut Mesh, n: usize, pos: F) -> Range<usize>
+    where F: Fn(f64, f64) -> DVec3
+{
+    let mut verts = Vec::new();
+    for i in 0..=n {
+        for j in 0..=n {
+            verts.push(Vertex {
+                pos: pos(i as f64, j as f64), norm: DVec3::z(), color: DVec3::zeros(),
+                uv: DVec2::new(i as f64 / n as f64, j as f64 / n as f64),
+            });
+        }
+    }
+    let row = n + 1;
+    let tris = (0..n).flat_map(|i| (0..n).flat_map(move |j| {
+        let k = i * row + j;
+        [[k, k + row, k + 1], [k + 1, k + row, k + row + 1]]
+    }));
+    push_triangles(mesh, verts, tris)
+}
+
+/// Marks a range of triangles as a face with no geometry
+pub(crate) fn push_face(mesh: &mut Mesh, triangles: Range<usize>, surface: SurfaceType) {
+    mesh.faces.push(Face {
+        id: mesh.faces.len(), triangles, surface, dimensions: None,
+        geometry: None, transform: DMat4::identity(), failed: false,
+        grid: None,
+    });
+}
+
+/// Marks a range of triangles as a face lying on the given surface
+pub(crate) fn push_surface(mesh: &mut Mesh, triangles: Range<usize>, surface: Surface) {
+    mesh.faces.push(Face {
+        id: mesh.faces.len(), triangles,
+        surface: surface.surface_type(), dimensions: surface.dimensions(),
+        geometry: Some(Arc::new(surface)), transform: DMat4::identity(), failed: false,
+        grid: None,
+    });
+}
+
+/// Marks the triangles added since the last solid as a new solid
+pub(crate) fn end_solid(mesh: &mut Mesh) {
+    let start = mesh.solids.last().map(|s| s.triangles.end).unwrap_or(0);
+    mesh.solids.push(Solid {
+        id: mesh.solids.len(), name: None, color: None, material: None,
+        triangles: start..mesh.triangles.len(),
+        instance_of: None, transform: DMat4::identity(),
+    });
+}
+
+/// Appends a closed, axis-aligned box from `lo` to `hi` with shared
+/// corners, facing inwards if `inward` is set.  Returns the range of new
+/// triangles.
+pub(crate) fn cuboid(mesh: &mut Mesh, lo: DVec3, hi: DVec3, inward: bool) -> Range<usize> {
+    let verts = (0..8).map(|i| vertex(corner(lo, hi, i), DVec3::zeros())).collect();
+    let tris = CUBOID_SIDES.iter()
+        .flat_map(|q| [[q[0], q[1], q[2]], [q[0], q[2], q[3]]])
+        .map(|t| if inward { [t[0], t[2], t[1]] } else { t });
+    push_triangles(mesh, verts, tris)
+}
+
+/// Like [`cuboid`] (facing outwards), but with separate vertices for each
+/// side, which have the side's normal
+pub(crate) fn faceted_cuboid(mesh: &mut Mesh, lo: DVec3, hi: DVec3) -> Range<usize> {
+    let mut verts = Vec::new();
+    for q in &CUBOID_SIDES {
+        let p = q.map(|i| corner(lo, hi, i));
+        let norm = (p[1] - p[0]).cross(&(p[2] - p[0])).normalize();
+        verts.extend(p.iter().map(|&p| vertex(p, norm)));
+    }
+    let tris = (0..6).flat_map(|i| [[4 * i, 4 * i + 1, 4 * i + 2], [4 * i, 4 * i + 2, 4 * i + 3]]);
+    push_triangles(mesh, verts, tris)
+}
+
+/// Corners of each side of a cuboid, counter-clockwise from outside, as
+/// indices for [`corner`]
+const CUBOID_SIDES: [[usize; 4]; 6] = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4],
+                                       [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+
+/// Returns a corner of the box from `lo` to `hi`, with bits 0, 1, and 2 of
+/// `i` picking the high side along X, Y, and Z
+fn corner(lo: DVec3, hi: DVec3, i: usize) -> DVec3 {
+    let c = DVec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, (i >> 2) as f64);
+    lo + c.component_mul(&(hi - lo))
+}
@@ -0,0 +1,94 @@
+//! Writing regular grids of samples (from `voxel` and `sdf`) to files
+use nalgebra_glm::DVec3;
+
+/// A value which can be stored in a grid file
+pub(crate) trait Sample: Copy {
+    /// Name of the type in a NRRD header
+    const NRRD_TYPE: &'static str;
+    /// Appends the value's little-endian bytes
+    fn write(self, out: &mut Vec<u8>);
+}
+
+impl Sample for u8 {
+    const NRRD_TYPE: &'static str = "uchar";
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(self);
+    }
+}
+
+impl Sample for f32 {
+    const NRRD_TYPE: &'static str = "float";
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend(self.to_le_bytes());
+    }
+}
+
+/// Writes samples as raw little-endian values
+pub(crate) fn save_raw<T, I>(filename: &str, samples: I) -> std::io::Result<()>
+    where T: Sample, I: IntoIterator<Item=T>
+{
+    let mut out = Vec::new();
+    for s in samples {
+        s.write(&mut out);
+    }
+    std::fs::write(filename, out)
+}
+
+/// Writes samples as a NRRD volume, which is the raw data behind a short
+/// text header giving the grid's size and placement.  `origin` is the
+/// position of the first sample, and samples are `spacing` apart, with X
+/// varying fastest and then Y.
+pub(crate) fn save_nrrd<T, I>(filename: &str, origin: DVec3, spacing: DVec3,
+                              dims: [usize; 3], samples: I)
+    -> std::io::Result<()>
+    where T: Sample, I: IntoIterator<Item=T>
+{
+    let (o, s) = (origin, spacing);
+    let endian = if std::mem::size_of::<T>() > 1 { "endian: little\n" } else { "" };
+    let mut out = format!("NRRD0004
+type: {}
+dimension: 3
+sizes: {} {} {}
+space dimension: 3
+space origin: ({},{},{})
+space directions: ({},0,0) (0,{},0) (0,0,{})
+{}encoding: raw
+
+", T::NRRD_TYPE, dims[0], dims[1], dims[2], o.x, o.y, o.z, s.x, s.y, s.z, endian)
+        .into_bytes();
+    for s in samples {
+        s.write(&mut out);
+    }
+    std::fs::write(filename, out)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nrrd_headers() {
+        let path = std::env::temp_dir()
+            .join(format!("foxtrot-grid-{}.nrrd", std::process::id()));
+        let file = path.to_str().unwrap();
+        let (o, s) = (DVec3::new(1.0, 2.0, 3.0), DVec3::new(0.5, 0.5, 2.0));
+
+        save_nrrd(file, o, s, [2, 1, 1], [1u8, 0]).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("type: uchar\n"));
+        assert!(!text.contains("endian"));
+        assert!(text.contains("space origin: (1,2,3)\n"));
+        assert!(text.contains("space directions: (0.5,0,0) (0,0.5,0) (0,0,2)\n"));
+        assert!(data.ends_with(b"\n\n\x01\x00"));
+
+        save_nrrd(file, o, s, [1, 1, 1], [1.5f32]).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("type: float\n") && text.contains("endian: little\n"));
+        assert!(data.ends_with(&1.5f32.to_le_bytes()));
+    }
+}
@@ -0,0 +1,166 @@
+use nalgebra_glm::DVec3;
+
+use crate::mesh::{Face, Mesh};
+
+/// Distance below which faces are treated as sharing a plane or axis, as a
+/// fraction of the model's bounding box diagonal
+const EPSILON: f64 = 1e-6;
+
+/// Largest angle (in radians) between the normals of coplanar faces, or the
+/// axes of coaxial faces
+const ANGLE: f64 = 1e-6;
+
+/// What the faces in a [`FaceGroup`] have in common
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GroupKind {
+    /// Planar faces which lie in the same plane
+    Plane,
+    /// Cylindrical and conical faces which share an axis
+    Axis,
+}
+
+/// A set of faces (possibly from several solids) which lie in the same plane
+/// or around the same axis, e.g. the mating surfaces of two parts
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaceGroup {
+    pub kind: GroupKind,
+    /// A point on the plane or axis, in mesh coordinates
+    pub point: DVec3,
+    /// The plane's unit normal, or the axis's unit direction.  Faces may
+    /// point either way along it.
+    pub direction: DVec3,
+    /// Indices into `mesh.faces`, in ascending order
+    pub faces: Vec<usize>,
+}
+
+impl FaceGroup {
+    /// Returns the solids which own the group's faces, as indices into
+    /// `mesh.solids`, in ascending order
+    pub fn solids(&self, mesh: &Mesh) -> Vec<usize> {
+        let mut out: Vec<usize> = self.faces.iter()
+            .filter_map(|&f| mesh.solids.iter()
+                .position(|s| s.triangles.contains(&mesh.faces[f].triangles.start)))
+            .collect();
+        out.dedup();
+        out
+    }
+}
+
+/// Returns the plane or axis of a face, in mesh coordinates
+fn face_frame(face: &Face) -> Option<(GroupKind, DVec3, DVec3)> {
+    let surf = face.geometry.as_ref()?;
+    let (kind, (p, d)) = match (surf.plane(), surf.axis()) {
+        (Some(p), _) => (GroupKind::Plane, p),
+        (None, Some(a)) => (GroupKind::Axis, a),
+        (None, None) => return None,
+    };
+    let p = (face.transform * p.push(1.0)).xyz();
+    let d = (face.transform * d.push(0.0)).xyz();
+    if d.norm() > 0.0 {
+        Some((kind, p, d.normalize()))
+    } else {
+        None
+    }
+}
+
+/// Groups the faces of a mesh which lie in the same plane or around the same
+/// axis, whichever solids they belong to.  Only groups of two or more faces
+/// are returned; those which span several solids (see [`FaceGroup::solids`])
+/// are candidates for mating surfaces.
+///
+/// This relies on the faces' analytic geometry, so B-spline faces are never
+/// grouped.
+pub fn face_groups(mesh: &Mesh) -> Vec<FaceGroup> {
+    let (lo, hi) = mesh.verts.iter().fold(
+        (DVec3::repeat(f64::INFINITY), DVec3::repeat(-f64::INFINITY)),
+        |(lo, hi), v| (lo.inf(&v.pos), hi.sup(&v.pos)));
+    let eps = (hi - lo).norm() * EPSILON;
+
+    let mut groups: Vec<FaceGroup> = Vec::new();
+    for (i, face) in mesh.faces.iter().enumerate() {
+        if face.triangles.is_empty() {
+            continue;
+        }
+        let (kind, p, d) = match face_frame(face) {
+            Some(f) => f,
+            None => continue,
+        };
+        let same = |g: &&mut FaceGroup| {
+            let dp = p - g.point;
+            g.kind == kind && d.cross(&g.direction).norm() < ANGLE && match kind {
+                GroupKind::Plane => dp.dot(&g.direction).abs() < eps,
+                GroupKind::Axis =>
+                    (dp - g.direction * dp.dot(&g.direction)).norm() < eps,
+            }
+        };
+        match groups.iter_mut().find(same) {
+            Some(g) => g.faces.push(i),
+            None => groups.push(FaceGroup {
+                kind, point: p, direction: d, faces: vec![i],
+            }),
+        }
+    }
+    groups.retain(|g| g.faces.len() > 1);
+    groups
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::{end_solid, push_surface, push_triangles, vertex}, surface::Surface};
+
+    /// Adds a square face of the given surface, with corners at the given
+    /// points (which should lie on the surface), as a new solid
+    fn square(mesh: &mut Mesh, surface: Surface, corners: [DVec3; 4]) {
+        let verts = corners.iter().map(|&pos| vertex(pos, DVec3::z())).collect();
+        let tris = push_triangles(mesh, verts, [[0, 1, 2], [0, 2, 3]]);
+        push_surface(mesh, tris, surface);
+        end_solid(mesh);
+    }
+
+    fn plane(mesh: &mut Mesh, normal: DVec3, origin: DVec3) {
+        let x = if normal.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() };
+        let x = (x - normal * x.dot(&normal)).normalize();
+        let y = normal.cross(&x);
+        let surf = Surface::new_plane(normal, x, origin);
+        square(mesh, surf, [origin, origin + x, origin + x + y, origin + y]);
+    }
+
+    #[test]
+    fn mating_planes() {
+        let mut mesh = Mesh::default();
+        // Two parts stacked at z = 1, whose faces point in opposite
+        // directions and whose planes are placed at different origins
+        plane(&mut mesh, DVec3::z(), DVec3::new(0.0, 0.0, 1.0));
+        plane(&mut mesh, -DVec3::z(), DVec3::new(3.0, 2.0, 1.0));
+        // A parallel face which isn't in the same plane
+        plane(&mut mesh, DVec3::z(), DVec3::new(0.0, 0.0, 2.0));
+        // A face in a different plane through the same point
+        plane(&mut mesh, DVec3::x(), DVec3::new(0.0, 0.0, 1.0));
+
+        let groups = face_groups(&mesh);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, GroupKind::Plane);
+        assert_eq!(groups[0].faces, vec![0, 1]);
+        assert_eq!(groups[0].solids(&mesh), vec![0, 1]);
+    }
+
+    #[test]
+    fn coaxial_cylinders() {
+        // A shaft and a bore of different radii around the same axis, and
+        // a parallel cylinder beside them
+        let mut mesh = Mesh::default();
+        for (r, x, z) in [(1.0, 0.0, 0.0), (2.0, 0.0, 5.0), (1.0, 3.0, 0.0)] {
+            let c = DVec3::new(x, 0.0, z);
+            let surf = Surface::new_cylinder(DVec3::z(), DVec3::x(), c, r);
+            let [a, b] = [DVec3::new(r, 0.0, 0.0), DVec3::new(0.0, r, 0.0)];
+            square(&mut mesh, surf, [c + a, c + b, c + b + DVec3::z(), c + a + DVec3::z()]);
+        }
+        let groups = face_groups(&mesh);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, GroupKind::Axis);
+        assert_eq!(groups[0].faces, vec![0, 1]);
+    }
+}
@@ -0,0 +1,388 @@
+use std::f64::consts::PI;
+
+use nalgebra_glm::DVec3;
+
+use crate::mesh::{Face, Mesh, SurfaceType};
+
+/// Distance below which positions (and radii) are treated as equal, as a
+/// fraction of the model's bounding box diagonal
+const EPSILON: f64 = 1e-6;
+
+/// Fraction of a full turn which the faces around each section of a hole
+/// must cover, allowing for the chords of a coarse tessellation
+const MIN_COVERAGE: f64 = 0.95;
+
+/// A wider section at the entrance of a hole, e.g. for a bolt head
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Counterbore {
+    pub diameter: f64,
+    pub depth: f64,
+}
+
+/// A round hole, built from a chain of coaxial cylindrical and conical faces
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hole {
+    /// Center of the hole's entrance, in mesh coordinates
+    pub origin: DVec3,
+    /// Unit direction from the entrance into the hole
+    pub axis: DVec3,
+    /// Smallest diameter of the hole's cylindrical sections
+    pub diameter: f64,
+    /// Length of the hole along its axis, including any counterbore (and the
+    /// point at the bottom of a drilled blind hole)
+    pub depth: f64,
+    /// True if the hole is open at both ends
+    pub through: bool,
+    pub counterbore: Option<Counterbore>,
+    /// Faces which make up the hole, as indices into `mesh.faces`, including
+    /// the floors of counterbores and blind holes
+    pub faces: Vec<usize>,
+}
+
+/// The extent of one or more faces along an axis, with `z.0 < z.1`
+#[derive(Clone, Debug)]
+struct Section {
+    z: (f64, f64),
+    /// Radius at each end
+    r: (f64, f64),
+    area: f64,
+    cone: bool,
+    faces: Vec<usize>,
+}
+
+impl Section {
+    /// Returns the area of the full turn of a cylinder or cone section
+    fn lateral_area(&self) -> f64 {
+        PI * (self.r.0 + self.r.1) * (self.r.1 - self.r.0).hypot(self.z.1 - self.z.0)
+    }
+}
+
+/// Finds the round holes in each solid of the mesh (or in the whole mesh,
+/// if it has no solids), by chaining together coaxial cylindrical and
+/// conical faces which enclose empty space, then looking for planar faces
+/// which form counterbore steps and the floors of blind holes.
+///
+/// This relies on the faces' analytic geometry, so holes which were
+/// exported as B-spline surfaces aren't found.
+pub fn find_holes(mesh: &Mesh) -> Vec<Hole> {
+    let (lo, hi) = mesh.verts.iter().fold(
+        (DVec3::repeat(f64::INFINITY), DVec3::repeat(-f64::INFINITY)),
+        |(lo, hi), v| (lo.inf(&v.pos), hi.sup(&v.pos)));
+    let eps = (hi - lo).norm() * EPSILON;
+
+    let ranges: Vec<_> = if mesh.solids.is_empty() {
+        std::iter::once(0..mesh.triangles.len()).collect()
+    } else {
+        mesh.solids.iter().map(|s| s.triangles.clone()).collect()
+    };
+    let mut out = Vec::new();
+    for r in ranges {
+        let faces: Vec<usize> = mesh.faces.iter()
+            .enumerate()
+            .filter(|(_, f)| !f.triangles.is_empty() && r.contains(&f.triangles.start))
+            .map(|(i, _)| i)
+            .collect();
+
+        // Group the faces by their axes
+        let mut groups: Vec<(DVec3, DVec3, Vec<usize>)> = Vec::new();
+        for &i in &faces {
+            let (p, d) = match face_axis(&mesh.faces[i]) {
+                Some(a) => a,
+                None => continue,
+            };
+            let coaxial = |(q, e, _): &&mut (DVec3, DVec3, Vec<usize>)| {
+                let dq = p - *q;
+                d.cross(e).norm() < EPSILON && (dq - *e * dq.dot(e)).norm() < eps
+            };
+            match groups.iter_mut().find(coaxial) {
+                Some(g) => g.2.push(i),
+                None => groups.push((p, d, vec![i])),
+            }
+        }
+        for (origin, axis, members) in groups {
+            let sections = members.iter()
+                .filter_map(|&i| section(mesh, i, origin, axis, eps))
+                .collect();
+            for chain in chains(sections, eps) {
+                if let Some(h) = hole(mesh, &faces, origin, axis, &chain, eps) {
+                    out.push(h);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Returns a point on the axis of a cylindrical or conical face and the
+/// axis's unit direction, in mesh coordinates
+fn face_axis(face: &Face) -> Option<(DVec3, DVec3)> {
+    let (p, d) = face.geometry.as_ref()?.axis()?;
+    let p = (face.transform * p.push(1.0)).xyz();
+    let d = (face.transform * d.push(0.0)).xyz();
+    if d.norm() > 0.0 {
+        Some((p, d.normalize()))
+    } else {
+        None
+    }
+}
+
+/// Returns the positions of the vertices used by a face, measured as a
+/// height along the axis and a distance from it, along with their normals
+fn measure<'a>(mesh: &'a Mesh, face: &Face, origin: DVec3, axis: DVec3)
+    -> impl Iterator<Item=(f64, f64, DVec3, DVec3)> + 'a
+{
+    mesh.triangles[face.triangles.clone()].iter()
+        .flat_map(|t| t.verts.iter().copied().collect::<Vec<_>>())
+        .map(move |i| {
+            let v = &mesh.verts[i as usize];
+            let d = v.pos - origin;
+            let z = d.dot(&axis);
+            let radial = d - axis * z;
+            (z, radial.norm(), radial, v.norm)
+        })
+}
+
+/// Measures a cylindrical or conical face along an axis, returning `None`
+/// unless its normals point towards the axis (i.e. it encloses empty space)
+fn section(mesh: &Mesh, i: usize, origin: DVec3, axis: DVec3, eps: f64)
+    -> Option<Section>
+{
+    let face = &mesh.faces[i];
+    let pts: Vec<_> = measure(mesh, face, origin, axis).collect();
+    let z0 = pts.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let z1 = pts.iter().map(|p| p.0).fold(-f64::INFINITY, f64::max);
+    // Faces without any vertices give inverted (infinite) bounds, so they
+    // also fail this test
+    if z1 - z0 <= eps {
+        return None;
+    }
+    let facing: f64 = pts.iter()
+        .filter(|p| p.1 > eps)
+        .map(|(_, r, radial, norm)| norm.dot(radial) / r)
+        .sum();
+    if facing >= 0.0 {
+        return None;
+    }
+    let radius_at = |z: f64| {
+        let (sum, n) = pts.iter()
+            .filter(|p| (p.0 - z).abs() < eps)
+            .fold((0.0, 0), |(sum, n), p| (sum + p.1, n + 1));
+        sum / n as f64
+    };
+    Some(Section {
+        z: (z0, z1),
+        r: (radius_at(z0), radius_at(z1)),
+        area: mesh.area(face.triangles.clone()),
+        cone: face.surface == SurfaceType::Cone,
+        faces: vec![i],
+    })
+}
+
+/// Merges sections which are split between several faces, drops those which
+/// don't go all the way around, then splits the rest into chains of sections
+/// which touch end-to-end
+fn chains(mut sections: Vec<Section>, eps: f64) -> Vec<Vec<Section>> {
+    sections.sort_by(|a, b| a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal));
+    let mut merged: Vec<Section> = Vec::new();
+    for s in sections {
+        let same = |m: &&mut Section| (m.z.0 - s.z.0).abs() < eps
+            && (m.z.1 - s.z.1).abs() < eps
+            && (m.r.0 - s.r.0).abs() < eps
+            && (m.r.1 - s.r.1).abs() < eps;
+        match merged.iter_mut().find(same) {
+            Some(m) => {
+                m.area += s.area;
+                m.faces.extend(s.faces);
+            },
+            None => merged.push(s),
+        }
+    }
+    merged.retain(|s| s.area >= s.lateral_area() * MIN_COVERAGE);
+
+    let mut out: Vec<Vec<Section>> = Vec::new();
+    for s in merged {
+        match out.last_mut() {
+            Some(c) if (c.last().unwrap().z.1 - s.z.0).abs() < eps => c.push(s),
+            _ => out.push(vec![s]),
+        }
+    }
+    out
+}
+
+/// Builds a hole from a chain of sections, finding the planar faces which
+/// join them and close off its ends.  Returns `None` if the chain is closed
+/// at both ends or has no cylindrical sections.
+fn hole(mesh: &Mesh, faces: &[usize], origin: DVec3, axis: DVec3,
+        chain: &[Section], eps: f64) -> Option<Hole>
+{
+    // Finds planar faces at the given height which lie between two radii
+    let floors = |z: f64, r_in: f64, r_out: f64| -> Vec<usize> {
+        faces.iter()
+            .copied()
+            .filter(|&i| mesh.faces[i].surface == SurfaceType::Plane)
+            .filter(|&i| measure(mesh, &mesh.faces[i], origin, axis)
+                .all(|(pz, pr, _, _)| (pz - z).abs() < eps
+                     && pr > r_in - eps && pr < r_out + eps))
+            .collect()
+    };
+    let (first, last) = (&chain[0], &chain[chain.len() - 1]);
+    let mut hole_faces: Vec<usize> = chain.iter()
+        .flat_map(|s| s.faces.iter().copied())
+        .collect();
+
+    // An end is closed by a floor, or by a cone which comes to a point
+    let mut closed = |z: f64, r: f64| {
+        let f = floors(z, 0.0, r);
+        let out = r < eps || !f.is_empty();
+        hole_faces.extend(f);
+        out
+    };
+    let (closed_lo, closed_hi) = (closed(first.z.0, first.r.0), closed(last.z.1, last.r.1));
+    if closed_lo && closed_hi {
+        return None;
+    }
+    for w in chain.windows(2) {
+        let (a, b) = (w[0].r.1, w[1].r.0);
+        if (a - b).abs() > eps {
+            hole_faces.extend(floors(w[0].z.1, a.min(b), a.max(b)));
+        }
+    }
+    hole_faces.sort_unstable();
+    hole_faces.dedup();
+
+    let radius = chain.iter()
+        .filter(|s| !s.cone)
+        .map(|s| s.r.0.min(s.r.1))
+        .fold(None, |a: Option<f64>, r| Some(a.map_or(r, |a| a.min(r))))?;
+
+    // The entrance is the open end, or the wider end of a through hole
+    let from_hi = closed_lo || (!closed_hi && last.r.1 > first.r.0 + eps);
+    let (z, dir, entry) = if from_hi {
+        (last.z.1, -axis, last)
+    } else {
+        (first.z.0, axis, first)
+    };
+    let counterbore = if chain.len() > 1 && !entry.cone
+        && entry.r.0 > radius + eps
+    {
+        Some(Counterbore {
+            diameter: entry.r.0 * 2.0,
+            depth: entry.z.1 - entry.z.0,
+        })
+    } else {
+        None
+    };
+    Some(Hole {
+        origin: origin + axis * z,
+        axis: dir,
+        diameter: radius * 2.0,
+        depth: last.z.1 - first.z.0,
+        through: !closed_lo && !closed_hi,
+        counterbore,
+        faces: hole_faces,
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::DVec2;
+    use crate::{fixtures::{push_surface, push_triangles}, mesh::Vertex, surface::Surface};
+
+    const N: usize = 32;
+
+    /// Adds a cylinder around the Z axis, whose normals point inwards (like
+    /// a hole) or outwards (like a boss)
+    fn cylinder(mesh: &mut Mesh, r: f64, z: (f64, f64), inwards: bool) {
+        let mut verts = Vec::new();
+        for i in 0..N {
+            let a = i as f64 / N as f64 * 2.0 * PI;
+            let radial = DVec3::new(a.cos(), a.sin(), 0.0);
+            let norm = if inwards { -radial } else { radial };
+            for z in [z.0, z.1] {
+                verts.push(Vertex {
+                    pos: radial * r + DVec3::z() * z,
+                    norm, color: DVec3::zeros(), uv: DVec2::zeros(),
+                });
+            }
+        }
+        let tris = (0..N).flat_map(|i| {
+            let j = (i + 1) % N;
+            [[2 * i, 2 * j, 2 * j + 1], [2 * i, 2 * j + 1, 2 * i + 1]]
+        });
+        let surf = Surface::new_cylinder(DVec3::z(), DVec3::x(), DVec3::zeros(), r);
+        let tris = push_triangles(mesh, verts, tris);
+        push_surface(mesh, tris, surf);
+    }
+
+    /// Adds a flat ring (or disk, if `r.0` is zero) at the given height
+    fn ring(mesh: &mut Mesh, z: f64, r: (f64, f64)) {
+        let mut verts = Vec::new();
+        for i in 0..N {
+            let a = i as f64 / N as f64 * 2.0 * PI;
+            let radial = DVec3::new(a.cos(), a.sin(), 0.0);
+            for r in [r.0, r.1] {
+                verts.push(Vertex {
+                    pos: radial * r + DVec3::z() * z,
+                    norm: DVec3::z(), color: DVec3::zeros(), uv: DVec2::zeros(),
+                });
+            }
+        }
+        let tris = (0..N).flat_map(|i| {
+            let j = (i + 1) % N;
+            [[2 * i, 2 * j, 2 * j + 1], [2 * i, 2 * j + 1, 2 * i + 1]]
+        });
+        let surf = Surface::new_plane(DVec3::z(), DVec3::x(), DVec3::z() * z);
+        let tris = push_triangles(mesh, verts, tris);
+        push_surface(mesh, tris, surf);
+    }
+
+    #[test]
+    fn counterbore() {
+        // A hole of diameter 2 and depth 8, below a counterbore of diameter
+        // 4 and depth 2, with the surrounding block's top face at z = 10
+        let mut mesh = Mesh::default();
+        cylinder(&mut mesh, 2.0, (8.0, 10.0), true);
+        ring(&mut mesh, 8.0, (1.0, 2.0));
+        cylinder(&mut mesh, 1.0, (0.0, 8.0), true);
+        ring(&mut mesh, 10.0, (2.0, 20.0));
+
+        let holes = find_holes(&mesh);
+        assert_eq!(holes.len(), 1);
+        let h = &holes[0];
+        assert!(h.through);
+        assert!((h.diameter - 2.0).abs() < 1e-9);
+        assert!((h.depth - 10.0).abs() < 1e-9);
+        assert!((h.origin - DVec3::z() * 10.0).norm() < 1e-9);
+        assert!((h.axis + DVec3::z()).norm() < 1e-9);
+        let c = h.counterbore.unwrap();
+        assert!((c.diameter - 4.0).abs() < 1e-9);
+        assert!((c.depth - 2.0).abs() < 1e-9);
+        assert_eq!(h.faces, vec![0, 1, 2]);
+
+        // Capping the bottom makes it a blind hole, which is entered from
+        // the open end
+        ring(&mut mesh, 0.0, (0.0, 1.0));
+        let h = &find_holes(&mesh)[0];
+        assert!(!h.through);
+        assert!((h.axis + DVec3::z()).norm() < 1e-9);
+        assert_eq!(h.faces, vec![0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn bosses() {
+        // Cylinders whose normals point outwards are pins, not holes
+        let mut mesh = Mesh::default();
+        cylinder(&mut mesh, 1.0, (0.0, 5.0), false);
+        assert!(find_holes(&mesh).is_empty());
+
+        // Half a hole (e.g. a fillet) isn't a hole either
+        let mut mesh = Mesh::default();
+        cylinder(&mut mesh, 1.0, (0.0, 5.0), true);
+        let half = mesh.faces[0].triangles.start..mesh.faces[0].triangles.start + N;
+        mesh.faces[0].triangles = half;
+        assert!(find_holes(&mesh).is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use nalgebra_glm::{DMat3, DVec3};
+
+use crate::rng::Rng;
+
+/// Distance below which points are treated as lying on a hull face (or a
+/// sphere), as a fraction of the points' bounding box diagonal
+const EPSILON: f64 = 1e-10;
+
+/// Convex hull of a set of points, as a closed triangle mesh, e.g. for use
+/// as a collision proxy
+#[derive(Clone, Debug)]
+pub struct ConvexHull {
+    /// The points which are corners of the hull
+    pub points: Vec<DVec3>,
+    /// Triangles indexing `points`, wound counter-clockwise when seen from
+    /// outside the hull
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// A triangle of the hull while it's being built
+struct HullFace {
+    verts: [usize; 3],
+    normal: DVec3,
+    offset: f64,
+    /// Points which are outside this face, and not yet in the hull
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl HullFace {
+    fn new(pts: &[DVec3], verts: [usize; 3]) -> Self {
+        let [a, b, c] = verts.map(|i| pts[i]);
+        let n = (b - a).cross(&(c - a));
+        let normal = if n.norm() > 0.0 { n.normalize() } else { n };
+        Self { verts, normal, offset: normal.dot(&a), outside: vec![], alive: true }
+    }
+
+    /// Returns the signed distance of a point above the face's plane
+    fn distance(&self, p: DVec3) -> f64 {
+        self.normal.dot(&p) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.verts;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Returns the index of the point which maximizes the given function
+fn argmax<F: Fn(DVec3) -> f64>(pts: &[DVec3], f: F) -> usize {
+    (0..pts.len())
+        .map(|i| (i, f(pts[i])))
+        .fold((0, -f64::INFINITY), |b, c| if c.1 > b.1 { c } else { b })
+        .0
+}
+
+/// Returns the diagonal of the points' bounding box
+fn diagonal(pts: &[DVec3]) -> f64 {
+    let (lo, hi) = pts.iter().fold(
+        (DVec3::repeat(f64::INFINITY), DVec3::repeat(-f64::INFINITY)),
+        |(lo, hi), p| (lo.inf(p), hi.sup(p)));
+    (hi - lo).norm()
+}
+
+impl ConvexHull {
+    /// Builds the convex hull of a set of points with quickhull.  Starting
+    /// from a tetrahedron of extreme points, each face with points outside
+    /// it is replaced by a cone of faces out to its farthest point, until
+    /// no points are left outside.
+    ///
+    /// Returns `None` if there are fewer than four points, or if they're
+    /// all (nearly) coplanar.  Points closer than a small tolerance to the
+    /// hull's faces are left out of it, so nearly-flat faces stay flat.
+    pub fn from_points(pts: &[DVec3]) -> Option<Self> {
+        let eps = diagonal(pts) * EPSILON;
+        if pts.len() < 4 || eps.is_nan() || eps <= 0.0 {
+            return None;
+        }
+
+        // The initial tetrahedron has the two points which are farthest
+        // apart along the longest axis, the point farthest from the line
+        // through them, and the point farthest from the plane through those
+        // three
+        let extent = |i: usize| pts.iter().map(|p| p[i]).fold(-f64::INFINITY, f64::max)
+            - pts.iter().map(|p| p[i]).fold(f64::INFINITY, f64::min);
+        let axis = (0..3).map(|i| (i, extent(i)))
+            .fold((0, -1.0), |b, c| if c.1 > b.1 { c } else { b })
+            .0;
+        let a = argmax(pts, |p| -p[axis]);
+        let b = argmax(pts, |p| p[axis]);
+        let dir = (pts[b] - pts[a]).normalize();
+        let off_line = |p: DVec3| {
+            let d = p - pts[a];
+            (d - dir * d.dot(&dir)).norm()
+        };
+        let c = argmax(pts, off_line);
+        if off_line(pts[c]) <= eps {
+            return None;
+        }
+        let n = (pts[b] - pts[a]).cross(&(pts[c] - pts[a])).normalize();
+        let d = argmax(pts, |p| n.dot(&(p - pts[a])).abs());
+        let height = n.dot(&(pts[d] - pts[a]));
+        if height.abs() <= eps {
+            return None;
+        }
+        // Wind the base so that it faces away from the apex
+        let base = if height > 0.0 { [a, c, b] } else { [a, b, c] };
+        let mut faces = vec![HullFace::new(pts, base)];
+        for (s, t) in faces[0].edges() {
+            faces.push(HullFace::new(pts, [t, s, d]));
+        }
+
+        // Each directed edge of a live face maps to that face, so that the
+        // face across an edge (s, t) is the one with edge (t, s)
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for (i, f) in faces.iter().enumerate() {
+            for e in f.edges() {
+                edges.insert(e, i);
+            }
+        }
+        for (i, p) in pts.iter().enumerate() {
+            if [a, b, c, d].contains(&i) {
+                continue;
+            }
+            if let Some(f) = faces.iter_mut().find(|f| f.distance(*p) > eps) {
+                f.outside.push(i);
+            }
+        }
+
+        let mut todo: Vec<usize> = (0..faces.len()).collect();
+        while let Some(fi) = todo.pop() {
+            if !faces[fi].alive || faces[fi].outside.is_empty() {
+                continue;
+            }
+            let f = &faces[fi];
+            let p = *f.outside.iter()
+                .max_by(|&&i, &&j| f.distance(pts[i])
+                    .partial_cmp(&f.distance(pts[j])).unwrap())
+                .unwrap();
+
+            // Find every face which can see the point, spreading out from
+            // this one, along with the horizon around them
+            let mut visible = vec![fi];
+            let mut horizon = Vec::new();
+            let mut i = 0;
+            while i < visible.len() {
+                for (s, t) in faces[visible[i]].edges() {
+                    let next = edges[&(t, s)];
+                    if visible.contains(&next) {
+                        continue;
+                    } else if faces[next].distance(pts[p]) > eps {
+                        visible.push(next);
+                    } else {
+                        horizon.push((s, t));
+                    }
+                }
+                i += 1;
+            }
+
+            let mut orphans = Vec::new();
+            for &v in &visible {
+                faces[v].alive = false;
+                orphans.append(&mut faces[v].outside);
+            }
+            let start = faces.len();
+            for (s, t) in horizon {
+                let f = HullFace::new(pts, [s, t, p]);
+                for e in f.edges() {
+                    edges.insert(e, faces.len());
+                }
+                todo.push(faces.len());
+                faces.push(f);
+            }
+            for i in orphans {
+                if i == p {
+                    continue;
+                }
+                if let Some(f) = faces[start..].iter_mut().find(|f| f.distance(pts[i]) > eps) {
+                    f.outside.push(i);
+                }
+            }
+        }
+
+        // Keep only the points which are corners of the final hull
+        let mut index = vec![None; pts.len()];
+        let mut points = Vec::new();
+        let triangles = faces.iter()
+            .filter(|f| f.alive)
+            .map(|f| f.verts.map(|v| *index[v].get_or_insert_with(|| {
+                points.push(pts[v]);
+                points.len() - 1
+            })))
+            .collect();
+        Some(Self { points, triangles })
+    }
+
+    /// Returns the volume enclosed by the hull
+    pub fn volume(&self) -> f64 {
+        let o = self.points[0];
+        self.triangles.iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| self.points[i] - o);
+                a.dot(&b.cross(&c)) / 6.0
+            })
+            .sum()
+    }
+
+    /// Returns the hull's surface area
+    pub fn area(&self) -> f64 {
+        self.triangles.iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| self.points[i]);
+                (b - a).cross(&(c - a)).norm() / 2.0
+            })
+            .sum()
+    }
+}
+
+/// Smallest sphere which encloses a set of points, e.g. for picking a level
+/// of detail by its size on screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: DVec3,
+    pub radius: f64,
+}
+
+impl BoundingSphere {
+    /// Finds the smallest sphere around every point with Welzl's algorithm.
+    /// Points are visited in a shuffled (but repeatable) order, which keeps
+    /// the expected running time linear; passing only the corners of the
+    /// [`ConvexHull`] gives the same sphere, faster.
+    ///
+    /// Returns `None` if there are no points.
+    pub fn from_points(pts: &[DVec3]) -> Option<Self> {
+        let mut pts = pts.to_vec();
+        let mut rng = Rng(pts.len() as u64);
+        for i in (1..pts.len()).rev() {
+            let j = ((rng.next() * (i + 1) as f64) as usize).min(i);
+            pts.swap(i, j);
+        }
+        let eps = diagonal(&pts) * EPSILON;
+        let outside = |s: &Self, p: DVec3| (p - s.center).norm() > s.radius + eps;
+
+        let mut s = Self { center: *pts.first()?, radius: 0.0 };
+        for i in 1..pts.len() {
+            if !outside(&s, pts[i]) {
+                continue;
+            }
+            // Each nested loop finds the smallest sphere with another point
+            // on its surface
+            s = Self { center: pts[i], radius: 0.0 };
+            for j in 0..i {
+                if !outside(&s, pts[j]) {
+                    continue;
+                }
+                s = Self::through(&[pts[i], pts[j]]);
+                for k in 0..j {
+                    if !outside(&s, pts[k]) {
+                        continue;
+                    }
+                    s = Self::through(&[pts[i], pts[j], pts[k]]);
+                    for l in 0..k {
+                        if outside(&s, pts[l]) {
+                            s = Self::through(&[pts[i], pts[j], pts[k], pts[l]]);
+                        }
+                    }
+                }
+            }
+        }
+        Some(s)
+    }
+
+    /// Returns the smallest sphere with two to four points on its surface,
+    /// falling back to a sphere around all of them if the points are
+    /// collinear (or coplanar, for four points)
+    fn through(pts: &[DVec3]) -> Self {
+        let a = pts[0];
+        match pts.len() {
+            2 => Self { center: (a + pts[1]) / 2.0, radius: (pts[1] - a).norm() / 2.0 },
+            3 => {
+                let (ab, ac) = (pts[1] - a, pts[2] - a);
+                let n = ab.cross(&ac);
+                if n.norm_squared() <= (ab.norm_squared() * ac.norm_squared()) * 1e-24 {
+                    return Self::around_pairs(pts);
+                }
+                let d = (n.cross(&ab) * ac.norm_squared()
+                    + ac.cross(&n) * ab.norm_squared()) / (2.0 * n.norm_squared());
+                Self { center: a + d, radius: d.norm() }
+            },
+            _ => {
+                let rows = [1, 2, 3].map(|i| pts[i] - a);
+                let m = DMat3::from_rows(&rows.map(|r| r.transpose()));
+                let rhs = DVec3::new(rows[0].norm_squared(), rows[1].norm_squared(),
+                                     rows[2].norm_squared()) / 2.0;
+                let scale = rows.iter().map(|r| r.norm()).product::<f64>();
+                match m.try_inverse() {
+                    Some(inv) if m.determinant().abs() > scale * 1e-12 => {
+                        let d = inv * rhs;
+                        Self { center: a + d, radius: d.norm() }
+                    },
+                    // The points lie on a circle (or closer), so the sphere
+                    // through three of them contains the fourth
+                    _ => [[0, 1, 2], [0, 1, 3], [0, 2, 3], [1, 2, 3]].iter()
+                        .map(|t| Self::through(&t.map(|i| pts[i])))
+                        .filter(|s| pts.iter().all(|p|
+                            (p - s.center).norm() <= s.radius * (1.0 + 1e-9)))
+                        .min_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap())
+                        .unwrap_or_else(|| Self::around_pairs(pts)),
+                }
+            },
+        }
+    }
+
+    /// Returns the sphere with the two farthest-apart points as a diameter
+    fn around_pairs(pts: &[DVec3]) -> Self {
+        let mut best = Self { center: pts[0], radius: 0.0 };
+        for (i, a) in pts.iter().enumerate() {
+            for b in &pts[i + 1..] {
+                if (b - a).norm() / 2.0 > best.radius {
+                    best = Self::through(&[*a, *b]);
+                }
+            }
+        }
+        best
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns random points in the unit cube
+    fn random_points(n: usize, seed: u64) -> Vec<DVec3> {
+        let mut rng = Rng(seed);
+        (0..n).map(|_| DVec3::new(rng.next(), rng.next(), rng.next())).collect()
+    }
+
+    #[test]
+    fn cube_hull() {
+        // The corners of a box, the middle of each edge, and points inside
+        let mut pts = random_points(200, 1);
+        for i in 0..8 {
+            pts.push(DVec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, (i >> 2) as f64));
+        }
+        for i in 0..3 {
+            let mut p = DVec3::repeat(0.0);
+            p[i] = 0.5;
+            pts.push(p);
+        }
+        let hull = ConvexHull::from_points(&pts).unwrap();
+        assert_eq!(hull.points.len(), 8);
+        assert_eq!(hull.triangles.len(), 12);
+        assert!((hull.volume() - 1.0).abs() < 1e-12, "{}", hull.volume());
+        assert!((hull.area() - 6.0).abs() < 1e-12, "{}", hull.area());
+
+        assert!(ConvexHull::from_points(&pts[..3]).is_none());
+        let flat: Vec<DVec3> = pts.iter().map(|p| DVec3::new(p.x, p.y, 0.0)).collect();
+        assert!(ConvexHull::from_points(&flat).is_none());
+        let line: Vec<DVec3> = pts.iter().map(|p| DVec3::new(0.0, p.y, 0.0)).collect();
+        assert!(ConvexHull::from_points(&line).is_none());
+    }
+
+    #[test]
+    fn random_hull() {
+        let pts = random_points(2000, 2);
+        let hull = ConvexHull::from_points(&pts).unwrap();
+        // Every point is inside every face, and every edge is shared by
+        // exactly two faces going opposite ways
+        let mut edges = HashMap::new();
+        for t in &hull.triangles {
+            let [a, b, c] = t.map(|i| hull.points[i]);
+            let n = (b - a).cross(&(c - a));
+            assert!(pts.iter().all(|p| n.dot(&(p - a)) <= 1e-12));
+            for e in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                *edges.entry(e).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.iter().all(|(&(a, b), &n)| n == 1 && edges.get(&(b, a)) == Some(&1)));
+        assert_eq!(hull.triangles.len(), 2 * hull.points.len() - 4);
+    }
+
+    #[test]
+    fn bounding_sphere() {
+        // A regular tetrahedron, whose circumsphere is the smallest
+        let tet = [DVec3::new(1.0, 1.0, 1.0), DVec3::new(1.0, -1.0, -1.0),
+                   DVec3::new(-1.0, 1.0, -1.0), DVec3::new(-1.0, -1.0, 1.0)];
+        let s = BoundingSphere::from_points(&tet).unwrap();
+        assert!(s.center.norm() < 1e-12);
+        assert!((s.radius - 3f64.sqrt()).abs() < 1e-12);
+
+        // Points inside a sphere, plus two points on opposite sides of it
+        let mut pts: Vec<DVec3> = random_points(1000, 3).into_iter()
+            .map(|p| p * 2.0 - DVec3::repeat(1.0))
+            .filter(|p| p.norm() < 1.0)
+            .collect();
+        pts.push(DVec3::new(0.0, 0.0, 1.2));
+        pts.push(DVec3::new(0.0, 0.0, -1.2));
+        let s = BoundingSphere::from_points(&pts).unwrap();
+        assert!(s.center.norm() < 1e-12, "{:?}", s.center);
+        assert!((s.radius - 1.2).abs() < 1e-12, "{}", s.radius);
+
+        // The hull's corners give the same sphere
+        let hull = ConvexHull::from_points(&pts).unwrap();
+        let h = BoundingSphere::from_points(&hull.points).unwrap();
+        assert!((h.center - s.center).norm() < 1e-12 && (h.radius - s.radius).abs() < 1e-12);
+
+        assert!(BoundingSphere::from_points(&[]).is_none());
+    }
+}
@@ -1,7 +1,40 @@
+pub mod bvh;
+pub mod clash;
+pub mod curvature;
+pub mod decimate;
+pub mod diff;
+pub mod draughting;
+pub mod external;
+#[cfg(test)]
+mod fixtures;
+mod grid;
+pub mod groups;
+pub mod holes;
+pub mod hull;
+pub mod mass;
+pub mod material;
+pub mod memory;
 pub mod mesh;
+pub mod obb;
+pub mod outline;
+mod ply;
+pub mod points;
+pub mod quad;
+pub mod render;
+mod rng;
+pub mod scan;
+pub mod sdf;
+pub mod section;
+pub mod sew;
+pub mod slice;
+pub mod spill;
 pub mod stats;
 pub mod surface;
+pub mod svg;
+pub mod thickness;
 pub mod triangulate;
+pub mod units;
+pub mod voxel;
 pub mod curve;
 
 #[derive(thiserror::Error, Debug, Eq, PartialEq)]
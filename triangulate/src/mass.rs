@@ -0,0 +1,80 @@
+use nalgebra_glm::{DMat3, DVec3};
+
+use crate::mesh::Mesh;
+
+/// Mass properties of one or more solids
+#[derive(Copy, Clone, Debug)]
+pub struct MassProperties {
+    /// Enclosed volume, in cubic model units
+    pub volume: f64,
+    /// Mass, in kg
+    pub mass: f64,
+    /// Center of mass, in model units
+    pub center: DVec3,
+    /// Inertia tensor about the center of mass, in kg·m²
+    pub inertia: DMat3,
+}
+
+impl MassProperties {
+    /// Returns the principal moments of inertia (in kg·m², smallest first)
+    pub fn principal_moments(&self) -> DVec3 {
+        let mut m: Vec<f64> = self.inertia.symmetric_eigenvalues().iter()
+            .copied()
+            .collect();
+        m.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        DVec3::new(m[0], m[1], m[2])
+    }
+}
+
+/// Computes the mass properties of a set of solids, each given as a range
+/// of triangles in the mesh and a density in kg/m³.  `length_unit` is the
+/// length of one model unit in metres.
+///
+/// Each solid's triangles should form a closed shell; its volume is split
+/// into tetrahedra from a common point to each triangle, whose moments are
+/// summed.  Inside-out shells are flipped, so the winding doesn't matter.
+/// Returns `None` if the solids enclose no volume.
+pub fn mass_properties<I>(mesh: &Mesh, solids: I, length_unit: f64)
+    -> Option<MassProperties>
+    where I: IntoIterator<Item=(std::ops::Range<usize>, f64)>
+{
+    // Moments are taken about a point on the model, rather than the origin,
+    // to avoid cancellation in models which are far from the origin
+    let mut origin = None;
+    let (mut volume, mut mass) = (0.0, 0.0);
+    let mut first = DVec3::zeros();
+    let mut second = DMat3::zeros();
+    for (triangles, density) in solids {
+        let (mut v, mut f, mut s) = (0.0, DVec3::zeros(), DMat3::zeros());
+        for t in &mesh.triangles[triangles] {
+            let o = *origin.get_or_insert(mesh.verts[t.verts.x as usize].pos);
+            let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+                .map(|i| mesh.verts[i as usize].pos - o);
+            let dv = a.dot(&b.cross(&c)) / 6.0;
+            let sum = a + b + c;
+            v += dv;
+            f += sum * (dv / 4.0);
+            s += (a * a.transpose() + b * b.transpose() + c * c.transpose()
+                  + sum * sum.transpose()) * (dv / 20.0);
+        }
+        let sign = v.signum();
+        let k = density * length_unit.powi(3) * sign;
+        volume += v * sign;
+        mass += v * k;
+        first += f * k;
+        second += s * k;
+    }
+    if volume <= 0.0 || mass <= 0.0 {
+        return None;
+    }
+
+    let offset = first / mass;
+    let covariance = (second - offset * offset.transpose() * mass)
+        * length_unit.powi(2);
+    let inertia = DMat3::identity() * covariance.trace() - covariance;
+    Some(MassProperties {
+        volume, mass,
+        center: origin.unwrap_or_else(DVec3::zeros) + offset,
+        inertia,
+    })
+}
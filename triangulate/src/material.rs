@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use log::warn;
+use step::{
+    ap214::{Entity, MeasureValue, Representation, ShapeRepresentationRelationship_},
+    step_file::{FromEntity, StepFile},
+};
+
+use crate::units;
+
+/// Material properties which a STEP file assigns to a product
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Material {
+    /// Name of the material, e.g. `Steel`
+    pub name: Option<String>,
+    /// Density in kg/m³
+    pub density: Option<f64>,
+    /// Description of the surface finish, e.g. `Anodized`
+    pub finish: Option<String>,
+}
+
+impl Material {
+    fn is_empty(&self) -> bool {
+        *self == Material::default()
+    }
+
+    /// Estimates a mass in kg, given a volume in model units and the length
+    /// of one model unit in metres
+    pub fn mass(&self, volume: f64, length_unit: f64) -> Option<f64> {
+        self.density.map(|d| d * volume * length_unit.powi(3))
+    }
+}
+
+/// Finds the product definition which a characterized definition refers
+/// to, either directly or through its shape
+fn product_definition(s: &StepFile, id: usize) -> Option<usize> {
+    match s.0.get(id)? {
+        Entity::ProductDefinition(_) => Some(id),
+        Entity::ProductDefinitionShape(p) => match s.0.get(p.definition.0)? {
+            Entity::ProductDefinition(_) => Some(p.definition.0),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Reads a plain number from a measure, or `None` for non-numeric measures
+fn measure_value(v: &MeasureValue) -> Option<f64> {
+    match v {
+        MeasureValue::PositiveRatioMeasure(m) => Some(m.0.0),
+        MeasureValue::RatioMeasure(m) => Some(m.0),
+        MeasureValue::NumericMeasure(m) => Some(m.0),
+        MeasureValue::MassMeasure(m) => Some(m.0),
+        MeasureValue::ParameterValue(m) => Some(m.0),
+        _ => None,
+    }
+}
+
+/// Builds a map from representations to the materials of the products which
+/// they represent (like `product_names` in the triangulator).
+///
+/// Materials are read from `MATERIAL_DESIGNATION` entities and from
+/// property definitions whose representations hold a descriptive material
+/// name or surface finish, or a density measure (the layout used by most
+/// CAD exporters).
+fn product_materials<'a>(s: &'a StepFile) -> HashMap<Representation<'a>, Material> {
+    let mut by_product: HashMap<usize, Material> = HashMap::new();
+    for e in &s.0 {
+        match e {
+            Entity::MaterialDesignation(m) => {
+                for d in &m.definitions {
+                    if let Some(pd) = product_definition(s, d.0) {
+                        by_product.entry(pd).or_default().name
+                            .get_or_insert_with(|| m.name.0.to_string());
+                    }
+                }
+            },
+            Entity::PropertyDefinitionRepresentation(r) => {
+                let prop = match s.0.get(r.definition.0) {
+                    Some(Entity::PropertyDefinition(p)) => p,
+                    _ => continue,
+                };
+                let (pd, rep) = match (product_definition(s, prop.definition.0),
+                                       s.0.get(r.used_representation.0)) {
+                    (Some(pd), Some(Entity::Representation(rep))) => (pd, rep),
+                    _ => continue,
+                };
+                // Exporters disagree on which label says what the property
+                // is, so all of them are checked
+                let context = format!("{} {} {}", prop.name.0,
+                                      prop.description.as_ref().map_or("", |d| &d.0),
+                                      rep.name.0).to_lowercase();
+                let material = by_product.entry(pd).or_default();
+                for item in &rep.items {
+                    match s.0.get(item.0) {
+                        Some(Entity::DescriptiveRepresentationItem(d)) => {
+                            let label = format!("{} {}", context, d.name.0).to_lowercase();
+                            let text = if d.description.0.is_empty() {
+                                &d.name.0
+                            } else {
+                                &d.description.0
+                            }.to_string();
+                            if label.contains("finish") {
+                                material.finish.get_or_insert(text);
+                            } else if label.contains("material") {
+                                material.name.get_or_insert(text);
+                            }
+                        },
+                        Some(Entity::MeasureRepresentationItem(m)) => {
+                            let label = format!("{} {}", context, m.name.0).to_lowercase();
+                            if !label.contains("density") {
+                                continue;
+                            }
+                            match (measure_value(&m.value_component),
+                                   units::density_scale(s, m.unit_component.0)) {
+                                (Some(v), Some(k)) => {
+                                    material.density.get_or_insert(v * k);
+                                },
+                                _ => warn!("Skipping density with unknown value or unit"),
+                            }
+                        },
+                        _ => (),
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+    by_product.retain(|_, m| !m.is_empty());
+
+    s.0.iter()
+        .filter_map(|e| match e {
+            Entity::ShapeDefinitionRepresentation(r) => Some(r),
+            _ => None,
+        })
+        .filter_map(|r| product_definition(s, r.definition.0)
+            .and_then(|pd| by_product.get(&pd))
+            .map(|m| (r.used_representation, m.clone())))
+        .collect()
+}
+
+/// Builds a map from solid entities to the materials of the products which
+/// own them.  Solids are found in the product's own representation, or in
+/// one which it's linked to by a `SHAPE_REPRESENTATION_RELATIONSHIP`, which
+/// is how many exporters attach the B-rep to the product's shape.
+pub(crate) fn solid_materials(s: &StepFile) -> HashMap<usize, Material> {
+    let reps = product_materials(s);
+    let mut linked: Vec<(usize, &Material)> = reps.iter()
+        .map(|(r, m)| (r.0, m))
+        .collect();
+    // Sort by entity id, so that the output doesn't depend on the hash
+    // map's iteration order
+    linked.sort_by_key(|(r, _)| *r);
+    for r in s.0.iter().filter_map(ShapeRepresentationRelationship_::try_from_entity) {
+        if let Some(m) = reps.get(&r.rep_1) {
+            linked.push((r.rep_2.0, m));
+        } else if let Some(m) = reps.get(&r.rep_2) {
+            linked.push((r.rep_1.0, m));
+        }
+    }
+
+    let mut out = HashMap::new();
+    for (r, m) in linked {
+        let items = match s.0.get(r) {
+            Some(Entity::AdvancedBrepShapeRepresentation(b)) => &b.items,
+            Some(Entity::ShapeRepresentation(b)) => &b.items,
+            Some(Entity::ManifoldSurfaceShapeRepresentation(b)) => &b.items,
+            _ => continue,
+        };
+        for item in items {
+            out.entry(item.0).or_insert_with(|| m.clone());
+        }
+    }
+    out
+}
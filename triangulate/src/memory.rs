@@ -0,0 +1,194 @@
+//! Memory instrumentation for the loading pipeline.
+//!
+//! With the `memory-profile` feature, installing [`CountingAlloc`] as the
+//! global allocator counts allocations per pipeline [`Stage`], and each
+//! face's CDT records the size of its hull and half-edge arrays.  Without
+//! the feature, [`stage`] simply runs its closure and [`report`] is empty.
+//!
+//! Stages are tracked per thread, so work on other threads (e.g. rayon
+//! workers) is attributed correctly as long as each one marks its stage.
+
+/// A part of the loading pipeline
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stage {
+    /// Anything outside of the other stages
+    Other,
+    /// Reading and parsing a STEP file
+    Parse,
+    /// Building each face's constrained Delaunay triangulation, including
+    /// its sweep-line hull
+    Triangulate,
+    /// Sampling curves and surfaces, sewing, and placing solids into the
+    /// output mesh
+    Assembly,
+}
+
+/// Allocation counts for one stage
+#[derive(Copy, Clone, Debug)]
+pub struct StageStats {
+    pub stage: Stage,
+    pub allocations: usize,
+    /// Total bytes allocated (not counting frees)
+    pub bytes: usize,
+    /// Largest number of bytes live across the whole process at any
+    /// allocation made during this stage
+    pub peak: usize,
+}
+
+/// Everything recorded since the last [`reset`]
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub stages: Vec<StageStats>,
+    /// Number of faces triangulated
+    pub faces: usize,
+    /// Largest CDT data structures built for a single face, in bytes
+    pub max_cdt: cdt::MemoryUsage,
+    /// Sum of every face's hull size, in bytes
+    pub total_hull: usize,
+}
+
+/// Runs `f`, attributing allocations on this thread to the given stage
+pub fn stage<T, F: FnOnce() -> T>(s: Stage, f: F) -> T {
+    #[cfg(feature = "memory-profile")]
+    {
+        /// Restores the previous stage, even if `f` panics
+        struct Restore(u8);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                profile::STAGE.with(|c| c.set(self.0));
+            }
+        }
+        let _restore = Restore(profile::STAGE.with(|c| c.replace(s as u8)));
+        f()
+    }
+    #[cfg(not(feature = "memory-profile"))]
+    {
+        let _ = s;
+        f()
+    }
+}
+
+/// Records the data structures of a finished triangulation
+pub fn record_cdt(t: &cdt::Triangulation) {
+    #[cfg(feature = "memory-profile")]
+    profile::record_cdt(t.memory_usage());
+    #[cfg(not(feature = "memory-profile"))]
+    let _ = t;
+}
+
+/// Returns everything recorded so far, which is empty unless the
+/// `memory-profile` feature is enabled (and allocation counts are zero
+/// unless [`CountingAlloc`] is the global allocator)
+pub fn report() -> Report {
+    #[cfg(feature = "memory-profile")]
+    {
+        profile::report()
+    }
+    #[cfg(not(feature = "memory-profile"))]
+    {
+        Report::default()
+    }
+}
+
+/// Clears all recorded statistics
+pub fn reset() {
+    #[cfg(feature = "memory-profile")]
+    profile::reset();
+}
+
+#[cfg(feature = "memory-profile")]
+pub use profile::CountingAlloc;
+
+#[cfg(feature = "memory-profile")]
+mod profile {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{Report, Stage, StageStats};
+
+    const STAGES: [Stage; 4] = [Stage::Other, Stage::Parse, Stage::Triangulate, Stage::Assembly];
+
+    thread_local! {
+        pub(super) static STAGE: Cell<u8> = const { Cell::new(0) };
+    }
+
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+    static ALLOCATIONS: [AtomicUsize; STAGES.len()] =
+        [const { AtomicUsize::new(0) }; STAGES.len()];
+    static BYTES: [AtomicUsize; STAGES.len()] =
+        [const { AtomicUsize::new(0) }; STAGES.len()];
+    static PEAK: [AtomicUsize; STAGES.len()] =
+        [const { AtomicUsize::new(0) }; STAGES.len()];
+
+    static CDT: Mutex<(usize, cdt::MemoryUsage, usize)> =
+        Mutex::new((0, cdt::MemoryUsage { points: 0, hull: 0, half_edges: 0 }, 0));
+
+    /// A global allocator which wraps the system allocator, counting
+    /// allocations by stage.  Install it in a binary with
+    /// `#[global_allocator] static A: CountingAlloc = CountingAlloc;`
+    pub struct CountingAlloc;
+
+    fn count(size: usize) {
+        let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+        // The thread-local may already be gone while a thread shuts down
+        let s = STAGE.try_with(|c| c.get()).unwrap_or(0) as usize;
+        ALLOCATIONS[s].fetch_add(1, Ordering::Relaxed);
+        BYTES[s].fetch_add(size, Ordering::Relaxed);
+        PEAK[s].fetch_max(live, Ordering::Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count(layout.size());
+            System.alloc(layout)
+        }
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            count(layout.size());
+            System.alloc_zeroed(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr, layout)
+        }
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+            count(new_size);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    pub fn record_cdt(m: cdt::MemoryUsage) {
+        let mut c = CDT.lock().expect("Could not lock CDT stats");
+        c.0 += 1;
+        c.1.points = c.1.points.max(m.points);
+        c.1.hull = c.1.hull.max(m.hull);
+        c.1.half_edges = c.1.half_edges.max(m.half_edges);
+        c.2 += m.hull;
+    }
+
+    pub fn report() -> Report {
+        let c = *CDT.lock().expect("Could not lock CDT stats");
+        Report {
+            stages: STAGES.iter().enumerate()
+                .map(|(i, stage)| StageStats {
+                    stage: *stage,
+                    allocations: ALLOCATIONS[i].load(Ordering::Relaxed),
+                    bytes: BYTES[i].load(Ordering::Relaxed),
+                    peak: PEAK[i].load(Ordering::Relaxed),
+                })
+                .collect(),
+            faces: c.0,
+            max_cdt: c.1,
+            total_hull: c.2,
+        }
+    }
+
+    pub fn reset() {
+        for a in ALLOCATIONS.iter().chain(&BYTES).chain(&PEAK) {
+            a.store(0, Ordering::Relaxed);
+        }
+        *CDT.lock().expect("Could not lock CDT stats") = Default::default();
+    }
+}
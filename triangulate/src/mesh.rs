@@ -1,21 +1,205 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
-use nalgebra_glm::{DVec3, U32Vec3};
+use std::sync::Arc;
+use nalgebra_glm::{DMat4, DVec2, DVec3, U32Vec3};
+
+use crate::{material::Material, ply, surface::Surface};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub pos: DVec3,
     pub norm: DVec3,
     pub color: DVec3,
+
+    /// Surface parameters used during triangulation, normalized to the 0-1
+    /// range within each face
+    pub uv: DVec2,
 }
 #[derive(Copy, Clone, Debug)]
 pub struct Triangle {
     pub verts: U32Vec3,
 }
 
-#[derive(Default)]
+/// A contiguous run of triangles generated from a single solid.  If a solid
+/// is instanced multiple times in an assembly, each instance gets its own
+/// `Solid` with a distinct triangle range.
+#[derive(Clone, Debug)]
+pub struct Solid {
+    /// Index of the source entity in the STEP file
+    pub id: usize,
+    /// Name of the product which owns this solid, falling back to the solid's
+    /// own label (if either is present in the file)
+    pub name: Option<String>,
+    /// Color assigned to the solid by the STEP file's styling, if any.
+    /// Unstyled solids are drawn in a default gray.
+    pub color: Option<DVec3>,
+    /// Material (name, density, and finish) of the product which owns this
+    /// solid, if the STEP file assigns one
+    pub material: Option<Material>,
+    pub triangles: std::ops::Range<usize>,
+
+    /// If this is a repeated instance of an earlier solid, then this is the
+    /// index of that solid in `Mesh::solids`.  Both cover the same number of
+    /// triangles, in the same order.
+    pub instance_of: Option<usize>,
+    /// Transform from the original solid's vertex positions to this one's
+    /// (identity unless `instance_of` is set)
+    pub transform: DMat4,
+}
+
+/// A product in the file's assembly tree
+#[derive(Clone, Debug)]
+pub struct AssemblyNode {
+    pub name: String,
+    /// Index of the enclosing product in `Mesh::assembly`, if any
+    pub parent: Option<usize>,
+    /// Transform from this product's coordinates into its parent's (or into
+    /// the mesh's coordinates, for a root)
+    pub transform: DMat4,
+    /// Indices into `Mesh::solids` of the solids directly in this product
+    pub solids: Vec<usize>,
+}
+
+/// The kind of underlying surface for a face
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SurfaceType {
+    Plane,
+    Cylinder,
+    Cone,
+    Sphere,
+    Torus,
+    BSpline,
+    NURBS,
+}
+
+/// Analytic dimensions of a face's underlying surface, taken from the STEP
+/// data rather than measured from the tessellation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dimensions {
+    Cylinder { radius: f64 },
+    /// `radius` is measured at the cone's placement, and `half_angle` is
+    /// in radians (whatever the file's plane angle units)
+    Cone { radius: f64, half_angle: f64 },
+    Sphere { radius: f64 },
+    Torus { major_radius: f64, minor_radius: f64 },
+}
+
+impl Dimensions {
+    /// Returns the dimensions of the surface after uniform scaling, which
+    /// changes lengths but not angles
+    pub fn scaled(self, factor: f64) -> Self {
+        match self {
+            Dimensions::Cylinder { radius } =>
+                Dimensions::Cylinder { radius: radius * factor },
+            Dimensions::Cone { radius, half_angle } =>
+                Dimensions::Cone { radius: radius * factor, half_angle },
+            Dimensions::Sphere { radius } =>
+                Dimensions::Sphere { radius: radius * factor },
+            Dimensions::Torus { major_radius, minor_radius } =>
+                Dimensions::Torus { major_radius: major_radius * factor,
+                                    minor_radius: minor_radius * factor },
+        }
+    }
+}
+
+/// A contiguous run of triangles generated from a single face.  Like
+/// [`Solid`], instanced faces are recorded once per instance.
+#[derive(Clone, Debug)]
+pub struct Face {
+    /// Index of the source `ADVANCED_FACE` entity in the STEP file
+    pub id: usize,
+    pub triangles: std::ops::Range<usize>,
+    pub surface: SurfaceType,
+    pub dimensions: Option<Dimensions>,
+
+    /// The face's underlying surface, in the solid's local coordinates,
+    /// which is shared between instances of the face
+    pub geometry: Option<Arc<Surface>>,
+    /// Transform from the surface's local coordinates into the mesh's
+    /// coordinates (which differs between instances)
+    pub transform: DMat4,
+
+    /// True if the face couldn't be triangulated, in which case its
+    /// triangles are a placeholder fan over its boundary loops
+    pub failed: bool,
+    /// Number of quads in each direction if the face was tessellated as a
+    /// structured grid, in which case its triangles come in pairs which
+    /// make up each quad (see [`TessellationParams::quad_grids`])
+    ///
+    /// [`TessellationParams::quad_grids`]: crate::triangulate::TessellationParams::quad_grids
+    pub grid: Option<[usize; 2]>,
+}
+
+impl Face {
+    /// Projects a point onto the face's untrimmed analytic surface, if known
+    pub fn project(&self, p: DVec3) -> Option<DVec3> {
+        let surf = self.geometry.as_ref()?;
+        let mat_i = self.transform.try_inverse()?;
+        let local = (mat_i * p.push(1.0)).xyz();
+        let out = surf.project(local)?;
+        Some((self.transform * out.push(1.0)).xyz())
+    }
+}
+
+/// Which axis points upwards in a mesh's coordinates.  STEP models are
+/// Z-up, while glTF (and most animation and game tools) expect Y-up.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    /// Returns the transform which takes a Z-up model into this convention.
+    /// This is a rotation (rather than swapping axes) so that the model
+    /// stays right-handed.
+    pub fn from_z_up(self) -> DMat4 {
+        match self {
+            UpAxis::Y => DMat4::new(1.0, 0.0, 0.0, 0.0,
+                                    0.0, 0.0, 1.0, 0.0,
+                                    0.0, -1.0, 0.0, 0.0,
+                                    0.0, 0.0, 0.0, 1.0),
+            UpAxis::Z => DMat4::identity(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Mesh {
     pub verts: Vec<Vertex>,
     pub triangles: Vec<Triangle>,
+    pub solids: Vec<Solid>,
+    pub faces: Vec<Face>,
+    /// Length of one model unit in metres, if the file declares it
+    pub length_unit: Option<f64>,
+    /// Products in the file's assembly, listed before their children.
+    /// Solids which aren't part of a named product aren't included.
+    pub assembly: Vec<AssemblyNode>,
+    /// Curves of the file's 2D annotations (such as graphical PMI), as
+    /// polylines in model coordinates.  These are only collected if
+    /// requested in the [`TessellationParams`](crate::triangulate::TessellationParams).
+    pub annotations: Vec<Vec<DVec3>>,
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Returns the unit normal of a triangle as written to STL files, which is
+/// zero for degenerate triangles
+pub(crate) fn facet_normal(a: DVec3, b: DVec3, c: DVec3) -> [f32; 3] {
+    let n = (b - a).cross(&(c - a));
+    let n = if n.norm() > 0.0 { n.normalize() } else { n };
+    [n.x as f32, n.y as f32, n.z as f32]
+}
+
+/// Finds the index of the item whose triangle range contains `t`, given a
+/// list of items sorted by (non-overlapping) triangle ranges.
+fn find_range<T, F>(items: &[T], range: F, t: usize) -> Option<usize>
+    where F: Fn(&T) -> &std::ops::Range<usize>
+{
+    let i = items.partition_point(|a| range(a).end <= t);
+    items.get(i).filter(|a| range(a).contains(&t)).map(|_| i)
 }
 
 impl Mesh {
@@ -23,12 +207,209 @@ impl Mesh {
     // (why yes, this _is_ a monoid)
     pub fn combine(mut a: Self, b: Self) -> Self {
         let dv = a.verts.len().try_into().expect("too many triangles");
+        let dt = a.triangles.len();
+        let ds = a.solids.len();
+        a.solids.extend(b.solids.into_iter()
+            .map(|s| Solid {
+                triangles: (s.triangles.start + dt)..(s.triangles.end + dt),
+                instance_of: s.instance_of.map(|i| i + ds),
+                ..s
+            }));
+        a.faces.extend(b.faces.into_iter()
+            .map(|f| Face {
+                triangles: (f.triangles.start + dt)..(f.triangles.end + dt),
+                ..f
+            }));
         a.verts.extend(b.verts);
         a.triangles.extend(b.triangles.into_iter()
             .map(|t| Triangle { verts: t.verts.add_scalar(dv) }));
+        let dn = a.assembly.len();
+        a.assembly.extend(b.assembly.into_iter()
+            .map(|n| AssemblyNode {
+                parent: n.parent.map(|p| p + dn),
+                solids: n.solids.into_iter().map(|s| s + ds).collect(),
+                ..n
+            }));
+        a.annotations.extend(b.annotations);
+        a.length_unit = a.length_unit.or(b.length_unit);
         a
     }
 
+    /// Moves the whole mesh by the given transform, e.g. to place a
+    /// component within an assembly.  If the transform changes handedness
+    /// (i.e. it's a mirror), triangle winding is reversed so that triangles
+    /// still face outwards.
+    pub fn transform(&mut self, mat: &DMat4) {
+        for v in &mut self.verts {
+            v.pos = (mat * v.pos.push(1.0)).xyz();
+            v.norm = (mat * v.norm.push(0.0)).xyz();
+        }
+        if nalgebra_glm::mat4_to_mat3(mat).determinant() < 0.0 {
+            for t in &mut self.triangles {
+                t.verts.swap_rows(1, 2);
+            }
+        }
+        for f in &mut self.faces {
+            f.transform = mat * f.transform;
+        }
+        for n in self.assembly.iter_mut().filter(|n| n.parent.is_none()) {
+            n.transform = mat * n.transform;
+        }
+        for p in self.annotations.iter_mut().flatten() {
+            *p = (mat * p.push(1.0)).xyz();
+        }
+        // Instance transforms are relative to the original solid, which has
+        // itself been moved
+        let mat_i = mat.try_inverse().unwrap_or_else(DMat4::identity);
+        for s in &mut self.solids {
+            if s.instance_of.is_some() {
+                s.transform = mat * s.transform * mat_i;
+            }
+        }
+    }
+
+    /// Scales the whole mesh uniformly about the origin, e.g. to convert it
+    /// between units
+    pub fn scale(&mut self, factor: f64) {
+        self.transform(&DMat4::new_scaling(factor));
+        for v in &mut self.verts {
+            v.norm /= factor;
+        }
+        for f in &mut self.faces {
+            f.dimensions = f.dimensions.map(|d| d.scaled(factor));
+        }
+    }
+
+    /// Smooths normals across the boundaries between faces of the same
+    /// solid, wherever the faces meet at an angle (in radians) below
+    /// `crease_angle`.  Faces don't share vertices, so each vertex on a
+    /// boundary gets the average normal of the coincident vertices from its
+    /// neighbors; those beyond the crease angle are left out, which keeps
+    /// hard edges sharp.  Normals within each face are already smooth, since
+    /// they come from the underlying surface.
+    pub fn smooth_normals(&mut self, crease_angle: f64) {
+        let cos = crease_angle.cos();
+        let ranges: Vec<_> = if self.solids.is_empty() {
+            std::iter::once(0..self.triangles.len()).collect()
+        } else {
+            self.solids.iter().map(|s| s.triangles.clone()).collect()
+        };
+        let mut norms: Vec<DVec3> = self.verts.iter().map(|v| v.norm).collect();
+        for r in ranges {
+            let mut used: Vec<u32> = self.triangles[r].iter()
+                .flat_map(|t| t.verts.iter().copied())
+                .collect();
+            used.sort_unstable();
+            used.dedup();
+            let mut groups: HashMap<[u64; 3], Vec<u32>> = HashMap::new();
+            for v in used {
+                let p = self.verts[v as usize].pos;
+                groups.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+                    .or_default()
+                    .push(v);
+            }
+            for group in groups.values().filter(|g| g.len() > 1) {
+                for &v in group {
+                    let n = self.verts[v as usize].norm;
+                    let sum: DVec3 = group.iter()
+                        .map(|&w| self.verts[w as usize].norm)
+                        .filter(|m| m.dot(&n) >= cos * m.norm() * n.norm())
+                        .sum();
+                    if sum.norm() > 0.0 {
+                        norms[v as usize] = sum.normalize();
+                    }
+                }
+            }
+        }
+        for (v, n) in self.verts.iter_mut().zip(norms) {
+            v.norm = n;
+        }
+    }
+
+    /// Returns the face which generated the given triangle, if known
+    pub fn face_of(&self, triangle: usize) -> Option<&Face> {
+        self.face_index(triangle).map(|i| &self.faces[i])
+    }
+
+    /// Returns the index into `self.faces` of the face which generated the
+    /// given triangle, if known
+    pub fn face_index(&self, triangle: usize) -> Option<usize> {
+        find_range(&self.faces, |f| &f.triangles, triangle)
+    }
+
+    /// Builds a new mesh containing only the given faces (as indices into
+    /// `self.faces`), with unused vertices removed.  Solid information is
+    /// not preserved.
+    pub fn extract_faces(&self, faces: &[usize]) -> Mesh {
+        let mut out = Mesh { length_unit: self.length_unit, ..Default::default() };
+        let mut remap = vec![u32::MAX; self.verts.len()];
+        for &i in faces {
+            let face = &self.faces[i];
+            let start = out.triangles.len();
+            for t in &self.triangles[face.triangles.clone()] {
+                let mut verts = t.verts;
+                for v in verts.iter_mut() {
+                    let r = &mut remap[*v as usize];
+                    if *r == u32::MAX {
+                        *r = out.verts.len().try_into()
+                            .expect("Too many vertices");
+                        out.verts.push(self.verts[*v as usize]);
+                    }
+                    *v = *r;
+                }
+                out.triangles.push(Triangle { verts });
+            }
+            out.faces.push(Face {
+                triangles: start..out.triangles.len(),
+                ..face.clone()
+            });
+        }
+        out
+    }
+
+    /// Returns the total area of a range of triangles
+    pub fn area(&self, triangles: std::ops::Range<usize>) -> f64 {
+        self.triangles[triangles].iter()
+            .map(|t| {
+                let a = self.verts[t.verts.x as usize].pos;
+                let b = self.verts[t.verts.y as usize].pos;
+                let c = self.verts[t.verts.z as usize].pos;
+                (b - a).cross(&(c - a)).norm() / 2.0
+            })
+            .sum()
+    }
+
+    /// Returns the volume enclosed by a range of triangles, which should
+    /// form a closed shell.  This is the sum of signed tetrahedra from the
+    /// origin to each triangle, so it's negative for inside-out shells.
+    pub fn volume(&self, triangles: std::ops::Range<usize>) -> f64 {
+        self.triangles[triangles].iter()
+            .map(|t| {
+                let a = self.verts[t.verts.x as usize].pos;
+                let b = self.verts[t.verts.y as usize].pos;
+                let c = self.verts[t.verts.z as usize].pos;
+                a.dot(&b.cross(&c)) / 6.0
+            })
+            .sum()
+    }
+
+    /// Returns the positions of the vertices used by a range of triangles,
+    /// without duplicates
+    pub fn points(&self, triangles: std::ops::Range<usize>) -> Vec<DVec3> {
+        let mut verts: Vec<u32> = self.triangles[triangles].iter()
+            .flat_map(|t| t.verts.iter().copied())
+            .collect();
+        verts.sort_unstable();
+        verts.dedup();
+        verts.iter().map(|&i| self.verts[i as usize].pos).collect()
+    }
+
+    /// Returns the solid which contains the given triangle, if known
+    pub fn solid_of(&self, triangle: usize) -> Option<&Solid> {
+        find_range(&self.solids, |s| &s.triangles, triangle)
+            .map(|i| &self.solids[i])
+    }
+
     /// Writes the triangulation to a STL, for debugging
     pub fn save_stl(&self, filename: &str) -> std::io::Result<()> {
         let mut out: Vec<u8> = Vec::new();
@@ -36,10 +417,14 @@ impl Mesh {
             out.push('x' as u8);
         }
         let u: u32 = self.triangles.len().try_into()
-            .expect("Too many triangles");
+            .map_err(|_| invalid("Too many triangles for STL"))?;
         out.extend(&u.to_le_bytes());
         for t in self.triangles.iter() {
-            out.extend(std::iter::repeat(0).take(12)); // normal
+            let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+                .map(|i| self.verts[i as usize].pos);
+            for c in facet_normal(a, b, c).iter() {
+                out.extend(&c.to_le_bytes());
+            }
             for v in t.verts.iter() {
                 let v = self.verts[*v as usize];
                 out.extend(&(v.pos.x as f32).to_le_bytes());
@@ -50,4 +435,330 @@ impl Mesh {
         }
         std::fs::write(filename, out)
     }
+
+    /// Returns the STEP entity id of the face which generated each triangle
+    pub fn triangle_face_ids(&self) -> Vec<Option<usize>> {
+        let mut out = vec![None; self.triangles.len()];
+        for f in &self.faces {
+            out[f.triangles.clone()].iter_mut().for_each(|t| *t = Some(f.id));
+        }
+        out
+    }
+
+    /// Writes the triangulation to a Wavefront OBJ file, with normals and
+    /// texture coordinates (the normalized surface parameters).  Triangles
+    /// are grouped by face, with groups named `face_<id>` after the face's
+    /// STEP entity id.
+    pub fn save_obj(&self, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        for v in self.verts.iter() {
+            writeln!(out, "v {} {} {}", v.pos.x, v.pos.y, v.pos.z)?;
+        }
+        for v in self.verts.iter() {
+            writeln!(out, "vt {} {}", v.uv.x, v.uv.y)?;
+        }
+        for v in self.verts.iter() {
+            writeln!(out, "vn {} {} {}", v.norm.x, v.norm.y, v.norm.z)?;
+        }
+        let mut group = None;
+        for (t, id) in self.triangles.iter().zip(self.triangle_face_ids()) {
+            if id != group {
+                match id {
+                    Some(id) => writeln!(out, "g face_{}", id)?,
+                    None => writeln!(out, "g default")?,
+                }
+                group = id;
+            }
+            // OBJ indices are 1-based
+            let (a, b, c) = (t.verts.x + 1, t.verts.y + 1, t.verts.z + 1);
+            writeln!(out, "f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c)?;
+        }
+        out.flush()
+    }
+
+    /// Writes the triangulation to a binary PLY file, including per-vertex
+    /// colors (which STL can't represent) and each triangle's face entity id
+    /// (or -1 if unknown)
+    pub fn save_ply(&self, filename: &str) -> std::io::Result<()> {
+        let mut out: Vec<u8> = Vec::new();
+        ply::write_header(&mut out, &[("vertex", self.verts.len(), ply::MESH_VERTEX),
+                                      ("face", self.triangles.len(), ply::MESH_FACE)])?;
+        for v in self.verts.iter() {
+            out.extend(&(v.pos.x as f32).to_le_bytes());
+            out.extend(&(v.pos.y as f32).to_le_bytes());
+            out.extend(&(v.pos.z as f32).to_le_bytes());
+            for c in v.color.iter() {
+                out.push((c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+        for (t, id) in self.triangles.iter().zip(self.triangle_face_ids()) {
+            out.push(3);
+            for v in t.verts.iter() {
+                out.extend(&v.to_le_bytes());
+            }
+            out.extend(&ply::face_id(id)?.to_le_bytes());
+        }
+        std::fs::write(filename, out)
+    }
+
+    /// Writes the triangulation to a binary glTF (GLB) file.  Each face is
+    /// stored as a separate primitive, with its STEP entity id recorded as
+    /// `extras.step_face_id`.  Surface parameters are stored as
+    /// `TEXCOORD_0`, with `v` flipped to match glTF's top-left origin.
+    pub fn save_glb(&self, filename: &str) -> std::io::Result<()> {
+        let mut bin: Vec<u8> = Vec::new();
+        let mut lo = DVec3::repeat(f64::INFINITY);
+        let mut hi = DVec3::repeat(-f64::INFINITY);
+        for v in self.verts.iter() {
+            lo = lo.inf(&v.pos);
+            hi = hi.sup(&v.pos);
+        }
+        if self.verts.is_empty() {
+            lo = DVec3::zeros();
+            hi = DVec3::zeros();
+        }
+        for attr in 0..3 {
+            for v in self.verts.iter() {
+                let f = match attr {
+                    0 => v.pos,
+                    1 => v.norm,
+                    _ => v.color,
+                };
+                for c in f.iter() {
+                    bin.extend(&(*c as f32).to_le_bytes());
+                }
+            }
+        }
+        for v in self.verts.iter() {
+            bin.extend(&(v.uv.x as f32).to_le_bytes());
+            bin.extend(&(1.0 - v.uv.y as f32).to_le_bytes());
+        }
+        let vert_bytes = self.verts.len() * 12;
+        let index_offset = bin.len();
+        for t in self.triangles.iter() {
+            for v in t.verts.iter() {
+                bin.extend(&v.to_le_bytes());
+            }
+        }
+
+        // Split triangles into runs with the same face id, each of which
+        // becomes a primitive with its own index accessor
+        let ids = self.triangle_face_ids();
+        let mut runs: Vec<(usize, usize, Option<usize>)> = Vec::new();
+        for (i, id) in ids.iter().enumerate() {
+            match runs.last_mut() {
+                Some(r) if r.1 == i && r.2 == *id => r.1 += 1,
+                _ => runs.push((i, i + 1, *id)),
+            }
+        }
+
+        let vec3 = |v: DVec3| format!("[{},{},{}]", v.x as f32, v.y as f32, v.z as f32);
+        let mut accessors = vec![
+            format!(r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":{},"max":{}}}"#,
+                    self.verts.len(), vec3(lo), vec3(hi)),
+            format!(r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}}"#,
+                    self.verts.len()),
+            format!(r#"{{"bufferView":2,"componentType":5126,"count":{},"type":"VEC3"}}"#,
+                    self.verts.len()),
+            format!(r#"{{"bufferView":3,"componentType":5126,"count":{},"type":"VEC2"}}"#,
+                    self.verts.len()),
+        ];
+        let mut primitives = Vec::new();
+        for (start, end, id) in runs {
+            primitives.push(format!(
+                r#"{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2,"TEXCOORD_0":3}},"indices":{}{}}}"#,
+                accessors.len(),
+                id.map(|id| format!(r#","extras":{{"step_face_id":{}}}"#, id))
+                    .unwrap_or_default()));
+            accessors.push(format!(
+                r#"{{"bufferView":4,"byteOffset":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+                start * 12, (end - start) * 3));
+        }
+        let view = |offset: usize, length: usize, target: usize| format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, length, target);
+        let views = [
+            view(0, vert_bytes, 34962),
+            view(vert_bytes, vert_bytes, 34962),
+            view(vert_bytes * 2, vert_bytes, 34962),
+            view(vert_bytes * 3, self.verts.len() * 8, 34962),
+            view(index_offset, bin.len() - index_offset, 34963),
+        ];
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+        let mut json = format!(
+            r#"{{"asset":{{"version":"2.0","generator":"foxtrot"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"meshes":[{{"primitives":[{}]}}],"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#,
+            primitives.join(","), accessors.join(","), views.join(","), bin.len());
+        while !json.len().is_multiple_of(4) {
+            json.push(' ');
+        }
+
+        let mut out: Vec<u8> = Vec::new();
+        let total: u32 = (12 + 8 + json.len() + 8 + bin.len()).try_into()
+            .map_err(|_| invalid("Mesh is too large for GLB"))?;
+        out.extend(b"glTF");
+        out.extend(&2u32.to_le_bytes());
+        out.extend(&total.to_le_bytes());
+        out.extend(&(json.len() as u32).to_le_bytes());
+        out.extend(b"JSON");
+        out.extend(json.as_bytes());
+        out.extend(&(bin.len() as u32).to_le_bytes());
+        out.extend(b"BIN\0");
+        out.extend(bin);
+        std::fs::write(filename, out)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a unit square made of two faces, with entity ids 12 and 34,
+    /// and an untracked triangle after them
+    fn two_faces() -> Mesh {
+        let mut mesh = Mesh::default();
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (2.0, 0.0)] {
+            mesh.verts.push(Vertex {
+                pos: DVec3::new(x, y, 0.0), norm: DVec3::z(),
+                color: DVec3::new(1.0, 0.5, 0.0), uv: DVec2::new(x / 2.0, y),
+            });
+        }
+        for t in [[0, 1, 2], [0, 2, 3], [1, 4, 2]] {
+            mesh.triangles.push(Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) });
+        }
+        for (id, triangles) in [(12, 0..1), (34, 1..2)] {
+            mesh.faces.push(Face {
+                id, triangles, surface: SurfaceType::Plane, dimensions: None,
+                geometry: None, transform: DMat4::identity(), failed: false, grid: None,
+            });
+        }
+        mesh
+    }
+
+    #[test]
+    fn scale_dimensions() {
+        let mut mesh = two_faces();
+        mesh.faces[0].dimensions = Some(Dimensions::Cone { radius: 2.0, half_angle: 0.5 });
+        mesh.faces[1].dimensions = Some(Dimensions::Torus {
+            major_radius: 3.0, minor_radius: 1.0 });
+        mesh.scale(10.0);
+        assert_eq!(mesh.faces[0].dimensions,
+                   Some(Dimensions::Cone { radius: 20.0, half_angle: 0.5 }));
+        assert_eq!(mesh.faces[1].dimensions,
+                   Some(Dimensions::Torus { major_radius: 30.0, minor_radius: 10.0 }));
+        assert_eq!(mesh.verts[2].pos, DVec3::new(10.0, 10.0, 0.0));
+    }
+
+    fn temp_file(ext: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("foxtrot-mesh-{}.{}", std::process::id(), ext))
+    }
+
+    #[test]
+    fn ply_face_ids() {
+        let path = temp_file("ply");
+        two_faces().save_ply(path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut ids = Vec::new();
+        ply::read(&data, |e, values| {
+            if e.name == "face" {
+                assert_eq!(e.props[1].name(), "face_id");
+                ids.push(values[1][0]);
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(ids, vec![12.0, 34.0, -1.0]);
+    }
+
+    #[test]
+    fn obj_groups() {
+        let path = temp_file("obj");
+        two_faces().save_obj(path.to_str().unwrap()).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut group = None;
+        let mut groups = Vec::new();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "g" => group = Some(words[1]),
+                "f" => groups.push(group.unwrap()),
+                _ => (),
+            }
+        }
+        assert_eq!(groups, vec!["face_12", "face_34", "default"]);
+    }
+
+    #[test]
+    fn glb_extras() {
+        let path = temp_file("glb");
+        two_faces().save_glb(path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&data[..4], b"glTF");
+        let json_len = u32::from_le_bytes([data[12], data[13], data[14], data[15]]) as usize;
+        assert_eq!(&data[16..20], b"JSON");
+        let json: serde_json::Value = serde_json::from_slice(&data[20..20 + json_len]).unwrap();
+        let ids: Vec<Option<u64>> = json["meshes"][0]["primitives"].as_array().unwrap()
+            .iter()
+            .map(|p| p["extras"]["step_face_id"].as_u64())
+            .collect();
+        assert_eq!(ids, vec![Some(12), Some(34), None]);
+        let counts: Vec<u64> = json["meshes"][0]["primitives"].as_array().unwrap()
+            .iter()
+            .map(|p| json["accessors"][p["indices"].as_u64().unwrap() as usize]["count"]
+                 .as_u64().unwrap())
+            .collect();
+        assert_eq!(counts, vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn uv_export() {
+        let mesh = two_faces();
+        let expected: Vec<(f64, f64)> = mesh.verts.iter().map(|v| (v.uv.x, v.uv.y)).collect();
+
+        let path = temp_file("uv.obj");
+        mesh.save_obj(path.to_str().unwrap()).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let uvs: Vec<(f64, f64)> = text.lines()
+            .filter_map(|line| line.strip_prefix("vt "))
+            .map(|line| {
+                let mut words = line.split_whitespace().map(|w| w.parse().unwrap());
+                (words.next().unwrap(), words.next().unwrap())
+            })
+            .collect();
+        assert_eq!(uvs, expected);
+        // Each corner uses the texture coordinate with the vertex's index
+        assert!(text.lines().any(|line| line == "f 1/1/1 2/2/2 3/3/3"));
+
+        // glTF stores them as TEXCOORD_0, with v flipped
+        let path = temp_file("uv.glb");
+        mesh.save_glb(path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let json_len = u32::from_le_bytes([data[12], data[13], data[14], data[15]]) as usize;
+        let json: serde_json::Value = serde_json::from_slice(&data[20..20 + json_len]).unwrap();
+        let accessor = json["meshes"][0]["primitives"][0]["attributes"]["TEXCOORD_0"]
+            .as_u64().unwrap() as usize;
+        let view = &json["bufferViews"][json["accessors"][accessor]["bufferView"]
+            .as_u64().unwrap() as usize];
+        let offset = 20 + json_len + 8 + view["byteOffset"].as_u64().unwrap() as usize;
+        let f = |i: usize| {
+            let b = &data[offset + i..offset + i + 4];
+            f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+        };
+        let uvs: Vec<(f64, f64)> = (0..mesh.verts.len())
+            .map(|i| (f(i * 8), 1.0 - f(i * 8 + 4)))
+            .collect();
+        assert_eq!(uvs, expected);
+    }
 }
@@ -0,0 +1,173 @@
+use nalgebra_glm as glm;
+use glm::{DMat3, DVec2, DVec3};
+
+use crate::section::plane_basis;
+
+/// Oriented bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Obb {
+    pub center: DVec3,
+    /// Orthonormal box axes, sorted from longest to shortest extent
+    pub axes: [DVec3; 3],
+    /// Half of the box's size along each axis
+    pub half_extents: DVec3,
+}
+
+impl Obb {
+    /// Builds a tight oriented bounding box around a set of points.
+    ///
+    /// Principal component analysis gives three candidate axes.  For each
+    /// one, the points are projected onto the perpendicular plane and the
+    /// minimum-area rectangle around their 2D convex hull is found with
+    /// rotating calipers; the smallest resulting box wins.  This is exact
+    /// whenever one of the optimal box's axes matches a principal axis,
+    /// which is the common case for machined parts.
+    pub fn from_points(pts: &[DVec3]) -> Option<Obb> {
+        if pts.is_empty() {
+            return None;
+        }
+        let mean = pts.iter().sum::<DVec3>() / pts.len() as f64;
+        let mut cov = DMat3::zeros();
+        for p in pts {
+            let d = p - mean;
+            cov += d * d.transpose();
+        }
+        let eig = cov.symmetric_eigen();
+        let pca: Vec<DVec3> = (0..3)
+            .map(|i| eig.eigenvectors.column(i).into_owned())
+            .collect();
+
+        let mut best = Self::from_axes(pts, [pca[0], pca[1], pca[2]]);
+        for axis in &pca {
+            let (u, v) = plane_basis(*axis);
+            let flat: Vec<DVec2> = pts.iter()
+                .map(|p| DVec2::new(p.dot(&u), p.dot(&v)))
+                .collect();
+            if let Some(dir) = min_area_direction(&flat) {
+                let a = u * dir.x + v * dir.y;
+                let b = axis.cross(&a);
+                let obb = Self::from_axes(pts, [a, b, *axis]);
+                if obb.volume() < best.volume() {
+                    best = obb;
+                }
+            }
+        }
+        Some(best)
+    }
+
+    /// Builds the box with the given (orthonormal) axes which encloses
+    /// every point, then sorts the axes by extent.
+    fn from_axes(pts: &[DVec3], axes: [DVec3; 3]) -> Obb {
+        let mut lo = DVec3::repeat(f64::INFINITY);
+        let mut hi = DVec3::repeat(-f64::INFINITY);
+        for p in pts {
+            let q = DVec3::new(p.dot(&axes[0]), p.dot(&axes[1]), p.dot(&axes[2]));
+            lo = lo.inf(&q);
+            hi = hi.sup(&q);
+        }
+        let mid = (lo + hi) / 2.0;
+        let half = (hi - lo) / 2.0;
+
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| half[b].partial_cmp(&half[a]).unwrap());
+        Obb {
+            center: axes[0] * mid.x + axes[1] * mid.y + axes[2] * mid.z,
+            axes: order.map(|i| axes[i]),
+            half_extents: DVec3::new(half[order[0]], half[order[1]], half[order[2]]),
+        }
+    }
+
+    /// Returns the full size of the box along each of its axes
+    pub fn size(&self) -> DVec3 {
+        self.half_extents * 2.0
+    }
+
+    pub fn volume(&self) -> f64 {
+        let s = self.size();
+        s.x * s.y * s.z
+    }
+}
+
+/// Returns the unit direction of one side of the minimum-area rectangle
+/// which encloses the points, or `None` if they are all collinear (or the
+/// hull can't be found).
+fn min_area_direction(pts: &[DVec2]) -> Option<DVec2> {
+    let flat: Vec<(f64, f64)> = pts.iter().map(|p| (p.x, p.y)).collect();
+    // The triangulator can panic on degenerate input, in which case the
+    // caller falls back to the principal axes
+    let hull: Vec<DVec2> = std::panic::catch_unwind(|| cdt::convex_hull(&flat))
+        .ok()?
+        .ok()?
+        .into_iter()
+        .map(|i| pts[i])
+        .collect();
+    let mut best: Option<(f64, DVec2)> = None;
+    // The optimal rectangle has one side collinear with a hull edge, so
+    // it's enough to check every edge direction.
+    for (i, a) in hull.iter().enumerate() {
+        let e = hull[(i + 1) % hull.len()] - a;
+        if e.norm_squared() == 0.0 {
+            continue;
+        }
+        let e = e.normalize();
+        let n = DVec2::new(-e.y, e.x);
+        let (mut e_lo, mut e_hi) = (f64::INFINITY, -f64::INFINITY);
+        let (mut n_lo, mut n_hi) = (f64::INFINITY, -f64::INFINITY);
+        for p in &hull {
+            let (pe, pn) = (p.dot(&e), p.dot(&n));
+            e_lo = e_lo.min(pe);
+            e_hi = e_hi.max(pe);
+            n_lo = n_lo.min(pn);
+            n_hi = n_hi.max(pn);
+        }
+        let area = (e_hi - e_lo) * (n_hi - n_lo);
+        if best.map(|b| area < b.0).unwrap_or(true) {
+            best = Some((area, e));
+        }
+    }
+    best.map(|b| b.1)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_box() {
+        let rot = glm::rotation(0.4, &DVec3::new(1.0, 2.0, 0.5).normalize())
+            * glm::rotation(1.1, &DVec3::z());
+        let mut pts = Vec::new();
+        for i in 0..8 {
+            let p = DVec3::new(
+                if i & 1 == 0 { 0.0 } else { 3.0 },
+                if i & 2 == 0 { 0.0 } else { 2.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 });
+            pts.push(glm::vec4_to_vec3(&(rot * glm::vec3_to_vec4(&p)))
+                + DVec3::new(5.0, -1.0, 2.0));
+        }
+        let obb = Obb::from_points(&pts).unwrap();
+        let size = obb.size();
+        assert!((size - DVec3::new(3.0, 2.0, 1.0)).norm() < 1e-9, "{:?}", size);
+        for p in &pts {
+            let d = p - obb.center;
+            for i in 0..3 {
+                assert!(d.dot(&obb.axes[i]).abs() <= obb.half_extents[i] + 1e-9);
+            }
+        }
+    }
+    #[test]
+    fn flat_rectangle() {
+        // Coplanar points, which are collinear when projected along some
+        // of the principal axes
+        let rot = glm::rotation(0.3, &DVec3::z());
+        let pts: Vec<DVec3> = [(0.0, 0.0), (4.0, 0.0), (4.0, 1.0), (0.0, 1.0), (1.0, 0.5)]
+            .iter()
+            .map(|&(x, y)| glm::vec4_to_vec3(&(rot * glm::vec4(x, y, 0.0, 1.0))))
+            .collect();
+        let obb = Obb::from_points(&pts).unwrap();
+        let size = obb.size();
+        assert!((size - DVec3::new(4.0, 1.0, 0.0)).norm() < 1e-9, "{:?}", size);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::Bvh, mesh::Mesh, section::plane_basis, svg::{self, Svg}};
+
+/// Edges are split into pieces no longer than this (as a fraction of the
+/// model's size), each of which is checked for visibility separately
+const PIECE_LENGTH: f64 = 1.0 / 500.0;
+
+/// Visibility rays start this far from each edge towards the viewer (as a
+/// fraction of the model's size), so that they don't hit the edge's own
+/// triangles
+const RAY_OFFSET: f64 = 1e-6;
+
+/// A 2D line drawing of a model, as seen from a particular direction
+#[derive(Clone, Debug, Default)]
+pub struct Drawing {
+    /// Visible line segments, in drawing coordinates (X right and Y up)
+    pub lines: Vec<[(f64, f64); 2]>,
+}
+
+/// Builds a hidden-line drawing of the mesh, looking at it from far away
+/// along `toward_viewer` (which points from the model to the viewer).
+///
+/// The drawing includes the boundaries between faces and the silhouette
+/// edges where the surface turns away from the viewer.  Each edge is split
+/// into short pieces, which are kept if nothing lies between them and the
+/// viewer.
+pub fn outline(mesh: &Mesh, toward_viewer: DVec3) -> Drawing {
+    let view = toward_viewer.normalize();
+    let mut lo = DVec3::repeat(f64::INFINITY);
+    let mut hi = DVec3::repeat(-f64::INFINITY);
+    for v in &mesh.verts {
+        lo = lo.inf(&v.pos);
+        hi = hi.sup(&v.pos);
+    }
+    if mesh.verts.is_empty() {
+        return Drawing::default();
+    }
+    let size = (hi - lo).norm().max(f64::MIN_POSITIVE);
+
+    let edges = drawn_edges(mesh, view);
+    let bvh = Bvh::new(mesh);
+    let visible = |p: DVec3| bvh.raycast(p + view * size * RAY_OFFSET, view).is_none();
+
+    let pieces = {
+        #[cfg(feature = "rayon")]
+        { edges.par_iter() }
+        #[cfg(not(feature = "rayon"))]
+        { edges.iter() }
+    };
+    let segments: Vec<Vec<(DVec3, DVec3)>> = pieces
+        .map(|&(a, b)| {
+            // Consecutive visible pieces are merged back into one segment
+            let n = ((b - a).norm() / (size * PIECE_LENGTH)).ceil().max(1.0) as usize;
+            let mut out = Vec::new();
+            let mut start = None;
+            for i in 0..n {
+                let t0 = i as f64 / n as f64;
+                let t1 = (i + 1) as f64 / n as f64;
+                let mid = a + (b - a) * ((t0 + t1) / 2.0);
+                if visible(mid) {
+                    start.get_or_insert(a + (b - a) * t0);
+                } else if let Some(s) = start.take() {
+                    out.push((s, a + (b - a) * t0));
+                }
+            }
+            if let Some(s) = start {
+                out.push((s, b));
+            }
+            out
+        })
+        .collect();
+
+    let (u, v) = plane_basis(view);
+    let flat = |p: DVec3| (p.dot(&u), p.dot(&v));
+    Drawing {
+        lines: segments.into_iter().flatten().map(|(a, b)| [flat(a), flat(b)]).collect(),
+    }
+}
+
+/// Finds the mesh edges which belong in a drawing: boundaries between
+/// faces (or of the mesh itself), and silhouettes within a face, where one
+/// neighboring triangle faces the viewer and the other faces away.
+/// Faces don't share vertices, so edges are matched up by position.
+fn drawn_edges(mesh: &Mesh, view: DVec3) -> Vec<(DVec3, DVec3)> {
+    let key = |p: DVec3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+    let mut edges: HashMap<([u64; 3], [u64; 3]), Vec<usize>> = HashMap::new();
+    for (i, t) in mesh.triangles.iter().enumerate() {
+        for j in 0..3 {
+            let a = key(mesh.verts[t.verts[j] as usize].pos);
+            let b = key(mesh.verts[t.verts[(j + 1) % 3] as usize].pos);
+            if a != b {
+                edges.entry(if a < b { (a, b) } else { (b, a) }).or_default().push(i);
+            }
+        }
+    }
+
+    let normal = |i: usize| {
+        let t = mesh.triangles[i];
+        let p = [0, 1, 2].map(|j| mesh.verts[t.verts[j] as usize].pos);
+        (p[1] - p[0]).cross(&(p[2] - p[0]))
+    };
+    let from_key = |k: [u64; 3]| DVec3::new(
+        f64::from_bits(k[0]), f64::from_bits(k[1]), f64::from_bits(k[2]));
+    let mut out: Vec<(DVec3, DVec3)> = edges.into_iter()
+        .filter(|(_, tris)| match tris[..] {
+            [a, b] => mesh.face_index(a) != mesh.face_index(b) ||
+                (normal(a).dot(&view) > 0.0) != (normal(b).dot(&view) > 0.0),
+            _ => true,
+        })
+        .map(|((a, b), _)| (from_key(a), from_key(b)))
+        .collect();
+    // Sort for deterministic output, since the map is unordered
+    out.sort_by(|a, b| (a.0.as_slice(), a.1.as_slice())
+        .partial_cmp(&(b.0.as_slice(), b.1.as_slice()))
+        .unwrap_or(std::cmp::Ordering::Equal));
+    out
+}
+
+impl Drawing {
+    /// Returns the lower-left and upper-right corners of the drawing, or
+    /// the origin if it's empty
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        let mut lo = (f64::INFINITY, f64::INFINITY);
+        let mut hi = (-f64::INFINITY, -f64::INFINITY);
+        for p in self.lines.iter().flatten() {
+            lo = (lo.0.min(p.0), lo.1.min(p.1));
+            hi = (hi.0.max(p.0), hi.1.max(p.1));
+        }
+        if lo.0 > hi.0 {
+            ((0.0, 0.0), (0.0, 0.0))
+        } else {
+            (lo, hi)
+        }
+    }
+
+    /// Returns the drawing's bounds grown by a small margin, which are
+    /// never empty
+    fn page(&self) -> ((f64, f64), (f64, f64)) {
+        let (lo, hi) = self.bounds();
+        svg::page(lo, hi)
+    }
+
+    /// Saves the drawing as an SVG file.  If the length of a model unit is
+    /// known (in metres), the drawing is sized to print at 1:1.
+    pub fn save_svg(&self, length_unit: Option<f64>, filename: &str)
+        -> std::io::Result<()>
+    {
+        let (lo, hi) = self.page();
+        let mut out = Svg::create(filename, lo, hi, length_unit)?;
+        let attrs = format!("fill=\"none\" stroke=\"black\" stroke-width=\"{}\" \
+                             stroke-linecap=\"round\"", out.stroke_width());
+        out.path(&attrs, &self.lines, false)?;
+        out.finish()
+    }
+
+    /// Saves the drawing as a single-page PDF.  If the length of a model
+    /// unit is known (in metres), the page is sized to print at 1:1;
+    /// otherwise, the drawing is scaled to fit an A4 page.
+    pub fn save_pdf(&self, length_unit: Option<f64>, filename: &str)
+        -> std::io::Result<()>
+    {
+        use std::fmt::Write;
+        let (lo, hi) = self.page();
+        let (w, h) = (hi.0 - lo.0, hi.1 - lo.1);
+        // PDF units are points, at 72 per inch
+        let scale = match length_unit {
+            Some(unit) => unit / 0.0254 * 72.0,
+            None => (595.0 / w).min(842.0 / h),
+        };
+        let mut stream = String::new();
+        writeln!(stream, "{} w 1 J 1 j", (w.max(h) * scale * 1e-3).max(0.25)).unwrap();
+        for [a, b] in &self.lines {
+            writeln!(stream, "{:.3} {:.3} m {:.3} {:.3} l",
+                     (a.0 - lo.0) * scale, (a.1 - lo.1) * scale,
+                     (b.0 - lo.0) * scale, (b.1 - lo.1) * scale).unwrap();
+        }
+        stream += "S\n";
+
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] \
+                     /Contents 4 0 R >>", w * scale, h * scale),
+            format!("<< /Length {} >>\nstream\n{}endstream", stream.len(), stream),
+        ];
+        let mut out = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (i, o) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            writeln!(out, "{} 0 obj\n{}\nendobj", i + 1, o).unwrap();
+        }
+        let xref = out.len();
+        writeln!(out, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1).unwrap();
+        for o in offsets {
+            writeln!(out, "{:010} 00000 n ", o).unwrap();
+        }
+        writeln!(out, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+                 objects.len() + 1, xref).unwrap();
+        std::fs::write(filename, out)
+    }
+}
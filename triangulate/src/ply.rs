@@ -0,0 +1,243 @@
+//! Reading and writing PLY files, shared by the mesh, point cloud, and scan
+//! code.  Files are written as binary little-endian PLY, and read in any of
+//! the three standard formats.
+use std::convert::TryInto;
+use std::io::Write;
+
+fn invalid<S: Into<String>>(msg: S) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Per-vertex properties written for meshes
+pub(crate) const MESH_VERTEX: &[&str] = &[
+    "float x", "float y", "float z", "uchar red", "uchar green", "uchar blue",
+];
+/// Per-face properties written for meshes
+pub(crate) const MESH_FACE: &[&str] = &[
+    "list uchar uint vertex_indices", "int face_id",
+];
+
+/// Writes the header of a binary PLY file, given each element's name, item
+/// count, and property declarations (e.g. `"float x"`)
+pub(crate) fn write_header<W: Write>(out: &mut W, elements: &[(&str, usize, &[&str])])
+    -> std::io::Result<()>
+{
+    writeln!(out, "ply")?;
+    writeln!(out, "format binary_little_endian 1.0")?;
+    for (name, count, props) in elements {
+        writeln!(out, "element {} {}", name, count)?;
+        for p in props.iter() {
+            writeln!(out, "property {}", p)?;
+        }
+    }
+    writeln!(out, "end_header")
+}
+
+/// Converts a STEP entity id into the value of a `face_id` property, which
+/// is -1 if the id is unknown
+pub(crate) fn face_id(id: Option<usize>) -> std::io::Result<i32> {
+    match id {
+        Some(i) => i.try_into()
+            .map_err(|_| invalid(format!("Entity id {} is too large for PLY", i))),
+        None => Ok(-1),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Scalar { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn decode(self, b: &[u8], big_endian: bool) -> f64 {
+        let mut buf = [0; 8];
+        buf[..b.len()].copy_from_slice(b);
+        if big_endian {
+            buf[..b.len()].reverse();
+        }
+        match self {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        }
+    }
+}
+
+pub(crate) enum Property {
+    Scalar(String, Scalar),
+    /// A list, with the types of its length and of its items
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) count: usize,
+    pub(crate) props: Vec<Property>,
+}
+
+/// Reads values from the body of a PLY file, in either ASCII or binary form
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn next(&mut self, ty: Scalar) -> std::io::Result<f64> {
+        match self {
+            Body::Ascii(words) => words.next()
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| invalid("Truncated or invalid PLY data")),
+            Body::Binary { data, big_endian } => {
+                if data.len() < ty.size() {
+                    return Err(invalid("Truncated PLY data"));
+                }
+                let (b, rest) = data.split_at(ty.size());
+                *data = rest;
+                Ok(ty.decode(b, *big_endian))
+            },
+        }
+    }
+}
+
+/// Reads a PLY file (ASCII or binary), calling `f` for every item of every
+/// element in file order.  `f` is given the element and one slice of values
+/// per property, which holds a single value for a scalar property and every
+/// item for a list.
+pub(crate) fn read<F>(data: &[u8], mut f: F) -> std::io::Result<()>
+    where F: FnMut(&Element, &[Vec<f64>]) -> std::io::Result<()>
+{
+    let end = data.windows(10).position(|w| w == b"end_header")
+        .ok_or_else(|| invalid("PLY file has no end_header"))?;
+    let body_start = data[end..].iter().position(|&c| c == b'\n')
+        .map(|i| end + i + 1)
+        .unwrap_or(data.len());
+    let header = std::str::from_utf8(&data[..end])
+        .map_err(|_| invalid("PLY header isn't valid text"))?;
+
+    let mut lines = header.lines().map(str::split_whitespace);
+    if lines.next().and_then(|mut w| w.next()) != Some("ply") {
+        return Err(invalid("Not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for mut words in lines {
+        match words.next() {
+            Some("format") => format = words.next(),
+            Some("element") => {
+                let name = words.next().unwrap_or_default().to_owned();
+                let count = words.next().and_then(|c| c.parse().ok())
+                    .ok_or_else(|| invalid("Invalid PLY element count"))?;
+                elements.push(Element { name, count, props: Vec::new() });
+            },
+            Some("property") => {
+                let e = elements.last_mut()
+                    .ok_or_else(|| invalid("PLY property outside of an element"))?;
+                let words: Vec<&str> = words.collect();
+                let ty = |s: &str| Scalar::parse(s)
+                    .ok_or_else(|| invalid(format!("Unknown PLY type '{}'", s)));
+                e.props.push(match words[..] {
+                    ["list", n, t, name] => Property::List(name.to_owned(), ty(n)?, ty(t)?),
+                    [t, name] => Property::Scalar(name.to_owned(), ty(t)?),
+                    _ => return Err(invalid("Invalid PLY property")),
+                });
+            },
+            _ => (),
+        }
+    }
+    let mut body = match format {
+        Some("ascii") => Body::Ascii(std::str::from_utf8(&data[body_start..])
+            .map_err(|_| invalid("ASCII PLY data isn't valid text"))?
+            .split_ascii_whitespace()),
+        Some("binary_little_endian") =>
+            Body::Binary { data: &data[body_start..], big_endian: false },
+        Some("binary_big_endian") =>
+            Body::Binary { data: &data[body_start..], big_endian: true },
+        _ => return Err(invalid("Unknown PLY format")),
+    };
+
+    for e in &elements {
+        let mut values = vec![Vec::new(); e.props.len()];
+        for _ in 0..e.count {
+            for (p, v) in e.props.iter().zip(&mut values) {
+                v.clear();
+                match p {
+                    Property::Scalar(_, ty) => v.push(body.next(*ty)?),
+                    Property::List(_, n, ty) => {
+                        let n = body.next(*n)? as usize;
+                        for _ in 0..n {
+                            v.push(body.next(*ty)?);
+                        }
+                    },
+                }
+            }
+            f(e, &values)?;
+        }
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        write_header(&mut out, &[("face", 2, MESH_FACE)]).unwrap();
+        for (verts, id) in [([0u32, 1, 2], Some(7)), ([2, 1, 3], None)] {
+            out.push(3);
+            for v in verts {
+                out.extend(&v.to_le_bytes());
+            }
+            out.extend(&face_id(id).unwrap().to_le_bytes());
+        }
+        let mut rows = Vec::new();
+        read(&out, |e, values| {
+            assert_eq!(e.name, "face");
+            assert_eq!(e.props[1].name(), "face_id");
+            rows.push(values.to_vec());
+            Ok(())
+        }).unwrap();
+        assert_eq!(rows, vec![vec![vec![0.0, 1.0, 2.0], vec![7.0]],
+                              vec![vec![2.0, 1.0, 3.0], vec![-1.0]]]);
+        assert!(face_id(Some(usize::MAX)).is_err());
+    }
+}
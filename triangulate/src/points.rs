@@ -0,0 +1,160 @@
+use nalgebra_glm::DVec3;
+
+use crate::{mesh::Mesh, ply, rng::Rng};
+
+/// A point on the surface of a mesh
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfacePoint {
+    pub pos: DVec3,
+    /// Unit normal, interpolated from the triangle's vertex normals
+    pub norm: DVec3,
+    /// STEP entity id of the face which the point lies on, if known
+    pub face_id: Option<usize>,
+}
+
+/// Points spread over a mesh's surface, e.g. for registration against a 3D
+/// scan
+#[derive(Clone, Debug, Default)]
+pub struct PointCloud {
+    pub points: Vec<SurfacePoint>,
+}
+
+impl PointCloud {
+    /// Picks random points on the mesh's triangles, with (on average)
+    /// `density` points per unit of area.  Each triangle gets a number of
+    /// points proportional to its area, rounded up or down at random so
+    /// that slivers aren't skipped altogether.
+    pub fn sample(mesh: &Mesh, density: f64, seed: u64) -> Self {
+        let mut rng = Rng(seed);
+        let face_ids = mesh.triangle_face_ids();
+        let mut points = Vec::new();
+        for (t, face_id) in mesh.triangles.iter().zip(face_ids) {
+            let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+                .map(|i| &mesh.verts[i as usize]);
+            let cross = (b.pos - a.pos).cross(&(c.pos - a.pos));
+            let expected = cross.norm() / 2.0 * density;
+            let n = expected.floor() as usize
+                + (rng.next() < expected.fract()) as usize;
+            for _ in 0..n {
+                // Reflect points from the far half of the parallelogram
+                // back into the triangle, which keeps them uniform
+                let (mut u, mut v) = (rng.next(), rng.next());
+                if u + v > 1.0 {
+                    u = 1.0 - u;
+                    v = 1.0 - v;
+                }
+                let w = 1.0 - u - v;
+                let pos = a.pos * w + b.pos * u + c.pos * v;
+                let norm = a.norm * w + b.norm * u + c.norm * v;
+                let norm = if norm.norm() > 0.0 {
+                    norm.normalize()
+                } else {
+                    cross.normalize()
+                };
+                points.push(SurfacePoint { pos, norm, face_id });
+            }
+        }
+        Self { points }
+    }
+
+    /// Writes the cloud to a binary PLY file, with normals and each point's
+    /// face entity id (or -1 if unknown)
+    pub fn save_ply(&self, filename: &str) -> std::io::Result<()> {
+        let mut out: Vec<u8> = Vec::new();
+        ply::write_header(&mut out, &[("vertex", self.points.len(), &[
+            "float x", "float y", "float z",
+            "float nx", "float ny", "float nz", "int face_id",
+        ])])?;
+        for p in &self.points {
+            for c in p.pos.iter().chain(p.norm.iter()) {
+                out.extend(&(*c as f32).to_le_bytes());
+            }
+            out.extend(&ply::face_id(p.face_id)?.to_le_bytes());
+        }
+        std::fs::write(filename, out)
+    }
+
+    /// Writes the cloud to a text file, with one `x y z nx ny nz` line per
+    /// point
+    pub fn save_xyz(&self, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        for p in &self.points {
+            writeln!(out, "{} {} {} {} {} {}", p.pos.x, p.pos.y, p.pos.z,
+                     p.norm.x, p.norm.y, p.norm.z)?;
+        }
+        out.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{DVec2, U32Vec3};
+    use crate::mesh::{Triangle, Vertex};
+
+    #[test]
+    fn uniform_density() {
+        // A 10 x 1 rectangle, split into two large triangles and a sliver
+        let mut mesh = Mesh::default();
+        for (x, y) in [(0.0, 0.0), (10.0, 0.0), (10.0, 1.0), (0.0, 1.0), (9.9, 1.0)] {
+            mesh.verts.push(Vertex {
+                pos: DVec3::new(x, y, 0.0), norm: DVec3::z(),
+                color: DVec3::zeros(), uv: DVec2::zeros(),
+            });
+        }
+        for t in [[0, 1, 4], [1, 2, 4], [0, 4, 3]] {
+            mesh.triangles.push(Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) });
+        }
+        let cloud = PointCloud::sample(&mesh, 1000.0, 1);
+        let n = cloud.points.len() as f64;
+        assert!((n - 10_000.0).abs() < 10.0, "{} points", n);
+
+        // Each tenth of the rectangle gets a tenth of the points, including
+        // the one which contains the sliver
+        for i in 0..10 {
+            let x = i as f64;
+            let k = cloud.points.iter().filter(|p| p.pos.x >= x && p.pos.x < x + 1.0).count();
+            assert!((k as f64 - n / 10.0).abs() < n * 0.01, "{} points in {}", k, i);
+        }
+        assert!(cloud.points.iter().all(|p| p.norm == DVec3::z() && p.pos.z == 0.0));
+
+        // The same seed gives the same points
+        assert_eq!(cloud.points, PointCloud::sample(&mesh, 1000.0, 1).points);
+    }
+
+    #[test]
+    fn save_ply() {
+        let cloud = PointCloud { points: vec![
+            SurfacePoint { pos: DVec3::new(1.0, 2.0, 3.0), norm: DVec3::z(), face_id: Some(5) },
+            SurfacePoint { pos: DVec3::new(-1.0, 0.5, 0.0), norm: DVec3::x(), face_id: None },
+        ]};
+        let path = std::env::temp_dir()
+            .join(format!("foxtrot-points-{}.ply", std::process::id()));
+        cloud.save_ply(path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut points = Vec::new();
+        ply::read(&data, |e, values| {
+            assert_eq!(e.name, "vertex");
+            let v: Vec<f64> = values.iter().map(|v| v[0]).collect();
+            let id = if v[6] < 0.0 { None } else { Some(v[6] as usize) };
+            points.push(SurfacePoint {
+                pos: DVec3::new(v[0], v[1], v[2]),
+                norm: DVec3::new(v[3], v[4], v[5]),
+                face_id: id,
+            });
+            Ok(())
+        }).unwrap();
+        assert_eq!(points, cloud.points);
+
+        // Ids which don't fit in the PLY property are an error, not a panic
+        let far = PointCloud { points: vec![
+            SurfacePoint { face_id: Some(usize::MAX), ..cloud.points[0] },
+        ]};
+        assert!(far.save_ply(path.to_str().unwrap()).is_err());
+    }
+}
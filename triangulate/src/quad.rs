@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::ops::Range;
+
+use nalgebra_glm::{DVec2, DVec3, U32Vec3, U32Vec4};
+
+use crate::{mesh::{Mesh, SurfaceType, Vertex}, surface::Surface};
+
+/// Largest allowed deviation of a quad's corner angles from 90°, in degrees
+const MAX_CORNER_DEVIATION: f64 = 45.0;
+
+/// Largest allowed angle between the two triangles which make up a quad,
+/// in degrees
+const MAX_FOLD: f64 = 20.0;
+
+/// Largest deviation of a boundary segment from a grid line, as a fraction
+/// of the face's extent in each surface coordinate
+const GRID_TOLERANCE: f64 = 1e-6;
+
+/// An element in a mixed quad / triangle mesh, with vertices given as
+/// indices into the source `Mesh::verts` (in counter-clockwise order)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Element {
+    Quad(U32Vec4),
+    Triangle(U32Vec3),
+}
+
+/// A quad-dominant version of a [`Mesh`], which shares the mesh's vertices.
+/// `faces` has one range of elements for each face in `Mesh::faces`.
+#[derive(Clone, Debug, Default)]
+pub struct QuadMesh {
+    pub elements: Vec<Element>,
+    pub faces: Vec<std::ops::Range<usize>>,
+}
+
+/// Checks whether a surface type tessellates into structured strips, which
+/// pair up cleanly into quads
+fn is_structured(s: SurfaceType) -> bool {
+    matches!(s, SurfaceType::Plane | SurfaceType::Cylinder | SurfaceType::Cone)
+}
+
+/// Returns how far a quad is from being a planar rectangle (as the worst
+/// corner deviation from 90°, in degrees), or `None` if it's unacceptable
+fn quad_score(p: [DVec3; 4]) -> Option<f64> {
+    let n1 = (p[1] - p[0]).cross(&(p[2] - p[0]));
+    let n2 = (p[2] - p[0]).cross(&(p[3] - p[0]));
+    if n1.norm() == 0.0 || n2.norm() == 0.0 ||
+        n1.angle(&n2).to_degrees() > MAX_FOLD
+    {
+        return None;
+    }
+    let normal = (n1 + n2).normalize();
+    let mut worst: f64 = 0.0;
+    for i in 0..4 {
+        let prev = p[(i + 3) % 4] - p[i];
+        let next = p[(i + 1) % 4] - p[i];
+        // A reflex (or flipped) corner means the quad isn't convex
+        if next.cross(&prev).dot(&normal) <= 0.0 {
+            return None;
+        }
+        worst = worst.max((next.angle(&prev).to_degrees() - 90.0).abs());
+    }
+    if worst > MAX_CORNER_DEVIATION {
+        None
+    } else {
+        Some(worst)
+    }
+}
+
+/// A structured grid of quads covering a face, from [`structured_grid`]
+pub(crate) struct Grid {
+    /// New vertices inside the face, which follow its boundary vertices
+    pub interior: Vec<Vertex>,
+    /// Quads in counter-clockwise order (around the face's normal), as
+    /// indices into the boundary vertices followed by `interior`
+    pub quads: Vec<[usize; 4]>,
+    /// Number of quads across and along the surface's straight lines
+    pub size: [usize; 2],
+}
+
+/// Where the nodes of a grid come from.  Node `(i, j)` is at
+/// `nodes[j * s.len() + i]`, which is the index of a boundary vertex, or
+/// `None` for a node inside the face.  `s` and `t` are the grid lines'
+/// normalized surface coordinates; nodes along `t` follow straight lines on
+/// the surface, so rows 0 and `t.len() - 1` are always on the boundary.
+struct Layout {
+    nodes: Vec<Option<usize>>,
+    s: Vec<f64>,
+    t: Vec<f64>,
+}
+
+/// Wraps an angle into the range ±π
+fn wrap(a: f64) -> f64 {
+    a - (a / TAU).round() * TAU
+}
+
+/// Normalizes grid line coordinates to run from 0 to 1, returning `None`
+/// unless they're strictly increasing or decreasing
+fn grid_lines(v: Vec<f64>) -> Option<Vec<f64>> {
+    let (first, last) = (v[0], v[v.len() - 1]);
+    let out: Vec<f64> = v.into_iter().map(|x| (x - first) / (last - first)).collect();
+    if out.windows(2).all(|w| w[1] > w[0]) {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+/// Lays out a grid over a single boundary loop which is a rectangle in
+/// surface coordinates, with matching samples on opposite sides
+fn rectangle(coords: &[DVec2], plane: bool) -> Option<Layout> {
+    let n = coords.len();
+    let mut c = coords.to_vec();
+    if plane {
+        // Rotate the plane's coordinates to line up with the first side
+        let d = (c[1] - c[0]).try_normalize(0.0)?;
+        for p in &mut c {
+            *p = DVec2::new(p.dot(&d), d.x * p.y - d.y * p.x);
+        }
+    } else {
+        // Unwrap angles along the loop, so that seams don't jump by 2π
+        for k in 1..n {
+            c[k].x = c[k - 1].x + wrap(c[k].x - c[k - 1].x);
+        }
+    }
+    let (lo, hi) = c.iter().fold(
+        (DVec2::repeat(f64::INFINITY), DVec2::repeat(-f64::INFINITY)),
+        |(lo, hi), p| (lo.inf(p), hi.sup(p)));
+    let tol = (hi - lo) * GRID_TOLERANCE;
+    if tol.x == 0.0 || tol.y == 0.0 {
+        return None;
+    }
+
+    // Each segment must run along one coordinate: true for varying `s`
+    // (e.g. around a cylinder), false for varying `t` (along its axis)
+    let along_s = (0..n).map(|k| {
+        let d = c[(k + 1) % n] - c[k];
+        match (d.x.abs() > tol.x, d.y.abs() > tol.y) {
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            _ => None,
+        }
+    }).collect::<Option<Vec<bool>>>()?;
+
+    // Split the loop into sides, starting from a corner before a side
+    // along `s`, then check that it's got four of them
+    let start = (0..n).find(|&k| along_s[k] && !along_s[(k + n - 1) % n])?;
+    let mut sides: Vec<Vec<usize>> = Vec::new();
+    for m in 0..n {
+        let k = (start + m) % n;
+        if m == 0 || along_s[k] != along_s[(k + n - 1) % n] {
+            sides.push(vec![k]);
+        }
+        sides.last_mut().unwrap().push((k + 1) % n);
+    }
+    if sides.len() != 4 {
+        return None;
+    }
+    let (ns, nt) = (sides[0].len() - 1, sides[1].len() - 1);
+    if sides[2].len() != ns + 1 || sides[3].len() != nt + 1 {
+        return None;
+    }
+    let matched = (0..=ns).all(|i|
+            (c[sides[0][i]].x - c[sides[2][ns - i]].x).abs() <= tol.x) &&
+        (0..=nt).all(|j|
+            (c[sides[1][j]].y - c[sides[3][nt - j]].y).abs() <= tol.y);
+    if !matched {
+        return None;
+    }
+    let s = grid_lines(sides[0].iter().map(|&k| c[k].x).collect())?;
+    let t = grid_lines(sides[1].iter().map(|&k| c[k].y).collect())?;
+
+    let mut nodes = vec![None; (ns + 1) * (nt + 1)];
+    for i in 0..=ns {
+        nodes[i] = Some(sides[0][i]);
+        nodes[nt * (ns + 1) + i] = Some(sides[2][ns - i]);
+    }
+    for j in 0..=nt {
+        nodes[j * (ns + 1) + ns] = Some(sides[1][j]);
+        nodes[j * (ns + 1)] = Some(sides[3][nt - j]);
+    }
+    Some(Layout { nodes, s, t })
+}
+
+/// Returns the vertices of a loop which is a full circle around a cylinder
+/// or cone (with heights within `tol`), in order of increasing angle
+fn circle(coords: &[DVec2], r: Range<usize>, tol: f64) -> Option<Vec<usize>> {
+    let n = r.len();
+    if n < 3 {
+        return None;
+    }
+    let height = coords[r.start].y;
+    let steps: Vec<f64> = (0..n)
+        .map(|k| wrap(coords[r.start + (k + 1) % n].x - coords[r.start + k].x))
+        .collect();
+    let turn: f64 = steps.iter().sum();
+    if r.clone().any(|k| (coords[k].y - height).abs() > tol) ||
+        (turn.abs() - TAU).abs() > GRID_TOLERANCE ||
+        steps.iter().any(|d| d * turn <= 0.0)
+    {
+        return None;
+    }
+    let mut out: Vec<usize> = r.collect();
+    if turn < 0.0 {
+        out.reverse();
+    }
+    Some(out)
+}
+
+/// Lays out a band between two boundary loops which are full circles around
+/// a cylinder or cone, sampled at the same angles
+fn band(coords: &[DVec2], a: Range<usize>, b: Range<usize>) -> Option<Layout> {
+    let tol = (coords[a.start].y - coords[b.start].y).abs() * GRID_TOLERANCE;
+    let (lo, hi) = (circle(coords, a, tol)?, circle(coords, b, tol)?);
+    let n = lo.len();
+    if hi.len() != n {
+        return None;
+    }
+    let angle = |k: usize| coords[k].x;
+    let m = (0..n).min_by(|&x, &y| wrap(angle(hi[x]) - angle(lo[0])).abs()
+                              .total_cmp(&wrap(angle(hi[y]) - angle(lo[0])).abs()))?;
+    if (0..n).any(|i| wrap(angle(hi[(m + i) % n]) - angle(lo[i])).abs() >
+                      TAU * GRID_TOLERANCE)
+    {
+        return None;
+    }
+
+    // The last column is the first one again, closing the band
+    let mut s = vec![0.0];
+    for i in 1..=n {
+        s.push(s[i - 1] + wrap(angle(lo[i % n]) - angle(lo[i - 1])) / TAU);
+    }
+    let nodes = (0..=n).map(|i| Some(lo[i % n]))
+        .chain((0..=n).map(|i| Some(hi[(m + i) % n])))
+        .collect();
+    Some(Layout { nodes, s, t: vec![0.0, 1.0] })
+}
+
+/// Tessellates a face on a plane, cylinder, or cone as a structured grid
+/// of quads, if its boundary allows: a single loop which is a rectangle in
+/// [`Surface::grid_coords`] (with matching samples on opposite sides), or
+/// two full circles around a cylinder or cone, sampled at the same angles.
+///
+/// `verts` are the face's boundary vertices with surface normals, split
+/// into `loops`, and they're given texture coordinates from the grid.  New
+/// nodes are interpolated along the surface's straight lines, so they're
+/// exactly on it.  Returns `None` if the face doesn't fit a grid, in which
+/// case it should be triangulated as usual.
+pub(crate) fn structured_grid(surf: &Surface, verts: &mut [Vertex],
+                              loops: &[Range<usize>], same_sense: bool)
+    -> Option<Grid>
+{
+    // Points which aren't on a loop (e.g. a cone's apex) don't fit
+    if loops.iter().map(|r| r.len()).sum::<usize>() != verts.len() {
+        return None;
+    }
+    let coords = verts.iter()
+        .map(|v| surf.grid_coords(v.pos))
+        .collect::<Option<Vec<_>>>()?;
+    let plane = surf.surface_type() == SurfaceType::Plane;
+    let layout = match loops {
+        [r] if r.len() >= 4 => {
+            let mut out = rectangle(&coords[r.clone()], plane)?;
+            for v in out.nodes.iter_mut().flatten() {
+                *v += r.start;
+            }
+            out
+        },
+        [a, b] if !plane => band(&coords, a.clone(), b.clone())?,
+        _ => return None,
+    };
+
+    let Layout { nodes, s, t } = layout;
+    let (ns, nt) = (s.len() - 1, t.len() - 1);
+    let mut index = vec![0; nodes.len()];
+    let mut interior: Vec<Vertex> = Vec::new();
+    for (j, &tj) in t.iter().enumerate() {
+        for (i, &si) in s.iter().enumerate() {
+            let k = j * (ns + 1) + i;
+            let uv = DVec2::new(si, tj);
+            index[k] = match nodes[k] {
+                // A band's last column reuses its first column's vertices,
+                // which keep their texture coordinates
+                Some(v) if i == ns && nodes[k - ns] == Some(v) => v,
+                Some(v) => {
+                    verts[v].uv = uv;
+                    v
+                },
+                None => {
+                    let (a, b) = (verts[nodes[i]?], verts[nodes[nt * (ns + 1) + i]?]);
+                    interior.push(Vertex {
+                        pos: a.pos.lerp(&b.pos, tj),
+                        norm: a.norm.lerp(&b.norm, tj).normalize(),
+                        color: a.color,
+                        uv,
+                    });
+                    verts.len() + interior.len() - 1
+                },
+            };
+        }
+    }
+
+    let node = |i: usize, j: usize| index[j * (ns + 1) + i];
+    let mut quads: Vec<[usize; 4]> = (0..nt).flat_map(|j| (0..ns).map(move |i|
+            [node(i, j), node(i + 1, j), node(i + 1, j + 1), node(i, j + 1)]))
+        .collect();
+
+    // Wind the quads around the face's normal, which is the surface normal
+    // unless the face is reversed
+    let vert = |k: usize| if k < verts.len() { verts[k] } else { interior[k - verts.len()] };
+    let facing: f64 = quads.iter().map(|q| {
+        let [a, b, c, d] = q.map(vert);
+        (c.pos - a.pos).cross(&(d.pos - b.pos)).dot(&a.norm)
+    }).sum();
+    let facing = if same_sense { facing } else { -facing };
+    if facing.is_nan() || facing == 0.0 {
+        return None;
+    } else if facing < 0.0 {
+        for q in &mut quads {
+            q.swap(1, 3);
+        }
+    }
+    Some(Grid { interior, quads, size: [ns, nt] })
+}
+
+impl QuadMesh {
+    /// Converts a triangle mesh into quads, for consumers (like CFD and FEA
+    /// pre-processors) which prefer them.  Faces which were tessellated as
+    /// structured grids (see [`TessellationParams::quad_grids`]) become
+    /// their grids' quads.  On other faces on planes, cylinders, and cones,
+    /// adjacent triangles are greedily merged into quads, starting with the
+    /// most rectangular pairs; everything else, including faces on other
+    /// surfaces, is kept as triangles.
+    ///
+    /// [`TessellationParams::quad_grids`]: crate::triangulate::TessellationParams::quad_grids
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        let mut out = Self::default();
+        for face in &mesh.faces {
+            let start = out.elements.len();
+            let tris = &mesh.triangles[face.triangles.clone()];
+            let mut used = vec![false; tris.len()];
+
+            if face.grid.is_some() {
+                // Each of the grid's quads (a, b, c, d) was stored as the
+                // triangles (a, b, c) and (a, c, d)
+                for (i, pair) in tris.chunks_exact(2).enumerate() {
+                    let (t, u) = (pair[0].verts, pair[1].verts);
+                    if t[0] == u[0] && t[2] == u[1] {
+                        used[2 * i] = true;
+                        used[2 * i + 1] = true;
+                        out.elements.push(Element::Quad(U32Vec4::new(t[0], t[1], t[2], u[2])));
+                    }
+                }
+            } else if is_structured(face.surface) {
+                // Map each directed edge to the triangle which contains it
+                let mut edges = HashMap::new();
+                for (i, t) in tris.iter().enumerate() {
+                    for j in 0..3 {
+                        edges.insert((t.verts[j], t.verts[(j + 1) % 3]), (i, j));
+                    }
+                }
+                let mut pairs = Vec::new();
+                for (i, t) in tris.iter().enumerate() {
+                    for j in 0..3 {
+                        let (a, b) = (t.verts[j], t.verts[(j + 1) % 3]);
+                        let c = t.verts[(j + 2) % 3];
+                        let (k, m) = match edges.get(&(b, a)) {
+                            Some(&(k, m)) if k > i => (k, m),
+                            _ => continue,
+                        };
+                        let d = tris[k].verts[(m + 2) % 3];
+                        let q = U32Vec4::new(a, d, b, c);
+                        let pos = |v: u32| mesh.verts[v as usize].pos;
+                        if let Some(s) = quad_score([pos(a), pos(d), pos(b), pos(c)]) {
+                            pairs.push((s, i, k, q));
+                        }
+                    }
+                }
+                pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+                for (_, i, k, q) in pairs {
+                    if !used[i] && !used[k] {
+                        used[i] = true;
+                        used[k] = true;
+                        out.elements.push(Element::Quad(q));
+                    }
+                }
+            }
+            for (t, u) in tris.iter().zip(&used) {
+                if !u {
+                    out.elements.push(Element::Triangle(t.verts));
+                }
+            }
+            out.faces.push(start..out.elements.len());
+        }
+        out
+    }
+
+    /// Returns the number of quads in the mesh
+    pub fn quad_count(&self) -> usize {
+        self.elements.iter()
+            .filter(|e| matches!(e, Element::Quad(..)))
+            .count()
+    }
+
+    /// Writes the mesh to a Wavefront OBJ file (using the source mesh's
+    /// vertices), with one group per face
+    pub fn save_obj(&self, mesh: &Mesh, filename: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        for v in mesh.verts.iter() {
+            writeln!(out, "v {} {} {}", v.pos.x, v.pos.y, v.pos.z)?;
+        }
+        for v in mesh.verts.iter() {
+            writeln!(out, "vt {} {}", v.uv.x, v.uv.y)?;
+        }
+        for v in mesh.verts.iter() {
+            writeln!(out, "vn {} {} {}", v.norm.x, v.norm.y, v.norm.z)?;
+        }
+        for (face, range) in mesh.faces.iter().zip(&self.faces) {
+            writeln!(out, "g face_{}", face.id)?;
+            for e in &self.elements[range.clone()] {
+                write!(out, "f")?;
+                let verts: &[u32] = match e {
+                    Element::Quad(q) => q.as_slice(),
+                    Element::Triangle(t) => t.as_slice(),
+                };
+                // OBJ indices are 1-based
+                for v in verts {
+                    write!(out, " {}/{}/{}", v + 1, v + 1, v + 1)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Builds an `n x n` grid of split squares as a single face
+    fn grid(n: usize, surface: SurfaceType) -> Mesh {
+        let mut mesh = Mesh::default();
+        let tris = fixtures::grid(&mut mesh, n, |i, j| DVec3::new(i, j, 0.0));
+        fixtures::push_face(&mut mesh, tris, surface);
+        mesh
+    }
+
+    #[test]
+    fn pairs_grid_into_quads() {
+        let mesh = grid(4, SurfaceType::Plane);
+        let q = QuadMesh::from_mesh(&mesh);
+        assert_eq!(q.elements.len(), 16);
+        assert_eq!(q.quad_count(), 16);
+        assert_eq!(q.faces, vec![0..16]);
+        // Quads keep the triangles' winding
+        for e in &q.elements {
+            if let Element::Quad(v) = e {
+                let p = |i: usize| mesh.verts[v[i] as usize].pos;
+                assert!((p(1) - p(0)).cross(&(p(2) - p(0))).z > 0.0);
+            }
+        }
+
+        // Other surfaces fall back to triangles
+        let mesh = grid(4, SurfaceType::Sphere);
+        let q = QuadMesh::from_mesh(&mesh);
+        assert_eq!(q.elements.len(), 32);
+        assert_eq!(q.quad_count(), 0);
+    }
+
+    /// Returns a point on a unit cylinder around the Z axis
+    fn on_cylinder(a: f64, z: f64) -> Vertex {
+        fixtures::vertex(DVec3::new(a.cos(), a.sin(), z), DVec3::zeros())
+    }
+
+    /// Lowers vertices onto a unit cylinder around the Z axis, filling in
+    /// their normals
+    fn cylinder(verts: &mut [Vertex]) -> Surface {
+        let mut surf = Surface::new_cylinder(DVec3::z(), DVec3::x(), DVec3::zeros(), 1.0);
+        surf.lower_verts(verts).unwrap();
+        surf
+    }
+
+    #[test]
+    fn cylinder_grid() {
+        // A closed band cut along a seam at angle 0: 8 samples around the
+        // bottom and top, with the seam split in two
+        let angle = |i: usize| i as f64 * TAU / 8.0;
+        let mut verts: Vec<Vertex> = (0..=8).map(|i| on_cylinder(angle(i), 0.0))
+            .chain([on_cylinder(angle(8), 1.0)])
+            .chain((0..=8).rev().map(|i| on_cylinder(angle(i), 2.0)))
+            .chain([on_cylinder(0.0, 1.0)])
+            .collect();
+        let surf = cylinder(&mut verts);
+        let boundary = 0..verts.len();
+        let loops = [boundary];
+        let grid = structured_grid(&surf, &mut verts, &loops, true).unwrap();
+        assert_eq!(grid.size, [8, 2]);
+        assert_eq!(grid.quads.len(), 16);
+
+        // New nodes are on the cylinder, halfway up
+        assert_eq!(grid.interior.len(), 7);
+        for v in &grid.interior {
+            assert!((v.pos.xy().norm() - 1.0).abs() < 1e-12 && v.pos.z == 1.0);
+            assert!((v.norm - v.pos.xy().push(0.0)).norm() < 1e-12);
+        }
+        assert_eq!(verts[4].uv, DVec2::new(0.5, 0.0));
+        assert_eq!(verts[9].uv, DVec2::new(1.0, 0.5));
+
+        // Quads face outwards, or inwards if the face is reversed
+        let all: Vec<Vertex> = verts.iter().chain(&grid.interior).cloned().collect();
+        for q in &grid.quads {
+            let [a, b, c, d] = q.map(|k| all[k].pos);
+            assert!((c - a).cross(&(d - b)).dot(&(a + c).xy().push(0.0)) > 0.0);
+        }
+        let reversed = structured_grid(&surf, &mut verts, &loops, false).unwrap();
+        assert_eq!(reversed.quads[0], [grid.quads[0][0], grid.quads[0][3],
+                                       grid.quads[0][2], grid.quads[0][1]]);
+
+        // Opposite sides must have matching samples
+        verts[2].pos = on_cylinder(angle(2) + 0.1, 0.0).pos;
+        assert!(structured_grid(&surf, &mut verts, &loops, true).is_none());
+    }
+
+    #[test]
+    fn cylinder_band() {
+        // Two full circles, starting at different angles and running in
+        // opposite directions
+        let angle = |i: usize| i as f64 * TAU / 6.0;
+        let mut verts: Vec<Vertex> = (0..6).map(|i| on_cylinder(angle(i), 0.0))
+            .chain((0..6).rev().map(|i| on_cylinder(angle(i + 2), 3.0)))
+            .collect();
+        let surf = cylinder(&mut verts);
+        let grid = structured_grid(&surf, &mut verts, &[0..6, 6..12], true).unwrap();
+        assert_eq!(grid.size, [6, 1]);
+        assert!(grid.interior.is_empty());
+        for q in &grid.quads {
+            let [a, b, c, d] = q.map(|k| verts[k].pos);
+            // Each quad spans one step around the cylinder, along its axis
+            assert_eq!((a.z, b.z, c.z, d.z), (0.0, 0.0, 3.0, 3.0));
+            assert!((a - d).xy().norm() < 1e-12 && (b - c).xy().norm() < 1e-12);
+            assert!((c - a).cross(&(d - b)).dot(&(a + c).xy().push(0.0)) > 0.0);
+        }
+
+        // Circles sampled at different angles can't be joined by a grid
+        let mut verts: Vec<Vertex> = (0..6).map(|i| on_cylinder(angle(i), 0.0))
+            .chain((0..6).map(|i| on_cylinder(angle(i) + 0.1, 3.0)))
+            .collect();
+        let surf = cylinder(&mut verts);
+        assert!(structured_grid(&surf, &mut verts, &[0..6, 6..12], true).is_none());
+    }
+}
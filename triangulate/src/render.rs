@@ -0,0 +1,199 @@
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::{Aabb, Bvh, Hit}, mesh::Mesh, rng::Rng, section::plane_basis};
+
+/// Angular radius of the key light, in radians, which sets the softness of
+/// its shadows
+const LIGHT_RADIUS: f64 = 0.1;
+
+/// Brightness of the key light, relative to the surrounding environment
+const LIGHT_INTENSITY: f64 = 0.8;
+
+/// Brightness of the uniform environment around the model, which gives
+/// ambient occlusion in creases and under overhangs
+const ENVIRONMENT: f64 = 0.5;
+
+/// Secondary rays start this far from the surface (as a fraction of the
+/// model's size), so that they don't hit the triangle that they left
+const RAY_OFFSET: f64 = 1e-6;
+
+/// Settings for [`render`]
+#[derive(Copy, Clone, Debug)]
+pub struct RenderParams {
+    pub width: usize,
+    pub height: usize,
+    /// Paths traced per pixel; noise falls off with the square root
+    pub samples: usize,
+    /// Number of times that each path may bounce between surfaces
+    pub bounces: usize,
+    /// Direction towards the key light, which casts soft shadows
+    pub light: DVec3,
+    /// Color of pixels which don't hit the model
+    pub background: DVec3,
+    /// If set, only geometry inside this box is rendered
+    pub clip: Option<Aabb>,
+}
+
+impl Default for RenderParams {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            samples: 64,
+            bounces: 3,
+            light: DVec3::new(-1.0, 1.0, 2.0),
+            background: DVec3::repeat(1.0),
+            clip: None,
+        }
+    }
+}
+
+/// Picks a direction from the hemisphere around `n`, with probability
+/// proportional to the cosine of its angle from `n` (which matches the
+/// falloff of diffuse reflection, so samples needn't be weighted)
+fn cosine_sample(n: DVec3, rng: &mut Rng) -> DVec3 {
+    let (u, v) = plane_basis(n);
+    let r = rng.next().sqrt();
+    let phi = rng.next() * std::f64::consts::TAU;
+    u * (r * phi.cos()) + v * (r * phi.sin()) + n * (1.0 - r * r).max(0.0).sqrt()
+}
+
+/// Picks a direction uniformly from the cone of the given angular radius
+/// around `axis`
+fn cone_sample(axis: DVec3, radius: f64, rng: &mut Rng) -> DVec3 {
+    let (u, v) = plane_basis(axis);
+    let cos = 1.0 - rng.next() * (1.0 - radius.cos());
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let phi = rng.next() * std::f64::consts::TAU;
+    u * (sin * phi.cos()) + v * (sin * phi.sin()) + axis * cos
+}
+
+/// Path-traces an image of the mesh on the CPU, returning tightly-packed
+/// sRGB pixels (row-major, top row first) with full alpha.
+///
+/// `ray` maps a position in pixels (which may be fractional) to a ray
+/// `(origin, direction)`, which lets callers use any kind of camera.
+/// Surfaces are diffuse, colored by their vertex colors, and lit by a soft
+/// key light and a uniform environment; the environment's light is blocked
+/// in crevices, which gives ambient occlusion.
+pub fn render<F>(mesh: &Mesh, params: &RenderParams, ray: F) -> Vec<u8>
+    where F: Fn(f64, f64) -> (DVec3, DVec3) + Sync
+{
+    let bvh = Bvh::new(mesh);
+    let mut bounds = Aabb::empty();
+    for v in &mesh.verts {
+        bounds.expand(v.pos);
+    }
+    let offset = (bounds.max - bounds.min).norm().max(f64::MIN_POSITIVE) * RAY_OFFSET;
+    let light = params.light.normalize();
+
+    let cast = |origin: DVec3, dir: DVec3| -> Option<Hit> {
+        match &params.clip {
+            None => bvh.raycast(origin, dir),
+            Some(c) => bvh.raycast_all(origin, dir).into_iter()
+                .find(|h| (0..3).all(|i| h.point[i] >= c.min[i] && h.point[i] <= c.max[i])),
+        }
+    };
+
+    // Returns the incoming light along one path
+    let trace = |origin: DVec3, dir: DVec3, rng: &mut Rng| -> DVec3 {
+        let (mut origin, mut dir) = (origin, dir.normalize());
+        let mut weight = DVec3::repeat(1.0);
+        let mut out = DVec3::zeros();
+        for bounce in 0..=params.bounces {
+            let hit = match cast(origin, dir) {
+                Some(h) => h,
+                None => {
+                    let sky = if bounce == 0 {
+                        params.background
+                    } else {
+                        DVec3::repeat(ENVIRONMENT)
+                    };
+                    return out + weight.component_mul(&sky);
+                },
+            };
+
+            // Interpolate the normal and color from the triangle's vertices
+            let t = mesh.triangles[hit.triangle];
+            let v = [0, 1, 2].map(|i| &mesh.verts[t.verts[i] as usize]);
+            let mut ng = (v[1].pos - v[0].pos).cross(&(v[2].pos - v[0].pos));
+            let area = ng.norm();
+            if area == 0.0 {
+                break;
+            }
+            ng /= area;
+            // Each vertex is weighted by the area of the sub-triangle opposite
+            let b = [0, 1, 2].map(|i| {
+                let (p, q) = (v[(i + 1) % 3].pos, v[(i + 2) % 3].pos);
+                (q - p).cross(&(hit.point - p)).dot(&ng) / area
+            });
+            let mut ns = v.iter().zip(b.iter())
+                .map(|(v, b)| v.norm * *b)
+                .sum::<DVec3>();
+            let albedo = v.iter().zip(b.iter())
+                .map(|(v, b)| v.color * *b)
+                .sum::<DVec3>();
+            // Surfaces are two-sided, facing whichever way the ray came from
+            if ng.dot(&dir) > 0.0 {
+                ng = -ng;
+                ns = -ns;
+            }
+            if ns.dot(&ng) <= 0.0 || ns.norm() == 0.0 {
+                ns = ng;
+            } else {
+                ns = ns.normalize();
+            }
+            weight = weight.component_mul(&albedo);
+            origin = hit.point + ng * offset;
+
+            let l = cone_sample(light, LIGHT_RADIUS, rng);
+            let cos = ns.dot(&l);
+            if cos > 0.0 && cast(origin, l).is_none() {
+                out += weight * (cos * LIGHT_INTENSITY);
+            }
+            dir = cosine_sample(ns, rng);
+            if dir.dot(&ng) <= 0.0 {
+                break;
+            }
+        }
+        out
+    };
+
+    let rows = {
+        #[cfg(feature = "rayon")]
+        { (0..params.height).into_par_iter() }
+        #[cfg(not(feature = "rayon"))]
+        { 0..params.height }
+    };
+    let samples = params.samples.max(1);
+    let pixels: Vec<Vec<u8>> = rows.map(|y| (0..params.width).flat_map(|x| {
+            // Each pixel gets its own generator, so that the image doesn't
+            // depend on thread scheduling
+            let mut rng = Rng((y * params.width + x) as u64);
+            let sum: DVec3 = (0..samples)
+                .map(|_| {
+                    let (origin, dir) = ray(x as f64 + rng.next(),
+                                            y as f64 + rng.next());
+                    trace(origin, dir, &mut rng)
+                })
+                .sum();
+            let c = sum / samples as f64;
+            let px = [c.x, c.y, c.z].map(|c| (to_srgb(c) * 255.0).round() as u8);
+            [px[0], px[1], px[2], 255]
+        }).collect())
+        .collect();
+    pixels.concat()
+}
+
+/// Converts a linear color channel into sRGB, clamping it into range
+fn to_srgb(c: f64) -> f64 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,19 @@
+//! Deterministic random numbers, shared by the sampling code (point clouds,
+//! hull construction, and path tracing) so that results are reproducible.
+
+/// Small deterministic generator (SplitMix64), so that the same input and
+/// seed always give the same output.  Consecutive seeds give unrelated
+/// streams, so callers may seed one generator per item (e.g. per pixel).
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    /// Returns a uniform value in [0, 1)
+    pub(crate) fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
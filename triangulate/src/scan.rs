@@ -0,0 +1,352 @@
+use nalgebra_glm::{DMat3, DMat4, DVec2, DVec3, U32Vec3};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::Bvh, mesh::{Mesh, Triangle, Vertex}, ply};
+
+/// Registration uses at most this many points from the scan, picked evenly
+/// through its vertex list
+const MAX_POINTS: usize = 20_000;
+
+/// Correspondences more than this many times the RMS distance apart are
+/// ignored, so that stray points (e.g. the scanner's turntable) don't drag
+/// the registration
+const OUTLIER_RATIO: f64 = 3.0;
+
+/// Registration stops once an iteration improves the RMS distance by less
+/// than this fraction of the target's size
+const CONVERGED: f64 = 1e-7;
+
+fn invalid<S: Into<String>>(msg: S) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Loads a scanned mesh or point cloud, picking the format (PLY or STL) from
+/// the filename's extension.  Point clouds are returned as a mesh with no
+/// triangles.
+pub fn load_scan(filename: &str) -> std::io::Result<Mesh> {
+    let data = std::fs::read(filename)?;
+    match filename.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+        Some("ply") => load_ply(&data),
+        Some("stl") => load_stl(&data),
+        _ => Err(invalid(format!("Unknown scan format for '{}' \
+                                  (expected .ply or .stl)", filename))),
+    }
+}
+
+/// Loads a PLY file (ASCII or binary), reading vertex positions (and
+/// normals and colors, if present) and faces, which are split into fans of
+/// triangles
+pub fn load_ply(data: &[u8]) -> std::io::Result<Mesh> {
+    let mut mesh = Mesh::default();
+    let mut has_normals = false;
+    ply::read(data, |e, values| {
+        match e.name.as_str() {
+            "vertex" => {
+                let mut vert = Vertex {
+                    pos: DVec3::zeros(), norm: DVec3::zeros(),
+                    color: DVec3::repeat(0.5), uv: DVec2::zeros(),
+                };
+                for (p, v) in e.props.iter().zip(values) {
+                    let v = v.first().copied().unwrap_or_default();
+                    match p.name() {
+                        "x" => vert.pos.x = v,
+                        "y" => vert.pos.y = v,
+                        "z" => vert.pos.z = v,
+                        "nx" => vert.norm.x = v,
+                        "ny" => vert.norm.y = v,
+                        "nz" => vert.norm.z = v,
+                        "red" => vert.color.x = v / 255.0,
+                        "green" => vert.color.y = v / 255.0,
+                        "blue" => vert.color.z = v / 255.0,
+                        _ => (),
+                    }
+                }
+                has_normals |= vert.norm != DVec3::zeros();
+                mesh.verts.push(vert);
+            },
+            "face" => {
+                let poly = e.props.iter().zip(values)
+                    .find(|(p, _)| matches!(p, ply::Property::List(..)) &&
+                          matches!(p.name(), "vertex_indices" | "vertex_index"))
+                    .map(|(_, v)| v.as_slice())
+                    .unwrap_or_default();
+                for i in 2..poly.len() {
+                    mesh.triangles.push(Triangle {
+                        verts: U32Vec3::new(poly[0] as u32, poly[i - 1] as u32,
+                                            poly[i] as u32),
+                    });
+                }
+            },
+            _ => (),
+        }
+        Ok(())
+    })?;
+    let n = mesh.verts.len() as u32;
+    if mesh.triangles.iter().any(|t| t.verts.iter().any(|&i| i >= n)) {
+        return Err(invalid("PLY face refers to a missing vertex"));
+    }
+    if !has_normals {
+        vertex_normals(&mut mesh);
+    }
+    Ok(mesh)
+}
+
+/// Loads a STL file (ASCII or binary), with separate vertices for each
+/// triangle
+pub fn load_stl(data: &[u8]) -> std::io::Result<Mesh> {
+    let mut pts = Vec::new();
+    let binary_len = data.get(80..84)
+        .map(|n| 84 + 50 * u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as usize);
+    if binary_len == Some(data.len()) {
+        for t in data[84..].chunks_exact(50) {
+            for v in t[12..48].chunks_exact(12) {
+                let c = |i: usize| f32::from_le_bytes(
+                    [v[i], v[i + 1], v[i + 2], v[i + 3]]) as f64;
+                pts.push(DVec3::new(c(0), c(4), c(8)));
+            }
+        }
+    } else if data.starts_with(b"solid") {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| invalid("ASCII STL isn't valid text"))?;
+        let mut words = text.split_ascii_whitespace();
+        while let Some(w) = words.next() {
+            if w == "vertex" {
+                let mut c = || words.next().and_then(|c| c.parse().ok())
+                    .ok_or_else(|| invalid("Invalid STL vertex"));
+                pts.push(DVec3::new(c()?, c()?, c()?));
+            }
+        }
+        if pts.len() % 3 != 0 {
+            return Err(invalid("STL facets must have three vertices"));
+        }
+    } else {
+        return Err(invalid("Not a STL file"));
+    }
+
+    let mut mesh = Mesh {
+        triangles: (0..pts.len() as u32 / 3)
+            .map(|i| Triangle { verts: U32Vec3::new(i * 3, i * 3 + 1, i * 3 + 2) })
+            .collect(),
+        verts: pts.into_iter()
+            .map(|pos| Vertex {
+                pos, norm: DVec3::zeros(), color: DVec3::repeat(0.5), uv: DVec2::zeros(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    vertex_normals(&mut mesh);
+    Ok(mesh)
+}
+
+/// Sets each vertex normal to the area-weighted average of the normals of
+/// the triangles which use it
+fn vertex_normals(mesh: &mut Mesh) {
+    let mut norms = vec![DVec3::zeros(); mesh.verts.len()];
+    for t in &mesh.triangles {
+        let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+            .map(|i| mesh.verts[i as usize].pos);
+        let n = (b - a).cross(&(c - a));
+        for i in t.verts.iter() {
+            norms[*i as usize] += n;
+        }
+    }
+    for (v, n) in mesh.verts.iter_mut().zip(norms) {
+        if n.norm() > 0.0 {
+            v.norm = n.normalize();
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Result of [`register`]
+#[derive(Copy, Clone, Debug)]
+pub struct Registration {
+    /// Rigid transform from the scan's coordinates into the model's
+    pub transform: DMat4,
+    /// RMS distance from the registered points to the model, not counting
+    /// outliers
+    pub rms: f64,
+    /// Number of refinement steps which were run
+    pub iterations: usize,
+    /// Number of scan points used for the registration, which are a
+    /// subsample of large scans
+    pub points: usize,
+}
+
+/// Returns a translation which moves the centroid of the points onto the
+/// (area-weighted) centroid of the mesh's surface, as a rough starting point
+/// for [`register`]
+pub fn centroid_alignment(points: &[DVec3], mesh: &Mesh) -> DMat4 {
+    let (mut sum, mut area) = (DVec3::zeros(), 0.0);
+    for t in &mesh.triangles {
+        let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+            .map(|i| mesh.verts[i as usize].pos);
+        let w = (b - a).cross(&(c - a)).norm();
+        sum += (a + b + c) * (w / 3.0);
+        area += w;
+    }
+    if points.is_empty() || area == 0.0 {
+        return DMat4::identity();
+    }
+    let from = points.iter().sum::<DVec3>() / points.len() as f64;
+    nalgebra_glm::translation(&(sum / area - from))
+}
+
+/// Aligns a set of points (e.g. the vertices of a 3D scan) with the surface
+/// in `target` by iterative closest point (ICP), starting from `initial`.
+///
+/// Each step pairs every point with its closest point on the surface and
+/// finds the rigid motion which best overlaps the pairs, so the initial
+/// transform must be close enough that most points pair with the right
+/// part of the model.  With `max_iterations` set to 0, this only measures
+/// how well `initial` fits.
+pub fn register(points: &[DVec3], target: &Bvh, initial: DMat4,
+                max_iterations: usize) -> Registration
+{
+    let stride = (points.len() / MAX_POINTS).max(1);
+    let points: Vec<DVec3> = points.iter().step_by(stride).copied().collect();
+
+    let bounds = target.bounds();
+    let eps = (bounds.max - bounds.min).norm() * CONVERGED;
+    let mut transform = initial;
+    let mut prev = f64::INFINITY;
+    let mut iterations = 0;
+    loop {
+        let pairs = correspondences(&points, target, &transform);
+        let rms = (pairs.iter().map(|(p, c)| (p - c).norm_squared()).sum::<f64>()
+                   / pairs.len().max(1) as f64).sqrt();
+        if iterations == max_iterations || pairs.len() < 3 ||
+            prev - rms < eps
+        {
+            return Registration { transform, rms, iterations, points: points.len() };
+        }
+        prev = rms;
+        transform = best_fit(&pairs) * transform;
+        iterations += 1;
+    }
+}
+
+/// Pairs each transformed point with its closest point on the target,
+/// dropping outliers
+fn correspondences(points: &[DVec3], target: &Bvh, transform: &DMat4)
+    -> Vec<(DVec3, DVec3)>
+{
+    let iter = {
+        #[cfg(feature = "rayon")]
+        { points.par_iter() }
+        #[cfg(not(feature = "rayon"))]
+        { points.iter() }
+    };
+    let pairs: Vec<(DVec3, DVec3, f64)> = iter
+        .filter_map(|p| {
+            let p = (transform * p.push(1.0)).xyz();
+            target.closest_point(p).map(|c| (p, c.point, c.distance))
+        })
+        .collect();
+    let rms = (pairs.iter().map(|p| p.2 * p.2).sum::<f64>()
+               / pairs.len().max(1) as f64).sqrt();
+    pairs.into_iter()
+        .filter(|p| p.2 <= rms * OUTLIER_RATIO)
+        .map(|(p, c, _)| (p, c))
+        .collect()
+}
+
+/// Finds the rigid transform which minimizes the squared distances between
+/// pairs of points (the Kabsch algorithm)
+fn best_fit(pairs: &[(DVec3, DVec3)]) -> DMat4 {
+    let n = pairs.len() as f64;
+    let from = pairs.iter().map(|p| p.0).sum::<DVec3>() / n;
+    let to = pairs.iter().map(|p| p.1).sum::<DVec3>() / n;
+    let h: DMat3 = pairs.iter()
+        .map(|(p, c)| (p - from) * (c - to).transpose())
+        .sum();
+    let svd = h.svd(true, true);
+    let (u, v_t) = match (svd.u, svd.v_t) {
+        (Some(u), Some(v_t)) => (u, v_t),
+        _ => return DMat4::identity(),
+    };
+    // Flip the least significant axis if needed, so that this is a
+    // rotation rather than a reflection
+    let mut d = DMat3::identity();
+    if (v_t.transpose() * u.transpose()).determinant() < 0.0 {
+        d[(2, 2)] = -1.0;
+    }
+    let r = v_t.transpose() * d * u.transpose();
+    let mut out = DMat4::identity();
+    out.fixed_slice_mut::<3, 3>(0, 0).copy_from(&r);
+    out.fixed_slice_mut::<3, 1>(0, 3).copy_from(&(to - r * from));
+    out
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_ply() {
+        let data = b"ply
+format ascii 1.0
+comment a unit square, as one quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+property int face_id
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+4 0 1 2 3 7
+";
+        let mesh = load_ply(data).unwrap();
+        assert_eq!(mesh.verts.len(), 4);
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(mesh.verts[2].pos, DVec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.verts[0].color, DVec3::x());
+        assert!(mesh.verts.iter().all(|v| v.norm == DVec3::z()));
+    }
+
+    #[test]
+    fn ascii_stl() {
+        let data = b"solid square
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 1 1 0
+  endloop
+endfacet
+endsolid square
+";
+        let mesh = load_stl(data).unwrap();
+        assert_eq!(mesh.triangles.len(), 1);
+        assert_eq!(mesh.verts[1].pos, DVec3::x());
+        assert_eq!(mesh.verts[0].norm, DVec3::z());
+        assert!(load_stl(b"solid square\nvertex 0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn kabsch() {
+        let pts = [DVec3::zeros(), DVec3::x(), DVec3::y(), DVec3::new(0.0, 0.0, 2.0)];
+        let mat = nalgebra_glm::translation(&DVec3::new(1.0, 2.0, 3.0)) *
+                  nalgebra_glm::rotation(0.3, &DVec3::new(1.0, 1.0, 0.0));
+        let pairs: Vec<_> = pts.iter()
+            .map(|p| (*p, (mat * p.push(1.0)).xyz()))
+            .collect();
+        let fit = best_fit(&pairs);
+        assert!((fit - mat).abs().max() < 1e-12);
+    }
+}
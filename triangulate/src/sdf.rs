@@ -0,0 +1,234 @@
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::Bvh, grid, mesh::Mesh};
+
+/// Direction of the rays which decide whether a sample is inside the
+/// model, picked (as in `clash`) to avoid running along axis-aligned edges
+const RAY_DIR: DVec3 = DVec3::new(1.0, 0.754_877_666_2, 0.569_840_291_0);
+
+/// Edge length of the blocks in a sparse file
+const BLOCK: usize = 8;
+
+/// A signed distance field sampled on a regular grid, which is negative
+/// inside the model and positive outside
+#[derive(Clone, Debug)]
+pub struct DistanceField {
+    /// Position of the first sample, i.e. the center of the first voxel
+    pub origin: DVec3,
+    /// Distance between neighboring samples
+    pub spacing: f64,
+    /// Number of samples along each axis
+    pub dims: [usize; 3],
+    /// Distances, with X varying fastest and then Y
+    pub values: Vec<f32>,
+}
+
+impl DistanceField {
+    /// Samples the signed distance to the closed solids in the mesh, on a
+    /// grid with the given spacing which covers the model and `padding`
+    /// extra voxels on every side.
+    ///
+    /// Each sample's sign comes from counting crossings along a ray, so
+    /// solids must be closed and shouldn't overlap each other.
+    pub fn new(mesh: &Mesh, spacing: f64, padding: usize) -> Self {
+        assert!(spacing > 0.0, "Voxel spacing must be positive");
+        let bvh = Bvh::new(mesh);
+        let bounds = bvh.bounds();
+        let (lo, hi) = if mesh.triangles.is_empty() {
+            (DVec3::zeros(), DVec3::zeros())
+        } else {
+            (bounds.min, bounds.max)
+        };
+        let pad = padding as f64 * spacing;
+        let dims = [0, 1, 2].map(|i|
+            ((hi[i] - lo[i] + 2.0 * pad) / spacing).ceil().max(0.0) as usize + 1);
+        // Center the grid on the model, so that padding is even
+        let extent = DVec3::new(dims[0] as f64, dims[1] as f64, dims[2] as f64)
+            .add_scalar(-1.0) * spacing;
+        let origin = (lo + hi - extent) / 2.0;
+
+        // Hits closer together than this are treated as the same crossing,
+        // e.g. where a ray passes through an edge between two triangles
+        let eps = (hi - lo).norm() * 1e-9;
+        let sample = |p: DVec3| {
+            let d = match bvh.closest_point(p) {
+                Some(c) => c.distance,
+                None => return f32::INFINITY,
+            };
+            let hits = bvh.raycast_all(p, RAY_DIR);
+            let crossings = 1 + hits.windows(2)
+                .filter(|w| w[1].distance - w[0].distance > eps)
+                .count();
+            if !hits.is_empty() && crossings % 2 == 1 {
+                -d as f32
+            } else {
+                d as f32
+            }
+        };
+        let slice = |z: usize| {
+            let mut out = Vec::with_capacity(dims[0] * dims[1]);
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    let p = origin + DVec3::new(x as f64, y as f64, z as f64) * spacing;
+                    out.push(sample(p));
+                }
+            }
+            out
+        };
+        let slices: Vec<Vec<f32>> = {
+            #[cfg(feature = "rayon")]
+            { (0..dims[2]).into_par_iter().map(slice).collect() }
+            #[cfg(not(feature = "rayon"))]
+            { (0..dims[2]).map(slice).collect() }
+        };
+        Self { origin, spacing, dims, values: slices.concat() }
+    }
+
+    /// Returns the sample at the given grid position
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[x + self.dims[0] * (y + self.dims[1] * z)]
+    }
+
+    /// Writes the samples as raw little-endian 32-bit floats, with X varying
+    /// fastest (the grid's size and placement aren't recorded)
+    pub fn save_raw(&self, filename: &str) -> std::io::Result<()> {
+        grid::save_raw(filename, self.values.iter().copied())
+    }
+
+    /// Writes the samples as a NRRD volume, which is the raw data behind a
+    /// short text header giving the grid's size and placement (readable by
+    /// e.g. ParaView and 3D Slicer)
+    pub fn save_nrrd(&self, filename: &str) -> std::io::Result<()> {
+        grid::save_nrrd(filename, self.origin, DVec3::repeat(self.spacing),
+                        self.dims, self.values.iter().copied())
+    }
+
+    /// Writes a narrow band of the field around the surface to a sparse
+    /// file, in the spirit of OpenVDB.  The grid is split into blocks of
+    /// 8³ samples.  Blocks with any sample closer than `band` to the surface
+    /// are stored in full; blocks entirely inside are stored as a single
+    /// `-band` value, and blocks entirely outside are omitted (and read as
+    /// `band`).
+    ///
+    /// All values are little-endian.  The file starts with the magic bytes
+    /// `FXSDF\0\0\x01`, followed by the grid's dimensions (3 × `u32`),
+    /// origin (3 × `f64`), spacing (`f64`), band (`f32`), and block count
+    /// (`u32`).  Each block then has its position in blocks (3 × `u32`)
+    /// and a tag byte, which is 0 for an interior block (followed by
+    /// nothing) or 1 for a stored block (followed by 512 `f32` samples with
+    /// X varying fastest, padded with `band` past the edge of the grid).
+    pub fn save_sparse(&self, band: f32, filename: &str) -> std::io::Result<()> {
+        let nb = self.dims.map(|d| d.div_ceil(BLOCK));
+        let mut blocks: Vec<u8> = Vec::new();
+        let mut count: u32 = 0;
+        for bz in 0..nb[2] {
+            for by in 0..nb[1] {
+                for bx in 0..nb[0] {
+                    let mut samples = Vec::with_capacity(BLOCK.pow(3));
+                    for z in bz * BLOCK..(bz + 1) * BLOCK {
+                        for y in by * BLOCK..(by + 1) * BLOCK {
+                            for x in bx * BLOCK..(bx + 1) * BLOCK {
+                                let inside = x < self.dims[0] && y < self.dims[1]
+                                    && z < self.dims[2];
+                                samples.push(if inside { self.get(x, y, z) } else { band });
+                            }
+                        }
+                    }
+                    let near = samples.iter().any(|v| v.abs() < band);
+                    if !near && samples.iter().all(|v| *v > 0.0) {
+                        continue;
+                    }
+                    for b in [bx, by, bz] {
+                        blocks.extend(&(b as u32).to_le_bytes());
+                    }
+                    if near {
+                        blocks.push(1);
+                        blocks.extend(samples.iter().flat_map(|v| v.to_le_bytes()));
+                    } else {
+                        blocks.push(0);
+                    }
+                    count += 1;
+                }
+            }
+        }
+
+        let mut out: Vec<u8> = b"FXSDF\0\0\x01".to_vec();
+        for d in self.dims {
+            out.extend(&(d as u32).to_le_bytes());
+        }
+        for c in self.origin.iter() {
+            out.extend(&c.to_le_bytes());
+        }
+        out.extend(&self.spacing.to_le_bytes());
+        out.extend(&band.to_le_bytes());
+        out.extend(&count.to_le_bytes());
+        out.extend(blocks);
+        std::fs::write(filename, out)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Builds a closed, axis-aligned box from 0 to `size`
+    fn cuboid(size: DVec3) -> Mesh {
+        let mut mesh = Mesh::default();
+        fixtures::cuboid(&mut mesh, DVec3::zeros(), size, false);
+        mesh
+    }
+
+    #[test]
+    fn cuboid_distances() {
+        let size = DVec3::new(4.0, 2.0, 1.0);
+        let sdf = DistanceField::new(&cuboid(size), 0.25, 2);
+        assert_eq!(sdf.dims, [21, 13, 9]);
+        assert_eq!(sdf.values.len(), 21 * 13 * 9);
+        assert_eq!(sdf.origin, DVec3::repeat(-0.5));
+
+        for z in 0..sdf.dims[2] {
+            for y in 0..sdf.dims[1] {
+                for x in 0..sdf.dims[0] {
+                    // Exact distance to the box
+                    let p = sdf.origin + DVec3::new(x as f64, y as f64, z as f64) * sdf.spacing;
+                    let q = (p - size / 2.0).abs() - size / 2.0;
+                    let outside = q.sup(&DVec3::zeros()).norm();
+                    let inside = q.max().min(0.0);
+                    let d = outside + inside;
+                    assert!((sdf.get(x, y, z) as f64 - d).abs() < 1e-6,
+                            "{} at {:?}", sdf.get(x, y, z), p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_blocks() {
+        // With 8 voxels of padding, the outermost blocks are all outside,
+        // and the middle of the box is further than the band from its walls
+        let sdf = DistanceField::new(&cuboid(DVec3::repeat(4.0)), 0.125, 8);
+        assert_eq!(sdf.dims, [49, 49, 49]);
+        let path = std::env::temp_dir()
+            .join(format!("foxtrot-sdf-blocks-{}.fsdf", std::process::id()));
+        sdf.save_sparse(0.25, path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let count = u32::from_le_bytes([data[56], data[57], data[58], data[59]]);
+
+        let mut i = 60;
+        let mut tags = [0, 0];
+        for _ in 0..count {
+            i += 12;
+            tags[data[i] as usize] += 1;
+            i += 1 + if data[i] == 1 { 512 * 4 } else { 0 };
+        }
+        assert_eq!(i, data.len());
+        assert!(tags[0] > 0 && tags[1] > 0, "{:?}", tags);
+        assert!((count as usize) < 7 * 7 * 7);
+    }
+}
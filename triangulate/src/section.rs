@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use nalgebra_glm as glm;
+use glm::DVec3;
+
+use crate::{mesh::Mesh, svg::{self, Svg}};
+
+/// Chain ends which are closer than this (as a fraction of the solid's
+/// size) are joined, to close loops across small gaps in the mesh
+const JOIN_TOLERANCE: f64 = 1e-6;
+
+/// Where a plane cuts through one solid of a mesh
+#[derive(Clone, Debug)]
+pub struct Section {
+    /// Index into `Mesh::solids`, or `None` if the mesh has no solids
+    pub solid: Option<usize>,
+    /// Closed loops, without repeating the first point at the end.  Loops
+    /// may be wound either way; nesting decides which are holes.
+    pub loops: Vec<Vec<DVec3>>,
+    /// Number of chains which couldn't be closed into loops (e.g. because
+    /// the mesh has gaps), which are left out of `loops`
+    pub open: usize,
+}
+
+/// Cuts every solid in the mesh with the plane through `origin` with the
+/// given normal, returning one [`Section`] per solid which the plane
+/// crosses.  Meshes without solid information are cut as a single solid.
+pub fn section(mesh: &Mesh, origin: DVec3, normal: DVec3) -> Vec<Section> {
+    let normal = normal.normalize();
+    let ranges: Vec<(Option<usize>, std::ops::Range<usize>)> = if mesh.solids.is_empty() {
+        vec![(None, 0..mesh.triangles.len())]
+    } else {
+        mesh.solids.iter().enumerate()
+            .map(|(i, s)| (Some(i), s.triangles.clone()))
+            .collect()
+    };
+    ranges.into_iter()
+        .filter_map(|(solid, range)| {
+            let segments = cut_triangles(mesh, range, origin, normal);
+            if segments.is_empty() {
+                return None;
+            }
+            let (loops, open) = chain(&segments);
+            Some(Section { solid, loops, open })
+        })
+        .collect()
+}
+
+/// Intersects each triangle with the plane, returning a segment for every
+/// triangle which straddles it
+fn cut_triangles(mesh: &Mesh, range: std::ops::Range<usize>,
+                 origin: DVec3, normal: DVec3) -> Vec<(DVec3, DVec3)> {
+    let mut out = Vec::new();
+    for t in &mesh.triangles[range] {
+        let p = [0, 1, 2].map(|i| mesh.verts[t.verts[i] as usize].pos);
+        // Points exactly on the plane count as being above it, so that each
+        // triangle crosses it zero or two times
+        let d = p.map(|p| (p - origin).dot(&normal));
+        let above = d.map(|d| d >= 0.0);
+        let mut pts = Vec::with_capacity(2);
+        for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+            if above[i] != above[j] {
+                pts.push(crossing((p[i], d[i]), (p[j], d[j])));
+            }
+        }
+        if let [a, b] = pts[..] {
+            if a != b {
+                out.push((a, b));
+            }
+        }
+    }
+    out
+}
+
+/// Finds where the edge between two points crosses the plane, given their
+/// signed distances from it.  The endpoints are sorted first, so that
+/// triangles which share an edge (in either direction) get bit-identical
+/// points, which are then chained together exactly.
+fn crossing(a: (DVec3, f64), b: (DVec3, f64)) -> DVec3 {
+    let key = |p: DVec3| (p.x, p.y, p.z);
+    let (a, b) = if key(a.0) <= key(b.0) { (a, b) } else { (b, a) };
+    let t = a.1 / (a.1 - b.1);
+    a.0 + (b.0 - a.0) * t
+}
+
+/// Joins segments which share endpoints into chains, returning the closed
+/// loops and the number of chains which stayed open
+fn chain(segments: &[(DVec3, DVec3)]) -> (Vec<Vec<DVec3>>, usize) {
+    let key = |p: &DVec3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+    let mut ends: HashMap<[u64; 3], Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        ends.entry(key(a)).or_default().push(i);
+        ends.entry(key(b)).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut chains: Vec<Vec<DVec3>> = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut pts = vec![segments[start].0, segments[start].1];
+        // Walk forward from the end, then backward from the start
+        for _ in 0..2 {
+            loop {
+                let last = *pts.last().unwrap();
+                let next = ends[&key(&last)].iter().copied().find(|&i| !used[i]);
+                match next {
+                    Some(i) => {
+                        used[i] = true;
+                        let (a, b) = segments[i];
+                        pts.push(if a == last { b } else { a });
+                    },
+                    None => break,
+                }
+            }
+            pts.reverse();
+        }
+        chains.push(pts);
+    }
+
+    // Chains which end where they start are loops; the rest may be joined
+    // across small gaps (e.g. between faces which weren't sewn together)
+    let mut lo = DVec3::repeat(f64::INFINITY);
+    let mut hi = DVec3::repeat(-f64::INFINITY);
+    for (a, b) in segments {
+        lo = lo.inf(a).inf(b);
+        hi = hi.sup(a).sup(b);
+    }
+    let tol = (hi - lo).norm() * JOIN_TOLERANCE;
+    let mut loops = Vec::new();
+    let mut open: Vec<Vec<DVec3>> = Vec::new();
+    let mut stuck = 0;
+    for c in chains {
+        if c.len() > 3 && c[0] == *c.last().unwrap() {
+            loops.push(c);
+        } else {
+            open.push(c);
+        }
+    }
+    'outer: while let Some(mut c) = open.pop() {
+        // Extend this chain until it closes or there's nothing nearby
+        loop {
+            let end = *c.last().unwrap();
+            if c.len() > 3 && (end - c[0]).norm() <= tol {
+                loops.push(c);
+                continue 'outer;
+            }
+            let near = open.iter().enumerate()
+                .flat_map(|(i, o)| [(i, false, o[0]), (i, true, *o.last().unwrap())])
+                .map(|(i, rev, p)| (i, rev, (p - end).norm()))
+                .filter(|(_, _, d)| *d <= tol)
+                .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+            match near {
+                Some((i, rev, _)) => {
+                    let mut o = open.swap_remove(i);
+                    if rev {
+                        o.reverse();
+                    }
+                    c.extend(o.into_iter().skip(1));
+                },
+                None => {
+                    stuck += 1;
+                    continue 'outer;
+                },
+            }
+        }
+    }
+    for l in loops.iter_mut() {
+        l.pop();
+    }
+    (loops, stuck)
+}
+
+impl Section {
+    /// Fills the area enclosed by the loops (with nested loops as holes),
+    /// returning the loop points in order and triangles indexing them.
+    /// Returns `None` if the loops couldn't be triangulated (e.g. because
+    /// they cross each other).
+    pub fn cap(&self, normal: DVec3) -> Option<(Vec<DVec3>, Vec<[usize; 3]>)> {
+        let (u, v) = plane_basis(normal);
+        let pts: Vec<DVec3> = self.loops.iter().flatten().copied().collect();
+        let flat: Vec<(f64, f64)> = pts.iter().map(|p| (p.dot(&u), p.dot(&v))).collect();
+        let mut edges = Vec::new();
+        let mut start = 0;
+        for l in &self.loops {
+            edges.extend((0..l.len()).map(|i| (start + i, start + (i + 1) % l.len())));
+            start += l.len();
+        }
+        // The triangulator can panic on degenerate input, which shouldn't
+        // take down the caller
+        let tris = std::panic::catch_unwind(|| cdt::triangulate_with_edges(&flat, &edges))
+            .ok()?
+            .ok()?;
+        Some((pts, tris.into_iter().map(|(a, b, c)| [a, b, c]).collect()))
+    }
+
+    /// Returns the total enclosed area, counting holes as negative
+    pub fn area(&self, normal: DVec3) -> f64 {
+        // Each loop's sign depends on its winding, so use its nesting depth
+        // instead
+        let (u, v) = plane_basis(normal);
+        let mut flat: Vec<Vec<(f64, f64)>> = self.loops.iter()
+            .map(|l| l.iter().map(|p| (p.dot(&u), p.dot(&v))).collect())
+            .collect();
+        cdt::orient_rings(&mut flat).iter()
+            .map(|i| if i.depth.is_multiple_of(2) { i.area.abs() } else { -i.area.abs() })
+            .sum()
+    }
+}
+
+/// Returns two unit vectors which span the plane with the given normal,
+/// forming a right-handed frame with it.  Axis-aligned planes get the other
+/// two axes in order (e.g. X and Y for a Z normal, Y and Z for an X normal),
+/// so that exported drawings come out in the expected orientation.
+pub fn plane_basis(normal: DVec3) -> (DVec3, DVec3) {
+    let n = normal.normalize();
+    let a = if n.z.abs() < 0.9 { DVec3::z() } else { DVec3::y() };
+    let u = a.cross(&n).normalize();
+    (u, n.cross(&u))
+}
+
+/// 2D loops for each section, as projected onto the plane
+type Flat = Vec<Vec<Vec<(f64, f64)>>>;
+
+/// Projects every loop onto the plane, returning 2D loops grouped by
+/// section along with their bounds
+fn flatten(sections: &[Section], normal: DVec3) -> (Flat, (f64, f64), (f64, f64)) {
+    let (u, v) = plane_basis(normal);
+    let mut lo = (f64::INFINITY, f64::INFINITY);
+    let mut hi = (-f64::INFINITY, -f64::INFINITY);
+    let flat = sections.iter()
+        .map(|s| s.loops.iter()
+            .map(|l| l.iter()
+                .map(|p| {
+                    let q = (p.dot(&u), p.dot(&v));
+                    lo = (lo.0.min(q.0), lo.1.min(q.1));
+                    hi = (hi.0.max(q.0), hi.1.max(q.1));
+                    q
+                })
+                .collect())
+            .collect())
+        .collect::<Flat>();
+    if lo.0 > hi.0 {
+        // Nothing was cut, so fall back to empty bounds at the origin
+        return (flat, (0.0, 0.0), (0.0, 0.0));
+    }
+    (flat, lo, hi)
+}
+
+/// Saves section loops as an SVG drawing, looking down onto the plane (so
+/// that the normal points towards the viewer).  If the length of a model
+/// unit is known (in metres), the drawing is sized to print at 1:1.
+pub fn save_svg(sections: &[Section], normal: DVec3, length_unit: Option<f64>,
+                filename: &str) -> std::io::Result<()>
+{
+    let (flat, lo, hi) = flatten(sections, normal);
+    let (lo, hi) = svg::page(lo, hi);
+    let mut out = Svg::create(filename, lo, hi, length_unit)?;
+    for (s, loops) in sections.iter().zip(&flat) {
+        if loops.is_empty() {
+            continue;
+        }
+        let id = s.solid.map(|i| format!("id=\"solid_{}\" ", i)).unwrap_or_default();
+        let attrs = format!("{}fill=\"none\" stroke=\"black\" stroke-width=\"{}\"",
+                            id, out.stroke_width());
+        out.path(&attrs, loops, true)?;
+    }
+    out.finish()
+}
+
+/// Saves section loops as closed polylines in an ASCII DXF drawing, with
+/// the plane mapped onto the drawing's XY plane as in [`save_svg`].  Each
+/// solid gets its own layer.
+pub fn save_dxf(sections: &[Section], normal: DVec3, length_unit: Option<f64>,
+                filename: &str) -> std::io::Result<()>
+{
+    use std::io::Write;
+    let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+    let (flat, _, _) = flatten(sections, normal);
+    // $INSUNITS codes for the units which DXF knows about
+    let units = length_unit.and_then(|u| [(1e-3, 4), (1e-2, 5), (1.0, 6), (0.0254, 1)]
+        .iter()
+        .find(|(m, _)| ((u - m) / m).abs() < 1e-9)
+        .map(|(_, code)| *code));
+    writeln!(out, "0\nSECTION\n2\nHEADER")?;
+    if let Some(code) = units {
+        writeln!(out, "9\n$INSUNITS\n70\n{}", code)?;
+    }
+    writeln!(out, "0\nENDSEC\n0\nSECTION\n2\nENTITIES")?;
+    for (s, loops) in sections.iter().zip(&flat) {
+        let layer = match s.solid {
+            Some(i) => format!("SOLID_{}", i),
+            None => "0".to_owned(),
+        };
+        for l in loops {
+            // Flag 1 marks the polyline as closed
+            writeln!(out, "0\nPOLYLINE\n8\n{}\n66\n1\n70\n1", layer)?;
+            for p in l {
+                writeln!(out, "0\nVERTEX\n8\n{}\n10\n{}\n20\n{}\n30\n0", layer, p.0, p.1)?;
+            }
+            writeln!(out, "0\nSEQEND\n8\n{}", layer)?;
+        }
+    }
+    writeln!(out, "0\nENDSEC\n0\nEOF")?;
+    out.flush()
+}
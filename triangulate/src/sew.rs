@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use nalgebra_glm::{DVec3, U32Vec3};
+
+use crate::mesh::{Mesh, Triangle, Vertex};
+
+/// Maximum number of passes when splitting edges at T-junctions, since each
+/// pass only splits one edge per triangle
+const MAX_PASSES: usize = 8;
+
+type Cell = (i64, i64, i64);
+
+fn cell(p: &DVec3, size: f64) -> Cell {
+    let c = (p / size).map(f64::floor);
+    (c.x as i64, c.y as i64, c.z as i64)
+}
+
+/// Moves each point which is within `tol` of an earlier point onto it,
+/// returning the number of points which moved
+pub(crate) fn merge_points<'a, I>(pts: I, tol: f64) -> usize
+    where I: Iterator<Item=&'a mut DVec3>
+{
+    if tol <= 0.0 {
+        return 0;
+    }
+    let mut grid: HashMap<Cell, Vec<DVec3>> = HashMap::new();
+    let mut moved = 0;
+    for p in pts {
+        let (x, y, z) = cell(p, tol);
+        let found = (-1..=1)
+            .flat_map(|i| (-1..=1).flat_map(move |j| (-1..=1).map(move |k| (i, j, k))))
+            .filter_map(|(i, j, k)| grid.get(&(x + i, y + j, z + k)))
+            .flatten()
+            .find(|q| (*q - *p).norm() <= tol)
+            .copied();
+        match found {
+            Some(q) => if q != *p {
+                *p = q;
+                moved += 1;
+            },
+            None => grid.entry((x, y, z)).or_default().push(*p),
+        }
+    }
+    moved
+}
+
+/// Key which identifies vertices (from any face) at the same position
+fn key(p: &DVec3) -> [u64; 3] {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+/// A triangle edge which isn't shared with any other triangle, as the
+/// triangle's index and the edge's index within it (from vertex `i` to
+/// vertex `i + 1`)
+#[derive(Copy, Clone)]
+struct BoundaryEdge {
+    tri: usize,
+    edge: usize,
+}
+
+/// Finds edges in the given triangles which aren't shared by another
+/// triangle.  Faces don't share vertices, so edges are matched by position.
+fn boundary_edges(mesh: &Mesh, triangles: Range<usize>) -> Vec<BoundaryEdge> {
+    let mut edges: HashMap<([u64; 3], [u64; 3]), (usize, BoundaryEdge)> =
+        HashMap::new();
+    for tri in triangles {
+        let t = mesh.triangles[tri].verts;
+        for edge in 0..3 {
+            let a = key(&mesh.verts[t[edge] as usize].pos);
+            let b = key(&mesh.verts[t[(edge + 1) % 3] as usize].pos);
+            if a == b {
+                continue;
+            }
+            edges.entry((a.min(b), a.max(b)))
+                .or_insert((0, BoundaryEdge { tri, edge }))
+                .0 += 1;
+        }
+    }
+    let mut out: Vec<_> = edges.into_values()
+        .filter(|(n, _)| *n == 1)
+        .map(|(_, e)| e)
+        .collect();
+    out.sort_by_key(|e| (e.tri, e.edge));
+    out
+}
+
+/// Stitches together the boundaries of neighboring faces within a range of
+/// triangles (typically one solid), closing gaps of up to `tol` which are
+/// left by imprecise geometry.  Boundary vertices within `tol` of each other
+/// are moved together, then boundary edges which pass within `tol` of
+/// another face's boundary vertex are split there, so that both sides of
+/// each seam have matching vertices.  Triangles which collapse in the
+/// process are removed.
+///
+/// Face and solid triangle ranges are updated to match.  Returns the number
+/// of vertices which were moved or inserted.
+pub fn sew(mesh: &mut Mesh, triangles: Range<usize>, tol: f64) -> usize {
+    if tol <= 0.0 || triangles.is_empty() {
+        return 0;
+    }
+
+    // Close gaps between boundary vertices
+    let mut boundary: Vec<u32> = boundary_edges(mesh, triangles.clone())
+        .iter()
+        .flat_map(|e| {
+            let t = mesh.triangles[e.tri].verts;
+            [t[e.edge], t[(e.edge + 1) % 3]]
+        })
+        .collect();
+    boundary.sort_unstable();
+    boundary.dedup();
+    let mut pts: Vec<DVec3> = boundary.iter()
+        .map(|v| mesh.verts[*v as usize].pos)
+        .collect();
+    let mut count = merge_points(pts.iter_mut(), tol);
+    for (v, p) in boundary.iter().zip(&pts) {
+        mesh.verts[*v as usize].pos = *p;
+    }
+
+    // Split boundary edges at T-junctions, then remove degenerate triangles
+    let mut end = triangles.end;
+    for _ in 0..MAX_PASSES {
+        let (inserted, new_end) = split_edges(mesh, triangles.start..end, tol);
+        count += inserted;
+        end = new_end;
+        if inserted == 0 {
+            break;
+        }
+    }
+    let degenerate = |t: &Triangle| {
+        let p = |i: usize| key(&mesh.verts[t.verts[i] as usize].pos);
+        p(0) == p(1) || p(1) == p(2) || p(2) == p(0)
+    };
+    let keep: Vec<Vec<Triangle>> = mesh.triangles[triangles.start..end].iter()
+        .map(|t| if degenerate(t) { vec![] } else { vec![*t] })
+        .collect();
+    replace(mesh, triangles.start, keep);
+    count
+}
+
+/// Splits each triangle in the range which has a boundary edge passing
+/// within `tol` of boundary vertices from other faces, so that the edge
+/// passes through those vertices.  Returns the number of vertices inserted
+/// and the new end of the range.
+fn split_edges(mesh: &mut Mesh, triangles: Range<usize>, tol: f64) -> (usize, usize) {
+    let edges = boundary_edges(mesh, triangles.clone());
+    if edges.is_empty() {
+        return (0, triangles.end);
+    }
+    let ends = |e: &BoundaryEdge| {
+        let t = mesh.triangles[e.tri].verts;
+        (mesh.verts[t[e.edge] as usize].pos,
+         mesh.verts[t[(e.edge + 1) % 3] as usize].pos)
+    };
+
+    // Bin boundary vertex positions into a grid with cells on the scale of
+    // a typical edge, so that each edge only checks nearby vertices
+    let size = edges.iter()
+        .map(|e| { let (a, b) = ends(e); (b - a).norm() })
+        .sum::<f64>() / edges.len() as f64;
+    let size = size.max(tol);
+    let mut grid: HashMap<Cell, Vec<DVec3>> = HashMap::new();
+    for e in &edges {
+        let (a, _) = ends(e);
+        let c = grid.entry(cell(&a, size)).or_default();
+        if !c.contains(&a) {
+            c.push(a);
+        }
+    }
+
+    // Find the points to insert along each triangle's boundary edges, using
+    // at most one edge per triangle in each pass
+    let mut splits: HashMap<usize, (usize, Vec<(f64, DVec3)>)> = HashMap::new();
+    for e in &edges {
+        if splits.contains_key(&e.tri) {
+            continue;
+        }
+        let (a, b) = ends(e);
+        let d = b - a;
+        let len = d.norm();
+        let lo = cell(&a.inf(&b).add_scalar(-tol), size);
+        let hi = cell(&a.sup(&b).add_scalar(tol), size);
+        let mut found = Vec::new();
+        for x in lo.0..=hi.0 {
+            for y in lo.1..=hi.1 {
+                for z in lo.2..=hi.2 {
+                    for p in grid.get(&(x, y, z)).into_iter().flatten() {
+                        let t = (p - a).dot(&d) / (len * len);
+                        let dist = (a + d * t - p).norm();
+                        if dist <= tol && t * len > tol && (1.0 - t) * len > tol {
+                            found.push((t, *p));
+                        }
+                    }
+                }
+            }
+        }
+        if !found.is_empty() {
+            found.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            splits.insert(e.tri, (e.edge, found));
+        }
+    }
+    if splits.is_empty() {
+        return (0, triangles.end);
+    }
+
+    // Replace each split triangle with a fan around its opposite vertex
+    let mut inserted = 0;
+    let mut out = Vec::with_capacity(triangles.len() + splits.len());
+    for tri in triangles.clone() {
+        let t = mesh.triangles[tri].verts;
+        let (edge, pts) = match splits.get(&tri) {
+            Some(s) => s,
+            None => {
+                out.push(vec![mesh.triangles[tri]]);
+                continue;
+            },
+        };
+        let (a, b, c) = (t[*edge], t[(*edge + 1) % 3], t[(*edge + 2) % 3]);
+        let (va, vb) = (mesh.verts[a as usize], mesh.verts[b as usize]);
+        let mut chain = vec![a];
+        for (s, p) in pts {
+            chain.push(mesh.verts.len() as u32);
+            mesh.verts.push(Vertex {
+                pos: *p,
+                norm: va.norm.lerp(&vb.norm, *s).normalize(),
+                color: va.color.lerp(&vb.color, *s),
+                uv: va.uv.lerp(&vb.uv, *s),
+            });
+            inserted += 1;
+        }
+        chain.push(b);
+        out.push(chain.windows(2)
+            .map(|w| Triangle { verts: U32Vec3::new(w[0], w[1], c) })
+            .collect());
+    }
+    let end = triangles.start + out.iter().map(Vec::len).sum::<usize>();
+    replace(mesh, triangles.start, out);
+    (inserted, end)
+}
+
+/// Replaces the triangles starting at `start`, with each one replaced by
+/// its entry in `tris` (which may have any length), and updates the face
+/// and solid ranges to match
+fn replace(mesh: &mut Mesh, start: usize, tris: Vec<Vec<Triangle>>) {
+    let end = start + tris.len();
+    // Map from old triangle indices to new ones (including one past the end)
+    let mut remap = Vec::with_capacity(mesh.triangles.len() + 1);
+    remap.extend(0..start);
+    let mut i = start;
+    for t in &tris {
+        remap.push(i);
+        i += t.len();
+    }
+    let shift = i as isize - end as isize;
+    remap.extend((end..=mesh.triangles.len())
+        .map(|j| (j as isize + shift) as usize));
+
+    mesh.triangles.splice(start..end, tris.into_iter().flatten());
+    for f in &mut mesh.faces {
+        f.triangles = remap[f.triangles.start]..remap[f.triangles.end];
+    }
+    for s in &mut mesh.solids {
+        s.triangles = remap[s.triangles.start]..remap[s.triangles.end];
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{Face, SurfaceType};
+    use nalgebra_glm::{DMat4, DVec2};
+
+    /// Builds a face from a list of points and triangles
+    fn push_face(mesh: &mut Mesh, pts: &[(f64, f64)], tris: &[(u32, u32, u32)]) {
+        let base = mesh.verts.len() as u32;
+        for &(x, y) in pts {
+            mesh.verts.push(Vertex {
+                pos: DVec3::new(x, y, 0.0), norm: DVec3::z(),
+                color: DVec3::zeros(), uv: DVec2::zeros(),
+            });
+        }
+        let start = mesh.triangles.len();
+        for &(a, b, c) in tris {
+            mesh.triangles.push(Triangle {
+                verts: U32Vec3::new(a + base, b + base, c + base),
+            });
+        }
+        mesh.faces.push(Face {
+            id: mesh.faces.len(), triangles: start..mesh.triangles.len(),
+            surface: SurfaceType::Plane, dimensions: None, geometry: None,
+            transform: DMat4::identity(), failed: false, grid: None,
+        });
+    }
+
+    #[test]
+    fn closes_gaps() {
+        // Two unit squares, where the second is shifted slightly away from
+        // the first and has an extra vertex along their shared edge
+        let mut mesh = Mesh::default();
+        push_face(&mut mesh, &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+                  &[(0, 1, 2), (0, 2, 3)]);
+        push_face(&mut mesh, &[(1.001, 0.0), (2.0, 0.0), (2.0, 1.0),
+                               (1.001, 1.0), (1.001, 0.5)],
+                  &[(0, 1, 4), (4, 1, 2), (4, 2, 3)]);
+        let n = mesh.triangles.len();
+        assert_eq!(boundary_edges(&mesh, 0..n).len(), 9);
+
+        // Too small a tolerance does nothing
+        assert_eq!(sew(&mut mesh, 0..n, 1e-4), 0);
+
+        // Two vertices are moved, and one is inserted into the first square
+        assert_eq!(sew(&mut mesh, 0..n, 0.01), 3);
+        let n = mesh.triangles.len();
+        assert_eq!(n, 6);
+        assert_eq!(boundary_edges(&mesh, 0..n).len(), 6);
+        assert_eq!(mesh.faces[0].triangles, 0..3);
+        assert_eq!(mesh.faces[1].triangles, 3..6);
+    }
+}
@@ -0,0 +1,197 @@
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use cdt::{BooleanOp, orient_rings, polygon_boolean, signed_area};
+
+use crate::{mesh::Mesh, section::{plane_basis, section}};
+
+/// A point in a layer's plane, in the frame given by [`plane_basis`]
+pub type Point = (f64, f64);
+
+/// One filled region of a layer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon {
+    /// Outer boundary, wound counter-clockwise when looking down onto the
+    /// plane (so that the normal points towards the viewer)
+    pub outer: Vec<Point>,
+    /// Holes directly inside the outer boundary, wound clockwise.  Islands
+    /// inside a hole are separate polygons.
+    pub holes: Vec<Vec<Point>>,
+}
+
+/// Where one plane of a stack cuts through the model
+#[derive(Clone, Debug)]
+pub struct Layer {
+    /// Distance of the plane from the origin along the normal
+    pub height: f64,
+    /// Filled regions, which don't overlap each other
+    pub polygons: Vec<Polygon>,
+    /// Number of chains which couldn't be closed into loops (e.g. because
+    /// the mesh has gaps), which are left out of `polygons`
+    pub open: usize,
+}
+
+impl Polygon {
+    /// Returns the filled area, i.e. the outer boundary's area minus the
+    /// holes
+    pub fn area(&self) -> f64 {
+        signed_area(&self.outer)
+            + self.holes.iter().map(|h| signed_area(h)).sum::<f64>()
+    }
+}
+
+impl Layer {
+    /// Returns the total filled area of the layer
+    pub fn area(&self) -> f64 {
+        self.polygons.iter().map(|p| p.area()).sum()
+    }
+}
+
+/// Returns the heights of the planes which cut the model into layers of
+/// the given thickness along the normal, starting from the model's lowest
+/// point.  Each plane is in the middle of its layer, so that it doesn't
+/// graze flat faces at the model's top and bottom.
+pub fn layer_heights(mesh: &Mesh, normal: DVec3, thickness: f64) -> Vec<f64> {
+    assert!(thickness > 0.0, "Layer thickness must be positive");
+    let n = normal.normalize();
+    let (lo, hi) = mesh.triangles.iter()
+        .flat_map(|t| t.verts.iter().map(|&i| mesh.verts[i as usize].pos.dot(&n)))
+        .fold((f64::INFINITY, -f64::INFINITY), |(lo, hi), d| (lo.min(d), hi.max(d)));
+    if lo > hi {
+        return vec![];
+    }
+    let count = ((hi - lo) / thickness).ceil().max(1.0) as usize;
+    (0..count).map(|i| lo + (i as f64 + 0.5) * thickness).collect()
+}
+
+/// Cuts the model with a stack of parallel planes (with the given normal,
+/// at the given heights along it), returning one [`Layer`] per plane.
+///
+/// The solids must be closed.  Each solid's loops are nested to decide
+/// which are holes, then overlapping solids are merged, so each layer is a
+/// set of separate polygons ready for e.g. generating toolpaths.
+pub fn slice(mesh: &Mesh, normal: DVec3, heights: &[f64]) -> Vec<Layer> {
+    let normal = normal.normalize();
+    #[cfg(feature = "rayon")]
+    { heights.par_iter().map(|h| slice_layer(mesh, normal, *h)).collect() }
+    #[cfg(not(feature = "rayon"))]
+    { heights.iter().map(|h| slice_layer(mesh, normal, *h)).collect() }
+}
+
+/// Cuts the model with a single plane
+fn slice_layer(mesh: &Mesh, normal: DVec3, height: f64) -> Layer {
+    let (u, v) = plane_basis(normal);
+    let sections = section(mesh, normal * height, normal);
+    let open = sections.iter().map(|s| s.open).sum();
+
+    // Loops may be wound either way, so orient each solid's loops by their
+    // nesting first; then the union (by the non-zero rule) fills every
+    // solid and merges those which overlap.
+    let mut rings: Vec<Vec<Point>> = Vec::new();
+    for s in &sections {
+        let mut flat: Vec<Vec<Point>> = s.loops.iter()
+            .map(|l| l.iter().map(|p| (p.dot(&u), p.dot(&v))).collect())
+            .collect();
+        orient_rings(&mut flat);
+        rings.extend(flat);
+    }
+    if rings.is_empty() {
+        return Layer { height, polygons: vec![], open };
+    }
+    // The triangulator can panic on degenerate input, in which case the
+    // oriented loops are used as-is (which is only wrong where solids
+    // overlap)
+    let empty: &[Vec<Point>] = &[];
+    let merged = std::panic::catch_unwind(||
+            polygon_boolean(&rings, empty, BooleanOp::Union))
+        .ok()
+        .and_then(|r| r.ok());
+    let mut rings: Vec<Vec<Point>> = merged.unwrap_or(rings).into_iter()
+        .filter(|r| r.len() >= 3 && signed_area(r) != 0.0)
+        .collect();
+
+    // Rings at even depths are outer boundaries, and each hole belongs to
+    // the innermost ring around it
+    let info = orient_rings(&mut rings);
+    let mut index = vec![None; rings.len()];
+    let mut polygons: Vec<Polygon> = Vec::new();
+    for (i, r) in rings.iter().enumerate() {
+        if info[i].depth.is_multiple_of(2) {
+            index[i] = Some(polygons.len());
+            polygons.push(Polygon { outer: r.clone(), holes: vec![] });
+        }
+    }
+    for (r, info) in rings.into_iter().zip(&info) {
+        if !info.depth.is_multiple_of(2) {
+            if let Some(p) = info.parent.and_then(|p| index[p]) {
+                polygons[p].holes.push(r);
+            }
+        }
+    }
+    Layer { height, polygons, open }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cuboid, end_solid};
+
+    #[test]
+    fn nested_solids() {
+        // A hollow box, with a smaller box floating in its cavity
+        let mut mesh = Mesh::default();
+        cuboid(&mut mesh, DVec3::zeros(), DVec3::repeat(10.0), false);
+        cuboid(&mut mesh, DVec3::repeat(2.0), DVec3::repeat(8.0), true);
+        end_solid(&mut mesh);
+        cuboid(&mut mesh, DVec3::repeat(4.0), DVec3::repeat(6.0), false);
+        end_solid(&mut mesh);
+
+        let heights = layer_heights(&mesh, DVec3::z(), 1.0);
+        assert_eq!(heights, (0..10).map(|i| i as f64 + 0.5).collect::<Vec<_>>());
+        let layers = slice(&mesh, DVec3::z(), &heights);
+        for layer in &layers {
+            assert_eq!(layer.open, 0);
+            let z = layer.height;
+            let (count, area) = if !(2.0..8.0).contains(&z) {
+                (1, 100.0)
+            } else if !(4.0..6.0).contains(&z) {
+                (1, 64.0)
+            } else {
+                (2, 68.0)
+            };
+            assert_eq!(layer.polygons.len(), count, "at {}", z);
+            assert!((layer.area() - area).abs() < 1e-9, "{} at {}", layer.area(), z);
+            for p in &layer.polygons {
+                assert!(signed_area(&p.outer) > 0.0);
+                assert!(p.holes.iter().all(|h| signed_area(h) < 0.0));
+            }
+        }
+
+        // In the middle, the shell has one hole and the island has none
+        let mut mid = layers[5].polygons.clone();
+        mid.sort_by(|a, b| b.area().partial_cmp(&a.area()).unwrap());
+        assert_eq!(mid[0].holes.len(), 1);
+        assert!((mid[0].area() - 64.0).abs() < 1e-9);
+        assert!(mid[1].holes.is_empty());
+        assert!((mid[1].area() - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn overlapping_solids() {
+        // Two boxes which overlap by half, which are merged into one outline
+        let mut mesh = Mesh::default();
+        cuboid(&mut mesh, DVec3::zeros(), DVec3::new(2.0, 1.0, 1.0), false);
+        end_solid(&mut mesh);
+        cuboid(&mut mesh, DVec3::new(1.0, 0.0, 0.0), DVec3::new(3.0, 1.0, 1.0), false);
+        end_solid(&mut mesh);
+
+        let layers = slice(&mesh, DVec3::z(), &[0.5]);
+        assert_eq!(layers[0].polygons.len(), 1);
+        assert!(layers[0].polygons[0].holes.is_empty());
+        assert!((layers[0].area() - 3.0).abs() < 1e-9, "{}", layers[0].area());
+    }
+}
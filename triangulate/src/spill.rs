@@ -0,0 +1,417 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::Mmap;
+use nalgebra_glm::{DVec2, DVec3, U32Vec3};
+
+use crate::{mesh::{facet_normal, AssemblyNode, Face, Mesh, Solid, Triangle, Vertex}, ply};
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Compact vertex record, as stored on disk
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct PackedVertex {
+    pub pos: [f32; 3],
+    pub norm: [f32; 3],
+    pub uv: [f32; 2],
+    /// Linear RGB color, with the last byte unused
+    pub color: [u8; 4],
+}
+
+impl PackedVertex {
+    fn new(v: &Vertex) -> Self {
+        let f = |v: DVec3| [v.x as f32, v.y as f32, v.z as f32];
+        let c = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Self {
+            pos: f(v.pos),
+            norm: f(v.norm),
+            uv: [v.uv.x as f32, v.uv.y as f32],
+            color: [c(v.color.x), c(v.color.y), c(v.color.z), 255],
+        }
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        for f in self.pos.iter().chain(&self.norm).chain(&self.uv) {
+            out.write_all(&f.to_ne_bytes())?;
+        }
+        out.write_all(&self.color)
+    }
+
+    pub fn to_vertex(&self) -> Vertex {
+        let f = |v: [f32; 3]| DVec3::new(v[0] as f64, v[1] as f64, v[2] as f64);
+        Vertex {
+            pos: f(self.pos),
+            norm: f(self.norm),
+            color: DVec3::new(self.color[0] as f64, self.color[1] as f64,
+                              self.color[2] as f64) / 255.0,
+            uv: DVec2::new(self.uv[0] as f64, self.uv[1] as f64),
+        }
+    }
+}
+
+/// A file in the temporary directory, which is deleted when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn create(kind: &str) -> std::io::Result<(Self, File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "foxtrot-{}-{}.{}", std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed), kind));
+        let file = std::fs::OpenOptions::new()
+            .read(true).write(true).create_new(true)
+            .open(&path)?;
+        Ok((Self(path), file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A read-only memory map of a temporary file.  Empty files can't be
+/// mapped, so they're represented with `None`.
+struct Buffer {
+    map: Option<Mmap>,
+    _file: TempFile,
+}
+
+impl Buffer {
+    fn new(file: TempFile, out: BufWriter<File>) -> std::io::Result<Self> {
+        let f = out.into_inner().map_err(|e| e.into_error())?;
+        let map = if f.metadata()?.len() == 0 {
+            None
+        } else {
+            // Safety: the file is private to this process and is no longer
+            // written after this point
+            Some(unsafe { Mmap::map(&f)? })
+        };
+        Ok(Self { map, _file: file })
+    }
+
+    /// Reinterprets the buffer as a slice of `T`
+    ///
+    /// # Safety
+    /// The buffer must hold a whole number of valid `T` records
+    unsafe fn cast<T>(&self) -> &[T] {
+        match &self.map {
+            // Memory maps are page-aligned, so this is aligned for any `T`
+            Some(m) => std::slice::from_raw_parts(
+                m.as_ptr() as *const T, m.len() / std::mem::size_of::<T>()),
+            None => &[],
+        }
+    }
+}
+
+/// Accumulates meshes in memory, moving their vertices and triangles into
+/// temporary files whenever they exceed a memory budget
+pub struct MeshWriter {
+    max_bytes: usize,
+    pending: Mesh,
+
+    verts: (TempFile, BufWriter<File>),
+    triangles: (TempFile, BufWriter<File>),
+    vert_count: usize,
+    triangle_count: usize,
+    faces: Vec<Face>,
+    solids: Vec<Solid>,
+    length_unit: Option<f64>,
+    assembly: Vec<AssemblyNode>,
+    annotations: Vec<Vec<DVec3>>,
+}
+
+/// Returns the approximate memory used by a mesh's vertices and triangles
+fn mesh_bytes(mesh: &Mesh) -> usize {
+    mesh.verts.len() * std::mem::size_of::<Vertex>() +
+        mesh.triangles.len() * std::mem::size_of::<Triangle>()
+}
+
+impl MeshWriter {
+    pub fn new(max_bytes: usize) -> std::io::Result<Self> {
+        let (vf, v) = TempFile::create("verts")?;
+        let (tf, t) = TempFile::create("tris")?;
+        Ok(Self {
+            max_bytes,
+            pending: Mesh::default(),
+            verts: (vf, BufWriter::new(v)),
+            triangles: (tf, BufWriter::new(t)),
+            vert_count: 0,
+            triangle_count: 0,
+            faces: Vec::new(),
+            solids: Vec::new(),
+            length_unit: None,
+            assembly: Vec::new(),
+            annotations: Vec::new(),
+        })
+    }
+
+    /// Appends a mesh, which may be written out immediately
+    pub fn push(&mut self, mesh: Mesh) -> std::io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending = Mesh::combine(pending, mesh);
+        if mesh_bytes(&self.pending) > self.max_bytes {
+            self.flush_()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the pending mesh to disk
+    fn flush_(&mut self) -> std::io::Result<()> {
+        let mesh = std::mem::take(&mut self.pending);
+        let dv: u32 = self.vert_count.try_into()
+            .map_err(|_| invalid("Too many vertices"))?;
+        let dt = self.triangle_count;
+        let ds = self.solids.len();
+        for v in &mesh.verts {
+            PackedVertex::new(v).write(&mut self.verts.1)?;
+        }
+        for t in &mesh.triangles {
+            for i in t.verts.add_scalar(dv).iter() {
+                self.triangles.1.write_all(&i.to_ne_bytes())?;
+            }
+        }
+        self.faces.extend(mesh.faces.into_iter()
+            .map(|f| Face {
+                triangles: (f.triangles.start + dt)..(f.triangles.end + dt),
+                ..f
+            }));
+        self.solids.extend(mesh.solids.into_iter()
+            .map(|s| Solid {
+                triangles: (s.triangles.start + dt)..(s.triangles.end + dt),
+                instance_of: s.instance_of.map(|i| i + ds),
+                ..s
+            }));
+        let dn = self.assembly.len();
+        self.assembly.extend(mesh.assembly.into_iter()
+            .map(|n| AssemblyNode {
+                parent: n.parent.map(|p| p + dn),
+                solids: n.solids.into_iter().map(|s| s + ds).collect(),
+                ..n
+            }));
+        self.annotations.extend(mesh.annotations);
+        self.length_unit = self.length_unit.or(mesh.length_unit);
+        self.vert_count += mesh.verts.len();
+        self.triangle_count += mesh.triangles.len();
+        Ok(())
+    }
+
+    /// Writes out any pending data and maps the files back into memory
+    pub fn finish(mut self) -> std::io::Result<SpilledMesh> {
+        self.flush_()?;
+        Ok(SpilledMesh {
+            verts: Buffer::new(self.verts.0, self.verts.1)?,
+            triangles: Buffer::new(self.triangles.0, self.triangles.1)?,
+            faces: self.faces,
+            solids: self.solids,
+            length_unit: self.length_unit,
+            assembly: self.assembly,
+            annotations: self.annotations,
+        })
+    }
+}
+
+/// A mesh whose vertices and triangles live in memory-mapped temporary files,
+/// so that the operating system can page them out as needed.  Faces,
+/// solids, and the other metadata are kept in memory, with the same meaning
+/// as in [`Mesh`].
+pub struct SpilledMesh {
+    verts: Buffer,
+    triangles: Buffer,
+    pub faces: Vec<Face>,
+    pub solids: Vec<Solid>,
+    pub length_unit: Option<f64>,
+    pub assembly: Vec<AssemblyNode>,
+    pub annotations: Vec<Vec<DVec3>>,
+}
+
+impl SpilledMesh {
+    pub fn verts(&self) -> &[PackedVertex] {
+        // Safety: the file was written as a sequence of `PackedVertex`
+        // records, which are `repr(C)` without padding
+        unsafe { self.verts.cast() }
+    }
+
+    /// Returns triangles, as indices into [`SpilledMesh::verts`]
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        // Safety: the file was written as triples of `u32`
+        unsafe { self.triangles.cast() }
+    }
+
+    /// Loads the whole mesh into memory
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh {
+            verts: self.verts().iter().map(PackedVertex::to_vertex).collect(),
+            triangles: self.triangles().iter()
+                .map(|t| Triangle { verts: U32Vec3::new(t[0], t[1], t[2]) })
+                .collect(),
+            faces: self.faces.clone(),
+            solids: self.solids.clone(),
+            length_unit: self.length_unit,
+            assembly: self.assembly.clone(),
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// Writes the mesh to a binary STL file, streaming from disk
+    pub fn save_stl(&self, filename: &str) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(filename)?);
+        out.write_all(&[b'x'; 80])?; // header
+        let n: u32 = self.triangles().len().try_into()
+            .map_err(|_| invalid("Too many triangles for STL"))?;
+        out.write_all(&n.to_le_bytes())?;
+        let verts = self.verts();
+        for t in self.triangles() {
+            let pos = t.map(|i| {
+                let p = verts[i as usize].pos;
+                DVec3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+            });
+            for c in facet_normal(pos[0], pos[1], pos[2]).iter() {
+                out.write_all(&c.to_le_bytes())?;
+            }
+            for i in t {
+                for c in &verts[*i as usize].pos {
+                    out.write_all(&c.to_le_bytes())?;
+                }
+            }
+            out.write_all(&[0; 2])?; // attributes
+        }
+        out.flush()
+    }
+
+    /// Writes the mesh to a binary PLY file, streaming from disk.  The
+    /// format matches [`Mesh::save_ply`].
+    pub fn save_ply(&self, filename: &str) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(filename)?);
+        ply::write_header(&mut out, &[("vertex", self.verts().len(), ply::MESH_VERTEX),
+                                      ("face", self.triangles().len(), ply::MESH_FACE)])?;
+        for v in self.verts() {
+            for c in &v.pos {
+                out.write_all(&c.to_le_bytes())?;
+            }
+            out.write_all(&v.color[..3])?;
+        }
+        let mut ids = self.faces.iter()
+            .flat_map(|f| f.triangles.clone().map(move |t| (t, f.id)))
+            .peekable();
+        for (i, t) in self.triangles().iter().enumerate() {
+            out.write_all(&[3])?;
+            for v in t {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            // Faces are sorted by triangle range, so we can walk through
+            // them alongside the triangles
+            while ids.peek().map(|(t, _)| *t < i).unwrap_or(false) {
+                ids.next();
+            }
+            let id = match ids.peek() {
+                Some((t, id)) if *t == i => Some(*id),
+                _ => None,
+            };
+            out.write_all(&ply::face_id(id)?.to_le_bytes())?;
+        }
+        out.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::DMat4;
+
+    fn square(offset: f64) -> Mesh {
+        let v = |x: f64, y: f64| Vertex {
+            pos: DVec3::new(x + offset, y, 0.0),
+            norm: DVec3::z(),
+            color: DVec3::new(1.0, 0.0, 0.0),
+            uv: DVec2::new(x, y),
+        };
+        Mesh {
+            verts: vec![v(0.0, 0.0), v(1.0, 0.0), v(1.0, 1.0), v(0.0, 1.0)],
+            triangles: vec![Triangle { verts: U32Vec3::new(0, 1, 2) },
+                            Triangle { verts: U32Vec3::new(0, 2, 3) }],
+            solids: vec![Solid {
+                id: 1, name: None, color: None, material: None, triangles: 0..2,
+                instance_of: None, transform: DMat4::identity(),
+            }],
+            faces: Vec::new(),
+            length_unit: None,
+            assembly: Vec::new(),
+            annotations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        // A zero budget forces every mesh to disk as soon as it arrives
+        let mut w = MeshWriter::new(0).unwrap();
+        for i in 0..3 {
+            w.push(square(i as f64 * 2.0)).unwrap();
+        }
+        w.push(Mesh {
+            length_unit: Some(0.001),
+            annotations: vec![vec![DVec3::zeros(), DVec3::x()]],
+            assembly: vec![AssemblyNode {
+                name: "part".to_owned(), parent: None,
+                transform: DMat4::identity(), solids: vec![0],
+            }],
+            ..Default::default()
+        }).unwrap();
+        let spilled = w.finish().unwrap();
+        let mut expected = Mesh::default();
+        for i in 0..3 {
+            expected = Mesh::combine(expected, square(i as f64 * 2.0));
+        }
+
+        let mesh = spilled.to_mesh();
+        assert_eq!(mesh.verts.len(), expected.verts.len());
+        for (a, b) in mesh.verts.iter().zip(&expected.verts) {
+            assert!((a.pos - b.pos).norm() < 1e-6);
+            assert!((a.color - b.color).norm() < 1e-6);
+        }
+        for (a, b) in mesh.triangles.iter().zip(&expected.triangles) {
+            assert_eq!(a.verts, b.verts);
+        }
+        let ranges: Vec<_> = spilled.solids.iter().map(|s| s.triangles.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 2..4, 4..6]);
+
+        // Metadata survives the trip through disk, with solid indices
+        // shifted past the meshes which came before it
+        assert_eq!(mesh.length_unit, Some(0.001));
+        assert_eq!(mesh.annotations, vec![vec![DVec3::zeros(), DVec3::x()]]);
+        assert_eq!(mesh.assembly.len(), 1);
+        assert_eq!(mesh.assembly[0].solids, vec![3]);
+    }
+
+    #[test]
+    fn stl_normals() {
+        let mut w = MeshWriter::new(0).unwrap();
+        w.push(square(0.0)).unwrap();
+        let spilled = w.finish().unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("foxtrot-spill-{}.stl", std::process::id()));
+        spilled.save_stl(path.to_str().unwrap()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Both triangles face up, and match the in-memory export
+        assert_eq!(data.len(), 84 + 50 * 2);
+        for t in data[84..].chunks_exact(50) {
+            let c = |i: usize| f32::from_le_bytes([t[i], t[i + 1], t[i + 2], t[i + 3]]);
+            assert_eq!([c(0), c(4), c(8)], [0.0, 0.0, 1.0]);
+        }
+        square(0.0).save_stl(path.to_str().unwrap()).unwrap();
+        let expected = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, expected);
+    }
+}
@@ -1,9 +1,22 @@
+use std::time::Duration;
+
 #[derive(Default)]
 pub struct Stats {
     pub num_shells: usize,
     pub num_faces: usize,
     pub num_errors: usize,
     pub num_panics: usize,
+    /// Number of vertices moved or inserted when sewing faces together
+    pub num_sewn: usize,
+    /// Number of entities which only describe 2D drawings or annotations
+    /// (draughting and PMI presentation), which were skipped
+    pub num_draughting: usize,
+
+    /// Time spent reading and parsing STEP files, which is only recorded
+    /// when loading from disk (see [`triangulate_file`](crate::external::triangulate_file))
+    pub parse_time: Duration,
+    /// Time spent triangulating parsed files, recorded alongside `parse_time`
+    pub triangulate_time: Duration,
 }
 
 impl Stats {
@@ -14,6 +27,10 @@ impl Stats {
         a.num_faces += b.num_faces;
         a.num_errors += b.num_errors;
         a.num_panics += b.num_panics;
+        a.num_sewn += b.num_sewn;
+        a.num_draughting += b.num_draughting;
+        a.parse_time += b.parse_time;
+        a.triangulate_time += b.triangulate_time;
         a
     }
 }
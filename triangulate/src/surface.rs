@@ -4,7 +4,10 @@ use nalgebra_glm as glm;
 use glm::{DVec2, DVec3, DVec4, DMat4};
 
 use nurbs::{AbstractSurface, NDBSplineSurface, SampledSurface};
-use crate::{Error, mesh::Vertex};
+use crate::{
+    Error, curve::segments_per_turn, mesh::{Dimensions, SurfaceType, Vertex},
+    triangulate::TessellationParams,
+};
 
 // Represents a surface in 3D space, with a function to project a 3D point
 // on the surface down to a 2D space.
@@ -26,6 +29,7 @@ pub enum Surface {
     Cone {
         mat: DMat4,
         mat_i: DMat4,
+        radius: f64,
         angle: f64,
     },
     BSpline(SampledSurface<3>),
@@ -47,6 +51,125 @@ pub enum Surface {
 }
 
 impl Surface {
+    pub fn surface_type(&self) -> SurfaceType {
+        match self {
+            Surface::Cylinder { .. } => SurfaceType::Cylinder,
+            Surface::Plane { .. } => SurfaceType::Plane,
+            Surface::Cone { .. } => SurfaceType::Cone,
+            Surface::BSpline(..) => SurfaceType::BSpline,
+            Surface::NURBS(..) => SurfaceType::NURBS,
+            Surface::Sphere { .. } => SurfaceType::Sphere,
+            Surface::Torus { .. } => SurfaceType::Torus,
+        }
+    }
+
+    /// Returns the analytic dimensions of the surface, if it has any
+    pub fn dimensions(&self) -> Option<Dimensions> {
+        match self {
+            Surface::Cylinder { radius, .. } =>
+                Some(Dimensions::Cylinder { radius: *radius }),
+            Surface::Cone { radius, angle, .. } =>
+                Some(Dimensions::Cone { radius: *radius, half_angle: *angle }),
+            Surface::Sphere { radius, .. } =>
+                Some(Dimensions::Sphere { radius: *radius }),
+            Surface::Torus { major_radius, minor_radius, .. } =>
+                Some(Dimensions::Torus { major_radius: *major_radius,
+                                         minor_radius: *minor_radius }),
+            Surface::Plane { .. } | Surface::BSpline(..) | Surface::NURBS(..) =>
+                None,
+        }
+    }
+
+    /// Returns the point on a plane which is closest to the origin, and the
+    /// plane's unit normal
+    pub fn plane(&self) -> Option<(DVec3, DVec3)> {
+        match self {
+            Surface::Plane { normal, .. } =>
+                Some((self.project(DVec3::zeros())?, normal.normalize())),
+            _ => None,
+        }
+    }
+
+    /// Returns a point on the axis of a cylinder or cone, and the axis's
+    /// unit direction
+    pub fn axis(&self) -> Option<(DVec3, DVec3)> {
+        match self {
+            Surface::Cylinder { location, axis, .. } =>
+                Some((*location, axis.normalize())),
+            Surface::Cone { mat, .. } =>
+                Some((mat.column(3).xyz(), mat.column(2).xyz().normalize())),
+            _ => None,
+        }
+    }
+
+    /// Returns a point's coordinates in the frame of a plane (local X and
+    /// Y), or of a cylinder or cone (the angle around the axis, in the range
+    /// ±π, and the height along it), so that the surface's straight lines
+    /// run along one coordinate.  Returns `None` for other surfaces.
+    pub(crate) fn grid_coords(&self, p: DVec3) -> Option<DVec2> {
+        let p = match self {
+            Surface::Plane { mat_i, .. } => return Some((mat_i * p.push(1.0)).xy()),
+            Surface::Cylinder { mat_i, .. } | Surface::Cone { mat_i, .. } =>
+                mat_i * p.push(1.0),
+            _ => return None,
+        };
+        Some(DVec2::new(p.y.atan2(p.x), p.z))
+    }
+
+    /// Returns the closest point on the (untrimmed) surface to `p`, or
+    /// `None` if the projection fails to converge
+    pub fn project(&self, p: DVec3) -> Option<DVec3> {
+        let p_ = DVec4::new(p.x, p.y, p.z, 1.0);
+        match self {
+            Surface::Plane { mat_i, normal } => Some(p - normal * (mat_i * p_).z),
+            Surface::Cylinder { mat, mat_i, radius, .. } => {
+                let q = mat_i * p_;
+                let xy = q.xy();
+                if xy.norm() < f64::EPSILON {
+                    return None;
+                }
+                let xy = xy.normalize() * *radius;
+                Some((mat * DVec4::new(xy.x, xy.y, q.z, 1.0)).xyz())
+            },
+            Surface::Cone { mat, mat_i, radius, angle } => {
+                // Work in the half-plane containing the point and the axis,
+                // where the cone is a line through (radius, 0)
+                let q = mat_i * p_;
+                let r = q.xy().norm();
+                if r < f64::EPSILON {
+                    return None;
+                }
+                let dir = DVec2::new(angle.sin(), angle.cos());
+                let t = DVec2::new(r - radius, q.z).dot(&dir);
+                let rz = DVec2::new(*radius, 0.0) + dir * t;
+                let xy = q.xy() / r * rz.x;
+                Some((mat * DVec4::new(xy.x, xy.y, rz.y, 1.0)).xyz())
+            },
+            Surface::Sphere { location, radius, .. } => {
+                let d = p - location;
+                if d.norm() < f64::EPSILON {
+                    return None;
+                }
+                Some(location + d.normalize() * *radius)
+            },
+            Surface::Torus { location, axis, major_radius, minor_radius, .. } => {
+                let d = p - location;
+                let radial = d - axis * d.dot(axis);
+                if radial.norm() < f64::EPSILON {
+                    return None;
+                }
+                let ring = location + radial.normalize() * *major_radius;
+                let d = p - ring;
+                if d.norm() < f64::EPSILON {
+                    return None;
+                }
+                Some(ring + d.normalize() * *minor_radius)
+            },
+            Surface::BSpline(s) => s.uv_from_point(p).map(|uv| s.surf.point(uv)),
+            Surface::NURBS(s) => s.uv_from_point(p).map(|uv| s.surf.point(uv)),
+        }
+    }
+
     pub fn new_sphere(location: DVec3, radius: f64) -> Self {
         Surface::Sphere {
             // mat and mat_i are built in prepare()
@@ -86,11 +209,13 @@ impl Surface {
         }
     }
 
-    pub fn new_cone(axis: DVec3, ref_direction: DVec3, location: DVec3, angle: f64) -> Self {
+    pub fn new_cone(axis: DVec3, ref_direction: DVec3, location: DVec3,
+                    radius: f64, angle: f64) -> Self {
         let mat = Self::make_rigid_transform(axis, ref_direction, location);
         Surface::Cone {
             mat,
             mat_i: mat.try_inverse().expect("Could not invert"),
+            radius,
             angle,
         }
     }
@@ -333,14 +458,26 @@ impl Surface {
         (xmin, xmax, ymin, ymax)
     }
 
+    /// Adds a grid of interior points to curved surfaces, so that their
+    /// triangles follow the curvature.  With a tolerance, the grid is scaled
+    /// by the same factor as circle segment counts for the curvature radius.
     pub fn add_steiner_points(&self, pts: &mut Vec<(f64, f64)>,
-                                     verts: &mut Vec<Vertex>)
+                              verts: &mut Vec<Vertex>,
+                              params: &TessellationParams)
     {
         let (xmin, xmax, ymin, ymax) = Self::bbox(&pts);
-        let num_pts = match self {
-            Surface::Sphere { .. }   => 6,
-            Surface::Torus { .. } => 32,
-            _ => 0,
+        let (num_pts, radius) = match self {
+            Surface::Sphere { radius, .. } => (6, *radius),
+            Surface::Torus { minor_radius, .. } => (32, *minor_radius),
+            _ => (0, 0.0),
+        };
+        let num_pts = match params.tolerance {
+            Some(_) if num_pts > 0 => {
+                let scale = segments_per_turn(radius, params) as f64 /
+                            segments_per_turn(radius, &TessellationParams::default()) as f64;
+                ((num_pts as f64 * scale).ceil() as usize).clamp(1, 256)
+            },
+            _ => num_pts,
         };
 
         for x in 0..num_pts {
@@ -357,6 +494,7 @@ impl Surface {
                         pos,
                         norm: self.normal(pos, uv),
                         color: DVec3::new(0.0, 0.0, 0.0),
+                        uv: DVec2::zeros(),
                     });
                 }
             }
@@ -372,6 +510,156 @@ impl Surface {
         n.normalize()
     }
 
+    /// Returns the principal curvatures of a NURBS or b-spline surface at
+    /// the given UV coordinates, from its first and second fundamental forms
+    fn surf_curvatures<const N: usize>(uv: DVec2, surf: &SampledSurface<N>)
+        -> Option<DVec2>
+        where NDBSplineSurface<N>: AbstractSurface
+    {
+        let d = surf.surf.derivs::<2>(uv);
+        let (su, sv) = (d[1][0], d[0][1]);
+        let n = su.cross(&sv);
+        if n.norm() < f64::EPSILON {
+            return None;
+        }
+        let n = n.normalize();
+        let (e, f, g) = (su.dot(&su), su.dot(&sv), sv.dot(&sv));
+        let (l, m, nn) = (d[2][0].dot(&n), d[1][1].dot(&n), d[0][2].dot(&n));
+        let det = e * g - f * f;
+        // Curvatures are negated, so that a surface which bends away from
+        // its normal (like the outside of a sphere) is positive
+        let k = (l * nn - m * m) / det;
+        let h = -(e * nn - 2.0 * f * m + g * l) / (2.0 * det);
+        let s = (h * h - k).max(0.0).sqrt();
+        Some(DVec2::new(h + s, h - s)).filter(|k| k.x.is_finite() && k.y.is_finite())
+    }
+
+    /// Tessellates the whole of a closed surface (a sphere or torus), for
+    /// faces which have no edges to bound them.  Returns vertices and
+    /// outward-facing triangles, or `None` for surfaces which aren't closed.
+    pub fn closed_mesh(&self, params: &TessellationParams)
+        -> Option<(Vec<Vertex>, Vec<[usize; 3]>)>
+    {
+        match self {
+            Surface::Sphere { location, radius, .. } => {
+                let (n, r) = (segments_per_turn(*radius, params), *radius);
+                Some(Self::grid_mesh(*location, DVec3::z(), n, (n / 2).max(2), false,
+                    |u, v| {
+                        let v = v * PI - PI / 2.0;
+                        let n = DVec3::new(v.cos() * u.cos(), v.cos() * u.sin(), v.sin());
+                        (n * r, n)
+                    }))
+            },
+            Surface::Torus { location, axis, major_radius, minor_radius, .. } => {
+                let (big, small) = (*major_radius, *minor_radius);
+                Some(Self::grid_mesh(*location, axis.normalize(),
+                    segments_per_turn(big + small, params),
+                    segments_per_turn(small, params), true,
+                    |u, v| {
+                        let v = v * 2.0 * PI;
+                        let radial = DVec3::new(u.cos(), u.sin(), 0.0);
+                        let n = radial * v.cos() + DVec3::z() * v.sin();
+                        (radial * big + n * small, n)
+                    }))
+            },
+            _ => None,
+        }
+    }
+
+    /// Builds a grid of `n_u` points around an axis by `n_v` points along a
+    /// meridian, from a function which maps an angle around the Z axis and
+    /// a fraction along the meridian to a position and normal.  The grid
+    /// wraps around in `u`, and in `v` if `wrap_v` is set; otherwise, each
+    /// end of the meridian is a single pole vertex, so the mesh is closed.
+    fn grid_mesh<F>(location: DVec3, axis: DVec3, n_u: usize, n_v: usize,
+                    wrap_v: bool, point: F) -> (Vec<Vertex>, Vec<[usize; 3]>)
+        where F: Fn(f64, f64) -> (DVec3, DVec3)
+    {
+        // Rotate from the Z axis onto the surface's axis
+        let x = if axis.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() };
+        let x = (x - axis * x.dot(&axis)).normalize();
+        let frame = glm::DMat3::from_columns(&[x, axis.cross(&x), axis]);
+        let vertex = |u: f64, v: f64| {
+            let (p, n) = point(u * 2.0 * PI, v);
+            Vertex {
+                pos: location + frame * p,
+                norm: frame * n,
+                color: DVec3::zeros(),
+                uv: DVec2::new(u, v),
+            }
+        };
+
+        let rows: Vec<usize> = if wrap_v { (0..n_v).collect() } else { (1..n_v).collect() };
+        let mut verts = Vec::new();
+        for &i in &rows {
+            for j in 0..n_u {
+                verts.push(vertex(j as f64 / n_u as f64, i as f64 / n_v as f64));
+            }
+        }
+        let index = |row: usize, j: usize| row * n_u + j % n_u;
+
+        let mut tris = Vec::new();
+        let bands = if wrap_v { rows.len() } else { rows.len() - 1 };
+        for r in 0..bands {
+            let next = (r + 1) % rows.len();
+            for j in 0..n_u {
+                let (a, b) = (index(r, j), index(r, j + 1));
+                let (c, d) = (index(next, j + 1), index(next, j));
+                tris.push([a, b, c]);
+                tris.push([a, c, d]);
+            }
+        }
+        if !wrap_v {
+            let (south, north) = (verts.len(), verts.len() + 1);
+            verts.push(vertex(0.0, 0.0));
+            verts.push(vertex(0.0, 1.0));
+            let last = rows.len() - 1;
+            for j in 0..n_u {
+                tris.push([south, index(0, j + 1), index(0, j)]);
+                tris.push([north, index(last, j), index(last, j + 1)]);
+            }
+        }
+        (verts, tris)
+    }
+
+    /// Returns the principal curvatures at a point on the surface (the
+    /// larger first), measured relative to [`Surface::normal`]: a surface
+    /// which bends away from its normal, like the outside of a sphere, has
+    /// positive curvature.  Returns `None` if the point can't be evaluated.
+    pub fn principal_curvatures(&self, p: DVec3) -> Option<DVec2> {
+        let p_ = DVec4::new(p.x, p.y, p.z, 1.0);
+        match self {
+            Surface::Plane { .. } => Some(DVec2::zeros()),
+            Surface::Cylinder { radius, .. } => Some(DVec2::new(1.0 / radius, 0.0)),
+            Surface::Sphere { radius, .. } => Some(DVec2::repeat(1.0 / radius)),
+            Surface::Cone { mat_i, angle, .. } => {
+                // Curvature around the axis, at the point's distance from it
+                let r = (mat_i * p_).xy().norm();
+                if r < f64::EPSILON {
+                    return None;
+                }
+                let k = angle.cos() / r;
+                Some(if k > 0.0 { DVec2::new(k, 0.0) } else { DVec2::new(0.0, k) })
+            },
+            Surface::Torus { location, axis, minor_radius, .. } => {
+                // The tube curves at its minor radius; around the axis, the
+                // curvature depends on how the normal tilts towards the axis
+                let d = p - location;
+                let radial = d - axis * d.dot(axis);
+                let rho = radial.norm();
+                if rho < f64::EPSILON {
+                    return None;
+                }
+                let n = self.normal(p, DVec2::zeros());
+                let a = 1.0 / minor_radius;
+                let b = n.dot(&(radial / rho)) / rho;
+                Some(DVec2::new(a.max(b), a.min(b)))
+            },
+            Surface::BSpline(s) => Self::surf_curvatures(s.uv_from_point(p)?, s),
+            Surface::NURBS(s) => Self::surf_curvatures(s.uv_from_point(p)?, s),
+        }
+    }
+
     // Calculate the surface normal, using either the 3D or 2D position
     pub fn normal(&self, p: DVec3, uv: DVec2) -> DVec3 {
         match self {
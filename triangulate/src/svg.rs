@@ -0,0 +1,109 @@
+//! A minimal SVG writer for 2D drawings (sections, outlines, and slices),
+//! which flips the Y axis so that drawings stay right-handed
+use std::io::Write;
+
+/// A point in drawing coordinates, with X right and Y up
+pub type Point = (f64, f64);
+
+/// Grows a drawing's bounds by a small margin on every side, returning
+/// bounds which are never empty
+pub fn page(lo: Point, hi: Point) -> (Point, Point) {
+    if lo.0 > hi.0 {
+        // Nothing was drawn, so fall back to bounds around the origin
+        return page((0.0, 0.0), (0.0, 0.0));
+    }
+    let size = (hi.0 - lo.0).max(hi.1 - lo.1);
+    let margin = if size > 0.0 { size * 0.02 } else { 1.0 };
+    ((lo.0 - margin, lo.1 - margin), (hi.0 + margin, hi.1 + margin))
+}
+
+/// An SVG file which is being written, covering a fixed region of the page
+pub struct Svg<W: Write> {
+    out: W,
+    stroke_width: f64,
+}
+
+impl Svg<std::io::BufWriter<std::fs::File>> {
+    /// Creates an SVG file showing the region from `lo` to `hi` (see
+    /// [`Svg::new`])
+    pub fn create(filename: &str, lo: Point, hi: Point, length_unit: Option<f64>)
+        -> std::io::Result<Self>
+    {
+        let out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        Self::new(out, lo, hi, length_unit)
+    }
+}
+
+impl<W: Write> Svg<W> {
+    /// Writes the header of a drawing which shows the region from `lo` to
+    /// `hi`.  If the length of a model unit is known (in metres), the
+    /// drawing is sized to print at 1:1.
+    pub fn new(mut out: W, lo: Point, hi: Point, length_unit: Option<f64>)
+        -> std::io::Result<Self>
+    {
+        let (w, h) = (hi.0 - lo.0, hi.1 - lo.1);
+        let size = match length_unit {
+            Some(unit) => format!(" width=\"{}mm\" height=\"{}mm\"",
+                                  w * unit * 1e3, h * unit * 1e3),
+            None => String::new(),
+        };
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        // SVG's Y axis points down, so the drawing is flipped
+        writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\"{} \
+                       viewBox=\"{} {} {} {}\">", size, lo.0, -hi.1, w, h)?;
+        Ok(Self { out, stroke_width: w.max(h) * 1e-3 })
+    }
+
+    /// Returns a line width which is thin relative to the page
+    pub fn stroke_width(&self) -> f64 {
+        self.stroke_width
+    }
+
+    /// Writes a single path made of several polylines, closing each one if
+    /// `closed` is set.  `attrs` are added to the path element as-is (e.g.
+    /// `fill="black"`).
+    pub fn path<I, R>(&mut self, attrs: &str, polylines: I, closed: bool)
+        -> std::io::Result<()>
+        where I: IntoIterator<Item=R>, R: AsRef<[Point]>
+    {
+        write!(self.out, "<path {} d=\"", attrs)?;
+        for line in polylines {
+            for (i, p) in line.as_ref().iter().enumerate() {
+                write!(self.out, "{}{} {} ", if i == 0 { "M" } else { "L" }, p.0, -p.1)?;
+            }
+            if closed {
+                write!(self.out, "Z ")?;
+            }
+        }
+        writeln!(self.out, "\"/>")
+    }
+
+    /// Closes the drawing and flushes it
+    pub fn finish(mut self) -> std::io::Result<()> {
+        writeln!(self.out, "</svg>")?;
+        self.out.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flipped_paths() {
+        let (lo, hi) = page((0.0, 0.0), (50.0, 25.0));
+        assert_eq!((lo, hi), ((-1.0, -1.0), (51.0, 26.0)));
+        let mut svg = Svg::new(Vec::new(), lo, hi, Some(1.0)).unwrap();
+        svg.path("fill=\"none\"", [[(0.0, 0.0), (1.0, 2.0)]], false).unwrap();
+        svg.path("fill=\"black\"", [vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]], true)
+            .unwrap();
+        let out = String::from_utf8(std::mem::take(&mut svg.out)).unwrap();
+        assert!(out.contains("width=\"52000mm\" height=\"27000mm\""));
+        assert!(out.contains("viewBox=\"-1 -26 52 27\""));
+        assert!(out.contains("<path fill=\"none\" d=\"M0 -0 L1 -2 \"/>"));
+        assert!(out.contains("d=\"M0 -0 L1 -0 L0 -1 Z \"/>"));
+        assert_eq!(page((1.0, 1.0), (-1.0, -1.0)), ((-1.0, -1.0), (1.0, 1.0)));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{bvh::Bvh, mesh::Mesh};
+
+/// Fraction of the solid's bounding box diagonal which rays ignore, so that
+/// they don't hit the triangle they started from
+const SELF_HIT: f64 = 1e-9;
+
+/// Estimates the local wall thickness at every vertex, by casting a ray
+/// inwards from the center of each triangle (against its vertex normals)
+/// and finding where it leaves the same solid.  Each vertex takes the
+/// thinnest value of the triangles around it.
+///
+/// Vertices without any hit (e.g. on open shells, or where the normals point
+/// inwards) are `None`.
+pub fn wall_thickness(mesh: &Mesh) -> Vec<Option<f64>> {
+    let mut out = vec![None; mesh.verts.len()];
+    let ranges: Vec<_> = if mesh.solids.is_empty() {
+        std::iter::once(0..mesh.triangles.len()).collect()
+    } else {
+        mesh.solids.iter().map(|s| s.triangles.clone()).collect()
+    };
+    for r in ranges {
+        let bvh = Bvh::from_triangles(mesh, r.clone());
+        let b = bvh.bounds();
+        let eps = (b.max - b.min).norm() * SELF_HIT;
+        for t in &mesh.triangles[r] {
+            let v = t.verts.map(|i| i as usize);
+            let [a, b, c] = [v.x, v.y, v.z].map(|i| &mesh.verts[i]);
+            let center = (a.pos + b.pos + c.pos) / 3.0;
+
+            // Use the geometric normal, flipped to agree with the vertex
+            // normals, since triangle winding isn't always consistent
+            let n = (b.pos - a.pos).cross(&(c.pos - a.pos));
+            let n = if n.dot(&(a.norm + b.norm + c.norm)) < 0.0 { -n } else { n };
+            if n.norm() == 0.0 {
+                continue;
+            }
+            let dir = -n.normalize();
+
+            let d = bvh.raycast(center + dir * eps, dir).map(|h| h.distance + eps);
+            if let Some(d) = d {
+                for i in [v.x, v.y, v.z] {
+                    out[i] = Some(out[i].map_or(d, |o: f64| o.min(d)));
+                }
+            }
+        }
+    }
+    out
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::DVec3;
+    use crate::fixtures::faceted_cuboid;
+
+    #[test]
+    fn box_thickness() {
+        let size = DVec3::new(1.0, 2.0, 3.0);
+        let mut mesh = Mesh::default();
+        faceted_cuboid(&mut mesh, DVec3::zeros(), size);
+        let t = wall_thickness(&mesh);
+        for (v, t) in mesh.verts.iter().zip(&t) {
+            let axis = v.norm.iamax();
+            assert!((t.unwrap() - size[axis]).abs() < 1e-9);
+        }
+
+        // Flipping the normals sends rays out of the box, so nothing is hit
+        let mut flipped = mesh.clone();
+        for v in flipped.verts.iter_mut() {
+            v.norm = -v.norm;
+        }
+        assert!(wall_thickness(&flipped).iter().all(|t| t.is_none()));
+    }
+}
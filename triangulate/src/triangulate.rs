@@ -2,27 +2,113 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 
 use nalgebra_glm as glm;
-use glm::{DVec3, DVec4, DMat4, U32Vec3};
+use glm::{DVec2, DVec3, DVec4, DMat4, U32Vec3};
 use log::{info, warn, error};
 
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
 use step::{
-    ap214, ap214::*, step_file::{FromEntity, StepFile}, id::Id, ap214::Entity,
+    ap214, ap214::*, cancel::CancelToken, protocol::Protocol,
+    step_file::{FromEntity, StepFile},
+    id::Id, ap214::Entity,
 };
 use crate::{
     Error,
     curve::Curve,
+    draughting,
+    material,
+    memory::{self, Stage},
     mesh, mesh::{Mesh, Triangle},
+    quad,
+    sew,
+    spill::{MeshWriter, SpilledMesh},
     stats::Stats,
-    surface::Surface
+    surface::Surface,
+    units,
 };
-use nurbs::{BSplineSurface, SampledCurve, SampledSurface, NURBSSurface, KnotVector};
+use nurbs::{AbstractSurface, BSplineSurface, SampledCurve, SampledSurface, NURBSSurface, KnotVector};
 
 const SAVE_DEBUG_SVGS: bool = false;
 const SAVE_PANIC_SVGS: bool = false;
 
+/// Default value for [`TessellationParams::relative_tolerance`], which
+/// keeps edges within a ten-thousandth of the model's size
+pub const DEFAULT_RELATIVE_TOLERANCE: f64 = 1e-4;
+
+/// Controls how finely curves and surfaces are sampled.  By default, edges
+/// are kept within [`DEFAULT_RELATIVE_TOLERANCE`] of the model's size.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TessellationParams {
+    /// Maximum distance between an edge's line segments and the curve which
+    /// they approximate, in model units.  If this is `None`, curves are
+    /// sampled at fixed rates (64 segments per full circle, and 8 samples
+    /// per B-spline knot span), unless `relative_tolerance` is set.
+    pub tolerance: Option<f64>,
+    /// Tolerance as a fraction of the diagonal of the model's bounding box
+    /// (which spans every vertex in the file), used if `tolerance` is `None`.
+    /// The result is never smaller than the length uncertainty, so the same
+    /// setting suits tiny connectors and huge weldments alike.  Set both
+    /// this and `tolerance` to `None` for fixed sampling rates.
+    pub relative_tolerance: Option<f64>,
+    /// Smallest number of segments in a full circle, whatever the tolerance
+    /// (partial arcs get their share).  If this is `None`, circles get at
+    /// least 16 segments.
+    pub min_segments_per_turn: Option<usize>,
+    /// Distance below which points are treated as coincident, in model
+    /// units.  If this is `None`, the length uncertainty declared by the
+    /// solid's representation context is used.
+    pub uncertainty: Option<f64>,
+    /// Largest gap between the boundaries of neighboring faces which is
+    /// stitched closed, in model units.  If this is `None`, faces are only
+    /// joined where their boundaries agree to within the uncertainty.
+    pub sewing: Option<f64>,
+    /// If true, faces which fail to triangulate are replaced by a fan of
+    /// triangles over their boundary loops, marked as `failed` in the mesh's
+    /// face list.  This is meant for viewers, which can highlight missing
+    /// geometry; exported meshes should leave it off.
+    pub placeholders: bool,
+    /// If true, the curves of 2D annotations (such as graphical PMI) are
+    /// collected into [`Mesh::annotations`]
+    pub annotations: bool,
+    /// If true, faces on planes, cylinders, and cones whose boundaries are
+    /// rectangles in the surface's coordinates (or bands between two
+    /// circles) are tessellated as structured grids of quads, recorded in
+    /// [`mesh::Face::grid`] for [`QuadMesh`](crate::quad::QuadMesh)
+    pub quad_grids: bool,
+    /// Number of threads used to triangulate each face with hundreds of
+    /// thousands of points or more (see
+    /// [`cdt::triangulate_with_edges_parallel`]).  Faces whose boundaries
+    /// aren't already Delaunay edges fall back to a single thread, as do all
+    /// faces if this is 1.
+    pub face_threads: usize,
+}
+
+impl Default for TessellationParams {
+    fn default() -> Self {
+        Self {
+            tolerance: None,
+            relative_tolerance: Some(DEFAULT_RELATIVE_TOLERANCE),
+            min_segments_per_turn: None,
+            uncertainty: None,
+            sewing: None,
+            placeholders: false,
+            annotations: false,
+            quad_grids: false,
+            face_threads: 1,
+        }
+    }
+}
+
+/// Per-entity tessellation settings, keyed by the STEP entity id of an
+/// `ADVANCED_FACE` or of a solid (e.g. a `MANIFOLD_SOLID_BREP`).  Face
+/// entries take priority over solid entries.
+///
+/// An edge is sampled by the first face which reaches it, and the faces
+/// after it reuse those samples, so a face's tolerance only applies to the
+/// edges which it doesn't share with an earlier face.
+pub type TessellationOverrides = HashMap<usize, TessellationParams>;
+
 /// `TransformStack` is a mapping of representations to transformed children.
 type TransformStack<'a> =
     HashMap<Representation<'a>, Vec<(Representation<'a>, DMat4)>>;
@@ -64,11 +150,81 @@ fn transform_stack_roots<'a>(transform_stack: &TransformStack<'a>) -> Vec<Repres
 }
 
 pub fn triangulate(s: &StepFile) -> (Mesh, Stats) {
-    let styled_items: Vec<_> = s.0.iter()
-        .filter_map(|e| MechanicalDesignGeometricPresentationRepresentation_::try_from_entity(e))
-        .flat_map(|m| m.items.iter())
-        .filter_map(|item| s.entity(item.cast::<StyledItem_>()))
-        .collect();
+    triangulate_with(s, TessellationParams::default(), &HashMap::new())
+}
+
+/// Triangulates a STEP file with the given default tessellation settings,
+/// which are overridden for particular faces or solids by `overrides`.
+pub fn triangulate_with(s: &StepFile, params: TessellationParams,
+                        overrides: &TessellationOverrides) -> (Mesh, Stats)
+{
+    triangulate_(s, params, overrides, None, &CancelToken::default())
+        .expect("In-memory triangulation should not perform I/O")
+}
+
+/// Triangulates a STEP file like [`triangulate_with`], returning `None` if
+/// the token is cancelled before triangulation finishes.  Cancellation is
+/// checked between faces.
+pub fn triangulate_cancellable(s: &StepFile, params: TessellationParams,
+                               overrides: &TessellationOverrides,
+                               cancel: &CancelToken) -> Option<(Mesh, Stats)>
+{
+    let out = triangulate_(s, params, overrides, None, cancel)
+        .expect("In-memory triangulation should not perform I/O");
+    if cancel.is_cancelled() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// Triangulates a STEP file out-of-core, streaming finished solids to
+/// temporary files once more than `max_bytes` of mesh data is held in
+/// memory.  Solids are triangulated one at a time, so each solid (with its
+/// instances) must still fit in memory.  Returns `None` if the token is
+/// cancelled before triangulation finishes.
+pub fn triangulate_out_of_core(s: &StepFile, params: TessellationParams,
+                               overrides: &TessellationOverrides,
+                               max_bytes: usize, cancel: &CancelToken)
+    -> std::io::Result<Option<(SpilledMesh, Stats)>>
+{
+    let mut writer = MeshWriter::new(max_bytes)?;
+    let (meta, stats) = triangulate_(s, params, overrides, Some(&mut writer),
+                                     cancel)?;
+    if cancel.is_cancelled() {
+        return Ok(None);
+    }
+    writer.push(meta)?;
+    let mut mesh = writer.finish()?;
+    mesh.assembly = assembly_tree(s, &mesh.solids);
+    Ok(Some((mesh, stats)))
+}
+
+/// Shared implementation of the public triangulation functions.  If
+/// `writer` is provided, the mesh is passed to it and the returned mesh
+/// only has the file's units and annotations (leaving the caller to build
+/// the assembly once every solid has been written).  If `cancel` is
+/// cancelled, the result is incomplete.
+fn triangulate_(s: &StepFile, params: TessellationParams,
+                overrides: &TessellationOverrides,
+                writer: Option<&mut MeshWriter>, cancel: &CancelToken)
+    -> std::io::Result<(Mesh, Stats)>
+{
+    let styled_items: Vec<_> = match s.protocol() {
+        // AP214 exporters attach styles through a presentation
+        // representation, which is cheaper to search than every entity
+        Protocol::Ap214 => s.0.iter()
+            .filter_map(|e| MechanicalDesignGeometricPresentationRepresentation_::try_from_entity(e))
+            .flat_map(|m| m.items.iter())
+            .filter_map(|item| s.entity(item.cast::<StyledItem_>()))
+            .collect(),
+        // Other protocols often leave styled items outside of a presentation
+        // representation (e.g. in an AP242 DRAUGHTING_MODEL, or not
+        // referenced at all), so we look at all of them
+        _ => s.0.iter()
+            .filter_map(StyledItem_::try_from_entity)
+            .collect(),
+    };
     let brep_colors: HashMap<_, DVec3> = styled_items.iter()
         .filter_map(|styled|
             if styled.styles.len() != 1 {
@@ -79,66 +235,48 @@ pub fn triangulate(s: &StepFile) -> (Mesh, Stats) {
             })
         .collect();
 
-    // Store a map of parent -> (child, transform)
-    let mut transform_stack = build_transform_stack(s, false);
-    let mut roots = transform_stack_roots(&transform_stack);
-    // The transformation graph isn't directional (because STEP is a Good File
-    // Format), so if it's got more than one root, assume it's backwards.  We
-    // are assuming that directions in the graph are consistent within the file,
-    // until we find a counterexample.
-    if roots.len() > 1 {
-        info!("Flipping transform stack");
-        transform_stack = build_transform_stack(s, true);
-        roots = transform_stack_roots(&transform_stack);
-    }
-    let mut todo: Vec<_> = roots.into_iter()
-        .map(|v| (v, DMat4::identity()))
-        .collect();
-    if todo.len() > 1 {
-        warn!("Transformation stack has more than one root!");
-    }
-
-    // Store a map of ShapeRepresentationRelationships, which some models
-    // use to map from axes to specific instances
-    let mut shape_rep_relationship: HashMap<Id<_>, Vec<Id<_>>> = HashMap::new();
-    for (r1, r2) in s.0.iter()
-        .filter_map(|e| ShapeRepresentationRelationship_::try_from_entity(e))
-        .map(|e| (e.rep_1, e.rep_2))
-    {
-        shape_rep_relationship.entry(r1).or_default().push(r2);
-    }
-
+    // Each solid is labelled with the name of the innermost product which
+    // contains it, which is tracked as we walk down the transform stack.
     let mut to_mesh: HashMap<Id<_>, Vec<_>> = HashMap::new();
-    while let Some((id, mat)) = todo.pop() {
-        for child in shape_rep_relationship.get(&id).unwrap_or(&vec![]) {
-            todo.push((*child, mat));
-        }
-        if let Some(children) = transform_stack.get(&id) {
-            for (child, next_mat) in children {
-                todo.push((*child, mat * next_mat));
-            }
-        } else {
-            // Bind this transform to the RepresentationItem, which is
-            // either a ManifoldSolidBrep or a ShellBasedSurfaceModel
-            let items = match &s[id] {
-                Entity::AdvancedBrepShapeRepresentation(b) => &b.items,
-                Entity::ShapeRepresentation(b) => &b.items,
-                Entity::ManifoldSurfaceShapeRepresentation(b) => &b.items,
-                e => panic!("Could not get shape from {:?}", e),
-            };
+    let mut solid_names: HashMap<Id<_>, &str> = HashMap::new();
+    let mut solid_uncertainty: HashMap<Id<_>, f64> = HashMap::new();
+    walk_assembly(s, |id, mat, name| {
+        // Bind this transform to the RepresentationItem, which is
+        // either a ManifoldSolidBrep or a ShellBasedSurfaceModel
+        let (items, context) = match s.0.get(id.0) {
+            Some(Entity::AdvancedBrepShapeRepresentation(b)) =>
+                (&b.items, b.context_of_items),
+            Some(Entity::ShapeRepresentation(b)) =>
+                (&b.items, b.context_of_items),
+            Some(Entity::ManifoldSurfaceShapeRepresentation(b)) =>
+                (&b.items, b.context_of_items),
+            // Edition 3 references to other files leave a gap in the
+            // entity list, which is filled in by the `external` module
+            None | Some(Entity::_EmptySlot) => return,
+            // Drawings and PMI can be placed like shapes, but have no solids
+            Some(e) if draughting::is_draughting(e) => return,
+            Some(e) => panic!("Could not get shape from {:?}", e),
+        };
 
-            for m in items.iter() {
-                match &s[*m] {
-                    Entity::ManifoldSolidBrep(_)
-                    | Entity::BrepWithVoids(_)
-                    | Entity::ShellBasedSurfaceModel(_) =>
-                        to_mesh.entry(*m).or_default().push(mat),
-                    Entity::Axis2Placement3d(_) => (),
-                    e => warn!("Skipping {:?}", e),
-                }
+        for m in items.iter() {
+            match &s[*m] {
+                Entity::ManifoldSolidBrep(_)
+                | Entity::BrepWithVoids(_)
+                | Entity::ShellBasedSurfaceModel(_) => {
+                    to_mesh.entry(*m).or_default().push(mat);
+                    if let Some(name) = name {
+                        solid_names.entry(*m).or_insert(name);
+                    }
+                    if let Some(u) = length_uncertainty(s, context) {
+                        solid_uncertainty.entry(*m).or_insert(u);
+                    }
+                },
+                Entity::Axis2Placement3d(_) => (),
+                e if draughting::is_draughting(e) => (),
+                e => warn!("Skipping {:?}", e),
             }
         }
-    }
+    });
     // If there are items in breps that aren't attached to a transformation
     // chain, then draw them individually (with an identity matrix)
     if to_mesh.is_empty() {
@@ -156,80 +294,167 @@ pub fn triangulate(s: &StepFile) -> (Mesh, Stats) {
             .for_each(|i| to_mesh.entry(i).or_default().push(DMat4::identity()));
     }
 
+    let solid_materials = material::solid_materials(s);
+    let model_size = model_size(s);
+    // Angles of conics and spherical surfaces are in the file's angle unit,
+    // which is found once here rather than for every entity which needs it
+    let angle_unit = units::angle_unit(s).unwrap_or(1.0);
+
+    // Solids whose context doesn't declare an uncertainty (or which aren't
+    // in a representation) fall back to the smallest one in the file
+    let file_uncertainty = s.0.iter()
+        .filter_map(UncertaintyMeasureWithUnit_::try_from_entity)
+        .filter_map(length_measure)
+        .fold(None, |a: Option<f64>, u| Some(a.map_or(u, |a| a.min(u))));
+
+    // Sort solids by entity id, so that the output doesn't depend on the
+    // hash map's iteration order
+    let mut to_mesh: Vec<_> = to_mesh.into_iter().collect();
+    to_mesh.sort_by_key(|(id, _)| id.0);
+
+    // Triangulates a single solid (and its instances), appending it to
+    // the mesh
+    let build_solid = |(mut mesh, mut stats): (Mesh, Stats),
+                       (id, mats): &(Id<RepresentationItem_>, Vec<DMat4>)| {
+        let v_start = mesh.verts.len();
+        let t_start = mesh.triangles.len();
+        let f_start = mesh.faces.len();
+        let mut solid_params = *overrides.get(&id.0).unwrap_or(&params);
+        solid_params.uncertainty = solid_params.uncertainty
+            .or_else(|| solid_uncertainty.get(id).copied())
+            .or(file_uncertainty);
+        let solid_params = absolute_tolerance(solid_params, model_size);
+        let tess = (&solid_params, overrides, cancel, model_size, angle_unit);
+        let mut edges = EdgeSamples::new();
+        match &s[*id] {
+            Entity::ManifoldSolidBrep(b) =>
+                closed_shell(s, b.outer, &mut mesh, &mut stats, tess, &mut edges),
+            Entity::ShellBasedSurfaceModel(b) =>
+                for v in &b.sbsm_boundary {
+                    shell(s, *v, &mut mesh, &mut stats, tess, &mut edges);
+                },
+            Entity::BrepWithVoids(b) =>
+                // TODO: handle voids
+                closed_shell(s, b.outer, &mut mesh, &mut stats, tess, &mut edges),
+            _ => {
+                warn!("Skipping {:?} (not a known solid)", s[*id]);
+                return (mesh, stats);
+            },
+        };
+        // Faces share the samples of their common edges, but edges which
+        // meet at distinct (coincident) vertices or overlapping curves only
+        // line up to within the model's uncertainty
+        if let Some(tol) = solid_params.uncertainty {
+            sew::merge_points(mesh.verts[v_start..].iter_mut().map(|v| &mut v.pos), tol);
+        }
+        if let Some(gap) = solid_params.sewing {
+            let t_end = mesh.triangles.len();
+            stats.num_sewn += sew::sew(&mut mesh, t_start..t_end, gap);
+        }
+
+        // Pick out a color from the color map and apply it to each
+        // newly-created vertex
+        let style = brep_colors.get(id).copied();
+        let color = style.unwrap_or(DVec3::new(0.5, 0.5, 0.5));
+
+        // Build copies of the mesh by copying and applying transforms
+        let v_end = mesh.verts.len();
+        let t_end = mesh.triangles.len();
+        let f_end = mesh.faces.len();
+        let name = solid_names.get(id).copied()
+            .or_else(|| solid_label(s, *id))
+            .map(|n| n.to_owned());
+        let material = solid_materials.get(&id.0).cloned();
+        let original = mesh.solids.len();
+        mesh.solids.push(mesh::Solid {
+            id: id.0, name: name.clone(), color: style, material: material.clone(),
+            triangles: t_start..t_end,
+            instance_of: None, transform: DMat4::identity(),
+        });
+        // Copies are positioned relative to the original solid,
+        // which is itself moved by the first transform below
+        let mat0_i = mats[0].try_inverse().unwrap_or_else(DMat4::identity);
+        for mat in &mats[1..] {
+            for v in v_start..v_end {
+                let p = mesh.verts[v].pos;
+                let p_h = DVec4::new(p.x, p.y, p.z, 1.0);
+                let pos = (mat * p_h).xyz();
+
+                let n = mesh.verts[v].norm;
+                let norm = (mat * glm::vec3_to_vec4(&n)).xyz();
+
+                let uv = mesh.verts[v].uv;
+                mesh.verts.push(mesh::Vertex { pos, norm, color, uv });
+            }
+            let offset = mesh.verts.len() - v_end;
+            let t = mesh.triangles.len();
+            mesh.solids.push(mesh::Solid {
+                id: id.0, name: name.clone(), color: style, material: material.clone(),
+                triangles: t..(t + t_end - t_start),
+                instance_of: Some(original), transform: mat * mat0_i,
+            });
+            for f in f_start..f_end {
+                let f = mesh.faces[f].clone();
+                mesh.faces.push(mesh::Face {
+                    triangles: (f.triangles.start + t - t_start)..
+                               (f.triangles.end + t - t_start),
+                    transform: mat * f.transform,
+                    ..f
+                });
+            }
+            for t in t_start..t_end {
+                let mut tri = mesh.triangles[t];
+                tri.verts.add_scalar_mut(offset as u32);
+                mesh.triangles.push(tri);
+            }
+        }
+
+        // Now that we've built all of the other copies of the mesh,
+        // re-use the original mesh and apply the first transform
+        let mat = mats[0];
+        for f in &mut mesh.faces[f_start..f_end] {
+            f.transform = mat * f.transform;
+        }
+        for v in v_start..v_end {
+            let p = mesh.verts[v].pos;
+            let p_h = DVec4::new(p.x, p.y, p.z, 1.0);
+            mesh.verts[v].pos = (mat * p_h).xyz();
+
+            let n = mesh.verts[v].norm;
+            mesh.verts[v].norm = (mat * glm::vec3_to_vec4(&n)).xyz();
+
+            mesh.verts[v].color = color;
+        }
+        (mesh, stats)
+    };
+    let build = |acc: (Mesh, Stats), item: &(Id<RepresentationItem_>, Vec<DMat4>)|
+        memory::stage(Stage::Assembly, || build_solid(acc, item));
+
+    // In out-of-core mode, solids are triangulated one at a time and handed
+    // to the writer, which decides when to move them to disk
+    if let Some(writer) = writer {
+        let mut stats = Stats::default();
+        for item in &to_mesh {
+            let (m, st) = build((Mesh::default(), Stats::default()), item);
+            stats = Stats::combine(stats, st);
+            writer.push(m)?;
+        }
+        stats.num_draughting = draughting::count(s);
+        let meta = Mesh {
+            length_unit: units::length_unit(s),
+            annotations: annotations(s, &params),
+            ..Default::default()
+        };
+        return Ok((meta, stats));
+    }
+
     let (to_mesh_iter, empty) = {
         #[cfg(feature = "rayon")]
         { (to_mesh.par_iter(), || (Mesh::default(), Stats::default())) }
         #[cfg(not(feature = "rayon"))]
         { (to_mesh.iter(), (Mesh::default(), Stats::default())) }
     };
-    let mesh_fold = to_mesh_iter
-        .fold(
-            // Empty constructor
-            empty,
-
-            // Fold operation
-            |(mut mesh, mut stats), (id, mats)| {
-                let v_start = mesh.verts.len();
-                let t_start = mesh.triangles.len();
-                match &s[*id] {
-                    Entity::ManifoldSolidBrep(b) =>
-                        closed_shell(s, b.outer, &mut mesh, &mut stats),
-                    Entity::ShellBasedSurfaceModel(b) =>
-                        for v in &b.sbsm_boundary {
-                            shell(s, *v, &mut mesh, &mut stats);
-                        },
-                    Entity::BrepWithVoids(b) =>
-                        // TODO: handle voids
-                        closed_shell(s, b.outer, &mut mesh, &mut stats),
-                    _ => {
-                        warn!("Skipping {:?} (not a known solid)", s[*id]);
-                        return (mesh, stats);
-                    },
-                };
-
-                // Pick out a color from the color map and apply it to each
-                // newly-created vertex
-                let color = brep_colors.get(id)
-                    .map(|c| *c)
-                    .unwrap_or(DVec3::new(0.5, 0.5, 0.5));
-
-                // Build copies of the mesh by copying and applying transforms
-                let v_end = mesh.verts.len();
-                let t_end = mesh.triangles.len();
-                for mat in &mats[1..] {
-                    for v in v_start..v_end {
-                        let p = mesh.verts[v].pos;
-                        let p_h = DVec4::new(p.x, p.y, p.z, 1.0);
-                        let pos = (mat * p_h).xyz();
-
-                        let n = mesh.verts[v].norm;
-                        let norm = (mat * glm::vec3_to_vec4(&n)).xyz();
-
-                        mesh.verts.push(mesh::Vertex { pos, norm, color });
-                    }
-                    let offset = mesh.verts.len() - v_end;
-                    for t in t_start..t_end {
-                        let mut tri = mesh.triangles[t];
-                        tri.verts.add_scalar_mut(offset as u32);
-                        mesh.triangles.push(tri);
-                    }
-                }
-
-                // Now that we've built all of the other copies of the mesh,
-                // re-use the original mesh and apply the first transform
-                let mat = mats[0];
-                for v in v_start..v_end {
-                    let p = mesh.verts[v].pos;
-                    let p_h = DVec4::new(p.x, p.y, p.z, 1.0);
-                    mesh.verts[v].pos = (mat * p_h).xyz();
-
-                    let n = mesh.verts[v].norm;
-                    mesh.verts[v].norm = (mat * glm::vec3_to_vec4(&n)).xyz();
-
-                    mesh.verts[v].color = color;
-                }
-                (mesh, stats)
-            });
+    let mesh_fold = to_mesh_iter.fold(empty, &build);
 
     let (mesh, stats) = {
         #[cfg(feature = "rayon")]
@@ -245,7 +470,165 @@ pub fn triangulate(s: &StepFile) -> (Mesh, Stats) {
     info!("num_faces: {}", stats.num_faces);
     info!("num_errors: {}", stats.num_errors);
     info!("num_panics: {}", stats.num_panics);
-    (mesh, stats)
+    let stats = Stats { num_draughting: draughting::count(s), ..stats };
+    let mesh = Mesh {
+        length_unit: units::length_unit(s),
+        annotations: annotations(s, &params),
+        assembly: assembly_tree(s, &mesh.solids),
+        ..mesh
+    };
+    Ok((mesh, stats))
+}
+
+/// Collects the curves of the file's 2D annotations, if requested
+fn annotations(s: &StepFile, params: &TessellationParams) -> Vec<Vec<DVec3>> {
+    if params.annotations {
+        draughting::annotation_curves(s, params)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Walks the assembly from its roots down the transform stack, calling
+/// `leaf` for each representation without transformed children, with its
+/// accumulated transform and the name of the innermost product containing
+/// it.  A representation which appears in several places is visited once
+/// per instance.
+pub(crate) fn walk_assembly<'a, F>(s: &'a StepFile, mut leaf: F)
+    where F: FnMut(Representation<'a>, DMat4, Option<&'a str>)
+{
+    walk_products(s, (), |_, _, _| (), |id, mat, name, _| leaf(id, mat, name))
+}
+
+/// Walks the assembly like [`walk_assembly`], also calling `product` on
+/// entering each named product, with the value returned for its enclosing
+/// product (or `root`), its name, and its transform relative to the
+/// enclosing product.  `leaf` is passed the value for its innermost product.
+fn walk_products<'a, T, P, F>(s: &'a StepFile, root: T, mut product: P, mut leaf: F)
+    where T: Copy,
+          P: FnMut(T, &'a str, DMat4) -> T,
+          F: FnMut(Representation<'a>, DMat4, Option<&'a str>, T)
+{
+    // Store a map of parent -> (child, transform)
+    let mut transform_stack = build_transform_stack(s, false);
+    let mut roots = transform_stack_roots(&transform_stack);
+    // The transformation graph isn't directional (because STEP is a Good File
+    // Format), so if it's got more than one root, assume it's backwards.  We
+    // are assuming that directions in the graph are consistent within the file,
+    // until we find a counterexample.
+    if roots.len() > 1 {
+        info!("Flipping transform stack");
+        transform_stack = build_transform_stack(s, true);
+        roots = transform_stack_roots(&transform_stack);
+    }
+    let product_names = product_names(s);
+    let mut todo: Vec<_> = roots.into_iter()
+        .map(|v| (v, DMat4::identity(), DMat4::identity(), None, root))
+        .collect();
+    if todo.len() > 1 {
+        warn!("Transformation stack has more than one root!");
+    }
+
+    // Store a map of ShapeRepresentationRelationships, which some models
+    // use to map from axes to specific instances
+    let mut shape_rep_relationship: HashMap<Id<_>, Vec<Id<_>>> = HashMap::new();
+    for (r1, r2) in s.0.iter()
+        .filter_map(|e| ShapeRepresentationRelationship_::try_from_entity(e))
+        .map(|e| (e.rep_1, e.rep_2))
+    {
+        shape_rep_relationship.entry(r1).or_default().push(r2);
+    }
+
+    // Each entry tracks its transform relative to the innermost product as
+    // well as the accumulated transform
+    while let Some((id, mat, rel, name, parent)) = todo.pop() {
+        let (name, rel, parent) = match product_names.get(&id) {
+            Some(n) => (Some(*n), DMat4::identity(), product(parent, n, rel)),
+            None => (name, rel, parent),
+        };
+        for child in shape_rep_relationship.get(&id).unwrap_or(&vec![]) {
+            todo.push((*child, mat, rel, name, parent));
+        }
+        if let Some(children) = transform_stack.get(&id) {
+            for (child, next_mat) in children {
+                todo.push((*child, mat * next_mat, rel * next_mat, name, parent));
+            }
+        } else {
+            leaf(id, mat, name, parent);
+        }
+    }
+}
+
+/// Builds the tree of named products in the file, with each solid instance
+/// in `solids` listed under its innermost product
+fn assembly_tree(s: &StepFile, solids: &[mesh::Solid]) -> Vec<mesh::AssemblyNode> {
+    // Instances of a solid are stored contiguously in `solids`, in the same
+    // order as they're visited while walking the assembly
+    let mut instances: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, solid) in solids.iter().enumerate() {
+        instances.entry(solid.id).or_default().push(i);
+    }
+    let mut seen: HashMap<usize, usize> = HashMap::new();
+
+    let mut nodes = Vec::new();
+    let mut members = Vec::new();
+    walk_products(s, None, |parent, name, transform| {
+        nodes.push(mesh::AssemblyNode {
+            name: name.to_owned(),
+            parent, transform,
+            solids: Vec::new(),
+        });
+        Some(nodes.len() - 1)
+    }, |id, _mat, _name, parent| {
+        let items = match s.0.get(id.0) {
+            Some(Entity::AdvancedBrepShapeRepresentation(b)) => &b.items,
+            Some(Entity::ShapeRepresentation(b)) => &b.items,
+            Some(Entity::ManifoldSurfaceShapeRepresentation(b)) => &b.items,
+            _ => return,
+        };
+        for m in items.iter() {
+            let k = seen.entry(m.0).or_default();
+            if let Some(i) = instances.get(&m.0).and_then(|v| v.get(*k)) {
+                *k += 1;
+                if let Some(p) = parent {
+                    members.push((p, *i));
+                }
+            }
+        }
+    });
+    for (p, i) in members {
+        nodes[p].solids.push(i);
+    }
+    nodes
+}
+
+/// Builds a map from representations to the names of the products which
+/// they represent, following the chain
+/// `SHAPE_DEFINITION_REPRESENTATION -> PRODUCT_DEFINITION_SHAPE ->
+///  PRODUCT_DEFINITION -> PRODUCT_DEFINITION_FORMATION -> PRODUCT`
+fn product_names<'a>(s: &'a StepFile) -> HashMap<Representation<'a>, &'a str> {
+    s.0.iter()
+        .filter_map(ShapeDefinitionRepresentation_::try_from_entity)
+        .filter_map(|r| {
+            s.entity(r.definition.cast::<ProductDefinitionShape_>())
+                .and_then(|p| s.entity(p.definition.cast::<ProductDefinition_>()))
+                .and_then(|p| s.entity(p.formation))
+                .and_then(|f| s.entity(f.of_product))
+                .map(|p| &*p.name.0)
+                .filter(|n| !n.is_empty())
+                .map(|n| (r.used_representation, n))
+        })
+        .collect()
+}
+
+/// Returns the solid's own name label, if it's not empty
+fn solid_label<'a>(s: &'a StepFile, id: RepresentationItem<'a>) -> Option<&'a str> {
+    match &s[id] {
+        Entity::ManifoldSolidBrep(b) => Some(&*b.name.0),
+        Entity::BrepWithVoids(b) => Some(&*b.name.0),
+        Entity::ShellBasedSurfaceModel(b) => Some(&*b.name.0),
+        _ => None,
+    }.filter(|n| !n.is_empty())
 }
 
 fn item_defined_transformation(s: &StepFile, t: Id<ItemDefinedTransformation_>) -> DMat4 {
@@ -305,19 +688,21 @@ fn presentation_style_color(s: &StepFile, p: PresentationStyleAssignment)
         .map(|c| DVec3::new(c.red, c.green, c.blue))
 }
 
-fn cartesian_point(s: &StepFile, a: Id<CartesianPoint_>) -> DVec3 {
+pub(crate) fn cartesian_point(s: &StepFile, a: Id<CartesianPoint_>) -> DVec3 {
     let p = s.entity(a).expect("Could not get cartesian point");
-    DVec3::new(p.coordinates[0].0, p.coordinates[1].0, p.coordinates[2].0)
+    // 2D points (as used by pcurves) are placed at z = 0
+    DVec3::new(p.coordinates[0].0, p.coordinates[1].0,
+               p.coordinates.get(2).map_or(0.0, |c| c.0))
 }
 
 fn direction(s: &StepFile, a: Direction) -> DVec3 {
     let p = s.entity(a).expect("Could not get cartesian point");
     DVec3::new(p.direction_ratios[0],
                p.direction_ratios[1],
-               p.direction_ratios[2])
+               p.direction_ratios.get(2).copied().unwrap_or(0.0))
 }
 
-fn axis2_placement_3d(s: &StepFile, t: Id<Axis2Placement3d_>) -> (DVec3, DVec3, DVec3) {
+pub(crate) fn axis2_placement_3d(s: &StepFile, t: Id<Axis2Placement3d_>) -> (DVec3, DVec3, DVec3) {
     let a = s.entity(t).expect("Could not get Axis2Placement3d");
     let location = cartesian_point(s, a.location);
     // TODO: this doesn't necessarily match the behavior of `build_axes`
@@ -329,28 +714,124 @@ fn axis2_placement_3d(s: &StepFile, t: Id<Axis2Placement3d_>) -> (DVec3, DVec3,
     (location, axis, ref_direction)
 }
 
-fn shell(s: &StepFile, c: Shell, mesh: &mut Mesh, stats: &mut Stats) {
+/// Tessellation settings for a solid, along with the overrides which may
+/// replace them for individual faces, a token to stop early, the model size
+/// (for relative tolerances), and the file's angle unit in radians
+type Tess<'a> = (&'a TessellationParams, &'a TessellationOverrides,
+                 &'a CancelToken, f64, f64);
+
+/// Points sampled along each `EDGE_CURVE` in a solid, keyed by entity id and
+/// running from its start vertex to its end vertex.  Every face bordering an
+/// edge reuses the same samples, so their triangulations meet at exactly
+/// the same 3D points, which each face then projects into its own UV space.
+type EdgeSamples = HashMap<usize, Vec<DVec3>>;
+
+/// Looks up the parameters for a single face, which inherits the solid's
+/// uncertainty unless it overrides it (and always inherits placeholders and
+/// quad grids)
+fn face_params(tess: Tess, face: usize) -> TessellationParams {
+    let mut params = *tess.1.get(&face).unwrap_or(tess.0);
+    params.uncertainty = params.uncertainty.or(tess.0.uncertainty);
+    params.placeholders |= tess.0.placeholders;
+    params.quad_grids |= tess.0.quad_grids;
+    absolute_tolerance(params, tess.3)
+}
+
+/// Returns the diagonal of the bounding box of every vertex in the file, in
+/// model units.  Vertices aren't moved by assembly transforms, which is
+/// close enough for picking a tolerance.
+fn model_size(s: &StepFile) -> f64 {
+    let (lo, hi) = s.0.iter()
+        .filter_map(VertexPoint_::try_from_entity)
+        .filter_map(|v| match s.0.get(v.vertex_geometry.0) {
+            Some(Entity::CartesianPoint(_)) =>
+                Some(cartesian_point(s, v.vertex_geometry.cast())),
+            _ => None,
+        })
+        .fold((DVec3::repeat(f64::INFINITY), DVec3::repeat(-f64::INFINITY)),
+              |(lo, hi), p| (lo.inf(&p), hi.sup(&p)));
+    if lo.x <= hi.x {
+        (hi - lo).norm()
+    } else {
+        0.0
+    }
+}
+
+/// Converts a relative tolerance into an absolute one, for a model of the
+/// given size, unless an absolute tolerance is already set
+fn absolute_tolerance(mut params: TessellationParams, size: f64)
+    -> TessellationParams
+{
+    if let (None, Some(r)) = (params.tolerance, params.relative_tolerance) {
+        let tol = (size * r).max(params.uncertainty.unwrap_or(0.0));
+        if tol > 0.0 {
+            params.tolerance = Some(tol);
+        }
+    }
+    params
+}
+
+/// Returns the smallest length uncertainty assigned by a representation
+/// context, which is typically a complex entity that includes a
+/// `GLOBAL_UNCERTAINTY_ASSIGNED_CONTEXT`
+fn length_uncertainty(s: &StepFile, c: RepresentationContext) -> Option<f64> {
+    let assigned = match s.0.get(c.0)? {
+        Entity::GlobalUncertaintyAssignedContext(g) => &g.uncertainty,
+        Entity::ComplexEntity(v) => v.iter()
+            .find_map(|e| match e {
+                Entity::GlobalUncertaintyAssignedContext(g) => Some(&g.uncertainty),
+                _ => None,
+            })?,
+        _ => return None,
+    };
+    assigned.iter()
+        .filter_map(|u| s.entity(*u))
+        .filter_map(length_measure)
+        .fold(None, |a: Option<f64>, u| Some(a.map_or(u, |a| a.min(u))))
+}
+
+/// Returns the value of an uncertainty, if it's a length
+fn length_measure(u: &UncertaintyMeasureWithUnit_) -> Option<f64> {
+    match &u.value_component {
+        MeasureValue::LengthMeasure(m) => Some(m.0),
+        MeasureValue::PositiveLengthMeasure(m) => Some(m.0.0.0),
+        _ => None,
+    }
+}
+
+fn shell(s: &StepFile, c: Shell, mesh: &mut Mesh, stats: &mut Stats, tess: Tess,
+         edges: &mut EdgeSamples) {
     match &s[c] {
-        Entity::ClosedShell(_) => closed_shell(s, c.cast(), mesh, stats),
-        Entity::OpenShell(_) => open_shell(s, c.cast(), mesh, stats),
+        Entity::ClosedShell(_) => closed_shell(s, c.cast(), mesh, stats, tess, edges),
+        Entity::OpenShell(_) => open_shell(s, c.cast(), mesh, stats, tess, edges),
         h => warn!("Skipping {:?} (unknown Shell type)", h),
     }
 }
 
-fn open_shell(s: &StepFile, c: OpenShell, mesh: &mut Mesh, stats: &mut Stats) {
+fn open_shell(s: &StepFile, c: OpenShell, mesh: &mut Mesh, stats: &mut Stats,
+              tess: Tess, edges: &mut EdgeSamples) {
     let cs = s.entity(c).expect("Could not get OpenShell");
     for face in &cs.cfs_faces {
-        if let Err(err) = advanced_face(s, face.cast(), mesh, stats) {
+        if tess.2.is_cancelled() {
+            return;
+        }
+        let params = face_params(tess, face.0);
+        if let Err(err) = advanced_face(s, face.cast(), mesh, stats, &params, tess.4, edges) {
             error!("Failed to triangulate {:?}: {}", s[*face], err);
         }
     }
     stats.num_shells += 1;
 }
 
-fn closed_shell(s: &StepFile, c: ClosedShell, mesh: &mut Mesh, stats: &mut Stats) {
+fn closed_shell(s: &StepFile, c: ClosedShell, mesh: &mut Mesh, stats: &mut Stats,
+                tess: Tess, edges: &mut EdgeSamples) {
     let cs = s.entity(c).expect("Could not get ClosedShell");
     for face in &cs.cfs_faces {
-        if let Err(err) = advanced_face(s, face.cast(), mesh, stats) {
+        if tess.2.is_cancelled() {
+            return;
+        }
+        let params = face_params(tess, face.0);
+        if let Err(err) = advanced_face(s, face.cast(), mesh, stats, &params, tess.4, edges) {
             error!("Failed to triangulate {:?}: {}", s[*face], err);
         }
     }
@@ -358,24 +839,58 @@ fn closed_shell(s: &StepFile, c: ClosedShell, mesh: &mut Mesh, stats: &mut Stats
 }
 
 fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
-                 stats: &mut Stats) -> Result<(), Error>
+                 stats: &mut Stats, params: &TessellationParams, angle: f64,
+                 samples: &mut EdgeSamples) -> Result<(), Error>
 {
     let face = s.entity(f).expect("Could not get AdvancedFace");
     stats.num_faces += 1;
 
     // Grab the surface, returning early if it's unimplemented
-    let mut surf = get_surface(s, face.face_geometry)?;
+    let mut surf = get_surface(s, face.face_geometry, angle)?;
 
     // This is the starting point at which we insert new vertices
     let offset = mesh.verts.len();
 
+    // A face whose bounds don't cut anything out of its surface (a single
+    // vertex, or seams around a sphere or torus) covers the whole surface,
+    // which can't be flattened for triangulation, so it's built directly
+    if face.bounds.iter().all(|b| bounds_nothing(s, *b)) {
+        if let Some((verts, tris)) = surf.closed_mesh(params) {
+            let flip = if face.same_sense { 1.0 } else { -1.0 };
+            mesh.verts.extend(verts.into_iter()
+                .map(|v| mesh::Vertex { norm: v.norm * flip, ..v }));
+            let t_start = mesh.triangles.len();
+            for [a, b, c] in tris {
+                let [a, b, c] = [a, b, c].map(|i| (i + offset) as u32);
+                mesh.triangles.push(Triangle { verts:
+                    if face.same_sense {
+                        U32Vec3::new(a, b, c)
+                    } else {
+                        U32Vec3::new(a, c, b)
+                    }
+                });
+            }
+            mesh.faces.push(mesh::Face {
+                id: f.0, triangles: t_start..mesh.triangles.len(),
+                surface: surf.surface_type(),
+                dimensions: surf.dimensions(),
+                geometry: Some(std::sync::Arc::new(surf)),
+                transform: DMat4::identity(),
+                failed: false,
+                grid: None,
+            });
+            return Ok(());
+        }
+    }
+
     // For each contour, project from 3D down to the surface, then
     // start collecting them as constrained edges for triangulation
     let mut edges = Vec::new();
+    let mut loops = Vec::new(); // (point range, edge range) for each loop
     let v_start = mesh.verts.len();
     let mut num_pts = 0;
     for b in &face.bounds {
-        let bound_contours = face_bound(s, *b)?;
+        let bound_contours = face_bound(s, *b, params, angle, samples)?;
 
         match bound_contours.len() {
             // We should always have non-zero items in the contour
@@ -390,6 +905,7 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                     pos: bound_contours[0],
                     norm: DVec3::zeros(),
                     color: DVec3::new(0.0, 0.0, 0.0),
+                    uv: DVec2::zeros(),
                 });
             },
 
@@ -397,6 +913,7 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
             _ => {
                 // Record the initial point to close the loop
                 let start = num_pts;
+                let edge_start = edges.len();
                 for pt in bound_contours {
                     // The contour marches forward!
                     edges.push((num_pts, num_pts + 1));
@@ -406,6 +923,7 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                         pos: pt,
                         norm: DVec3::zeros(),
                         color: DVec3::new(0.0, 0.0, 0.0),
+                        uv: DVec2::zeros(),
                     });
                     num_pts += 1;
                 }
@@ -418,6 +936,7 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                 // Close the loop by returning to the starting point
                 edges.pop();
                 edges.last_mut().unwrap().1 = start;
+                loops.push((start..num_pts, edge_start..edges.len()));
             }
         }
     }
@@ -428,27 +947,116 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
     // assigning it to the first point in the list, which causes it to get
     // deduplicated), then retry.
     let mut pts = surf.lower_verts(&mut mesh.verts[v_start..])?;
+
+    // Rectangular faces on planes, cylinders, and cones may be built as
+    // structured grids of quads instead, for meshers which want them
+    if params.quad_grids {
+        let ranges: Vec<_> = loops.iter().map(|(p, _)| p.clone()).collect();
+        let grid = quad::structured_grid(&surf, &mut mesh.verts[v_start..],
+                                         &ranges, face.same_sense);
+        if let Some(grid) = grid {
+            mesh.verts.extend(grid.interior);
+            let t_start = mesh.triangles.len();
+            for q in grid.quads {
+                let [a, b, c, d] = q.map(|i| (i + offset) as u32);
+                mesh.triangles.push(Triangle { verts: U32Vec3::new(a, b, c) });
+                mesh.triangles.push(Triangle { verts: U32Vec3::new(a, c, d) });
+            }
+            mesh.faces.push(mesh::Face {
+                id: f.0, triangles: t_start..mesh.triangles.len(),
+                surface: surf.surface_type(),
+                dimensions: surf.dimensions(),
+                geometry: Some(std::sync::Arc::new(surf)),
+                transform: DMat4::identity(),
+                failed: false,
+                grid: Some(grid.size),
+            });
+            if !face.same_sense {
+                for v in &mut mesh.verts[v_start..] {
+                    v.norm = -v.norm;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    // Sloppy exporters sometimes emit trimming loops which overlap or cross
+    // themselves, which the odd-even fill below would turn into holes, so
+    // they're merged into clean loops first
+    let mirror = if face.same_sense { 1.0 } else { -1.0 };
+    let mut verts = mesh.verts.split_off(v_start);
+    if merge_loops(&mut pts, &mut verts, &mut edges, &mut loops, mirror) {
+        info!("Merged crossing trimming loops on face #{}", f.0);
+    }
+    mesh.verts.extend(verts);
     let bonus_points = pts.len();
-    surf.add_steiner_points(&mut pts, &mut mesh.verts);
-    let result = std::panic::catch_unwind(|| {
+
+    // Outer loops should run counter-clockwise in UV and holes clockwise
+    // (or the opposite, if the face is reversed relative to its surface, so
+    // we mirror the loops in that case).  The triangulation fills by odd-even
+    // counting, so loop senses don't change its output; a reversed loop is
+    // only reported, as a sign that the file's loop senses are wrong.  The
+    // nesting is still used by placeholder faces.
+    let mut rings: Vec<Vec<(f64, f64)>> = loops.iter()
+        .map(|(p, _)| pts[p.clone()].iter().map(|p| (p.0 * mirror, p.1)).collect())
+        .collect();
+    let ring_info = cdt::orient_rings(&mut rings);
+    let reversed = ring_info.iter().filter(|r| r.reversed).count();
+    if reversed > 0 {
+        warn!("{} of {} trimming loops on face #{} have the wrong sense",
+              reversed, loops.len(), f.0);
+    }
+
+    surf.add_steiner_points(&mut pts, &mut mesh.verts, params);
+    let steiner_points = pts.len();
+    let result = std::panic::catch_unwind(|| memory::stage(Stage::Triangulate, || {
         // TODO: this is only needed because we use pts below to save a debug
         // SVG if this panics.  Once we're confident in never panicking, we
         // can remove this.
         let mut pts = pts.clone();
+        let mut edges = edges.clone();
+        let mut crossings = Vec::new();
+        // Huge faces may be split across threads, which gives up (leaving
+        // the serial loop below to handle Steiner points on fixed edges and
+        // crossing edges) unless the boundaries are already Delaunay
+        if params.face_threads > 1 {
+            if let Some(t) = cdt::triangulate_with_edges_parallel(
+                &pts, &edges, params.face_threads)
+            {
+                return Ok((t, crossings));
+            }
+        }
         loop {
             let mut t = match cdt::Triangulation::new_with_edges(&pts, &edges) {
                 Err(e) => break Err(e),
                 Ok(t) => t,
             };
             match t.run() {
-                Ok(()) => break Ok(t),
+                Ok(()) => {
+                    memory::record_cdt(&t);
+                    break Ok((t.triangles().collect(), crossings));
+                },
                 // If triangulation failed due to a Steiner point on a fixed
                 // edge, then reassign that point to pts[0] (so it will be
                 // ignored as a duplicate)
-                Err(cdt::Error::PointOnFixedEdge(p)) if p >= bonus_points => {
+                Err(cdt::Error::PointOnFixedEdge(p))
+                    if p >= bonus_points && p < steiner_points =>
+                {
                     pts[p] = pts[0];
                     continue;
                 },
+                // Sloppy exporters sometimes emit trimming loops which cross
+                // each other; split them where they cross and try again.
+                Err(cdt::Error::CrossingFixedEdge) if crossings.is_empty() => {
+                    let (split, c) = cdt::split_crossing_edges(&pts, &edges);
+                    if c.is_empty() {
+                        break Err(cdt::Error::CrossingFixedEdge);
+                    }
+                    pts.extend(c.iter().map(|c| c.pos));
+                    crossings = c;
+                    edges = split;
+                    continue;
+                },
                 Err(e) => {
                     if SAVE_DEBUG_SVGS {
                         let filename = format!("err{}.svg", face.face_geometry.0);
@@ -459,10 +1067,37 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                 },
             }
         }
-    });
+    }));
     match result {
-        Ok(Ok(t)) => {
-            for (a, b, c) in t.triangles() {
+        Ok(Ok((triangles, crossings))) => {
+            // Points inserted at crossings are interpolated along the first
+            // of their two edges
+            for c in &crossings {
+                let (a, b) = edges[c.edges.0];
+                let (va, vb) = (mesh.verts[offset + a], mesh.verts[offset + b]);
+                mesh.verts.push(mesh::Vertex {
+                    pos: va.pos.lerp(&vb.pos, c.t.0),
+                    norm: va.norm.lerp(&vb.norm, c.t.0),
+                    color: va.color,
+                    uv: DVec2::zeros(),
+                });
+                pts.push(c.pos);
+            }
+
+            // Store the (normalized) surface parameters for each vertex
+            let (lo, hi) = pts.iter().fold(
+                (DVec2::repeat(f64::INFINITY), DVec2::repeat(-f64::INFINITY)),
+                |(lo, hi), p| {
+                    let p = DVec2::new(p.0, p.1);
+                    (lo.inf(&p), hi.sup(&p))
+                });
+            let range = (hi - lo).sup(&DVec2::repeat(f64::EPSILON));
+            for (v, p) in mesh.verts[offset..].iter_mut().zip(&pts) {
+                v.uv = (DVec2::new(p.0, p.1) - lo).component_div(&range);
+            }
+
+            let t_start = mesh.triangles.len();
+            for (a, b, c) in triangles {
                 let a = (a + offset) as u32;
                 let b = (b + offset) as u32;
                 let c = (c + offset) as u32;
@@ -474,11 +1109,23 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                     }
                 });
             }
+            mesh.faces.push(mesh::Face {
+                id: f.0, triangles: t_start..mesh.triangles.len(),
+                surface: surf.surface_type(),
+                dimensions: surf.dimensions(),
+                geometry: Some(std::sync::Arc::new(surf)),
+                transform: DMat4::identity(),
+                failed: false,
+                grid: None,
+            });
         },
         Ok(Err(e)) => {
             error!("Got error while triangulating {}: {:?}",
                    face.face_geometry.0, e);
             stats.num_errors += 1;
+            if params.placeholders {
+                placeholder_face(f, surf, &loops, &ring_info, offset, mesh);
+            }
         },
         Err(e) => {
             error!("Got panic while triangulating {}: {:?}",
@@ -489,6 +1136,9 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
                     .expect("Could not save debug SVG");
             }
             stats.num_panics += 1;
+            if params.placeholders {
+                placeholder_face(f, surf, &loops, &ring_info, offset, mesh);
+            }
         }
     }
     // Flip normals of new vertices, depending on the same_sense flag
@@ -500,7 +1150,141 @@ fn advanced_face(s: &StepFile, f: AdvancedFace, mesh: &mut Mesh,
     Ok(())
 }
 
-fn get_surface(s: &StepFile, surf: ap214::Surface) -> Result<Surface, Error> {
+/// Merges a face's trimming loops with [`cdt::polygon_boolean`] if any of
+/// them cross (including a loop crossing itself), so that overlapping
+/// regions are filled once rather than cancelling out.  The loops' own
+/// senses (mirrored if the face is reversed) decide what's inside, since
+/// nesting is ambiguous once loops cross.
+///
+/// On success, `pts`, `verts`, `edges`, and `loops` are replaced by the
+/// merged loops, followed by any points which weren't on a loop (e.g. a
+/// cone's apex), and this returns `true`.  Points where loops crossed are
+/// interpolated along the nearest original edge.  If the loops don't cross
+/// or can't be merged, they're left unchanged.
+fn merge_loops(pts: &mut Vec<(f64, f64)>, verts: &mut Vec<mesh::Vertex>,
+               edges: &mut Vec<(usize, usize)>,
+               loops: &mut Vec<(std::ops::Range<usize>, std::ops::Range<usize>)>,
+               mirror: f64) -> bool
+{
+    // Points which aren't finite are rejected by the triangulation instead
+    if pts.iter().any(|p| !p.0.is_finite() || !p.1.is_finite())
+        || cdt::split_crossing_edges(pts, edges).1.is_empty()
+    {
+        return false;
+    }
+    let rings: Vec<Vec<(f64, f64)>> = loops.iter()
+        .map(|(p, _)| pts[p.clone()].iter().map(|p| (p.0 * mirror, p.1)).collect())
+        .collect();
+    let empty: &[Vec<(f64, f64)>] = &[];
+    let merged = match std::panic::catch_unwind(||
+        cdt::polygon_boolean(&rings, empty, cdt::BooleanOp::Union))
+    {
+        Ok(Ok(merged)) if !merged.is_empty() => merged,
+        _ => return false,
+    };
+
+    // Points on the merged loops are matched to the originals by exact
+    // position (as in polygon_boolean)
+    let key = |p: (f64, f64)| ((p.0 + 0.0).to_bits(), (p.1 + 0.0).to_bits());
+    let index: HashMap<(u64, u64), usize> = loops.iter()
+        .flat_map(|(p, _)| p.clone())
+        .map(|i| (key(pts[i]), i))
+        .collect();
+    let vertex = |p: (f64, f64)| {
+        if let Some(&i) = index.get(&key(p)) {
+            return verts[i];
+        }
+        let (a, b, t) = edges.iter()
+            .map(|&(a, b)| {
+                let (pa, pb) = (DVec2::new(pts[a].0, pts[a].1),
+                                DVec2::new(pts[b].0, pts[b].1));
+                let d = pb - pa;
+                let t = ((DVec2::new(p.0, p.1) - pa).dot(&d) / d.norm_squared())
+                    .clamp(0.0, 1.0);
+                (a, b, t, (pa + d * t - DVec2::new(p.0, p.1)).norm())
+            })
+            .min_by(|x, y| x.3.partial_cmp(&y.3).unwrap())
+            .map(|(a, b, t, _)| (a, b, t))
+            .expect("Loops must have edges to cross");
+        let (va, vb) = (verts[a], verts[b]);
+        mesh::Vertex {
+            pos: va.pos.lerp(&vb.pos, t),
+            norm: va.norm.lerp(&vb.norm, t),
+            color: va.color,
+            uv: DVec2::zeros(),
+        }
+    };
+
+    let mut new_pts = Vec::new();
+    let mut new_verts = Vec::new();
+    let mut new_edges = Vec::new();
+    let mut new_loops = Vec::new();
+    for ring in &merged {
+        let start = new_pts.len();
+        let edge_start = new_edges.len();
+        for (i, p) in ring.iter().enumerate() {
+            let p = (p.0 * mirror, p.1);
+            new_pts.push(p);
+            new_verts.push(vertex(p));
+            new_edges.push((start + i, start + (i + 1) % ring.len()));
+        }
+        new_loops.push((start..new_pts.len(), edge_start..new_edges.len()));
+    }
+    let mut on_loop = vec![false; pts.len()];
+    for (p, _) in loops.iter() {
+        on_loop[p.clone()].iter_mut().for_each(|b| *b = true);
+    }
+    for (i, _) in on_loop.iter().enumerate().filter(|(_, b)| !**b) {
+        new_pts.push(pts[i]);
+        new_verts.push(verts[i]);
+    }
+    *pts = new_pts;
+    *verts = new_verts;
+    *edges = new_edges;
+    *loops = new_loops;
+    true
+}
+
+/// Stands in for a face which failed to triangulate, by fanning triangles
+/// over each of its outer boundary loops (skipping holes, which would
+/// otherwise be covered twice).  Loops don't have to be convex, so the fan
+/// may spill outside the face, but it shows where geometry is missing.
+fn placeholder_face(f: AdvancedFace, surf: Surface,
+                    loops: &[(std::ops::Range<usize>, std::ops::Range<usize>)],
+                    ring_info: &[cdt::RingInfo], offset: usize, mesh: &mut Mesh)
+{
+    let t_start = mesh.triangles.len();
+    for ((p, _), info) in loops.iter().zip(ring_info) {
+        if info.depth % 2 == 1 {
+            continue;
+        }
+        // Loops which were reversed by orient_rings wind the wrong way
+        let a = (offset + p.start) as u32;
+        for i in (p.start + 1)..p.end.saturating_sub(1) {
+            let b = (offset + i) as u32;
+            let c = b + 1;
+            mesh.triangles.push(Triangle { verts:
+                if info.reversed {
+                    U32Vec3::new(a, c, b)
+                } else {
+                    U32Vec3::new(a, b, c)
+                }
+            });
+        }
+    }
+    mesh.faces.push(mesh::Face {
+        id: f.0, triangles: t_start..mesh.triangles.len(),
+        surface: surf.surface_type(),
+        dimensions: surf.dimensions(),
+        geometry: Some(std::sync::Arc::new(surf)),
+        transform: DMat4::identity(),
+        failed: true,
+        grid: None,
+    });
+}
+
+/// Builds a surface, with angles scaled into radians by `angle`
+fn get_surface(s: &StepFile, surf: ap214::Surface, angle: f64) -> Result<Surface, Error> {
     match &s[surf] {
         Entity::CylindricalSurface(c) => {
             let (location, axis, ref_direction) = axis2_placement_3d(s, c.position);
@@ -516,10 +1300,13 @@ fn get_surface(s: &StepFile, surf: ap214::Surface) -> Result<Surface, Error> {
             let (location, axis, ref_direction) = axis2_placement_3d(s, p.position);
             Ok(Surface::new_plane(axis, ref_direction, location))
         },
-        // We treat cones like planes, since that's a valid mapping into 2D
+        // We treat cones like planes, since that's a valid mapping into 2D.
+        // The semi-angle is in the file's angle units, so it's converted
+        // into radians here.
         Entity::ConicalSurface(c) => {
             let (location, axis, ref_direction) = axis2_placement_3d(s, c.position);
-            Ok(Surface::new_cone(axis, ref_direction, location, c.semi_angle.0))
+            Ok(Surface::new_cone(axis, ref_direction, location, c.radius.0,
+                                 c.semi_angle.0 * angle))
         },
         Entity::SphericalSurface(c) => {
             // We'll ignore axis and ref_direction in favor of building an
@@ -626,7 +1413,9 @@ fn control_points_2d(s: &StepFile, rows: &Vec<Vec<CartesianPoint>>) -> Vec<Vec<D
         .collect()
 }
 
-fn face_bound(s: &StepFile, b: FaceBound) -> Result<Vec<DVec3>, Error> {
+fn face_bound(s: &StepFile, b: FaceBound, params: &TessellationParams,
+              angle: f64, samples: &mut EdgeSamples) -> Result<Vec<DVec3>, Error>
+{
     let (bound, orientation) = match &s[b] {
         Entity::FaceBound(b) => (b.bound, b.orientation),
         Entity::FaceOuterBound(b) => (b.bound, b.orientation),
@@ -634,7 +1423,7 @@ fn face_bound(s: &StepFile, b: FaceBound) -> Result<Vec<DVec3>, Error> {
     };
     match &s[bound] {
         Entity::EdgeLoop(e) => {
-            let mut d = edge_loop(s, &e.edge_list)?;
+            let mut d = edge_loop(s, &e.edge_list, params, angle, samples)?;
             if !orientation {
                 d.reverse()
             }
@@ -649,7 +1438,39 @@ fn face_bound(s: &StepFile, b: FaceBound) -> Result<Vec<DVec3>, Error> {
     }
 }
 
-fn edge_loop(s: &StepFile, edge_list: &[OrientedEdge])
+/// Checks whether a face bound leaves its surface whole: it's a single
+/// vertex, or an edge loop made only of degenerate edges and seams (which
+/// are walked once in each direction)
+fn bounds_nothing(s: &StepFile, b: FaceBound) -> bool {
+    let bound = match &s[b] {
+        Entity::FaceBound(b) => b.bound,
+        Entity::FaceOuterBound(b) => b.bound,
+        _ => return false,
+    };
+    let edges = match &s[bound] {
+        Entity::VertexLoop(_) => return true,
+        Entity::EdgeLoop(e) => &e.edge_list,
+        _ => return false,
+    };
+    edges.iter().all(|e| {
+        let edge = s.entity(*e).expect("Could not get OrientedEdge");
+        let element = edge.edge_element;
+        let degenerate = s.entity(element.cast::<EdgeCurve_>())
+            .is_some_and(|c| is_degenerate(s, c.edge_geometry));
+        degenerate || edges.iter()
+            .filter(|o| s.entity(**o).is_some_and(|o| o.edge_element == element))
+            .count() == 2
+    })
+}
+
+/// Checks for curves which collapse to a point, such as the edge at the
+/// pole of a sphere
+fn is_degenerate(s: &StepFile, c: ap214::Curve) -> bool {
+    matches!(&s[c], Entity::DegeneratePcurve(_) | Entity::EvaluatedDegeneratePcurve(_))
+}
+
+fn edge_loop(s: &StepFile, edge_list: &[OrientedEdge],
+             params: &TessellationParams, angle: f64, samples: &mut EdgeSamples)
     -> Result<Vec<DVec3>, Error>
 {
     let mut out = Vec::new();
@@ -660,42 +1481,81 @@ fn edge_loop(s: &StepFile, edge_list: &[OrientedEdge])
             out.pop();
         }
         let edge = s.entity(*e).expect("Could not get OrientedEdge");
-        let o = edge_curve(s, edge.edge_element.cast(), edge.orientation)?;
+        let o = edge_curve(s, edge.edge_element.cast(), edge.orientation,
+                           params, angle, samples)?;
         out.extend(o.into_iter());
     }
+    // Degenerate edges and zero-length curves leave repeated points, which
+    // would become zero-length constraints in the triangulation
+    out.dedup();
     Ok(out)
 }
 
-fn edge_curve(s: &StepFile, e: EdgeCurve, orientation: bool) -> Result<Vec<DVec3>, Error> {
+/// Samples an edge, walking it backwards if `orientation` is false.  The
+/// samples are stored in `samples` by the first face to use the edge, and
+/// reused by the others.
+fn edge_curve(s: &StepFile, e: EdgeCurve, orientation: bool,
+              params: &TessellationParams, angle: f64, samples: &mut EdgeSamples)
+    -> Result<Vec<DVec3>, Error>
+{
     let edge_curve = s.entity(e).expect("Could not get EdgeCurve");
-    let curve = curve(s, edge_curve, edge_curve.edge_geometry, orientation)?;
+    let (start, end) = (edge_curve.edge_start, edge_curve.edge_end);
+    if is_degenerate(s, edge_curve.edge_geometry) {
+        let v = if orientation { start } else { end };
+        return Ok(vec![vertex_point(s, v)]);
+    }
 
-    let (start, end) = if orientation {
-        (edge_curve.edge_start, edge_curve.edge_end)
-    } else {
-        (edge_curve.edge_end, edge_curve.edge_start)
+    let mut pts = match samples.get(&e.0) {
+        Some(pts) => pts.clone(),
+        None => {
+            let u = vertex_point(s, start);
+            let v = vertex_point(s, end);
+            // Some exporters close a curve with two distinct (but
+            // coincident) vertices, rather than reusing the same vertex at
+            // both ends
+            let closed = start == end
+                || params.uncertainty.map(|tol| (u - v).norm() <= tol).unwrap_or(false);
+            let curve = curve(s, edge_curve.edge_geometry, edge_curve.same_sense,
+                              closed, params, angle)?;
+            let pts = curve.build(u, v, params);
+            samples.insert(e.0, pts.clone());
+            pts
+        },
     };
-    let u = vertex_point(s, start);
-    let v = vertex_point(s, end);
-    Ok(curve.build(u, v))
+    if !orientation {
+        pts.reverse();
+    }
+    Ok(pts)
 }
 
-fn curve(s: &StepFile, edge_curve: &ap214::EdgeCurve_,
-         curve_id: ap214::Curve, orientation: bool) -> Result<Curve, Error>
+/// Converts a curve entity into a [`Curve`], which is walked along the
+/// curve's own direction if `dir` is true (and backwards otherwise).
+/// Angles in the file are scaled into radians by `angle`.
+fn curve(s: &StepFile, curve_id: ap214::Curve, dir: bool, closed: bool,
+         params: &TessellationParams, angle: f64) -> Result<Curve, Error>
 {
     Ok(match &s[curve_id] {
         Entity::Circle(c) => {
             let (location, axis, ref_direction) = axis2_placement_3d(s, c.position.cast());
             Curve::new_circle(location, axis, ref_direction, c.radius.0.0.0,
-                              edge_curve.edge_start == edge_curve.edge_end,
-                              edge_curve.same_sense ^ !orientation)
+                              closed, dir)
         },
         Entity::Ellipse(c) => {
             let (location, axis, ref_direction) = axis2_placement_3d(s, c.position.cast());
             Curve::new_ellipse(location, axis, ref_direction,
                                c.semi_axis_1.0.0.0, c.semi_axis_2.0.0.0,
-                               edge_curve.edge_start == edge_curve.edge_end,
-                               edge_curve.same_sense ^ !orientation)
+                               closed, dir)
+        },
+        // Hyperbolas and parabolas are open, so the edge's vertices are
+        // enough to know which way they're walked
+        Entity::Hyperbola(c) => {
+            let (location, axis, ref_direction) = axis2_placement_3d(s, c.position.cast());
+            Curve::new_hyperbola(location, axis, ref_direction,
+                                 c.semi_axis.0.0.0, c.semi_imag_axis.0.0.0)
+        },
+        Entity::Parabola(c) => {
+            let (location, axis, ref_direction) = axis2_placement_3d(s, c.position.cast());
+            Curve::new_parabola(location, axis, ref_direction, c.focal_dist.0)
         },
         Entity::BSplineCurveWithKnots(c) => {
             if c.closed_curve.0 != Some(false) {
@@ -757,14 +1617,25 @@ fn curve(s: &StepFile, edge_curve: &ap214::EdgeCurve_,
             );
             Curve::NURBSCurve(SampledCurve::new(curve))
         },
-        Entity::SurfaceCurve(v) => {
-            curve(s, edge_curve, v.curve_3d, orientation)?
-        },
-        Entity::SeamCurve(v) => {
-            curve(s, edge_curve, v.curve_3d, orientation)?
-        },
+        Entity::SurfaceCurve(_) | Entity::SeamCurve(_) | Entity::IntersectionCurve(_) =>
+            surface_curve(s, curve_id, dir, closed, params, angle)?,
         // The Line type ignores pnt / dir and just uses u and v
         Entity::Line(_) => Curve::new_line(),
+        Entity::Polyline(p) => {
+            let mut points = control_points_1d(s, &p.points);
+            if !dir {
+                points.reverse();
+            }
+            Curve::Polyline { points, closed }
+        },
+        // An edge's vertices already trim its curve, so only the direction
+        // matters here (trims are used to join the segments of composites)
+        Entity::TrimmedCurve(c) =>
+            curve(s, c.basis_curve, dir == c.sense_agreement, closed, params, angle)?,
+        Entity::CompositeCurve(c) =>
+            composite_curve(s, &c.segments, dir, closed, params, angle)?,
+        Entity::CompositeCurveOnSurface(c) =>
+            composite_curve(s, &c.segments, dir, closed, params, angle)?,
         e => {
             warn!("Could not get edge from {:?}", e);
             return Err(Error::UnknownCurveType);
@@ -772,6 +1643,257 @@ fn curve(s: &StepFile, edge_curve: &ap214::EdgeCurve_,
     })
 }
 
+/// Picks a representation for a curve which lies on one or two surfaces
+/// (a surface, seam, or intersection curve).  The pcurve named by the
+/// curve's preference is used if it can be evaluated, then the 3D curve,
+/// then any other pcurve (intersection curves are often written with a
+/// rough 3D approximation and exact pcurves, or the other way around).
+fn surface_curve(s: &StepFile, curve_id: ap214::Curve, dir: bool, closed: bool,
+                 params: &TessellationParams, angle: f64) -> Result<Curve, Error>
+{
+    let (curve_3d, associated, master) = match &s[curve_id] {
+        Entity::SurfaceCurve(v) =>
+            (v.curve_3d, &v.associated_geometry, &v.master_representation),
+        Entity::SeamCurve(v) =>
+            (v.curve_3d, &v.associated_geometry, &v.master_representation),
+        Entity::IntersectionCurve(v) =>
+            (v.curve_3d, &v.associated_geometry, &v.master_representation),
+        _ => return Err(Error::UnknownCurveType),
+    };
+    let preferred = match master {
+        PreferredSurfaceCurveRepresentation::PcurveS1 => Some(0),
+        PreferredSurfaceCurveRepresentation::PcurveS2 => Some(1),
+        _ => None,
+    };
+    let from_pcurve = |i: usize| match associated.get(i).map(|a| &s[*a]) {
+        Some(Entity::Pcurve(p)) => pcurve(s, p, dir, closed, params, angle),
+        _ => Err(Error::UnknownCurveType),
+    };
+    if let Some(c) = preferred.and_then(|i| from_pcurve(i).ok()) {
+        return Ok(c);
+    }
+    curve(s, curve_3d, dir, closed, params, angle).or_else(|e| {
+        (0..associated.len())
+            .filter(|i| Some(*i) != preferred)
+            .find_map(|i| from_pcurve(i).ok())
+            .ok_or(e)
+    })
+}
+
+/// Converts a pcurve (a 2D curve in a surface's parameter space) into a 3D
+/// polyline.  Only bounded curves are handled (polylines, B-splines, and
+/// trimmed curves); they're sampled in UV space, finely enough to meet the
+/// tolerance in 3D if there is one.
+fn pcurve(s: &StepFile, p: &Pcurve_, dir: bool, closed: bool,
+          params: &TessellationParams, angle: f64) -> Result<Curve, Error>
+{
+    let rep = s.entity(p.reference_to_curve)
+        .expect("Could not get DefinitionalRepresentation");
+    let uv_id: ap214::Curve = match rep.items.first() {
+        Some(i) => i.cast(),
+        None => return Err(Error::UnknownCurveType),
+    };
+    // Conics need 2D placements, which `curve` doesn't read
+    let bounded = |id: ap214::Curve| matches!(&s[id],
+        Entity::Polyline(_) | Entity::BSplineCurveWithKnots(_)
+        | Entity::ComplexEntity(_) | Entity::Line(_));
+    let ok = match &s[uv_id] {
+        Entity::TrimmedCurve(c) => bounded(c.basis_curve),
+        Entity::Line(_) => false,
+        _ => bounded(uv_id),
+    };
+    if !ok {
+        return Err(Error::UnknownCurveType);
+    }
+    let (a, b) = curve_ends(s, uv_id, angle)?;
+    let uv_curve = curve(s, uv_id, true, false, params, angle)?;
+    // Splines are built once here, rather than again for every sample
+    let spline = match &s[p.basis_surface] {
+        Entity::BSplineSurfaceWithKnots(_) | Entity::ComplexEntity(_) =>
+            get_surface(s, p.basis_surface, angle).ok(),
+        _ => None,
+    };
+    let raise = |uv: &[DVec3]| uv.iter()
+        .map(|q| surface_point(s, p.basis_surface, DVec2::new(q.x, q.y), angle,
+                               spline.as_ref()))
+        .collect::<Option<Vec<DVec3>>>()
+        .ok_or(Error::UnknownSurfaceType);
+
+    let uv = uv_curve.build(a, b, &TessellationParams::default());
+    let mut points = raise(&uv)?;
+    // The tolerance is in model units, so it's scaled into UV space by how
+    // much the surface stretches this curve
+    if let Some(tol) = params.tolerance {
+        let length = |p: &[DVec3]| p.windows(2).map(|w| (w[1] - w[0]).norm()).sum::<f64>();
+        let stretch = length(&points) / length(&uv).max(f64::EPSILON);
+        if stretch > 0.0 {
+            let uv_params = TessellationParams { tolerance: Some(tol / stretch), ..*params };
+            points = raise(&uv_curve.build(a, b, &uv_params))?;
+        }
+    }
+    if !dir {
+        points.reverse();
+    }
+    Ok(Curve::Polyline { points, closed })
+}
+
+/// Evaluates a surface at a point in its STEP parameter space, where
+/// angles are in the file's angle unit (scaled by `angle` into radians).
+/// Spline surfaces are evaluated with `spline`, which is built from `surf`
+/// by the caller.
+fn surface_point(s: &StepFile, surf: ap214::Surface, uv: DVec2, angle: f64,
+                 spline: Option<&Surface>) -> Option<DVec3>
+{
+    // Position on a surface of revolution, given as a radius, a height
+    // along the axis, and an angle around it
+    let revolve = |position, radius: f64, height: f64, u: f64| {
+        let (location, axis, ref_direction) = axis2_placement_3d(s, position);
+        let axis = axis.normalize();
+        let x = (ref_direction - axis * ref_direction.dot(&axis)).normalize();
+        let y = axis.cross(&x);
+        let u = u * angle;
+        location + (x * u.cos() + y * u.sin()) * radius + axis * height
+    };
+    Some(match &s[surf] {
+        Entity::Plane(p) => {
+            let (location, axis, ref_direction) = axis2_placement_3d(s, p.position);
+            let axis = axis.normalize();
+            let x = (ref_direction - axis * ref_direction.dot(&axis)).normalize();
+            location + x * uv.x + axis.cross(&x) * uv.y
+        },
+        Entity::CylindricalSurface(c) =>
+            revolve(c.position, c.radius.0.0.0, uv.y, uv.x),
+        Entity::ConicalSurface(c) => revolve(
+            c.position, c.radius.0 + uv.y * (c.semi_angle.0 * angle).tan(), uv.y, uv.x),
+        Entity::SphericalSurface(c) => {
+            let (r, v) = (c.radius.0.0.0, uv.y * angle);
+            revolve(c.position, r * v.cos(), r * v.sin(), uv.x)
+        },
+        Entity::ToroidalSurface(c) => {
+            let (r, v) = (c.minor_radius.0.0.0, uv.y * angle);
+            revolve(c.position, c.major_radius.0.0.0 + r * v.cos(), r * v.sin(), uv.x)
+        },
+        _ => match spline? {
+            Surface::BSpline(b) => b.surf.point(uv),
+            Surface::NURBS(b) => b.surf.point(uv),
+            _ => return None,
+        },
+    })
+}
+
+/// Builds a composite curve from its segments, which are bounded curves
+/// (typically trimmed curves or polylines) whose ends are found from their
+/// own geometry.  Where a segment continues smoothly into the next, the
+/// next segment starts exactly where it ends, which closes small gaps left
+/// by exporters; discontinuous transitions are joined by a straight line.
+fn composite_curve(s: &StepFile, segments: &[CompositeCurveSegment],
+                   dir: bool, closed: bool, params: &TessellationParams, angle: f64)
+    -> Result<Curve, Error>
+{
+    let mut parts = Vec::new();
+    for seg in segments {
+        let seg = s.entity(*seg).expect("Could not get CompositeCurveSegment");
+        let (mut a, mut b) = curve_ends(s, seg.parent_curve, angle)?;
+        if !seg.same_sense {
+            std::mem::swap(&mut a, &mut b);
+        }
+        let continuous = !matches!(seg.transition, TransitionCode::Discontinuous);
+        parts.push((seg, a, b, continuous));
+    }
+    // A segment's transition describes how it joins the following segment,
+    // so walking backwards uses the transition of each segment being added,
+    // rather than that of the segment before it
+    if !dir {
+        parts.reverse();
+        for p in parts.iter_mut() {
+            std::mem::swap(&mut p.1, &mut p.2);
+        }
+    }
+
+    let mut out: Vec<(Curve, DVec3, DVec3)> = Vec::new();
+    for (i, &(seg, mut a, b, _)) in parts.iter().enumerate() {
+        if let Some(prev) = out.last() {
+            let continuous = if dir { parts[i - 1].3 } else { parts[i].3 };
+            if continuous {
+                a = prev.2;
+            }
+        }
+        let seg_closed = (a - b).norm() <= 1e-9 * a.norm().max(b.norm()).max(1.0);
+        let c = curve(s, seg.parent_curve, seg.same_sense == dir, seg_closed, params, angle)?;
+        out.push((c, a, b));
+    }
+    Ok(Curve::Composite { segments: out, closed })
+}
+
+/// Returns the start and end points of a bounded curve, in the curve's own
+/// direction
+fn curve_ends(s: &StepFile, curve_id: ap214::Curve, angle: f64)
+    -> Result<(DVec3, DVec3), Error>
+{
+    match &s[curve_id] {
+        Entity::TrimmedCurve(c) =>
+            Ok((trim_point(s, c, &c.trim_1, angle)?, trim_point(s, c, &c.trim_2, angle)?)),
+        Entity::SurfaceCurve(v) => curve_ends(s, v.curve_3d, angle),
+        Entity::SeamCurve(v) => curve_ends(s, v.curve_3d, angle),
+        Entity::IntersectionCurve(v) => curve_ends(s, v.curve_3d, angle),
+        _ => curve(s, curve_id, true, false, &TessellationParams::default(), angle)?.ends().ok_or_else(|| {
+            warn!("Could not find the ends of {:?}", s[curve_id]);
+            Error::UnknownCurveType
+        }),
+    }
+}
+
+/// Finds one end of a trimmed curve, from either a point or a parameter on
+/// its basis curve (picking whichever the curve says is preferred, if it
+/// has both)
+fn trim_point(s: &StepFile, c: &TrimmedCurve_, trim: &[TrimmingSelect],
+              angle: f64) -> Result<DVec3, Error>
+{
+    let point = trim.iter().find_map(|t| match t {
+        TrimmingSelect::CartesianPoint(p) => Some(cartesian_point(s, *p)),
+        _ => None,
+    });
+    let param = trim.iter().find_map(|t| match t {
+        TrimmingSelect::ParameterValue(p) => Some(p.0),
+        _ => None,
+    });
+    let prefer_param = matches!(c.master_representation,
+                                TrimmingPreference::Parameter);
+    match (point, param) {
+        (Some(p), None) => Ok(p),
+        (Some(p), Some(_)) if !prefer_param => Ok(p),
+        (_, Some(t)) => curve_point(s, c.basis_curve, t, angle),
+        (None, None) => {
+            warn!("Trimmed curve has no trimming point or parameter");
+            Err(Error::UnknownCurveType)
+        },
+    }
+}
+
+/// Evaluates a curve at a parameter, where the angles which parameterize
+/// conics are scaled into radians by `angle`
+fn curve_point(s: &StepFile, curve_id: ap214::Curve, t: f64, angle: f64)
+    -> Result<DVec3, Error>
+{
+    let p = match &s[curve_id] {
+        Entity::Line(l) => {
+            let v = s.entity(l.dir).expect("Could not get Vector");
+            Some(cartesian_point(s, l.pnt) + direction(s, v.orientation) * v.magnitude.0 * t)
+        },
+        // Conics are parameterized by angle, in the file's angle unit
+        Entity::Circle(_) | Entity::Ellipse(_) => curve(s, curve_id, true, false, &TessellationParams::default(), angle)?
+            .point(t * angle),
+        Entity::SurfaceCurve(v) => return curve_point(s, v.curve_3d, t, angle),
+        Entity::SeamCurve(v) => return curve_point(s, v.curve_3d, t, angle),
+        Entity::IntersectionCurve(v) => return curve_point(s, v.curve_3d, t, angle),
+        _ => curve(s, curve_id, true, false, &TessellationParams::default(), angle)?.point(t),
+    };
+    p.ok_or_else(|| {
+        warn!("Could not evaluate {:?}", s[curve_id]);
+        Error::UnknownCurveType
+    })
+}
+
 fn vertex_point(s: &StepFile, v: Vertex) -> DVec3 {
     cartesian_point(s,
         s.entity(v.cast::<VertexPoint_>())
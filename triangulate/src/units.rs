@@ -0,0 +1,212 @@
+use step::{
+    ap214::{Entity, MeasureValue, SiPrefix, SiUnitName},
+    step_file::StepFile,
+};
+
+/// Conversion-based units may be defined in terms of each other, so we stop
+/// following them after this many steps (in case of a cycle)
+const MAX_DEPTH: usize = 8;
+
+/// Returns the length of one model unit in metres, from the first length
+/// unit declared by a `GLOBAL_UNIT_ASSIGNED_CONTEXT` in the file.  This
+/// handles SI units (with any prefix) and conversion-based units such as
+/// inches, which are defined as a multiple of another length unit.
+pub fn length_unit(s: &StepFile) -> Option<f64> {
+    global_unit(s, Quantity::Length)
+}
+
+/// Returns the size of one model angle unit in radians, from the file's
+/// global units (like [`length_unit`]).  Parameters of conics are angles
+/// in this unit.
+pub(crate) fn angle_unit(s: &StepFile) -> Option<f64> {
+    global_unit(s, Quantity::PlaneAngle)
+}
+
+/// Finds the first global unit of the given quantity
+fn global_unit(s: &StepFile, quantity: Quantity) -> Option<f64> {
+    s.0.iter()
+        .filter_map(|e| match e {
+            Entity::GlobalUnitAssignedContext(g) => Some(&g.units),
+            Entity::ComplexEntity(v) => v.iter().find_map(|e| match e {
+                Entity::GlobalUnitAssignedContext(g) => Some(&g.units),
+                _ => None,
+            }),
+            _ => None,
+        })
+        .flat_map(|units| units.iter())
+        .find_map(|u| unit_scale(s, u.0, quantity, 0))
+}
+
+/// Returns the size of a density unit in kg/m³, for a derived unit of mass
+/// per cubic length (such as grams per cubic centimetre), or `None` if the
+/// unit is anything else
+pub(crate) fn density_scale(s: &StepFile, id: usize) -> Option<f64> {
+    let elements = match s.0.get(id)? {
+        Entity::DerivedUnit(d) => &d.elements,
+        Entity::ComplexEntity(v) => v.iter().find_map(|e| match e {
+            Entity::DerivedUnit(d) => Some(&d.elements),
+            _ => None,
+        })?,
+        _ => return None,
+    };
+    let (mut scale, mut mass, mut length) = (1.0, 0.0, 0.0);
+    for e in elements {
+        let e = match s.0.get(e.0)? {
+            Entity::DerivedUnitElement(e) => e,
+            _ => return None,
+        };
+        if let Some(k) = unit_scale(s, e.unit.0, Quantity::Mass, 0) {
+            mass += e.exponent;
+            scale *= k.powf(e.exponent);
+        } else {
+            length += e.exponent;
+            scale *= unit_scale(s, e.unit.0, Quantity::Length, 0)?.powf(e.exponent);
+        }
+    }
+    if mass == 1.0 && length == -3.0 {
+        Some(scale)
+    } else {
+        None
+    }
+}
+
+/// Kinds of unit which can be converted into SI base units
+#[derive(Copy, Clone, PartialEq)]
+enum Quantity {
+    /// Converted into metres
+    Length,
+    /// Converted into kilograms
+    Mass,
+    /// Converted into radians
+    PlaneAngle,
+}
+
+/// Returns the size of the given unit in metres, kilograms or radians, or `None` if
+/// it isn't a unit of the given quantity
+fn unit_scale(s: &StepFile, id: usize, quantity: Quantity, depth: usize) -> Option<f64> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let parts = match s.0.get(id)? {
+        Entity::ComplexEntity(v) => v.as_slice(),
+        e => std::slice::from_ref(e),
+    };
+    parts.iter().find_map(|e| match e {
+        Entity::SiUnit(u) => {
+            let prefix = u.prefix.as_ref().map_or(1.0, prefix_scale);
+            match (&u.name, quantity) {
+                (SiUnitName::Metre, Quantity::Length) => Some(prefix),
+                (SiUnitName::Gram, Quantity::Mass) => Some(prefix * 1e-3),
+                (SiUnitName::Radian, Quantity::PlaneAngle) => Some(prefix),
+                _ => None,
+            }
+        },
+        Entity::ConversionBasedUnit(c) => {
+            let (value, unit) = match s.0.get(c.conversion_factor.0)? {
+                Entity::MeasureWithUnit(m) => (&m.value_component, m.unit_component),
+                Entity::LengthMeasureWithUnit(m) => (&m.value_component, m.unit_component),
+                Entity::MassMeasureWithUnit(m) => (&m.value_component, m.unit_component),
+                Entity::PlaneAngleMeasureWithUnit(m) => (&m.value_component, m.unit_component),
+                _ => return None,
+            };
+            let value = match value {
+                MeasureValue::LengthMeasure(m) => m.0,
+                MeasureValue::PositiveLengthMeasure(m) => m.0.0.0,
+                MeasureValue::MassMeasure(m) => m.0,
+                MeasureValue::PlaneAngleMeasure(m) => m.0,
+                _ => return None,
+            };
+            Some(value * unit_scale(s, unit.0, quantity, depth + 1)?)
+        },
+        _ => None,
+    })
+}
+
+fn prefix_scale(p: &SiPrefix) -> f64 {
+    match p {
+        SiPrefix::Exa => 1e18,
+        SiPrefix::Peta => 1e15,
+        SiPrefix::Tera => 1e12,
+        SiPrefix::Giga => 1e9,
+        SiPrefix::Mega => 1e6,
+        SiPrefix::Kilo => 1e3,
+        SiPrefix::Hecto => 1e2,
+        SiPrefix::Deca => 1e1,
+        SiPrefix::Deci => 1e-1,
+        SiPrefix::Centi => 1e-2,
+        SiPrefix::Milli => 1e-3,
+        SiPrefix::Micro => 1e-6,
+        SiPrefix::Nano => 1e-9,
+        SiPrefix::Pico => 1e-12,
+        SiPrefix::Femto => 1e-15,
+        SiPrefix::Atto => 1e-18,
+        SiPrefix::_Unused(_) => 1.0,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_of(data: &str) -> Option<f64> {
+        let flat = StepFile::strip_flatten(data.as_bytes());
+        let step = StepFile::parse(&flat);
+        length_unit(&step)
+    }
+
+    #[test]
+    fn si_units() {
+        let data = include_str!("../../examples/abstract_pca.step");
+        assert_eq!(unit_of(data), Some(1e-3));
+        let data = include_str!("../../examples/cuboid.step");
+        assert_eq!(unit_of(data), Some(1.0));
+    }
+
+    #[test]
+    fn inches() {
+        let data = "DATA;
+#1=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));
+#2=LENGTH_MEASURE_WITH_UNIT(LENGTH_MEASURE(25.4),#1);
+#3=DIMENSIONAL_EXPONENTS(1.,0.,0.,0.,0.,0.,0.);
+#4=(CONVERSION_BASED_UNIT('INCH',#2)LENGTH_UNIT()NAMED_UNIT(#3));
+#5=(NAMED_UNIT(*)PLANE_ANGLE_UNIT()SI_UNIT($,.RADIAN.));
+#6=(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNIT_ASSIGNED_CONTEXT((#5,#4))REPRESENTATION_CONTEXT('',''));
+ENDSEC;";
+        assert!((unit_of(data).unwrap() - 0.0254).abs() < 1e-12);
+    }
+
+    #[test]
+    fn degrees() {
+        let data = "DATA;
+#1=(NAMED_UNIT(*)PLANE_ANGLE_UNIT()SI_UNIT($,.RADIAN.));
+#2=PLANE_ANGLE_MEASURE_WITH_UNIT(PLANE_ANGLE_MEASURE(0.0174532925),#1);
+#3=DIMENSIONAL_EXPONENTS(0.,0.,0.,0.,0.,0.,0.);
+#4=(CONVERSION_BASED_UNIT('DEGREE',#2)NAMED_UNIT(#3)PLANE_ANGLE_UNIT());
+#5=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));
+#6=(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNIT_ASSIGNED_CONTEXT((#5,#4))REPRESENTATION_CONTEXT('',''));
+ENDSEC;";
+        let flat = StepFile::strip_flatten(data.as_bytes());
+        let step = StepFile::parse(&flat);
+        assert!((angle_unit(&step).unwrap() - 0.0174532925).abs() < 1e-12);
+        assert_eq!(length_unit(&step), Some(1e-3));
+    }
+
+    #[test]
+    fn density() {
+        let data = "DATA;
+#1=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.CENTI.,.METRE.));
+#2=(MASS_UNIT()NAMED_UNIT(*)SI_UNIT($,.GRAM.));
+#3=DERIVED_UNIT_ELEMENT(#2,1.);
+#4=DERIVED_UNIT_ELEMENT(#1,-3.);
+#5=DERIVED_UNIT((#3,#4));
+#6=DERIVED_UNIT((#3));
+ENDSEC;";
+        let flat = StepFile::strip_flatten(data.as_bytes());
+        let step = StepFile::parse(&flat);
+        // One gram per cubic centimetre is 1000 kg/m³
+        assert!((density_scale(&step, 5).unwrap() - 1000.0).abs() < 1e-9);
+        assert_eq!(density_scale(&step, 6), None);
+    }
+}
@@ -0,0 +1,148 @@
+use nalgebra_glm::DVec3;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{bvh::Bvh, grid, mesh::Mesh, sdf::DistanceField};
+
+/// Which voxels of a grid are inside the model, e.g. to preview the layers
+/// of a 3D print
+#[derive(Clone, Debug)]
+pub struct Occupancy {
+    /// Corner of the first voxel, i.e. the low corner of the grid
+    pub origin: DVec3,
+    /// Size of a voxel along each axis, where Z is the layer height
+    pub spacing: DVec3,
+    /// Number of voxels along each axis
+    pub dims: [usize; 3],
+    /// Whether each voxel's center is inside, with X varying fastest and
+    /// then Y
+    pub filled: Vec<bool>,
+}
+
+impl Occupancy {
+    /// Fills a grid which covers the mesh, with square `pixel`-sized voxels
+    /// in each layer and layers `layer` apart along Z.
+    ///
+    /// The solids must be closed.  Each column of voxels is filled between
+    /// pairs of crossings of a ray straight up through its centers, so this
+    /// is much faster than sampling a [`DistanceField`].
+    pub fn new(mesh: &Mesh, pixel: f64, layer: f64) -> Self {
+        assert!(pixel > 0.0 && layer > 0.0, "Voxel size must be positive");
+        let bvh = Bvh::new(mesh);
+        let bounds = bvh.bounds();
+        let (lo, hi) = if mesh.triangles.is_empty() {
+            (DVec3::zeros(), DVec3::zeros())
+        } else {
+            (bounds.min, bounds.max)
+        };
+        let spacing = DVec3::new(pixel, pixel, layer);
+        let dims = [0, 1, 2].map(|i|
+            (((hi[i] - lo[i]) / spacing[i]).ceil() as usize).max(1));
+        // Center the grid on the model
+        let extent = DVec3::new(dims[0] as f64, dims[1] as f64, dims[2] as f64)
+            .component_mul(&spacing);
+        let origin = (lo + hi - extent) / 2.0;
+
+        // Hits closer together than this are treated as the same crossing,
+        // e.g. where a ray passes through an edge between two triangles
+        let eps = (hi - lo).norm() * 1e-9;
+        let start = lo.z - (hi.z - lo.z).max(1.0);
+        let row = |y: usize| {
+            let mut out = vec![false; dims[0] * dims[2]];
+            for x in 0..dims[0] {
+                let p = origin + DVec3::new(x as f64 + 0.5, y as f64 + 0.5, 0.0)
+                    .component_mul(&spacing);
+                let mut z: Vec<f64> = Vec::new();
+                for h in bvh.raycast_all(DVec3::new(p.x, p.y, start), DVec3::z()) {
+                    let d = h.distance + start;
+                    if z.last().map(|l| d - l > eps).unwrap_or(true) {
+                        z.push(d);
+                    }
+                }
+                // An unpaired crossing (e.g. from a gap in the mesh) is
+                // ignored, rather than filling the rest of the column
+                for span in z.chunks_exact(2) {
+                    let a = ((span[0] - origin.z) / layer - 0.5).ceil().max(0.0) as usize;
+                    let b = ((span[1] - origin.z) / layer - 0.5).floor();
+                    if b < 0.0 {
+                        continue;
+                    }
+                    for k in a..=(b as usize).min(dims[2] - 1) {
+                        out[x + dims[0] * k] = true;
+                    }
+                }
+            }
+            out
+        };
+        let rows: Vec<Vec<bool>> = {
+            #[cfg(feature = "rayon")]
+            { (0..dims[1]).into_par_iter().map(row).collect() }
+            #[cfg(not(feature = "rayon"))]
+            { (0..dims[1]).map(row).collect() }
+        };
+
+        // Rows are built as XZ slices, so reorder them into layers
+        let mut filled = vec![false; dims[0] * dims[1] * dims[2]];
+        for (y, r) in rows.iter().enumerate() {
+            for k in 0..dims[2] {
+                let src = &r[dims[0] * k..dims[0] * (k + 1)];
+                let i = dims[0] * (y + dims[1] * k);
+                filled[i..i + dims[0]].copy_from_slice(src);
+            }
+        }
+        Self { origin, spacing, dims, filled }
+    }
+
+    /// Marks the samples of a distance field which are inside the model,
+    /// with a voxel centered on each sample
+    pub fn from_field(field: &DistanceField) -> Self {
+        let spacing = DVec3::repeat(field.spacing);
+        Self {
+            origin: field.origin - spacing / 2.0,
+            spacing,
+            dims: field.dims,
+            filled: field.values.iter().map(|v| *v < 0.0).collect(),
+        }
+    }
+
+    /// Returns whether the voxel at the given grid position is filled
+    pub fn get(&self, x: usize, y: usize, z: usize) -> bool {
+        self.filled[x + self.dims[0] * (y + self.dims[1] * z)]
+    }
+
+    /// Returns the number of filled voxels
+    pub fn count(&self) -> usize {
+        self.filled.iter().filter(|f| **f).count()
+    }
+
+    /// Returns the volume of the filled voxels
+    pub fn volume(&self) -> f64 {
+        self.count() as f64 * self.spacing.x * self.spacing.y * self.spacing.z
+    }
+
+    /// Returns one layer as an 8-bit grayscale image (255 where filled),
+    /// with rows from top to bottom so that +Y points up
+    pub fn layer_image(&self, z: usize) -> Vec<u8> {
+        let [nx, ny, _] = self.dims;
+        let mut out = Vec::with_capacity(nx * ny);
+        for y in (0..ny).rev() {
+            out.extend((0..nx).map(|x| if self.get(x, y, z) { 255 } else { 0 }));
+        }
+        out
+    }
+
+    /// Writes the grid as raw bytes, 1 where filled and 0 elsewhere, with X
+    /// varying fastest (the grid's size and placement aren't recorded)
+    pub fn save_raw(&self, filename: &str) -> std::io::Result<()> {
+        grid::save_raw(filename, self.filled.iter().map(|f| *f as u8))
+    }
+
+    /// Writes the grid as a NRRD volume of bytes (1 where filled), whose
+    /// header gives the grid's size and placement
+    pub fn save_nrrd(&self, filename: &str) -> std::io::Result<()> {
+        // NRRD places samples at voxel centers
+        grid::save_nrrd(filename, self.origin + self.spacing / 2.0, self.spacing,
+                        self.dims, self.filled.iter().map(|f| *f as u8))
+    }
+}
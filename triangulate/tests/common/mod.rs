@@ -0,0 +1,74 @@
+//! Helpers shared by the integration tests.  Each test file only uses some
+//! of them, so unused ones aren't warnings.
+#![allow(dead_code)]
+use std::fmt::Write;
+
+use nalgebra_glm::DVec3;
+use step::step_file::StepFile;
+use triangulate::{mesh::Mesh, triangulate::triangulate};
+
+/// Parses and triangulates STEP data with the default settings
+pub fn load(data: &str) -> Mesh {
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    triangulate(&step).0
+}
+
+/// Like [`load`], but checks that every face was triangulated
+pub fn load_cleanly(data: &str) -> Mesh {
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, stats) = triangulate(&step);
+    assert_eq!(stats.num_errors, 0);
+    assert_eq!(stats.num_panics, 0);
+    mesh
+}
+
+/// Returns the lower and upper corners of the mesh's bounding box
+pub fn bounds(mesh: &Mesh) -> (DVec3, DVec3) {
+    mesh.verts.iter().fold(
+        (DVec3::repeat(f64::INFINITY), DVec3::repeat(-f64::INFINITY)),
+        |(lo, hi), v| (lo.inf(&v.pos), hi.sup(&v.pos)))
+}
+
+/// Returns STEP data for a flat face in the XY plane bounded by the given
+/// polygons, each of which becomes an edge loop of straight edges (in the
+/// order given)
+pub fn polygon_face(polygons: &[&[(f64, f64)]]) -> String {
+    let mut data = String::from("DATA;
+#1=SHELL_BASED_SURFACE_MODEL('',(#2));
+#2=OPEN_SHELL('',(#3));
+#20=PLANE('',#21);
+#21=AXIS2_PLACEMENT_3D('',#22,#23,#24);
+#22=CARTESIAN_POINT('',(0.,0.,0.));
+#23=DIRECTION('',(0.,0.,1.));
+#24=DIRECTION('',(1.,0.,0.));
+");
+    let mut bounds = Vec::new();
+    // Each loop gets its own block of entity ids, with five per point
+    let mut b = 100;
+    for poly in polygons {
+        let n = poly.len();
+        let id = |kind: usize, i: usize| b + 10 + kind * n + i;
+        bounds.push(format!("#{}", b));
+        let edges: Vec<String> = (0..n).map(|i| format!("#{}", id(0, i))).collect();
+        writeln!(data, "#{}=FACE_BOUND('',#{},.T.);", b, b + 1).unwrap();
+        writeln!(data, "#{}=EDGE_LOOP('',({}));", b + 1, edges.join(",")).unwrap();
+        for (i, (x, y)) in poly.iter().enumerate() {
+            let j = (i + 1) % n;
+            writeln!(data, "#{}=ORIENTED_EDGE('',*,*,#{},.T.);", id(0, i), id(4, i))
+                .unwrap();
+            writeln!(data, "#{}=CARTESIAN_POINT('',({:?},{:?},0.));", id(1, i), x, y)
+                .unwrap();
+            writeln!(data, "#{}=VERTEX_POINT('',#{});", id(2, i), id(1, i)).unwrap();
+            writeln!(data, "#{}=POLYLINE('',(#{},#{}));", id(3, i), id(1, i), id(1, j))
+                .unwrap();
+            writeln!(data, "#{}=EDGE_CURVE('',#{},#{},#{},.T.);",
+                     id(4, i), id(2, i), id(2, j), id(3, i)).unwrap();
+        }
+        b = id(5, 0);
+    }
+    writeln!(data, "#3=ADVANCED_FACE('',({}),#20,.T.);\nENDSEC;", bounds.join(","))
+        .unwrap();
+    data
+}
@@ -0,0 +1,137 @@
+//! Tests for edges along composite and trimmed curves
+use std::collections::HashMap;
+
+use step::step_file::StepFile;
+use triangulate::{
+    mass::mass_properties,
+    triangulate::{triangulate, triangulate_with, TessellationParams},
+};
+
+/// `examples/cuboid.step`, with two of its edges split into composite
+/// curves: one made of polylines, and one made of lines trimmed by
+/// parameter.  In each, the second segment runs backwards.
+fn composite_cuboid() -> String {
+    let data = include_str!("../../examples/cuboid.step");
+    data.replace("#64=LINE('',#159,#76);", "
+#64=COMPOSITE_CURVE('',(#601,#602),.F.);
+#601=COMPOSITE_CURVE_SEGMENT(.CONTINUOUS.,.T.,#603);
+#602=COMPOSITE_CURVE_SEGMENT(.CONTINUOUS.,.F.,#605);
+#603=POLYLINE('',(#160,#604));
+#604=CARTESIAN_POINT('',(0.,0.0127,0.));
+#605=POLYLINE('',(#161,#604));")
+        .replace("#65=LINE('',#162,#77);", "
+#65=COMPOSITE_CURVE('',(#610,#611),.F.);
+#610=COMPOSITE_CURVE_SEGMENT(.CONTINUOUS.,.T.,#612);
+#611=COMPOSITE_CURVE_SEGMENT(.CONTINUOUS.,.F.,#613);
+#612=TRIMMED_CURVE('',#614,(PARAMETER_VALUE(0.)),(PARAMETER_VALUE(0.03)),.T.,.PARAMETER.);
+#613=TRIMMED_CURVE('',#614,(PARAMETER_VALUE(0.0762)),(PARAMETER_VALUE(0.03)),.F.,.PARAMETER.);
+#614=LINE('',#162,#77);")
+}
+
+#[test]
+fn composite_edges() {
+    let data = composite_cuboid();
+    assert!(data.contains("#614=LINE"));
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, stats) = triangulate(&step);
+    assert_eq!(stats.num_errors, 0);
+    assert_eq!(mesh.solids.len(), 1);
+
+    // The joints between segments become vertices of the mesh
+    let has_vertex = |x: f64, y: f64, z: f64| mesh.verts.iter()
+        .any(|v| (v.pos.x - x).abs() < 1e-9 && (v.pos.y - y).abs() < 1e-9
+                 && (v.pos.z - z).abs() < 1e-9);
+    assert!(has_vertex(0.0, 0.0127, 0.0));
+    assert!(has_vertex(0.0, 0.0, 0.0762 - 0.03));
+
+    // The block is still closed, with the same area and volume
+    let size = [0.0508, 0.0254, 0.0762];
+    let area = 2.0 * (size[0] * size[1] + size[1] * size[2] + size[2] * size[0]);
+    let solid = &mesh.solids[0];
+    assert!((mesh.area(solid.triangles.clone()) - area).abs() < 1e-12);
+    let p = mass_properties(&mesh, [(solid.triangles.clone(), 1.0)], 1.0)
+        .expect("Could not compute mass properties");
+    assert!((p.volume - size[0] * size[1] * size[2]).abs() < 1e-12);
+}
+
+/// A flat face in the XY plane, bounded by the parabola `x = y² / 2` and
+/// the line `x = 0.5`, with the given entity as the parabolic edge's curve
+fn parabolic_face(curve: &str) -> String {
+    format!("DATA;
+#1=SHELL_BASED_SURFACE_MODEL('',(#2));
+#2=OPEN_SHELL('',(#3));
+#3=ADVANCED_FACE('',(#4),#20,.T.);
+#4=FACE_OUTER_BOUND('',#5,.T.);
+#5=EDGE_LOOP('',(#6,#7));
+#6=ORIENTED_EDGE('',*,*,#8,.T.);
+#7=ORIENTED_EDGE('',*,*,#9,.T.);
+#8=EDGE_CURVE('',#10,#11,#14,.T.);
+#9=EDGE_CURVE('',#11,#10,#16,.T.);
+#10=VERTEX_POINT('',#12);
+#11=VERTEX_POINT('',#13);
+#12=CARTESIAN_POINT('',(0.5,-1.,0.));
+#13=CARTESIAN_POINT('',(0.5,1.,0.));
+#14={}
+#15=PARABOLA('',#19,0.5);
+#16=LINE('',#13,#17);
+#17=VECTOR('',#24,1.);
+#18=COMPOSITE_CURVE_SEGMENT(.CONTINUOUS.,.T.,#25);
+#19=AXIS2_PLACEMENT_3D('',#21,#22,#23);
+#20=PLANE('',#19);
+#21=CARTESIAN_POINT('',(0.,0.,0.));
+#22=DIRECTION('',(0.,0.,1.));
+#23=DIRECTION('',(1.,0.,0.));
+#24=DIRECTION('',(0.,-1.,0.));
+#25=TRIMMED_CURVE('',#15,(PARAMETER_VALUE(-1.)),(PARAMETER_VALUE(1.)),.T.,.PARAMETER.);
+ENDSEC;", curve)
+}
+
+#[test]
+fn parabola() {
+    // The parabola on its own, and as a single trimmed segment
+    for curve in ["PARABOLA('',#19,0.5);", "COMPOSITE_CURVE('',(#18),.F.);"] {
+        let data = parabolic_face(curve);
+        let flat = StepFile::strip_flatten(data.as_bytes());
+        let step = StepFile::parse(&flat);
+        let (mesh, stats) = triangulate(&step);
+        assert_eq!(stats.num_errors, 0, "{}", curve);
+        assert!(mesh.verts.iter().all(|v| v.pos.x >= v.pos.y * v.pos.y / 2.0 - 1e-9));
+
+        // A parabolic segment covers two thirds of its bounding rectangle
+        let area = mesh.area(0..mesh.triangles.len());
+        assert!((area - 2.0 / 3.0).abs() < 0.01, "{}: area is {}", curve, area);
+    }
+}
+
+#[test]
+fn intersection_curve() {
+    // The parabola as a quadratic B-spline in the plane's parameter space,
+    // attached to intersection curves whose 3D curve is either a straight
+    // (wrong) line or something which can't be evaluated.  The pcurve is
+    // used when it's preferred, or when the 3D curve is unusable.
+    let pcurve = "
+#30=PCURVE('',#20,#31);
+#31=DEFINITIONAL_REPRESENTATION('',(#32),#33);
+#32=B_SPLINE_CURVE_WITH_KNOTS('',2,(#34,#35,#36),.UNSPECIFIED.,.F.,.F.,(3,3),(0.,1.),.UNSPECIFIED.);
+#33=(GEOMETRIC_REPRESENTATION_CONTEXT(2)PARAMETRIC_REPRESENTATION_CONTEXT()REPRESENTATION_CONTEXT('2D SPACE',''));
+#34=CARTESIAN_POINT('',(0.5,-1.));
+#35=CARTESIAN_POINT('',(-0.5,0.));
+#36=CARTESIAN_POINT('',(0.5,1.));
+#37=LINE('',#12,#38);
+#38=VECTOR('',#39,1.);
+#39=DIRECTION('',(0.,1.,0.));
+#40=OFFSET_CURVE_3D('',#15,0.1,.F.,#22);
+ENDSEC;";
+    for curve in ["INTERSECTION_CURVE('',#37,(#30,#20),.PCURVE_S1.);",
+                  "INTERSECTION_CURVE('',#40,(#20,#30),.CURVE_3D.);"] {
+        let data = parabolic_face(curve).replace("ENDSEC;", pcurve);
+        let flat = StepFile::strip_flatten(data.as_bytes());
+        let step = StepFile::parse(&flat);
+        let params = TessellationParams { tolerance: Some(1e-4), ..Default::default() };
+        let (mesh, stats) = triangulate_with(&step, params, &HashMap::new());
+        assert_eq!(stats.num_errors, 0, "{}", curve);
+        let area = mesh.area(0..mesh.triangles.len());
+        assert!((area - 2.0 / 3.0).abs() < 1e-3, "{}: area is {}", curve, area);
+    }
+}
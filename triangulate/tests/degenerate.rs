@@ -0,0 +1,147 @@
+//! Tests for faces whose bounds are a single vertex, or include degenerate
+//! edges (such as the apex of a cone or the poles of a sphere)
+use std::f64::consts::PI;
+
+use triangulate::{mass::mass_properties, mesh::{Dimensions, Mesh}};
+
+mod common;
+use common::load_cleanly as load;
+
+/// Checks that a unit sphere at the origin is closed, with outward normals
+fn check_sphere(mesh: &Mesh) {
+    assert_eq!(mesh.solids.len(), 1);
+    let solid = &mesh.solids[0];
+    let area = mesh.area(solid.triangles.clone());
+    assert!((area - 4.0 * PI).abs() < 0.05, "Area is {}", area);
+    let p = mass_properties(mesh, [(solid.triangles.clone(), 1.0)], 1.0)
+        .expect("Could not compute mass properties");
+    assert!((p.volume - 4.0 / 3.0 * PI).abs() < 0.05, "Volume is {}", p.volume);
+    assert!(p.center.norm() < 1e-9);
+    for v in &mesh.verts {
+        assert!((v.pos.norm() - 1.0).abs() < 1e-9);
+        assert!((v.norm - v.pos).norm() < 1e-9);
+    }
+    // Triangles wind outwards
+    for t in &mesh.triangles {
+        let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+            .map(|i| mesh.verts[i as usize].pos);
+        assert!((b - a).cross(&(c - a)).dot(&(a + b + c)) > 0.0);
+    }
+}
+
+const SPHERE: &str = "DATA;
+#1=MANIFOLD_SOLID_BREP('',#2);
+#2=CLOSED_SHELL('',(#3));
+#3=ADVANCED_FACE('',(#4),#10,.T.);
+#4=FACE_BOUND('',#5,.T.);
+#5=VERTEX_LOOP('',#6);
+#6=VERTEX_POINT('',#7);
+#7=CARTESIAN_POINT('',(0.,0.,1.));
+#10=SPHERICAL_SURFACE('',#11,1.);
+#11=AXIS2_PLACEMENT_3D('',#12,#13,#14);
+#12=CARTESIAN_POINT('',(0.,0.,0.));
+#13=DIRECTION('',(0.,0.,1.));
+#14=DIRECTION('',(1.,0.,0.));
+ENDSEC;";
+
+#[test]
+fn vertex_loop_sphere() {
+    check_sphere(&load(SPHERE));
+}
+
+#[test]
+fn seam_sphere() {
+    // The same sphere, bounded by a seam from pole to pole (walked down one
+    // side and back up the other) and degenerate edges at each pole
+    let data = SPHERE.replace("#4=FACE_BOUND('',#5,.T.);", "
+#4=FACE_BOUND('',#20,.T.);
+#20=EDGE_LOOP('',(#21,#22,#23,#24));
+#21=ORIENTED_EDGE('',*,*,#30,.T.);
+#22=ORIENTED_EDGE('',*,*,#31,.T.);
+#23=ORIENTED_EDGE('',*,*,#32,.T.);
+#24=ORIENTED_EDGE('',*,*,#31,.F.);
+#30=EDGE_CURVE('',#6,#6,#40,.T.);
+#31=EDGE_CURVE('',#6,#33,#41,.T.);
+#32=EDGE_CURVE('',#33,#33,#42,.T.);
+#33=VERTEX_POINT('',#34);
+#34=CARTESIAN_POINT('',(0.,0.,-1.));
+#40=DEGENERATE_PCURVE('',#10,#43);
+#41=SEAM_CURVE('',#44,(#10,#10),.CURVE_3D.);
+#42=DEGENERATE_PCURVE('',#10,#43);
+#43=DEFINITIONAL_REPRESENTATION('',(),#45);
+#44=CIRCLE('',#46,1.);
+#45=(GEOMETRIC_REPRESENTATION_CONTEXT(2)PARAMETRIC_REPRESENTATION_CONTEXT()REPRESENTATION_CONTEXT('2D SPACE',''));
+#46=AXIS2_PLACEMENT_3D('',#12,#47,#14);
+#47=DIRECTION('',(0.,-1.,0.));");
+    check_sphere(&load(&data));
+}
+
+/// A cone from a unit circle at z = 0 down to its apex at z = -1, bounded by
+/// the base circle, a seam up to the apex, and a degenerate edge there
+const CONE: &str = "DATA;
+#1=SHELL_BASED_SURFACE_MODEL('',(#2));
+#2=OPEN_SHELL('',(#3));
+#3=ADVANCED_FACE('',(#4),#10,.T.);
+#4=FACE_OUTER_BOUND('',#5,.T.);
+#5=EDGE_LOOP('',(#20,#21,#22,#23));
+#20=ORIENTED_EDGE('',*,*,#30,.T.);
+#21=ORIENTED_EDGE('',*,*,#31,.T.);
+#22=ORIENTED_EDGE('',*,*,#32,.T.);
+#23=ORIENTED_EDGE('',*,*,#31,.F.);
+#30=EDGE_CURVE('',#40,#40,#50,.T.);
+#31=EDGE_CURVE('',#40,#41,#51,.T.);
+#32=EDGE_CURVE('',#41,#41,#52,.T.);
+#40=VERTEX_POINT('',#60);
+#41=VERTEX_POINT('',#61);
+#50=CIRCLE('',#11,1.);
+#51=LINE('',#60,#53);
+#52=DEGENERATE_PCURVE('',#10,#55);
+#53=VECTOR('',#54,1.);
+#54=DIRECTION('',(-0.7071067811865476,0.,-0.7071067811865476));
+#55=DEFINITIONAL_REPRESENTATION('',(),#57);
+#57=(GEOMETRIC_REPRESENTATION_CONTEXT(2)PARAMETRIC_REPRESENTATION_CONTEXT()REPRESENTATION_CONTEXT('2D SPACE',''));
+#60=CARTESIAN_POINT('',(1.,0.,0.));
+#61=CARTESIAN_POINT('',(0.,0.,-1.));
+#10=CONICAL_SURFACE('',#11,1.,0.785398163397);
+#11=AXIS2_PLACEMENT_3D('',#12,#13,#14);
+#12=CARTESIAN_POINT('',(0.,0.,0.));
+#13=DIRECTION('',(0.,0.,1.));
+#14=DIRECTION('',(1.,0.,0.));
+ENDSEC;";
+
+/// Checks the cone built from [`CONE`]
+fn check_cone(mesh: &Mesh) {
+    // The lateral area of a cone is pi * radius * slant height
+    let area = mesh.area(0..mesh.triangles.len());
+    let expected = PI * 2f64.sqrt();
+    assert!((area - expected).abs() < 0.05, "Area is {}", area);
+    assert!(mesh.verts.iter().any(|v| (v.pos.z + 1.0).abs() < 1e-9));
+    match mesh.faces[0].dimensions {
+        Some(Dimensions::Cone { radius, half_angle }) => {
+            assert_eq!(radius, 1.0);
+            assert!((half_angle - PI / 4.0).abs() < 1e-9, "{}", half_angle);
+        },
+        d => panic!("Unexpected dimensions {:?}", d),
+    }
+}
+
+#[test]
+fn cone_apex() {
+    check_cone(&load(CONE));
+}
+
+#[test]
+fn cone_degrees() {
+    // The same cone, in a file whose angles are in degrees
+    let data = CONE
+        .replace("1.,0.785398163397);", "1.,45.);")
+        .replace("ENDSEC;", "\
+#90=(NAMED_UNIT(*)PLANE_ANGLE_UNIT()SI_UNIT($,.RADIAN.));
+#91=PLANE_ANGLE_MEASURE_WITH_UNIT(PLANE_ANGLE_MEASURE(0.0174532925199433),#90);
+#92=DIMENSIONAL_EXPONENTS(0.,0.,0.,0.,0.,0.,0.);
+#93=(CONVERSION_BASED_UNIT('DEGREE',#91)NAMED_UNIT(#92)PLANE_ANGLE_UNIT());
+#94=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.MILLI.,.METRE.));
+#95=(GEOMETRIC_REPRESENTATION_CONTEXT(3)GLOBAL_UNIT_ASSIGNED_CONTEXT((#94,#93))REPRESENTATION_CONTEXT('',''));
+ENDSEC;");
+    check_cone(&load(&data));
+}
@@ -0,0 +1,57 @@
+//! Tests for skipping 2D drawing (draughting) entities
+use std::collections::HashMap;
+
+use step::step_file::StepFile;
+use triangulate::triangulate::{triangulate, triangulate_with, TessellationParams};
+
+/// `examples/cuboid.step` with a draughting model holding a single leader
+/// line along one edge of the block and an annotation circle
+fn annotated_cuboid() -> String {
+    let data = include_str!("../../examples/cuboid.step");
+    let end = data.rfind("ENDSEC;").unwrap();
+    format!("{}{}{}", &data[..end], "
+#500=DRAUGHTING_MODEL('',(#501,#504),#185);
+#501=ANNOTATION_CURVE_OCCURRENCE('',(#505),#502);
+#502=GEOMETRIC_CURVE_SET('',(#503));
+#503=POLYLINE('',(#160,#161));
+#504=ANNOTATION_OCCURRENCE('',(#505),#506);
+#505=PRESENTATION_STYLE_ASSIGNMENT(());
+#506=CIRCLE('',#507,0.01);
+#507=AXIS2_PLACEMENT_3D('',#160,#131,#132);
+#508=DRAUGHTING_MODEL_ITEM_ASSOCIATION('','',#190,#500,#501);
+", &data[end..])
+}
+
+#[test]
+fn skips_draughting() {
+    let data = annotated_cuboid();
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, stats) = triangulate(&step);
+    assert_eq!(mesh.solids.len(), 1);
+    assert!(stats.num_draughting >= 4, "{} draughting entities", stats.num_draughting);
+    assert_eq!(stats.num_errors, 0);
+    assert!(mesh.annotations.is_empty());
+}
+
+#[test]
+fn annotation_curves() {
+    let data = annotated_cuboid();
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let params = TessellationParams { annotations: true, ..Default::default() };
+    let (mesh, _) = triangulate_with(&step, params, &HashMap::new());
+    assert_eq!(mesh.annotations.len(), 2);
+
+    let line = &mesh.annotations[0];
+    assert_eq!(line.len(), 2);
+    assert!((line[1].y - 0.0254).abs() < 1e-9);
+
+    // The circle is closed, and every point is on it
+    let circle = &mesh.annotations[1];
+    assert!(circle.len() > 8);
+    assert!((circle[0] - circle[circle.len() - 1]).norm() < 1e-9);
+    for p in circle {
+        assert!((p.norm() - 0.01).abs() < 1e-9);
+    }
+}
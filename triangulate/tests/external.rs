@@ -0,0 +1,125 @@
+//! Tests for assemblies which pull component geometry from sibling files
+use std::path::{Path, PathBuf};
+
+use step::{cancel::CancelToken, step_file::ParseMode};
+use triangulate::{external::triangulate_file, mesh::Mesh};
+
+/// Placement of two instances of the component, at x = 0 and x = 100
+const INSTANCES: &str = "
+#3=(REPRESENTATION_RELATIONSHIP('','',#2,#1)
+    REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#5)
+    SHAPE_REPRESENTATION_RELATIONSHIP());
+#4=(REPRESENTATION_RELATIONSHIP('','',#2,#1)
+    REPRESENTATION_RELATIONSHIP_WITH_TRANSFORMATION(#6)
+    SHAPE_REPRESENTATION_RELATIONSHIP());
+#5=ITEM_DEFINED_TRANSFORMATION('','',#10,#10);
+#6=ITEM_DEFINED_TRANSFORMATION('','',#10,#11);
+#10=AXIS2_PLACEMENT_3D('',#12,#14,#15);
+#11=AXIS2_PLACEMENT_3D('',#13,#14,#15);
+#12=CARTESIAN_POINT('',(0.,0.,0.));
+#13=CARTESIAN_POINT('',(100.,0.,0.));
+#14=DIRECTION('',(0.,0.,1.));
+#15=DIRECTION('',(1.,0.,0.));
+#1=SHAPE_REPRESENTATION('assembly',(#10),#16);
+#16=REPRESENTATION_CONTEXT('','');
+";
+
+/// Assembly using an `APPLIED_DOCUMENT_REFERENCE`, which is how edition 2
+/// files refer to external geometry
+fn document_file() -> String {
+    format!("ISO-10303-21;
+HEADER;
+FILE_SCHEMA(('AUTOMOTIVE_DESIGN'));
+ENDSEC;
+DATA;
+{}
+#2=SHAPE_REPRESENTATION('part',(#10),#16);
+#20=SHAPE_DEFINITION_REPRESENTATION(#21,#2);
+#21=PRODUCT_DEFINITION_SHAPE('','',#22);
+#22=PRODUCT_DEFINITION('part','',#23,#16);
+#23=PRODUCT_DEFINITION_FORMATION('','',#24);
+#24=PRODUCT('part','part','',(#16));
+#25=DOCUMENT_FILE('cuboid.step','',$,#26,'',$);
+#26=DOCUMENT_TYPE('');
+#27=APPLIED_DOCUMENT_REFERENCE(#25,'',(#22));
+ENDSEC;
+END-ISO-10303-21;
+", INSTANCES)
+}
+
+/// Assembly using an edition 3 reference section
+fn reference_section() -> String {
+    format!("ISO-10303-21;
+HEADER;
+FILE_SCHEMA(('AP242_MANAGED_MODEL_BASED_3D_ENGINEERING_MIM_LF'));
+ENDSEC;
+REFERENCE;
+#2=<cuboid.step>;
+ENDSEC;
+DATA('assembly',('AP242_MANAGED_MODEL_BASED_3D_ENGINEERING_MIM_LF'));
+{}
+ENDSEC;
+END-ISO-10303-21;
+", INSTANCES)
+}
+
+/// Writes an assembly into a scratch directory next to a copy of
+/// `examples/cuboid.step`, returning the assembly's path
+fn setup(name: &str, assembly: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("foxtrot-external-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    std::fs::copy(examples.join("cuboid.step"), dir.join("cuboid.step")).unwrap();
+    let path = dir.join("assembly.step");
+    std::fs::write(&path, assembly).unwrap();
+    path
+}
+
+fn load(path: &Path) -> Mesh {
+    triangulate_file(path, Default::default(), &Default::default(),
+                     ParseMode::Lenient, &CancelToken::default())
+        .unwrap()
+        .unwrap()
+        .0
+}
+
+fn x_range(mesh: &Mesh) -> (f64, f64) {
+    mesh.verts.iter().fold((f64::INFINITY, -f64::INFINITY),
+        |(lo, hi), v| (lo.min(v.pos.x), hi.max(v.pos.x)))
+}
+
+fn check(name: &str, assembly: &str) {
+    let path = setup(name, assembly);
+    let part = load(&path.with_file_name("cuboid.step"));
+    let asm = load(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+    assert!(!part.triangles.is_empty());
+    assert_eq!(asm.triangles.len(), 2 * part.triangles.len());
+    assert_eq!(asm.faces.len(), 2 * part.faces.len());
+    let (lo, hi) = x_range(&part);
+    let (asm_lo, asm_hi) = x_range(&asm);
+    assert!((asm_lo - lo).abs() < 1e-9);
+    assert!((asm_hi - (hi + 100.0)).abs() < 1e-9);
+}
+
+#[test]
+fn document_file_reference() {
+    check("document", &document_file());
+}
+
+#[test]
+fn edition_3_reference() {
+    check("ed3", &reference_section());
+}
+
+#[test]
+fn missing_reference() {
+    // References to files which don't exist are skipped
+    let path = setup("missing", &reference_section());
+    std::fs::remove_file(path.with_file_name("cuboid.step")).unwrap();
+    let asm = load(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    assert!(asm.triangles.is_empty());
+}
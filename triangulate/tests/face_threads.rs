@@ -0,0 +1,38 @@
+//! Tests for triangulating large faces on several threads
+use std::f64::consts::TAU;
+
+use step::step_file::StepFile;
+use triangulate::{mesh::Mesh, triangulate::{triangulate_with, TessellationParams}};
+
+mod common;
+use common::polygon_face;
+
+/// Triangulates STEP data with the given number of threads per face
+fn load(data: &str, face_threads: usize) -> Mesh {
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let params = TessellationParams { face_threads, ..Default::default() };
+    let (mesh, stats) = triangulate_with(&step, params, &Default::default());
+    assert_eq!(stats.num_errors + stats.num_panics, 0);
+    mesh
+}
+
+#[test]
+fn wavy_face() {
+    // A finely sampled wavy loop, whose edges are all Delaunay
+    let n = 6000;
+    let pts: Vec<(f64, f64)> = (0..n).map(|i| {
+        let a = i as f64 * TAU / n as f64;
+        let r = 1.0 + 0.2 * (5.0 * a).sin() + 0.01 * (37.0 * a + 0.3).sin();
+        (r * a.cos(), r * a.sin())
+    }).collect();
+    let edges: Vec<(usize, usize)> = (0..n).map(|i| (i, (i + 1) % n)).collect();
+    assert!(cdt::triangulate_with_edges_parallel(&pts, &edges, 4).is_some());
+
+    let data = polygon_face(&[&pts]);
+    let (serial, parallel) = (load(&data, 1), load(&data, 4));
+    assert_eq!(parallel.triangles.len(), n - 2);
+    assert_eq!(parallel.triangles.len(), serial.triangles.len());
+    let (a, b) = (parallel.area(0..n - 2), serial.area(0..n - 2));
+    assert!((a - b).abs() < 1e-9 * b, "{} vs {}", a, b);
+}
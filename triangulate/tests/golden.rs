@@ -0,0 +1,127 @@
+//! Golden-mesh regression tests.  Every STEP file in `examples/` is
+//! triangulated and summarized, and the summary is compared against the
+//! committed copy in `tests/golden/<name>.txt`, so that changes to the
+//! triangulator which alter its output can't slip through unnoticed.
+//!
+//! After an intentional change, regenerate the summaries with
+//! ```sh
+//! FOXTROT_BLESS=1 cargo test -p triangulate --test golden
+//! ```
+//! and commit the results alongside the change.
+use std::path::{Path, PathBuf};
+
+use step::{cancel::CancelToken, step_file::StepFile};
+use triangulate::{
+    mesh::Mesh, stats::Stats,
+    triangulate::{triangulate, triangulate_cancellable},
+};
+
+/// Positions are rounded to this fraction of the bounding box diagonal
+/// before hashing, so that the hash doesn't depend on last-bit differences
+/// in floating-point math between platforms
+const QUANTUM: f64 = 1e-6;
+
+/// 64-bit FNV-1a hash, which is used (rather than `DefaultHasher`) because
+/// its output is stable between Rust versions
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Builds a text summary of a triangulated model
+fn summarize(mesh: &Mesh, stats: &Stats) -> String {
+    let mut lo = nalgebra_glm::DVec3::repeat(f64::INFINITY);
+    let mut hi = nalgebra_glm::DVec3::repeat(-f64::INFINITY);
+    for v in &mesh.verts {
+        lo = lo.inf(&v.pos);
+        hi = hi.sup(&v.pos);
+    }
+    let scale = (hi - lo).norm() * QUANTUM;
+
+    let mut hash = Fnv::new();
+    for v in &mesh.verts {
+        for c in (v.pos - lo).iter() {
+            hash.write(&((c / scale).round() as i64).to_le_bytes());
+        }
+    }
+    for t in &mesh.triangles {
+        for i in t.verts.iter() {
+            hash.write(&i.to_le_bytes());
+        }
+    }
+    for f in &mesh.faces {
+        hash.write(&f.id.to_le_bytes());
+        hash.write(&f.triangles.start.to_le_bytes());
+        hash.write(&f.triangles.end.to_le_bytes());
+    }
+
+    format!("verts {}\ntriangles {}\nfaces {}\nsolids {}\n\
+             errors {}\npanics {}\narea {:.6e}\nhash {:016x}\n",
+            mesh.verts.len(), mesh.triangles.len(), mesh.faces.len(),
+            mesh.solids.len(), stats.num_errors, stats.num_panics,
+            mesh.area(0..mesh.triangles.len()), hash.0)
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .expect("Could not read examples directory")
+        .map(|e| e.expect("Could not read directory entry").path())
+        .filter(|p| p.extension().map(|e| e == "step").unwrap_or(false))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn golden_meshes() {
+    let bless = std::env::var_os("FOXTROT_BLESS").is_some();
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut failures = Vec::new();
+    for path in corpus() {
+        let data = std::fs::read(&path).expect("Could not read STEP file");
+        let flat = StepFile::strip_flatten(&data);
+        let step = StepFile::parse(&flat);
+        let (mesh, stats) = triangulate(&step);
+        let summary = summarize(&mesh, &stats);
+
+        // Triangulating twice must give the same result, including through
+        // the cancellable entry point (as long as it isn't cancelled)
+        let cancel = CancelToken::new();
+        let (again, stats) = triangulate_cancellable(
+            &step, Default::default(), &Default::default(), &cancel)
+            .expect("Triangulation was cancelled");
+        assert_eq!(summary, summarize(&again, &stats),
+                   "{:?} is not deterministic", path);
+        cancel.cancel();
+        assert!(triangulate_cancellable(
+            &step, Default::default(), &Default::default(), &cancel).is_none());
+
+        let name = path.file_stem().expect("Could not get file name");
+        let expected = golden.join(name).with_extension("txt");
+        if bless {
+            std::fs::write(&expected, &summary)
+                .expect("Could not write golden summary");
+            continue;
+        }
+        match std::fs::read_to_string(&expected) {
+            Ok(s) if s == summary => (),
+            Ok(s) => failures.push(format!(
+                "{:?} changed\n--- expected\n{}--- actual\n{}", path, s, summary)),
+            Err(_) => failures.push(format!(
+                "{:?} has no golden summary at {:?}", path, expected)),
+        }
+    }
+    assert!(failures.is_empty(),
+            "{}\nIf this is intentional, rerun with FOXTROT_BLESS=1",
+            failures.join("\n"));
+}
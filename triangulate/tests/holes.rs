@@ -0,0 +1,25 @@
+//! Tests for recognizing holes in triangulated models
+use step::step_file::StepFile;
+use triangulate::{holes::find_holes, triangulate::triangulate};
+
+#[test]
+fn through_hole() {
+    // A half-inch hole through the middle of a 2" × 1" × 1" block
+    let data = include_str!("../../examples/cube_hole.step");
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, _stats) = triangulate(&step);
+    let holes = find_holes(&mesh);
+    assert_eq!(holes.len(), 1);
+
+    let h = &holes[0];
+    assert!(h.through);
+    assert!(h.counterbore.is_none());
+    assert!((h.diameter - 0.0127).abs() < 1e-9);
+    assert!((h.depth - 0.0254).abs() < 1e-9);
+    assert!((h.origin.x - 0.0254).abs() < 1e-9);
+    assert!((h.origin.y - 0.0127).abs() < 1e-9);
+    assert!((h.axis.z.abs() - 1.0).abs() < 1e-9);
+    assert_eq!(h.faces.len(), 1);
+    assert_eq!(mesh.faces[h.faces[0]].id, 129);
+}
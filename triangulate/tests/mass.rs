@@ -0,0 +1,45 @@
+//! Tests for mass properties of triangulated solids
+use step::step_file::StepFile;
+use triangulate::{mass::mass_properties, triangulate::triangulate};
+
+#[test]
+fn block() {
+    // `examples/cuboid.step` is a 2 x 1 x 3 inch block (in metres), with
+    // one corner at the origin
+    let data = include_str!("../../examples/cuboid.step");
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, _stats) = triangulate(&step);
+    let solid = &mesh.solids[0];
+    let density = 2700.0;
+    let p = mass_properties(&mesh, [(solid.triangles.clone(), density)], 1.0)
+        .expect("Could not compute mass properties");
+
+    let size = [0.0508, 0.0254, 0.0762];
+    let volume = size[0] * size[1] * size[2];
+    assert!((p.volume - volume).abs() < 1e-12);
+    assert!((p.mass - volume * density).abs() < 1e-9);
+    for (c, s) in p.center.iter().zip(size) {
+        assert!((c - s / 2.0).abs() < 1e-9);
+    }
+
+    // Inertia of a box about its center is m(b² + c²)/12 along each axis,
+    // with no products of inertia
+    for i in 0..3 {
+        let (b, c) = (size[(i + 1) % 3], size[(i + 2) % 3]);
+        let expected = p.mass * (b * b + c * c) / 12.0;
+        assert!((p.inertia[(i, i)] - expected).abs() < 1e-12,
+                "I[{}] was {}, expected {}", i, p.inertia[(i, i)], expected);
+        assert!(p.inertia[(i, (i + 1) % 3)].abs() < 1e-12);
+    }
+    let m = p.principal_moments();
+    assert!(m[0] <= m[1] && m[1] <= m[2]);
+
+    // Reading the same coordinates as millimetres keeps the volume (which is
+    // in model units), and shrinks the mass by 10⁹ and the inertia by 10¹⁵
+    let mm = mass_properties(&mesh, [(solid.triangles.clone(), density)], 1e-3)
+        .unwrap();
+    assert!((mm.volume - p.volume).abs() < 1e-12);
+    assert!((mm.mass / p.mass - 1e-9).abs() < 1e-18);
+    assert!((mm.inertia[(0, 0)] / p.inertia[(0, 0)] - 1e-15).abs() < 1e-24);
+}
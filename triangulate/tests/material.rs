@@ -0,0 +1,49 @@
+//! Tests for reading material assignments from a product's properties
+mod common;
+use common::load;
+
+/// `examples/cuboid.step` (a 1 x 2 x 3 inch block), made of aluminium with a
+/// density of 2.7 g/cm³ and anodized
+fn aluminium_cuboid() -> String {
+    let data = include_str!("../../examples/cuboid.step");
+    let end = data.rfind("ENDSEC;").unwrap();
+    format!("{}{}{}", &data[..end], "
+#400=MATERIAL_DESIGNATION('AL6061-T6',(#190));
+#401=PROPERTY_DEFINITION_REPRESENTATION(#402,#403);
+#402=PROPERTY_DEFINITION('material property','density',#190);
+#403=REPRESENTATION('density',(#404),#185);
+#404=MEASURE_REPRESENTATION_ITEM('density',POSITIVE_RATIO_MEASURE(2.7),#405);
+#405=DERIVED_UNIT((#406,#407));
+#406=DERIVED_UNIT_ELEMENT(#408,1.);
+#407=DERIVED_UNIT_ELEMENT(#409,-3.);
+#408=(MASS_UNIT()NAMED_UNIT(*)SI_UNIT($,.GRAM.));
+#409=(LENGTH_UNIT()NAMED_UNIT(*)SI_UNIT(.CENTI.,.METRE.));
+#410=PROPERTY_DEFINITION_REPRESENTATION(#411,#412);
+#411=PROPERTY_DEFINITION('surface finish','',#190);
+#412=REPRESENTATION('',(#413),#185);
+#413=DESCRIPTIVE_REPRESENTATION_ITEM('finish','Anodized');
+", &data[end..])
+}
+
+#[test]
+fn reads_material() {
+    let mesh = load(&aluminium_cuboid());
+    assert_eq!(mesh.solids.len(), 1);
+    let solid = &mesh.solids[0];
+    let material = solid.material.as_ref().expect("Missing material");
+    assert_eq!(material.name.as_deref(), Some("AL6061-T6"));
+    assert_eq!(material.finish.as_deref(), Some("Anodized"));
+    assert!((material.density.unwrap() - 2700.0).abs() < 1e-9);
+
+    // The block is six cubic inches, so it weighs about 265 grams
+    let volume = mesh.volume(solid.triangles.clone());
+    assert!((volume - 6.0 * 0.0254f64.powi(3)).abs() < 1e-12, "volume was {}", volume);
+    let mass = material.mass(volume, mesh.length_unit.unwrap()).unwrap();
+    assert!((mass - 0.2655).abs() < 1e-4, "mass was {}", mass);
+}
+
+#[test]
+fn no_material() {
+    let mesh = load(include_str!("../../examples/cuboid.step"));
+    assert!(mesh.solids.iter().all(|s| s.material.is_none()));
+}
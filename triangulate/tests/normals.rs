@@ -0,0 +1,65 @@
+//! Tests for smoothing normals across face boundaries, and for keeping
+//! normals consistent with triangle winding under transforms
+use nalgebra_glm::{DMat4, DVec3};
+use step::step_file::StepFile;
+use triangulate::{mesh::{Mesh, UpAxis}, triangulate::triangulate};
+
+fn cuboid() -> Mesh {
+    let data = include_bytes!("../../examples/cuboid.step");
+    let flat = StepFile::strip_flatten(data);
+    let step = StepFile::parse(&flat);
+    triangulate(&step).0
+}
+
+#[test]
+fn keeps_creases() {
+    // The cuboid's faces meet at right angles, so a smaller crease angle
+    // leaves every normal alone
+    let mesh = cuboid();
+    let mut smoothed = mesh.clone();
+    smoothed.smooth_normals(60f64.to_radians());
+    for (a, b) in mesh.verts.iter().zip(&smoothed.verts) {
+        assert_eq!(a.norm, b.norm);
+    }
+
+    // A larger angle averages the three faces at each corner
+    smoothed.smooth_normals(100f64.to_radians());
+    let corners = smoothed.verts.iter()
+        .filter(|v| (v.norm.x.abs() - 1.0 / 3f64.sqrt()).abs() < 1e-9
+                 && (v.norm.y.abs() - v.norm.x.abs()).abs() < 1e-9
+                 && (v.norm.z.abs() - v.norm.x.abs()).abs() < 1e-9)
+        .count();
+    assert_eq!(corners, 8 * 3);
+    for v in &smoothed.verts {
+        assert!((v.norm.norm() - 1.0).abs() < 1e-9);
+    }
+}
+
+/// Checks that every triangle's winding agrees with its vertex normals
+fn winding_matches_normals(mesh: &Mesh) -> bool {
+    mesh.triangles.iter().all(|t| {
+        let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z]
+            .map(|i| &mesh.verts[i as usize]);
+        let n = (b.pos - a.pos).cross(&(c.pos - a.pos));
+        n.dot(&(a.norm + b.norm + c.norm)) > 0.0
+    })
+}
+
+#[test]
+fn keeps_handedness() {
+    let mesh = cuboid();
+    assert!(winding_matches_normals(&mesh));
+
+    // Converting to Y-up moves the Z axis onto Y
+    let mut y_up = mesh.clone();
+    y_up.transform(&UpAxis::Y.from_z_up());
+    assert!(winding_matches_normals(&y_up));
+    for (a, b) in mesh.verts.iter().zip(&y_up.verts) {
+        assert!((b.pos - DVec3::new(a.pos.x, a.pos.z, -a.pos.y)).norm() < 1e-12);
+    }
+
+    // Mirroring flips the triangles, so they still face outwards
+    let mut mirrored = mesh.clone();
+    mirrored.transform(&DMat4::new_nonuniform_scaling(&DVec3::new(-1.0, 1.0, 1.0)));
+    assert!(winding_matches_normals(&mirrored));
+}
@@ -0,0 +1,36 @@
+//! Tests for hidden-line drawings
+use nalgebra_glm::DVec3;
+use triangulate::outline::outline;
+
+mod common;
+use common::{bounds, load};
+
+#[test]
+fn cuboid_outline() {
+    // A box seen from a corner shows 9 of its 12 edges, each in one piece
+    let mesh = load(include_str!("../../examples/cuboid.step"));
+    for view in [DVec3::new(1.0, -1.0, 1.0), DVec3::new(-1.0, 1.0, -1.0)] {
+        let d = outline(&mesh, view);
+        assert_eq!(d.lines.len(), 9);
+    }
+
+    // Looking straight down, the drawing's axes are the model's X and Y
+    let d = outline(&mesh, DVec3::z());
+    let (lo, hi) = d.bounds();
+    let (mlo, mhi) = bounds(&mesh);
+    assert!((lo.0 - mlo.x).abs() < 1e-9 && (hi.0 - mhi.x).abs() < 1e-9);
+    assert!((lo.1 - mlo.y).abs() < 1e-9 && (hi.1 - mhi.y).abs() < 1e-9);
+}
+
+#[test]
+fn save_pdf() {
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let d = outline(&mesh, DVec3::new(1.0, -1.0, 1.0));
+    let path = std::env::temp_dir()
+        .join(format!("foxtrot-outline-{}.pdf", std::process::id()));
+    d.save_pdf(Some(1e-3), path.to_str().unwrap()).unwrap();
+    let pdf = std::fs::read(&path).unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert!(pdf.ends_with(b"%%EOF\n"));
+    assert_eq!(pdf.windows(3).filter(|w| w == b" l\n").count(), d.lines.len());
+}
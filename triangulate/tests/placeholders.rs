@@ -0,0 +1,36 @@
+//! Tests for placeholder geometry standing in for faces which fail to
+//! triangulate
+use step::step_file::StepFile;
+use triangulate::triangulate::{triangulate_with, TessellationParams};
+
+/// `examples/cube_hole.step`, with one face listing the same boundary loop
+/// twice, which the triangulator rejects
+fn broken_cube_hole() -> String {
+    let data = include_str!("../../examples/cube_hole.step");
+    assert!(data.contains("#129=ADVANCED_FACE('',(#113,#114),#22,.F.);"));
+    data.replace("#129=ADVANCED_FACE('',(#113,#114),#22,.F.);",
+                 "#129=ADVANCED_FACE('',(#113,#113),#22,.F.);")
+}
+
+#[test]
+fn failed_face_placeholder() {
+    let data = broken_cube_hole();
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+
+    // By default, the face is left out of the mesh
+    let (mesh, stats) = triangulate_with(&step, Default::default(), &Default::default());
+    assert_eq!(stats.num_errors, 1);
+    assert!(mesh.faces.iter().all(|f| !f.failed && f.id != 129));
+    let num_triangles = mesh.triangles.len();
+
+    // With placeholders, it's replaced by a fan over its boundary
+    let params = TessellationParams { placeholders: true, ..Default::default() };
+    let (mesh, stats) = triangulate_with(&step, params, &Default::default());
+    assert_eq!(stats.num_errors, 1);
+    let failed: Vec<_> = mesh.faces.iter().filter(|f| f.failed).collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].id, 129);
+    assert!(!failed[0].triangles.is_empty());
+    assert_eq!(mesh.triangles.len(), num_triangles + failed[0].triangles.len());
+}
@@ -0,0 +1,60 @@
+//! Tests for faces tessellated as structured grids of quads
+use step::step_file::StepFile;
+use triangulate::{
+    mass::mass_properties,
+    mesh::{Mesh, SurfaceType},
+    quad::{Element, QuadMesh},
+    triangulate::{triangulate_with, TessellationParams},
+};
+
+/// Triangulates `examples/cube_hole.step` (a cube with a cylindrical hole
+/// through it), optionally with quad grids
+fn cube_hole(quad_grids: bool) -> Mesh {
+    let data = include_str!("../../examples/cube_hole.step");
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let params = TessellationParams { quad_grids, ..Default::default() };
+    let (mesh, stats) = triangulate_with(&step, params, &Default::default());
+    assert_eq!(stats.num_errors + stats.num_panics, 0);
+    mesh
+}
+
+/// Returns the volume enclosed by the mesh's first solid
+fn volume(mesh: &Mesh) -> f64 {
+    mass_properties(mesh, [(mesh.solids[0].triangles.clone(), 1.0)], 1.0)
+        .expect("Could not compute mass properties")
+        .volume
+}
+
+#[test]
+fn cube_hole_grids() {
+    let mesh = cube_hole(true);
+    let quads = QuadMesh::from_mesh(&mesh);
+    for (face, range) in mesh.faces.iter().zip(&quads.faces) {
+        let elements = &quads.elements[range.clone()];
+        let all_quads = elements.iter().all(|e| matches!(e, Element::Quad(..)));
+        match face.surface {
+            // The hole is a band between two circles, which becomes a
+            // single ring of quads running along its axis
+            SurfaceType::Cylinder => {
+                let [n, rows] = face.grid.expect("Cylinder should be a grid");
+                assert_eq!(rows, 1);
+                assert_eq!(elements.len(), n);
+                assert!(all_quads);
+            },
+            // The cube's sides are single quads, while the faces with the
+            // hole in them aren't rectangles
+            SurfaceType::Plane if face.grid.is_some() => {
+                assert_eq!(face.grid, Some([1, 1]));
+                assert_eq!(elements.len(), 1);
+                assert!(all_quads);
+            },
+            _ => (),
+        }
+    }
+    assert_eq!(mesh.faces.iter().filter(|f| f.grid.is_some()).count(), 5);
+
+    // The grids share the other faces' boundaries, so the solid is the same
+    let v = volume(&cube_hole(false));
+    assert!((volume(&mesh) - v).abs() < v * 1e-3, "{} vs {}", volume(&mesh), v);
+}
@@ -0,0 +1,42 @@
+//! Tests for the path tracer
+use nalgebra_glm::DVec3;
+use triangulate::render::{render, RenderParams};
+
+mod common;
+use common::{bounds, load};
+
+#[test]
+fn render_cuboid() {
+    let mut mesh = load(include_str!("../../examples/cuboid.step"));
+    for v in mesh.verts.iter_mut() {
+        v.color = DVec3::repeat(0.5);
+    }
+    let (lo, hi) = bounds(&mesh);
+    let center = (lo + hi) / 2.0;
+    let size = (hi - lo).max();
+
+    // Look straight down, with the model filling the middle half of the
+    // image and a key light from directly above
+    let params = RenderParams {
+        width: 16,
+        height: 16,
+        samples: 4,
+        light: DVec3::z(),
+        background: DVec3::zeros(),
+        ..Default::default()
+    };
+    let ray = |x: f64, y: f64| {
+        let p = DVec3::new(center.x + (x / 8.0 - 1.0) * size,
+                           center.y + (1.0 - y / 8.0) * size,
+                           hi.z + size);
+        (p, -DVec3::z())
+    };
+    let pixels = render(&mesh, &params, ray);
+    assert_eq!(pixels.len(), 16 * 16 * 4);
+    let px = |x: usize, y: usize| &pixels[(y * 16 + x) * 4..][..4];
+    assert_eq!(px(0, 0), &[0, 0, 0, 255]);
+    assert!(px(8, 8)[0] > 100);
+
+    // The same image comes back each time
+    assert_eq!(pixels, render(&mesh, &params, ray));
+}
@@ -0,0 +1,51 @@
+//! Tests for comparing scans against the models which they were taken from
+use nalgebra_glm as glm;
+use glm::DVec3;
+use triangulate::{
+    bvh::Bvh, diff::signed_deviation, points::PointCloud,
+    scan::{centroid_alignment, load_scan, register},
+};
+
+mod common;
+use common::load;
+
+#[test]
+fn register_point_cloud() {
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let path = std::env::temp_dir()
+        .join(format!("foxtrot-scan-{}.ply", std::process::id()));
+    let area = mesh.area(0..mesh.triangles.len());
+    PointCloud::sample(&mesh, 500.0 / area, 0).save_ply(path.to_str().unwrap()).unwrap();
+    let scan = load_scan(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let mut scan = scan.unwrap();
+    assert!(scan.triangles.is_empty());
+    assert!((scan.verts.len() as f64 - 500.0).abs() < 50.0);
+
+    // Move the scan off of the model, then find the way back
+    let size = 0.0254;
+    let offset = glm::translation(&DVec3::new(0.1, -0.05, 0.02).scale(size)) *
+                 glm::rotation(0.05, &DVec3::new(1.0, 2.0, 3.0));
+    scan.transform(&offset);
+    let points: Vec<DVec3> = scan.verts.iter().map(|v| v.pos).collect();
+    let bvh = Bvh::new(&mesh);
+    let reg = register(&points, &bvh, centroid_alignment(&points, &mesh), 200);
+    assert!(reg.iterations > 0);
+    assert_eq!(reg.points, points.len());
+    assert!(reg.rms < size * 1e-4, "rms {}", reg.rms);
+    let back = reg.transform * offset;
+    assert!((back - glm::DMat4::identity()).abs().max() < 1e-3, "{}", back);
+
+    // Points pushed out of the model have positive deviation, and points
+    // pulled into it have negative deviation
+    scan.transform(&reg.transform);
+    for (v, i) in scan.verts.iter_mut().zip(0..) {
+        let d = if i % 2 == 0 { 1e-4 } else { -1e-4 };
+        v.pos += v.norm * d;
+    }
+    let d = signed_deviation(&scan, &bvh, &mesh);
+    let n = d.iter().enumerate()
+        .filter(|(i, d)| (**d - if i % 2 == 0 { 1e-4 } else { -1e-4 }).abs() < 2e-5)
+        .count();
+    assert!(n as f64 > d.len() as f64 * 0.95, "{} of {}", n, d.len());
+}
@@ -0,0 +1,56 @@
+//! Tests for sampling signed distance fields from solids
+use std::convert::TryInto;
+
+use nalgebra_glm::DVec3;
+use triangulate::sdf::DistanceField;
+
+mod common;
+use common::load;
+
+#[test]
+fn cube_hole() {
+    // A 2 x 1 x 1 inch block, with a half-inch hole through its middle
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let inch = 0.0254;
+    let spacing = inch / 16.0;
+    let sdf = DistanceField::new(&mesh, spacing, 2);
+    let at = |p: DVec3| {
+        let i = (p - sdf.origin) / spacing;
+        sdf.get(i.x.round() as usize, i.y.round() as usize, i.z.round() as usize) as f64
+    };
+    // Within the solid, beside the hole, and on the hole's axis
+    let d = at(DVec3::new(0.25, 0.5, 0.5) * inch);
+    assert!((d + 0.25 * inch).abs() < spacing, "{}", d);
+    let d = at(DVec3::new(1.0, 0.5, 0.5) * inch);
+    assert!((d - 0.25 * inch).abs() < spacing, "{}", d);
+    let d = at(DVec3::new(-2.0, 0.5, 0.5) * spacing);
+    assert!(d > 0.0, "{}", d);
+
+    // Counting the voxels inside gives the solid's volume
+    let inside = sdf.values.iter().filter(|v| **v < 0.0).count() as f64;
+    let volume = mesh.volume(0..mesh.triangles.len()).abs();
+    assert!((inside * spacing.powi(3) - volume).abs() < volume * 0.02,
+            "{} vs {}", inside * spacing.powi(3), volume);
+
+    let path = std::env::temp_dir()
+        .join(format!("foxtrot-sdf-{}.fsdf", std::process::id()));
+    sdf.save_sparse((3.0 * spacing) as f32, path.to_str().unwrap()).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    assert!(data.starts_with(b"FXSDF\0\0\x01"));
+
+    // Walk the blocks, which should exactly fill the file
+    let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+    assert_eq!(u32_at(8) as usize, sdf.dims[0]);
+    let count = u32_at(56);
+    let mut i = 60;
+    for _ in 0..count {
+        i += 12;
+        match data[i] {
+            0 => (),
+            1 => i += 512 * 4,
+            t => panic!("Invalid tag {}", t),
+        }
+        i += 1;
+    }
+    assert_eq!(i, data.len());
+}
@@ -0,0 +1,87 @@
+//! Tests for cutting meshes with planes
+use nalgebra_glm::DVec3;
+use triangulate::section::{save_dxf, save_svg, section};
+
+mod common;
+use common::{bounds, load};
+
+/// Sums the areas of a cap's triangles
+fn cap_area(pts: &[DVec3], tris: &[[usize; 3]]) -> f64 {
+    tris.iter()
+        .map(|t| (pts[t[1]] - pts[t[0]]).cross(&(pts[t[2]] - pts[t[0]])).norm() / 2.0)
+        .sum()
+}
+
+#[test]
+fn cuboid_sections() {
+    let mesh = load(include_str!("../../examples/cuboid.step"));
+    let (lo, hi) = bounds(&mesh);
+    let size = hi - lo;
+    for axis in 0..3 {
+        let mut n = DVec3::zeros();
+        n[axis] = 1.0;
+        let s = section(&mesh, (lo + hi) / 2.0, n);
+        assert_eq!(s.len(), 1);
+        assert_eq!(s[0].loops.len(), 1);
+        assert_eq!(s[0].open, 0);
+
+        let expected = size.iter().enumerate()
+            .filter(|(i, _)| *i != axis)
+            .map(|(_, d)| d)
+            .product::<f64>();
+        assert!((s[0].area(n) - expected).abs() < expected * 1e-9);
+        let (pts, tris) = s[0].cap(n).unwrap();
+        assert!((cap_area(&pts, &tris) - expected).abs() < expected * 1e-9);
+    }
+
+    // Planes which miss the model don't produce any sections
+    assert!(section(&mesh, hi * 2.0, DVec3::x()).is_empty());
+}
+
+#[test]
+fn section_with_hole() {
+    // The hole runs along Z, so cutting across it leaves a ring
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let (lo, hi) = bounds(&mesh);
+    let s = section(&mesh, (lo + hi) / 2.0, DVec3::z());
+    assert_eq!(s.len(), 1);
+    assert_eq!(s[0].loops.len(), 2);
+    assert_eq!(s[0].open, 0);
+
+    let area = s[0].area(DVec3::z());
+    let outer = (hi.x - lo.x) * (hi.y - lo.y);
+    assert!(area > 0.0 && area < outer);
+    let (pts, tris) = s[0].cap(DVec3::z()).unwrap();
+    assert!((cap_area(&pts, &tris) - area).abs() < area * 1e-9);
+}
+
+#[test]
+fn export_drawings() {
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let (lo, hi) = bounds(&mesh);
+    let s = section(&mesh, (lo + hi) / 2.0, DVec3::z());
+    let dir = std::env::temp_dir()
+        .join(format!("foxtrot-section-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let svg = dir.join("section.svg");
+    save_svg(&s, DVec3::z(), Some(1e-3), svg.to_str().unwrap()).unwrap();
+    let svg = std::fs::read_to_string(svg).unwrap();
+    assert!(svg.contains("width=\""));
+    assert_eq!(svg.matches("<path").count(), 1);
+    assert_eq!(svg.matches('Z').count(), 2);
+
+    // Looking down Z, the drawing's axes are the model's X and Y
+    let dxf = dir.join("section.dxf");
+    save_dxf(&s, DVec3::z(), Some(1e-3), dxf.to_str().unwrap()).unwrap();
+    let dxf = std::fs::read_to_string(dxf).unwrap();
+    assert!(dxf.contains("$INSUNITS\n70\n4\n"));
+    assert_eq!(dxf.matches("\nPOLYLINE\n").count(), 2);
+    let xs: Vec<f64> = dxf.lines().collect::<Vec<_>>()
+        .windows(2)
+        .filter(|w| w[0] == "10")
+        .map(|w| w[1].parse().unwrap())
+        .collect();
+    let max = xs.iter().cloned().fold(-f64::INFINITY, f64::max);
+    assert!((max - hi.x).abs() < 1e-9);
+}
@@ -0,0 +1,44 @@
+//! Tests for sharing the samples of an edge between the faces which it
+//! borders
+use std::collections::HashMap;
+
+use step::step_file::StepFile;
+use triangulate::triangulate::{triangulate_with, TessellationOverrides, TessellationParams};
+
+/// Counts the triangle edges (keyed by the exact positions of their ends)
+/// which aren't used by exactly two triangles
+fn open_edges(data: &str, params: TessellationParams,
+              overrides: &TessellationOverrides) -> usize
+{
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, stats) = triangulate_with(&step, params, overrides);
+    assert_eq!(stats.num_errors, 0);
+
+    let key = |i: u32| {
+        let p = mesh.verts[i as usize].pos;
+        (p.x.to_bits(), p.y.to_bits(), p.z.to_bits())
+    };
+    let mut edges: HashMap<_, usize> = HashMap::new();
+    for t in &mesh.triangles {
+        let [a, b, c] = [t.verts.x, t.verts.y, t.verts.z].map(key);
+        for (a, b) in [(a, b), (b, c), (c, a)] {
+            *edges.entry(if a < b { (a, b) } else { (b, a) }).or_default() += 1;
+        }
+    }
+    edges.values().filter(|&&n| n != 2).count()
+}
+
+#[test]
+fn watertight_with_overrides() {
+    let data = include_str!("../../examples/cube_hole.step");
+    // Merging points is disabled, so only exactly-shared samples connect
+    let params = TessellationParams { uncertainty: Some(0.0), ..Default::default() };
+    assert_eq!(open_edges(data, params, &Default::default()), 0);
+
+    // The cylindrical face (#129) samples its circles more finely than the
+    // planar faces around it would, but they share its samples
+    let mut overrides = TessellationOverrides::new();
+    overrides.insert(129, TessellationParams { tolerance: Some(1e-6), ..params });
+    assert_eq!(open_edges(data, params, &overrides), 0);
+}
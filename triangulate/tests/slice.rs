@@ -0,0 +1,33 @@
+//! Tests for slicing solids into layers of polygons
+use triangulate::slice::{layer_heights, slice};
+use nalgebra_glm::DVec3;
+
+mod common;
+use common::load;
+
+#[test]
+fn cube_hole_layers() {
+    // A 2 x 1 x 1 inch block, with a half-inch hole down through its middle
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let inch = 0.0254;
+    let heights = layer_heights(&mesh, DVec3::z(), inch / 10.0);
+    assert_eq!(heights.len(), 10);
+
+    // Every layer is the block's outline with one round hole in it
+    let area = 2.0 * inch * inch - std::f64::consts::PI * (inch / 4.0).powi(2);
+    for layer in slice(&mesh, DVec3::z(), &heights) {
+        assert_eq!(layer.open, 0);
+        assert_eq!(layer.polygons.len(), 1);
+        assert_eq!(layer.polygons[0].holes.len(), 1);
+        assert!((layer.area() - area).abs() < area * 0.01,
+                "{} vs {}", layer.area(), area);
+    }
+
+    // Slicing sideways cuts the hole into two halves of the block
+    let heights = layer_heights(&mesh, DVec3::x(), inch / 4.0);
+    let layers = slice(&mesh, DVec3::x(), &heights);
+    assert_eq!(layers.len(), 8);
+    let middle = &layers[4];
+    assert_eq!(middle.polygons.len(), 2, "at {}", middle.height);
+    assert!(middle.polygons.iter().all(|p| p.holes.is_empty()));
+}
@@ -0,0 +1,30 @@
+//! Tests for out-of-core triangulation
+use step::{cancel::CancelToken, step_file::StepFile};
+use triangulate::triangulate::{triangulate, triangulate_out_of_core};
+
+#[test]
+fn matches_in_memory() {
+    let data = include_str!("../../examples/abstract_pca.step");
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, _stats) = triangulate(&step);
+    let (spilled, _stats) = triangulate_out_of_core(
+            &step, Default::default(), &Default::default(), 0,
+            &CancelToken::default())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(spilled.triangles().len(), mesh.triangles.len());
+    assert_eq!(spilled.length_unit, mesh.length_unit);
+    assert!(mesh.length_unit.is_some());
+    assert!(!mesh.assembly.is_empty());
+    assert_eq!(spilled.assembly.len(), mesh.assembly.len());
+    for (a, b) in spilled.assembly.iter().zip(&mesh.assembly) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.solids, b.solids);
+    }
+
+    let out = spilled.to_mesh();
+    assert_eq!(out.length_unit, mesh.length_unit);
+    assert_eq!(out.assembly.len(), mesh.assembly.len());
+}
@@ -0,0 +1,90 @@
+//! Tests for tessellation tolerances which scale with the model
+use step::step_file::StepFile;
+use triangulate::{
+    mesh::Mesh,
+    triangulate::{
+        triangulate_with, TessellationOverrides, TessellationParams, DEFAULT_RELATIVE_TOLERANCE,
+    },
+};
+
+mod common;
+use common::bounds;
+
+fn mesh(data: &str, params: TessellationParams) -> Mesh {
+    mesh_with(data, params, &Default::default())
+}
+
+fn mesh_with(data: &str, params: TessellationParams,
+             overrides: &TessellationOverrides) -> Mesh
+{
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    triangulate_with(&step, params, overrides).0
+}
+
+/// Counts the triangles in each face, keyed by the face's entity id
+fn face_sizes(mesh: &Mesh) -> Vec<(usize, usize)> {
+    let mut out: Vec<_> = mesh.faces.iter().map(|f| (f.id, f.triangles.len())).collect();
+    out.sort_unstable();
+    out
+}
+
+#[test]
+fn relative_tolerance() {
+    let data = include_str!("../../examples/cube_hole.step");
+    let relative = |r| mesh(data, TessellationParams {
+        relative_tolerance: Some(r), ..Default::default()
+    });
+
+    // The model's corners are vertices, so the mesh spans the same box
+    let m = relative(DEFAULT_RELATIVE_TOLERANCE);
+    let (lo, hi) = bounds(&m);
+    let size = (hi - lo).norm();
+    let absolute = mesh(data, TessellationParams {
+        tolerance: Some(size * DEFAULT_RELATIVE_TOLERANCE), ..Default::default()
+    });
+    assert_eq!(m.verts.len(), absolute.verts.len());
+
+    // A tighter fraction gives a finer mesh
+    assert!(relative(DEFAULT_RELATIVE_TOLERANCE / 100.0).verts.len() > m.verts.len());
+
+    // An absolute tolerance takes priority
+    let both = mesh(data, TessellationParams {
+        tolerance: Some(size * DEFAULT_RELATIVE_TOLERANCE),
+        relative_tolerance: Some(0.1),
+        ..Default::default()
+    });
+    assert_eq!(both.verts.len(), absolute.verts.len());
+}
+
+#[test]
+fn overrides() {
+    let data = include_str!("../../examples/cube_hole.step");
+    let params = TessellationParams { tolerance: Some(0.1), ..Default::default() };
+    let base = face_sizes(&mesh(data, params));
+    let fine = TessellationParams { tolerance: Some(1e-4), ..params };
+
+    // Overriding the cylindrical face (#129) refines it, while the planar
+    // faces stay as they were (apart from the two which border the hole,
+    // whose edges are shared with it)
+    let mut overrides = TessellationOverrides::new();
+    overrides.insert(129, fine);
+    let face = face_sizes(&mesh_with(data, params, &overrides));
+    assert_eq!(face.len(), base.len());
+    for (b, f) in base.iter().zip(&face) {
+        assert_eq!(b.0, f.0);
+        if b.0 == 129 {
+            assert!(f.1 > b.1, "{} vs {}", f.1, b.1);
+        }
+    }
+    let changed = base.iter().zip(&face).filter(|(b, f)| b.1 != f.1).count();
+    assert_eq!(changed, 3);
+
+    // Overriding the solid (#141) refines the same face, and matches
+    // changing the default settings
+    let mut overrides = TessellationOverrides::new();
+    overrides.insert(141, fine);
+    let solid = face_sizes(&mesh_with(data, params, &overrides));
+    assert_eq!(solid, face_sizes(&mesh(data, fine)));
+    assert!(solid.iter().zip(&face).all(|(s, f)| s.0 != 129 || s.1 == f.1));
+}
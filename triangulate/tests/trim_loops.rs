@@ -0,0 +1,37 @@
+//! Tests for faces whose trimming loops overlap or cross each other
+mod common;
+use common::{load_cleanly as load, polygon_face};
+
+#[test]
+fn overlapping_loops() {
+    // Two squares which overlap in a unit square, which is filled once
+    // rather than being cut out by the odd-even rule
+    let a: &[(f64, f64)] = &[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+    let b: &[(f64, f64)] = &[(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)];
+    let mesh = load(&polygon_face(&[a, b]));
+    let area = mesh.area(0..mesh.triangles.len());
+    assert!((area - 7.0).abs() < 1e-9, "Area is {}", area);
+
+    // The corners where the loops cross are vertices of the mesh
+    for (x, y) in [(2.0, 1.0), (1.0, 2.0)] {
+        assert!(mesh.verts.iter().any(|v| v.pos.x == x && v.pos.y == y && v.pos.z == 0.0));
+    }
+
+    // A hole (wound clockwise) in one of the squares is still cut out
+    let hole: &[(f64, f64)] = &[(0.25, 0.25), (0.25, 0.75), (0.75, 0.75), (0.75, 0.25)];
+    let mesh = load(&polygon_face(&[a, b, hole]));
+    let area = mesh.area(0..mesh.triangles.len());
+    assert!((area - 6.75).abs() < 1e-9, "Area is {}", area);
+}
+
+#[test]
+fn self_intersecting_loop() {
+    // A loop which doubles back over itself, so that the rectangle from
+    // (1, 0.5) to (2, 1) is wound twice (and would be a hole by odd-even)
+    let loop_: &[(f64, f64)] = &[(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0),
+                                 (1.0, 0.5), (3.0, 0.5), (3.0, 2.0), (0.0, 2.0)];
+    let mesh = load(&polygon_face(&[loop_]));
+    let area = mesh.area(0..mesh.triangles.len());
+    // The square from (0, 0) to (2, 2), plus the strip from (2, 0.5) to (3, 2)
+    assert!((area - (4.0 + 1.5)).abs() < 1e-9, "Area is {}", area);
+}
@@ -0,0 +1,43 @@
+//! Tests for merging points which coincide to within the model's declared
+//! uncertainty
+use std::collections::HashSet;
+
+use step::step_file::StepFile;
+use triangulate::triangulate::{triangulate_with, TessellationParams};
+
+/// `examples/cuboid.step`, with one edge ending at a duplicate of the
+/// corner vertex which is slightly offset from it, as written by exporters
+/// which don't share vertices between faces.  The file declares an
+/// uncertainty of 5e-6.
+fn offset_cuboid() -> String {
+    let data = include_str!("../../examples/cuboid.step");
+    assert!(data.contains("#44=EDGE_CURVE('',#56,#57,#64,.T.);"));
+    data.replace("#44=EDGE_CURVE('',#56,#57,#64,.T.);",
+                 "#44=EDGE_CURVE('',#56,#300,#64,.T.);\n\
+                  #300=VERTEX_POINT('',#301);\n\
+                  #301=CARTESIAN_POINT('',(0.,0.0254000001,0.));")
+}
+
+/// Counts the distinct vertex positions in the triangulated model
+fn distinct_positions(data: &str, params: TessellationParams) -> usize {
+    let flat = StepFile::strip_flatten(data.as_bytes());
+    let step = StepFile::parse(&flat);
+    let (mesh, _stats) = triangulate_with(&step, params, &Default::default());
+    mesh.verts.iter()
+        .map(|v| (v.pos.x.to_bits(), v.pos.y.to_bits(), v.pos.z.to_bits()))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[test]
+fn merges_within_uncertainty() {
+    let data = offset_cuboid();
+    let exact = distinct_positions(include_str!("../../examples/cuboid.step"),
+                                   Default::default());
+    // The declared uncertainty merges the offset vertex into the corner
+    assert_eq!(distinct_positions(&data, Default::default()), exact);
+
+    // Overriding the uncertainty with zero disables merging
+    let params = TessellationParams { uncertainty: Some(0.0), ..Default::default() };
+    assert_eq!(distinct_positions(&data, params), exact + 1);
+}
@@ -0,0 +1,33 @@
+//! Tests for filling voxel grids from solids
+use triangulate::{sdf::DistanceField, voxel::Occupancy};
+
+mod common;
+use common::load;
+
+#[test]
+fn cube_hole_layers() {
+    // A 2 x 1 x 1 inch block, with a half-inch hole down through its middle
+    let mesh = load(include_str!("../../examples/cube_hole.step"));
+    let inch = 0.0254;
+    let grid = Occupancy::new(&mesh, inch / 20.0, inch / 10.0);
+    assert_eq!(grid.dims, [40, 20, 10]);
+
+    let volume = mesh.volume(0..mesh.triangles.len()).abs();
+    assert!((grid.volume() - volume).abs() < volume * 0.01,
+            "{} vs {}", grid.volume(), volume);
+
+    // Every layer is the same: a rectangle of pixels, minus the hole
+    let first = grid.layer_image(0);
+    assert_eq!(first.len(), 40 * 20);
+    for z in 1..grid.dims[2] {
+        assert_eq!(grid.layer_image(z), first);
+    }
+    assert!(!grid.get(20, 10, 5));
+    assert!(grid.get(2, 10, 5));
+
+    // Filling from a distance field gives nearly the same grid
+    let field = DistanceField::new(&mesh, inch / 10.0, 0);
+    let coarse = Occupancy::from_field(&field);
+    assert!((coarse.volume() - volume).abs() < volume * 0.1,
+            "{} vs {}", coarse.volume(), volume);
+}